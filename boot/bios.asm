@@ -0,0 +1,36 @@
+; A tiny firmware to test the firmware boot, it prints a message to the serial
+; port and halts. The image is mapped right below 4GB, so the cpu starts at its
+; last 16 bytes with cs based at 0xffff0000.
+
+bits 16
+org 0
+
+COM1 equ 0x3f8
+
+    times 0xff00 - ($ - $$) db 0
+
+start:
+    cli
+    mov dx, COM1
+    mov si, message
+.next:
+    ; ds is based at 0, the message is only reachable through cs
+    mov al, [cs:si]
+    test al, al
+    jz .halt
+    out dx, al
+    inc si
+    jmp .next
+.halt:
+    hlt
+    jmp .halt
+
+message:
+    db "Hello from the test firmware", 13, 10, 0
+
+    times 0xfff0 - ($ - $$) db 0
+
+reset_vector:
+    jmp start
+
+    times 0x10000 - ($ - $$) db 0
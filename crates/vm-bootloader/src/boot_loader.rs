@@ -10,6 +10,7 @@ use vm_core::device::Device;
 use vm_core::device::error::DeviceError;
 use vm_firmware::acpi::error::AcpiError;
 use vm_mm::manager::MemoryAddressSpace;
+use vm_utils::cpu_topology::CpuTopology;
 use vm_utils::range_allocator::RangeAllocator;
 use vm_utils::range_allocator::RangeAllocatorError;
 
@@ -19,6 +20,7 @@ use crate::kernel_loader::error::KernelLoaderError;
 use crate::kernel_loader::linux::x86_64::zero_page::ZeroPageError;
 
 pub mod arch;
+pub mod firmware;
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -40,6 +42,12 @@ pub enum Error {
     #[error("Load dtb failed, reason: {0}")]
     LoadDtbFailed(String),
 
+    #[error("Failed to load firmware, err: {0}")]
+    LoadFirmware(String),
+
+    #[error("Failed to save the firmware vars, err: {0}")]
+    SaveFirmwareVars(String),
+
     #[error("Failed to setup acpi, err: {0}")]
     Acpi(#[from] AcpiError),
 
@@ -79,7 +87,7 @@ pub trait BootLoader {
     async fn load(
         &self,
         ram_size: u64,
-        vcpus: usize,
+        cpu_topology: &CpuTopology,
         boot_vcpu: &mut Vcpu,
         ram_allocator: &mut RangeAllocator<u64>,
         memory: &MemoryAddressSpace,
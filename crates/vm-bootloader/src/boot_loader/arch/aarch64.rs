@@ -3,14 +3,25 @@ use std::path::PathBuf;
 use std::slice::Iter;
 
 use async_trait::async_trait;
-use vm_core::arch::aarch64::layout::DTB_START;
-use vm_core::arch::aarch64::layout::INITRD_START;
+use vm_core::arch::aarch64::layout::ACPI_MAX_LEN;
+use vm_core::arch::aarch64::layout::DTB_MAX_LEN;
+use vm_core::arch::aarch64::layout::ECAM_BASE;
+use vm_core::arch::aarch64::layout::ECAM_LENGTH;
+use vm_core::arch::aarch64::layout::GIC_DISTRIBUTOR;
+use vm_core::arch::aarch64::layout::GIC_MSI;
+use vm_core::arch::aarch64::layout::GIC_REDISTRIBUTOR;
 use vm_core::arch::aarch64::layout::RAM_BASE;
 use vm_core::arch::irq::InterruptController;
+use vm_core::arch::irq::Phandle;
 use vm_core::cpu::vcpu::Vcpu;
 use vm_core::device::Device;
 use vm_fdt::FdtWriter;
+use vm_firmware::acpi::builder::AcpiTableBuilder;
+use vm_firmware::acpi::numa::LOCAL_DISTANCE;
+use vm_firmware::acpi::numa::NumaNode;
+use vm_firmware::acpi::numa::REMOTE_DISTANCE;
 use vm_mm::manager::MemoryAddressSpace;
+use vm_utils::cpu_topology::CpuTopology;
 use vm_utils::range_allocator::RangeAllocator;
 
 use crate::boot_loader::BootLoader;
@@ -22,23 +33,108 @@ use crate::initrd_loader::InitrdLoader;
 use crate::kernel_loader::linux::aarch64::image::AArch64BootParams;
 use crate::kernel_loader::linux::aarch64::image::Image;
 use crate::kernel_loader::linux::aarch64::image::LoadResult;
+use crate::utils::aml::build_definition_block;
 
 const IRQ_TYPE_LEVEL_LOW: u32 = 0x00000008;
 
+// The RD_base and the SGI_base frames of a cpu
+const GIC_REDISTRIBUTOR_STRIDE: u64 = 0x2_0000;
+
+// The dtb is mapped by 2MB blocks, the no-map acpi tables must not share a block with it
+const DTB_ALIGN: usize = 0x20_0000;
+const ACPI_ALIGN: usize = 0x20_0000;
+
 pub struct AArch64BootLoader {
     kernel: PathBuf,
     initrd: Option<PathBuf>,
     cmdline: Option<String>,
+    /// Describe the hardware by ACPI instead of the device tree
+    acpi: bool,
+    numa_nodes: Vec<NumaNode>,
+    /// The other vcpus of the topology are left for hotplug
+    present_vcpus: Option<u32>,
 }
 
 impl AArch64BootLoader {
+    pub fn with_acpi(mut self, acpi: bool) -> Self {
+        self.acpi = acpi;
+        self
+    }
+
+    pub fn with_numa_nodes(mut self, numa_nodes: Vec<NumaNode>) -> Self {
+        self.numa_nodes = numa_nodes;
+        self
+    }
+
+    pub fn with_present_vcpus(mut self, present_vcpus: u32) -> Self {
+        self.present_vcpus = Some(present_vcpus);
+        self
+    }
+
+    fn numa_node_of_vcpu(&self, vcpu_id: u32) -> Option<u32> {
+        self.numa_nodes
+            .iter()
+            .position(|node| node.vcpus.contains(&vcpu_id))
+            .map(|node| node as u32)
+    }
+
+    fn setup_acpi(
+        &self,
+        ram_allocator: &mut RangeAllocator<u64>,
+        memory: &MemoryAddressSpace,
+        ram_end: u64,
+        cpu_topology: &CpuTopology,
+        devices: Iter<'_, Box<dyn Device>>,
+    ) -> Result<u64> {
+        let acpi_start = ram_allocator
+            .alloc_aligned_below(ACPI_MAX_LEN, ACPI_ALIGN, ram_end)?
+            .start;
+
+        let mut acpi_ram_allocator = RangeAllocator::<u64>::default();
+        acpi_ram_allocator.insert(acpi_start, ACPI_MAX_LEN)?;
+
+        let serial_console = devices.clone().find_map(|device| device.serial_console());
+        let iommu_base_address = devices.clone().find_map(|device| device.iommu_mmio_base());
+
+        let mut acpi = AcpiTableBuilder::default()
+            .set_cpu_topology(*cpu_topology)?
+            .set_numa_nodes(self.numa_nodes.clone())?
+            .set_definition_block(build_definition_block(devices))?
+            .set_gic_distributor_address(GIC_DISTRIBUTOR)?
+            .set_gic_redistributor_range(
+                GIC_REDISTRIBUTOR,
+                (GIC_REDISTRIBUTOR_STRIDE * cpu_topology.vcpus() as u64)
+                    .try_into()
+                    .map_err(|_| Error::VcpuExceedsAcpiCapability)?,
+            )?
+            .set_gic_its_address(GIC_MSI)?
+            .set_pci_mmio_base_addr(ECAM_BASE as u64)?
+            .set_pci_end_bus_number(((ECAM_LENGTH >> 20) - 1) as u8)?;
+        if let Some((base, gsi)) = serial_console {
+            acpi = acpi.set_serial_console(base, gsi)?;
+        }
+        if let Some(present_vcpus) = self.present_vcpus {
+            acpi = acpi.set_present_vcpus(present_vcpus)?;
+        }
+        if let Some(iommu_base_address) = iommu_base_address {
+            acpi = acpi.set_iommu_base_address(iommu_base_address)?;
+        }
+        let acpi = acpi.build()?;
+
+        // The rsdp is at the start of the region
+        acpi.install(&mut acpi_ram_allocator, memory, acpi_start)?;
+
+        Ok(acpi_start)
+    }
+
     fn load_initrd(
         &self,
         ram_allocator: &mut RangeAllocator<u64>,
         memory: &MemoryAddressSpace,
         initrd: &Path,
+        ram_end: u64,
     ) -> Result<InitrdLoadResult> {
-        let result = InitrdLoader::new(initrd)?.load(ram_allocator, memory, INITRD_START)?;
+        let result = InitrdLoader::new(initrd)?.load(ram_allocator, memory, ram_end)?;
 
         Ok(result)
     }
@@ -48,20 +144,16 @@ impl AArch64BootLoader {
         ram_allocator: &mut RangeAllocator<u64>,
         memory: &MemoryAddressSpace,
         dtb: Vec<u8>,
+        ram_end: u64,
     ) -> Result<u64> {
-        let dtb_start = DTB_START;
-
-        if !dtb_start.is_multiple_of(8) {
-            return Err(Error::LoadDtbFailed(
-                "dtb must be placed on an 8-byte boundary".to_string(),
-            ));
-        }
-
-        if dtb.len() >= (2 << 20) {
+        if dtb.len() > DTB_MAX_LEN {
             return Err(Error::LoadDtbFailed("dtb too large".to_string()));
         }
 
-        ram_allocator.reserve(dtb_start, dtb.len())?;
+        let dtb_start = ram_allocator
+            .alloc_aligned_below(dtb.len(), DTB_ALIGN, ram_end)
+            .map_err(|_| Error::LoadDtbFailed("no room for the dtb".to_string()))?
+            .start;
         memory
             .copy_from_slice(dtb_start, &dtb)
             .map_err(|_| Error::LoadDtbFailed("failed to copy".to_string()))?;
@@ -69,50 +161,95 @@ impl AArch64BootLoader {
         Ok(dtb_start)
     }
 
-    fn generate_dtb(
+    /// The nodes describing the hardware, ACPI describes them instead if it is enabled
+    fn generate_hardware_nodes(
         &self,
-        ram_size: u64,
-        initrd_load_result: Option<InitrdLoadResult>,
-        vcpus: usize,
+        fdt: &mut FdtWriter,
+        cpu_topology: &CpuTopology,
         irq_chip: &dyn InterruptController,
         devices: Iter<'_, Box<dyn Device>>,
-    ) -> Result<Vec<u8>> {
-        let mut fdt = FdtWriter::new()?;
-        let root_node = fdt.begin_node("")?;
-
-        fdt.property_string("compatible", "linux,virt")?;
-        fdt.property_u32("#address-cells", 2)?;
-        fdt.property_u32("#size-cells", 2)?;
-
-        {
-            let memory_node = fdt.begin_node(&format!("memory@{:08x}", RAM_BASE))?;
-            fdt.property_string("device_type", "memory")?;
-            fdt.property_array_u64("reg", &[RAM_BASE, ram_size])?;
-            fdt.end_node(memory_node)?;
-        }
-
+    ) -> Result<()> {
         {
             let cpu_node = fdt.begin_node("cpus")?;
             fdt.property_u32("#address-cells", 1)?;
             fdt.property_u32("#size-cells", 0)?;
-            for i in 0..vcpus {
-                let cpu_node = fdt.begin_node(&format!("cpu@{}", i))?;
+            for i in 0..cpu_topology.vcpus() {
+                // Aff3 is always 0, so the hwid fits in one cell
+                let mpidr = cpu_topology.hwid(i) as u32;
+
+                let cpu_node = fdt.begin_node(&format!("cpu@{:x}", mpidr))?;
                 fdt.property_string("device_type", "cpu")?;
                 fdt.property_string("compatible", "arm,cortex-a72")?;
-                fdt.property_u32("reg", i as u32)?;
-                if vcpus > 1 {
+                fdt.property_u32("reg", mpidr)?;
+                fdt.property_phandle(Phandle::CPU as u32 + i)?;
+                if cpu_topology.vcpus() > 1 {
                     fdt.property_string("enable-method", "psci")?;
                 }
+                if let Some(node) = self.numa_node_of_vcpu(i) {
+                    fdt.property_u32("numa-node-id", node)?;
+                }
                 fdt.end_node(cpu_node)?;
             }
+
+            {
+                let cpu_map_node = fdt.begin_node("cpu-map")?;
+                for socket in 0..cpu_topology.sockets {
+                    let socket_node = fdt.begin_node(&format!("socket{}", socket))?;
+                    // Every socket has a single cluster of all its cores
+                    let cluster_node = fdt.begin_node("cluster0")?;
+                    for core in 0..cpu_topology.cores_per_socket {
+                        let core_node = fdt.begin_node(&format!("core{}", core))?;
+                        let vcpu_id = (socket * cpu_topology.cores_per_socket + core)
+                            * cpu_topology.threads_per_core;
+                        if cpu_topology.threads_per_core == 1 {
+                            fdt.property_u32("cpu", Phandle::CPU as u32 + vcpu_id)?;
+                        } else {
+                            for thread in 0..cpu_topology.threads_per_core {
+                                let thread_node = fdt.begin_node(&format!("thread{}", thread))?;
+                                fdt.property_u32("cpu", Phandle::CPU as u32 + vcpu_id + thread)?;
+                                fdt.end_node(thread_node)?;
+                            }
+                        }
+                        fdt.end_node(core_node)?;
+                    }
+                    fdt.end_node(cluster_node)?;
+                    fdt.end_node(socket_node)?;
+                }
+                fdt.end_node(cpu_map_node)?;
+            }
+
             fdt.end_node(cpu_node)?;
         }
 
+        if !self.numa_nodes.is_empty() {
+            let distance_map_node = fdt.begin_node("distance-map")?;
+            fdt.property_string("compatible", "numa-distance-map-v1")?;
+
+            let mut matrix = Vec::new();
+            for (i, node) in self.numa_nodes.iter().enumerate() {
+                for j in 0..self.numa_nodes.len() {
+                    let distance = match node.distances.get(j) {
+                        Some(distance) => *distance,
+                        None if i == j => LOCAL_DISTANCE,
+                        None => REMOTE_DISTANCE,
+                    };
+                    matrix.extend_from_slice(&[i as u32, j as u32, distance as u32]);
+                }
+            }
+            fdt.property_array_u32("distance-matrix", &matrix)?;
+
+            fdt.end_node(distance_map_node)?;
+        }
+
         {
             let psci_node = fdt.begin_node("psci")?;
             fdt.property_string_list(
                 "compatible",
-                vec!["arm,psci-0.2".to_string(), "arm,psci".to_string()],
+                vec![
+                    "arm,psci-1.0".to_string(),
+                    "arm,psci-0.2".to_string(),
+                    "arm,psci".to_string(),
+                ],
             )?;
             fdt.property_string("method", "smc")?;
             fdt.property_u32("cpu_suspend", 0x84000001)?;
@@ -123,7 +260,7 @@ impl AArch64BootLoader {
         }
 
         let irq_phandle = irq_chip
-            .write_device_tree(&mut fdt)
+            .write_device_tree(fdt)
             .map_err(|err| Error::LoadDtbFailed(err.to_string()))?;
 
         {
@@ -159,25 +296,84 @@ impl AArch64BootLoader {
 
             for device in devices {
                 if let Some(mmio_device) = device.support_mmio_transport() {
-                    mmio_device.generate_dt(&mut fdt)?;
+                    mmio_device.generate_dt(fdt)?;
                 }
             }
 
             fdt.end_node(soc_node)?;
         }
 
+        Ok(())
+    }
+
+    pub(crate) fn generate_dtb(
+        &self,
+        ram_size: u64,
+        initrd_load_result: Option<InitrdLoadResult>,
+        acpi_start: Option<u64>,
+        cpu_topology: &CpuTopology,
+        irq_chip: &dyn InterruptController,
+        devices: Iter<'_, Box<dyn Device>>,
+    ) -> Result<Vec<u8>> {
+        let mut fdt = FdtWriter::new()?;
+        let root_node = fdt.begin_node("")?;
+
+        fdt.property_string("compatible", "linux,virt")?;
+        fdt.property_u32("#address-cells", 2)?;
+        fdt.property_u32("#size-cells", 2)?;
+
+        if self.numa_nodes.is_empty() {
+            let memory_node = fdt.begin_node(&format!("memory@{:08x}", RAM_BASE))?;
+            fdt.property_string("device_type", "memory")?;
+            fdt.property_array_u64("reg", &[RAM_BASE, ram_size])?;
+            fdt.end_node(memory_node)?;
+        } else {
+            for (i, node) in self.numa_nodes.iter().enumerate() {
+                let memory_node = fdt.begin_node(&format!("memory@{:08x}", node.memory.start))?;
+                fdt.property_string("device_type", "memory")?;
+                fdt.property_array_u64(
+                    "reg",
+                    &[node.memory.start, node.memory.end - node.memory.start],
+                )?;
+                fdt.property_u32("numa-node-id", i as u32)?;
+                fdt.end_node(memory_node)?;
+            }
+        }
+
+        if let Some(acpi_start) = acpi_start {
+            // The kernel maps the tables by itself, they must stay out of its linear map
+            let reserved_memory_node = fdt.begin_node("reserved-memory")?;
+            fdt.property_u32("#address-cells", 2)?;
+            fdt.property_u32("#size-cells", 2)?;
+            fdt.property_null("ranges")?;
+
+            let acpi_node = fdt.begin_node(&format!("acpi@{:08x}", acpi_start))?;
+            fdt.property_array_u64("reg", &[acpi_start, ACPI_MAX_LEN as u64])?;
+            fdt.property_null("no-map")?;
+            fdt.end_node(acpi_node)?;
+
+            fdt.end_node(reserved_memory_node)?;
+        } else {
+            self.generate_hardware_nodes(&mut fdt, cpu_topology, irq_chip, devices)?;
+        }
+
         {
             let chosen_node = fdt.begin_node("chosen")?;
             fdt.property_u32("stdout-path", 2)?;
-            if let Some(cmdline) = &self.cmdline {
-                fdt.property_string("bootargs", cmdline)?;
+
+            let mut bootargs = self.cmdline.clone().unwrap_or_default();
+            if let Some(acpi_start) = acpi_start {
+                // There is no uefi to pass the rsdp
+                bootargs.push_str(&format!(" acpi=force acpi_rsdp={:#x}", acpi_start));
+            }
+            if !bootargs.is_empty() {
+                fdt.property_string("bootargs", bootargs.trim_start())?;
             }
-            if self.initrd.is_some() {
-                fdt.property_u64("linux,initrd-start", INITRD_START)?;
+            if let Some(initrd_load_result) = &initrd_load_result {
+                fdt.property_u64("linux,initrd-start", initrd_load_result.initrd_start)?;
                 fdt.property_u64(
                     "linux,initrd-end",
-                    initrd_load_result.as_ref().unwrap().initrd_start
-                        + initrd_load_result.as_ref().unwrap().initrd_len as u64,
+                    initrd_load_result.initrd_start + initrd_load_result.initrd_len as u64,
                 )?;
             }
 
@@ -207,6 +403,9 @@ impl BootLoaderBuilder for AArch64BootLoader {
             kernel,
             initrd: initramfs,
             cmdline,
+            acpi: false,
+            numa_nodes: Vec::new(),
+            present_vcpus: None,
         }
     }
 }
@@ -216,26 +415,48 @@ impl BootLoader for AArch64BootLoader {
     async fn load(
         &self,
         ram_size: u64,
-        vcpus: usize,
+        cpu_topology: &CpuTopology,
         boot_vcpu: &mut Vcpu,
         ram_allocator: &mut RangeAllocator<u64>,
         memory: &MemoryAddressSpace,
         irq_chip: &dyn InterruptController,
         devices: Iter<'_, Box<dyn Device>>,
     ) -> Result<()> {
+        // The kernel is at the start of the ram, the others are placed downwards from its end
+        let kernel_loader = self.load_image(ram_allocator, memory)?;
+
+        let ram_end = RAM_BASE + ram_size;
+
         let initrd_loader = if let Some(initrd) = &self.initrd {
-            let load_result = self.load_initrd(ram_allocator, memory, initrd)?;
+            let load_result = self.load_initrd(ram_allocator, memory, initrd, ram_end)?;
             Some(load_result)
         } else {
             None
         };
 
-        let dtb_start = {
-            let dtb = self.generate_dtb(ram_size, initrd_loader, vcpus, irq_chip, devices)?;
-            self.load_dtb(ram_allocator, memory, dtb)?
+        let acpi_start = if self.acpi {
+            Some(self.setup_acpi(
+                ram_allocator,
+                memory,
+                ram_end,
+                cpu_topology,
+                devices.clone(),
+            )?)
+        } else {
+            None
         };
 
-        let kernel_loader = self.load_image(ram_allocator, memory)?;
+        let dtb_start = {
+            let dtb = self.generate_dtb(
+                ram_size,
+                initrd_loader,
+                acpi_start,
+                cpu_topology,
+                irq_chip,
+                devices,
+            )?;
+            self.load_dtb(ram_allocator, memory, dtb, ram_end)?
+        };
 
         boot_vcpu
             .setup_vcpu(kernel_loader.start_pc, dtb_start)
@@ -6,25 +6,30 @@ use std::slice::Iter;
 
 use async_trait::async_trait;
 use vm_core::arch::irq::InterruptController;
+use vm_core::arch::registers::x86_64::BootProtocol;
 use vm_core::arch::x86_64::layout::ACPI_MAX_LEN;
 use vm_core::arch::x86_64::layout::ACPI_RSDP_START;
+use vm_core::arch::x86_64::layout::ACPI_SLEEP_PORT;
 use vm_core::arch::x86_64::layout::APIC_ADDR;
 use vm_core::arch::x86_64::layout::BOOT_PARAMS_START;
-use vm_core::arch::x86_64::layout::CMDLINE_START;
+use vm_core::arch::x86_64::layout::CMDLINE_LIMIT;
 use vm_core::arch::x86_64::layout::ECAM_BASE;
 use vm_core::arch::x86_64::layout::ECAM_LENGTH;
 use vm_core::arch::x86_64::layout::GDT_START;
-use vm_core::arch::x86_64::layout::INITRD_START;
 use vm_core::arch::x86_64::layout::IOAPIC_ADDR;
 use vm_core::arch::x86_64::layout::KERNEL_START;
+use vm_core::arch::x86_64::layout::MEMORY_HOTPLUG_START;
 use vm_core::arch::x86_64::layout::MMIO_LEN;
 use vm_core::arch::x86_64::layout::MMIO_START;
+use vm_core::arch::x86_64::layout::PVH_INFO_START;
 use vm_core::cpu::vcpu::Vcpu;
 use vm_core::device::Device;
 use vm_firmware::acpi::builder::AcpiTableBuilder;
+use vm_firmware::acpi::numa::NumaNode;
 use vm_firmware::x86_64::gdt::Gdt;
 use vm_firmware::x86_64::gdt::GdtEntry;
 use vm_mm::manager::MemoryAddressSpace;
+use vm_utils::cpu_topology::CpuTopology;
 use vm_utils::range_allocator::RangeAllocator;
 use zerocopy::IntoBytes;
 
@@ -37,7 +42,11 @@ use crate::initrd_loader::InitrdLoader;
 use crate::kernel_loader::linux::x86_64::bzimage::BzImage;
 use crate::kernel_loader::linux::x86_64::bzimage::BzImageBootParams;
 use crate::kernel_loader::linux::x86_64::bzimage::LoadResult;
+use crate::kernel_loader::linux::x86_64::pvh::HvmMemmapEntry;
+use crate::kernel_loader::linux::x86_64::pvh::Pvh;
+use crate::kernel_loader::linux::x86_64::pvh::StartInfoParams;
 use crate::kernel_loader::linux::x86_64::zero_page::BootParams;
+use crate::kernel_loader::linux::x86_64::zero_page::E820Type;
 use crate::kernel_loader::linux::x86_64::zero_page::SetupHeader;
 use crate::kernel_loader::linux::x86_64::zero_page::ZeroPageBuilder;
 use crate::utils::aml::build_definition_block;
@@ -46,14 +55,27 @@ pub struct X86_64BootLoader {
     kernel: PathBuf,
     initramfs: Option<PathBuf>,
     cmdline: Option<String>,
+    numa_nodes: Vec<NumaNode>,
+    /// The other vcpus of the topology are left for hotplug
+    present_vcpus: Option<u32>,
 }
 
 impl X86_64BootLoader {
+    pub fn with_numa_nodes(mut self, numa_nodes: Vec<NumaNode>) -> Self {
+        self.numa_nodes = numa_nodes;
+        self
+    }
+
+    pub fn with_present_vcpus(mut self, present_vcpus: u32) -> Self {
+        self.present_vcpus = Some(present_vcpus);
+        self
+    }
+
     fn setup_acpi(
         &self,
         ram_allocator: &mut RangeAllocator<u64>,
         mm: &MemoryAddressSpace,
-        vcpus: usize,
+        cpu_topology: &CpuTopology,
         devices: Iter<'_, Box<dyn Device>>,
     ) -> Result<(u32, u32)> {
         let acpi_rsdp_addr = ACPI_RSDP_START as u64;
@@ -64,17 +86,24 @@ impl X86_64BootLoader {
         let mut acpi_ram_allocator = RangeAllocator::<u64>::default();
         acpi_ram_allocator.insert(acpi_rsdp_addr, acpi_max_length)?;
 
-        let acpi = AcpiTableBuilder::default()
-            .set_vcpus(
-                vcpus
-                    .try_into()
-                    .map_err(|_| Error::VcpuExceedsAcpiCapability)?,
-            )?
+        let iommu_base_address = devices.clone().find_map(|device| device.iommu_mmio_base());
+
+        let mut acpi = AcpiTableBuilder::default()
+            .set_cpu_topology(*cpu_topology)?
+            .set_numa_nodes(self.numa_nodes.clone())?
             .set_definition_block(build_definition_block(devices))?
             .set_apic_base_address(APIC_ADDR)?
             .set_io_apic_address(IOAPIC_ADDR)?
+            .set_sleep_register_port(ACPI_SLEEP_PORT)?
             .set_pci_mmio_base_addr(ECAM_BASE as u64)?
-            .build()?;
+            .set_pci_end_bus_number(((ECAM_LENGTH >> 20) - 1) as u8)?;
+        if let Some(present_vcpus) = self.present_vcpus {
+            acpi = acpi.set_present_vcpus(present_vcpus)?;
+        }
+        if let Some(iommu_base_address) = iommu_base_address {
+            acpi = acpi.set_iommu_base_address(iommu_base_address)?;
+        }
+        let acpi = acpi.build()?;
 
         acpi.install(&mut acpi_ram_allocator, mm, acpi_rsdp_addr)?;
 
@@ -112,9 +141,9 @@ impl X86_64BootLoader {
         ram_allocator: &mut RangeAllocator<u64>,
         memory: &MemoryAddressSpace,
         initramfs: &Path,
+        limit: u64,
     ) -> Result<InitrdLoadResult> {
-        let result =
-            InitrdLoader::new(initramfs)?.load(ram_allocator, memory, INITRD_START as u64)?;
+        let result = InitrdLoader::new(initramfs)?.load(ram_allocator, memory, limit)?;
 
         Ok(result)
     }
@@ -124,8 +153,6 @@ impl X86_64BootLoader {
         ram_allocator: &mut RangeAllocator<u64>,
         memory: &MemoryAddressSpace,
     ) -> Result<(u32, u32)> {
-        let cmdline_start = CMDLINE_START;
-
         let cmdline = if let Some(cmdline) = &self.cmdline {
             CString::new(cmdline.to_string()).map_err(|err| Error::Cmdline(err.to_string()))?
         } else {
@@ -133,12 +160,14 @@ impl X86_64BootLoader {
         };
 
         let buf = cmdline.as_bytes_with_nul();
-        let range = ram_allocator.reserve(cmdline_start as u64, buf.len())?;
+        let range = ram_allocator
+            .alloc_aligned_below(buf.len(), 1, CMDLINE_LIMIT as u64)
+            .map_err(|_| Error::Cmdline("no room for the cmdline".to_string()))?;
         memory
             .copy_from_slice(range.start, cmdline.as_bytes_with_nul())
             .map_err(|err| Error::Cmdline(err.to_string()))?;
 
-        Ok((cmdline_start, buf.len() as u32))
+        Ok((range.start as u32, buf.len() as u32))
     }
 
     fn setup_zero_page(
@@ -182,7 +211,6 @@ impl X86_64BootLoader {
         &self,
         ram_allocator: &mut RangeAllocator<u64>,
         memory: &MemoryAddressSpace,
-        initrd_load_result: Option<InitrdLoadResult>,
         cmdline_start: u32,
         cmdline_len: u32,
     ) -> Result<LoadResult> {
@@ -190,13 +218,76 @@ impl X86_64BootLoader {
             cmdline_start,
             cmdline_len,
             kernel_start: KERNEL_START,
-            initrd: initrd_load_result,
         };
 
-        let load_result = BzImage::new(&self.kernel)?.load(ram_allocator, memory, &params)?;
+        let mut load_result = BzImage::new(&self.kernel)?.load(ram_allocator, memory, &params)?;
+
+        // The kernel is placed first so that the initramfs goes around it
+        if let Some(initramfs) = &self.initramfs {
+            let initrd_load_result =
+                self.load_initramfs(ram_allocator, memory, initramfs, load_result.initrd_limit())?;
+            load_result.setup_initrd(&initrd_load_result)?;
+        }
 
         Ok(load_result)
     }
+
+    /// Load a vmlinux and its `hvm_start_info`, returns the address of the start info and the
+    /// entry point
+    fn load_pvh(
+        &self,
+        ram_allocator: &mut RangeAllocator<u64>,
+        memory: &MemoryAddressSpace,
+        cmdline_start: u32,
+        acpi_rsdp_addr: u32,
+        acpi_max_length: u32,
+    ) -> Result<(u64, u32)> {
+        let pvh = Pvh::new(&self.kernel)?;
+        let load_result = pvh.load(ram_allocator, memory)?;
+
+        // Below the 32-bit mmio hole
+        let initrd_load_result = if let Some(initramfs) = &self.initramfs {
+            Some(self.load_initramfs(ram_allocator, memory, initramfs, MMIO_START as u64)?)
+        } else {
+            None
+        };
+
+        // The same layout as the e820 table of the zero page
+        let mut memmap = memory
+            .regions()
+            .range(..MEMORY_HOTPLUG_START)
+            .map(|(_, region)| HvmMemmapEntry::new(region.gpa, region.len() as u64, E820Type::Ram))
+            .collect::<Vec<_>>();
+        memmap.push(HvmMemmapEntry::new(
+            acpi_rsdp_addr as u64,
+            acpi_max_length as u64,
+            E820Type::Acpi,
+        ));
+        memmap.push(HvmMemmapEntry::new(
+            MMIO_START as u64,
+            MMIO_LEN as u64,
+            E820Type::Reserved,
+        ));
+        memmap.push(HvmMemmapEntry::new(
+            ECAM_BASE as u64,
+            ECAM_LENGTH as u64,
+            E820Type::Reserved,
+        ));
+
+        let start_info = pvh.setup_start_info(
+            ram_allocator,
+            memory,
+            PVH_INFO_START as u64,
+            StartInfoParams {
+                cmdline_start,
+                rsdp_addr: acpi_rsdp_addr,
+                initrd: initrd_load_result.as_ref(),
+                memmap,
+            },
+        )?;
+
+        Ok((start_info, load_result.start_pc))
+    }
 }
 
 impl BootLoaderBuilder for X86_64BootLoader {
@@ -205,6 +296,8 @@ impl BootLoaderBuilder for X86_64BootLoader {
             kernel,
             initramfs,
             cmdline,
+            numa_nodes: Vec::new(),
+            present_vcpus: None,
         }
     }
 }
@@ -214,7 +307,7 @@ impl BootLoader for X86_64BootLoader {
     async fn load(
         &self,
         _ram_size: u64,
-        vcpus: usize,
+        cpu_topology: &CpuTopology,
         boot_vcpu: &mut Vcpu,
         ram_allocator: &mut RangeAllocator<u64>,
         memory: &MemoryAddressSpace,
@@ -222,25 +315,29 @@ impl BootLoader for X86_64BootLoader {
         devices: Iter<'_, Box<dyn Device>>,
     ) -> Result<()> {
         let (acpi_rsdt_addr, acpi_max_length) =
-            self.setup_acpi(ram_allocator, memory, vcpus, devices)?;
+            self.setup_acpi(ram_allocator, memory, cpu_topology, devices)?;
 
         let (gdt, gdt_start) = self.setup_gdt(ram_allocator, memory)?;
 
-        let initrd_load_result = if let Some(initramfs) = &self.initramfs {
-            Some(self.load_initramfs(ram_allocator, memory, initramfs)?)
-        } else {
-            None
-        };
-
         let (cmdline_start, cmdline_len) = self.load_cmdline(ram_allocator, memory)?;
 
-        let load_result = self.load_image(
-            ram_allocator,
-            memory,
-            initrd_load_result,
-            cmdline_start,
-            cmdline_len,
-        )?;
+        if Pvh::is_elf(&self.kernel)? {
+            let (start_info, start_pc) = self.load_pvh(
+                ram_allocator,
+                memory,
+                cmdline_start,
+                acpi_rsdt_addr,
+                acpi_max_length,
+            )?;
+
+            boot_vcpu
+                .setup_vcpu(gdt, gdt_start, BootProtocol::Pvh { start_info }, start_pc)
+                .await?;
+
+            return Ok(());
+        }
+
+        let load_result = self.load_image(ram_allocator, memory, cmdline_start, cmdline_len)?;
 
         let zero_page_start = self.setup_zero_page(
             ram_allocator,
@@ -251,7 +348,14 @@ impl BootLoader for X86_64BootLoader {
         )?;
 
         boot_vcpu
-            .setup_vcpu(gdt, gdt_start, zero_page_start, load_result.start_pc)
+            .setup_vcpu(
+                gdt,
+                gdt_start,
+                BootProtocol::Linux {
+                    boot_params: zero_page_start as u64,
+                },
+                load_result.start_pc,
+            )
             .await?;
 
         Ok(())
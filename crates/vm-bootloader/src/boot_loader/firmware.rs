@@ -0,0 +1,280 @@
+use std::fs;
+use std::path::Path;
+#[cfg(target_arch = "aarch64")]
+use std::path::PathBuf;
+use std::slice::Iter;
+
+use async_trait::async_trait;
+#[cfg(target_arch = "aarch64")]
+use vm_core::arch::aarch64::layout::DTB_MAX_LEN;
+#[cfg(target_arch = "aarch64")]
+use vm_core::arch::aarch64::layout::FIRMWARE_BANK_LEN;
+#[cfg(target_arch = "aarch64")]
+use vm_core::arch::aarch64::layout::FIRMWARE_CODE_START;
+#[cfg(target_arch = "aarch64")]
+use vm_core::arch::aarch64::layout::FIRMWARE_VARS_START;
+#[cfg(target_arch = "aarch64")]
+use vm_core::arch::aarch64::layout::RAM_BASE;
+use vm_core::arch::irq::InterruptController;
+#[cfg(target_arch = "x86_64")]
+use vm_core::arch::x86_64::layout::FIRMWARE_LEN;
+#[cfg(target_arch = "x86_64")]
+use vm_core::arch::x86_64::layout::FIRMWARE_START;
+#[cfg(target_arch = "x86_64")]
+use vm_core::arch::x86_64::layout::LEGACY_BIOS_LEN;
+#[cfg(target_arch = "x86_64")]
+use vm_core::arch::x86_64::layout::LEGACY_BIOS_START;
+use vm_core::cpu::vcpu::Vcpu;
+use vm_core::device::Device;
+use vm_mm::manager::MemoryAddressSpace;
+use vm_utils::cpu_topology::CpuTopology;
+use vm_utils::range_allocator::RangeAllocator;
+
+use crate::boot_loader::BootLoader;
+#[cfg(target_arch = "aarch64")]
+use crate::boot_loader::BootLoaderBuilder;
+use crate::boot_loader::Error;
+use crate::boot_loader::Result;
+#[cfg(target_arch = "aarch64")]
+use crate::boot_loader::arch::aarch64::AArch64BootLoader;
+
+// The flash regions are mapped into the guest as they are
+const FIRMWARE_ALIGN: usize = 0x1000;
+
+/// A pflash region of the firmware, it is mapped into the guest by the vmm
+pub struct FlashRegion<'a> {
+    pub gpa: u64,
+    pub image: &'a [u8],
+    pub read_only: bool,
+}
+
+/// Boot a firmware image instead of loading a kernel, the vcpu starts at the reset vector
+/// and the firmware is in charge of everything else
+pub struct FirmwareBootLoader {
+    code: Vec<u8>,
+    /// The writable variable store, the guest writes are saved back to the file by
+    /// `flush_vars`
+    vars: Option<Vec<u8>>,
+}
+
+fn read_image(path: &Path) -> Result<Vec<u8>> {
+    let image = fs::read(path)
+        .map_err(|err| Error::LoadFirmware(format!("{}: {}", path.display(), err)))?;
+
+    if image.is_empty() || !image.len().is_multiple_of(FIRMWARE_ALIGN) {
+        return Err(Error::LoadFirmware(format!(
+            "{}: the size must be a non-zero multiple of {:#x}",
+            path.display(),
+            FIRMWARE_ALIGN
+        )));
+    }
+
+    Ok(image)
+}
+
+/// Whether a memory region is the firmware code, which is mapped read-only
+#[cfg(target_arch = "x86_64")]
+pub fn is_firmware_code(gpa: u64, len: usize) -> bool {
+    gpa >= FIRMWARE_START as u64 && gpa + len as u64 == 1 << 32
+}
+
+/// Whether a memory region is the firmware code, which is mapped read-only
+#[cfg(target_arch = "aarch64")]
+pub fn is_firmware_code(gpa: u64, len: usize) -> bool {
+    gpa == FIRMWARE_CODE_START && len <= FIRMWARE_BANK_LEN
+}
+
+impl FirmwareBootLoader {
+    pub fn new(code: &Path, vars: Option<&Path>) -> Result<Self> {
+        let code = read_image(code)?;
+        let vars = vars.map(read_image).transpose()?;
+
+        #[cfg(target_arch = "x86_64")]
+        let fits = code.len() + vars.as_ref().map_or(0, Vec::len) <= FIRMWARE_LEN as usize;
+        #[cfg(target_arch = "aarch64")]
+        let fits = code.len() <= FIRMWARE_BANK_LEN
+            && vars.as_ref().map_or(0, Vec::len) <= FIRMWARE_BANK_LEN;
+
+        if !fits {
+            return Err(Error::LoadFirmware("firmware too large".to_string()));
+        }
+
+        Ok(FirmwareBootLoader { code, vars })
+    }
+
+    /// The code ends at 4GB on x86 so that its last 16 bytes are the reset vector
+    #[cfg(target_arch = "x86_64")]
+    fn code_start(&self) -> u64 {
+        (1 << 32) - self.code.len() as u64
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    fn code_start(&self) -> u64 {
+        FIRMWARE_CODE_START
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    fn vars_start(&self, vars: &[u8]) -> u64 {
+        let start = self.code_start() - vars.len() as u64;
+        assert!(start >= FIRMWARE_START as u64);
+        start
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    fn vars_start(&self, _vars: &[u8]) -> u64 {
+        FIRMWARE_VARS_START
+    }
+
+    /// Writes the vars bank of the guest back to `path`
+    pub fn flush_vars(&self, memory: &MemoryAddressSpace, path: &Path) -> Result<()> {
+        let Some(vars) = &self.vars else {
+            return Ok(());
+        };
+
+        let mut buf = vec![0; vars.len()];
+        memory
+            .copy_to_slice(self.vars_start(vars), &mut buf)
+            .map_err(|err| Error::SaveFirmwareVars(err.to_string()))?;
+        fs::write(path, buf)
+            .map_err(|err| Error::SaveFirmwareVars(format!("{}: {}", path.display(), err)))?;
+
+        Ok(())
+    }
+
+    pub fn flash_regions(&self) -> Vec<FlashRegion<'_>> {
+        let mut regions = vec![FlashRegion {
+            gpa: self.code_start(),
+            image: &self.code,
+            read_only: true,
+        }];
+
+        if let Some(vars) = &self.vars {
+            regions.push(FlashRegion {
+                gpa: self.vars_start(vars),
+                image: vars,
+                read_only: false,
+            });
+        }
+
+        regions
+    }
+}
+
+#[async_trait]
+impl BootLoader for FirmwareBootLoader {
+    async fn load(
+        &self,
+        _ram_size: u64,
+        _cpu_topology: &CpuTopology,
+        _boot_vcpu: &mut Vcpu,
+        ram_allocator: &mut RangeAllocator<u64>,
+        memory: &MemoryAddressSpace,
+        _irq_chip: &dyn InterruptController,
+        _devices: Iter<'_, Box<dyn Device>>,
+    ) -> Result<()> {
+        // A legacy bios far jumps to its segment at 0xf000 right after the reset vector
+        #[cfg(target_arch = "x86_64")]
+        {
+            let len = self.code.len().min(LEGACY_BIOS_LEN as usize);
+            let start = (LEGACY_BIOS_START + LEGACY_BIOS_LEN) as u64 - len as u64;
+
+            ram_allocator.reserve(start, len)?;
+            memory
+                .copy_from_slice(start, &self.code[self.code.len() - len..])
+                .map_err(|err| Error::LoadFirmware(err.to_string()))?;
+
+            // A new vcpu is already in its reset state, cs:ip points to the reset vector, the
+            // firmware reads the ram size from the cmos
+        }
+
+        // Like on qemu, the firmware finds the dtb describing the hardware at the start of
+        // the ram
+        #[cfg(target_arch = "aarch64")]
+        {
+            let dtb = AArch64BootLoader::new(PathBuf::new(), None, None).generate_dtb(
+                _ram_size,
+                None,
+                None,
+                _cpu_topology,
+                _irq_chip,
+                _devices,
+            )?;
+            if dtb.len() > DTB_MAX_LEN {
+                return Err(Error::LoadDtbFailed("dtb too large".to_string()));
+            }
+
+            ram_allocator.reserve(RAM_BASE, DTB_MAX_LEN)?;
+            memory
+                .copy_from_slice(RAM_BASE, &dtb)
+                .map_err(|_| Error::LoadDtbFailed("failed to copy".to_string()))?;
+
+            _boot_vcpu.setup_vcpu(self.code_start(), RAM_BASE).await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempfile::NamedTempFile;
+    use vm_mm::allocator::Allocator;
+    use vm_mm::allocator::mmap_allocator::MmapAllocator;
+    use vm_mm::region::MemoryRegion;
+
+    use super::*;
+
+    #[test]
+    fn test_flash_regions() {
+        let firmware = FirmwareBootLoader {
+            code: vec![0; 0x2000],
+            vars: Some(vec![0; 0x1000]),
+        };
+
+        let regions = firmware.flash_regions();
+        assert_eq!(regions.len(), 2);
+        assert!(regions[0].read_only);
+        assert!(!regions[1].read_only);
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            assert_eq!(regions[0].gpa, 0xffff_e000);
+            assert_eq!(regions[1].gpa, 0xffff_d000);
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            assert_eq!(regions[0].gpa, FIRMWARE_CODE_START);
+            assert_eq!(regions[1].gpa, FIRMWARE_VARS_START);
+        }
+    }
+
+    #[test]
+    fn test_flush_vars() {
+        let mut vars = NamedTempFile::new().unwrap();
+        vars.write_all(&[0; 0x1000]).unwrap();
+        let mut code = NamedTempFile::new().unwrap();
+        code.write_all(&[0; 0x2000]).unwrap();
+
+        let firmware = FirmwareBootLoader::new(code.path(), Some(vars.path())).unwrap();
+        let bank = firmware.flash_regions()[1].gpa;
+
+        let mut memory = MemoryAddressSpace::default();
+        let region = MmapAllocator.alloc(0x1000, None).unwrap();
+        assert!(
+            memory
+                .try_insert(MemoryRegion::new(bank, Box::new(region)))
+                .is_ok()
+        );
+
+        // Written by the guest
+        memory.copy_from_slice(bank + 0x10, b"BootOrder").unwrap();
+
+        firmware.flush_vars(&memory, vars.path()).unwrap();
+
+        let saved = fs::read(vars.path()).unwrap();
+        assert_eq!(saved.len(), 0x1000);
+        assert_eq!(&saved[0x10..0x19], b"BootOrder");
+    }
+}
@@ -17,8 +17,13 @@ pub enum InitrdLoaderError {
 
     #[error("Failed to reserve ram for initramfs, err: {0}")]
     ReserveRam(#[from] RangeAllocatorError),
+
+    #[error("No room for the initramfs of {len} bytes below 0x{limit:x}")]
+    NoRoom { len: usize, limit: u64 },
 }
 
+const INITRD_ALIGN: usize = 0x1000;
+
 pub struct InitrdLoadResult {
     pub initrd_start: u64,
     pub initrd_len: usize,
@@ -36,13 +41,20 @@ impl InitrdLoader {
         Ok(InitrdLoader { initrd })
     }
 
+    /// The initramfs is placed as high as possible in the ram, it must end at or below `limit`
     pub fn load(
         &self,
         ram_allocator: &mut RangeAllocator<u64>,
         memory: &MemoryAddressSpace,
-        addr: u64,
+        limit: u64,
     ) -> Result<InitrdLoadResult, InitrdLoaderError> {
-        ram_allocator.reserve(addr, self.initrd.len())?;
+        let addr = ram_allocator
+            .alloc_aligned_below(self.initrd.len(), INITRD_ALIGN, limit)
+            .map_err(|_| InitrdLoaderError::NoRoom {
+                len: self.initrd.len(),
+                limit,
+            })?
+            .start;
 
         memory.copy_from_slice(addr, &self.initrd)?;
 
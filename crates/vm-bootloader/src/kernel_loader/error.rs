@@ -9,6 +9,15 @@ pub enum KernelLoaderError {
     #[error("Invalid kernel image")]
     InvalidKernelImage,
 
+    #[error("Unknown kernel format")]
+    UnknownKernelFormat,
+
+    #[error("Unsupported kernel compression: {0}")]
+    UnsupportedCompression(String),
+
+    #[error("Failed to decompress kernel, reason: {0}")]
+    Decompress(String),
+
     #[error("Set kernel start offset is not supported")]
     KernelStartOffsetNotSupport,
 
@@ -32,4 +41,7 @@ pub enum KernelLoaderError {
 
     #[error("Cmdline too large")]
     CmdlineTooLarge,
+
+    #[error("The elf kernel has no pvh entry point")]
+    PvhEntryNotFound,
 }
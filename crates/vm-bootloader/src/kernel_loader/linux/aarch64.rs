@@ -1 +1,2 @@
+mod decompress;
 pub(crate) mod image;
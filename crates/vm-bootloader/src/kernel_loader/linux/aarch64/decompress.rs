@@ -0,0 +1,212 @@
+//! Distro kernels are shipped compressed, either as a bare compressed stream (e.g. Image.gz) or
+//! wrapped in an EFI zboot image which decompresses itself when booted by a uefi firmware.
+
+use std::io::Read;
+
+use flate2::read::MultiGzDecoder;
+use ruzstd::decoding::StreamingDecoder;
+
+use crate::kernel_loader::error::KernelLoaderError;
+
+const ARM64_IMAGE_MAGIC_OFFSET: usize = 0x38;
+const ARM64_IMAGE_MAGIC: &[u8] = b"ARM\x64";
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const LZ4_FRAME_MAGIC: &[u8] = &[0x04, 0x22, 0x4d, 0x18];
+const LZ4_LEGACY_MAGIC: &[u8] = &[0x02, 0x21, 0x4c, 0x18];
+const XZ_MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+
+// The blocks of the legacy lz4 frame decompress to at most 8MB
+const LZ4_LEGACY_BLOCK_SIZE: usize = 8 << 20;
+
+/// drivers/firmware/efi/libstub/zboot-header.S
+const ZBOOT_MAGIC_OFFSET: usize = 4;
+const ZBOOT_MAGIC: &[u8] = b"zimg";
+const ZBOOT_PAYLOAD_OFFSET: usize = 8;
+const ZBOOT_PAYLOAD_SIZE: usize = 12;
+const ZBOOT_COMP_TYPE_OFFSET: usize = 24;
+const ZBOOT_COMP_TYPE_LEN: usize = 32;
+
+fn read_u32(buf: &[u8], offset: usize) -> Result<u32, KernelLoaderError> {
+    buf.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or(KernelLoaderError::InvalidKernelImage)
+}
+
+fn decompress_error(err: impl ToString) -> KernelLoaderError {
+    KernelLoaderError::Decompress(err.to_string())
+}
+
+fn read_all(mut reader: impl Read) -> Result<Vec<u8>, KernelLoaderError> {
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf).map_err(decompress_error)?;
+
+    Ok(buf)
+}
+
+fn decompress_lz4_legacy(mut payload: &[u8]) -> Result<Vec<u8>, KernelLoaderError> {
+    let mut image = Vec::new();
+
+    while !payload.is_empty() {
+        // Several legacy frames may be concatenated
+        if payload.starts_with(LZ4_LEGACY_MAGIC) {
+            payload = &payload[LZ4_LEGACY_MAGIC.len()..];
+            continue;
+        }
+
+        let block_len = read_u32(payload, 0)? as usize;
+
+        let block = payload
+            .get(4..4 + block_len)
+            .ok_or_else(|| decompress_error("truncated lz4 block"))?;
+
+        let start = image.len();
+        image.resize(start + LZ4_LEGACY_BLOCK_SIZE, 0);
+        let len = lz4_flex::block::decompress_into(block, &mut image[start..])
+            .map_err(decompress_error)?;
+        image.truncate(start + len);
+
+        payload = &payload[4 + block_len..];
+    }
+
+    Ok(image)
+}
+
+/// Decompress a bare compressed stream, the format is told by its magic
+fn decompress_stream(payload: &[u8]) -> Result<Vec<u8>, KernelLoaderError> {
+    if payload.starts_with(GZIP_MAGIC) {
+        read_all(MultiGzDecoder::new(payload))
+    } else if payload.starts_with(ZSTD_MAGIC) {
+        let mut payload = payload;
+        read_all(StreamingDecoder::new(&mut payload).map_err(decompress_error)?)
+    } else if payload.starts_with(LZ4_FRAME_MAGIC) {
+        read_all(lz4_flex::frame::FrameDecoder::new(payload))
+    } else if payload.starts_with(LZ4_LEGACY_MAGIC) {
+        decompress_lz4_legacy(&payload[LZ4_LEGACY_MAGIC.len()..])
+    } else if payload.starts_with(XZ_MAGIC) {
+        let mut image = Vec::new();
+        lzma_rs::xz_decompress(&mut &payload[..], &mut image).map_err(decompress_error)?;
+        Ok(image)
+    } else {
+        Err(KernelLoaderError::UnknownKernelFormat)
+    }
+}
+
+/// The compressed payload of an EFI zboot image, the compression is named in its header
+fn decompress_zboot(kernel: &[u8]) -> Result<Vec<u8>, KernelLoaderError> {
+    let offset = read_u32(kernel, ZBOOT_PAYLOAD_OFFSET)? as usize;
+    let size = read_u32(kernel, ZBOOT_PAYLOAD_SIZE)? as usize;
+    let payload = kernel
+        .get(offset..offset + size)
+        .ok_or(KernelLoaderError::InvalidKernelImage)?;
+
+    let comp_type = kernel
+        .get(ZBOOT_COMP_TYPE_OFFSET..ZBOOT_COMP_TYPE_OFFSET + ZBOOT_COMP_TYPE_LEN)
+        .ok_or(KernelLoaderError::InvalidKernelImage)?;
+    let comp_type = comp_type.split(|b| *b == 0).next().unwrap();
+    let comp_type = String::from_utf8_lossy(comp_type);
+
+    match comp_type.as_ref() {
+        "gzip" | "zstd22" | "lz4" | "xzkern" => decompress_stream(payload),
+        // The .lzma format has no magic
+        "lzma" => {
+            let mut image = Vec::new();
+            lzma_rs::lzma_decompress(&mut &payload[..], &mut image).map_err(decompress_error)?;
+            Ok(image)
+        }
+        _ => Err(KernelLoaderError::UnsupportedCompression(
+            comp_type.into_owned(),
+        )),
+    }
+}
+
+fn is_arm64_image(kernel: &[u8]) -> bool {
+    kernel.get(ARM64_IMAGE_MAGIC_OFFSET..ARM64_IMAGE_MAGIC_OFFSET + ARM64_IMAGE_MAGIC.len())
+        == Some(ARM64_IMAGE_MAGIC)
+}
+
+/// Returns the arm64 Image, the kernel is returned as is if it is not compressed
+pub fn decompress(kernel: Vec<u8>) -> Result<Vec<u8>, KernelLoaderError> {
+    // An Image with the efi stub starts with "MZ" as well, so it is checked first
+    if is_arm64_image(&kernel) {
+        return Ok(kernel);
+    }
+
+    let image = if kernel.starts_with(b"MZ")
+        && kernel.get(ZBOOT_MAGIC_OFFSET..ZBOOT_MAGIC_OFFSET + ZBOOT_MAGIC.len())
+            == Some(ZBOOT_MAGIC)
+    {
+        decompress_zboot(&kernel)?
+    } else {
+        decompress_stream(&kernel)?
+    };
+
+    if !is_arm64_image(&image) {
+        return Err(KernelLoaderError::InvalidKernelImage);
+    }
+
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+
+    use super::*;
+
+    fn image() -> Vec<u8> {
+        let mut image = vec![0xaa; 0x1000];
+        image[ARM64_IMAGE_MAGIC_OFFSET..ARM64_IMAGE_MAGIC_OFFSET + 4]
+            .copy_from_slice(ARM64_IMAGE_MAGIC);
+        image
+    }
+
+    fn gzip(buf: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(buf).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_decompress() {
+        assert_eq!(decompress(image()).unwrap(), image());
+        assert_eq!(decompress(gzip(&image())).unwrap(), image());
+
+        let mut lz4 = LZ4_LEGACY_MAGIC.to_vec();
+        let block = lz4_flex::block::compress(&image());
+        lz4.extend_from_slice(&(block.len() as u32).to_le_bytes());
+        lz4.extend_from_slice(&block);
+        assert_eq!(decompress(lz4).unwrap(), image());
+
+        assert!(matches!(
+            decompress(vec![0; 0x100]),
+            Err(KernelLoaderError::UnknownKernelFormat)
+        ));
+    }
+
+    #[test]
+    fn test_decompress_zboot() {
+        let payload = gzip(&image());
+
+        let mut zboot = vec![0; 0x200];
+        zboot[..2].copy_from_slice(b"MZ");
+        zboot[ZBOOT_MAGIC_OFFSET..ZBOOT_MAGIC_OFFSET + 4].copy_from_slice(ZBOOT_MAGIC);
+        zboot[ZBOOT_PAYLOAD_OFFSET..ZBOOT_PAYLOAD_OFFSET + 4]
+            .copy_from_slice(&0x200u32.to_le_bytes());
+        zboot[ZBOOT_PAYLOAD_SIZE..ZBOOT_PAYLOAD_SIZE + 4]
+            .copy_from_slice(&(payload.len() as u32).to_le_bytes());
+        zboot[ZBOOT_COMP_TYPE_OFFSET..ZBOOT_COMP_TYPE_OFFSET + 4].copy_from_slice(b"gzip");
+        zboot.extend_from_slice(&payload);
+        assert_eq!(decompress(zboot.clone()).unwrap(), image());
+
+        zboot[ZBOOT_COMP_TYPE_OFFSET..ZBOOT_COMP_TYPE_OFFSET + 4].copy_from_slice(b"lzo\0");
+        assert!(matches!(
+            decompress(zboot),
+            Err(KernelLoaderError::UnsupportedCompression(comp_type)) if comp_type == "lzo"
+        ));
+    }
+}
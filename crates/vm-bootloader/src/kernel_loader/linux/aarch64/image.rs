@@ -11,6 +11,7 @@ use vm_utils::range_allocator::RangeAllocator;
 use zerocopy::FromBytes;
 
 use crate::kernel_loader::error::KernelLoaderError;
+use crate::kernel_loader::linux::aarch64::decompress::decompress;
 
 const DEFAULT_TEXT_OFFSET: u64 = 0x80000;
 
@@ -44,6 +45,7 @@ pub struct Image {
 impl Image {
     pub fn new(kernel: &Path) -> Result<Self, KernelLoaderError> {
         let kernel = fs::read(kernel).map_err(|_| KernelLoaderError::ReadFailed)?;
+        let kernel = decompress(kernel)?;
 
         let image = Image { kernel };
 
@@ -1,2 +1,3 @@
 pub(crate) mod bzimage;
+pub(crate) mod pvh;
 pub(crate) mod zero_page;
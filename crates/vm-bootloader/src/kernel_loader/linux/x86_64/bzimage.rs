@@ -12,18 +12,43 @@ use crate::kernel_loader::error::KernelLoaderError;
 use crate::kernel_loader::linux::x86_64::zero_page::SetupHeader;
 
 const MINIMAL_VERSION: u16 = 0x206;
+// The first version with `init_size`
+const INIT_SIZE_VERSION: u16 = 0x20a;
 
 pub struct LoadResult {
     pub start_pc: u32,
     pub setup_hdr: SetupHeader,
 }
 
+impl LoadResult {
+    /// The initramfs must end at or below it
+    pub fn initrd_limit(&self) -> u64 {
+        self.setup_hdr.initrd_addr_max as u64 + 1
+    }
+
+    pub fn setup_initrd(&mut self, initrd: &InitrdLoadResult) -> Result<(), KernelLoaderError> {
+        if initrd.initrd_start + initrd.initrd_len as u64 > self.initrd_limit() {
+            return Err(KernelLoaderError::InitramfsAddressTooHigh);
+        }
+
+        self.setup_hdr.ramdisk_image = initrd
+            .initrd_start
+            .try_into()
+            .map_err(|_| KernelLoaderError::InitramfsAddressTooHigh)?;
+        self.setup_hdr.ramdisk_size = initrd
+            .initrd_len
+            .try_into()
+            .map_err(|_| KernelLoaderError::InitramfsTooLarge)?;
+
+        Ok(())
+    }
+}
+
 pub struct BzImageBootParams {
     // pub heap_end: u32,
     pub cmdline_start: u32,
     pub cmdline_len: u32,
     pub kernel_start: u32,
-    pub initrd: Option<InitrdLoadResult>,
 }
 
 pub struct BzImage {
@@ -97,21 +122,6 @@ impl BzImage {
         // If booting 64-bit linux, we should plus 0x200 offset.
         let kernel_start = setup_hdr.code32_start;
 
-        if let Some(initrd) = &params.initrd {
-            if initrd.initrd_start + initrd.initrd_len as u64 > setup_hdr.initrd_addr_max as u64 {
-                return Err(KernelLoaderError::InitramfsAddressTooHigh);
-            }
-
-            setup_hdr.ramdisk_image = initrd
-                .initrd_start
-                .try_into()
-                .map_err(|_| KernelLoaderError::InitramfsAddressTooHigh)?;
-            setup_hdr.ramdisk_size = initrd
-                .initrd_len
-                .try_into()
-                .map_err(|_| KernelLoaderError::InitramfsTooLarge)?;
-        }
-
         {
             if params.cmdline_len > setup_hdr.cmdline_size {
                 return Err(KernelLoaderError::CmdlineTooLarge);
@@ -128,7 +138,13 @@ impl BzImage {
 
             let setup_size = (setup_sects as usize + 1) * 0x200;
             let kernel_len = self.bzimage.len() - setup_size;
-            let range = ram_allocator.reserve(params.kernel_start as u64, kernel_len)?;
+            // The kernel decompresses itself in place, it needs `init_size` bytes from its start
+            let reserved_len = if version >= INIT_SIZE_VERSION {
+                kernel_len.max(setup_hdr.init_size as usize)
+            } else {
+                kernel_len
+            };
+            let range = ram_allocator.reserve(params.kernel_start as u64, reserved_len)?;
             memory.copy_from_slice(range.start, &self.bzimage[setup_size..])?;
         }
 
@@ -0,0 +1,337 @@
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use vm_mm::manager::MemoryAddressSpace;
+use vm_utils::range_allocator::RangeAllocator;
+use zerocopy::FromBytes;
+use zerocopy::Immutable;
+use zerocopy::IntoBytes;
+use zerocopy::KnownLayout;
+
+use crate::initrd_loader::InitrdLoadResult;
+use crate::kernel_loader::error::KernelLoaderError;
+use crate::kernel_loader::linux::x86_64::zero_page::E820Type;
+
+const ELF_MAGIC: [u8; 4] = *b"\x7fELF";
+const ELF_CLASS_64: u8 = 2;
+const ELF_DATA_LSB: u8 = 1;
+const ELF_MACHINE_X86_64: u16 = 62;
+
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+
+const XEN_ELFNOTE_NAME: &[u8] = b"Xen\0";
+const XEN_ELFNOTE_PHYS32_ENTRY: u32 = 18;
+
+const HVM_START_MAGIC_VALUE: u32 = 0x336ec578;
+
+#[derive(FromBytes, KnownLayout, Immutable)]
+#[repr(C)]
+struct Elf64Ehdr {
+    e_ident: [u8; 16],
+    e_type: u16,
+    e_machine: u16,
+    e_version: u32,
+    e_entry: u64,
+    e_phoff: u64,
+    e_shoff: u64,
+    e_flags: u32,
+    e_ehsize: u16,
+    e_phentsize: u16,
+    e_phnum: u16,
+    e_shentsize: u16,
+    e_shnum: u16,
+    e_shstrndx: u16,
+}
+
+#[derive(FromBytes, KnownLayout, Immutable)]
+#[repr(C)]
+struct Elf64Phdr {
+    p_type: u32,
+    p_flags: u32,
+    p_offset: u64,
+    p_vaddr: u64,
+    p_paddr: u64,
+    p_filesz: u64,
+    p_memsz: u64,
+    p_align: u64,
+}
+
+#[derive(FromBytes, KnownLayout, Immutable)]
+#[repr(C)]
+struct Elf64Nhdr {
+    n_namesz: u32,
+    n_descsz: u32,
+    n_type: u32,
+}
+
+#[derive(Immutable, IntoBytes)]
+#[repr(C)]
+struct HvmStartInfo {
+    magic: u32,
+    version: u32,
+    flags: u32,
+    nr_modules: u32,
+    modlist_paddr: u64,
+    cmdline_paddr: u64,
+    rsdp_paddr: u64,
+    memmap_paddr: u64,
+    memmap_entries: u32,
+    reserved: u32,
+}
+
+#[derive(Immutable, IntoBytes)]
+#[repr(C)]
+struct HvmModlistEntry {
+    paddr: u64,
+    size: u64,
+    cmdline_paddr: u64,
+    reserved: u64,
+}
+
+#[derive(Immutable, IntoBytes)]
+#[repr(C)]
+pub struct HvmMemmapEntry {
+    addr: u64,
+    size: u64,
+    r#type: u32,
+    reserved: u32,
+}
+
+impl HvmMemmapEntry {
+    pub(crate) fn new(addr: u64, size: u64, r#type: E820Type) -> Self {
+        HvmMemmapEntry {
+            addr,
+            size,
+            r#type: r#type as u32,
+            reserved: 0,
+        }
+    }
+}
+
+pub struct LoadResult {
+    /// The 32-bit entry point of `XEN_ELFNOTE_PHYS32_ENTRY`
+    pub start_pc: u32,
+}
+
+pub struct StartInfoParams<'a> {
+    pub cmdline_start: u32,
+    pub rsdp_addr: u32,
+    pub initrd: Option<&'a InitrdLoadResult>,
+    pub memmap: Vec<HvmMemmapEntry>,
+}
+
+/// An uncompressed vmlinux booted through its pvh entry point
+pub struct Pvh {
+    vmlinux: Vec<u8>,
+}
+
+impl Pvh {
+    /// Whether the kernel is an elf, a bzImage is loaded otherwise
+    pub fn is_elf(path: &Path) -> Result<bool, KernelLoaderError> {
+        let mut magic = [0; 4];
+        File::open(path)
+            .and_then(|mut file| file.read_exact(&mut magic))
+            .map_err(|_| KernelLoaderError::ReadFailed)?;
+
+        Ok(magic == ELF_MAGIC)
+    }
+
+    pub fn new(path: &Path) -> Result<Self, KernelLoaderError> {
+        let vmlinux = fs::read(path).map_err(|_| KernelLoaderError::ReadFailed)?;
+
+        Ok(Pvh { vmlinux })
+    }
+
+    fn program_headers(&self) -> Result<Vec<Elf64Phdr>, KernelLoaderError> {
+        let (ehdr, _) = Elf64Ehdr::read_from_prefix(&self.vmlinux)
+            .map_err(|_| KernelLoaderError::InvalidKernelImage)?;
+
+        if ehdr.e_ident[..4] != ELF_MAGIC
+            || ehdr.e_ident[4] != ELF_CLASS_64
+            || ehdr.e_ident[5] != ELF_DATA_LSB
+            || ehdr.e_machine != ELF_MACHINE_X86_64
+            || ehdr.e_phentsize as usize != size_of::<Elf64Phdr>()
+        {
+            return Err(KernelLoaderError::InvalidKernelImage);
+        }
+
+        (0..ehdr.e_phnum as usize)
+            .map(|i| {
+                let offset = ehdr.e_phoff as usize + i * size_of::<Elf64Phdr>();
+                let (phdr, _) = Elf64Phdr::read_from_prefix(
+                    self.vmlinux
+                        .get(offset..)
+                        .ok_or(KernelLoaderError::InvalidKernelImage)?,
+                )
+                .map_err(|_| KernelLoaderError::InvalidKernelImage)?;
+
+                Ok(phdr)
+            })
+            .collect()
+    }
+
+    fn segment(&self, phdr: &Elf64Phdr) -> Result<&[u8], KernelLoaderError> {
+        let start = phdr.p_offset as usize;
+        let end = start
+            .checked_add(phdr.p_filesz as usize)
+            .ok_or(KernelLoaderError::InvalidKernelImage)?;
+
+        self.vmlinux
+            .get(start..end)
+            .ok_or(KernelLoaderError::InvalidKernelImage)
+    }
+
+    fn find_pvh_entry(&self, notes: &[u8]) -> Option<u32> {
+        let align4 = |len: u32| (len as usize).next_multiple_of(4);
+
+        let mut notes = notes;
+        while let Ok((nhdr, rest)) = Elf64Nhdr::read_from_prefix(notes) {
+            let name_len = align4(nhdr.n_namesz);
+            let desc_len = align4(nhdr.n_descsz);
+
+            let name = rest.get(..nhdr.n_namesz as usize)?;
+            let desc = rest.get(name_len..name_len + nhdr.n_descsz as usize)?;
+
+            // The entry is a 32-bit physical address though it may be stored in 8 bytes
+            if name == XEN_ELFNOTE_NAME && nhdr.n_type == XEN_ELFNOTE_PHYS32_ENTRY {
+                return Some(u32::from_le_bytes(desc.get(..4)?.try_into().unwrap()));
+            }
+
+            notes = rest.get(name_len + desc_len..)?;
+        }
+
+        None
+    }
+
+    pub fn load(
+        &self,
+        ram_allocator: &mut RangeAllocator<u64>,
+        memory: &MemoryAddressSpace,
+    ) -> Result<LoadResult, KernelLoaderError> {
+        let mut start_pc = None;
+
+        for phdr in self.program_headers()? {
+            match phdr.p_type {
+                PT_LOAD => {
+                    let segment = self.segment(&phdr)?;
+                    if phdr.p_memsz < phdr.p_filesz {
+                        return Err(KernelLoaderError::InvalidKernelImage);
+                    }
+
+                    let range = ram_allocator.reserve(phdr.p_paddr, phdr.p_memsz as usize)?;
+                    memory.copy_from_slice(range.start, segment)?;
+
+                    // The bss may be left dirty by the previous boot
+                    let bss_len = (phdr.p_memsz - phdr.p_filesz) as usize;
+                    if bss_len > 0 {
+                        memory.copy_from_slice(range.start + phdr.p_filesz, &vec![0; bss_len])?;
+                    }
+                }
+                PT_NOTE if start_pc.is_none() => {
+                    start_pc = self.find_pvh_entry(self.segment(&phdr)?);
+                }
+                _ => (),
+            }
+        }
+
+        Ok(LoadResult {
+            start_pc: start_pc.ok_or(KernelLoaderError::PvhEntryNotFound)?,
+        })
+    }
+
+    /// Write the `hvm_start_info` followed by the module list and the memory map at `addr`,
+    /// its address is passed to the kernel in ebx.
+    pub fn setup_start_info(
+        &self,
+        ram_allocator: &mut RangeAllocator<u64>,
+        memory: &MemoryAddressSpace,
+        addr: u64,
+        params: StartInfoParams,
+    ) -> Result<u64, KernelLoaderError> {
+        let modlist_paddr = addr + size_of::<HvmStartInfo>() as u64;
+        let modlist = params
+            .initrd
+            .map(|initrd| HvmModlistEntry {
+                paddr: initrd.initrd_start,
+                size: initrd.initrd_len as u64,
+                cmdline_paddr: 0,
+                reserved: 0,
+            })
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        let memmap_paddr = modlist_paddr + modlist.as_bytes().len() as u64;
+
+        let start_info = HvmStartInfo {
+            magic: HVM_START_MAGIC_VALUE,
+            version: 1,
+            flags: 0,
+            nr_modules: modlist.len() as u32,
+            modlist_paddr: if modlist.is_empty() { 0 } else { modlist_paddr },
+            cmdline_paddr: params.cmdline_start as u64,
+            rsdp_paddr: params.rsdp_addr as u64,
+            memmap_paddr,
+            memmap_entries: params.memmap.len() as u32,
+            reserved: 0,
+        };
+
+        let buf = [
+            start_info.as_bytes(),
+            modlist.as_bytes(),
+            params.memmap.as_bytes(),
+        ]
+        .concat();
+
+        let range = ram_allocator.reserve(addr, buf.len())?;
+        memory.copy_from_slice(range.start, &buf)?;
+
+        Ok(range.start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(name: &[u8], r#type: u32, desc: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&r#type.to_le_bytes());
+        buf.extend_from_slice(name);
+        buf.resize(buf.len().next_multiple_of(4), 0);
+        buf.extend_from_slice(desc);
+        buf.resize(buf.len().next_multiple_of(4), 0);
+        buf
+    }
+
+    #[test]
+    fn test_find_pvh_entry() {
+        let pvh = Pvh { vmlinux: vec![] };
+
+        let notes = [
+            note(b"GNU\0", 3, &[0xaa; 20]),
+            note(XEN_ELFNOTE_NAME, 1, &[0; 8]),
+            note(
+                XEN_ELFNOTE_NAME,
+                XEN_ELFNOTE_PHYS32_ENTRY,
+                &0x0100_0000u64.to_le_bytes(),
+            ),
+        ]
+        .concat();
+        assert_eq!(pvh.find_pvh_entry(&notes), Some(0x0100_0000));
+
+        let notes = note(b"GNU\0", 3, &[0xaa; 20]);
+        assert_eq!(pvh.find_pvh_entry(&notes), None);
+    }
+
+    #[test]
+    fn test_start_info_layout() {
+        assert_eq!(size_of::<HvmStartInfo>(), 56);
+        assert_eq!(size_of::<HvmModlistEntry>(), 32);
+        assert_eq!(size_of::<HvmMemmapEntry>(), 24);
+    }
+}
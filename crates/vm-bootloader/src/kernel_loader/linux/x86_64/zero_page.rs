@@ -1,6 +1,7 @@
 use std::cell::OnceCell;
 
 use thiserror::Error;
+use vm_core::arch::x86_64::layout::MEMORY_HOTPLUG_START;
 use vm_mm::manager::MemoryAddressSpace;
 use zerocopy::FromBytes;
 use zerocopy::FromZeros;
@@ -159,7 +160,7 @@ pub struct SetupHeader {
 
 #[allow(dead_code)]
 #[repr(u32)]
-enum E820Type {
+pub(crate) enum E820Type {
     Ram = 1,
     Reserved = 2,
     Acpi = 3,
@@ -477,7 +478,8 @@ impl ZeroPageBuilder {
 
         let mut index = 0;
 
-        for region in memory.regions().values() {
+        // Hotpluggable memory is discovered through virtio-mem instead
+        for (_, region) in memory.regions().range(..MEMORY_HOTPLUG_START) {
             e820_table[index] = BootE820Entry {
                 addr: region.gpa,
                 size: region.len() as u64,
@@ -1,2 +1 @@
-#[cfg(target_arch = "x86_64")]
 pub(crate) mod aml;
@@ -1,10 +1,14 @@
 use std::path::PathBuf;
 
+use clap::Args;
 use clap::Parser;
 use clap::Subcommand;
 
+use crate::cmd::run::RunArgs;
+
 pub mod device;
 pub mod json;
+pub mod run;
 
 #[derive(Debug, Parser)]
 pub struct Cli {
@@ -19,7 +23,52 @@ pub enum Command {
         path: PathBuf,
     },
 
-    Snapshot {
+    /// Configure the vm with flags instead of a json file
+    Run(Box<RunArgs>),
+
+    /// Restore the vm from a snapshot
+    Snapshot(SnapshotArgs),
+
+    /// Check a config without creating the vm
+    Validate {
+        #[command(subcommand)]
+        config: ValidateCommand,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ValidateCommand {
+    Json {
+        #[arg(long)]
+        path: PathBuf,
+    },
+
+    Run(Box<RunArgs>),
+}
+
+#[derive(Debug, Args)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+pub struct SnapshotArgs {
+    #[command(subcommand)]
+    pub command: Option<SnapshotCommand>,
+
+    #[arg(long, required = true)]
+    pub path: Option<PathBuf>,
+
+    /// A json merge patch of the saved config, only the host side resources may change, e.g.
+    /// `{"gdb_port": 1235}`
+    #[arg(long = "override")]
+    pub config_override: Option<String>,
+
+    /// Where the monitor of the restored vm listens, e.g. to run it next to the saved one
+    #[arg(long)]
+    pub monitor_socket: Option<PathBuf>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SnapshotCommand {
+    /// Print the header and the device sections of a snapshot
+    Inspect {
         #[arg(long)]
         path: PathBuf,
     },
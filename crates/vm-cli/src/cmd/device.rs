@@ -1,36 +1,108 @@
-#[cfg(target_os = "linux")]
 use std::path::PathBuf;
 
 use serde::Deserialize;
 use vm_device::device::VirtioTransport;
+use vm_device::device::virtio::virtio_9p::id_map::IdMap;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct VirtioBlk {
+    path: PathBuf,
+    #[serde(default)]
+    read_only: bool,
+}
+
+impl VirtioBlk {
+    fn into_device(self, transport: VirtioTransport) -> vm_device::device::Device {
+        vm_device::device::Device::VirtioBlk {
+            transport,
+            path: self.path,
+            read_only: self.read_only,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Virtio9p {
+    tag: String,
+    path: PathBuf,
+    #[serde(default)]
+    read_only: bool,
+    #[serde(default)]
+    uid_map: Vec<IdMap>,
+    #[serde(default)]
+    gid_map: Vec<IdMap>,
+}
+
+impl Virtio9p {
+    fn into_device(self, transport: VirtioTransport) -> vm_device::device::Device {
+        vm_device::device::Device::Virtio9p {
+            transport,
+            tag: self.tag,
+            path: self.path,
+            read_only: self.read_only,
+            uid_map: self.uid_map,
+            gid_map: self.gid_map,
+        }
+    }
+}
+
+fn default_virtio_mem_block_size() -> usize {
+    2 << 20
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct VirtioMem {
+    region_size: usize,
+    #[serde(default = "default_virtio_mem_block_size")]
+    block_size: usize,
+    #[serde(default)]
+    requested_size: usize,
+}
+
+impl VirtioMem {
+    fn into_device(self, transport: VirtioTransport) -> vm_device::device::Device {
+        vm_device::device::Device::VirtioMem {
+            transport,
+            region_size: self.region_size,
+            block_size: self.block_size,
+            requested_size: self.requested_size,
+        }
+    }
+}
 
 #[derive(Debug, Clone, Deserialize)]
 pub enum Device {
     GicV3,
-    VirtioMmioBlk,
-    VirtioPciBlk,
+    VirtioMmioBlk(VirtioBlk),
+    VirtioPciBlk(VirtioBlk),
     VirtioMmioBalloon,
     VirtioMmioEntropy,
     VirtioPciEntropy,
     VirtioPciGpu,
     VirtioMmioGpu,
+    VirtioMmio9p(Virtio9p),
+    VirtioPci9p(Virtio9p),
+    VirtioMmioMem(VirtioMem),
+    VirtioPciMem(VirtioMem),
+    VirtioMmioIommu,
     #[cfg(target_os = "linux")]
     VfioPci {
         name: String,
         path: PathBuf,
     },
+    #[cfg(target_os = "linux")]
+    VfioUser {
+        name: String,
+        socket: PathBuf,
+    },
 }
 
 impl From<Device> for vm_device::device::Device {
     fn from(device: Device) -> Self {
         match device {
             Device::GicV3 => vm_device::device::Device::GicV3,
-            Device::VirtioMmioBlk => vm_device::device::Device::VirtioBlk {
-                transport: VirtioTransport::Mmio,
-            },
-            Device::VirtioPciBlk => vm_device::device::Device::VirtioBlk {
-                transport: VirtioTransport::Pci,
-            },
+            Device::VirtioMmioBlk(blk) => blk.into_device(VirtioTransport::Mmio),
+            Device::VirtioPciBlk(blk) => blk.into_device(VirtioTransport::Pci),
             Device::VirtioMmioBalloon => vm_device::device::Device::VirtioBalloon {
                 transport: VirtioTransport::Mmio,
             },
@@ -46,8 +118,17 @@ impl From<Device> for vm_device::device::Device {
             Device::VirtioPciGpu => vm_device::device::Device::VirtioGpu {
                 transport: VirtioTransport::Pci,
             },
+            Device::VirtioMmio9p(p9) => p9.into_device(VirtioTransport::Mmio),
+            Device::VirtioPci9p(p9) => p9.into_device(VirtioTransport::Pci),
+            Device::VirtioMmioMem(mem) => mem.into_device(VirtioTransport::Mmio),
+            Device::VirtioPciMem(mem) => mem.into_device(VirtioTransport::Pci),
+            Device::VirtioMmioIommu => vm_device::device::Device::VirtioIommu,
             #[cfg(target_os = "linux")]
             Device::VfioPci { name, path } => vm_device::device::Device::VfioPci { name, path },
+            #[cfg(target_os = "linux")]
+            Device::VfioUser { name, socket } => {
+                vm_device::device::Device::VfioUser { name, socket }
+            }
         }
     }
 }
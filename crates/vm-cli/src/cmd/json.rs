@@ -1,27 +1,78 @@
 use std::path::PathBuf;
 
 use serde::Deserialize;
+use vm_device::device::rtc::RtcConfig;
+use vm_device::device::serial::SerialConfig;
+use vm_utils::cpu_topology::CpuTopology;
+use vm_vmm::vm::config::FirmwareConfig;
+use vm_vmm::vm::config::NumaNodeConfig;
 use vm_vmm::vm::config::VmConfig;
 
 use crate::cmd::device::Device;
 use crate::error::Error;
 
+#[derive(Debug, Deserialize)]
+pub struct NumaNodeArgs {
+    cpus: Vec<u32>,
+
+    memory: String,
+
+    #[serde(default)]
+    distances: Vec<u8>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct CreateArgs {
     cpus: usize,
 
+    /// The cpus above `cpus` can be plugged at runtime
+    max_cpus: Option<usize>,
+
+    /// The sockets, cores and threads of the cpus
+    topology: Option<CpuTopology>,
+
+    #[serde(default)]
+    numa: Vec<NumaNodeArgs>,
+
     memory: String,
 
     #[serde(default)]
     device: Vec<Device>,
 
-    kernel: PathBuf,
+    #[serde(default)]
+    pci_hotplug_slots: usize,
+
+    /// Size of the 64-bit prefetchable pci window, e.g. "64G"
+    pci_mmio64_size: Option<String>,
+
+    kernel: Option<PathBuf>,
+
+    /// Boot the firmware instead of a kernel
+    firmware: Option<PathBuf>,
+
+    /// The variable store of the firmware
+    firmware_vars: Option<PathBuf>,
 
     cmdline: Option<String>,
 
     initramfs: Option<PathBuf>,
 
     gdb: Option<u16>,
+
+    #[serde(default)]
+    rtc: RtcConfig,
+
+    #[serde(default)]
+    serial: SerialConfig,
+
+    /// Zero the memory when the guest reboots
+    #[serde(default)]
+    clear_memory_on_reset: bool,
+
+    /// Boot with acpi tables instead of the device tree
+    #[cfg(target_arch = "aarch64")]
+    #[serde(default)]
+    acpi: bool,
 }
 
 impl TryInto<VmConfig> for CreateArgs {
@@ -31,18 +82,47 @@ impl TryInto<VmConfig> for CreateArgs {
         let vm_config = VmConfig {
             memory_size: parse_memory(&self.memory)?,
             vcpus: self.cpus,
+            max_vcpus: self.max_cpus,
+            cpu_topology: self.topology,
+            numa_nodes: self
+                .numa
+                .into_iter()
+                .map(|node| {
+                    Ok(NumaNodeConfig {
+                        vcpus: node.cpus,
+                        memory_size: parse_memory(&node.memory)?,
+                        distances: node.distances,
+                    })
+                })
+                .collect::<Result<_, Error>>()?,
             devices: self.device.into_iter().map(Into::into).collect(),
+            pci_hotplug_slots: self.pci_hotplug_slots,
+            pci_mmio64_size: self
+                .pci_mmio64_size
+                .as_deref()
+                .map(parse_memory)
+                .transpose()?
+                .unwrap_or_default(),
             gdb_port: self.gdb,
             kernel: self.kernel,
+            firmware: self.firmware.map(|code| FirmwareConfig {
+                code,
+                vars: self.firmware_vars,
+            }),
             initramfs: self.initramfs,
             cmdline: self.cmdline,
+            rtc: self.rtc,
+            serial: self.serial,
+            clear_memory_on_reset: self.clear_memory_on_reset,
+            #[cfg(target_arch = "aarch64")]
+            acpi: self.acpi,
         };
 
         Ok(vm_config)
     }
 }
 
-fn parse_memory(s: &str) -> Result<usize, Error> {
+pub(crate) fn parse_memory(s: &str) -> Result<usize, Error> {
     let s = s.trim().to_lowercase();
 
     let pos = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
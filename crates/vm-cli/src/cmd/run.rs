@@ -0,0 +1,479 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use vm_device::device::Device;
+use vm_device::device::VirtioTransport;
+use vm_device::device::rtc::RtcBase;
+use vm_device::device::rtc::RtcConfig;
+use vm_device::device::serial::SerialConfig;
+use vm_utils::cpu_topology::CpuTopology;
+use vm_vmm::vm::config::FirmwareConfig;
+use vm_vmm::vm::config::NumaNodeConfig;
+use vm_vmm::vm::config::VmConfig;
+
+use crate::cmd::json::parse_memory;
+use crate::error::Error;
+
+/// The vm described by the command line, it gives the same `VmConfig` as a json config
+#[derive(Debug, Args)]
+pub struct RunArgs {
+    /// The vcpus at boot
+    #[arg(long)]
+    cpus: usize,
+
+    /// The vcpus above `--cpus` can be plugged at runtime
+    #[arg(long)]
+    max_cpus: Option<usize>,
+
+    /// sockets=N,cores=N,threads=N
+    #[arg(long)]
+    topology: Option<String>,
+
+    /// e.g. 1G
+    #[arg(long)]
+    memory: String,
+
+    /// cpus=0-1:4,memory=1G[,distances=10:20], the ram is split between the nodes in order
+    #[arg(long)]
+    numa: Vec<String>,
+
+    #[arg(long)]
+    kernel: Option<PathBuf>,
+
+    #[arg(long)]
+    initramfs: Option<PathBuf>,
+
+    #[arg(long)]
+    cmdline: Option<String>,
+
+    /// Boot the firmware instead of a kernel
+    #[arg(long)]
+    firmware: Option<PathBuf>,
+
+    /// The variable store of the firmware
+    #[arg(long)]
+    firmware_vars: Option<PathBuf>,
+
+    /// path=disk.img[,readonly=on][,transport=pci|mmio], a raw image
+    #[arg(long)]
+    disk: Vec<String>,
+
+    /// tag=share,path=dir[,readonly=on][,transport=pci|mmio], a virtio-9p shared directory
+    #[arg(long)]
+    fs: Vec<String>,
+
+    /// The other devices, e.g. `virtio-rng,transport=mmio` or `virtio-mem,size=1G`
+    #[arg(long)]
+    device: Vec<String>,
+
+    /// Pcie root ports left empty for `device_add`
+    #[arg(long, default_value_t = 0)]
+    pci_hotplug_slots: usize,
+
+    /// Size of the 64-bit prefetchable pci window, e.g. 64G
+    #[arg(long)]
+    pci_mmio64_size: Option<String>,
+
+    #[arg(long)]
+    gdb: Option<u16>,
+
+    /// base=utc|localtime[,offset=SECONDS]
+    #[arg(long)]
+    rtc: Option<String>,
+
+    /// tty|off|file=PATH, where the console of the guest is connected
+    #[arg(long)]
+    serial: Option<String>,
+
+    /// Zero the memory when the guest reboots
+    #[arg(long)]
+    clear_memory_on_reset: bool,
+
+    /// Boot with acpi tables instead of the device tree
+    #[cfg(target_arch = "aarch64")]
+    #[arg(long)]
+    acpi: bool,
+}
+
+/// The `key=value` pairs of an option, a leading item without a value is its kind
+struct Options<'a> {
+    option: &'static str,
+    value: &'a str,
+    kind: Option<&'a str>,
+    pairs: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> Options<'a> {
+    fn parse(option: &'static str, value: &'a str) -> Result<Self, Error> {
+        let mut options = Options {
+            option,
+            value,
+            kind: None,
+            pairs: Vec::new(),
+        };
+
+        for (i, item) in value.split(',').enumerate() {
+            match item.split_once('=') {
+                Some((key, val)) => options.pairs.push((key, val)),
+                None if i == 0 && !item.is_empty() => options.kind = Some(item),
+                None => return Err(options.error(format!("{item:?} is not a key=value pair"))),
+            }
+        }
+
+        Ok(options)
+    }
+
+    fn error(&self, reason: String) -> Error {
+        Error::InvalidOption {
+            option: self.option,
+            value: self.value.to_string(),
+            reason,
+        }
+    }
+
+    fn take(&mut self, key: &str) -> Option<&'a str> {
+        let index = self.pairs.iter().position(|(k, _)| *k == key)?;
+
+        Some(self.pairs.remove(index).1)
+    }
+
+    fn required(&mut self, key: &str) -> Result<&'a str, Error> {
+        self.take(key)
+            .ok_or_else(|| self.error(format!("{key} is missing")))
+    }
+
+    fn parse_value<T>(&mut self, key: &str) -> Result<Option<T>, Error>
+    where
+        T: std::str::FromStr,
+    {
+        self.take(key)
+            .map(|val| {
+                val.parse()
+                    .map_err(|_| self.error(format!("invalid {key} {val:?}")))
+            })
+            .transpose()
+    }
+
+    fn memory(&mut self, key: &str) -> Result<Option<usize>, Error> {
+        self.take(key).map(parse_memory).transpose()
+    }
+
+    fn flag(&mut self, key: &str) -> Result<bool, Error> {
+        match self.take(key) {
+            None | Some("off" | "false") => Ok(false),
+            Some("on" | "true") => Ok(true),
+            Some(val) => Err(self.error(format!("{key} is on or off, got {val:?}"))),
+        }
+    }
+
+    fn transport(&mut self) -> Result<VirtioTransport, Error> {
+        match self.take("transport") {
+            None | Some("pci") => Ok(VirtioTransport::Pci),
+            Some("mmio") => Ok(VirtioTransport::Mmio),
+            Some(val) => Err(self.error(format!("transport is pci or mmio, got {val:?}"))),
+        }
+    }
+
+    /// Every key has to be known
+    fn finish(self) -> Result<(), Error> {
+        match self.pairs.first() {
+            Some((key, _)) => Err(self.error(format!("unknown key {key}"))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// e.g. `0-1:4`
+fn parse_cpu_list(options: &Options, list: &str) -> Result<Vec<u32>, Error> {
+    let mut cpus = Vec::new();
+
+    for range in list.split(':') {
+        let invalid = || options.error(format!("invalid cpu list {list:?}"));
+
+        match range.split_once('-') {
+            Some((start, end)) => {
+                let start = start.parse::<u32>().map_err(|_| invalid())?;
+                let end = end.parse::<u32>().map_err(|_| invalid())?;
+                cpus.extend(start..=end);
+            }
+            None => cpus.push(range.parse().map_err(|_| invalid())?),
+        }
+    }
+
+    Ok(cpus)
+}
+
+fn parse_topology(value: &str) -> Result<CpuTopology, Error> {
+    let mut options = Options::parse("topology", value)?;
+
+    let topology = CpuTopology {
+        sockets: options.parse_value("sockets")?.unwrap_or(1),
+        cores_per_socket: options.parse_value("cores")?.unwrap_or(1),
+        threads_per_core: options.parse_value("threads")?.unwrap_or(1),
+    };
+    options.finish()?;
+
+    Ok(topology)
+}
+
+fn parse_numa_node(value: &str) -> Result<NumaNodeConfig, Error> {
+    let mut options = Options::parse("numa", value)?;
+
+    let cpus = options.required("cpus")?;
+    let vcpus = parse_cpu_list(&options, cpus)?;
+    let memory_size = parse_memory(options.required("memory")?)?;
+    let distances = match options.take("distances") {
+        Some(distances) => distances
+            .split(':')
+            .map(|distance| {
+                distance
+                    .parse()
+                    .map_err(|_| options.error(format!("invalid distance {distance:?}")))
+            })
+            .collect::<Result<_, _>>()?,
+        None => Vec::new(),
+    };
+    options.finish()?;
+
+    Ok(NumaNodeConfig {
+        vcpus,
+        memory_size,
+        distances,
+    })
+}
+
+fn parse_disk(value: &str) -> Result<Device, Error> {
+    let mut options = Options::parse("disk", value)?;
+
+    let device = Device::VirtioBlk {
+        path: options.required("path")?.into(),
+        read_only: options.flag("readonly")?,
+        transport: options.transport()?,
+    };
+    options.finish()?;
+
+    Ok(device)
+}
+
+fn parse_fs(value: &str) -> Result<Device, Error> {
+    let mut options = Options::parse("fs", value)?;
+
+    let device = Device::Virtio9p {
+        tag: options.required("tag")?.to_string(),
+        path: options.required("path")?.into(),
+        read_only: options.flag("readonly")?,
+        transport: options.transport()?,
+        uid_map: Vec::new(),
+        gid_map: Vec::new(),
+    };
+    options.finish()?;
+
+    Ok(device)
+}
+
+fn parse_device(value: &str) -> Result<Device, Error> {
+    let mut options = Options::parse("device", value)?;
+
+    let kind = options.kind;
+    let device = match kind {
+        Some("gic-v3") => Device::GicV3,
+        Some("virtio-rng") => Device::VirtioEntropy {
+            transport: options.transport()?,
+        },
+        Some("virtio-balloon") => Device::VirtioBalloon {
+            transport: options.transport()?,
+        },
+        Some("virtio-gpu") => Device::VirtioGpu {
+            transport: options.transport()?,
+        },
+        Some("virtio-mem") => Device::VirtioMem {
+            region_size: parse_memory(options.required("size")?)?,
+            block_size: options.memory("block-size")?.unwrap_or(2 << 20),
+            requested_size: options.memory("requested")?.unwrap_or_default(),
+            transport: options.transport()?,
+        },
+        Some("virtio-iommu") => Device::VirtioIommu,
+        #[cfg(target_os = "linux")]
+        Some("vfio-pci") => Device::VfioPci {
+            name: options.required("name")?.to_string(),
+            path: options.required("path")?.into(),
+        },
+        #[cfg(target_os = "linux")]
+        Some("vfio-user") => Device::VfioUser {
+            name: options.required("name")?.to_string(),
+            socket: options.required("socket")?.into(),
+        },
+        Some(kind) => return Err(options.error(format!("unknown device {kind}"))),
+        None => return Err(options.error("the device kind is missing".to_string())),
+    };
+    options.finish()?;
+
+    Ok(device)
+}
+
+fn parse_rtc(value: &str) -> Result<RtcConfig, Error> {
+    let mut options = Options::parse("rtc", value)?;
+
+    let base = match options.take("base") {
+        None | Some("utc") => RtcBase::Utc,
+        Some("localtime") => RtcBase::Localtime,
+        Some(base) => return Err(options.error(format!("base is utc or localtime, got {base:?}"))),
+    };
+    let offset = options.parse_value("offset")?.unwrap_or_default();
+    options.finish()?;
+
+    Ok(RtcConfig { base, offset })
+}
+
+fn parse_serial(value: &str) -> Result<SerialConfig, Error> {
+    let mut options = Options::parse("serial", value)?;
+
+    let serial = match (options.kind, options.take("file")) {
+        (Some("tty"), None) => SerialConfig::Tty,
+        (Some("off"), None) => SerialConfig::Off,
+        (None, Some(path)) => SerialConfig::File(path.into()),
+        _ => return Err(options.error("expected tty, off or file=PATH".to_string())),
+    };
+    options.finish()?;
+
+    Ok(serial)
+}
+
+impl TryInto<VmConfig> for RunArgs {
+    type Error = Error;
+
+    fn try_into(self) -> Result<VmConfig, Self::Error> {
+        let mut devices = Vec::new();
+        for disk in &self.disk {
+            devices.push(parse_disk(disk)?);
+        }
+        for fs in &self.fs {
+            devices.push(parse_fs(fs)?);
+        }
+        for device in &self.device {
+            devices.push(parse_device(device)?);
+        }
+
+        let vm_config = VmConfig {
+            memory_size: parse_memory(&self.memory)?,
+            vcpus: self.cpus,
+            max_vcpus: self.max_cpus,
+            cpu_topology: self.topology.as_deref().map(parse_topology).transpose()?,
+            numa_nodes: self
+                .numa
+                .iter()
+                .map(|node| parse_numa_node(node))
+                .collect::<Result<_, _>>()?,
+            devices,
+            pci_hotplug_slots: self.pci_hotplug_slots,
+            pci_mmio64_size: self
+                .pci_mmio64_size
+                .as_deref()
+                .map(parse_memory)
+                .transpose()?
+                .unwrap_or_default(),
+            gdb_port: self.gdb,
+            kernel: self.kernel,
+            firmware: self.firmware.map(|code| FirmwareConfig {
+                code,
+                vars: self.firmware_vars,
+            }),
+            initramfs: self.initramfs,
+            cmdline: self.cmdline,
+            rtc: self
+                .rtc
+                .as_deref()
+                .map(parse_rtc)
+                .transpose()?
+                .unwrap_or_default(),
+            serial: self
+                .serial
+                .as_deref()
+                .map(parse_serial)
+                .transpose()?
+                .unwrap_or_default(),
+            clear_memory_on_reset: self.clear_memory_on_reset,
+            #[cfg(target_arch = "aarch64")]
+            acpi: self.acpi,
+        };
+
+        Ok(vm_config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_disk() -> anyhow::Result<()> {
+        assert_eq!(
+            parse_disk("path=disk.img,readonly=on,transport=mmio")?,
+            Device::VirtioBlk {
+                transport: VirtioTransport::Mmio,
+                path: "disk.img".into(),
+                read_only: true,
+            }
+        );
+        assert_eq!(
+            parse_disk("path=disk.img")?,
+            Device::VirtioBlk {
+                transport: VirtioTransport::Pci,
+                path: "disk.img".into(),
+                read_only: false,
+            }
+        );
+
+        assert!(parse_disk("readonly=on").is_err());
+        assert!(parse_disk("path=disk.img,cache=none").is_err());
+        assert!(parse_disk("path=disk.img,readonly=yes").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_device() -> anyhow::Result<()> {
+        assert_eq!(parse_device("gic-v3")?, Device::GicV3);
+        assert_eq!(
+            parse_device("virtio-mem,size=1G,requested=512M")?,
+            Device::VirtioMem {
+                transport: VirtioTransport::Pci,
+                region_size: 1 << 30,
+                block_size: 2 << 20,
+                requested_size: 512 << 20,
+            }
+        );
+
+        assert!(parse_device("transport=pci").is_err());
+        assert!(parse_device("virtio-net").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_serial() -> anyhow::Result<()> {
+        assert_eq!(parse_serial("tty")?, SerialConfig::Tty);
+        assert_eq!(parse_serial("off")?, SerialConfig::Off);
+        assert_eq!(
+            parse_serial("file=console.log")?,
+            SerialConfig::File("console.log".into())
+        );
+
+        assert!(parse_serial("pty").is_err());
+        assert!(parse_serial("tty,file=console.log").is_err());
+        assert!(parse_serial("file=console.log,append=on").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_numa_node() -> anyhow::Result<()> {
+        let node = parse_numa_node("cpus=0-1:4,memory=1G,distances=10:20")?;
+
+        assert_eq!(node.vcpus, vec![0, 1, 4]);
+        assert_eq!(node.memory_size, 1 << 30);
+        assert_eq!(node.distances, vec![10, 20]);
+
+        Ok(())
+    }
+}
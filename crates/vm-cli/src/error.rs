@@ -7,4 +7,11 @@ pub enum Error {
 
     #[error("memory too large")]
     MemoryTooLarge(String),
+
+    #[error("invalid --{option} {value:?}: {reason}")]
+    InvalidOption {
+        option: &'static str,
+        value: String,
+        reason: String,
+    },
 }
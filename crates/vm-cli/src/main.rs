@@ -1,15 +1,21 @@
 #![deny(warnings)]
 
 use std::fs;
+use std::path::Path;
 
 use clap::Parser;
 use tracing::debug;
 use tracing_subscriber::EnvFilter;
 use vm_core::virtualization::hypervisor::Hypervisor;
+use vm_vmm::vm::config::VmConfig;
+use vm_vmm::vm::snapshot::inspect_snapshot;
 use vm_vmm::vmm::Vmm;
 
 use crate::cmd::Cli;
 use crate::cmd::Command;
+use crate::cmd::SnapshotArgs;
+use crate::cmd::SnapshotCommand;
+use crate::cmd::ValidateCommand;
 use crate::cmd::json::CreateArgs;
 use crate::term::term_init;
 
@@ -29,6 +35,52 @@ fn build_hypervisor() -> anyhow::Result<Box<dyn Hypervisor>> {
     }
 }
 
+fn config_from_json(path: &Path) -> anyhow::Result<VmConfig> {
+    let json = fs::read(path)?;
+    let json = serde_json::from_slice::<CreateArgs>(&json)?;
+
+    debug!("create vm from json: {:?}", json);
+
+    Ok(json.try_into()?)
+}
+
+/// No hypervisor is needed to check a config
+fn validate(config: ValidateCommand) -> anyhow::Result<()> {
+    let vm_config = match config {
+        ValidateCommand::Json { path } => config_from_json(&path)?,
+        ValidateCommand::Run(args) => (*args).try_into()?,
+    };
+
+    vm_config.validate()?;
+
+    println!("ok");
+
+    Ok(())
+}
+
+fn inspect(path: &Path) -> anyhow::Result<()> {
+    let (header, snap) = inspect_snapshot(path)?;
+
+    println!("format version: {}", header.format_version);
+    println!("arch: {}", header.arch);
+    println!("hypervisor: {}", header.hypervisor);
+    println!("vmm version: {}", header.vmm_version);
+    let vm_config = snap.vm_config()?;
+    println!("vcpus: {}", vm_config.vcpus);
+    println!("memory: {:#x}", vm_config.memory_size);
+    println!("irq chip: {} bytes", snap.irq_chip_len());
+    println!("devices:");
+    for (id, section) in snap.device_sections() {
+        println!(
+            "  {id}: version {}, {} bytes",
+            section.version,
+            section.data.len()
+        );
+    }
+
+    Ok(())
+}
+
 async fn build_and_run_vm(args: Command) -> anyhow::Result<()> {
     let hypervisor = build_hypervisor()?;
 
@@ -36,24 +88,42 @@ async fn build_and_run_vm(args: Command) -> anyhow::Result<()> {
 
     match args {
         Command::Json { path } => {
-            let json = fs::read(path)?;
-            let json = serde_json::from_slice::<CreateArgs>(&json)?;
+            vmm.create_vm_from_config(config_from_json(&path)?).await?;
 
-            debug!("create vm from json: {:?}", json);
+            vmm.try_boot().await?;
+        }
+        Command::Run(args) => {
+            debug!("create vm from args: {:?}", args);
 
-            vmm.create_vm_from_config(json.try_into()?).await?;
+            vmm.create_vm_from_config((*args).try_into()?).await?;
 
             vmm.try_boot().await?;
         }
-        Command::Snapshot { path } => {
+        Command::Snapshot(SnapshotArgs {
+            command: None,
+            path: Some(path),
+            config_override,
+            monitor_socket,
+        }) => {
             debug!("import snapshot from {:?}", path);
 
-            vmm.create_vm_from_snapshot(&path).await?;
+            if let Some(monitor_socket) = monitor_socket {
+                vmm.set_monitor_socket(monitor_socket);
+            }
+
+            let config_override = config_override
+                .map(|json| serde_json::from_str(&json))
+                .transpose()?;
+
+            vmm.create_vm_from_snapshot(&path, config_override).await?;
 
             debug!("vm is booting");
 
             vmm.try_boot().await?;
         }
+        Command::Snapshot(_) | Command::Validate { .. } => {
+            unreachable!("the command does not create a vm")
+        }
     }
 
     vmm.run().await?;
@@ -74,6 +144,15 @@ async fn main() -> anyhow::Result<()> {
     let args = Cli::parse();
     debug!(?args);
 
+    match args.command {
+        Command::Validate { config } => return validate(config),
+        Command::Snapshot(SnapshotArgs {
+            command: Some(SnapshotCommand::Inspect { path }),
+            ..
+        }) => return inspect(&path),
+        _ => (),
+    }
+
     let _term_backup = term_init()?;
 
     build_and_run_vm(args.command).await?;
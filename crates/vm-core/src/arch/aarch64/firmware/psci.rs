@@ -7,12 +7,19 @@ use crate::arch::aarch64::vcpu::AArch64Vcpu;
 
 pub mod error;
 pub mod function;
-pub mod psci_0_2;
+pub mod psci_1_1;
 pub mod return_value;
 pub mod version;
 
+pub enum PsciCallResult {
+    /// The return value is in x0, the caller carries on with the next instruction
+    Return,
+    /// The caller has turned itself off, it must not run until another vcpu turns it on
+    CpuOff,
+}
+
 pub trait Psci: Send + Sync {
     fn version(&self) -> u32;
 
-    fn call(&self, vcpu: &mut dyn AArch64Vcpu) -> Result<(), PsciError>;
+    fn call(&self, vcpu: &mut dyn AArch64Vcpu) -> Result<PsciCallResult, PsciError>;
 }
@@ -1,4 +1,9 @@
 use thiserror::Error;
 
+use crate::virtualization::vcpu::error::VcpuError;
+
 #[derive(Error, Debug)]
-pub enum PsciError {}
+pub enum PsciError {
+    #[error("Failed to access the registers of the caller: {0}")]
+    Vcpu(#[from] VcpuError),
+}
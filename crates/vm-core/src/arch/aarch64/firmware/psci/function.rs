@@ -8,7 +8,7 @@ pub const fn psci_0_2_fn64(i: u32) -> u32 {
     PSCI_0_2_FN64_BASE + i
 }
 
-pub struct PsciFunctionId(u32);
+pub struct PsciFunctionId(pub u32);
 
 impl PsciFunctionId {
     pub fn is_64bit(&self) -> bool {
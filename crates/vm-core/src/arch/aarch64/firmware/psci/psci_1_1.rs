@@ -0,0 +1,625 @@
+use std::sync::Arc;
+
+use futures::executor::block_on;
+use strum_macros::FromRepr;
+use tokio::sync::Mutex;
+
+use crate::arch::aarch64::firmware::psci::Psci;
+use crate::arch::aarch64::firmware::psci::PsciCallResult;
+use crate::arch::aarch64::firmware::psci::error::PsciError;
+use crate::arch::aarch64::firmware::psci::function::PsciFunctionId;
+use crate::arch::aarch64::firmware::psci::function::psci_0_2_fn;
+use crate::arch::aarch64::firmware::psci::function::psci_0_2_fn64;
+use crate::arch::aarch64::firmware::psci::return_value::AffinityInfo;
+use crate::arch::aarch64::firmware::psci::return_value::MIGRATE_INFO_TYPE_NOT_PRESENT;
+use crate::arch::aarch64::firmware::psci::return_value::PsciRet;
+use crate::arch::aarch64::firmware::psci::version::psci_version;
+use crate::arch::aarch64::vcpu::AArch64Vcpu;
+use crate::arch::aarch64::vcpu::reg::SysRegister;
+use crate::cpu::vcpu_manager::VcpuManager;
+use crate::device::system_event::SystemEvent;
+use crate::device::system_event::SystemEventNotifier;
+
+/// The only reset type of SYSTEM_RESET2 which is not vendor specific
+const PSCI_1_1_RESET_TYPE_SYSTEM_WARM_RESET: u32 = 0;
+
+#[derive(FromRepr)]
+#[repr(u32)]
+enum Psci11FunctionId {
+    Version = psci_0_2_fn(0),
+    CpuSuspend = psci_0_2_fn(1),
+    CpuSuspend64 = psci_0_2_fn64(1),
+    CpuOff = psci_0_2_fn(2),
+    CpuOn = psci_0_2_fn(3),
+    CpuOn64 = psci_0_2_fn64(3),
+    AffinityInfo = psci_0_2_fn(4),
+    AffinityInfo64 = psci_0_2_fn64(4),
+    MigrateInfoType = psci_0_2_fn(6),
+    SystemOff = psci_0_2_fn(8),
+    SystemReset = psci_0_2_fn(9),
+    Features = psci_0_2_fn(10),
+    SystemReset2 = psci_0_2_fn(18),
+    SystemReset264 = psci_0_2_fn64(18),
+}
+
+pub struct Psci11 {
+    pub vcpu_manager: Arc<Mutex<VcpuManager>>,
+    pub system_event_notifier: Arc<dyn SystemEventNotifier>,
+}
+
+impl Psci11 {
+    fn cpu_on(&self, target_cpu: u64, entry_point_address: u64, context_id: u64) -> u32 {
+        // The vcpu manager is not held while the target boots, the vmm may be waiting on
+        // this vcpu with it
+        let power_on = {
+            let mut vcpu_manager = self.vcpu_manager.blocking_lock();
+
+            let Ok(vcpu) = vcpu_manager.get_vcpu_by_mpidr_mut(target_cpu) else {
+                return PsciRet::INVALID_PARAMS as u32;
+            };
+
+            match vcpu.power_on() {
+                Ok(power_on) => power_on,
+                Err(_) => return PsciRet::ALREADY_ON as u32,
+            }
+        };
+
+        match block_on(power_on.boot(entry_point_address, context_id)) {
+            Ok(()) => PsciRet::SUCCESS as u32,
+            Err(_) => {
+                let mut vcpu_manager = self.vcpu_manager.blocking_lock();
+                let _ = vcpu_manager.power_off_vcpu_by_mpidr(target_cpu);
+
+                PsciRet::INTERNAL_FAILURE as u32
+            }
+        }
+    }
+
+    fn affinity_info(&self, target_affinity: u64, lowest_affinity_level: u64) -> u32 {
+        // Only the state of a single vcpu is reported
+        if lowest_affinity_level != 0 {
+            return PsciRet::INVALID_PARAMS as u32;
+        }
+
+        let mut vcpu_manager = self.vcpu_manager.blocking_lock();
+
+        match vcpu_manager.is_vcpu_on_by_mpidr(target_affinity) {
+            Ok(true) => AffinityInfo::ON as u32,
+            Ok(false) => AffinityInfo::OFF as u32,
+            Err(_) => PsciRet::INVALID_PARAMS as u32,
+        }
+    }
+
+    fn features(&self, function_id: u32) -> u32 {
+        match Psci11FunctionId::from_repr(function_id) {
+            // Original power state format, without os-initiated mode
+            Some(Psci11FunctionId::CpuSuspend | Psci11FunctionId::CpuSuspend64) => 0,
+            Some(_) => PsciRet::SUCCESS as u32,
+            None => PsciRet::NOT_SUPPORTED as u32,
+        }
+    }
+}
+
+impl Psci for Psci11 {
+    fn version(&self) -> u32 {
+        psci_version(1, 1)
+    }
+
+    fn call(&self, vcpu: &mut dyn AArch64Vcpu) -> Result<PsciCallResult, PsciError> {
+        let function_id = vcpu.get_smc_function_id()?;
+
+        // The arguments of the 32-bit calls are in the lower half of the registers
+        let arg_mask = if PsciFunctionId(function_id).is_64bit() {
+            u64::MAX
+        } else {
+            u32::MAX as u64
+        };
+        let arg1 = vcpu.get_smc_arg1()? & arg_mask;
+        let arg2 = vcpu.get_smc_arg2()? & arg_mask;
+        let arg3 = vcpu.get_smc_arg3()? & arg_mask;
+
+        let val = match Psci11FunctionId::from_repr(function_id) {
+            Some(function_id) => match function_id {
+                Psci11FunctionId::Version => self.version(),
+                Psci11FunctionId::CpuSuspend | Psci11FunctionId::CpuSuspend64 => {
+                    // Every power state is treated as standby which is woken up at once, the
+                    // caller is allowed to see it as a spurious wakeup
+                    PsciRet::SUCCESS as u32
+                }
+                Psci11FunctionId::CpuOff => {
+                    let mpidr = vcpu.get_sys_reg(SysRegister::MpidrEl1)?;
+
+                    let mut vcpu_manager = self.vcpu_manager.blocking_lock();
+                    match vcpu_manager.power_off_vcpu_by_mpidr(mpidr) {
+                        Ok(()) => return Ok(PsciCallResult::CpuOff),
+                        Err(_) => PsciRet::DENIED as u32,
+                    }
+                }
+                Psci11FunctionId::CpuOn | Psci11FunctionId::CpuOn64 => {
+                    // arg1 is the mpidr of the target
+                    self.cpu_on(arg1, arg2, arg3)
+                }
+                Psci11FunctionId::AffinityInfo | Psci11FunctionId::AffinityInfo64 => {
+                    self.affinity_info(arg1, arg2)
+                }
+                Psci11FunctionId::MigrateInfoType => MIGRATE_INFO_TYPE_NOT_PRESENT,
+                Psci11FunctionId::SystemOff => {
+                    // It does not return, the vcpus are stopped by the vmm
+                    self.system_event_notifier.notify(SystemEvent::Shutdown);
+
+                    PsciRet::SUCCESS as u32
+                }
+                Psci11FunctionId::SystemReset => {
+                    self.system_event_notifier.notify(SystemEvent::Reset);
+
+                    PsciRet::SUCCESS as u32
+                }
+                Psci11FunctionId::SystemReset2 | Psci11FunctionId::SystemReset264 => {
+                    if arg1 as u32 == PSCI_1_1_RESET_TYPE_SYSTEM_WARM_RESET {
+                        self.system_event_notifier.notify(SystemEvent::Reset);
+
+                        PsciRet::SUCCESS as u32
+                    } else {
+                        PsciRet::INVALID_PARAMS as u32
+                    }
+                }
+                Psci11FunctionId::Features => self.features(arg1 as u32),
+            },
+            None => PsciRet::NOT_SUPPORTED as u32,
+        };
+
+        vcpu.set_smc_return_value(val, 0, 0, 0)?;
+
+        Ok(PsciCallResult::Return)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex as StdMutex;
+
+    use tokio::sync::mpsc;
+    use tokio::sync::mpsc::Sender;
+    use tokio::sync::mpsc::WeakSender;
+    use vm_mm::manager::MemoryAddressSpace;
+    use vm_utils::cpu_topology::CpuTopology;
+    #[cfg(target_os = "linux")]
+    use vmm_sys_util::eventfd::EventFd;
+
+    use super::*;
+    use crate::arch::aarch64::vcpu::reg::CoreRegister;
+    use crate::arch::aarch64::vcpu::reg::FpRegister;
+    use crate::arch::irq::InterruptController;
+    use crate::arch::registers::aarch64::AArch64CoreRegisters;
+    use crate::arch::registers::aarch64::AArch64Registers;
+    use crate::arch::registers::aarch64::AArch64SysRegisters;
+    use crate::cpu::vm_exit::VmExit;
+    use crate::cpu::vm_exit::VmExitHandlerError;
+    use crate::interrupt_manager::InterruptManager;
+    use crate::virtualization::vcpu::HypervisorVcpu;
+    use crate::virtualization::vcpu::command::VcpuCommand;
+    use crate::virtualization::vcpu::command::VcpuCommandRequest;
+    use crate::virtualization::vcpu::command::VcpuCommandResponse;
+    use crate::virtualization::vcpu::error::VcpuError;
+    use crate::virtualization::vm::HypervisorVm;
+    #[cfg(target_os = "linux")]
+    use crate::virtualization::vm::IoEventAddress;
+    #[cfg(target_os = "linux")]
+    use crate::virtualization::vm::IoEventDatamatch;
+    use crate::virtualization::vm::SetUserMemoryRegionFlags;
+    use crate::virtualization::vm::error::VmError;
+
+    const VCPUS: u32 = 2;
+
+    /// Answers the commands of the vcpu manager from its own thread like a real vcpu
+    struct MockVcpu {
+        vcpu_id: u64,
+        command_tx: Sender<VcpuCommandRequest>,
+    }
+
+    impl MockVcpu {
+        fn new(vcpu_id: u64) -> Self {
+            let (command_tx, mut command_rx) = mpsc::channel::<VcpuCommandRequest>(8);
+
+            std::thread::spawn(move || {
+                while let Some(request) = command_rx.blocking_recv() {
+                    let resp = match request.cmd {
+                        VcpuCommand::ReadRegisters => {
+                            VcpuCommandResponse::Registers(Box::new(AArch64Registers {
+                                core: AArch64CoreRegisters {
+                                    general_purpose: [0; 31],
+                                    sp: 0,
+                                    pc: 0,
+                                    pstate: 0,
+                                    fp: [0; 32],
+                                    fpcr: 0,
+                                    fpsr: 0,
+                                },
+                                sys: AArch64SysRegisters {
+                                    mpidr_el1: 0,
+                                    sctlr_el1: 0,
+                                    cnthctl_el2: 0,
+                                },
+                            }))
+                        }
+                        _ => VcpuCommandResponse::Empty,
+                    };
+                    let _ = request.response.send(resp);
+                }
+            });
+
+            MockVcpu {
+                vcpu_id,
+                command_tx,
+            }
+        }
+    }
+
+    impl HypervisorVcpu for MockVcpu {
+        fn vcpu_id(&self) -> u64 {
+            self.vcpu_id
+        }
+
+        fn command_tx(&self) -> WeakSender<VcpuCommandRequest> {
+            self.command_tx.downgrade()
+        }
+
+        fn tick(&self) -> Result<(), VcpuError> {
+            Ok(())
+        }
+    }
+
+    struct MockVm;
+
+    impl HypervisorVm for MockVm {
+        fn create_vcpu(
+            &self,
+            vcpu_id: u64,
+            _cpu_topology: &CpuTopology,
+            _mm: Arc<MemoryAddressSpace>,
+            _vm_exit_handler: Arc<dyn VmExit>,
+        ) -> Result<Box<dyn HypervisorVcpu>, VmError> {
+            Ok(Box::new(MockVcpu::new(vcpu_id)))
+        }
+
+        fn create_irq_chip(&self) -> Result<Box<dyn InterruptController>, VmError> {
+            unreachable!()
+        }
+
+        fn create_irq_manager(&self) -> Result<InterruptManager, VmError> {
+            unreachable!()
+        }
+
+        fn set_user_memory_region(
+            &self,
+            _userspace_addr: u64,
+            _guest_phys_addr: u64,
+            _memory_size: usize,
+            _flags: SetUserMemoryRegionFlags,
+        ) -> Result<(), VmError> {
+            unreachable!()
+        }
+
+        fn remove_user_memory_region(
+            &self,
+            _guest_phys_addr: u64,
+            _memory_size: usize,
+        ) -> Result<(), VmError> {
+            unreachable!()
+        }
+
+        #[cfg(target_os = "linux")]
+        fn set_irqfd(&self, _fd: &EventFd, _gsi: u32) -> Result<(), VmError> {
+            unreachable!()
+        }
+
+        #[cfg(target_os = "linux")]
+        fn del_irqfd(&self, _fd: &EventFd, _gsi: u32) -> Result<(), VmError> {
+            unreachable!()
+        }
+
+        #[cfg(target_os = "linux")]
+        fn set_irqfd_with_resample(
+            &self,
+            _fd: &EventFd,
+            _resamplefd: &EventFd,
+            _gsi: u32,
+        ) -> Result<(), VmError> {
+            unreachable!()
+        }
+
+        #[cfg(target_os = "linux")]
+        fn set_gsi_routing(&self) -> Result<(), VmError> {
+            unreachable!()
+        }
+
+        #[cfg(target_os = "linux")]
+        fn register_ioeventfd(
+            &self,
+            _fd: &EventFd,
+            _addr: IoEventAddress,
+            _datamatch: IoEventDatamatch,
+        ) -> Result<(), VmError> {
+            unreachable!()
+        }
+
+        #[cfg(target_os = "linux")]
+        fn unregister_ioeventfd(
+            &self,
+            _fd: &EventFd,
+            _addr: IoEventAddress,
+            _datamatch: IoEventDatamatch,
+        ) -> Result<(), VmError> {
+            unreachable!()
+        }
+
+        fn secondary_cpu_should_run_on_booting(&self) -> bool {
+            false
+        }
+    }
+
+    struct NoExit;
+
+    impl VmExit for NoExit {
+        fn io_in(&self, _port: u16, _data: &mut [u8]) -> Result<(), VmExitHandlerError> {
+            unreachable!()
+        }
+
+        fn io_out(&self, _port: u16, _data: &[u8]) -> Result<(), VmExitHandlerError> {
+            unreachable!()
+        }
+
+        fn mmio_read(&self, _addr: u64, _data: &mut [u8]) -> Result<(), VmExitHandlerError> {
+            unreachable!()
+        }
+
+        fn mmio_write(&self, _addr: u64, _data: &[u8]) -> Result<(), VmExitHandlerError> {
+            unreachable!()
+        }
+
+        fn system_event(&self, _event: SystemEvent) {
+            unreachable!()
+        }
+
+        fn call_smc(
+            &self,
+            _vcpu: &mut dyn AArch64Vcpu,
+        ) -> Result<PsciCallResult, VmExitHandlerError> {
+            unreachable!()
+        }
+    }
+
+    #[derive(Default)]
+    struct Events(StdMutex<Vec<SystemEvent>>);
+
+    impl SystemEventNotifier for Events {
+        fn notify(&self, event: SystemEvent) {
+            self.0.lock().unwrap().push(event);
+        }
+    }
+
+    /// The vcpu making the call, only x0..x3 and MPIDR_EL1 are used
+    struct Caller {
+        x: [u64; 4],
+        mpidr: u64,
+    }
+
+    impl AArch64Vcpu for Caller {
+        fn get_core_reg(&self, reg: CoreRegister) -> Result<u64, VcpuError> {
+            Ok(match reg {
+                CoreRegister::X0 => self.x[0],
+                CoreRegister::X1 => self.x[1],
+                CoreRegister::X2 => self.x[2],
+                CoreRegister::X3 => self.x[3],
+                _ => unreachable!(),
+            })
+        }
+
+        fn set_core_reg(&mut self, reg: CoreRegister, value: u64) -> Result<(), VcpuError> {
+            match reg {
+                CoreRegister::X0 => self.x[0] = value,
+                CoreRegister::X1 => self.x[1] = value,
+                CoreRegister::X2 => self.x[2] = value,
+                CoreRegister::X3 => self.x[3] = value,
+                _ => unreachable!(),
+            }
+
+            Ok(())
+        }
+
+        fn get_fp_reg(&self, _reg: FpRegister) -> Result<u128, VcpuError> {
+            unreachable!()
+        }
+
+        fn set_fp_reg(&mut self, _reg: FpRegister, _value: u128) -> Result<(), VcpuError> {
+            unreachable!()
+        }
+
+        fn get_sys_reg(&self, reg: SysRegister) -> Result<u64, VcpuError> {
+            match reg {
+                SysRegister::MpidrEl1 => Ok(self.mpidr),
+                _ => unreachable!(),
+            }
+        }
+
+        fn set_sys_reg(&mut self, _reg: SysRegister, _value: u64) -> Result<(), VcpuError> {
+            unreachable!()
+        }
+
+        fn mm(&self) -> &MemoryAddressSpace {
+            unreachable!()
+        }
+    }
+
+    /// vcpu 0 is booted, the others are off
+    fn psci() -> (Psci11, Arc<Events>) {
+        let mut vcpu_manager = VcpuManager::new(Arc::new(MockVm), CpuTopology::flat(VCPUS));
+        for vcpu_id in 0..VCPUS as u64 {
+            vcpu_manager
+                .create_vcpu(
+                    vcpu_id,
+                    Arc::new(MemoryAddressSpace::default()),
+                    Arc::new(NoExit),
+                    vcpu_id == 0,
+                )
+                .unwrap();
+        }
+
+        let events = Arc::new(Events::default());
+        let psci = Psci11 {
+            vcpu_manager: Arc::new(Mutex::new(vcpu_manager)),
+            system_event_notifier: events.clone(),
+        };
+
+        (psci, events)
+    }
+
+    fn mpidr(vcpu_id: u32) -> u64 {
+        CpuTopology::flat(VCPUS).mpidr(vcpu_id)
+    }
+
+    /// Makes the call from `caller` and returns x0
+    fn call(psci: &Psci11, caller: u32, function_id: u32, args: [u64; 3]) -> (PsciCallResult, u32) {
+        let mut vcpu = Caller {
+            x: [function_id as u64, args[0], args[1], args[2]],
+            mpidr: mpidr(caller),
+        };
+
+        let result = psci.call(&mut vcpu).unwrap();
+        (result, vcpu.x[0] as u32)
+    }
+
+    #[test]
+    fn test_features() {
+        let (psci, _) = psci();
+
+        for (function_id, expected) in [
+            (Psci11FunctionId::Version as u32, PsciRet::SUCCESS as u32),
+            (Psci11FunctionId::CpuOn64 as u32, PsciRet::SUCCESS as u32),
+            (
+                Psci11FunctionId::SystemReset2 as u32,
+                PsciRet::SUCCESS as u32,
+            ),
+            (Psci11FunctionId::CpuSuspend as u32, 0),
+            // MEM_PROTECT and an arch function id
+            (psci_0_2_fn(19), PsciRet::NOT_SUPPORTED as u32),
+            (0x8000_0000, PsciRet::NOT_SUPPORTED as u32),
+        ] {
+            let (_, x0) = call(
+                &psci,
+                0,
+                Psci11FunctionId::Features as u32,
+                [function_id as u64, 0, 0],
+            );
+            assert_eq!(x0, expected, "function id {function_id:#x}");
+        }
+    }
+
+    #[test]
+    fn test_cpu_on() {
+        let (psci, _) = psci();
+
+        let (_, x0) = call(
+            &psci,
+            1,
+            Psci11FunctionId::CpuOn64 as u32,
+            [mpidr(0), 0x4000_0000, 0],
+        );
+        assert_eq!(x0, PsciRet::ALREADY_ON as u32);
+
+        let (_, x0) = call(
+            &psci,
+            0,
+            Psci11FunctionId::CpuOn64 as u32,
+            [mpidr(1), 0x4000_0000, 0],
+        );
+        assert_eq!(x0, PsciRet::SUCCESS as u32);
+
+        let (_, x0) = call(
+            &psci,
+            0,
+            Psci11FunctionId::CpuOn64 as u32,
+            [mpidr(1), 0x4000_0000, 0],
+        );
+        assert_eq!(x0, PsciRet::ALREADY_ON as u32);
+
+        let (_, x0) = call(
+            &psci,
+            0,
+            Psci11FunctionId::CpuOn64 as u32,
+            [mpidr(VCPUS), 0x4000_0000, 0],
+        );
+        assert_eq!(x0, PsciRet::INVALID_PARAMS as u32);
+    }
+
+    #[test]
+    fn test_affinity_info_after_cpu_off() {
+        let (psci, _) = psci();
+
+        call(
+            &psci,
+            0,
+            Psci11FunctionId::CpuOn64 as u32,
+            [mpidr(1), 0x4000_0000, 0],
+        );
+        let (_, x0) = call(
+            &psci,
+            0,
+            Psci11FunctionId::AffinityInfo64 as u32,
+            [mpidr(1), 0, 0],
+        );
+        assert_eq!(x0, AffinityInfo::ON as u32);
+
+        let (result, _) = call(&psci, 1, Psci11FunctionId::CpuOff as u32, [0; 3]);
+        assert!(matches!(result, PsciCallResult::CpuOff));
+
+        let (_, x0) = call(
+            &psci,
+            0,
+            Psci11FunctionId::AffinityInfo64 as u32,
+            [mpidr(1), 0, 0],
+        );
+        assert_eq!(x0, AffinityInfo::OFF as u32);
+
+        // It can be turned on again
+        let (_, x0) = call(
+            &psci,
+            0,
+            Psci11FunctionId::CpuOn64 as u32,
+            [mpidr(1), 0x4000_0000, 0],
+        );
+        assert_eq!(x0, PsciRet::SUCCESS as u32);
+    }
+
+    #[test]
+    fn test_system_off_and_reset() {
+        let (psci, events) = psci();
+
+        call(&psci, 0, Psci11FunctionId::SystemOff as u32, [0; 3]);
+        call(&psci, 0, Psci11FunctionId::SystemReset as u32, [0; 3]);
+        call(
+            &psci,
+            0,
+            Psci11FunctionId::SystemReset2 as u32,
+            [PSCI_1_1_RESET_TYPE_SYSTEM_WARM_RESET as u64, 0, 0],
+        );
+
+        // Vendor specific reset types are rejected
+        let (_, x0) = call(
+            &psci,
+            0,
+            Psci11FunctionId::SystemReset2 as u32,
+            [1 << 31, 0, 0],
+        );
+        assert_eq!(x0, PsciRet::INVALID_PARAMS as u32);
+
+        assert_eq!(
+            *events.0.lock().unwrap(),
+            vec![
+                SystemEvent::Shutdown,
+                SystemEvent::Reset,
+                SystemEvent::Reset
+            ]
+        );
+    }
+}
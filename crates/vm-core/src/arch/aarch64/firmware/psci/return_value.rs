@@ -11,3 +11,13 @@ pub enum PsciRet {
     DISABLED = -8,
     INVALID_ADDRESS = -9,
 }
+
+#[allow(non_camel_case_types)]
+pub enum AffinityInfo {
+    ON = 0,
+    OFF = 1,
+    ON_PENDING = 2,
+}
+
+/// Trusted OS is not present or does not require migration
+pub const MIGRATE_INFO_TYPE_NOT_PRESENT: u32 = 2;
@@ -1,10 +1,15 @@
 use static_assertions::const_assert;
-use static_assertions::const_assert_eq;
+
+// Two flash banks like the qemu virt machine, the firmware code starts executing at 0
+pub const FIRMWARE_CODE_START: u64 = 0x0000_0000;
+pub const FIRMWARE_VARS_START: u64 = 0x0400_0000;
+pub const FIRMWARE_BANK_LEN: usize = 0x0400_0000;
 
 pub const MMIO_START: u32 = 0x0900_0000;
 pub const MMIO_LEN: u32 = 0x0700_0000;
 
 pub const ECAM_BASE: u32 = 0x1000_0000;
+// 256 buses * (32 devices * 8 functions) * 4K = 0x1000_0000
 pub const ECAM_LENGTH: u32 = 0x1000_0000;
 
 pub const PCI_BAR_MMIO_WINDOW_START: u32 = 0x2000_0000;
@@ -14,15 +19,27 @@ pub const GIC_DISTRIBUTOR: u64 = 0x3000_0000;
 pub const GIC_REDISTRIBUTOR: u64 = 0x3001_0000;
 pub const GIC_MSI: u64 = 0x3a00_0000;
 pub const RAM_BASE: u64 = 0x4000_0000;
-pub const DTB_START: u64 = 0x4400_0000; // Reserve 64MB for kernel
-pub const INITRD_START: u64 = 0x44200000; // DTB + 2MB
+// The kernel is at the start of the ram, the initrd, the acpi tables and the dtb are placed at
+// its end by the bootloader
+pub const DTB_MAX_LEN: usize = 0x20_0000;
+pub const ACPI_MAX_LEN: usize = 0x10_0000;
+
+// Hotpluggable memory (virtio-mem)
+pub const MEMORY_HOTPLUG_START: u64 = 0x0008_0000_0000;
+pub const MEMORY_HOTPLUG_LEN: u64 = 0x0004_0000_0000;
 
-const KERNEL_MAX: usize = 0x0400_0000;
+// 64-bit prefetchable pci window above all the guest ram, its size comes from the config.
+// It ends at 64GB so that it still fits in the default 36-bit IPA space.
+pub const PCI_BAR_MMIO64_WINDOW_START: u64 = MEMORY_HOTPLUG_START + MEMORY_HOTPLUG_LEN;
+pub const PCI_BAR_MMIO64_WINDOW_MAX_LEN: u64 = (1 << 36) - PCI_BAR_MMIO64_WINDOW_START;
 
 // We use SPI index to facilitate device-tree generating, triggering irq should add 32.
 pub const IRQ_ALLOCATION_START: u32 = 0;
 pub const IRQ_ALLOCATION_LEN: usize = 256 - 32;
 
+const_assert!(FIRMWARE_VARS_START >= FIRMWARE_CODE_START + FIRMWARE_BANK_LEN as u64);
+const_assert!(MMIO_START as u64 >= FIRMWARE_VARS_START + FIRMWARE_BANK_LEN as u64);
 const_assert!(ECAM_BASE >= MMIO_START + MMIO_LEN);
 const_assert!(PCI_BAR_MMIO_WINDOW_START >= ECAM_BASE + ECAM_LENGTH);
-const_assert_eq!(RAM_BASE + KERNEL_MAX as u64, DTB_START);
+const_assert!(MEMORY_HOTPLUG_START > RAM_BASE);
+const_assert!(PCI_BAR_MMIO64_WINDOW_START >= MEMORY_HOTPLUG_START + MEMORY_HOTPLUG_LEN);
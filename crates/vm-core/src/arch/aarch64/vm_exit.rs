@@ -1,5 +1,6 @@
 use tracing::trace;
 
+use crate::arch::aarch64::firmware::psci::PsciCallResult;
 use crate::arch::aarch64::vcpu::AArch64Vcpu;
 use crate::arch::aarch64::vcpu::reg::CoreRegister;
 use crate::arch::aarch64::vcpu::reg::SysRegister;
@@ -70,9 +71,11 @@ pub fn handle_vm_exit(
         }
         VmExitReason::Smc => {
             // We only support psci for smc now
-            vm_exit_handler.call_smc(vcpu)?;
-
-            Ok(HandleVmExitResult::NextInstruction)
+            match vm_exit_handler.call_smc(vcpu)? {
+                PsciCallResult::Return => Ok(HandleVmExitResult::NextInstruction),
+                // Stops running it, CPU_ON sets up the pc again
+                PsciCallResult::CpuOff => Ok(HandleVmExitResult::Canceled),
+            }
         }
     }
 }
@@ -11,6 +11,11 @@ pub mod error;
 pub enum Phandle {
     GIC = 0x1,
     MSI = 0x2,
+    CLOCK = 0x3,
+    GPIO = 0x4,
+    IOMMU = 0x5,
+    /// The base of the phandles of the cpus, indexed by the vcpu id
+    CPU = 0x100,
 }
 
 pub trait InterruptController: Send + Sync + 'static {
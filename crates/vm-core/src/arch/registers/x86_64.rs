@@ -77,21 +77,35 @@ pub struct X86_64Registers {
     pub sregs: X86_64SRegisters,
 }
 
+/// Where the kernel entry finds the boot information
+#[derive(Debug, Clone, Copy)]
+pub enum BootProtocol {
+    /// The zero page in rsi
+    Linux { boot_params: u64 },
+    /// The hvm_start_info in rbx
+    Pvh { start_info: u64 },
+}
+
 impl X86_64Registers {
     pub fn boot_registers(
         gdt_addr: u64,
         gdt: Gdt<5>,
         kernel_entry: u64,
-        boot_params: u64,
+        boot_protocol: BootProtocol,
         regs: X86_64Registers,
     ) -> Self {
         let boot_cs = gdt.entries.get(2).unwrap().to_kvm_segment(2);
         let boot_ds = gdt.entries.get(3).unwrap().to_kvm_segment(3);
 
+        let (rbx, rsi) = match boot_protocol {
+            BootProtocol::Linux { boot_params } => (0, boot_params),
+            BootProtocol::Pvh { start_info } => (start_info, 0),
+        };
+
         X86_64Registers {
             regs: X86_64CoreRegisters {
-                rbx: 0,
-                rsi: boot_params,
+                rbx,
+                rsi,
                 rdi: 0,
                 rsp: 0x90000,
                 rbp: 0,
@@ -111,6 +125,15 @@ impl X86_64Registers {
                 },
                 // At entry, the CPU must be in 32-bit protected mode with paging disabled
                 cr0: (regs.sregs.cr0 | 0x1) & !(1 << 31),
+                // Pvh also requires all the cr4 bits and efer cleared
+                cr4: match boot_protocol {
+                    BootProtocol::Linux { .. } => regs.sregs.cr4,
+                    BootProtocol::Pvh { .. } => 0,
+                },
+                efer: match boot_protocol {
+                    BootProtocol::Linux { .. } => regs.sregs.efer,
+                    BootProtocol::Pvh { .. } => 0,
+                },
                 ..regs.sregs
             },
         }
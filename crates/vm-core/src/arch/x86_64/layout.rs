@@ -2,12 +2,14 @@ use static_assertions::const_assert;
 
 pub const RAM_BASE: u64 = 0x0000_0000;
 pub const GDT_START: u32 = 0x0000_0500;
+// The hvm_start_info, the module list and the memory map of the pvh boot
+pub const PVH_INFO_START: u32 = 0x0000_6000;
 pub const BOOT_PARAMS_START: u32 = 0x0000_7000;
-pub const CMDLINE_START: u32 = 0x0001_0000;
+// The command line is placed below the ebda
+pub const CMDLINE_LIMIT: u32 = 0x000a_0000;
 pub const ACPI_RSDP_START: u32 = 0x000e_0000;
 pub const ACPI_MAX_LEN: u32 = 0x20000;
 pub const KERNEL_START: u32 = 0x0010_0000;
-pub const INITRD_START: u32 = 0x1000_0000;
 
 // For mmio devices (excluding pci devices)
 pub const MMIO_START: u32 = 0xc000_0000;
@@ -17,16 +19,38 @@ pub const PCI_BAR_MMIO_WINDOW_START: u32 = 0xd000_0000;
 pub const PCI_BAR_MMIO_WINDOW_LENGTH: u32 = 0x1000_0000;
 
 pub const ECAM_BASE: u32 = 0xe000_0000;
-// Ecam base, we only support one bus yet (see acpi),
-// hence we reserved (32 devices * 8 functions) * 4K = 0x0010_0000
-pub const ECAM_LENGTH: u32 = 0x0010_0000;
+// 256 buses * (32 devices * 8 functions) * 4K = 0x1000_0000
+pub const ECAM_LENGTH: u32 = 0x1000_0000;
 
 pub const IOAPIC_ADDR: u32 = 0xfec0_0000;
 pub const APIC_ADDR: u32 = 0xfee0_0000;
 
+// The firmware code ends at 4GB so that the reset vector is its last 16 bytes, the vars
+// are right below it
+pub const FIRMWARE_START: u32 = 0xff00_0000;
+pub const FIRMWARE_LEN: u32 = 0x0100_0000;
+
+// The end of the firmware code is copied here like the isa bios alias, the acpi tables
+// are not installed when booting a firmware
+pub const LEGACY_BIOS_START: u32 = 0x000e_0000;
+pub const LEGACY_BIOS_LEN: u32 = 0x0002_0000;
+
+// Hotpluggable memory (virtio-mem), above the 32-bit mmio hole
+pub const MEMORY_HOTPLUG_START: u64 = 0x0001_0000_0000;
+pub const MEMORY_HOTPLUG_LEN: u64 = 0x0010_0000_0000;
+
+// 64-bit prefetchable pci window above all the guest ram, its size comes from the config
+pub const PCI_BAR_MMIO64_WINDOW_START: u64 = MEMORY_HOTPLUG_START + MEMORY_HOTPLUG_LEN;
+
 pub const IO_PORT_START: u16 = 0x0000;
 pub const IO_PORT_LEN: usize = 0x4000;
 
+// The sleep control and status registers of the hardware-reduced acpi
+pub const ACPI_SLEEP_PORT: u16 = 0x0600;
+
+// The reset control register, a byte wide port overlapping the pci config address
+pub const RESET_CONTROL_PORT: u16 = 0x0cf9;
+
 pub const PCI_IO_PORT_WINDOW_START: u16 = 0x2000;
 pub const PCI_IO_PORT_WINDOW_LENGTH: u16 = 0x2000;
 
@@ -36,7 +60,15 @@ pub const IRQ_ALLOCATION_LEN: usize = 16 - IRQ_ALLOCATION_START as usize;
 pub const GSI_ALLOCATION_START: u32 = 32;
 pub const GSI_ALLOCATION_LEN: usize = 256 - GSI_ALLOCATION_START as usize;
 
+const_assert!(PVH_INFO_START + 0x1000 <= BOOT_PARAMS_START);
+const_assert!(CMDLINE_LIMIT <= ACPI_RSDP_START);
 const_assert!(KERNEL_START >= ACPI_RSDP_START + ACPI_MAX_LEN);
 const_assert!(PCI_BAR_MMIO_WINDOW_START >= MMIO_START + MMIO_LEN);
 const_assert!(ECAM_BASE >= PCI_BAR_MMIO_WINDOW_START + PCI_BAR_MMIO_WINDOW_LENGTH);
 const_assert!(IOAPIC_ADDR >= ECAM_BASE + ECAM_LENGTH);
+const_assert!(FIRMWARE_START > APIC_ADDR);
+const_assert!(FIRMWARE_START as u64 + FIRMWARE_LEN as u64 == 1 << 32);
+const_assert!(LEGACY_BIOS_START + LEGACY_BIOS_LEN == 0x0010_0000);
+const_assert!(MEMORY_HOTPLUG_START > APIC_ADDR as u64);
+const_assert!(PCI_BAR_MMIO64_WINDOW_START >= MEMORY_HOTPLUG_START + MEMORY_HOTPLUG_LEN);
+const_assert!(ACPI_SLEEP_PORT < PCI_IO_PORT_WINDOW_START);
@@ -5,6 +5,8 @@ use vm_firmware::x86_64::gdt::Gdt;
 
 use crate::arch::registers::ArchCoreRegisters;
 use crate::arch::registers::ArchRegisters;
+#[cfg(target_arch = "x86_64")]
+use crate::arch::registers::x86_64::BootProtocol;
 use crate::cpu::error::CpuError;
 use crate::virtualization::vcpu::HypervisorVcpu;
 use crate::virtualization::vcpu::command::VcpuCommand;
@@ -14,14 +16,23 @@ use crate::virtualization::vcpu::command::VcpuCommandResponse;
 pub struct Vcpu {
     command_tx: WeakSender<VcpuCommandRequest>,
     vcpu_instance: Box<dyn HypervisorVcpu>,
+    /// Where the vcpu is in the cpu topology
+    #[cfg(target_arch = "aarch64")]
+    mpidr: u64,
     booted: bool,
 }
 
 impl Vcpu {
-    pub fn new(vcpu_instance: Box<dyn HypervisorVcpu>, booted: bool) -> Self {
+    pub fn new(
+        vcpu_instance: Box<dyn HypervisorVcpu>,
+        #[cfg(target_arch = "aarch64")] mpidr: u64,
+        booted: bool,
+    ) -> Self {
         Vcpu {
             command_tx: vcpu_instance.command_tx(),
             vcpu_instance,
+            #[cfg(target_arch = "aarch64")]
+            mpidr,
             booted,
         }
     }
@@ -30,6 +41,11 @@ impl Vcpu {
         self.vcpu_instance.vcpu_id()
     }
 
+    #[cfg(target_arch = "aarch64")]
+    pub fn mpidr(&self) -> u64 {
+        self.mpidr
+    }
+
     pub fn booted(&self) -> bool {
         self.booted
     }
@@ -40,7 +56,7 @@ impl Vcpu {
 
         let register = self.read_registers().await?;
         let registers =
-            AArch64Registers::boot_registers(self.vcpu_id(), dtb_or_context_id, pc, register);
+            AArch64Registers::boot_registers(self.mpidr, dtb_or_context_id, pc, register);
         self.write_registers(registers).await?;
 
         Ok(())
@@ -51,7 +67,7 @@ impl Vcpu {
         &mut self,
         gdt: Gdt<5>,
         gdt_start: u32,
-        boot_params: u32,
+        boot_protocol: BootProtocol,
         kernel_start: u32,
     ) -> Result<(), CpuError> {
         use crate::arch::registers::x86_64::X86_64Registers;
@@ -61,7 +77,7 @@ impl Vcpu {
             gdt_start as u64,
             gdt,
             kernel_start as u64,
-            boot_params as u64,
+            boot_protocol,
             register,
         );
         self.write_registers(registers).await?;
@@ -84,6 +100,11 @@ impl Vcpu {
         }
     }
 
+    /// The vcpu has turned itself off, its thread has already stopped running it
+    pub fn power_off(&mut self) {
+        self.booted = false;
+    }
+
     pub async fn read_registers(&mut self) -> Result<ArchRegisters, CpuError> {
         match self
             .send_command_and_then_wait(VcpuCommand::ReadRegisters)
@@ -162,6 +183,24 @@ impl Vcpu {
         }
     }
 
+    /// Back to the power-on state, it has to be booted again
+    pub async fn reset(&mut self) -> Result<(), CpuError> {
+        self.vcpu_instance.tick()?;
+
+        match self.send_command_and_then_wait(VcpuCommand::Reset).await? {
+            VcpuCommandResponse::Empty => {
+                self.booted = false;
+
+                Ok(())
+            }
+            VcpuCommandResponse::Err(err) => {
+                error!(?err);
+                Err(CpuError::VcpuError(err))
+            }
+            _ => unreachable!(),
+        }
+    }
+
     pub async fn save(&self) -> Result<Vec<u8>, CpuError> {
         match self.send_command_and_then_wait(VcpuCommand::Save).await? {
             VcpuCommandResponse::Save(buf) => Ok(buf),
@@ -191,15 +230,74 @@ impl Vcpu {
         &self,
         command: VcpuCommand,
     ) -> Result<VcpuCommandResponse, CpuError> {
-        let (req, rx) = VcpuCommandRequest::new(command);
+        send_command_and_then_wait(&self.command_tx, command).await
+    }
 
-        self.command_tx
-            .upgrade()
-            .ok_or(CpuError::VcpuCommandDisconnected)?
-            .send(req)
-            .await
-            .map_err(|_| CpuError::VcpuCommandDisconnected)?;
+    /// Marks the vcpu as on and returns a handle to boot it, the vcpu manager does not have
+    /// to be held while the vcpu is booted
+    #[cfg(target_arch = "aarch64")]
+    pub fn power_on(&mut self) -> Result<VcpuPowerOn, CpuError> {
+        if self.booted {
+            return Err(CpuError::CpuAlreadyBooted(self.vcpu_id()));
+        }
+        self.booted = true;
+
+        Ok(VcpuPowerOn {
+            command_tx: self.command_tx.clone(),
+            vcpu_id: self.vcpu_id(),
+            mpidr: self.mpidr,
+        })
+    }
+}
+
+/// Boots a vcpu turned on by another one, e.g. from its thread on a PSCI CPU_ON
+#[cfg(target_arch = "aarch64")]
+pub struct VcpuPowerOn {
+    command_tx: WeakSender<VcpuCommandRequest>,
+    vcpu_id: u64,
+    mpidr: u64,
+}
+
+#[cfg(target_arch = "aarch64")]
+impl VcpuPowerOn {
+    pub async fn boot(&self, pc: u64, context_id: u64) -> Result<(), CpuError> {
+        use crate::arch::registers::aarch64::AArch64Registers;
+
+        let registers =
+            match send_command_and_then_wait(&self.command_tx, VcpuCommand::ReadRegisters).await? {
+                VcpuCommandResponse::Registers(regs) => *regs,
+                VcpuCommandResponse::Err(err) => return Err(CpuError::VcpuError(err)),
+                _ => unreachable!(),
+            };
+        let registers = AArch64Registers::boot_registers(self.mpidr, context_id, pc, registers);
+
+        for command in [
+            VcpuCommand::WriteRegisters(Box::new(registers)),
+            VcpuCommand::Resume,
+        ] {
+            match send_command_and_then_wait(&self.command_tx, command).await? {
+                VcpuCommandResponse::Empty => (),
+                VcpuCommandResponse::Err(err) => return Err(CpuError::VcpuError(err)),
+                _ => return Err(CpuError::BootVcpu(self.vcpu_id)),
+            }
+        }
 
-        rx.await.map_err(|_| CpuError::VcpuCommandDisconnected)
+        Ok(())
     }
 }
+
+async fn send_command_and_then_wait(
+    command_tx: &WeakSender<VcpuCommandRequest>,
+    command: VcpuCommand,
+) -> Result<VcpuCommandResponse, CpuError> {
+    let (req, rx) = VcpuCommandRequest::new(command);
+
+    command_tx
+        .upgrade()
+        .ok_or(CpuError::VcpuCommandDisconnected)?
+        .send(req)
+        .await
+        .map_err(|_| CpuError::VcpuCommandDisconnected)?;
+
+    rx.await.map_err(|_| CpuError::VcpuCommandDisconnected)
+}
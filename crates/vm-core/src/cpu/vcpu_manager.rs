@@ -1,6 +1,7 @@
 use std::sync::Arc;
 
 use vm_mm::manager::MemoryAddressSpace;
+use vm_utils::cpu_topology::CpuTopology;
 
 use crate::cpu::vcpu::Vcpu;
 use crate::cpu::vm_exit::VmExit;
@@ -11,33 +12,71 @@ pub mod snapshot;
 
 pub struct VcpuManager {
     vm_instance: Arc<dyn HypervisorVm>,
+    cpu_topology: CpuTopology,
+    /// The unplugged vcpus are kept after the present ones
     vcpus: Vec<Vcpu>,
+    present_vcpus: usize,
 }
 
 impl VcpuManager {
-    pub fn new(vm_instance: Arc<dyn HypervisorVm>) -> Self {
+    pub fn new(vm_instance: Arc<dyn HypervisorVm>, cpu_topology: CpuTopology) -> Self {
         VcpuManager {
             vm_instance,
+            cpu_topology,
             vcpus: Default::default(),
+            present_vcpus: 0,
         }
     }
 
+    pub fn cpu_topology(&self) -> &CpuTopology {
+        &self.cpu_topology
+    }
+
+    /// Every vcpu of the topology, including the ones not plugged
+    pub fn max_vcpus(&self) -> usize {
+        self.cpu_topology.vcpus() as usize
+    }
+
     pub fn get_active_vcpus(&self) -> usize {
-        self.vcpus.len()
+        self.present_vcpus
     }
 
     pub fn get_vcpu(&self, vcpu_id: usize) -> Result<&Vcpu, VmError> {
-        self.vcpus
+        self.vcpus[..self.present_vcpus]
             .get(vcpu_id)
             .ok_or(VmError::VcpuNotCreated(vcpu_id))
     }
 
     pub fn get_vcpu_mut(&mut self, vcpu_id: usize) -> Result<&mut Vcpu, VmError> {
-        self.vcpus
+        self.vcpus[..self.present_vcpus]
             .get_mut(vcpu_id)
             .ok_or(VmError::VcpuNotCreated(vcpu_id))
     }
 
+    /// The vcpu whose MPIDR_EL1 has the affinity of `mpidr`
+    #[cfg(target_arch = "aarch64")]
+    pub fn get_vcpu_by_mpidr_mut(&mut self, mpidr: u64) -> Result<&mut Vcpu, VmError> {
+        let vcpu_id = self
+            .cpu_topology
+            .vcpu_id_of_mpidr(mpidr)
+            .ok_or(VmError::VcpuNotExistsForMpidr(mpidr))?;
+
+        self.get_vcpu_mut(vcpu_id as usize)
+    }
+
+    /// Whether the vcpu is on, a vcpu is off until it is booted and after it turns itself off
+    #[cfg(target_arch = "aarch64")]
+    pub fn is_vcpu_on_by_mpidr(&mut self, mpidr: u64) -> Result<bool, VmError> {
+        Ok(self.get_vcpu_by_mpidr_mut(mpidr)?.booted())
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    pub fn power_off_vcpu_by_mpidr(&mut self, mpidr: u64) -> Result<(), VmError> {
+        self.get_vcpu_by_mpidr_mut(mpidr)?.power_off();
+
+        Ok(())
+    }
+
     pub fn create_vcpu(
         &mut self,
         vcpu_id: u64,
@@ -45,11 +84,58 @@ impl VcpuManager {
         vm_exit_handler: Arc<dyn VmExit>,
         booted: bool, // for recovery
     ) -> Result<(), VmError> {
-        let vcpu_instance = self.vm_instance.create_vcpu(vcpu_id, mm, vm_exit_handler)?;
+        let vcpu_instance =
+            self.vm_instance
+                .create_vcpu(vcpu_id, &self.cpu_topology, mm, vm_exit_handler)?;
 
-        let vcpu = Vcpu::new(vcpu_instance, booted);
+        let vcpu = Vcpu::new(
+            vcpu_instance,
+            #[cfg(target_arch = "aarch64")]
+            self.cpu_topology.mpidr(vcpu_id as u32),
+            booted,
+        );
 
         self.vcpus.push(vcpu);
+        self.present_vcpus += 1;
+
+        Ok(())
+    }
+
+    /// Plugs the vcpu next to the present ones. A vcpu unplugged before is plugged again
+    /// instead of being created, kvm can't destroy a vcpu.
+    pub fn plug_vcpu(
+        &mut self,
+        mm: Arc<MemoryAddressSpace>,
+        vm_exit_handler: Arc<dyn VmExit>,
+    ) -> Result<usize, VmError> {
+        let vcpu_id = self.present_vcpus;
+
+        if vcpu_id == self.max_vcpus() {
+            return Err(VmError::NoVcpuToPlug(self.max_vcpus()));
+        }
+
+        if vcpu_id < self.vcpus.len() {
+            self.present_vcpus += 1;
+        } else {
+            self.create_vcpu(vcpu_id as u64, mm, vm_exit_handler, false)?;
+        }
+
+        Ok(vcpu_id)
+    }
+
+    /// The vcpu is reset and parked until it is plugged again
+    pub async fn unplug_vcpu(&mut self, vcpu_id: usize) -> Result<(), VmError> {
+        if self.present_vcpus <= 1 {
+            return Err(VmError::NoVcpuToUnplug);
+        }
+
+        let expected = self.present_vcpus - 1;
+        if vcpu_id != expected {
+            return Err(VmError::UnplugNotLastVcpu { vcpu_id, expected });
+        }
+
+        self.vcpus[vcpu_id].reset().await?;
+        self.present_vcpus -= 1;
 
         Ok(())
     }
@@ -69,4 +155,16 @@ impl VcpuManager {
 
         Ok(())
     }
+
+    /// Stops every vcpu before resetting any of them, a running vcpu could otherwise wake
+    /// up one which is already reset
+    pub async fn reset_all_vcpus(&mut self) -> Result<(), VmError> {
+        self.pause_all_vcpus().await?;
+
+        for vcpu in &mut self.vcpus {
+            vcpu.reset().await?;
+        }
+
+        Ok(())
+    }
 }
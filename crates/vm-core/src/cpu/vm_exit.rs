@@ -1,6 +1,7 @@
 use thiserror::Error;
 
 use crate::device::error::DeviceError;
+use crate::device::system_event::SystemEvent;
 
 #[derive(Error, Debug)]
 pub enum VmExitHandlerError {
@@ -38,9 +39,12 @@ pub trait VmExit: Send + Sync {
 
     // fn in_mmio_region(&self, addr: u64) -> bool;
 
+    /// The hypervisor has stopped the vcpu for a system wide event, e.g. a triple fault
+    fn system_event(&self, event: SystemEvent);
+
     #[cfg(target_arch = "aarch64")]
     fn call_smc(
         &self,
         vcpu: &mut dyn crate::arch::aarch64::vcpu::AArch64Vcpu,
-    ) -> Result<(), VmExitHandlerError>;
+    ) -> Result<crate::arch::aarch64::firmware::psci::PsciCallResult, VmExitHandlerError>;
 }
@@ -2,14 +2,22 @@ use std::io::Read;
 use std::io::Write;
 
 use acpi_tables::Aml;
+use vm_snapshot::section::Sections;
 
+use crate::device::error::DeviceError;
 use crate::device::error::DeviceSnapshotError;
 use crate::device::mmio::mmio_device::MmioDevice;
 use crate::device::pio::pio_device::PioDevice;
+use crate::device::snapshot::load_section;
+use crate::device::snapshot::save_section;
 
+pub mod cpu_hotplug;
 pub mod error;
 pub mod mmio;
 pub mod pio;
+pub mod power_button;
+pub mod snapshot;
+pub mod system_event;
 
 pub trait Device: Send + Sync {
     fn name(&self) -> String;
@@ -30,6 +38,27 @@ pub trait Device: Send + Sync {
         Err(DeviceSnapshotError::DeviceNotSupportSnapshot(self.name()))
     }
 
+    /// Bumped when the layout written by `save` changes
+    fn snapshot_version(&self) -> u32 {
+        1
+    }
+
+    /// The device is saved as a section keyed by its name, a bus saves a section per child
+    fn save_sections(&self, sections: &mut Sections) -> Result<(), DeviceSnapshotError> {
+        save_section(self, self.name(), sections)
+    }
+
+    fn load_sections(&mut self, sections: &mut Sections) -> Result<(), DeviceSnapshotError> {
+        let id = self.name();
+
+        load_section(self, &id, sections)
+    }
+
+    /// Back to the power-on state, it is called while the vcpus are stopped
+    fn reset(&self) -> Result<(), DeviceError> {
+        Ok(())
+    }
+
     fn support_aml(&self) -> Option<&dyn Aml> {
         None
     }
@@ -49,4 +78,14 @@ pub trait Device: Send + Sync {
     fn support_mmio_transport_mut(&mut self) -> Option<&mut dyn MmioDevice> {
         None
     }
+
+    /// The mmio base of the device if it is an iommu translating the pci devices
+    fn iommu_mmio_base(&self) -> Option<u64> {
+        None
+    }
+
+    /// The mmio base and the gsi of the device if it is the serial console
+    fn serial_console(&self) -> Option<(u64, u32)> {
+        None
+    }
 }
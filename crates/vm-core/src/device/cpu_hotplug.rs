@@ -0,0 +1,11 @@
+/// Tells the guest about the vcpus plugged or unplugged at runtime
+pub trait CpuHotplug: Send + Sync {
+    /// The vcpu is created, the guest is notified to bring it online
+    fn plug(&self, vcpu_id: u32);
+
+    /// Asks the guest to release the vcpu, it is unplugged once the guest ejects it
+    fn request_unplug(&self, vcpu_id: u32);
+
+    /// An unplug requested before is not ejected by the guest yet
+    fn unplug_pending(&self) -> bool;
+}
@@ -14,6 +14,30 @@ pub enum DeviceSnapshotError {
 
     #[error("device {0} does not support snapshot")]
     DeviceNotSupportSnapshot(String),
+
+    #[error("the snapshot has no section for device {0}")]
+    MissingSection(String),
+
+    #[error("the snapshot section {0} does not belong to any device")]
+    UnknownSection(String),
+
+    #[error("the snapshot has two sections for device {0}")]
+    DuplicateSection(String),
+
+    #[error(
+        "the snapshot section of device {id} has version {found} but version {expected} is supported"
+    )]
+    VersionMismatch {
+        id: String,
+        found: u32,
+        expected: u32,
+    },
+
+    #[error("the snapshot section of device {0} is longer than the device state")]
+    TrailingData(String),
+
+    #[error("failed to restore device {name}: {err}")]
+    Restore { name: String, err: String },
 }
 
 #[derive(Error, Debug)]
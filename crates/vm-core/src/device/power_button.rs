@@ -0,0 +1,4 @@
+/// Pressing the button asks the guest to shut down gracefully
+pub trait PowerButton: Send + Sync {
+    fn press(&self);
+}
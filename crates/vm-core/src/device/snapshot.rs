@@ -0,0 +1,151 @@
+use std::io::Cursor;
+
+use vm_snapshot::section::Section;
+use vm_snapshot::section::Sections;
+
+use crate::device::Device;
+use crate::device::error::DeviceSnapshotError;
+
+/// A device without snapshot support has no state to quiesce
+pub fn pause_device<D>(device: &D) -> Result<(), DeviceSnapshotError>
+where
+    D: Device + ?Sized,
+{
+    match device.pause() {
+        Err(DeviceSnapshotError::DeviceNotSupportSnapshot(_)) => Ok(()),
+        result => result,
+    }
+}
+
+pub fn resume_device<D>(device: &D) -> Result<(), DeviceSnapshotError>
+where
+    D: Device + ?Sized,
+{
+    match device.resume() {
+        Err(DeviceSnapshotError::DeviceNotSupportSnapshot(_)) => Ok(()),
+        result => result,
+    }
+}
+
+pub fn save_section<D>(
+    device: &D,
+    id: String,
+    sections: &mut Sections,
+) -> Result<(), DeviceSnapshotError>
+where
+    D: Device + ?Sized,
+{
+    let mut data = vec![];
+    device.save(&mut data)?;
+
+    if sections.contains_key(&id) {
+        return Err(DeviceSnapshotError::DuplicateSection(id));
+    }
+    sections.insert(
+        id,
+        Section {
+            version: device.snapshot_version(),
+            data,
+        },
+    );
+
+    Ok(())
+}
+
+/// The section must exist, have the version of the device and be read to the end
+pub fn load_section<D>(
+    device: &mut D,
+    id: &str,
+    sections: &mut Sections,
+) -> Result<(), DeviceSnapshotError>
+where
+    D: Device + ?Sized,
+{
+    let section = sections
+        .remove(id)
+        .ok_or_else(|| DeviceSnapshotError::MissingSection(id.to_string()))?;
+
+    if section.version != device.snapshot_version() {
+        return Err(DeviceSnapshotError::VersionMismatch {
+            id: id.to_string(),
+            found: section.version,
+            expected: device.snapshot_version(),
+        });
+    }
+
+    let mut reader = Cursor::new(&section.data);
+    device.load(&mut reader)?;
+
+    if reader.position() != section.data.len() as u64 {
+        return Err(DeviceSnapshotError::TrailingData(id.to_string()));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::io::Write;
+
+    use super::*;
+
+    struct Counter {
+        value: u8,
+        version: u32,
+    }
+
+    impl Device for Counter {
+        fn name(&self) -> String {
+            "counter".to_string()
+        }
+
+        fn save(&self, writer: &mut dyn Write) -> Result<(), DeviceSnapshotError> {
+            writer.write_all(&[self.value])?;
+            Ok(())
+        }
+
+        fn load(&mut self, reader: &mut dyn Read) -> Result<(), DeviceSnapshotError> {
+            let mut value = [0; 1];
+            reader.read_exact(&mut value)?;
+            self.value = value[0];
+            Ok(())
+        }
+
+        fn snapshot_version(&self) -> u32 {
+            self.version
+        }
+    }
+
+    #[test]
+    fn test_snapshot_version() {
+        let mut sections = Sections::default();
+        let device = Counter {
+            value: 7,
+            version: 2,
+        };
+        device.save_sections(&mut sections).unwrap();
+        assert_eq!(sections["counter"].version, 2);
+
+        let mut restored = Counter {
+            value: 0,
+            version: 2,
+        };
+        restored.load_sections(&mut sections.clone()).unwrap();
+        assert_eq!(restored.value, 7);
+
+        let mut older = Counter {
+            value: 0,
+            version: 1,
+        };
+        assert!(matches!(
+            older.load_sections(&mut sections),
+            Err(DeviceSnapshotError::VersionMismatch {
+                found: 2,
+                expected: 1,
+                ..
+            })
+        ));
+        assert_eq!(older.value, 0);
+    }
+}
@@ -0,0 +1,16 @@
+/// Requests raised by the guest through a device that the vmm has to act on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SystemEvent {
+    /// The guest has entered S5 or otherwise powered itself off
+    Shutdown,
+    /// The guest has asked for a reboot, the vm is reset and booted again
+    Reset,
+    /// The guest has ejected a vcpu whose unplug was requested
+    VcpuEjected(u32),
+    /// The guest has released the device of a pci hot-plug slot whose unplug was requested
+    PciDeviceReleased(u32),
+}
+
+pub trait SystemEventNotifier: Send + Sync {
+    fn notify(&self, event: SystemEvent);
+}
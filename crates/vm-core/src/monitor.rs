@@ -12,6 +12,9 @@ pub enum MonitorError {
     #[error("{0}")]
     Serde(#[from] serde_json::Error),
 
+    #[error("unknown command {0}")]
+    UnknownCommand(String),
+
     #[error("unknown subcommand {0:?}")]
     UnknownSubcommand(Vec<String>),
 
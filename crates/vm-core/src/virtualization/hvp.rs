@@ -29,6 +29,10 @@ use hv_unsafe_call;
 pub struct AppleHypervisor;
 
 impl Hypervisor for AppleHypervisor {
+    fn name(&self) -> &'static str {
+        "hvf"
+    }
+
     fn create_vm(&self) -> Result<Arc<dyn HypervisorVm>, HypervisorError> {
         let vm_config = unsafe { hv_vm_config_create() };
         hv_unsafe_call!(hv_vm_config_set_el2_enabled(vm_config, true))
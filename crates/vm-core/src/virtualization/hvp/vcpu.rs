@@ -481,6 +481,7 @@ impl AArch64Vcpu for HvpVcpuInternal {
 fn handle_command(
     running: &AtomicBool,
     hvp_vcpu_handler: Arc<Mutex<HvpVcpuInternal>>,
+    reset_state: &[u8],
     cmd: VcpuCommand,
 ) -> Result<VcpuCommandResponse, VcpuError> {
     match cmd {
@@ -536,6 +537,15 @@ fn handle_command(
         VcpuCommand::Resume => {
             running.store(true, Ordering::Release);
 
+            Ok(VcpuCommandResponse::Empty)
+        }
+        VcpuCommand::Reset => {
+            running.store(false, Ordering::Release);
+
+            let mut handler = hvp_vcpu_handler.lock().unwrap();
+
+            handler.load(reset_state.to_vec())?;
+
             Ok(VcpuCommandResponse::Empty)
         }
     }
@@ -544,9 +554,10 @@ fn handle_command(
 fn handle_command_and_send_response(
     running: &AtomicBool,
     hvp_vcpu_handler: Arc<Mutex<HvpVcpuInternal>>,
+    reset_state: &[u8],
     cmd: VcpuCommandRequest,
 ) {
-    if let Err(_err) = match handle_command(running, hvp_vcpu_handler, cmd.cmd) {
+    if let Err(_err) = match handle_command(running, hvp_vcpu_handler, reset_state, cmd.cmd) {
         Ok(resp) => cmd.response.send(resp),
         Err(err) => cmd.response.send(VcpuCommandResponse::Err(err)),
     } {
@@ -584,6 +595,9 @@ impl HvpVcpu {
 
             let hvp_vcpu_handler = Arc::new(Mutex::new(HvpVcpuInternal { vcpu, mm }));
 
+            // Restored on reset
+            let reset_state = hvp_vcpu_handler.lock().unwrap().save()?;
+
             handler_tx.send(hvp_vcpu_handler.clone()).unwrap();
 
             loop {
@@ -594,6 +608,7 @@ impl HvpVcpu {
                             handle_command_and_send_response(
                                 &is_running,
                                 hvp_vcpu_handler.clone(),
+                                &reset_state,
                                 cmd,
                             );
 
@@ -643,6 +658,7 @@ impl HvpVcpu {
                             handle_command_and_send_response(
                                 &is_running,
                                 hvp_vcpu_handler.clone(),
+                                &reset_state,
                                 cmd,
                             )
                         })?;
@@ -12,7 +12,9 @@ use applevisor_sys::hv_gic_config_t;
 use applevisor_sys::hv_gic_create;
 use applevisor_sys::hv_gic_get_spi_interrupt_range;
 use applevisor_sys::hv_vm_map;
+use applevisor_sys::hv_vm_unmap;
 use vm_mm::manager::MemoryAddressSpace;
+use vm_utils::cpu_topology::CpuTopology;
 
 use crate::arch::aarch64::irq::GIC_SPI_START;
 use crate::arch::aarch64::layout::GIC_DISTRIBUTOR;
@@ -36,6 +38,7 @@ impl From<SetUserMemoryRegionFlags> for MemPerms {
     fn from(flags: SetUserMemoryRegionFlags) -> Self {
         match flags {
             SetUserMemoryRegionFlags::ReadWriteExec => MemPerms::ReadWriteExec,
+            SetUserMemoryRegionFlags::ReadExec => MemPerms::ReadExec,
         }
     }
 }
@@ -46,6 +49,7 @@ impl HypervisorVm for AppleHypervisorVm {
     fn create_vcpu(
         &self,
         vcpu_id: u64,
+        _cpu_topology: &CpuTopology,
         mm: Arc<MemoryAddressSpace>,
         vm_exit_handler: Arc<dyn VmExit>,
     ) -> Result<Box<dyn HypervisorVcpu>, VmError> {
@@ -172,6 +176,17 @@ impl HypervisorVm for AppleHypervisorVm {
         Ok(())
     }
 
+    fn remove_user_memory_region(
+        &self,
+        guest_phys_addr: u64,
+        memory_size: usize,
+    ) -> Result<(), VmError> {
+        hv_unsafe_call!(hv_vm_unmap(guest_phys_addr, memory_size))
+            .map_err(|err| VmError::SetUserMemoryRegionError(err.to_string()))?;
+
+        Ok(())
+    }
+
     fn secondary_cpu_should_run_on_booting(&self) -> bool {
         false
     }
@@ -6,5 +6,8 @@ use crate::virtualization::vm::HypervisorVm;
 pub mod error;
 
 pub trait Hypervisor {
+    /// Recorded in the snapshots, which are only restored by the same backend
+    fn name(&self) -> &'static str;
+
     fn create_vm(&self) -> Result<Arc<dyn HypervisorVm>, HypervisorError>;
 }
@@ -26,6 +26,9 @@ pub struct KvmHypervisor {
     kvm: Kvm,
     #[cfg(target_arch = "x86_64")]
     supported_cpuid_patched: CpuId,
+    /// The msrs restored on a vcpu reset
+    #[cfg(target_arch = "x86_64")]
+    msr_indices: Arc<[u32]>,
 }
 
 impl KvmHypervisor {
@@ -33,16 +36,24 @@ impl KvmHypervisor {
         let kvm = Kvm::new()?;
         #[cfg(target_arch = "x86_64")]
         let supported_cpuid_patched = kvm.get_supported_cpuid(KVM_MAX_CPUID_ENTRIES)?;
+        #[cfg(target_arch = "x86_64")]
+        let msr_indices = kvm.get_msr_index_list()?.as_slice().into();
 
         Ok(KvmHypervisor {
             kvm,
             #[cfg(target_arch = "x86_64")]
             supported_cpuid_patched,
+            #[cfg(target_arch = "x86_64")]
+            msr_indices,
         })
     }
 }
 
 impl Hypervisor for KvmHypervisor {
+    fn name(&self) -> &'static str {
+        "kvm"
+    }
+
     fn create_vm(&self) -> Result<Arc<dyn HypervisorVm>, HypervisorError> {
         let vm_fd = self.kvm.create_vm()?;
 
@@ -56,6 +67,8 @@ impl Hypervisor for KvmHypervisor {
             vm_fd,
             #[cfg(target_arch = "x86_64")]
             self.supported_cpuid_patched.clone(),
+            #[cfg(target_arch = "x86_64")]
+            self.msr_indices.clone(),
         )))
     }
 }
@@ -2,6 +2,11 @@ use std::hint::black_box;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering;
 
+use kvm_bindings::KVM_ARM_VCPU_POWER_OFF;
+use kvm_bindings::KVM_ARM_VCPU_PSCI_0_2;
+use kvm_bindings::kvm_vcpu_init;
+use kvm_ioctls::VcpuFd;
+use kvm_ioctls::VmFd;
 use vm_mm::manager::MemoryAddressSpace;
 
 use crate::arch::aarch64::vcpu::AArch64Vcpu;
@@ -13,6 +18,35 @@ use crate::virtualization::vcpu::command::VcpuCommand;
 use crate::virtualization::vcpu::command::VcpuCommandResponse;
 use crate::virtualization::vcpu::error::VcpuError;
 
+/// The KVM_ARM_VCPU_INIT argument of a vcpu, re-running it resets the vcpu to its power-on state
+pub struct KvmVcpuResetState {
+    init: kvm_vcpu_init,
+}
+
+impl KvmVcpuResetState {
+    /// Initializes the vcpu for the preferred target of the host, the secondary vcpus start
+    /// powered off until a PSCI CPU_ON
+    pub fn init(vm_fd: &VmFd, vcpu_fd: &VcpuFd, vcpu_id: u64) -> Result<Self, VcpuError> {
+        let mut init = kvm_vcpu_init::default();
+        vm_fd.get_preferred_target(&mut init)?;
+
+        init.features[0] |= 1 << KVM_ARM_VCPU_PSCI_0_2;
+        if vcpu_id != 0 {
+            init.features[0] |= 1 << KVM_ARM_VCPU_POWER_OFF;
+        }
+
+        vcpu_fd.vcpu_init(&init)?;
+
+        Ok(KvmVcpuResetState { init })
+    }
+
+    pub fn restore(&self, vcpu_fd: &VcpuFd) -> Result<(), VcpuError> {
+        vcpu_fd.vcpu_init(&self.init)?;
+
+        Ok(())
+    }
+}
+
 impl<'a> AArch64Vcpu for KvmVcpuInternal<'a> {
     fn get_core_reg(&self, _reg: CoreRegister) -> Result<u64, VcpuError> {
         black_box(self.vcpu_fd);
@@ -66,6 +100,13 @@ impl<'a> KvmVcpuInternal<'a> {
             VcpuCommand::Resume => {
                 is_running.store(true, Ordering::Release);
 
+                Ok(VcpuCommandResponse::Empty)
+            }
+            VcpuCommand::Reset => {
+                is_running.store(false, Ordering::Release);
+
+                self.reset_state.restore(self.vcpu_fd)?;
+
                 Ok(VcpuCommandResponse::Empty)
             }
         }
@@ -1,7 +1,17 @@
+use kvm_bindings::KVM_MAX_MSR_ENTRIES;
+use kvm_bindings::Msrs;
 use kvm_bindings::kvm_dtable;
+use kvm_bindings::kvm_fpu;
+use kvm_bindings::kvm_lapic_state;
+use kvm_bindings::kvm_mp_state;
+use kvm_bindings::kvm_msr_entry;
 use kvm_bindings::kvm_regs;
 use kvm_bindings::kvm_segment;
 use kvm_bindings::kvm_sregs;
+use kvm_bindings::kvm_vcpu_events;
+use kvm_bindings::kvm_xcrs;
+use kvm_ioctls::VcpuFd;
+use tracing::warn;
 
 use crate::arch::registers::x86_64::X86_64CoreRegisters;
 use crate::arch::registers::x86_64::X86_64Dtable;
@@ -16,6 +26,87 @@ use crate::virtualization::vcpu::error::VcpuError;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering;
 
+/// The state of a vcpu right after it is created, i.e. its power-on state
+pub struct KvmVcpuResetState {
+    regs: kvm_regs,
+    sregs: kvm_sregs,
+    fpu: kvm_fpu,
+    lapic: kvm_lapic_state,
+    mp_state: kvm_mp_state,
+    msrs: Vec<kvm_msr_entry>,
+    xcrs: kvm_xcrs,
+    vcpu_events: kvm_vcpu_events,
+}
+
+fn msrs(entries: &[kvm_msr_entry]) -> Result<Msrs, VcpuError> {
+    Msrs::from_entries(entries).map_err(|_| kvm_ioctls::Error::new(libc::ENOMEM).into())
+}
+
+impl KvmVcpuResetState {
+    /// `msr_indices` is the list of msrs kvm saves and restores
+    pub fn capture(vcpu_fd: &VcpuFd, msr_indices: &[u32]) -> Result<Self, VcpuError> {
+        Ok(KvmVcpuResetState {
+            regs: vcpu_fd.get_regs()?,
+            sregs: vcpu_fd.get_sregs()?,
+            fpu: vcpu_fd.get_fpu()?,
+            lapic: vcpu_fd.get_lapic()?,
+            mp_state: vcpu_fd.get_mp_state()?,
+            msrs: Self::capture_msrs(vcpu_fd, msr_indices)?,
+            xcrs: vcpu_fd.get_xcrs()?,
+            vcpu_events: vcpu_fd.get_vcpu_events()?,
+        })
+    }
+
+    /// KVM_GET_MSRS stops at the first msr it can't read, e.g. one of a feature the vcpu
+    /// does not have, it is skipped
+    fn capture_msrs(
+        vcpu_fd: &VcpuFd,
+        msr_indices: &[u32],
+    ) -> Result<Vec<kvm_msr_entry>, VcpuError> {
+        let mut entries = vec![];
+
+        let mut rest = msr_indices;
+        while !rest.is_empty() {
+            let chunk = &rest[..rest.len().min(KVM_MAX_MSR_ENTRIES)];
+            let mut chunk = msrs(
+                &chunk
+                    .iter()
+                    .map(|index| kvm_msr_entry {
+                        index: *index,
+                        ..Default::default()
+                    })
+                    .collect::<Vec<_>>(),
+            )?;
+
+            let read = vcpu_fd.get_msrs(&mut chunk)?;
+            entries.extend_from_slice(&chunk.as_slice()[..read]);
+            rest = &rest[(read + 1).min(rest.len())..];
+        }
+
+        Ok(entries)
+    }
+
+    fn restore(&self, vcpu_fd: &VcpuFd) -> Result<(), VcpuError> {
+        vcpu_fd.set_regs(&self.regs)?;
+        vcpu_fd.set_sregs(&self.sregs)?;
+        vcpu_fd.set_fpu(&self.fpu)?;
+        vcpu_fd.set_lapic(&self.lapic)?;
+        vcpu_fd.set_mp_state(self.mp_state)?;
+        vcpu_fd.set_xcrs(&self.xcrs)?;
+        vcpu_fd.set_vcpu_events(&self.vcpu_events)?;
+
+        for chunk in self.msrs.chunks(KVM_MAX_MSR_ENTRIES) {
+            let written = vcpu_fd.set_msrs(&msrs(chunk)?)?;
+            if written != chunk.len() {
+                let index = chunk[written].index;
+                warn!(index, "Failed to restore the msr");
+            }
+        }
+
+        Ok(())
+    }
+}
+
 impl From<kvm_segment> for X86_64Segment {
     fn from(seg: kvm_segment) -> Self {
         X86_64Segment {
@@ -250,6 +341,13 @@ impl<'a> KvmVcpuInternal<'a> {
             VcpuCommand::Resume => {
                 is_running.store(true, Ordering::Release);
 
+                Ok(VcpuCommandResponse::Empty)
+            }
+            VcpuCommand::Reset => {
+                is_running.store(false, Ordering::Release);
+
+                self.reset_state.restore(self.vcpu_fd)?;
+
                 Ok(VcpuCommandResponse::Empty)
             }
         }
@@ -1,6 +1,12 @@
+use std::os::unix::thread::JoinHandleExt;
+use std::ptr::NonNull;
+use std::ptr::null_mut;
 use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::Once;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering;
+use std::thread::JoinHandle;
 
 #[cfg(target_arch = "x86_64")]
 use kvm_bindings::CpuId;
@@ -12,8 +18,14 @@ use tokio::sync::mpsc::WeakSender;
 use tokio::sync::mpsc::error::TryRecvError;
 use tracing::error;
 use vm_mm::manager::MemoryAddressSpace;
+#[cfg(target_arch = "x86_64")]
+use vm_utils::cpu_topology::CpuTopology;
 
 use crate::cpu::vm_exit::VmExit;
+#[cfg(target_arch = "aarch64")]
+use crate::virtualization::kvm::arch::aarch64::KvmVcpuResetState;
+#[cfg(target_arch = "x86_64")]
+use crate::virtualization::kvm::arch::x86_64::KvmVcpuResetState;
 use crate::virtualization::kvm::vcpu::vm_exit::VmExitResult;
 use crate::virtualization::kvm::vcpu::vm_exit::handle_vm_exit;
 use crate::virtualization::vcpu::HypervisorVcpu;
@@ -27,6 +39,7 @@ mod vm_exit;
 
 pub struct KvmVcpuInternal<'a> {
     pub vcpu_fd: &'a VcpuFd,
+    pub reset_state: &'a KvmVcpuResetState,
 }
 
 impl<'a> KvmVcpuInternal<'a> {
@@ -44,9 +57,59 @@ impl<'a> KvmVcpuInternal<'a> {
     }
 }
 
+extern "C" fn handle_kick_signal(_: libc::c_int) {}
+
+/// The vcpu threads are kicked out of KVM_RUN by a signal, its handler does nothing but
+/// interrupting the ioctl
+fn register_kick_signal_handler() {
+    static REGISTER: Once = Once::new();
+
+    REGISTER.call_once(|| {
+        let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+        action.sa_sigaction = handle_kick_signal as extern "C" fn(libc::c_int) as usize;
+        // Without SA_RESTART the ioctl fails with EINTR
+        action.sa_flags = 0;
+
+        let ret = unsafe { libc::sigaction(libc::SIGRTMIN(), &action, null_mut()) };
+        if ret != 0 {
+            error!("Failed to register the vcpu kick signal handler");
+        }
+    });
+}
+
+/// `kvm_run.immediate_exit` of a vcpu. `tick` sets it before the kick signal, so KVM_RUN
+/// returns at once if the signal arrives before the vcpu thread enters it.
+#[derive(Default)]
+struct ImmediateExit(Mutex<Option<NonNull<u8>>>);
+
+// SAFETY: the pointer is only written under the lock, and it is cleared before the kvm_run
+// mapping goes away
+unsafe impl Send for ImmediateExit {}
+unsafe impl Sync for ImmediateExit {}
+
+impl ImmediateExit {
+    fn set(&self) {
+        if let Some(ptr) = *self.0.lock().unwrap() {
+            unsafe { ptr.as_ptr().write_volatile(1) };
+        }
+    }
+}
+
+/// Clears the `ImmediateExit` when the vcpu thread exits, before its vcpu fd is closed
+struct ImmediateExitGuard(Arc<ImmediateExit>);
+
+impl Drop for ImmediateExitGuard {
+    fn drop(&mut self) {
+        *self.0.0.lock().unwrap() = None;
+    }
+}
+
 pub struct KvmVcpu {
     vcpu_id: u64,
     command_tx: Sender<VcpuCommandRequest>,
+    is_running: Arc<AtomicBool>,
+    immediate_exit: Arc<ImmediateExit>,
+    join_handler: JoinHandle<Result<(), VcpuError>>,
 }
 
 impl KvmVcpu {
@@ -54,35 +117,63 @@ impl KvmVcpu {
         vm_fd: &VmFd,
         vcpu_id: u64,
         #[cfg(target_arch = "x86_64")] supported_cpuid: &CpuId,
+        #[cfg(target_arch = "x86_64")] cpu_topology: &CpuTopology,
+        #[cfg(target_arch = "x86_64")] msr_indices: Arc<[u32]>,
         vm_exit_handler: Arc<dyn VmExit>,
         _mm: Arc<MemoryAddressSpace>,
     ) -> Result<Self, VcpuError> {
-        let mut vcpu_fd = vm_fd.create_vcpu(vcpu_id)?;
+        // The kvm vcpu id is the initial apic id on x86
         #[cfg(target_arch = "x86_64")]
-        {
+        let mut vcpu_fd = {
             use crate::virtualization::kvm::vcpu::cpu_id::update_cpuid;
 
-            let cpuid = update_cpuid(
-                supported_cpuid,
-                vcpu_id
-                    .try_into()
-                    .map_err(|_| VcpuError::UpdateCpuid("vcpu_id too large"))?,
-            );
+            let index: u32 = vcpu_id
+                .try_into()
+                .map_err(|_| VcpuError::UpdateCpuid("vcpu_id too large"))?;
+            let vcpu_fd = vm_fd.create_vcpu(cpu_topology.apic_id(index) as u64)?;
+
+            let cpuid = update_cpuid(supported_cpuid, index, cpu_topology);
             vcpu_fd.set_cpuid2(&cpuid)?;
-        }
+
+            vcpu_fd
+        };
+        #[cfg(target_arch = "aarch64")]
+        let mut vcpu_fd = vm_fd.create_vcpu(vcpu_id)?;
+
+        // Restored on reset
+        #[cfg(target_arch = "aarch64")]
+        let reset_state = KvmVcpuResetState::init(vm_fd, &vcpu_fd, vcpu_id)?;
+
+        register_kick_signal_handler();
 
         let (command_tx, mut command_rx) = mpsc::channel(8);
         let is_running = Arc::new(AtomicBool::new(false));
 
-        let _join_handler = {
+        let immediate_exit = Arc::new(ImmediateExit::default());
+        *immediate_exit.0.lock().unwrap() =
+            Some(NonNull::from(&mut vcpu_fd.get_kvm_run().immediate_exit));
+
+        let join_handler = {
             let is_running = is_running.clone();
+            let immediate_exit = immediate_exit.clone();
 
             std::thread::spawn(move || -> Result<(), VcpuError> {
+                let mut vcpu_fd = vcpu_fd;
+                // Dropped before `vcpu_fd`
+                let _immediate_exit = ImmediateExitGuard(immediate_exit);
+
+                // Restored on reset
+                #[cfg(target_arch = "x86_64")]
+                let reset_state = KvmVcpuResetState::capture(&vcpu_fd, &msr_indices)?;
+
                 loop {
                     {
                         match command_rx.try_recv() {
                             Ok(request) => {
-                                let mut vcpu = KvmVcpuInternal { vcpu_fd: &vcpu_fd };
+                                let mut vcpu = KvmVcpuInternal {
+                                    vcpu_fd: &vcpu_fd,
+                                    reset_state: &reset_state,
+                                };
 
                                 vcpu.handle_command_and_send_response(&is_running, request);
 
@@ -108,6 +199,11 @@ impl KvmVcpu {
                                 Ok(vm_exit) => vm_exit,
                                 Err(err) => match err.errno() {
                                     libc::EAGAIN => continue,
+                                    // Kicked by tick
+                                    libc::EINTR => {
+                                        vcpu_fd.set_kvm_immediate_exit(0);
+                                        continue;
+                                    }
                                     _ => panic!("{err}"),
                                 },
                             };
@@ -115,6 +211,10 @@ impl KvmVcpu {
                             match handle_vm_exit(vm_exit, vm_exit_handler.as_ref()) {
                                 Ok(result) => match result {
                                     VmExitResult::Ok => continue,
+                                    VmExitResult::Stop => {
+                                        is_running.store(false, Ordering::Release);
+                                        continue;
+                                    }
                                 },
                                 Err(err) => {
                                     error!(?err, "Failed to handle vm exit");
@@ -130,7 +230,10 @@ impl KvmVcpu {
                             .blocking_recv()
                             .ok_or(VcpuError::VcpuCommandDisconnected)
                             .map(|request| {
-                                let mut vcpu = KvmVcpuInternal { vcpu_fd: &vcpu_fd };
+                                let mut vcpu = KvmVcpuInternal {
+                                    vcpu_fd: &vcpu_fd,
+                                    reset_state: &reset_state,
+                                };
 
                                 vcpu.handle_command_and_send_response(&is_running, request)
                             })?;
@@ -142,6 +245,9 @@ impl KvmVcpu {
         let vcpu = KvmVcpu {
             vcpu_id,
             command_tx,
+            is_running,
+            immediate_exit,
+            join_handler,
         };
 
         Ok(vcpu)
@@ -158,6 +264,16 @@ impl HypervisorVcpu for KvmVcpu {
     }
 
     fn tick(&self) -> Result<(), VcpuError> {
-        todo!()
+        if !self.is_running.swap(false, Ordering::AcqRel) {
+            return Ok(());
+        }
+
+        self.immediate_exit.set();
+        let ret = unsafe { libc::pthread_kill(self.join_handler.as_pthread_t(), libc::SIGRTMIN()) };
+        if ret != 0 {
+            return Err(kvm_ioctls::Error::new(ret).into());
+        }
+
+        Ok(())
     }
 }
@@ -1,15 +1,72 @@
 use kvm_bindings::CpuId;
+use vm_utils::cpu_topology::CpuTopology;
 
-pub fn update_cpuid(cpuid: &CpuId, vcpu_id: u8) -> CpuId {
+// Level types of the extended topology enumeration leaves
+const LEVEL_TYPE_INVALID: u32 = 0;
+const LEVEL_TYPE_SMT: u32 = 1;
+const LEVEL_TYPE_CORE: u32 = 2;
+
+/// CPUID.01H:EDX, the package has more than one logical processor
+const EDX_HTT: u32 = 1 << 28;
+
+pub fn update_cpuid(cpuid: &CpuId, vcpu_id: u32, cpu_topology: &CpuTopology) -> CpuId {
     let mut cpuid = cpuid.clone();
 
+    let apic_id = cpu_topology.apic_id(vcpu_id);
+    let thread_width = cpu_topology.thread_id_width();
+    let package_width = cpu_topology.core_id_width() + thread_width;
+
     for entry in cpuid.as_mut_slice() {
         match entry.function {
             // Version and Features
             0x01 => {
-                entry.ebx &= 0xffffff;
-                // Update INITIAL_APIC_ID
-                entry.ebx |= (vcpu_id as u32) << 24;
+                // Update INITIAL_APIC_ID and the addressable ids of the package
+                let logical_processors = (1u32 << package_width).min(0xff);
+                entry.ebx &= 0xffff;
+                entry.ebx |= (apic_id & 0xff) << 24;
+                entry.ebx |= logical_processors << 16;
+
+                if logical_processors > 1 {
+                    entry.edx |= EDX_HTT;
+                } else {
+                    entry.edx &= !EDX_HTT;
+                }
+            }
+            // Deterministic Cache Parameters
+            0x04 => {
+                // No more caches
+                if entry.eax & 0x1f == 0 {
+                    continue;
+                }
+
+                let cache_level = (entry.eax >> 5) & 0x7;
+                // L1 and L2 are private to a core, the others are shared by the package
+                let sharing_width = if cache_level <= 2 {
+                    thread_width
+                } else {
+                    package_width
+                };
+
+                entry.eax &= 0x3fff;
+                entry.eax |= ((1 << cpu_topology.core_id_width()) - 1) << 26;
+                entry.eax |= ((1 << sharing_width) - 1) << 14;
+            }
+            // Extended Topology Enumeration, V2
+            0x0b | 0x1f => {
+                let (shift, processors, level_type) = match entry.index {
+                    0 => (thread_width, cpu_topology.threads_per_core, LEVEL_TYPE_SMT),
+                    1 => (
+                        package_width,
+                        cpu_topology.threads_per_socket(),
+                        LEVEL_TYPE_CORE,
+                    ),
+                    _ => (0, 0, LEVEL_TYPE_INVALID),
+                };
+
+                entry.eax = shift;
+                entry.ebx = processors;
+                entry.ecx = (level_type << 8) | entry.index;
+                entry.edx = apic_id;
             }
             _ => continue,
         }
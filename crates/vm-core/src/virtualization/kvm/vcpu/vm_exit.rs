@@ -1,11 +1,15 @@
+use kvm_bindings::KVM_SYSTEM_EVENT_RESET;
 use kvm_ioctls::VcpuExit;
 use tracing::trace;
 
 use crate::cpu::vm_exit::VmExit;
 use crate::cpu::vm_exit::VmExitHandlerError;
+use crate::device::system_event::SystemEvent;
 
 pub enum VmExitResult {
     Ok,
+    /// The vcpu can't run any more until it is reset
+    Stop,
 }
 
 pub fn handle_vm_exit(
@@ -37,7 +41,11 @@ pub fn handle_vm_exit(
         VcpuExit::Debug(..) => todo!(),
         VcpuExit::Hlt => todo!(),
         VcpuExit::IrqWindowOpen => todo!(),
-        VcpuExit::Shutdown => todo!(),
+        VcpuExit::Shutdown => {
+            // A triple fault, the guest is rebooted like the real hardware does
+            handler.system_event(SystemEvent::Reset);
+            Ok(VmExitResult::Stop)
+        }
         VcpuExit::FailEntry(_, _) => todo!(),
         VcpuExit::Intr => todo!(),
         VcpuExit::SetTpr => todo!(),
@@ -53,7 +61,14 @@ pub fn handle_vm_exit(
         VcpuExit::Watchdog => todo!(),
         VcpuExit::S390Tsch => todo!(),
         VcpuExit::Epr => todo!(),
-        VcpuExit::SystemEvent(..) => todo!(),
+        VcpuExit::SystemEvent(event_type, _) => {
+            let event = match event_type {
+                KVM_SYSTEM_EVENT_RESET => SystemEvent::Reset,
+                _ => SystemEvent::Shutdown,
+            };
+            handler.system_event(event);
+            Ok(VmExitResult::Stop)
+        }
         VcpuExit::S390Stsi => todo!(),
         VcpuExit::IoapicEoi(_) => todo!(),
         VcpuExit::Hyperv => todo!(),
@@ -1,10 +1,14 @@
+use std::collections::BTreeMap;
 use std::sync::Arc;
+use std::sync::Mutex;
 
 #[cfg(target_arch = "x86_64")]
 use kvm_bindings::CpuId;
+use kvm_bindings::KVM_MEM_READONLY;
 use kvm_bindings::kvm_userspace_memory_region;
 use kvm_ioctls::VmFd;
 use vm_mm::manager::MemoryAddressSpace;
+use vm_utils::cpu_topology::CpuTopology;
 use vmm_sys_util::eventfd::EventFd;
 
 #[cfg(target_arch = "aarch64")]
@@ -28,21 +32,43 @@ use crate::virtualization::kvm::irq_chip::KvmIrqChip;
 use crate::virtualization::kvm::vcpu::KvmVcpu;
 use crate::virtualization::vcpu::HypervisorVcpu;
 use crate::virtualization::vm::HypervisorVm;
+use crate::virtualization::vm::IoEventAddress;
+use crate::virtualization::vm::IoEventDatamatch;
 use crate::virtualization::vm::SetUserMemoryRegionFlags;
 use crate::virtualization::vm::error::VmError;
 
 pub struct KvmVm {
     vm_fd: Arc<VmFd>,
+    /// gpa |-> memory slot
+    memory_slots: Mutex<BTreeMap<u64, u32>>,
     #[cfg(target_arch = "x86_64")]
     supported_cpuid_patched: CpuId,
+    #[cfg(target_arch = "x86_64")]
+    msr_indices: Arc<[u32]>,
 }
 
 impl KvmVm {
-    pub fn new(vm_fd: VmFd, #[cfg(target_arch = "x86_64")] supported_cpuid_patched: CpuId) -> Self {
+    pub fn new(
+        vm_fd: VmFd,
+        #[cfg(target_arch = "x86_64")] supported_cpuid_patched: CpuId,
+        #[cfg(target_arch = "x86_64")] msr_indices: Arc<[u32]>,
+    ) -> Self {
         KvmVm {
             vm_fd: Arc::new(vm_fd),
+            memory_slots: Default::default(),
             #[cfg(target_arch = "x86_64")]
             supported_cpuid_patched,
+            #[cfg(target_arch = "x86_64")]
+            msr_indices,
+        }
+    }
+}
+
+impl From<IoEventAddress> for kvm_ioctls::IoEventAddress {
+    fn from(addr: IoEventAddress) -> Self {
+        match addr {
+            IoEventAddress::Pio(port) => kvm_ioctls::IoEventAddress::Pio(port),
+            IoEventAddress::Mmio(addr) => kvm_ioctls::IoEventAddress::Mmio(addr),
         }
     }
 }
@@ -51,6 +77,8 @@ impl HypervisorVm for KvmVm {
     fn create_vcpu(
         &self,
         vcpu_id: u64,
+        #[cfg_attr(not(target_arch = "x86_64"), allow(unused_variables))]
+        cpu_topology: &CpuTopology,
         mm: Arc<MemoryAddressSpace>,
         vm_exit_handler: Arc<dyn VmExit>,
     ) -> Result<Box<dyn HypervisorVcpu>, VmError> {
@@ -59,6 +87,10 @@ impl HypervisorVm for KvmVm {
             vcpu_id,
             #[cfg(target_arch = "x86_64")]
             &self.supported_cpuid_patched,
+            #[cfg(target_arch = "x86_64")]
+            cpu_topology,
+            #[cfg(target_arch = "x86_64")]
+            self.msr_indices.clone(),
             vm_exit_handler,
             mm,
         )
@@ -95,19 +127,58 @@ impl HypervisorVm for KvmVm {
         userspace_addr: u64,
         guest_phys_addr: u64,
         memory_size: usize,
-        _flags: SetUserMemoryRegionFlags,
+        flags: SetUserMemoryRegionFlags,
     ) -> Result<(), VmError> {
+        let mut memory_slots = self.memory_slots.lock().unwrap();
+
+        let slot = (0..)
+            .find(|slot| !memory_slots.values().any(|used| used == slot))
+            .unwrap();
+
         (unsafe {
             self.vm_fd
                 .set_user_memory_region(kvm_userspace_memory_region {
-                    slot: 0,
-                    flags: 0,
+                    slot,
+                    flags: match flags {
+                        SetUserMemoryRegionFlags::ReadWriteExec => 0,
+                        SetUserMemoryRegionFlags::ReadExec => KVM_MEM_READONLY,
+                    },
                     guest_phys_addr,
                     memory_size: memory_size as u64,
                     userspace_addr,
                 })
         })?;
 
+        memory_slots.insert(guest_phys_addr, slot);
+
+        Ok(())
+    }
+
+    fn remove_user_memory_region(
+        &self,
+        guest_phys_addr: u64,
+        _memory_size: usize,
+    ) -> Result<(), VmError> {
+        let mut memory_slots = self.memory_slots.lock().unwrap();
+
+        let slot = *memory_slots
+            .get(&guest_phys_addr)
+            .ok_or(VmError::MemoryRegionNotMapped(guest_phys_addr))?;
+
+        // A zero sized region deletes the slot
+        (unsafe {
+            self.vm_fd
+                .set_user_memory_region(kvm_userspace_memory_region {
+                    slot,
+                    flags: 0,
+                    guest_phys_addr,
+                    memory_size: 0,
+                    userspace_addr: 0,
+                })
+        })?;
+
+        memory_slots.remove(&guest_phys_addr);
+
         Ok(())
     }
 
@@ -148,6 +219,38 @@ impl HypervisorVm for KvmVm {
         Ok(())
     }
 
+    fn register_ioeventfd(
+        &self,
+        fd: &EventFd,
+        addr: IoEventAddress,
+        datamatch: IoEventDatamatch,
+    ) -> Result<(), VmError> {
+        let addr = addr.into();
+
+        match datamatch {
+            IoEventDatamatch::U16(val) => self.vm_fd.register_ioevent(fd, &addr, val)?,
+            IoEventDatamatch::U32(val) => self.vm_fd.register_ioevent(fd, &addr, val)?,
+        }
+
+        Ok(())
+    }
+
+    fn unregister_ioeventfd(
+        &self,
+        fd: &EventFd,
+        addr: IoEventAddress,
+        datamatch: IoEventDatamatch,
+    ) -> Result<(), VmError> {
+        let addr = addr.into();
+
+        match datamatch {
+            IoEventDatamatch::U16(val) => self.vm_fd.unregister_ioevent(fd, &addr, val)?,
+            IoEventDatamatch::U32(val) => self.vm_fd.unregister_ioevent(fd, &addr, val)?,
+        }
+
+        Ok(())
+    }
+
     fn secondary_cpu_should_run_on_booting(&self) -> bool {
         true
     }
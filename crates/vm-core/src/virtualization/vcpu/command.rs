@@ -13,6 +13,8 @@ pub enum VcpuCommand {
     Load(Vec<u8>),
     TranslateGvaToGpa(u64),
     Resume,
+    /// Stops the vcpu and restores the registers it had when it was created
+    Reset,
 }
 
 pub enum VcpuCommandResponse {
@@ -1,6 +1,7 @@
 use std::sync::Arc;
 
 use vm_mm::manager::MemoryAddressSpace;
+use vm_utils::cpu_topology::CpuTopology;
 #[cfg(target_os = "linux")]
 use vmm_sys_util::eventfd::EventFd;
 
@@ -15,12 +16,30 @@ pub mod state;
 
 pub enum SetUserMemoryRegionFlags {
     ReadWriteExec,
+    /// The guest writes exit as mmio accesses, e.g. the code of a firmware
+    ReadExec,
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IoEventAddress {
+    Pio(u64),
+    Mmio(u64),
+}
+
+/// The value and width a guest write must match to signal an ioeventfd
+#[cfg(target_os = "linux")]
+#[derive(Clone, Copy, Debug)]
+pub enum IoEventDatamatch {
+    U16(u16),
+    U32(u32),
 }
 
 pub trait HypervisorVm: Send + Sync {
     fn create_vcpu(
         &self,
         vcpu_id: u64,
+        cpu_topology: &CpuTopology,
         mm: Arc<MemoryAddressSpace>,
         vm_exit_handler: Arc<dyn VmExit>,
     ) -> Result<Box<dyn HypervisorVcpu>, VmError>;
@@ -37,6 +56,13 @@ pub trait HypervisorVm: Send + Sync {
         flags: SetUserMemoryRegionFlags,
     ) -> Result<(), VmError>;
 
+    /// Remove a mapping previously installed by `set_user_memory_region`
+    fn remove_user_memory_region(
+        &self,
+        guest_phys_addr: u64,
+        memory_size: usize,
+    ) -> Result<(), VmError>;
+
     #[cfg(target_os = "linux")]
     fn set_irqfd(&self, fd: &EventFd, gsi: u32) -> Result<(), VmError>;
 
@@ -54,5 +80,22 @@ pub trait HypervisorVm: Send + Sync {
     #[cfg(target_os = "linux")]
     fn set_gsi_routing(&self) -> Result<(), VmError>;
 
+    /// Signal `fd` in the kernel, without exiting to userspace, when the guest writes `datamatch` to `addr`
+    #[cfg(target_os = "linux")]
+    fn register_ioeventfd(
+        &self,
+        fd: &EventFd,
+        addr: IoEventAddress,
+        datamatch: IoEventDatamatch,
+    ) -> Result<(), VmError>;
+
+    #[cfg(target_os = "linux")]
+    fn unregister_ioeventfd(
+        &self,
+        fd: &EventFd,
+        addr: IoEventAddress,
+        datamatch: IoEventDatamatch,
+    ) -> Result<(), VmError>;
+
     fn secondary_cpu_should_run_on_booting(&self) -> bool;
 }
@@ -1,6 +1,8 @@
 use thiserror::Error;
 
 use crate::cpu::error::CpuError;
+use crate::device::error::DeviceError;
+use crate::device::error::DeviceSnapshotError;
 use crate::interrupt_manager::InterruptManagerError;
 use crate::virtualization::vm::state::VmState;
 
@@ -9,6 +11,9 @@ pub enum VmError {
     #[error("Vcpu {0} is not exists")]
     VcpuNotCreated(usize),
 
+    #[error("No vcpu has the mpidr {0:#x}")]
+    VcpuNotExistsForMpidr(u64),
+
     #[error("Failed to create vcpu: {0}")]
     CreateVcpuError(Box<dyn std::error::Error + Send + Sync>),
 
@@ -24,6 +29,9 @@ pub enum VmError {
     #[error("Failed to create memory region")]
     MemoryRegionOverlap,
 
+    #[error("No memory region is mapped at gpa 0x{0:x}")]
+    MemoryRegionNotMapped(u64),
+
     #[cfg(target_os = "macos")]
     #[error("Applevisor error: {0}")]
     ApplevisorError(#[from] applevisor::error::HypervisorError),
@@ -41,6 +49,39 @@ pub enum VmError {
     #[error("vm state is not satisfied, current: {current:?}")]
     VmState { current: VmState },
 
+    #[error("All the {0} vcpus are plugged")]
+    NoVcpuToPlug(usize),
+
+    #[error("Only the boot vcpu is plugged")]
+    NoVcpuToUnplug,
+
+    #[error("Only the last plugged vcpu {expected} can be unplugged, got {vcpu_id}")]
+    UnplugNotLastVcpu { vcpu_id: usize, expected: usize },
+
+    #[error("The vm has no cpu hotplug controller")]
+    CpuHotplugNotPresent,
+
+    #[error("The guest has not ejected the vcpu being unplugged yet")]
+    VcpuUnplugPending,
+
+    #[error("The vm has no power button")]
+    PowerButtonNotPresent,
+
     #[error("Failed to create listener for gdbstub")]
     GdbListenerCreation,
+
+    #[error("Failed to reset device {name}: {err}")]
+    ResetDevice { name: String, err: DeviceError },
+
+    #[error("Failed to pause device {name}: {err}")]
+    PauseDevice {
+        name: String,
+        err: DeviceSnapshotError,
+    },
+
+    #[error("Failed to resume device {name}: {err}")]
+    ResumeDevice {
+        name: String,
+        err: DeviceSnapshotError,
+    },
 }
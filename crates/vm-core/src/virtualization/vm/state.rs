@@ -42,4 +42,8 @@ impl VmState {
     pub fn ensure_is_not_running(&self) -> Result<(), VmError> {
         self.ensure_is_not(VmState::Running)
     }
+
+    pub fn ensure_is_not_created(&self) -> Result<(), VmError> {
+        self.ensure_is_not(VmState::Created)
+    }
 }
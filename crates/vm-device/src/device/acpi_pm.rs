@@ -0,0 +1,146 @@
+use std::io::Read;
+use std::io::Write;
+use std::ops::Range;
+use std::sync::Arc;
+
+use acpi_tables::Aml;
+use acpi_tables::AmlSink;
+use acpi_tables::aml::Name;
+use acpi_tables::aml::Package;
+use tracing::warn;
+use vm_core::arch::x86_64::layout::ACPI_SLEEP_PORT;
+use vm_core::device::Device;
+use vm_core::device::error::DeviceError;
+use vm_core::device::error::DeviceSnapshotError;
+use vm_core::device::pio::pio_device::PioDevice;
+use vm_core::device::system_event::SystemEvent;
+use vm_core::device::system_event::SystemEventNotifier;
+use vm_utils::range_allocator::RangeAllocator;
+
+/*
+ * Sleep control and status registers of the hardware-reduced ACPI, they
+ * replace the PM1 register blocks
+ * ACPI 6.5, 4.8.3.7 Sleep Control and Status Registers
+ */
+
+const LEN: usize = 1;
+
+const SLP_TYP_SHIFT: u8 = 2;
+const SLP_TYP_MASK: u8 = 0x7;
+const SLP_EN: u8 = 1 << 5;
+
+/// The only sleep state supported, it is advertised by the _S5_ package
+const S5_SLEEP_TYPE: u8 = 5;
+
+pub struct AcpiPm {
+    notifier: Arc<dyn SystemEventNotifier>,
+}
+
+impl AcpiPm {
+    pub fn new(
+        pio_allocator: &mut RangeAllocator<u16>,
+        notifier: Arc<dyn SystemEventNotifier>,
+    ) -> Result<Self, DeviceError> {
+        pio_allocator.reserve(ACPI_SLEEP_PORT, LEN)?;
+
+        Ok(AcpiPm { notifier })
+    }
+}
+
+impl Device for AcpiPm {
+    fn name(&self) -> String {
+        "acpi_pm".to_string()
+    }
+
+    fn pause(&self) -> Result<(), DeviceSnapshotError> {
+        Ok(())
+    }
+
+    fn resume(&self) -> Result<(), DeviceSnapshotError> {
+        Ok(())
+    }
+
+    fn save(&self, _writer: &mut dyn Write) -> Result<(), DeviceSnapshotError> {
+        Ok(())
+    }
+
+    fn load(&mut self, _reader: &mut dyn Read) -> Result<(), DeviceSnapshotError> {
+        Ok(())
+    }
+
+    fn support_aml(&self) -> Option<&dyn Aml> {
+        Some(self)
+    }
+
+    fn support_pio_transport(&self) -> Option<&dyn PioDevice> {
+        Some(self)
+    }
+
+    fn support_pio_transport_mut(&mut self) -> Option<&mut dyn PioDevice> {
+        Some(self)
+    }
+}
+
+impl Aml for AcpiPm {
+    fn to_aml_bytes(&self, sink: &mut dyn AmlSink) {
+        Name::new("_S5_".into(), &Package::new(vec![&S5_SLEEP_TYPE])).to_aml_bytes(sink);
+    }
+}
+
+impl PioDevice for AcpiPm {
+    fn ports(&self) -> Vec<Range<u16>> {
+        let range = ACPI_SLEEP_PORT..ACPI_SLEEP_PORT + LEN as u16;
+        vec![range]
+    }
+
+    fn io_in(&self, _port: u16, data: &mut [u8]) -> Result<(), DeviceError> {
+        // WAK_STS is never set, the guest does not come back from S5
+        data.fill(0);
+
+        Ok(())
+    }
+
+    fn io_out(&self, _port: u16, data: &[u8]) -> Result<(), DeviceError> {
+        let val = data[0];
+
+        if val & SLP_EN == 0 {
+            return Ok(());
+        }
+
+        match (val >> SLP_TYP_SHIFT) & SLP_TYP_MASK {
+            S5_SLEEP_TYPE => self.notifier.notify(SystemEvent::Shutdown),
+            sleep_type => warn!(sleep_type, "Unsupported sleep type"),
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::Events;
+
+    #[test]
+    fn test_enter_s5() {
+        let mut pio_allocator = RangeAllocator::<u16>::default();
+        pio_allocator.insert(0, 0x1000).unwrap();
+
+        let events = Arc::new(Events::default());
+        let acpi_pm = AcpiPm::new(&mut pio_allocator, events.clone()).unwrap();
+
+        // SLP_TYP without SLP_EN is ignored
+        acpi_pm
+            .io_out(ACPI_SLEEP_PORT, &[S5_SLEEP_TYPE << SLP_TYP_SHIFT])
+            .unwrap();
+        assert!(events.0.lock().unwrap().is_empty());
+
+        acpi_pm
+            .io_out(
+                ACPI_SLEEP_PORT,
+                &[(S5_SLEEP_TYPE << SLP_TYP_SHIFT) | SLP_EN],
+            )
+            .unwrap();
+        assert_eq!(*events.0.lock().unwrap(), vec![SystemEvent::Shutdown]);
+    }
+}
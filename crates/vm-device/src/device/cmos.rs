@@ -1,20 +1,545 @@
+use std::io::Read;
+use std::io::Write;
 use std::ops::Range;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
 
+use tokio::select;
+use tokio::sync::Notify;
+use vm_core::arch::irq::InterruptController;
 use vm_core::device::Device;
 use vm_core::device::error::DeviceError;
+use vm_core::device::error::DeviceSnapshotError;
 use vm_core::device::pio::pio_device::PioDevice;
+use vm_snapshot::helper::read_u64;
+use vm_snapshot::helper::write_u64;
 use vm_utils::range_allocator::RangeAllocator;
 
+use crate::device::cmos::reg_b::RegB;
+use crate::device::cmos::reg_c::RegC;
+use crate::device::rtc::RtcConfig;
+
+/*
+ * MC146818 real time clock with 128 bytes of CMOS RAM
+ * https://wiki.osdev.org/CMOS
+ * https://web.stanford.edu/class/cs140/projects/pintos/specs/mc146818a.pdf
+ */
+
 const PORT: u16 = 0x70;
 const LEN: usize = 2;
+const IRQ: u32 = 8;
+
+const NVRAM_LEN: usize = 128;
+
+const INDEX_PORT: u16 = 0;
+const DATA_PORT: u16 = 1;
+const INDEX_MASK: u8 = 0x7f; // Bit 7 disables NMI
+
+const RTC_SECONDS: usize = 0x00;
+const RTC_SECONDS_ALARM: usize = 0x01;
+const RTC_MINUTES: usize = 0x02;
+const RTC_MINUTES_ALARM: usize = 0x03;
+const RTC_HOURS: usize = 0x04;
+const RTC_HOURS_ALARM: usize = 0x05;
+const RTC_DAY_OF_WEEK: usize = 0x06;
+const RTC_DAY_OF_MONTH: usize = 0x07;
+const RTC_MONTH: usize = 0x08;
+const RTC_YEAR: usize = 0x09;
+const RTC_REG_A: usize = 0x0a;
+const RTC_REG_B: usize = 0x0b;
+const RTC_REG_C: usize = 0x0c;
+const RTC_REG_D: usize = 0x0d;
+const RTC_CENTURY: usize = 0x32;
+
+// The memory size, read by a firmware booted without fw_cfg
+const CMOS_BASE_MEMORY: usize = 0x15;
+const CMOS_EXTENDED_MEMORY: usize = 0x17;
+const CMOS_EXTENDED_MEMORY_COPY: usize = 0x30;
+const CMOS_MEMORY_ABOVE_16M: usize = 0x34;
+const CMOS_MEMORY_ABOVE_4G: usize = 0x5b;
+
+const REG_A_UIP: u8 = 1 << 7;
+const REG_A_DV_MASK: u8 = 0x70;
+const REG_A_DV_32KHZ: u8 = 0x20;
+const REG_A_RS_MASK: u8 = 0x0f;
+
+const REG_D_VRT: u8 = 1 << 7;
+
+const HOUR_PM: u8 = 1 << 7;
+const ALARM_DONT_CARE: u8 = 0xc0;
+
+/// UIP is set this long before the time registers are updated
+const UPDATE_IN_PROGRESS: Duration = Duration::from_micros(244);
+
+mod reg_b {
+    use bitflags::bitflags;
+
+    bitflags! {
+        #[derive(Clone, Copy)]
+        pub struct RegB: u8 {
+            const DSE = 1 << 0;
+            const HOUR_24 = 1 << 1;
+            const DM_BINARY = 1 << 2;
+            const SQWE = 1 << 3;
+            const UIE = 1 << 4;
+            const AIE = 1 << 5;
+            const PIE = 1 << 6;
+            const SET = 1 << 7;
+        }
+    }
+}
+
+mod reg_c {
+    use bitflags::bitflags;
+
+    bitflags! {
+        #[derive(Clone, Copy)]
+        pub struct RegC: u8 {
+            const UF = 1 << 4;
+            const AF = 1 << 5;
+            const PF = 1 << 6;
+            const IRQF = 1 << 7;
+        }
+    }
+}
+
+fn to_bcd(v: u8) -> u8 {
+    ((v / 10) << 4) | (v % 10)
+}
+
+fn from_bcd(v: u8) -> u8 {
+    (v >> 4) * 10 + (v & 0x0f)
+}
+
+/// Days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: u8, day: u8) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let month = month as i64;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146097 + doe - 719468
+}
 
-pub struct Cmos;
+fn civil_from_days(days: i64) -> (i64, u8, u8) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let doe = days.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u8;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+/// Store the ram size in the layout used by qemu, which seabios and ovmf read
+fn write_memory_size(nvram: &mut [u8; NVRAM_LEN], ram_below_4g: u64, ram_above_4g: u64) {
+    const KB: u64 = 1 << 10;
+    const MB: u64 = 1 << 20;
+
+    let base_kb = (ram_below_4g.min(640 * KB) / KB) as u16;
+    nvram[CMOS_BASE_MEMORY..CMOS_BASE_MEMORY + 2].copy_from_slice(&base_kb.to_le_bytes());
+
+    // Between 1M and 64M, in KB
+    let extended_kb = (ram_below_4g.saturating_sub(MB) / KB).min(0xffff) as u16;
+    nvram[CMOS_EXTENDED_MEMORY..CMOS_EXTENDED_MEMORY + 2]
+        .copy_from_slice(&extended_kb.to_le_bytes());
+    nvram[CMOS_EXTENDED_MEMORY_COPY..CMOS_EXTENDED_MEMORY_COPY + 2]
+        .copy_from_slice(&extended_kb.to_le_bytes());
+
+    // Between 16M and 4G, in 64K units
+    let above_16m = (ram_below_4g.saturating_sub(16 * MB) >> 16).min(0xffff) as u16;
+    nvram[CMOS_MEMORY_ABOVE_16M..CMOS_MEMORY_ABOVE_16M + 2]
+        .copy_from_slice(&above_16m.to_le_bytes());
+
+    // In 64K units, 3 bytes
+    let above_4g = (ram_above_4g >> 16).min(0xff_ffff) as u32;
+    nvram[CMOS_MEMORY_ABOVE_4G..CMOS_MEMORY_ABOVE_4G + 3]
+        .copy_from_slice(&above_4g.to_le_bytes()[..3]);
+}
+
+fn host_time() -> Duration {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+struct CmosInternal {
+    irq_chip: Arc<dyn InterruptController>,
+    irq_state: bool,
+
+    index: u8,
+    nvram: [u8; NVRAM_LEN],
+    /// Seconds between the host UTC time and the guest clock
+    offset: i64,
+
+    next_update: Instant,
+    next_periodic: Option<Instant>,
+}
+
+impl CmosInternal {
+    fn new(irq_chip: Arc<dyn InterruptController>, offset: i64) -> Self {
+        let mut nvram = [0; NVRAM_LEN];
+        nvram[RTC_REG_A] = REG_A_DV_32KHZ | 0x06; // 1024Hz periodic rate
+        nvram[RTC_REG_B] = RegB::HOUR_24.bits();
+        nvram[RTC_REG_D] = REG_D_VRT;
+
+        let mut cmos = CmosInternal {
+            irq_chip,
+            irq_state: false,
+            index: 0,
+            nvram,
+            offset,
+            next_update: Instant::now(),
+            next_periodic: None,
+        };
+        cmos.rearm_update();
+        cmos.rearm_periodic();
+
+        cmos
+    }
+
+    fn reg_b(&self) -> RegB {
+        RegB::from_bits_retain(self.nvram[RTC_REG_B])
+    }
+
+    fn reg_c(&self) -> RegC {
+        RegC::from_bits_retain(self.nvram[RTC_REG_C])
+    }
+
+    /// Guest time in seconds and the nanoseconds into the current second
+    fn now(&self) -> (i64, u32) {
+        let host = host_time();
+
+        (host.as_secs() as i64 + self.offset, host.subsec_nanos())
+    }
+
+    fn encode(&self, v: u8) -> u8 {
+        if self.reg_b().contains(RegB::DM_BINARY) {
+            v
+        } else {
+            to_bcd(v)
+        }
+    }
+
+    fn decode(&self, v: u8) -> u8 {
+        if self.reg_b().contains(RegB::DM_BINARY) {
+            v
+        } else {
+            from_bcd(v)
+        }
+    }
+
+    fn encode_hour(&self, hour: u8) -> u8 {
+        if self.reg_b().contains(RegB::HOUR_24) {
+            return self.encode(hour);
+        }
+
+        let pm = if hour >= 12 { HOUR_PM } else { 0 };
+        match hour % 12 {
+            0 => self.encode(12) | pm,
+            hour => self.encode(hour) | pm,
+        }
+    }
+
+    fn decode_hour(&self, v: u8) -> u8 {
+        if self.reg_b().contains(RegB::HOUR_24) {
+            return self.decode(v);
+        }
+
+        let hour = self.decode(v & !HOUR_PM) % 12;
+        if v & HOUR_PM != 0 { hour + 12 } else { hour }
+    }
+
+    /// Store `secs` into the time registers in the current data mode
+    fn latch_time(&mut self, secs: i64) {
+        let days = secs.div_euclid(86400);
+        let secs = secs.rem_euclid(86400);
+        let (year, month, day) = civil_from_days(days);
+
+        self.nvram[RTC_SECONDS] = self.encode((secs % 60) as u8);
+        self.nvram[RTC_MINUTES] = self.encode((secs / 60 % 60) as u8);
+        self.nvram[RTC_HOURS] = self.encode_hour((secs / 3600) as u8);
+        // Sunday is 1
+        self.nvram[RTC_DAY_OF_WEEK] = self.encode(((days + 4).rem_euclid(7) + 1) as u8);
+        self.nvram[RTC_DAY_OF_MONTH] = self.encode(day);
+        self.nvram[RTC_MONTH] = self.encode(month);
+        self.nvram[RTC_YEAR] = self.encode(year.rem_euclid(100) as u8);
+        self.nvram[RTC_CENTURY] = self.encode(year.div_euclid(100).clamp(0, 99) as u8);
+    }
+
+    /// The time programmed by the guest in the time registers
+    fn programmed_time(&self) -> i64 {
+        let year = self.decode(self.nvram[RTC_CENTURY]) as i64 * 100
+            + self.decode(self.nvram[RTC_YEAR]) as i64;
+        let days = days_from_civil(
+            year,
+            self.decode(self.nvram[RTC_MONTH]),
+            self.decode(self.nvram[RTC_DAY_OF_MONTH]),
+        );
+
+        days * 86400
+            + self.decode_hour(self.nvram[RTC_HOURS]) as i64 * 3600
+            + self.decode(self.nvram[RTC_MINUTES]) as i64 * 60
+            + self.decode(self.nvram[RTC_SECONDS]) as i64
+    }
+
+    fn apply_programmed_time(&mut self) {
+        self.offset = self.programmed_time() - host_time().as_secs() as i64;
+        self.rearm_update();
+    }
+
+    fn periodic_interval(&self) -> Option<Duration> {
+        let reg_a = self.nvram[RTC_REG_A];
+        if reg_a & REG_A_DV_MASK != REG_A_DV_32KHZ {
+            return None;
+        }
+
+        // Rate 1 and 2 are aliases of 8 and 9 with the 32.768kHz time base
+        let rate = match reg_a & REG_A_RS_MASK {
+            0 => return None,
+            rate @ 1..=2 => rate + 7,
+            rate => rate,
+        };
+
+        Some(Duration::from_nanos((1_000_000_000 << (rate - 1)) / 32768))
+    }
+
+    fn rearm_update(&mut self) {
+        let (_, nanos) = self.now();
+        self.next_update = Instant::now() + Duration::from_nanos(1_000_000_000 - nanos as u64);
+    }
+
+    fn rearm_periodic(&mut self) {
+        self.next_periodic = self
+            .periodic_interval()
+            .map(|interval| Instant::now() + interval);
+    }
+
+    fn next_event(&self) -> Option<Instant> {
+        let reg_b = self.reg_b();
+
+        let periodic = self.next_periodic.filter(|_| reg_b.contains(RegB::PIE));
+        let update = Some(self.next_update)
+            .filter(|_| !reg_b.contains(RegB::SET) && reg_b.intersects(RegB::UIE | RegB::AIE));
+
+        match (periodic, update) {
+            (Some(periodic), Some(update)) => Some(periodic.min(update)),
+            (periodic, update) => periodic.or(update),
+        }
+    }
+
+    fn tick(&mut self, now: Instant) {
+        let mut reg_c = self.reg_c();
+
+        if let Some(next_periodic) = self.next_periodic
+            && now >= next_periodic
+        {
+            reg_c.insert(RegC::PF);
+
+            // Missed periods are coalesced
+            let interval = self.periodic_interval().unwrap();
+            let next_periodic = next_periodic + interval;
+            self.next_periodic = Some(if next_periodic > now {
+                next_periodic
+            } else {
+                now + interval
+            });
+        }
+
+        if now >= self.next_update && !self.reg_b().contains(RegB::SET) {
+            reg_c.insert(RegC::UF);
+
+            let (secs, _) = self.now();
+            self.latch_time(secs);
+
+            let alarm_matches = [
+                (RTC_SECONDS_ALARM, RTC_SECONDS),
+                (RTC_MINUTES_ALARM, RTC_MINUTES),
+                (RTC_HOURS_ALARM, RTC_HOURS),
+            ]
+            .iter()
+            .all(|(alarm, time)| {
+                let alarm = self.nvram[*alarm];
+                alarm & ALARM_DONT_CARE == ALARM_DONT_CARE || alarm == self.nvram[*time]
+            });
+            if alarm_matches {
+                reg_c.insert(RegC::AF);
+            }
+
+            self.rearm_update();
+        }
+
+        self.nvram[RTC_REG_C] = reg_c.bits();
+        self.update_irq();
+    }
+
+    fn update_irq(&mut self) {
+        let reg_b = self.reg_b();
+        let mut reg_c = self.reg_c();
+
+        let pending = (reg_c.contains(RegC::PF) && reg_b.contains(RegB::PIE))
+            || (reg_c.contains(RegC::AF) && reg_b.contains(RegB::AIE))
+            || (reg_c.contains(RegC::UF) && reg_b.contains(RegB::UIE));
+
+        reg_c.set(RegC::IRQF, pending);
+        self.nvram[RTC_REG_C] = reg_c.bits();
+
+        if pending != self.irq_state {
+            self.irq_chip.trigger_irq(IRQ, pending);
+        }
+        self.irq_state = pending;
+    }
+
+    fn read(&mut self) -> u8 {
+        let index = self.index as usize;
+
+        match index {
+            RTC_SECONDS | RTC_MINUTES | RTC_HOURS | RTC_DAY_OF_WEEK | RTC_DAY_OF_MONTH
+            | RTC_MONTH | RTC_YEAR | RTC_CENTURY => {
+                // The registers hold the programmed time while SET is on
+                if !self.reg_b().contains(RegB::SET) {
+                    let (secs, _) = self.now();
+                    self.latch_time(secs);
+                }
+
+                self.nvram[index]
+            }
+            RTC_REG_A => {
+                let (_, nanos) = self.now();
+                let updating = !self.reg_b().contains(RegB::SET)
+                    && Duration::from_nanos(1_000_000_000 - nanos as u64) <= UPDATE_IN_PROGRESS;
+
+                if updating {
+                    self.nvram[index] | REG_A_UIP
+                } else {
+                    self.nvram[index]
+                }
+            }
+            RTC_REG_C => {
+                // Reading register C acknowledges all interrupts
+                let reg_c = self.nvram[index];
+                self.nvram[index] = 0;
+                self.update_irq();
+
+                reg_c
+            }
+            _ => self.nvram[index],
+        }
+    }
+
+    fn write(&mut self, data: u8) {
+        let index = self.index as usize;
+
+        match index {
+            RTC_SECONDS | RTC_MINUTES | RTC_HOURS | RTC_DAY_OF_WEEK | RTC_DAY_OF_MONTH
+            | RTC_MONTH | RTC_YEAR | RTC_CENTURY => {
+                if self.reg_b().contains(RegB::SET) {
+                    self.nvram[index] = data;
+                } else {
+                    // Update a single field of the running clock
+                    let (secs, _) = self.now();
+                    self.latch_time(secs);
+                    self.nvram[index] = data;
+                    self.apply_programmed_time();
+                }
+            }
+            RTC_REG_A => {
+                self.nvram[index] = data & !REG_A_UIP;
+                self.rearm_periodic();
+            }
+            RTC_REG_B => {
+                let old = self.reg_b();
+                let mut new = RegB::from_bits_retain(data);
+
+                if new.contains(RegB::SET) {
+                    // Setting SET aborts the update cycle and disables UIE
+                    new.remove(RegB::UIE);
+
+                    if !old.contains(RegB::SET) {
+                        let (secs, _) = self.now();
+                        self.latch_time(secs);
+                    }
+                }
+
+                self.nvram[index] = new.bits();
+
+                if old.contains(RegB::SET) && !new.contains(RegB::SET) {
+                    self.apply_programmed_time();
+                }
+
+                if !old.contains(RegB::PIE) && new.contains(RegB::PIE) {
+                    self.rearm_periodic();
+                }
+
+                if !old.intersects(RegB::UIE | RegB::AIE) && new.intersects(RegB::UIE | RegB::AIE) {
+                    self.rearm_update();
+                }
+
+                self.update_irq();
+            }
+            RTC_REG_C | RTC_REG_D => (), // Read only
+            _ => self.nvram[index] = data,
+        }
+    }
+}
+
+pub struct Cmos {
+    cmos: Arc<Mutex<CmosInternal>>,
+    timer: Arc<Notify>,
+}
 
 impl Cmos {
-    pub fn new(pio_allocator: &mut RangeAllocator<u16>) -> Result<Self, DeviceError> {
+    pub fn new(
+        pio_allocator: &mut RangeAllocator<u16>,
+        irq_chip: Arc<dyn InterruptController>,
+        config: &RtcConfig,
+    ) -> Result<Self, DeviceError> {
         let _ = pio_allocator.reserve(PORT, LEN)?;
 
-        Ok(Cmos)
+        let cmos = Arc::new(Mutex::new(CmosInternal::new(irq_chip, config.offset())));
+        let timer = Arc::new(Notify::new());
+
+        // Raise the periodic, alarm and update-ended interrupts
+        tokio::spawn({
+            let cmos = cmos.clone();
+            let timer = timer.clone();
+
+            async move {
+                loop {
+                    let next_event = cmos.lock().unwrap().next_event();
+
+                    match next_event {
+                        Some(deadline) => select! {
+                            _ = timer.notified() => {},
+                            _ = tokio::time::sleep_until(deadline.into()) => {
+                                cmos.lock().unwrap().tick(Instant::now());
+                            }
+                        },
+                        None => timer.notified().await,
+                    }
+                }
+            }
+        });
+
+        Ok(Cmos { cmos, timer })
+    }
+
+    /// A firmware sizes the ram from the cmos, there is no fw_cfg
+    pub fn set_memory_size(&self, ram_below_4g: u64, ram_above_4g: u64) {
+        write_memory_size(
+            &mut self.cmos.lock().unwrap().nvram,
+            ram_below_4g,
+            ram_above_4g,
+        );
     }
 }
 
@@ -23,6 +548,44 @@ impl Device for Cmos {
         "cmos".to_string()
     }
 
+    fn pause(&self) -> Result<(), DeviceSnapshotError> {
+        Ok(())
+    }
+
+    fn resume(&self) -> Result<(), DeviceSnapshotError> {
+        Ok(())
+    }
+
+    fn save(&self, writer: &mut dyn Write) -> Result<(), DeviceSnapshotError> {
+        let cmos = self.cmos.lock().unwrap();
+
+        writer.write_all(&[cmos.index])?;
+        writer.write_all(&cmos.nvram)?;
+        write_u64(writer, cmos.offset as u64)?;
+
+        Ok(())
+    }
+
+    fn load(&mut self, reader: &mut dyn Read) -> Result<(), DeviceSnapshotError> {
+        {
+            let mut cmos = self.cmos.lock().unwrap();
+
+            let mut index = [0u8; 1];
+            reader.read_exact(&mut index)?;
+            cmos.index = index[0];
+            reader.read_exact(&mut cmos.nvram)?;
+            cmos.offset = read_u64(reader)? as i64;
+
+            cmos.rearm_update();
+            cmos.rearm_periodic();
+            cmos.update_irq();
+        }
+
+        self.timer.notify_one();
+
+        Ok(())
+    }
+
     fn support_pio_transport(&self) -> Option<&dyn PioDevice> {
         Some(self)
     }
@@ -38,13 +601,268 @@ impl PioDevice for Cmos {
         vec![range]
     }
 
-    fn io_in(&self, _port: u16, _data: &mut [u8]) -> Result<(), DeviceError> {
-        // TODO
+    fn io_in(&self, port: u16, data: &mut [u8]) -> Result<(), DeviceError> {
+        let mut cmos = self.cmos.lock().unwrap();
+
+        match port - PORT {
+            INDEX_PORT => data.fill(cmos.index),
+            DATA_PORT => data.fill(cmos.read()),
+            _ => unreachable!(),
+        }
+
         Ok(())
     }
 
-    fn io_out(&self, _port: u16, _data: &[u8]) -> Result<(), DeviceError> {
-        // TODO
+    fn io_out(&self, port: u16, data: &[u8]) -> Result<(), DeviceError> {
+        {
+            let mut cmos = self.cmos.lock().unwrap();
+
+            match port - PORT {
+                INDEX_PORT => cmos.index = data[0] & INDEX_MASK,
+                DATA_PORT => cmos.write(data[0]),
+                _ => unreachable!(),
+            }
+        }
+
+        // Interrupt enables and rates may have changed
+        self.timer.notify_one();
+
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tokio::runtime::Builder;
+    use tokio::runtime::Runtime;
+
+    use super::*;
+    use crate::test_utils::IrqChip;
+
+    /// The timer task is never polled, the tests tick the clock themselves
+    fn new_cmos() -> (Runtime, Cmos, Arc<IrqChip>) {
+        let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+        let _guard = runtime.enter();
+
+        let mut pio_allocator = RangeAllocator::<u16>::default();
+        pio_allocator.insert(0, 0x1000).unwrap();
+
+        let irq_chip = Arc::new(IrqChip::default());
+        let cmos = Cmos::new(&mut pio_allocator, irq_chip.clone(), &RtcConfig::default()).unwrap();
+
+        (runtime, cmos, irq_chip)
+    }
+
+    fn read(cmos: &Cmos, index: usize) -> u8 {
+        cmos.io_out(PORT + INDEX_PORT, &[index as u8]).unwrap();
+        let mut data = [0];
+        cmos.io_in(PORT + DATA_PORT, &mut data).unwrap();
+        data[0]
+    }
+
+    fn write(cmos: &Cmos, index: usize, data: u8) {
+        cmos.io_out(PORT + INDEX_PORT, &[index as u8]).unwrap();
+        cmos.io_out(PORT + DATA_PORT, &[data]).unwrap();
+    }
+
+    fn tick(cmos: &Cmos, after: Duration) {
+        cmos.cmos.lock().unwrap().tick(Instant::now() + after);
+    }
+
+    /// 2024-03-15 13:45:30 in bcd and 24h mode, without periodic interrupts
+    fn program_time(cmos: &Cmos) {
+        write(cmos, RTC_REG_A, REG_A_DV_32KHZ);
+        write(cmos, RTC_REG_B, (RegB::HOUR_24 | RegB::SET).bits());
+        for (index, data) in [
+            (RTC_CENTURY, 0x20),
+            (RTC_YEAR, 0x24),
+            (RTC_MONTH, 0x03),
+            (RTC_DAY_OF_MONTH, 0x15),
+            (RTC_HOURS, 0x13),
+            (RTC_MINUTES, 0x45),
+            (RTC_SECONDS, 0x30),
+        ] {
+            write(cmos, index, data);
+        }
+        write(cmos, RTC_REG_B, RegB::HOUR_24.bits());
+    }
+
+    #[test]
+    fn test_data_mode_and_hour_format() {
+        let (_runtime, cmos, _) = new_cmos();
+        program_time(&cmos);
+
+        assert_eq!(read(&cmos, RTC_HOURS), 0x13);
+        assert_eq!(read(&cmos, RTC_MINUTES), 0x45);
+        assert_eq!(read(&cmos, RTC_MONTH), 0x03);
+        assert_eq!(read(&cmos, RTC_CENTURY), 0x20);
+
+        // 12h mode, bit 7 is PM
+        write(&cmos, RTC_REG_B, 0);
+        assert_eq!(read(&cmos, RTC_HOURS), HOUR_PM | 0x01);
+
+        write(&cmos, RTC_REG_B, RegB::DM_BINARY.bits());
+        assert_eq!(read(&cmos, RTC_HOURS), HOUR_PM | 1);
+        assert_eq!(read(&cmos, RTC_MINUTES), 45);
+
+        write(&cmos, RTC_REG_B, (RegB::DM_BINARY | RegB::HOUR_24).bits());
+        assert_eq!(read(&cmos, RTC_HOURS), 13);
+        assert_eq!(read(&cmos, RTC_DAY_OF_MONTH), 15);
+        assert_eq!(read(&cmos, RTC_YEAR), 24);
+        // Friday
+        assert_eq!(read(&cmos, RTC_DAY_OF_WEEK), 6);
+    }
+
+    #[test]
+    fn test_set_freezes_updates() {
+        let (_runtime, cmos, irq_chip) = new_cmos();
+        program_time(&cmos);
+
+        // Setting SET drops UIE
+        write(
+            &cmos,
+            RTC_REG_B,
+            (RegB::HOUR_24 | RegB::UIE | RegB::SET).bits(),
+        );
+        assert_eq!(read(&cmos, RTC_REG_B), (RegB::HOUR_24 | RegB::SET).bits());
+
+        write(&cmos, RTC_SECONDS, 0x59);
+        tick(&cmos, Duration::from_secs(2));
+
+        assert_eq!(read(&cmos, RTC_SECONDS), 0x59);
+        assert_eq!(read(&cmos, RTC_MINUTES), 0x45);
+        assert_eq!(read(&cmos, RTC_REG_A) & REG_A_UIP, 0);
+        assert_eq!(read(&cmos, RTC_REG_C), 0);
+        assert!(!irq_chip.is_active());
+
+        // The clock runs from the programmed time again
+        write(&cmos, RTC_REG_B, RegB::HOUR_24.bits());
+        assert_eq!(read(&cmos, RTC_HOURS), 0x13);
+        assert!(matches!(read(&cmos, RTC_MINUTES), 0x45 | 0x46));
+    }
+
+    #[test]
+    fn test_periodic_interrupt() {
+        let (_runtime, cmos, irq_chip) = new_cmos();
+
+        // SET keeps the update-ended flag out of the way
+        write(&cmos, RTC_REG_A, REG_A_DV_32KHZ | 0x06);
+        write(
+            &cmos,
+            RTC_REG_B,
+            (RegB::HOUR_24 | RegB::SET | RegB::PIE).bits(),
+        );
+        tick(&cmos, Duration::from_millis(1));
+
+        assert!(irq_chip.is_active());
+        // Register C is cleared by the read
+        assert_eq!(read(&cmos, RTC_REG_C), (RegC::IRQF | RegC::PF).bits());
+        assert!(!irq_chip.is_active());
+        assert_eq!(read(&cmos, RTC_REG_C), 0);
+
+        // Flags are set but no interrupt is raised once PIE is off
+        write(&cmos, RTC_REG_B, (RegB::HOUR_24 | RegB::SET).bits());
+        tick(&cmos, Duration::from_millis(2));
+        assert!(!irq_chip.is_active());
+        assert_eq!(read(&cmos, RTC_REG_C), RegC::PF.bits());
+    }
+
+    #[test]
+    fn test_alarm_and_update_interrupts() {
+        let (_runtime, cmos, irq_chip) = new_cmos();
+        program_time(&cmos);
+
+        write(&cmos, RTC_REG_B, (RegB::HOUR_24 | RegB::UIE).bits());
+        tick(&cmos, Duration::from_secs(1));
+
+        assert!(irq_chip.is_active());
+        let reg_c = RegC::from_bits_retain(read(&cmos, RTC_REG_C));
+        assert!(reg_c.contains(RegC::IRQF | RegC::UF));
+        assert!(!irq_chip.is_active());
+
+        // An alarm an hour away does not match
+        write(&cmos, RTC_SECONDS_ALARM, ALARM_DONT_CARE);
+        write(&cmos, RTC_MINUTES_ALARM, ALARM_DONT_CARE);
+        write(&cmos, RTC_HOURS_ALARM, 0x15);
+        write(&cmos, RTC_REG_B, (RegB::HOUR_24 | RegB::AIE).bits());
+        tick(&cmos, Duration::from_secs(2));
+
+        assert!(!irq_chip.is_active());
+        assert_eq!(read(&cmos, RTC_REG_C), RegC::UF.bits());
+
+        // Every second
+        write(&cmos, RTC_HOURS_ALARM, ALARM_DONT_CARE);
+        tick(&cmos, Duration::from_secs(3));
+
+        assert!(irq_chip.is_active());
+        assert_eq!(
+            read(&cmos, RTC_REG_C),
+            (RegC::IRQF | RegC::AF | RegC::UF).bits()
+        );
+        assert!(!irq_chip.is_active());
+    }
+
+    #[test]
+    fn test_nvram_snapshot() {
+        let (_runtime, cmos, _) = new_cmos();
+        program_time(&cmos);
+        write(&cmos, 0x40, 0xaa);
+        write(&cmos, NVRAM_LEN - 1, 0x55);
+        cmos.io_out(PORT + INDEX_PORT, &[0x40]).unwrap();
+
+        let mut buf = vec![];
+        cmos.save(&mut buf).unwrap();
+
+        let (_runtime, mut restored, _) = new_cmos();
+        restored.load(&mut buf.as_slice()).unwrap();
+
+        // The index register is restored too
+        let mut data = [0];
+        restored.io_in(PORT + DATA_PORT, &mut data).unwrap();
+        assert_eq!(data[0], 0xaa);
+        assert_eq!(read(&restored, NVRAM_LEN - 1), 0x55);
+        assert_eq!(read(&restored, RTC_HOURS), 0x13);
+        assert_eq!(read(&restored, RTC_DAY_OF_MONTH), 0x15);
+    }
+
+    #[test]
+    fn test_bcd() {
+        assert_eq!(to_bcd(59), 0x59);
+        assert_eq!(from_bcd(0x59), 59);
+    }
+
+    #[test]
+    fn test_civil_days() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+        assert_eq!(days_from_civil(2000, 2, 29), 11016);
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+
+        for days in [-100_000, -1, 0, 59, 60, 10_000, 20_000, 2_932_896] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+    }
+
+    #[test]
+    fn test_memory_size() {
+        let mut nvram = [0; NVRAM_LEN];
+        write_memory_size(&mut nvram, 2 << 30, 6 << 30);
+
+        assert_eq!(nvram[0x15..0x17], 640u16.to_le_bytes());
+        assert_eq!(nvram[0x17..0x19], 0xffffu16.to_le_bytes());
+        assert_eq!(nvram[0x30..0x32], 0xffffu16.to_le_bytes());
+        // ovmf: ((0x35 << 8 | 0x34) << 16) + 16M
+        assert_eq!(
+            nvram[0x34..0x36],
+            (((2u32 << 30) - (16 << 20)) >> 16).to_le_bytes()[..2]
+        );
+        assert_eq!(nvram[0x5b..0x5e], ((6u64 << 30) >> 16).to_le_bytes()[..3]);
+
+        write_memory_size(&mut nvram, 8 << 20, 0);
+        assert_eq!(nvram[0x17..0x19], (7u16 << 10).to_le_bytes());
+        assert_eq!(nvram[0x34..0x36], [0, 0]);
+        assert_eq!(nvram[0x5b..0x5e], [0, 0, 0]);
+    }
+}
@@ -0,0 +1,578 @@
+use std::io::Read;
+use std::io::Write;
+use std::ops::Range;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use acpi_tables::Aml;
+use acpi_tables::AmlSink;
+use acpi_tables::aml::Acquire;
+use acpi_tables::aml::Add;
+use acpi_tables::aml::Arg;
+use acpi_tables::aml::BufferData;
+use acpi_tables::aml::Device as AmlDevice;
+use acpi_tables::aml::Equal;
+use acpi_tables::aml::Field;
+use acpi_tables::aml::FieldAccessType;
+use acpi_tables::aml::FieldEntry;
+use acpi_tables::aml::FieldLockRule;
+use acpi_tables::aml::FieldUpdateRule;
+use acpi_tables::aml::If;
+use acpi_tables::aml::LessThan;
+use acpi_tables::aml::Local;
+use acpi_tables::aml::Method;
+use acpi_tables::aml::MethodCall;
+use acpi_tables::aml::Mutex as AmlMutex;
+use acpi_tables::aml::Name;
+use acpi_tables::aml::Notify;
+use acpi_tables::aml::ONE;
+use acpi_tables::aml::OpRegion;
+use acpi_tables::aml::OpRegionSpace;
+use acpi_tables::aml::Path;
+use acpi_tables::aml::Release;
+use acpi_tables::aml::Return;
+use acpi_tables::aml::Store;
+use acpi_tables::aml::While;
+use acpi_tables::aml::ZERO;
+use tracing::warn;
+use vm_core::device::Device;
+use vm_core::device::cpu_hotplug::CpuHotplug;
+use vm_core::device::error::DeviceError;
+use vm_core::device::error::DeviceSnapshotError;
+use vm_core::device::mmio::mmio_device::MmioDevice;
+use vm_core::device::system_event::SystemEvent;
+use vm_core::device::system_event::SystemEventNotifier;
+use vm_fdt::FdtWriter;
+use vm_firmware::acpi::processor::local_interrupt_controller;
+use vm_snapshot::helper::read_option_u32;
+use vm_snapshot::helper::read_u8;
+use vm_snapshot::helper::read_u32;
+use vm_snapshot::helper::write_option_u32;
+use vm_snapshot::helper::write_u8;
+use vm_snapshot::helper::write_u32;
+use vm_utils::cpu_topology::CpuTopology;
+use vm_utils::range_allocator::RangeAllocator;
+
+use crate::device::ged::GedEvent;
+
+/*
+ * ACPI cpu hotplug controller
+ *
+ * The guest selects a vcpu by writing its id to the selector register, then
+ * reads its status from the status byte. `\_SB_.CPUS.CSCN` is called by the
+ * ged on a cpu hotplug event, it notifies the processor devices whose
+ * insertion or removal is pending.
+ *
+ * Writing a status bit back acknowledges the insertion or the removal,
+ * writing the eject bit releases the vcpu.
+ */
+
+const LEN: u64 = 0x1000;
+/// The selector and the status byte
+const REGISTERS_LEN: u64 = 5;
+
+const SELECTOR_OFFSET: u64 = 0;
+const STATUS_OFFSET: u64 = 4;
+
+const STATUS_ENABLED: u8 = 1 << 0;
+const STATUS_INSERTING: u8 = 1 << 1;
+const STATUS_REMOVING: u8 = 1 << 2;
+const STATUS_EJECT: u8 = 1 << 3;
+
+/// Notify(Cxxx, 0x01), device check
+const NOTIFY_DEVICE_CHECK: u8 = 0x01;
+/// Notify(Cxxx, 0x03), eject request
+const NOTIFY_EJECT_REQUEST: u8 = 0x03;
+
+struct CpuHotplugInternal {
+    selector: u32,
+    status: Vec<u8>,
+    /// The vcpu whose unplug is requested, until the guest ejects it
+    unplugging: Option<u32>,
+    ged_event: GedEvent,
+    system_event_notifier: Arc<dyn SystemEventNotifier>,
+}
+
+impl CpuHotplugInternal {
+    fn selected(&mut self) -> Option<&mut u8> {
+        self.status.get_mut(self.selector as usize)
+    }
+
+    /// The vcpu manager only unplugs the last present vcpu and never the boot one, an eject
+    /// of any other vcpu is not honored
+    fn can_eject(&self, vcpu_id: u32) -> bool {
+        if self.unplugging == Some(vcpu_id) {
+            return true;
+        }
+
+        let last = self
+            .status
+            .iter()
+            .rposition(|status| status & STATUS_ENABLED != 0);
+        vcpu_id != 0 && last == Some(vcpu_id as usize)
+    }
+
+    fn write_status(&mut self, val: u8) {
+        let vcpu_id = self.selector;
+        let can_eject = self.can_eject(vcpu_id);
+        let Some(status) = self.selected() else {
+            return;
+        };
+
+        if val & STATUS_INSERTING != 0 {
+            *status &= !STATUS_INSERTING;
+        }
+        if val & STATUS_REMOVING != 0 {
+            *status &= !STATUS_REMOVING;
+        }
+        if val & STATUS_EJECT != 0 && *status & STATUS_ENABLED != 0 {
+            if !can_eject {
+                warn!(vcpu_id, "The guest ejected a vcpu which can't be unplugged");
+                return;
+            }
+
+            *status = 0;
+            if self.unplugging == Some(vcpu_id) {
+                self.unplugging = None;
+            }
+            self.system_event_notifier
+                .notify(SystemEvent::VcpuEjected(vcpu_id));
+        }
+    }
+}
+
+struct CpuHotplugHandle {
+    internal: Arc<Mutex<CpuHotplugInternal>>,
+}
+
+impl CpuHotplugHandle {
+    fn update(&self, vcpu_id: u32, f: impl FnOnce(&mut CpuHotplugInternal)) {
+        let mut internal = self.internal.lock().unwrap();
+
+        if (vcpu_id as usize) < internal.status.len() {
+            f(&mut internal);
+            internal.ged_event.raise();
+        }
+    }
+}
+
+impl CpuHotplug for CpuHotplugHandle {
+    fn plug(&self, vcpu_id: u32) {
+        self.update(vcpu_id, |internal| {
+            internal.status[vcpu_id as usize] = STATUS_ENABLED | STATUS_INSERTING;
+        });
+    }
+
+    fn request_unplug(&self, vcpu_id: u32) {
+        self.update(vcpu_id, |internal| {
+            internal.status[vcpu_id as usize] |= STATUS_REMOVING;
+            internal.unplugging = Some(vcpu_id);
+        });
+    }
+
+    fn unplug_pending(&self) -> bool {
+        self.internal.lock().unwrap().unplugging.is_some()
+    }
+}
+
+pub struct CpuHotplugController {
+    mmio_range: Range<u64>,
+    cpu_topology: CpuTopology,
+    internal: Arc<Mutex<CpuHotplugInternal>>,
+}
+
+impl CpuHotplugController {
+    pub fn new(
+        mmio_allocator: &mut RangeAllocator<u64>,
+        cpu_topology: CpuTopology,
+        present_vcpus: u32,
+        ged_event: GedEvent,
+        system_event_notifier: Arc<dyn SystemEventNotifier>,
+    ) -> Result<Self, DeviceError> {
+        let mmio_range = mmio_allocator.alloc(LEN as usize)?;
+
+        let status = (0..cpu_topology.vcpus())
+            .map(|vcpu_id| {
+                if vcpu_id < present_vcpus {
+                    STATUS_ENABLED
+                } else {
+                    0
+                }
+            })
+            .collect();
+
+        Ok(CpuHotplugController {
+            mmio_range,
+            cpu_topology,
+            internal: Arc::new(Mutex::new(CpuHotplugInternal {
+                selector: 0,
+                status,
+                unplugging: None,
+                ged_event,
+                system_event_notifier,
+            })),
+        })
+    }
+
+    pub fn cpu_hotplug(&self) -> Arc<dyn CpuHotplug> {
+        Arc::new(CpuHotplugHandle {
+            internal: self.internal.clone(),
+        })
+    }
+}
+
+impl Device for CpuHotplugController {
+    fn name(&self) -> String {
+        "cpu_hotplug".to_string()
+    }
+
+    fn pause(&self) -> Result<(), DeviceSnapshotError> {
+        Ok(())
+    }
+
+    fn resume(&self) -> Result<(), DeviceSnapshotError> {
+        Ok(())
+    }
+
+    fn save(&self, writer: &mut dyn Write) -> Result<(), DeviceSnapshotError> {
+        let internal = self.internal.lock().unwrap();
+
+        write_u32(writer, internal.selector)?;
+        write_option_u32(writer, &internal.unplugging)?;
+        for status in &internal.status {
+            write_u8(writer, *status)?;
+        }
+
+        Ok(())
+    }
+
+    fn load(&mut self, reader: &mut dyn Read) -> Result<(), DeviceSnapshotError> {
+        let mut internal = self.internal.lock().unwrap();
+
+        internal.selector = read_u32(reader)?;
+        internal.unplugging = read_option_u32(reader)?;
+        for status in internal.status.iter_mut() {
+            *status = read_u8(reader)?;
+        }
+
+        Ok(())
+    }
+
+    fn reset(&self) -> Result<(), DeviceError> {
+        // The vcpus present stay plugged, the pending notifications are dropped
+        let mut internal = self.internal.lock().unwrap();
+
+        internal.selector = 0;
+        internal.unplugging = None;
+        for status in internal.status.iter_mut() {
+            *status &= STATUS_ENABLED;
+        }
+
+        Ok(())
+    }
+
+    fn support_aml(&self) -> Option<&dyn Aml> {
+        Some(self)
+    }
+
+    fn support_mmio_transport(&self) -> Option<&dyn MmioDevice> {
+        Some(self)
+    }
+
+    fn support_mmio_transport_mut(&mut self) -> Option<&mut dyn MmioDevice> {
+        Some(self)
+    }
+}
+
+/// The processor device of a vcpu, its status and eject are forwarded to `\_SB_.CPUS`
+struct Processor<'a> {
+    vcpu_id: u32,
+    cpu_topology: &'a CpuTopology,
+}
+
+impl Aml for Processor<'_> {
+    fn to_aml_bytes(&self, sink: &mut dyn AmlSink) {
+        let status = MethodCall::new("CSTA".into(), vec![&self.vcpu_id]);
+        let eject = MethodCall::new("CEJ0".into(), vec![&self.vcpu_id]);
+        let mat = BufferData::new(local_interrupt_controller(
+            self.cpu_topology,
+            self.vcpu_id,
+            true,
+        ));
+
+        AmlDevice::new(
+            Path::new(&format!("C{:03X}", self.vcpu_id)),
+            vec![
+                &Name::new("_HID".into(), &"ACPI0007"),
+                &Name::new("_UID".into(), &self.vcpu_id),
+                &Method::new("_STA".into(), 0, false, vec![&Return::new(&status)]),
+                &Method::new("_EJ0".into(), 1, false, vec![&eject]),
+                &Method::new("_MAT".into(), 0, false, vec![&Return::new(&mat)]),
+            ],
+        )
+        .to_aml_bytes(sink);
+    }
+}
+
+impl Aml for CpuHotplugController {
+    fn to_aml_bytes(&self, sink: &mut dyn AmlSink) {
+        let vcpus = self.cpu_topology.vcpus();
+
+        let acquire = Acquire::new("CPLK".into(), 0xffff);
+        let release = Release::new("CPLK".into());
+        let arg0 = Arg(0);
+        let arg1 = Arg(1);
+
+        let selector = Path::new("CSEL");
+        let enabled = Path::new("CPEN");
+        let inserting = Path::new("CINS");
+        let removing = Path::new("CRMV");
+        let eject = Path::new("CEJ0");
+
+        // CSTA(vcpu), the _STA of a processor device
+        let status = Local(0);
+        let select_arg = Store::new(&selector, &arg0);
+        let not_present = Store::new(&status, &ZERO);
+        let is_enabled = Equal::new(&enabled, &ONE);
+        let present = Store::new(&status, &0xfu8);
+        let set_present = If::new(&is_enabled, vec![&present]);
+        let return_status = Return::new(&status);
+        let csta = Method::new(
+            "CSTA".into(),
+            1,
+            true,
+            vec![
+                &acquire,
+                &select_arg,
+                &not_present,
+                &set_present,
+                &release,
+                &return_status,
+            ],
+        );
+
+        // CEJ0(vcpu), the _EJ0 of a processor device
+        let set_eject = Store::new(&eject, &ONE);
+        let cej0 = Method::new(
+            "CEJ0".into(),
+            1,
+            true,
+            vec![&acquire, &select_arg, &set_eject, &release],
+        );
+
+        // CTFY(vcpu, event), notify the processor device of the vcpu
+        let vcpu_ids = (0..vcpus).collect::<Vec<_>>();
+        let processor_paths = vcpu_ids
+            .iter()
+            .map(|vcpu_id| Path::new(&format!("C{vcpu_id:03X}")))
+            .collect::<Vec<_>>();
+        let is_vcpus = vcpu_ids
+            .iter()
+            .map(|vcpu_id| Equal::new(&arg0, vcpu_id))
+            .collect::<Vec<_>>();
+        let notifies = processor_paths
+            .iter()
+            .map(|path| Notify::new(path, &arg1))
+            .collect::<Vec<_>>();
+        let ctfy_ifs = is_vcpus
+            .iter()
+            .zip(notifies.iter())
+            .map(|(is_vcpu, notify)| If::new(is_vcpu, vec![notify]))
+            .collect::<Vec<_>>();
+        let ctfy = Method::new(
+            "CTFY".into(),
+            2,
+            true,
+            ctfy_ifs.iter().map(|i| i as &dyn Aml).collect(),
+        );
+
+        // CSCN(), notify every vcpu whose insertion or removal is pending
+        let vcpu_id = Local(0);
+        let first_vcpu = Store::new(&vcpu_id, &ZERO);
+        let select_vcpu = Store::new(&selector, &vcpu_id);
+
+        let is_inserting = Equal::new(&inserting, &ONE);
+        let notify_insertion = MethodCall::new("CTFY".into(), vec![&vcpu_id, &NOTIFY_DEVICE_CHECK]);
+        let ack_insertion = Store::new(&inserting, &ONE);
+        let handle_insertion = If::new(&is_inserting, vec![&notify_insertion, &ack_insertion]);
+
+        let is_removing = Equal::new(&removing, &ONE);
+        let notify_removal = MethodCall::new("CTFY".into(), vec![&vcpu_id, &NOTIFY_EJECT_REQUEST]);
+        let ack_removal = Store::new(&removing, &ONE);
+        let handle_removal = If::new(&is_removing, vec![&notify_removal, &ack_removal]);
+
+        let next_vcpu = Add::new(&vcpu_id, &vcpu_id, &ONE);
+        let has_next_vcpu = LessThan::new(&vcpu_id, &vcpus);
+        let scan = While::new(
+            &has_next_vcpu,
+            vec![&select_vcpu, &handle_insertion, &handle_removal, &next_vcpu],
+        );
+        let cscn = Method::new(
+            "CSCN".into(),
+            0,
+            true,
+            vec![&acquire, &first_vcpu, &scan, &release],
+        );
+
+        let processors = vcpu_ids
+            .iter()
+            .map(|vcpu_id| Processor {
+                vcpu_id: *vcpu_id,
+                cpu_topology: &self.cpu_topology,
+            })
+            .collect::<Vec<_>>();
+
+        let mut children: Vec<&dyn Aml> = Vec::new();
+        let hid = Name::new("_HID".into(), &"ACPI0010");
+        let mutex = AmlMutex::new("CPLK".into(), 0);
+        let region = OpRegion::new(
+            "PRST".into(),
+            OpRegionSpace::SystemMemory,
+            &self.mmio_range.start,
+            &REGISTERS_LEN,
+        );
+        let selector_field = Field::new(
+            "PRST".into(),
+            FieldAccessType::DWord,
+            FieldLockRule::NoLock,
+            FieldUpdateRule::Preserve,
+            vec![FieldEntry::Named(*b"CSEL", 32)],
+        );
+        let status_field = Field::new(
+            "PRST".into(),
+            FieldAccessType::Byte,
+            FieldLockRule::NoLock,
+            FieldUpdateRule::WriteAsZeroes,
+            vec![
+                FieldEntry::Reserved(32),
+                FieldEntry::Named(*b"CPEN", 1),
+                FieldEntry::Named(*b"CINS", 1),
+                FieldEntry::Named(*b"CRMV", 1),
+                FieldEntry::Named(*b"CEJ0", 1),
+                FieldEntry::Reserved(4),
+            ],
+        );
+        children.extend([
+            &hid as &dyn Aml,
+            &mutex,
+            &region,
+            &selector_field,
+            &status_field,
+            &csta,
+            &cej0,
+            &ctfy,
+            &cscn,
+        ]);
+        children.extend(processors.iter().map(|p| p as &dyn Aml));
+
+        AmlDevice::new("_SB_.CPUS".into(), children).to_aml_bytes(sink);
+    }
+}
+
+impl MmioDevice for CpuHotplugController {
+    fn mmio_ranges(&self) -> Vec<Range<u64>> {
+        vec![self.mmio_range.clone()]
+    }
+
+    fn mmio_read(&self, addr: u64, buf: &mut [u8]) -> Result<(), DeviceError> {
+        let offset = addr - self.mmio_range.start;
+        let mut internal = self.internal.lock().unwrap();
+
+        buf.fill(0);
+        match offset {
+            SELECTOR_OFFSET => {
+                let selector = internal.selector.to_le_bytes();
+                let len = buf.len().min(selector.len());
+                buf[..len].copy_from_slice(&selector[..len]);
+            }
+            STATUS_OFFSET => {
+                buf[0] = internal.selected().map_or(0, |status| *status);
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    fn mmio_write(&self, addr: u64, buf: &[u8]) -> Result<(), DeviceError> {
+        let offset = addr - self.mmio_range.start;
+        let mut internal = self.internal.lock().unwrap();
+
+        match offset {
+            SELECTOR_OFFSET => {
+                let mut selector = [0; 4];
+                let len = buf.len().min(selector.len());
+                selector[..len].copy_from_slice(&buf[..len]);
+                internal.selector = u32::from_le_bytes(selector);
+            }
+            STATUS_OFFSET => internal.write_status(buf[0]),
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    fn generate_dt(&self, _fdt: &mut FdtWriter) -> Result<(), DeviceError> {
+        // It is only described by acpi
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::ged::Ged;
+    use crate::test_utils::Events;
+    use crate::test_utils::IrqChip;
+
+    fn eject(controller: &CpuHotplugController, vcpu_id: u32) {
+        let base = controller.mmio_range.start;
+        controller
+            .mmio_write(base + SELECTOR_OFFSET, &vcpu_id.to_le_bytes())
+            .unwrap();
+        controller
+            .mmio_write(base + STATUS_OFFSET, &[STATUS_EJECT])
+            .unwrap();
+    }
+
+    fn status(controller: &CpuHotplugController, vcpu_id: u32) -> u8 {
+        controller.internal.lock().unwrap().status[vcpu_id as usize]
+    }
+
+    #[test]
+    fn test_eject() {
+        let mut mmio_allocator = RangeAllocator::<u64>::default();
+        mmio_allocator.insert(0x1000_0000, 0x10_0000).unwrap();
+
+        let mut ged = Ged::new(&mut mmio_allocator, 5, Arc::new(IrqChip::default())).unwrap();
+        let events = Arc::new(Events::default());
+        let controller = CpuHotplugController::new(
+            &mut mmio_allocator,
+            CpuTopology::flat(4),
+            3,
+            ged.cpu_hotplug_event(),
+            events.clone(),
+        )
+        .unwrap();
+
+        // Neither the boot vcpu nor one in the middle
+        eject(&controller, 0);
+        eject(&controller, 1);
+        assert!(events.0.lock().unwrap().is_empty());
+        assert_eq!(status(&controller, 1), STATUS_ENABLED);
+
+        eject(&controller, 2);
+        assert_eq!(*events.0.lock().unwrap(), vec![SystemEvent::VcpuEjected(2)]);
+        assert_eq!(status(&controller, 2), 0);
+
+        // The unplug requested by the vmm
+        controller.cpu_hotplug().request_unplug(1);
+        assert!(controller.cpu_hotplug().unplug_pending());
+        eject(&controller, 1);
+        assert!(!controller.cpu_hotplug().unplug_pending());
+        assert_eq!(
+            *events.0.lock().unwrap(),
+            vec![SystemEvent::VcpuEjected(2), SystemEvent::VcpuEjected(1)]
+        );
+    }
+}
@@ -0,0 +1,286 @@
+use std::io::Read;
+use std::io::Write;
+use std::ops::Range;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use acpi_tables::Aml;
+use acpi_tables::AmlSink;
+use acpi_tables::aml::And;
+use acpi_tables::aml::Device as AmlDevice;
+use acpi_tables::aml::Equal;
+use acpi_tables::aml::Field;
+use acpi_tables::aml::FieldAccessType;
+use acpi_tables::aml::FieldEntry;
+use acpi_tables::aml::FieldLockRule;
+use acpi_tables::aml::FieldUpdateRule;
+use acpi_tables::aml::If;
+use acpi_tables::aml::Interrupt;
+use acpi_tables::aml::Local;
+use acpi_tables::aml::Method;
+use acpi_tables::aml::MethodCall;
+use acpi_tables::aml::Name;
+use acpi_tables::aml::Notify;
+use acpi_tables::aml::OpRegion;
+use acpi_tables::aml::OpRegionSpace;
+use acpi_tables::aml::Path;
+use acpi_tables::aml::ResourceTemplate;
+use acpi_tables::aml::Store;
+use acpi_tables::aml::ZERO;
+use vm_core::arch::irq::InterruptController;
+use vm_core::device::Device;
+use vm_core::device::error::DeviceError;
+use vm_core::device::error::DeviceSnapshotError;
+use vm_core::device::mmio::mmio_device::MmioDevice;
+use vm_core::device::power_button::PowerButton;
+use vm_fdt::FdtWriter;
+use vm_snapshot::helper::read_u32;
+use vm_snapshot::helper::write_u32;
+use vm_utils::range_allocator::RangeAllocator;
+
+/*
+ * ACPI Generic Event Device
+ * ACPI 6.5, 5.6.9 Interrupt-signaled ACPI events
+ *
+ * The guest reads the pending events from the selector register in _EVT,
+ * reading it acknowledges them.
+ */
+
+const LEN: u64 = 0x1000;
+const SELECTOR_LEN: u64 = 4;
+
+const EVENT_POWER_BUTTON: u32 = 1 << 0;
+const EVENT_CPU_HOTPLUG: u32 = 1 << 1;
+
+/// Notify(PWRB, 0x80), the power button is pressed
+const NOTIFY_POWER_BUTTON: u8 = 0x80;
+
+struct GedInternal {
+    irq: u32,
+    irq_chip: Arc<dyn InterruptController>,
+    events: u32,
+}
+
+impl GedInternal {
+    fn update_irq(&self) {
+        self.irq_chip.trigger_irq(self.irq, self.events != 0);
+    }
+
+    fn raise(&mut self, event: u32) {
+        self.events |= event;
+        self.update_irq();
+    }
+
+    fn take_events(&mut self) -> u32 {
+        let events = std::mem::take(&mut self.events);
+        self.update_irq();
+        events
+    }
+}
+
+struct GedPowerButton {
+    ged: Arc<Mutex<GedInternal>>,
+}
+
+impl PowerButton for GedPowerButton {
+    fn press(&self) {
+        self.ged.lock().unwrap().raise(EVENT_POWER_BUTTON);
+    }
+}
+
+/// Raises an event of the ged for another device, e.g. the cpu hotplug controller
+#[derive(Clone)]
+pub struct GedEvent {
+    ged: Arc<Mutex<GedInternal>>,
+    event: u32,
+}
+
+impl GedEvent {
+    pub fn raise(&self) {
+        self.ged.lock().unwrap().raise(self.event);
+    }
+}
+
+pub struct Ged {
+    irq: u32,
+    mmio_range: Range<u64>,
+    ged: Arc<Mutex<GedInternal>>,
+    /// `_EVT` scans the cpu hotplug controller if it is set
+    cpu_hotplug: bool,
+}
+
+impl Ged {
+    pub fn new(
+        mmio_allocator: &mut RangeAllocator<u64>,
+        irq: u32,
+        irq_chip: Arc<dyn InterruptController>,
+    ) -> Result<Self, DeviceError> {
+        let ged = Arc::new(Mutex::new(GedInternal {
+            irq,
+            irq_chip,
+            events: 0,
+        }));
+        let mmio_range = mmio_allocator.alloc(LEN as usize)?;
+
+        Ok(Ged {
+            irq,
+            mmio_range,
+            ged,
+            cpu_hotplug: false,
+        })
+    }
+
+    pub fn power_button(&self) -> Arc<dyn PowerButton> {
+        Arc::new(GedPowerButton {
+            ged: self.ged.clone(),
+        })
+    }
+
+    /// The event telling the guest to scan `\_SB_.CPUS`
+    pub fn cpu_hotplug_event(&mut self) -> GedEvent {
+        self.cpu_hotplug = true;
+
+        GedEvent {
+            ged: self.ged.clone(),
+            event: EVENT_CPU_HOTPLUG,
+        }
+    }
+}
+
+impl Device for Ged {
+    fn name(&self) -> String {
+        "ged".to_string()
+    }
+
+    fn pause(&self) -> Result<(), DeviceSnapshotError> {
+        Ok(())
+    }
+
+    fn resume(&self) -> Result<(), DeviceSnapshotError> {
+        Ok(())
+    }
+
+    fn save(&self, writer: &mut dyn Write) -> Result<(), DeviceSnapshotError> {
+        write_u32(writer, self.ged.lock().unwrap().events)?;
+
+        Ok(())
+    }
+
+    fn load(&mut self, reader: &mut dyn Read) -> Result<(), DeviceSnapshotError> {
+        let mut ged = self.ged.lock().unwrap();
+
+        ged.events = read_u32(reader)?;
+        ged.update_irq();
+
+        Ok(())
+    }
+
+    fn support_aml(&self) -> Option<&dyn Aml> {
+        Some(self)
+    }
+
+    fn support_mmio_transport(&self) -> Option<&dyn MmioDevice> {
+        Some(self)
+    }
+
+    fn support_mmio_transport_mut(&mut self) -> Option<&mut dyn MmioDevice> {
+        Some(self)
+    }
+}
+
+impl Aml for Ged {
+    fn to_aml_bytes(&self, sink: &mut dyn AmlSink) {
+        // The gsi of a spi is its id plus 32
+        #[cfg(target_arch = "aarch64")]
+        let gsi = self.irq + vm_core::arch::aarch64::irq::GIC_SPI_START;
+        #[cfg(not(target_arch = "aarch64"))]
+        let gsi = self.irq;
+
+        let pending_events = Local(0);
+        let event = Local(1);
+
+        let selector = Path::new("GDAT");
+        let read_events = Store::new(&pending_events, &selector);
+
+        let power_button_event = And::new(&event, &pending_events, &EVENT_POWER_BUTTON);
+        let is_power_button_event = Equal::new(&event, &EVENT_POWER_BUTTON);
+        let power_button = Path::new("\\_SB_.PWRB");
+        let notify_power_button = Notify::new(&power_button, &NOTIFY_POWER_BUTTON);
+        let handle_power_button = If::new(&is_power_button_event, vec![&notify_power_button]);
+
+        let cpu_hotplug_event = And::new(&event, &pending_events, &EVENT_CPU_HOTPLUG);
+        let is_cpu_hotplug_event = Equal::new(&event, &EVENT_CPU_HOTPLUG);
+        let scan_cpus = MethodCall::new("\\_SB_.CPUS.CSCN".into(), vec![]);
+        let handle_cpu_hotplug = If::new(&is_cpu_hotplug_event, vec![&scan_cpus]);
+
+        let mut evt: Vec<&dyn Aml> = vec![&read_events, &power_button_event, &handle_power_button];
+        if self.cpu_hotplug {
+            evt.push(&cpu_hotplug_event);
+            evt.push(&handle_cpu_hotplug);
+        }
+
+        AmlDevice::new(
+            "_SB_.GED_".into(),
+            vec![
+                &Name::new("_HID".into(), &"ACPI0013"),
+                &Name::new("_UID".into(), &ZERO),
+                &Name::new(
+                    "_CRS".into(),
+                    &ResourceTemplate::new(vec![&Interrupt::new(true, false, false, false, gsi)]),
+                ),
+                &OpRegion::new(
+                    "GDST".into(),
+                    OpRegionSpace::SystemMemory,
+                    &self.mmio_range.start,
+                    &SELECTOR_LEN,
+                ),
+                &Field::new(
+                    "GDST".into(),
+                    FieldAccessType::DWord,
+                    FieldLockRule::NoLock,
+                    FieldUpdateRule::WriteAsZeroes,
+                    vec![FieldEntry::Named(*b"GDAT", 32)],
+                ),
+                &Method::new("_EVT".into(), 1, true, evt),
+            ],
+        )
+        .to_aml_bytes(sink);
+
+        AmlDevice::new(
+            "_SB_.PWRB".into(),
+            vec![
+                &Name::new("_HID".into(), &"PNP0C0C"),
+                &Name::new("_UID".into(), &ZERO),
+            ],
+        )
+        .to_aml_bytes(sink);
+    }
+}
+
+impl MmioDevice for Ged {
+    fn mmio_ranges(&self) -> Vec<Range<u64>> {
+        vec![self.mmio_range.clone()]
+    }
+
+    fn mmio_read(&self, addr: u64, buf: &mut [u8]) -> Result<(), DeviceError> {
+        let offset = addr - self.mmio_range.start;
+
+        buf.fill(0);
+        if offset == 0 {
+            let events = self.ged.lock().unwrap().take_events().to_le_bytes();
+            let len = buf.len().min(events.len());
+            buf[..len].copy_from_slice(&events[..len]);
+        }
+
+        Ok(())
+    }
+
+    fn mmio_write(&self, _addr: u64, _buf: &[u8]) -> Result<(), DeviceError> {
+        Ok(())
+    }
+
+    fn generate_dt(&self, _fdt: &mut FdtWriter) -> Result<(), DeviceError> {
+        // It is only described by acpi
+        Ok(())
+    }
+}
@@ -12,4 +12,7 @@ pub enum I8042Cmd {
     AuxTest = 0xa9,
     AuxLoop = 0xd3,
     AuxSend = 0xd4,
+
+    /// Pulses the reset line of the cpu
+    CtlReset = 0xfe,
 }
@@ -2,10 +2,13 @@ use std::ops::Range;
 use std::sync::Arc;
 use std::sync::Mutex;
 
+use tracing::warn;
 use vm_core::arch::irq::InterruptController;
 use vm_core::device::Device;
 use vm_core::device::error::DeviceError;
 use vm_core::device::pio::pio_device::PioDevice;
+use vm_core::device::system_event::SystemEvent;
+use vm_core::device::system_event::SystemEventNotifier;
 use vm_utils::range_allocator::RangeAllocator;
 
 use crate::device::i8042::command::I8042Cmd;
@@ -22,6 +25,13 @@ mod status_register;
 
 const OUTPUT_BUFFER_SIZE: usize = 16;
 
+/// The replies to the self tests, no error
+const CTL_TEST_PASSED: u8 = 0x55;
+const AUX_TEST_PASSED: u8 = 0x00;
+
+/// The isa irq of the ps/2 mouse, it falls in the range the other devices are allocated from
+pub const AUX_IRQ: u32 = PsMouse::<OUTPUT_BUFFER_SIZE>::IRQ;
+
 const DATA_PORT: u16 = 0x60;
 const REGISTER_PORT: u16 = 0x64;
 const COMMAND_REGISTER: u16 = REGISTER_PORT;
@@ -36,10 +46,14 @@ struct I8042Raw {
     pending_command: Option<I8042Cmd>,
 
     irq: Arc<dyn InterruptController>,
+    system_event_notifier: Arc<dyn SystemEventNotifier>,
 }
 
 impl I8042Raw {
-    fn new(irq: Arc<dyn InterruptController>) -> Self {
+    fn new(
+        irq: Arc<dyn InterruptController>,
+        system_event_notifier: Arc<dyn SystemEventNotifier>,
+    ) -> Self {
         I8042Raw {
             atkbd: AtKbd::<OUTPUT_BUFFER_SIZE>::default(),
             ps_mouse: PsMouse::<OUTPUT_BUFFER_SIZE>::default(),
@@ -47,9 +61,19 @@ impl I8042Raw {
             ctr: Default::default(),
             pending_command: None,
             irq,
+            system_event_notifier,
         }
     }
 
+    fn reset(&mut self) {
+        self.atkbd = AtKbd::<OUTPUT_BUFFER_SIZE>::default();
+        self.ps_mouse = PsMouse::<OUTPUT_BUFFER_SIZE>::default();
+        self.status_register = Default::default();
+        self.ctr = Default::default();
+        self.pending_command = None;
+        self.update_state();
+    }
+
     fn update_state(&mut self) {
         let mut kbd_active = false;
         let mut aux_active = false;
@@ -94,12 +118,13 @@ impl I8042Raw {
     }
 
     fn read_data(&mut self, data: &mut [u8]) {
+        // Nothing to read, e.g. the driver flushing the buffer
         if self.status_register.contains(StatusRegister::STR_AUXDATA) {
-            data[0] = self.ps_mouse.pop_output_buffer().unwrap();
+            data[0] = self.ps_mouse.pop_output_buffer().unwrap_or_default();
             self.ps_mouse.trigger_irq(self.irq.as_ref(), false);
             self.update_state();
         } else {
-            data[0] = self.atkbd.pop_output_buffer().unwrap();
+            data[0] = self.atkbd.pop_output_buffer().unwrap_or_default();
             self.atkbd.trigger_irq(self.irq.as_ref(), false);
             self.update_state();
         }
@@ -113,20 +138,21 @@ impl I8042Raw {
     fn write_data(&mut self, data: u8) {
         match self.pending_command.take() {
             Some(cmd) => match cmd {
-                I8042Cmd::CtlRctr => todo!(),
                 I8042Cmd::CtlWctr => {
                     self.ctr = ControllerConfigurationByte::from_bits_truncate(data);
                     self.update_state();
                 }
-                I8042Cmd::CtlTest => todo!(),
-                I8042Cmd::AuxDisable => todo!(),
-                I8042Cmd::AuxEnable => todo!(),
-                I8042Cmd::AuxTest => todo!(),
                 I8042Cmd::AuxLoop => self.push_aux(data),
                 I8042Cmd::AuxSend => {
                     self.ps_mouse.handle_command(data);
                     self.update_state();
                 }
+                I8042Cmd::CtlRctr
+                | I8042Cmd::CtlTest
+                | I8042Cmd::AuxDisable
+                | I8042Cmd::AuxEnable
+                | I8042Cmd::AuxTest
+                | I8042Cmd::CtlReset => unreachable!("{cmd:?} takes no data"),
             },
             None => {
                 self.atkbd.handle_command(data);
@@ -137,18 +163,20 @@ impl I8042Raw {
 
     fn write_command_reg(&mut self, cmd: u8) {
         let Some(cmd) = I8042Cmd::from_repr(cmd) else {
-            todo!()
+            warn!(cmd, "i8042: unsupported command");
+            return;
         };
 
         match cmd {
             I8042Cmd::CtlRctr => self.push_kbd(self.ctr.bits()),
             I8042Cmd::CtlWctr => self.pending_command = Some(cmd),
-            I8042Cmd::CtlTest => todo!(),
+            I8042Cmd::CtlTest => self.push_kbd(CTL_TEST_PASSED),
             I8042Cmd::AuxDisable => self.ctr.insert(ControllerConfigurationByte::CTL_AUXDIS),
             I8042Cmd::AuxEnable => self.ctr.remove(ControllerConfigurationByte::CTL_AUXDIS),
-            I8042Cmd::AuxTest => todo!(),
+            I8042Cmd::AuxTest => self.push_kbd(AUX_TEST_PASSED),
             I8042Cmd::AuxLoop => self.pending_command = Some(cmd),
             I8042Cmd::AuxSend => self.pending_command = Some(cmd),
+            I8042Cmd::CtlReset => self.system_event_notifier.notify(SystemEvent::Reset),
         }
     }
 
@@ -176,11 +204,12 @@ impl I8042 {
     pub fn new(
         pio_allocator: &mut RangeAllocator<u16>,
         irq: Arc<dyn InterruptController>,
+        system_event_notifier: Arc<dyn SystemEventNotifier>,
     ) -> Result<Self, DeviceError> {
         let _ = pio_allocator.reserve(DATA_PORT, 1)?;
         let _ = pio_allocator.reserve(REGISTER_PORT, 1)?;
 
-        let i8042 = Arc::new(Mutex::new(I8042Raw::new(irq)));
+        let i8042 = Arc::new(Mutex::new(I8042Raw::new(irq, system_event_notifier)));
 
         Ok(I8042(i8042))
     }
@@ -190,6 +219,20 @@ impl Device for I8042 {
     fn name(&self) -> String {
         "i8042".to_string()
     }
+
+    fn reset(&self) -> Result<(), DeviceError> {
+        self.0.lock().unwrap().reset();
+
+        Ok(())
+    }
+
+    fn support_pio_transport(&self) -> Option<&dyn PioDevice> {
+        Some(self)
+    }
+
+    fn support_pio_transport_mut(&mut self) -> Option<&mut dyn PioDevice> {
+        Some(self)
+    }
 }
 
 impl PioDevice for I8042 {
@@ -209,3 +252,52 @@ impl PioDevice for I8042 {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::Events;
+    use crate::test_utils::IrqChip;
+
+    fn read_data(i8042: &I8042) -> u8 {
+        let mut data = [0];
+        i8042.io_in(DATA_PORT, &mut data).unwrap();
+        data[0]
+    }
+
+    #[test]
+    fn test_self_test_and_reset() {
+        let mut pio_allocator = RangeAllocator::<u16>::default();
+        pio_allocator.insert(0, 0x1000).unwrap();
+
+        let events = Arc::new(Events::default());
+        let i8042 = I8042::new(
+            &mut pio_allocator,
+            Arc::new(IrqChip::default()),
+            events.clone(),
+        )
+        .unwrap();
+
+        // The buffer is flushed by the driver before anything is queued
+        assert_eq!(read_data(&i8042), 0);
+
+        i8042
+            .io_out(COMMAND_REGISTER, &[I8042Cmd::CtlTest as u8])
+            .unwrap();
+        assert_eq!(read_data(&i8042), CTL_TEST_PASSED);
+
+        i8042
+            .io_out(COMMAND_REGISTER, &[I8042Cmd::AuxTest as u8])
+            .unwrap();
+        assert_eq!(read_data(&i8042), AUX_TEST_PASSED);
+
+        // Unknown commands are ignored
+        i8042.io_out(COMMAND_REGISTER, &[0xc0]).unwrap();
+        assert!(events.0.lock().unwrap().is_empty());
+
+        i8042
+            .io_out(COMMAND_REGISTER, &[I8042Cmd::CtlReset as u8])
+            .unwrap();
+        assert_eq!(*events.0.lock().unwrap(), vec![SystemEvent::Reset]);
+    }
+}
@@ -1,31 +1,47 @@
-#[cfg(target_os = "linux")]
 use std::path::PathBuf;
 
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::device::virtio::virtio_9p::id_map::IdMap;
+
 pub mod cmos;
+pub mod cpu_hotplug;
 pub mod dummy;
+pub mod ged;
 pub mod i8042;
 pub mod pic;
 pub mod post_debug;
+pub mod rtc;
+pub mod serial;
 pub mod uart8250;
 pub mod virtio;
 
+#[cfg(target_arch = "x86_64")]
+pub mod acpi_pm;
+
 #[cfg(target_arch = "aarch64")]
 pub mod pl011;
+#[cfg(target_arch = "aarch64")]
+pub mod pl031;
+#[cfg(target_arch = "aarch64")]
+pub mod pl061;
 
-#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum VirtioTransport {
     Mmio,
     Pci,
 }
 
-#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Device {
     GicV3,
     VirtioBlk {
         transport: VirtioTransport,
+        /// The disk image, a raw file
+        path: PathBuf,
+        #[serde(default)]
+        read_only: bool,
     },
     VirtioBalloon {
         transport: VirtioTransport,
@@ -36,11 +52,33 @@ pub enum Device {
     VirtioGpu {
         transport: VirtioTransport,
     },
+    Virtio9p {
+        transport: VirtioTransport,
+        tag: String,
+        path: PathBuf,
+        read_only: bool,
+        uid_map: Vec<IdMap>,
+        gid_map: Vec<IdMap>,
+    },
+    VirtioMem {
+        transport: VirtioTransport,
+        region_size: usize,
+        block_size: usize,
+        requested_size: usize,
+    },
+    /// Translates the dma of the pci devices, it is only available on the mmio transport
+    VirtioIommu,
     #[cfg(target_os = "linux")]
     VfioPci {
         name: String,
         path: PathBuf,
     },
+    /// A pci device emulated by a vfio-user server listening on `socket`
+    #[cfg(target_os = "linux")]
+    VfioUser {
+        name: String,
+        socket: PathBuf,
+    },
 }
 
 impl Device {
@@ -50,16 +88,42 @@ impl Device {
             Device::VirtioBlk { .. }
             | Device::VirtioBalloon { .. }
             | Device::VirtioEntropy { .. }
-            | Device::VirtioGpu { .. } => false,
+            | Device::VirtioGpu { .. }
+            | Device::Virtio9p { .. }
+            | Device::VirtioMem { .. }
+            | Device::VirtioIommu => false,
             #[cfg(target_os = "linux")]
             Device::VfioPci { .. } => false,
+            #[cfg(target_os = "linux")]
+            Device::VfioUser { .. } => false,
         }
     }
 
     pub fn is_vfio_device(&self) -> bool {
         match self {
             #[cfg(target_os = "linux")]
-            Device::VfioPci { .. } => true,
+            Device::VfioPci { .. } | Device::VfioUser { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// The files and sockets of the host backing the device, the guest does not see them
+    pub fn host_paths_mut(&mut self) -> Vec<&mut PathBuf> {
+        match self {
+            Device::VirtioBlk { path, .. } | Device::Virtio9p { path, .. } => vec![path],
+            #[cfg(target_os = "linux")]
+            Device::VfioPci { path, .. } => vec![path],
+            #[cfg(target_os = "linux")]
+            Device::VfioUser { socket, .. } => vec![socket],
+            _ => vec![],
+        }
+    }
+
+    /// The guest memory has to be shared with another process
+    pub fn needs_shared_memory(&self) -> bool {
+        match self {
+            #[cfg(target_os = "linux")]
+            Device::VfioUser { .. } => true,
             _ => false,
         }
     }
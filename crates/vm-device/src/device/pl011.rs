@@ -5,11 +5,22 @@ use std::ops::Range;
 use std::sync::Arc;
 use std::sync::Mutex;
 
+use acpi_tables::Aml;
+use acpi_tables::AmlSink;
+use acpi_tables::aml::Device as AmlDevice;
+use acpi_tables::aml::Interrupt;
+use acpi_tables::aml::Memory32Fixed;
+use acpi_tables::aml::Name;
+use acpi_tables::aml::ResourceTemplate;
+use acpi_tables::aml::ZERO;
 use bitflags::Flags;
 use strum_macros::FromRepr;
+use tracing::warn;
 use vm_core::arch::aarch64::irq::GIC_SPI;
+use vm_core::arch::aarch64::irq::GIC_SPI_START;
 use vm_core::arch::aarch64::irq::IRQ_TYPE_LEVEL_HIGH;
 use vm_core::arch::irq::InterruptController;
+use vm_core::arch::irq::Phandle;
 use vm_core::device::Device;
 use vm_core::device::error::DeviceError;
 use vm_core::device::error::DeviceSnapshotError;
@@ -33,6 +44,7 @@ use crate::device::pl011::ifls::Ifls;
 use crate::device::pl011::imsc::Imsc;
 use crate::device::pl011::lcrh::LcrH;
 use crate::device::pl011::ris::Ris;
+use crate::device::serial::SerialConfig;
 
 mod fr {
     use bitflags::bitflags;
@@ -262,10 +274,16 @@ struct Pl011Internal {
     rx_fifo: [Option<u16>; 32], // 12bit-wide(data and error bits), option to indicate thr's status
     rx_r_cursor: usize,
     rx_w_cursor: usize,
+
+    output: Box<dyn Write + Send>,
 }
 
 impl Pl011Internal {
-    fn new(irq: u32, irq_chip: Arc<dyn InterruptController>) -> Self {
+    fn new(
+        irq: u32,
+        irq_chip: Arc<dyn InterruptController>,
+        output: Box<dyn Write + Send>,
+    ) -> Self {
         Pl011Internal {
             irq,
             irq_chip,
@@ -280,6 +298,7 @@ impl Pl011Internal {
             rx_fifo: Default::default(),
             rx_r_cursor: Default::default(),
             rx_w_cursor: Default::default(),
+            output,
         }
     }
 
@@ -309,8 +328,13 @@ impl Pl011Internal {
          • if the FIFOs are not enabled, data is stored in the transmitter
            holding register (the bottom word of the transmit FIFO).
         */
-        print!("{}", data[0] as char);
-        io::stdout().flush().unwrap();
+        if let Err(err) = self
+            .output
+            .write_all(&data[..1])
+            .and_then(|_| self.output.flush())
+        {
+            warn!(?err, "failed to write the pl011 output");
+        }
 
         // if self.fifo_enabled() {
         //     self.tx_fifo[self.tx_w_cursor] = data[0];
@@ -555,26 +579,32 @@ impl Pl011 {
         mmio_allocator: &mut RangeAllocator<u64>,
         irq: u32,
         irq_chip: Arc<dyn InterruptController>,
+        serial: &SerialConfig,
     ) -> Result<Self, DeviceError> {
-        let pl011 = Arc::new(Mutex::new(Pl011Internal::new(irq, irq_chip)));
+        let output = serial
+            .output()
+            .map_err(|err| DeviceError::Device(Box::new(err)))?;
+        let pl011 = Arc::new(Mutex::new(Pl011Internal::new(irq, irq_chip, output)));
         let mmio_range = mmio_allocator.alloc(0x1000)?;
 
-        tokio::spawn({
-            let pl011 = pl011.clone();
-            async move {
-                let stdin = io::stdin();
-                let mut handle = stdin.lock();
-                let mut buffer = [0u8; 1];
-
-                while let Ok(n) = handle.read(&mut buffer) {
-                    if n == 0 {
-                        break;
+        if serial.has_input() {
+            tokio::spawn({
+                let pl011 = pl011.clone();
+                async move {
+                    let stdin = io::stdin();
+                    let mut handle = stdin.lock();
+                    let mut buffer = [0u8; 1];
+
+                    while let Ok(n) = handle.read(&mut buffer) {
+                        if n == 0 {
+                            break;
+                        }
+                        let mut pl011 = pl011.lock().unwrap();
+                        pl011.stdio(buffer[0]);
                     }
-                    let mut pl011 = pl011.lock().unwrap();
-                    pl011.stdio(buffer[0]);
                 }
-            }
-        });
+            });
+        }
 
         Ok(Pl011 {
             irq,
@@ -637,6 +667,10 @@ impl Device for Pl011 {
         Ok(())
     }
 
+    fn support_aml(&self) -> Option<&dyn Aml> {
+        Some(self)
+    }
+
     fn support_mmio_transport(&self) -> Option<&dyn MmioDevice> {
         Some(self)
     }
@@ -644,6 +678,36 @@ impl Device for Pl011 {
     fn support_mmio_transport_mut(&mut self) -> Option<&mut dyn MmioDevice> {
         Some(self)
     }
+
+    fn serial_console(&self) -> Option<(u64, u32)> {
+        Some((self.mmio_range.start, self.irq + GIC_SPI_START))
+    }
+}
+
+impl Aml for Pl011 {
+    fn to_aml_bytes(&self, sink: &mut dyn AmlSink) {
+        AmlDevice::new(
+            "_SB_.COM0".into(),
+            vec![
+                &Name::new("_HID".into(), &"ARMH0011"),
+                &Name::new("_UID".into(), &ZERO),
+                &Name::new(
+                    "_CRS".into(),
+                    &ResourceTemplate::new(vec![
+                        &Memory32Fixed::new(
+                            true,
+                            self.mmio_range.start.try_into().unwrap(),
+                            (self.mmio_range.end - self.mmio_range.start)
+                                .try_into()
+                                .unwrap(),
+                        ),
+                        &Interrupt::new(true, false, false, false, self.irq + GIC_SPI_START),
+                    ]),
+                ),
+            ],
+        )
+        .to_aml_bytes(sink);
+    }
 }
 
 impl MmioDevice for Pl011 {
@@ -674,7 +738,7 @@ impl MmioDevice for Pl011 {
         fdt.property_string("compatible", "fixed-clock")?;
         fdt.property_u32("#clock-cells", 0)?;
         fdt.property_u32("clock-frequency", 24000000)?;
-        fdt.property_phandle(3)?;
+        fdt.property_phandle(Phandle::CLOCK as u32)?;
         fdt.end_node(node)?;
 
         // let node = fdt.begin_node("apb_pclk")?;
@@ -701,7 +765,7 @@ impl MmioDevice for Pl011 {
         } else {
             unimplemented!()
         }
-        fdt.property_array_u32("clocks", &[3, 3])?;
+        fdt.property_array_u32("clocks", &[Phandle::CLOCK as u32, Phandle::CLOCK as u32])?;
         fdt.property_string_list(
             "clock-names",
             vec!["uartclk".to_string(), "apb_pclk".to_string()],
@@ -0,0 +1,394 @@
+use std::io::Read;
+use std::io::Write;
+use std::ops::Range;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+
+use strum_macros::FromRepr;
+use tokio::select;
+use tokio::sync::Notify;
+use vm_core::arch::aarch64::irq::GIC_SPI;
+use vm_core::arch::aarch64::irq::IRQ_TYPE_LEVEL_HIGH;
+use vm_core::arch::irq::InterruptController;
+use vm_core::arch::irq::Phandle;
+use vm_core::device::Device;
+use vm_core::device::error::DeviceError;
+use vm_core::device::error::DeviceSnapshotError;
+use vm_core::device::mmio::mmio_device::MmioDevice;
+use vm_fdt::FdtWriter;
+use vm_snapshot::helper::read_u32;
+use vm_snapshot::helper::read_u64;
+use vm_snapshot::helper::write_u32;
+use vm_snapshot::helper::write_u64;
+use vm_utils::range_allocator::RangeAllocator;
+
+use crate::device::rtc::RtcConfig;
+
+/*
+ * ARM PrimeCell Real Time Clock (PL031)
+ * https://developer.arm.com/documentation/ddi0224/c
+ */
+
+const PERIPH_ID: [u8; 4] = [0x31, 0x10, 0x14, 0x00];
+const CELL_ID: [u8; 4] = [0x0d, 0xf0, 0x05, 0xb1];
+
+const RTC_INTERRUPT: u32 = 1 << 0;
+
+#[derive(Debug, FromRepr)]
+#[repr(u16)]
+enum Register {
+    Dr = 0x00,
+    Mr = 0x04,
+    Lr = 0x08,
+    Cr = 0x0c,
+    Imsc = 0x10,
+    Ris = 0x14,
+    Mis = 0x18,
+    Icr = 0x1c,
+    PeriphID0 = 0xfe0,
+    PeriphID1 = 0xfe4,
+    PeriphID2 = 0xfe8,
+    PeriphID3 = 0xfec,
+    CellID0 = 0xff0,
+    CellId1 = 0xff4,
+    CellId2 = 0xff8,
+    CellId3 = 0xffc,
+}
+
+fn host_time() -> Duration {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+struct Pl031Internal {
+    irq: u32,
+    irq_chip: Arc<dyn InterruptController>,
+
+    /// Seconds between the host UTC time and the counter
+    offset: i64,
+    mr: u32,
+    imsc: u32,
+    ris: u32,
+}
+
+impl Pl031Internal {
+    fn counter(&self) -> u32 {
+        (host_time().as_secs() as i64 + self.offset) as u32
+    }
+
+    /// When the counter will be equal to the match register
+    fn next_match(&self) -> Instant {
+        let now = host_time();
+        let counter = (now.as_secs() as i64 + self.offset) as u32;
+
+        let secs = self.mr.wrapping_sub(counter) as u64;
+        Instant::now() + Duration::from_secs(secs) - Duration::from_nanos(now.subsec_nanos() as u64)
+    }
+
+    fn update_irq(&self) {
+        self.irq_chip
+            .trigger_irq(self.irq, self.ris & self.imsc != 0);
+    }
+
+    fn tick(&mut self) {
+        if self.counter() == self.mr {
+            self.ris |= RTC_INTERRUPT;
+            self.update_irq();
+        }
+    }
+
+    fn mmio_read(&self, offset: u64, data: &mut [u8]) {
+        let offset: u16 = offset.try_into().unwrap();
+
+        let val = match Register::from_repr(offset) {
+            Some(Register::Dr) => self.counter(),
+            Some(Register::Mr) => self.mr,
+            Some(Register::Lr) => self.counter(),
+            Some(Register::Cr) => 1, // The counter is always enabled
+            Some(Register::Imsc) => self.imsc,
+            Some(Register::Ris) => self.ris,
+            Some(Register::Mis) => self.ris & self.imsc,
+            Some(Register::PeriphID0) => PERIPH_ID[0] as u32,
+            Some(Register::PeriphID1) => PERIPH_ID[1] as u32,
+            Some(Register::PeriphID2) => PERIPH_ID[2] as u32,
+            Some(Register::PeriphID3) => PERIPH_ID[3] as u32,
+            Some(Register::CellID0) => CELL_ID[0] as u32,
+            Some(Register::CellId1) => CELL_ID[1] as u32,
+            Some(Register::CellId2) => CELL_ID[2] as u32,
+            Some(Register::CellId3) => CELL_ID[3] as u32,
+            Some(Register::Icr) | None => 0,
+        };
+
+        let len = data.len().min(4);
+        data[..len].copy_from_slice(&val.to_le_bytes()[..len]);
+    }
+
+    fn mmio_write(&mut self, offset: u64, data: &[u8]) {
+        let offset: u16 = offset.try_into().unwrap();
+
+        let mut buf = [0u8; 4];
+        let len = data.len().min(4);
+        buf[..len].copy_from_slice(&data[..len]);
+        let val = u32::from_le_bytes(buf);
+
+        match Register::from_repr(offset) {
+            Some(Register::Mr) => self.mr = val,
+            Some(Register::Lr) => {
+                self.offset = val as i64 - host_time().as_secs() as i64;
+            }
+            Some(Register::Imsc) => {
+                self.imsc = val & RTC_INTERRUPT;
+                self.update_irq();
+            }
+            Some(Register::Icr) => {
+                self.ris &= !val;
+                self.update_irq();
+            }
+            _ => (), // Read only or the counter can not be stopped
+        }
+    }
+}
+
+pub struct Pl031 {
+    irq: u32,
+    mmio_range: Range<u64>,
+    pl031: Arc<Mutex<Pl031Internal>>,
+    timer: Arc<Notify>,
+}
+
+impl Pl031 {
+    pub fn new(
+        mmio_allocator: &mut RangeAllocator<u64>,
+        irq: u32,
+        irq_chip: Arc<dyn InterruptController>,
+        config: &RtcConfig,
+    ) -> Result<Self, DeviceError> {
+        let pl031 = Arc::new(Mutex::new(Pl031Internal {
+            irq,
+            irq_chip,
+            offset: config.offset(),
+            mr: 0,
+            imsc: 0,
+            ris: 0,
+        }));
+        let mmio_range = mmio_allocator.alloc(0x1000)?;
+        let timer = Arc::new(Notify::new());
+
+        // Raise the match interrupt
+        tokio::spawn({
+            let pl031 = pl031.clone();
+            let timer = timer.clone();
+
+            async move {
+                loop {
+                    let deadline = pl031.lock().unwrap().next_match();
+
+                    select! {
+                        _ = timer.notified() => {},
+                        _ = tokio::time::sleep_until(deadline.into()) => {
+                            pl031.lock().unwrap().tick();
+                            // Do not fire twice within the matching second
+                            tokio::time::sleep(Duration::from_secs(1)).await;
+                        }
+                    }
+                }
+            }
+        });
+
+        Ok(Pl031 {
+            irq,
+            mmio_range,
+            pl031,
+            timer,
+        })
+    }
+}
+
+impl Device for Pl031 {
+    fn name(&self) -> String {
+        "pl031".to_string()
+    }
+
+    fn pause(&self) -> Result<(), DeviceSnapshotError> {
+        Ok(())
+    }
+
+    fn resume(&self) -> Result<(), DeviceSnapshotError> {
+        Ok(())
+    }
+
+    fn save(&self, writer: &mut dyn Write) -> Result<(), DeviceSnapshotError> {
+        let pl031 = self.pl031.lock().unwrap();
+
+        write_u64(writer, pl031.offset as u64)?;
+        write_u32(writer, pl031.mr)?;
+        write_u32(writer, pl031.imsc)?;
+        write_u32(writer, pl031.ris)?;
+
+        Ok(())
+    }
+
+    fn load(&mut self, reader: &mut dyn Read) -> Result<(), DeviceSnapshotError> {
+        {
+            let mut pl031 = self.pl031.lock().unwrap();
+
+            pl031.offset = read_u64(reader)? as i64;
+            pl031.mr = read_u32(reader)?;
+            pl031.imsc = read_u32(reader)?;
+            pl031.ris = read_u32(reader)?;
+            pl031.update_irq();
+        }
+
+        self.timer.notify_one();
+
+        Ok(())
+    }
+
+    fn support_mmio_transport(&self) -> Option<&dyn MmioDevice> {
+        Some(self)
+    }
+
+    fn support_mmio_transport_mut(&mut self) -> Option<&mut dyn MmioDevice> {
+        Some(self)
+    }
+}
+
+impl MmioDevice for Pl031 {
+    fn mmio_ranges(&self) -> Vec<Range<u64>> {
+        vec![self.mmio_range.clone()]
+    }
+
+    fn mmio_read(&self, addr: u64, buf: &mut [u8]) -> Result<(), DeviceError> {
+        self.pl031
+            .lock()
+            .unwrap()
+            .mmio_read(addr - self.mmio_range.start, buf);
+
+        Ok(())
+    }
+
+    fn mmio_write(&self, addr: u64, buf: &[u8]) -> Result<(), DeviceError> {
+        self.pl031
+            .lock()
+            .unwrap()
+            .mmio_write(addr - self.mmio_range.start, buf);
+
+        // The match or load register may have changed
+        self.timer.notify_one();
+
+        Ok(())
+    }
+
+    fn generate_dt(&self, fdt: &mut FdtWriter) -> Result<(), DeviceError> {
+        let node = fdt.begin_node(&format!("rtc@{:x}", self.mmio_range.start))?;
+        fdt.property_string_list(
+            "compatible",
+            vec!["arm,pl031".to_string(), "arm,primecell".to_string()],
+        )?;
+        fdt.property_array_u64(
+            "reg",
+            &[
+                self.mmio_range.start,
+                self.mmio_range.end - self.mmio_range.start,
+            ],
+        )?;
+        fdt.property_array_u32("interrupts", &[GIC_SPI, self.irq, IRQ_TYPE_LEVEL_HIGH])?;
+        fdt.property_u32("clocks", Phandle::CLOCK as u32)?;
+        fdt.property_string("clock-names", "apb_pclk")?;
+        fdt.end_node(node)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::IrqChip;
+
+    fn pl031(irq_chip: Arc<IrqChip>) -> Pl031Internal {
+        Pl031Internal {
+            irq: 5,
+            irq_chip,
+            offset: 0,
+            mr: 0,
+            imsc: 0,
+            ris: 0,
+        }
+    }
+
+    fn read(pl031: &Pl031Internal, register: Register) -> u32 {
+        let mut data = [0; 4];
+        pl031.mmio_read(register as u64, &mut data);
+        u32::from_le_bytes(data)
+    }
+
+    fn write(pl031: &mut Pl031Internal, register: Register, val: u32) {
+        pl031.mmio_write(register as u64, &val.to_le_bytes());
+    }
+
+    #[test]
+    fn test_id_registers() {
+        let pl031 = pl031(Arc::new(IrqChip::default()));
+
+        assert_eq!(read(&pl031, Register::PeriphID0), 0x31);
+        assert_eq!(read(&pl031, Register::PeriphID1), 0x10);
+        assert_eq!(read(&pl031, Register::PeriphID2), 0x14);
+        assert_eq!(read(&pl031, Register::PeriphID3), 0x00);
+        assert_eq!(read(&pl031, Register::CellID0), 0x0d);
+        assert_eq!(read(&pl031, Register::CellId1), 0xf0);
+        assert_eq!(read(&pl031, Register::CellId2), 0x05);
+        assert_eq!(read(&pl031, Register::CellId3), 0xb1);
+    }
+
+    #[test]
+    fn test_load_and_match() {
+        let mut pl031 = pl031(Arc::new(IrqChip::default()));
+
+        write(&mut pl031, Register::Lr, 0x1000);
+        let counter = read(&pl031, Register::Dr);
+        assert!((0x1000..=0x1001).contains(&counter));
+
+        write(&mut pl031, Register::Mr, 0x2000);
+        assert_eq!(read(&pl031, Register::Mr), 0x2000);
+
+        // The counter can not be stopped
+        write(&mut pl031, Register::Cr, 0);
+        assert_eq!(read(&pl031, Register::Cr), 1);
+
+        // Writes to the data register are ignored
+        write(&mut pl031, Register::Dr, 0);
+        assert!(read(&pl031, Register::Dr) >= 0x1000);
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let irq_chip = Arc::new(IrqChip::default());
+        let mut pl031 = pl031(irq_chip.clone());
+
+        // No match, no interrupt
+        write(&mut pl031, Register::Lr, 0x1000);
+        write(&mut pl031, Register::Mr, 0x2000);
+        pl031.tick();
+        assert_eq!(read(&pl031, Register::Ris), 0);
+
+        // A masked match is raw only
+        write(&mut pl031, Register::Lr, 0x2000);
+        pl031.tick();
+        assert_eq!(read(&pl031, Register::Ris), RTC_INTERRUPT);
+        assert_eq!(read(&pl031, Register::Mis), 0);
+        assert!(!irq_chip.is_active());
+
+        write(&mut pl031, Register::Imsc, RTC_INTERRUPT);
+        assert_eq!(read(&pl031, Register::Mis), RTC_INTERRUPT);
+        assert!(irq_chip.is_active());
+
+        write(&mut pl031, Register::Icr, RTC_INTERRUPT);
+        assert_eq!(read(&pl031, Register::Ris), 0);
+        assert!(!irq_chip.is_active());
+    }
+}
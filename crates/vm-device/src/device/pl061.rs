@@ -0,0 +1,456 @@
+use std::io::Read;
+use std::io::Write;
+use std::ops::Range;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use strum_macros::FromRepr;
+use vm_core::arch::aarch64::irq::GIC_SPI;
+use vm_core::arch::aarch64::irq::IRQ_TYPE_LEVEL_HIGH;
+use vm_core::arch::irq::InterruptController;
+use vm_core::arch::irq::Phandle;
+use vm_core::device::Device;
+use vm_core::device::error::DeviceError;
+use vm_core::device::error::DeviceSnapshotError;
+use vm_core::device::mmio::mmio_device::MmioDevice;
+use vm_core::device::power_button::PowerButton;
+use vm_fdt::FdtWriter;
+use vm_snapshot::helper::read_u8;
+use vm_snapshot::helper::write_u8;
+use vm_utils::range_allocator::RangeAllocator;
+
+/*
+ * ARM PrimeCell General Purpose Input/Output (PL061)
+ * https://developer.arm.com/documentation/ddi0190/b
+ */
+
+const PERIPH_ID: [u8; 4] = [0x61, 0x10, 0x04, 0x00];
+const CELL_ID: [u8; 4] = [0x0d, 0xf0, 0x05, 0xb1];
+
+/// The pin wired to the gpio-keys power key
+const POWER_KEY_PIN: u8 = 3;
+/// KEY_POWER in linux/input-event-codes.h
+const KEY_POWER: u32 = 116;
+const POWER_KEY_HOLD: Duration = Duration::from_millis(100);
+
+#[derive(Debug, FromRepr)]
+#[repr(u16)]
+enum Register {
+    Dir = 0x400,
+    Is = 0x404,
+    Ibe = 0x408,
+    Iev = 0x40c,
+    Ie = 0x410,
+    Ris = 0x414,
+    Mis = 0x418,
+    Ic = 0x41c,
+    Afsel = 0x420,
+    PeriphID0 = 0xfe0,
+    PeriphID1 = 0xfe4,
+    PeriphID2 = 0xfe8,
+    PeriphID3 = 0xfec,
+    CellID0 = 0xff0,
+    CellId1 = 0xff4,
+    CellId2 = 0xff8,
+    CellId3 = 0xffc,
+}
+
+#[derive(Default)]
+struct Pl061Regs {
+    data: u8,
+    dir: u8,
+    is: u8,
+    ibe: u8,
+    iev: u8,
+    ie: u8,
+    ris: u8,
+    afsel: u8,
+    /// Level of the input pins driven by the host
+    input: u8,
+}
+
+struct Pl061Internal {
+    irq: u32,
+    irq_chip: Arc<dyn InterruptController>,
+    regs: Pl061Regs,
+}
+
+impl Pl061Internal {
+    /// The level seen on each pin, outputs read back what was written
+    fn pins(&self) -> u8 {
+        (self.regs.data & self.regs.dir) | (self.regs.input & !self.regs.dir)
+    }
+
+    fn update_irq(&self) {
+        self.irq_chip
+            .trigger_irq(self.irq, self.regs.ris & self.regs.ie != 0);
+    }
+
+    fn set_pins(&mut self, old: u8) {
+        let new = self.pins();
+        let regs = &mut self.regs;
+
+        let changed = old ^ new;
+        let edge = !regs.is;
+        let both = changed & edge & regs.ibe;
+        let rising = changed & edge & !regs.ibe & regs.iev & new;
+        let falling = changed & edge & !regs.ibe & !regs.iev & !new;
+        let level = regs.is & !(new ^ regs.iev);
+
+        // Level interrupts follow the pins, edge interrupts latch until cleared
+        regs.ris = (regs.ris & edge) | both | rising | falling | level;
+
+        self.update_irq();
+    }
+
+    fn set_input(&mut self, pin: u8, high: bool) {
+        let old = self.pins();
+
+        if high {
+            self.regs.input |= 1 << pin;
+        } else {
+            self.regs.input &= !(1 << pin);
+        }
+
+        self.set_pins(old);
+    }
+
+    fn mmio_read(&self, offset: u64, data: &mut [u8]) {
+        let offset: u16 = offset.try_into().unwrap();
+
+        let val = if offset < 0x400 {
+            // Address bits [9:2] mask the pins being accessed
+            self.pins() & (offset >> 2) as u8
+        } else {
+            match Register::from_repr(offset) {
+                Some(Register::Dir) => self.regs.dir,
+                Some(Register::Is) => self.regs.is,
+                Some(Register::Ibe) => self.regs.ibe,
+                Some(Register::Iev) => self.regs.iev,
+                Some(Register::Ie) => self.regs.ie,
+                Some(Register::Ris) => self.regs.ris,
+                Some(Register::Mis) => self.regs.ris & self.regs.ie,
+                Some(Register::Afsel) => self.regs.afsel,
+                Some(Register::PeriphID0) => PERIPH_ID[0],
+                Some(Register::PeriphID1) => PERIPH_ID[1],
+                Some(Register::PeriphID2) => PERIPH_ID[2],
+                Some(Register::PeriphID3) => PERIPH_ID[3],
+                Some(Register::CellID0) => CELL_ID[0],
+                Some(Register::CellId1) => CELL_ID[1],
+                Some(Register::CellId2) => CELL_ID[2],
+                Some(Register::CellId3) => CELL_ID[3],
+                Some(Register::Ic) | None => 0,
+            }
+        };
+
+        data.fill(0);
+        data[0] = val;
+    }
+
+    fn mmio_write(&mut self, offset: u64, data: &[u8]) {
+        let offset: u16 = offset.try_into().unwrap();
+        let val = data[0];
+        let old = self.pins();
+
+        if offset < 0x400 {
+            let mask = (offset >> 2) as u8 & self.regs.dir;
+            self.regs.data = (self.regs.data & !mask) | (val & mask);
+        } else {
+            match Register::from_repr(offset) {
+                Some(Register::Dir) => self.regs.dir = val,
+                Some(Register::Is) => self.regs.is = val,
+                Some(Register::Ibe) => self.regs.ibe = val,
+                Some(Register::Iev) => self.regs.iev = val,
+                Some(Register::Ie) => self.regs.ie = val,
+                Some(Register::Ic) => self.regs.ris &= !val,
+                Some(Register::Afsel) => self.regs.afsel = val,
+                _ => (), // Read only
+            }
+        }
+
+        self.set_pins(old);
+    }
+}
+
+struct Pl061PowerButton {
+    pl061: Arc<Mutex<Pl061Internal>>,
+}
+
+impl PowerButton for Pl061PowerButton {
+    fn press(&self) {
+        self.pl061.lock().unwrap().set_input(POWER_KEY_PIN, true);
+
+        tokio::spawn({
+            let pl061 = self.pl061.clone();
+
+            async move {
+                tokio::time::sleep(POWER_KEY_HOLD).await;
+                pl061.lock().unwrap().set_input(POWER_KEY_PIN, false);
+            }
+        });
+    }
+}
+
+pub struct Pl061 {
+    irq: u32,
+    mmio_range: Range<u64>,
+    pl061: Arc<Mutex<Pl061Internal>>,
+}
+
+impl Pl061 {
+    pub fn new(
+        mmio_allocator: &mut RangeAllocator<u64>,
+        irq: u32,
+        irq_chip: Arc<dyn InterruptController>,
+    ) -> Result<Self, DeviceError> {
+        let pl061 = Arc::new(Mutex::new(Pl061Internal {
+            irq,
+            irq_chip,
+            regs: Pl061Regs::default(),
+        }));
+        let mmio_range = mmio_allocator.alloc(0x1000)?;
+
+        Ok(Pl061 {
+            irq,
+            mmio_range,
+            pl061,
+        })
+    }
+
+    pub fn power_button(&self) -> Arc<dyn PowerButton> {
+        Arc::new(Pl061PowerButton {
+            pl061: self.pl061.clone(),
+        })
+    }
+}
+
+impl Device for Pl061 {
+    fn name(&self) -> String {
+        "pl061".to_string()
+    }
+
+    fn pause(&self) -> Result<(), DeviceSnapshotError> {
+        Ok(())
+    }
+
+    fn resume(&self) -> Result<(), DeviceSnapshotError> {
+        Ok(())
+    }
+
+    fn save(&self, writer: &mut dyn Write) -> Result<(), DeviceSnapshotError> {
+        let pl061 = self.pl061.lock().unwrap();
+        let regs = &pl061.regs;
+
+        write_u8(writer, regs.data)?;
+        write_u8(writer, regs.dir)?;
+        write_u8(writer, regs.is)?;
+        write_u8(writer, regs.ibe)?;
+        write_u8(writer, regs.iev)?;
+        write_u8(writer, regs.ie)?;
+        write_u8(writer, regs.ris)?;
+        write_u8(writer, regs.afsel)?;
+        write_u8(writer, regs.input)?;
+
+        Ok(())
+    }
+
+    fn load(&mut self, reader: &mut dyn Read) -> Result<(), DeviceSnapshotError> {
+        let mut pl061 = self.pl061.lock().unwrap();
+
+        pl061.regs = Pl061Regs {
+            data: read_u8(reader)?,
+            dir: read_u8(reader)?,
+            is: read_u8(reader)?,
+            ibe: read_u8(reader)?,
+            iev: read_u8(reader)?,
+            ie: read_u8(reader)?,
+            ris: read_u8(reader)?,
+            afsel: read_u8(reader)?,
+            input: read_u8(reader)?,
+        };
+        pl061.update_irq();
+
+        Ok(())
+    }
+
+    fn support_mmio_transport(&self) -> Option<&dyn MmioDevice> {
+        Some(self)
+    }
+
+    fn support_mmio_transport_mut(&mut self) -> Option<&mut dyn MmioDevice> {
+        Some(self)
+    }
+}
+
+impl MmioDevice for Pl061 {
+    fn mmio_ranges(&self) -> Vec<Range<u64>> {
+        vec![self.mmio_range.clone()]
+    }
+
+    fn mmio_read(&self, addr: u64, buf: &mut [u8]) -> Result<(), DeviceError> {
+        self.pl061
+            .lock()
+            .unwrap()
+            .mmio_read(addr - self.mmio_range.start, buf);
+
+        Ok(())
+    }
+
+    fn mmio_write(&self, addr: u64, buf: &[u8]) -> Result<(), DeviceError> {
+        self.pl061
+            .lock()
+            .unwrap()
+            .mmio_write(addr - self.mmio_range.start, buf);
+
+        Ok(())
+    }
+
+    fn generate_dt(&self, fdt: &mut FdtWriter) -> Result<(), DeviceError> {
+        let node = fdt.begin_node(&format!("pl061@{:x}", self.mmio_range.start))?;
+        fdt.property_string_list(
+            "compatible",
+            vec!["arm,pl061".to_string(), "arm,primecell".to_string()],
+        )?;
+        fdt.property_array_u64(
+            "reg",
+            &[
+                self.mmio_range.start,
+                self.mmio_range.end - self.mmio_range.start,
+            ],
+        )?;
+        fdt.property_array_u32("interrupts", &[GIC_SPI, self.irq, IRQ_TYPE_LEVEL_HIGH])?;
+        fdt.property_null("gpio-controller")?;
+        fdt.property_u32("#gpio-cells", 2)?;
+        fdt.property_u32("clocks", Phandle::CLOCK as u32)?;
+        fdt.property_string("clock-names", "apb_pclk")?;
+        fdt.property_phandle(Phandle::GPIO as u32)?;
+        fdt.end_node(node)?;
+
+        let node = fdt.begin_node("gpio-keys")?;
+        fdt.property_string("compatible", "gpio-keys")?;
+        let key = fdt.begin_node("poweroff")?;
+        fdt.property_string("label", "GPIO Key Poweroff")?;
+        fdt.property_u32("linux,code", KEY_POWER)?;
+        fdt.property_array_u32("gpios", &[Phandle::GPIO as u32, POWER_KEY_PIN as u32, 0])?;
+        fdt.end_node(key)?;
+        fdt.end_node(node)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::IrqChip;
+
+    fn pl061(irq_chip: Arc<IrqChip>) -> Pl061 {
+        let mut mmio_allocator = RangeAllocator::<u64>::default();
+        mmio_allocator.insert(0x1000_0000, 0x10_0000).unwrap();
+
+        Pl061::new(&mut mmio_allocator, 5, irq_chip).unwrap()
+    }
+
+    fn read(pl061: &Pl061, offset: u64) -> u8 {
+        let mut data = [0; 4];
+        pl061
+            .mmio_read(pl061.mmio_range.start + offset, &mut data)
+            .unwrap();
+        data[0]
+    }
+
+    fn write(pl061: &Pl061, offset: u64, val: u8) {
+        pl061
+            .mmio_write(pl061.mmio_range.start + offset, &[val])
+            .unwrap();
+    }
+
+    fn set_input(pl061: &Pl061, pin: u8, high: bool) {
+        pl061.pl061.lock().unwrap().set_input(pin, high);
+    }
+
+    #[test]
+    fn test_id_registers() {
+        let pl061 = pl061(Arc::new(IrqChip::default()));
+
+        assert_eq!(read(&pl061, Register::PeriphID0 as u64), 0x61);
+        assert_eq!(read(&pl061, Register::PeriphID1 as u64), 0x10);
+        assert_eq!(read(&pl061, Register::PeriphID2 as u64), 0x04);
+        assert_eq!(read(&pl061, Register::PeriphID3 as u64), 0x00);
+        assert_eq!(read(&pl061, Register::CellID0 as u64), 0x0d);
+        assert_eq!(read(&pl061, Register::CellId1 as u64), 0xf0);
+        assert_eq!(read(&pl061, Register::CellId2 as u64), 0x05);
+        assert_eq!(read(&pl061, Register::CellId3 as u64), 0xb1);
+    }
+
+    #[test]
+    fn test_masked_data() {
+        let pl061 = pl061(Arc::new(IrqChip::default()));
+
+        write(&pl061, Register::Dir as u64, 0x0f);
+        assert_eq!(read(&pl061, Register::Dir as u64), 0x0f);
+
+        // Only the pins selected by the address and set as outputs change
+        write(&pl061, 0x33 << 2, 0xff);
+        assert_eq!(read(&pl061, 0xff << 2), 0x03);
+        assert_eq!(read(&pl061, 0x01 << 2), 0x01);
+        assert_eq!(read(&pl061, 0x0c << 2), 0x00);
+
+        // Inputs read the level driven by the host
+        set_input(&pl061, 7, true);
+        assert_eq!(read(&pl061, 0xff << 2), 0x83);
+        assert_eq!(read(&pl061, 0x7f << 2), 0x03);
+    }
+
+    #[test]
+    fn test_edge_interrupt() {
+        let irq_chip = Arc::new(IrqChip::default());
+        let pl061 = pl061(irq_chip.clone());
+
+        write(&pl061, Register::Iev as u64, 1 << POWER_KEY_PIN);
+        write(&pl061, Register::Ie as u64, 1 << POWER_KEY_PIN);
+
+        set_input(&pl061, POWER_KEY_PIN, true);
+        assert_eq!(read(&pl061, Register::Ris as u64), 1 << POWER_KEY_PIN);
+        assert_eq!(read(&pl061, Register::Mis as u64), 1 << POWER_KEY_PIN);
+        assert!(irq_chip.is_active());
+
+        // Edge interrupts latch until cleared
+        set_input(&pl061, POWER_KEY_PIN, false);
+        assert!(irq_chip.is_active());
+
+        write(&pl061, Register::Ic as u64, 1 << POWER_KEY_PIN);
+        assert_eq!(read(&pl061, Register::Ris as u64), 0);
+        assert!(!irq_chip.is_active());
+
+        // A falling edge does not match a rising edge interrupt
+        set_input(&pl061, POWER_KEY_PIN, true);
+        write(&pl061, Register::Ic as u64, 1 << POWER_KEY_PIN);
+        set_input(&pl061, POWER_KEY_PIN, false);
+        assert_eq!(read(&pl061, Register::Ris as u64), 0);
+    }
+
+    #[test]
+    fn test_level_interrupt() {
+        let irq_chip = Arc::new(IrqChip::default());
+        let pl061 = pl061(irq_chip.clone());
+
+        write(&pl061, Register::Is as u64, 1 << 1);
+        write(&pl061, Register::Iev as u64, 1 << 1);
+
+        // A masked interrupt is raw only
+        set_input(&pl061, 1, true);
+        assert_eq!(read(&pl061, Register::Ris as u64), 1 << 1);
+        assert_eq!(read(&pl061, Register::Mis as u64), 0);
+        assert!(!irq_chip.is_active());
+
+        write(&pl061, Register::Ie as u64, 1 << 1);
+        assert!(irq_chip.is_active());
+
+        // Level interrupts follow the pin
+        set_input(&pl061, 1, false);
+        assert_eq!(read(&pl061, Register::Ris as u64), 0);
+        assert!(!irq_chip.is_active());
+    }
+}
@@ -0,0 +1,39 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum RtcBase {
+    #[default]
+    Utc,
+    Localtime,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RtcConfig {
+    pub base: RtcBase,
+    /// Seconds added to the base time
+    pub offset: i64,
+}
+
+impl RtcConfig {
+    /// Seconds between the host UTC time and the guest RTC. The localtime
+    /// offset is sampled once, DST changes are not followed.
+    pub fn offset(&self) -> i64 {
+        match self.base {
+            RtcBase::Utc => self.offset,
+            RtcBase::Localtime => local_offset() + self.offset,
+        }
+    }
+}
+
+fn local_offset() -> i64 {
+    let now = unsafe { libc::time(std::ptr::null_mut()) };
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+
+    if unsafe { libc::localtime_r(&now, &mut tm) }.is_null() {
+        return 0;
+    }
+
+    tm.tm_gmtoff as i64
+}
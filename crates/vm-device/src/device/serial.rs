@@ -0,0 +1,35 @@
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Where the console of the guest is connected, the other serial ports print to stdout
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum SerialConfig {
+    /// The output goes to stdout and the input comes from stdin
+    #[default]
+    Tty,
+    /// The output is appended to the file, there is no input
+    File(PathBuf),
+    /// The output is dropped
+    Off,
+}
+
+impl SerialConfig {
+    pub fn output(&self) -> io::Result<Box<dyn Write + Send>> {
+        Ok(match self {
+            SerialConfig::Tty => Box::new(io::stdout()),
+            SerialConfig::File(path) => {
+                Box::new(OpenOptions::new().create(true).append(true).open(path)?)
+            }
+            SerialConfig::Off => Box::new(io::sink()),
+        })
+    }
+
+    pub fn has_input(&self) -> bool {
+        matches!(self, SerialConfig::Tty)
+    }
+}
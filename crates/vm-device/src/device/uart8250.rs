@@ -13,6 +13,7 @@ use vm_core::device::pio::pio_device::PioDevice;
 use vm_utils::range_allocator::RangeAllocator;
 use vm_utils::ring::Ring;
 
+use crate::device::serial::SerialConfig;
 use crate::device::uart8250::ier::IER;
 use crate::device::uart8250::lcr::LCR;
 use crate::device::uart8250::lsr::LSR;
@@ -174,6 +175,7 @@ struct Uart8250Internal<const IRQ: u32> {
     // Scratch Register
     sr: u8,
     irq_state: bool,
+    output: Box<dyn Write + Send>,
 }
 
 impl<const IRQ: u32> Uart8250Internal<IRQ> {
@@ -190,8 +192,13 @@ impl<const IRQ: u32> Uart8250Internal<IRQ> {
         // We reserved the push and pop to keep the semantics of uart,
         // I don't know if we need thr in the future.
         while let Some(c) = self.txr.try_pop() {
-            print!("{}", c as char);
-            io::stdout().flush().unwrap();
+            if let Err(err) = self
+                .output
+                .write_all(&[c])
+                .and_then(|_| self.output.flush())
+            {
+                warn!(?err, "failed to write the uart output");
+            }
         }
     }
 
@@ -374,10 +381,17 @@ impl<const IRQ: u32> Uart8250<IRQ> {
         pio_allocator: &mut RangeAllocator<u16>,
         port_base: u16,
         irq_controller: Arc<dyn InterruptController>,
-        console: bool,
+        console: Option<&SerialConfig>,
     ) -> Result<Self, DeviceError> {
         let _ = pio_allocator.reserve(port_base, 8)?;
 
+        let output = match console {
+            Some(serial) => serial
+                .output()
+                .map_err(|err| DeviceError::Device(Box::new(err)))?,
+            None => Box::new(io::stdout()),
+        };
+
         let internal = Arc::new(Mutex::new(Uart8250Internal {
             txr: Default::default(),
             rbr: Default::default(),
@@ -393,9 +407,10 @@ impl<const IRQ: u32> Uart8250<IRQ> {
             sr: Default::default(),
             irq_controller,
             irq_state: false,
+            output,
         }));
 
-        if console {
+        if console.is_some_and(SerialConfig::has_input) {
             tokio::spawn({
                 let raw = internal.clone();
                 async move {
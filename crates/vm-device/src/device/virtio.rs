@@ -1,4 +1,7 @@
+pub mod virtio_9p;
 pub mod virtio_balloon_traditional;
 pub mod virtio_blk;
 pub mod virtio_entropy;
 pub mod virtio_gpu;
+pub mod virtio_iommu;
+pub mod virtio_mem;
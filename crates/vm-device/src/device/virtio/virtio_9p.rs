@@ -0,0 +1,181 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tokio::sync::Mutex;
+use tracing::error;
+use vm_mm::manager::MemoryAddressSpace;
+use vm_pci::device::interrupt::legacy::InterruptPin;
+use vm_virtio::device::VirtioDevice;
+use vm_virtio::device::virtqueue::VirtqueueHandler;
+use vm_virtio::result::VirtioError;
+use vm_virtio::transport::pci::VirtioPciDevice;
+use vm_virtio::types::device::p9::VIRTIO_9P_MAX_TAG_LEN;
+use vm_virtio::types::device::p9::VIRTIO_9P_MOUNT_TAG;
+use vm_virtio::types::device::p9::VirtioP9Config;
+use vm_virtio::types::device::p9::VirtioP9Virtqueue;
+use vm_virtio::types::device_features::VIRTIO_F_VERSION_1;
+use vm_virtio::types::device_id::DeviceId;
+use vm_virtio::virtqueue::virtq_desc_table::VIRTQ_DESC_F_WRITE;
+use vm_virtio::virtqueue::virtq_desc_table::VirtqDescTableRef;
+use zerocopy::IntoBytes;
+
+use crate::device::virtio::virtio_9p::id_map::IdMap;
+use crate::device::virtio::virtio_9p::server::MAX_MSIZE;
+use crate::device::virtio::virtio_9p::server::P9Server;
+
+pub mod error;
+mod host_fs;
+pub mod id_map;
+mod server;
+mod wire;
+
+const REQUESTQ_SIZE_MAX: u16 = 128;
+
+struct RequestqHandler {
+    server: Arc<Mutex<P9Server>>,
+    memory: Arc<MemoryAddressSpace>,
+}
+
+#[async_trait]
+impl VirtqueueHandler for RequestqHandler {
+    async fn handle_desc(&self, desc_ring: &VirtqDescTableRef, desc_id: u16) -> u32 {
+        let chain = desc_ring.get_chain(desc_id);
+
+        // The driver puts the T-message in the device-readable buffers and
+        // leaves the device-writable buffers for the R-message.
+        let mut request = vec![];
+        for desc in chain
+            .iter()
+            .filter(|desc| desc.flags & VIRTQ_DESC_F_WRITE == 0)
+        {
+            let start = request.len();
+            let len = desc.len;
+            if start + len as usize > MAX_MSIZE as usize {
+                error!(len, "virtio-9p: request larger than the max msize");
+                return 0;
+            }
+            request.resize(start + len as usize, 0);
+            if let Err(err) = self.memory.copy_to_slice(desc.gpa(), &mut request[start..]) {
+                error!(?err, "virtio-9p: invalid request buffer");
+                return 0;
+            }
+        }
+
+        let response = self.server.lock().await.handle_message(&request);
+
+        let mut written = 0;
+        for desc in chain
+            .iter()
+            .filter(|desc| desc.flags & VIRTQ_DESC_F_WRITE != 0)
+        {
+            if written == response.len() {
+                break;
+            }
+
+            let len = (desc.len as usize).min(response.len() - written);
+            if let Err(err) = self
+                .memory
+                .copy_from_slice(desc.gpa(), &response[written..written + len])
+            {
+                error!(?err, "virtio-9p: invalid response buffer");
+                return 0;
+            }
+            written += len;
+        }
+
+        if written < response.len() {
+            error!(
+                len = response.len(),
+                written, "virtio-9p: response buffer too small"
+            );
+        }
+
+        written as u32
+    }
+}
+
+pub struct Virtio9p {
+    cfg: VirtioP9Config,
+    server: Arc<Mutex<P9Server>>,
+    memory: Arc<MemoryAddressSpace>,
+}
+
+impl Virtio9p {
+    pub fn new(
+        memory: Arc<MemoryAddressSpace>,
+        tag: &str,
+        path: PathBuf,
+        read_only: bool,
+        uid_map: Vec<IdMap>,
+        gid_map: Vec<IdMap>,
+    ) -> Result<Self, VirtioError> {
+        if tag.is_empty() || tag.len() > VIRTIO_9P_MAX_TAG_LEN {
+            return Err(VirtioError::Invalid9pMountTag(tag.to_string()));
+        }
+
+        let root = path
+            .canonicalize()
+            .map_err(|_| VirtioError::Invalid9pSharedDir(path.clone()))?;
+        if !root.is_dir() {
+            return Err(VirtioError::Invalid9pSharedDir(path));
+        }
+
+        let mut cfg = VirtioP9Config {
+            tag_len: tag.len() as u16,
+            tag: [0; VIRTIO_9P_MAX_TAG_LEN],
+        };
+        cfg.tag[..tag.len()].copy_from_slice(tag.as_bytes());
+
+        let server = P9Server::new(root, read_only, uid_map, gid_map)
+            .map_err(|_| VirtioError::Invalid9pSharedDir(path))?;
+
+        Ok(Virtio9p {
+            cfg,
+            server: Arc::new(Mutex::new(server)),
+            memory,
+        })
+    }
+}
+
+impl VirtioDevice for Virtio9p {
+    const NAME: &str = "virtio-9p";
+    const DEVICE_ID: u16 = DeviceId::P9 as u16;
+    const DEVICE_FEATURES: u64 = (1 << VIRTIO_F_VERSION_1) | (1 << VIRTIO_9P_MOUNT_TAG);
+
+    fn virtqueues_size_max(&self) -> Vec<u16> {
+        vec![REQUESTQ_SIZE_MAX]
+    }
+
+    fn reset(&mut self) {}
+
+    fn virtqueue_handler(&self, queue_sel: u16) -> Option<Box<dyn VirtqueueHandler>> {
+        match VirtioP9Virtqueue::from_repr(queue_sel)? {
+            VirtioP9Virtqueue::Requestq => Some(Box::new(RequestqHandler {
+                server: self.server.clone(),
+                memory: self.memory.clone(),
+            })),
+        }
+    }
+
+    fn read_config(&self, offset: usize, buf: &mut [u8]) -> Result<(), VirtioError> {
+        let cfg = self.cfg.as_bytes();
+        if offset + buf.len() > cfg.len() {
+            return Err(VirtioError::DriverReadDeviceConfigurationInvalid);
+        }
+
+        buf.copy_from_slice(&cfg[offset..offset + buf.len()]);
+        Ok(())
+    }
+
+    fn write_config(&mut self, _offset: usize, _buf: &[u8]) -> Result<(), VirtioError> {
+        // The mount tag is read-only
+        Err(VirtioError::DriverWriteDeviceConfigurationInvalid)
+    }
+}
+
+impl VirtioPciDevice for Virtio9p {
+    const DEVICE_SPECIFICATION_CONFIGURATION_LEN: usize = size_of::<VirtioP9Config>();
+    const CLASS_CODE: u32 = 0x018000;
+    const IRQ_PIN: u8 = InterruptPin::INTA as u8;
+}
@@ -0,0 +1,76 @@
+use std::io;
+
+use thiserror::Error;
+
+/// Linux errno values, 9P2000.L always speaks them regardless of the host.
+pub mod errno {
+    pub const EPERM: u32 = 1;
+    pub const ENOENT: u32 = 2;
+    pub const EIO: u32 = 5;
+    pub const EBADF: u32 = 9;
+    pub const EAGAIN: u32 = 11;
+    pub const EACCES: u32 = 13;
+    pub const EEXIST: u32 = 17;
+    pub const EXDEV: u32 = 18;
+    pub const ENOTDIR: u32 = 20;
+    pub const EISDIR: u32 = 21;
+    pub const EINVAL: u32 = 22;
+    pub const EFBIG: u32 = 27;
+    pub const ENOSPC: u32 = 28;
+    pub const EROFS: u32 = 30;
+    pub const ENAMETOOLONG: u32 = 36;
+    pub const ENOSYS: u32 = 38;
+    pub const ENOTEMPTY: u32 = 39;
+    pub const ELOOP: u32 = 40;
+    pub const EPROTO: u32 = 71;
+    pub const EOPNOTSUPP: u32 = 95;
+}
+
+#[derive(Error, Debug)]
+pub enum P9Error {
+    #[error("{0}")]
+    Io(#[from] io::Error),
+
+    #[error("errno {0}")]
+    Errno(u32),
+}
+
+impl P9Error {
+    pub fn errno(&self) -> u32 {
+        match self {
+            P9Error::Io(err) => io_error_to_errno(err),
+            P9Error::Errno(errno) => *errno,
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn io_error_to_errno(err: &io::Error) -> u32 {
+    err.raw_os_error().map(|e| e as u32).unwrap_or(errno::EIO)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn io_error_to_errno(err: &io::Error) -> u32 {
+    match err.kind() {
+        io::ErrorKind::NotFound => errno::ENOENT,
+        io::ErrorKind::PermissionDenied => errno::EACCES,
+        io::ErrorKind::AlreadyExists => errno::EEXIST,
+        io::ErrorKind::WouldBlock => errno::EAGAIN,
+        io::ErrorKind::InvalidInput => errno::EINVAL,
+        io::ErrorKind::NotADirectory => errno::ENOTDIR,
+        io::ErrorKind::IsADirectory => errno::EISDIR,
+        io::ErrorKind::DirectoryNotEmpty => errno::ENOTEMPTY,
+        io::ErrorKind::ReadOnlyFilesystem => errno::EROFS,
+        io::ErrorKind::StorageFull => errno::ENOSPC,
+        io::ErrorKind::FileTooLarge => errno::EFBIG,
+        io::ErrorKind::CrossesDevices => errno::EXDEV,
+        io::ErrorKind::InvalidFilename => errno::ENAMETOOLONG,
+        io::ErrorKind::Unsupported => errno::EOPNOTSUPP,
+        _ => match err.raw_os_error() {
+            Some(libc::ELOOP) => errno::ELOOP,
+            Some(libc::EPERM) => errno::EPERM,
+            Some(libc::EBADF) => errno::EBADF,
+            _ => errno::EIO,
+        },
+    }
+}
@@ -0,0 +1,286 @@
+use std::ffi::CStr;
+use std::ffi::CString;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::fd::FromRawFd;
+use std::os::fd::IntoRawFd;
+use std::os::fd::RawFd;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::ffi::OsStringExt;
+use std::path::Path;
+
+fn cvt(ret: libc::c_int) -> io::Result<libc::c_int> {
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret)
+    }
+}
+
+pub fn cstring(path: &Path) -> io::Result<CString> {
+    CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| io::Error::from_raw_os_error(libc::EINVAL))
+}
+
+/// A node named by its parent directory, the root of the share is named by its host path
+pub struct At {
+    pub dir: Option<File>,
+    pub name: CString,
+}
+
+impl At {
+    pub fn dirfd(&self) -> RawFd {
+        self.dir
+            .as_ref()
+            .map_or(libc::AT_FDCWD, |dir| dir.as_raw_fd())
+    }
+}
+
+/// Open the shared directory, the other nodes are resolved beneath it
+pub fn open_root(path: &Path) -> io::Result<File> {
+    let path = cstring(path)?;
+
+    #[cfg(target_os = "linux")]
+    let flags = libc::O_PATH | libc::O_DIRECTORY | libc::O_CLOEXEC;
+    #[cfg(not(target_os = "linux"))]
+    let flags = libc::O_EVTONLY | libc::O_DIRECTORY | libc::O_CLOEXEC;
+
+    let fd = cvt(unsafe { libc::open(path.as_ptr(), flags) })?;
+
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+/// Open a node only usable to name it in the other calls, `path` can't escape `dir` and a
+/// trailing symlink is opened itself.
+#[cfg(target_os = "linux")]
+pub fn open_beneath(dir: &File, path: &Path) -> io::Result<File> {
+    let path = if path.as_os_str().is_empty() {
+        c".".to_owned()
+    } else {
+        cstring(path)?
+    };
+
+    let mut how: libc::open_how = unsafe { std::mem::zeroed() };
+    how.flags = (libc::O_PATH | libc::O_NOFOLLOW | libc::O_CLOEXEC) as u64;
+    how.resolve = libc::RESOLVE_BENEATH;
+
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_openat2,
+            dir.as_raw_fd(),
+            path.as_ptr(),
+            &how as *const libc::open_how,
+            size_of::<libc::open_how>(),
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(unsafe { File::from_raw_fd(fd as RawFd) })
+}
+
+/// There is no openat2 on macOS, the components are opened one by one without following
+/// any symlink.
+#[cfg(not(target_os = "linux"))]
+pub fn open_beneath(dir: &File, path: &Path) -> io::Result<File> {
+    use std::path::Component;
+
+    let mut node = dir.try_clone()?;
+    let mut components = path.components().peekable();
+    while let Some(component) = components.next() {
+        let Component::Normal(name) = component else {
+            return Err(io::Error::from_raw_os_error(libc::EXDEV));
+        };
+        let name = cstring(Path::new(name))?;
+
+        let flags = if components.peek().is_some() {
+            libc::O_EVTONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC
+        } else {
+            libc::O_EVTONLY | libc::O_SYMLINK | libc::O_CLOEXEC
+        };
+        let fd = cvt(unsafe { libc::openat(node.as_raw_fd(), name.as_ptr(), flags) })?;
+        node = unsafe { File::from_raw_fd(fd) };
+    }
+
+    Ok(node)
+}
+
+/// Open the node for io, the caller makes sure it is not a symlink
+#[cfg(target_os = "linux")]
+pub fn reopen(node: &File, flags: libc::c_int) -> io::Result<File> {
+    let path = CString::new(format!("/proc/self/fd/{}", node.as_raw_fd())).unwrap();
+    let fd = cvt(unsafe { libc::open(path.as_ptr(), flags | libc::O_CLOEXEC) })?;
+
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn reopen(node: &File, flags: libc::c_int) -> io::Result<File> {
+    let mut path = [0 as libc::c_char; libc::PATH_MAX as usize];
+    cvt(unsafe { libc::fcntl(node.as_raw_fd(), libc::F_GETPATH, path.as_mut_ptr()) })?;
+
+    let fd = cvt(unsafe { libc::open(path.as_ptr(), flags | libc::O_NOFOLLOW | libc::O_CLOEXEC) })?;
+
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+pub fn create(dir: &File, name: &CStr, flags: libc::c_int, mode: u32) -> io::Result<File> {
+    let fd = cvt(unsafe {
+        libc::openat(
+            dir.as_raw_fd(),
+            name.as_ptr(),
+            flags | libc::O_CREAT | libc::O_NOFOLLOW | libc::O_CLOEXEC,
+            mode as libc::c_uint,
+        )
+    })?;
+
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+pub fn mkdir(dir: &File, name: &CStr, mode: u32) -> io::Result<()> {
+    cvt(unsafe { libc::mkdirat(dir.as_raw_fd(), name.as_ptr(), mode as libc::mode_t) })?;
+
+    Ok(())
+}
+
+pub fn mknod(dir: &File, name: &CStr, mode: u32) -> io::Result<()> {
+    cvt(unsafe { libc::mknodat(dir.as_raw_fd(), name.as_ptr(), mode as libc::mode_t, 0) })?;
+
+    Ok(())
+}
+
+pub fn symlink(target: &CStr, dir: &File, name: &CStr) -> io::Result<()> {
+    cvt(unsafe { libc::symlinkat(target.as_ptr(), dir.as_raw_fd(), name.as_ptr()) })?;
+
+    Ok(())
+}
+
+pub fn link(old: &At, dir: &File, name: &CStr) -> io::Result<()> {
+    cvt(unsafe {
+        libc::linkat(
+            old.dirfd(),
+            old.name.as_ptr(),
+            dir.as_raw_fd(),
+            name.as_ptr(),
+            0,
+        )
+    })?;
+
+    Ok(())
+}
+
+pub fn rename(olddirfd: RawFd, oldname: &CStr, newdir: &File, newname: &CStr) -> io::Result<()> {
+    cvt(unsafe {
+        libc::renameat(
+            olddirfd,
+            oldname.as_ptr(),
+            newdir.as_raw_fd(),
+            newname.as_ptr(),
+        )
+    })?;
+
+    Ok(())
+}
+
+pub fn unlink(dirfd: RawFd, name: &CStr, flags: libc::c_int) -> io::Result<()> {
+    cvt(unsafe { libc::unlinkat(dirfd, name.as_ptr(), flags) })?;
+
+    Ok(())
+}
+
+pub fn readlink(at: &At) -> io::Result<OsString> {
+    let mut buf = vec![0u8; libc::PATH_MAX as usize];
+    let len = unsafe {
+        libc::readlinkat(
+            at.dirfd(),
+            at.name.as_ptr(),
+            buf.as_mut_ptr() as *mut libc::c_char,
+            buf.len(),
+        )
+    };
+    if len < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    buf.truncate(len as usize);
+
+    Ok(OsString::from_vec(buf))
+}
+
+pub fn chmod(at: &At, mode: u32) -> io::Result<()> {
+    cvt(unsafe {
+        libc::fchmodat(
+            at.dirfd(),
+            at.name.as_ptr(),
+            mode as libc::mode_t,
+            libc::AT_SYMLINK_NOFOLLOW,
+        )
+    })?;
+
+    Ok(())
+}
+
+/// The ids left `None` are not changed
+pub fn chown(at: &At, uid: Option<u32>, gid: Option<u32>) -> io::Result<()> {
+    cvt(unsafe {
+        libc::fchownat(
+            at.dirfd(),
+            at.name.as_ptr(),
+            uid.unwrap_or(u32::MAX) as libc::uid_t,
+            gid.unwrap_or(u32::MAX) as libc::gid_t,
+            libc::AT_SYMLINK_NOFOLLOW,
+        )
+    })?;
+
+    Ok(())
+}
+
+pub fn utimens(at: &At, times: &[libc::timespec; 2]) -> io::Result<()> {
+    cvt(unsafe {
+        libc::utimensat(
+            at.dirfd(),
+            at.name.as_ptr(),
+            times.as_ptr(),
+            libc::AT_SYMLINK_NOFOLLOW,
+        )
+    })?;
+
+    Ok(())
+}
+
+pub fn statvfs(node: &File) -> io::Result<libc::statvfs> {
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    cvt(unsafe { libc::fstatvfs(node.as_raw_fd(), &mut stat) })?;
+
+    Ok(stat)
+}
+
+/// The names in the directory without `.` and `..`
+pub fn read_dir(node: &File) -> io::Result<Vec<OsString>> {
+    let dir = reopen(node, libc::O_RDONLY | libc::O_DIRECTORY)?;
+
+    let stream = unsafe { libc::fdopendir(dir.as_raw_fd()) };
+    if stream.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+    // The stream owns the fd from now on
+    let _ = dir.into_raw_fd();
+
+    let mut names = vec![];
+    loop {
+        let entry = unsafe { libc::readdir(stream) };
+        if entry.is_null() {
+            break;
+        }
+
+        let name = unsafe { CStr::from_ptr((*entry).d_name.as_ptr()) };
+        if name != c"." && name != c".." {
+            names.push(OsString::from_vec(name.to_bytes().to_vec()));
+        }
+    }
+    unsafe { libc::closedir(stream) };
+
+    Ok(names)
+}
@@ -0,0 +1,67 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Id reported to the guest for host ids without a mapping.
+pub const OVERFLOW_ID: u32 = 65534;
+
+/// Maps `count` consecutive guest ids starting at `guest` to host ids
+/// starting at `host`, in the same format as `/proc/self/uid_map`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct IdMap {
+    pub guest: u32,
+    pub host: u32,
+    pub count: u32,
+}
+
+pub fn guest_to_host(maps: &[IdMap], id: u32) -> Option<u32> {
+    if maps.is_empty() {
+        return Some(id);
+    }
+
+    maps.iter()
+        .find(|map| id >= map.guest && id - map.guest < map.count)
+        .map(|map| map.host + (id - map.guest))
+}
+
+pub fn host_to_guest(maps: &[IdMap], id: u32) -> u32 {
+    if maps.is_empty() {
+        return id;
+    }
+
+    maps.iter()
+        .find(|map| id >= map.host && id - map.host < map.count)
+        .map(|map| map.guest + (id - map.host))
+        .unwrap_or(OVERFLOW_ID)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_id_map() {
+        let maps = vec![
+            IdMap {
+                guest: 0,
+                host: 1000,
+                count: 1,
+            },
+            IdMap {
+                guest: 1000,
+                host: 100000,
+                count: 65536,
+            },
+        ];
+
+        assert_eq!(guest_to_host(&maps, 0), Some(1000));
+        assert_eq!(guest_to_host(&maps, 1001), Some(100001));
+        assert_eq!(guest_to_host(&maps, 1), None);
+
+        assert_eq!(host_to_guest(&maps, 1000), 0);
+        assert_eq!(host_to_guest(&maps, 100001), 1001);
+        assert_eq!(host_to_guest(&maps, 0), OVERFLOW_ID);
+
+        assert_eq!(guest_to_host(&[], 42), Some(42));
+        assert_eq!(host_to_guest(&[], 42), 42);
+    }
+}
@@ -0,0 +1,1239 @@
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::ffi::CString;
+use std::ffi::OsStr;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::FileExt;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::path::PathBuf;
+
+use tracing::debug;
+use vm_virtio::types::device::p9::P9_AT_REMOVEDIR;
+use vm_virtio::types::device::p9::P9_GETATTR_BASIC;
+use vm_virtio::types::device::p9::P9_LOCK_SUCCESS;
+use vm_virtio::types::device::p9::P9_LOCK_TYPE_UNLCK;
+use vm_virtio::types::device::p9::P9_QTDIR;
+use vm_virtio::types::device::p9::P9_QTFILE;
+use vm_virtio::types::device::p9::P9_QTSYMLINK;
+use vm_virtio::types::device::p9::P9_SETATTR_ATIME;
+use vm_virtio::types::device::p9::P9_SETATTR_ATIME_SET;
+use vm_virtio::types::device::p9::P9_SETATTR_GID;
+use vm_virtio::types::device::p9::P9_SETATTR_MODE;
+use vm_virtio::types::device::p9::P9_SETATTR_MTIME;
+use vm_virtio::types::device::p9::P9_SETATTR_MTIME_SET;
+use vm_virtio::types::device::p9::P9_SETATTR_SIZE;
+use vm_virtio::types::device::p9::P9_SETATTR_UID;
+use vm_virtio::types::device::p9::P9MessageType;
+
+use crate::device::virtio::virtio_9p::error::P9Error;
+use crate::device::virtio::virtio_9p::error::errno;
+use crate::device::virtio::virtio_9p::host_fs;
+use crate::device::virtio::virtio_9p::host_fs::At;
+use crate::device::virtio::virtio_9p::id_map::IdMap;
+use crate::device::virtio::virtio_9p::id_map::guest_to_host;
+use crate::device::virtio::virtio_9p::id_map::host_to_guest;
+use crate::device::virtio::virtio_9p::wire::Qid;
+use crate::device::virtio::virtio_9p::wire::WireReader;
+use crate::device::virtio::virtio_9p::wire::WireWriter;
+
+const P9_VERSION: &str = "9P2000.L";
+const P9_NOTAG: u16 = 0xffff;
+const P9_HEADER_LEN: usize = 7;
+// size[4] of Rread/Rreaddir
+const P9_IOHDR_LEN: usize = P9_HEADER_LEN + 4;
+pub const MAX_MSIZE: u32 = 512 << 10;
+const V9FS_MAGIC: u32 = 0x01021997;
+
+// Linux open flags used by Tlopen/Tlcreate, these are identical on every arch.
+const L_O_ACCMODE: u32 = 0o3;
+const L_O_WRONLY: u32 = 0o1;
+const L_O_RDWR: u32 = 0o2;
+const L_O_EXCL: u32 = 0o200;
+const L_O_TRUNC: u32 = 0o1000;
+const L_O_APPEND: u32 = 0o2000;
+
+// d_type of readdir entries
+const DT_FIFO: u8 = 1;
+const DT_CHR: u8 = 2;
+const DT_DIR: u8 = 4;
+const DT_BLK: u8 = 6;
+const DT_REG: u8 = 8;
+const DT_LNK: u8 = 10;
+const DT_SOCK: u8 = 12;
+
+struct DirEntry {
+    qid: Qid,
+    r#type: u8,
+    name: String,
+}
+
+struct Fid {
+    /// Relative to the shared directory
+    path: PathBuf,
+    /// Only names the node, it is never followed if it is a symlink
+    node: File,
+    /// Guest uid the fid is attached with
+    uid: u32,
+    /// Opened by Tlopen or Tlcreate
+    file: Option<File>,
+    dir_entries: Option<Vec<DirEntry>>,
+}
+
+impl Fid {
+    fn file(&self) -> Result<&File, P9Error> {
+        self.file.as_ref().ok_or(P9Error::Errno(errno::EBADF))
+    }
+}
+
+pub struct P9Server {
+    root_path: PathBuf,
+    /// Every node is resolved beneath it
+    root: File,
+    read_only: bool,
+    uid_map: Vec<IdMap>,
+    gid_map: Vec<IdMap>,
+    msize: u32,
+    fids: HashMap<u32, Fid>,
+}
+
+fn qid(metadata: &fs::Metadata) -> Qid {
+    let file_type = metadata.file_type();
+
+    let r#type = if file_type.is_dir() {
+        P9_QTDIR
+    } else if file_type.is_symlink() {
+        P9_QTSYMLINK
+    } else {
+        P9_QTFILE
+    };
+
+    Qid {
+        r#type,
+        version: metadata.mtime() as u32 ^ (metadata.size() as u32),
+        path: metadata.ino(),
+    }
+}
+
+fn dirent_type(file_type: fs::FileType) -> u8 {
+    if file_type.is_dir() {
+        DT_DIR
+    } else if file_type.is_symlink() {
+        DT_LNK
+    } else if file_type.is_char_device() {
+        DT_CHR
+    } else if file_type.is_block_device() {
+        DT_BLK
+    } else if file_type.is_fifo() {
+        DT_FIFO
+    } else if file_type.is_socket() {
+        DT_SOCK
+    } else {
+        DT_REG
+    }
+}
+
+fn open_flags(flags: u32) -> libc::c_int {
+    let mut host_flags = match flags & L_O_ACCMODE {
+        L_O_WRONLY => libc::O_WRONLY,
+        L_O_RDWR => libc::O_RDWR,
+        _ => libc::O_RDONLY,
+    };
+
+    if flags & L_O_TRUNC != 0 {
+        host_flags |= libc::O_TRUNC;
+    }
+
+    if flags & L_O_APPEND != 0 {
+        host_flags |= libc::O_APPEND;
+    }
+
+    host_flags
+}
+
+/// Resolve `name` in `dir` without leaving the shared directory.
+fn join(dir: &Path, name: &str) -> Result<PathBuf, P9Error> {
+    match name {
+        "" => Err(P9Error::Errno(errno::EINVAL)),
+        "." => Ok(dir.to_path_buf()),
+        ".." => Ok(dir.parent().unwrap_or(Path::new("")).to_path_buf()),
+        name if name.contains('/') => Err(P9Error::Errno(errno::EINVAL)),
+        name => Ok(dir.join(name)),
+    }
+}
+
+fn name_path(name: &CStr) -> &Path {
+    Path::new(OsStr::from_bytes(name.to_bytes()))
+}
+
+fn child_qid(dir: &File, name: &CStr) -> Result<Qid, P9Error> {
+    let node = host_fs::open_beneath(dir, name_path(name))?;
+
+    Ok(qid(&node.metadata()?))
+}
+
+impl P9Server {
+    pub fn new(
+        root_path: PathBuf,
+        read_only: bool,
+        uid_map: Vec<IdMap>,
+        gid_map: Vec<IdMap>,
+    ) -> io::Result<Self> {
+        Ok(P9Server {
+            root: host_fs::open_root(&root_path)?,
+            root_path,
+            read_only,
+            uid_map,
+            gid_map,
+            msize: MAX_MSIZE,
+            fids: Default::default(),
+        })
+    }
+
+    /// Handle a T-message and return the R-message.
+    pub fn handle_message(&mut self, request: &[u8]) -> Vec<u8> {
+        let mut reader = WireReader::new(request);
+
+        let header = (|| {
+            let _size = reader.read_u32()?;
+            let r#type = reader.read_u8()?;
+            let tag = reader.read_u16()?;
+            Ok::<_, P9Error>((r#type, tag))
+        })();
+
+        let mut body = WireWriter::default();
+
+        let (r#type, tag) = match header {
+            Ok((r#type, tag)) => {
+                let result = match P9MessageType::from_repr(r#type) {
+                    Some(r#type) => self.dispatch(r#type, &mut reader, &mut body),
+                    None => Err(P9Error::Errno(errno::ENOSYS)),
+                };
+
+                match result {
+                    Ok(r#type) => (r#type, tag),
+                    Err(err) => {
+                        debug!(message_type = r#type, %err, "9p request failed");
+                        body = WireWriter::default();
+                        body.write_u32(err.errno());
+                        (P9MessageType::Rlerror, tag)
+                    }
+                }
+            }
+            Err(err) => {
+                body.write_u32(err.errno());
+                (P9MessageType::Rlerror, P9_NOTAG)
+            }
+        };
+
+        let mut response = WireWriter::default();
+        response.write_u32((P9_HEADER_LEN + body.len()) as u32);
+        response.write_u8(r#type as u8);
+        response.write_u16(tag);
+        response.write_bytes(&body.into_inner());
+
+        response.into_inner()
+    }
+
+    fn dispatch(
+        &mut self,
+        r#type: P9MessageType,
+        reader: &mut WireReader,
+        writer: &mut WireWriter,
+    ) -> Result<P9MessageType, P9Error> {
+        match r#type {
+            P9MessageType::Tversion => {
+                self.version(reader, writer)?;
+                Ok(P9MessageType::Rversion)
+            }
+            P9MessageType::Tattach => {
+                self.attach(reader, writer)?;
+                Ok(P9MessageType::Rattach)
+            }
+            P9MessageType::Tflush => Ok(P9MessageType::Rflush),
+            P9MessageType::Twalk => {
+                self.walk(reader, writer)?;
+                Ok(P9MessageType::Rwalk)
+            }
+            P9MessageType::Tlopen => {
+                self.lopen(reader, writer)?;
+                Ok(P9MessageType::Rlopen)
+            }
+            P9MessageType::Tlcreate => {
+                self.lcreate(reader, writer)?;
+                Ok(P9MessageType::Rlcreate)
+            }
+            P9MessageType::Tsymlink => {
+                self.symlink(reader, writer)?;
+                Ok(P9MessageType::Rsymlink)
+            }
+            P9MessageType::Tmknod => {
+                self.mknod(reader, writer)?;
+                Ok(P9MessageType::Rmknod)
+            }
+            P9MessageType::Trename => {
+                self.rename(reader)?;
+                Ok(P9MessageType::Rrename)
+            }
+            P9MessageType::Treadlink => {
+                self.readlink(reader, writer)?;
+                Ok(P9MessageType::Rreadlink)
+            }
+            P9MessageType::Tgetattr => {
+                self.getattr(reader, writer)?;
+                Ok(P9MessageType::Rgetattr)
+            }
+            P9MessageType::Tsetattr => {
+                self.setattr(reader)?;
+                Ok(P9MessageType::Rsetattr)
+            }
+            P9MessageType::Tstatfs => {
+                self.statfs(reader, writer)?;
+                Ok(P9MessageType::Rstatfs)
+            }
+            P9MessageType::Treaddir => {
+                self.readdir(reader, writer)?;
+                Ok(P9MessageType::Rreaddir)
+            }
+            P9MessageType::Tfsync => {
+                self.fsync(reader)?;
+                Ok(P9MessageType::Rfsync)
+            }
+            P9MessageType::Tlock => {
+                let _fid = self.get_fid(reader.read_u32()?)?;
+                writer.write_u8(P9_LOCK_SUCCESS);
+                Ok(P9MessageType::Rlock)
+            }
+            P9MessageType::Tgetlock => {
+                self.getlock(reader, writer)?;
+                Ok(P9MessageType::Rgetlock)
+            }
+            P9MessageType::Tlink => {
+                self.link(reader)?;
+                Ok(P9MessageType::Rlink)
+            }
+            P9MessageType::Tmkdir => {
+                self.mkdir(reader, writer)?;
+                Ok(P9MessageType::Rmkdir)
+            }
+            P9MessageType::Trenameat => {
+                self.renameat(reader)?;
+                Ok(P9MessageType::Rrenameat)
+            }
+            P9MessageType::Tunlinkat => {
+                self.unlinkat(reader)?;
+                Ok(P9MessageType::Runlinkat)
+            }
+            P9MessageType::Tread => {
+                self.read(reader, writer)?;
+                Ok(P9MessageType::Rread)
+            }
+            P9MessageType::Twrite => {
+                self.write(reader, writer)?;
+                Ok(P9MessageType::Rwrite)
+            }
+            P9MessageType::Tclunk => {
+                self.fids
+                    .remove(&reader.read_u32()?)
+                    .ok_or(P9Error::Errno(errno::EBADF))?;
+                Ok(P9MessageType::Rclunk)
+            }
+            P9MessageType::Tremove => {
+                self.remove(reader)?;
+                Ok(P9MessageType::Rremove)
+            }
+            P9MessageType::Tauth | P9MessageType::Txattrwalk | P9MessageType::Txattrcreate => {
+                Err(P9Error::Errno(errno::EOPNOTSUPP))
+            }
+            _ => Err(P9Error::Errno(errno::ENOSYS)),
+        }
+    }
+
+    fn get_fid(&self, fid: u32) -> Result<&Fid, P9Error> {
+        self.fids.get(&fid).ok_or(P9Error::Errno(errno::EBADF))
+    }
+
+    fn get_fid_mut(&mut self, fid: u32) -> Result<&mut Fid, P9Error> {
+        self.fids.get_mut(&fid).ok_or(P9Error::Errno(errno::EBADF))
+    }
+
+    fn ensure_writable(&self) -> Result<(), P9Error> {
+        if self.read_only {
+            return Err(P9Error::Errno(errno::EROFS));
+        }
+
+        Ok(())
+    }
+
+    /// The directory of `dfid` and a new name in it
+    fn child(&self, dfid: u32, name: &str) -> Result<(File, CString, PathBuf), P9Error> {
+        let dir = self.get_fid(dfid)?;
+
+        match name {
+            "" | "." | ".." => Err(P9Error::Errno(errno::EINVAL)),
+            name if name.contains('/') => Err(P9Error::Errno(errno::EINVAL)),
+            name => Ok((
+                dir.node.try_clone()?,
+                CString::new(name).map_err(|_| P9Error::Errno(errno::EINVAL))?,
+                dir.path.join(name),
+            )),
+        }
+    }
+
+    /// Name the node at `path` by its parent directory for the *at calls.
+    fn at(&self, path: &Path) -> Result<At, P9Error> {
+        match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) => Ok(At {
+                dir: Some(host_fs::open_beneath(&self.root, parent)?),
+                name: host_fs::cstring(Path::new(name))?,
+            }),
+            _ => Ok(At {
+                dir: None,
+                name: host_fs::cstring(&self.root_path)?,
+            }),
+        }
+    }
+
+    /// Give a newly created node the host ids the guest ids map to.
+    fn chown(&self, dir: &File, name: &CStr, uid: u32, gid: u32) -> Result<(), P9Error> {
+        if self.uid_map.is_empty() && self.gid_map.is_empty() {
+            return Ok(());
+        }
+
+        let uid = guest_to_host(&self.uid_map, uid).ok_or(P9Error::Errno(errno::EPERM))?;
+        let gid = guest_to_host(&self.gid_map, gid).ok_or(P9Error::Errno(errno::EPERM))?;
+
+        let metadata = host_fs::open_beneath(dir, name_path(name))?.metadata()?;
+        if metadata.uid() != uid || metadata.gid() != gid {
+            let at = At {
+                dir: Some(dir.try_clone()?),
+                name: name.to_owned(),
+            };
+            host_fs::chown(&at, Some(uid), Some(gid))?;
+        }
+
+        Ok(())
+    }
+
+    fn version(&mut self, reader: &mut WireReader, writer: &mut WireWriter) -> Result<(), P9Error> {
+        let msize = reader.read_u32()?;
+        let version = reader.read_string()?;
+
+        // Rread and Rreaddir must fit their header
+        if msize < P9_IOHDR_LEN as u32 {
+            return Err(P9Error::Errno(errno::EINVAL));
+        }
+
+        self.fids.clear();
+        self.msize = msize.min(MAX_MSIZE);
+
+        writer.write_u32(self.msize);
+        if version == P9_VERSION {
+            writer.write_string(P9_VERSION);
+        } else {
+            writer.write_string("unknown");
+        }
+
+        Ok(())
+    }
+
+    fn attach(&mut self, reader: &mut WireReader, writer: &mut WireWriter) -> Result<(), P9Error> {
+        let fid = reader.read_u32()?;
+        let _afid = reader.read_u32()?;
+        let _uname = reader.read_string()?;
+        let _aname = reader.read_string()?;
+        let n_uname = reader.read_u32()?;
+
+        if self.fids.contains_key(&fid) {
+            return Err(P9Error::Errno(errno::EBADF));
+        }
+
+        let node = self.root.try_clone()?;
+        let metadata = node.metadata()?;
+
+        self.fids.insert(
+            fid,
+            Fid {
+                path: PathBuf::new(),
+                node,
+                uid: n_uname,
+                file: None,
+                dir_entries: None,
+            },
+        );
+
+        writer.write_qid(&qid(&metadata));
+
+        Ok(())
+    }
+
+    fn walk(&mut self, reader: &mut WireReader, writer: &mut WireWriter) -> Result<(), P9Error> {
+        let fid = reader.read_u32()?;
+        let newfid = reader.read_u32()?;
+        let nwname = reader.read_u16()?;
+
+        let (mut path, mut node, uid) = {
+            let fid = self.get_fid(fid)?;
+            (fid.path.clone(), fid.node.try_clone()?, fid.uid)
+        };
+
+        if newfid != fid && self.fids.contains_key(&newfid) {
+            return Err(P9Error::Errno(errno::EBADF));
+        }
+
+        let mut qids = vec![];
+        for i in 0..nwname {
+            let name = reader.read_string()?;
+
+            let next_path = join(&path, &name)?;
+            let next = match name.as_str() {
+                // Resolved from the root so `..` never leaves the shared directory
+                "." | ".." => host_fs::open_beneath(&self.root, &next_path),
+                name => host_fs::open_beneath(&node, Path::new(name)),
+            };
+            let (next, metadata) = match next.and_then(|next| {
+                let metadata = next.metadata()?;
+                Ok((next, metadata))
+            }) {
+                Ok(next) => next,
+                // Only the first element failing is an error
+                Err(err) if i == 0 => return Err(err.into()),
+                Err(_) => break,
+            };
+
+            // Never walk through a symlink, the guest resolves it with Treadlink
+            if i + 1 < nwname && !metadata.is_dir() {
+                if qids.is_empty() {
+                    return Err(P9Error::Errno(errno::ENOTDIR));
+                }
+                qids.push(qid(&metadata));
+                break;
+            }
+
+            qids.push(qid(&metadata));
+            path = next_path;
+            node = next;
+        }
+
+        if qids.len() == nwname as usize {
+            self.fids.insert(
+                newfid,
+                Fid {
+                    path,
+                    node,
+                    uid,
+                    file: None,
+                    dir_entries: None,
+                },
+            );
+        }
+
+        writer.write_u16(qids.len() as u16);
+        for qid in &qids {
+            writer.write_qid(qid);
+        }
+
+        Ok(())
+    }
+
+    fn lopen(&mut self, reader: &mut WireReader, writer: &mut WireWriter) -> Result<(), P9Error> {
+        let fid = reader.read_u32()?;
+        let flags = reader.read_u32()?;
+
+        if flags & L_O_ACCMODE != 0 || flags & (L_O_TRUNC | L_O_APPEND) != 0 {
+            self.ensure_writable()?;
+        }
+
+        let msize = self.msize;
+        let fid = self.get_fid_mut(fid)?;
+        if fid.file.is_some() {
+            return Err(P9Error::Errno(errno::EBADF));
+        }
+
+        // The guest resolves symlinks by itself
+        let metadata = fid.node.metadata()?;
+        if metadata.is_symlink() {
+            return Err(P9Error::Errno(errno::ELOOP));
+        }
+        if !metadata.is_dir() {
+            fid.file = Some(host_fs::reopen(&fid.node, open_flags(flags))?);
+        }
+
+        writer.write_qid(&qid(&metadata));
+        writer.write_u32(msize - P9_IOHDR_LEN as u32);
+
+        Ok(())
+    }
+
+    fn lcreate(&mut self, reader: &mut WireReader, writer: &mut WireWriter) -> Result<(), P9Error> {
+        let dfid = reader.read_u32()?;
+        let name = reader.read_string()?;
+        let flags = reader.read_u32()?;
+        let mode = reader.read_u32()?;
+        let gid = reader.read_u32()?;
+
+        self.ensure_writable()?;
+
+        let (dir, name, path) = self.child(dfid, &name)?;
+        let uid = self.get_fid(dfid)?.uid;
+
+        let mut host_flags = open_flags(flags);
+        if flags & L_O_ACCMODE == 0 {
+            // The file must be writable to be created
+            host_flags |= libc::O_RDWR;
+        }
+        if flags & L_O_EXCL != 0 {
+            host_flags |= libc::O_EXCL;
+        }
+
+        let file = host_fs::create(&dir, &name, host_flags, mode & 0o7777)?;
+        self.chown(&dir, &name, uid, gid)?;
+
+        let node = host_fs::open_beneath(&dir, name_path(&name))?;
+        let metadata = file.metadata()?;
+        let msize = self.msize;
+
+        let fid = self.get_fid_mut(dfid)?;
+        fid.path = path;
+        fid.node = node;
+        fid.file = Some(file);
+        fid.dir_entries = None;
+
+        writer.write_qid(&qid(&metadata));
+        writer.write_u32(msize - P9_IOHDR_LEN as u32);
+
+        Ok(())
+    }
+
+    fn symlink(&mut self, reader: &mut WireReader, writer: &mut WireWriter) -> Result<(), P9Error> {
+        let dfid = reader.read_u32()?;
+        let name = reader.read_string()?;
+        let target = reader.read_string()?;
+        let gid = reader.read_u32()?;
+
+        self.ensure_writable()?;
+
+        let (dir, name, _) = self.child(dfid, &name)?;
+        let uid = self.get_fid(dfid)?.uid;
+
+        let target = CString::new(target).map_err(|_| P9Error::Errno(errno::EINVAL))?;
+        host_fs::symlink(&target, &dir, &name)?;
+        self.chown(&dir, &name, uid, gid)?;
+
+        writer.write_qid(&child_qid(&dir, &name)?);
+
+        Ok(())
+    }
+
+    fn mknod(&mut self, reader: &mut WireReader, writer: &mut WireWriter) -> Result<(), P9Error> {
+        let dfid = reader.read_u32()?;
+        let name = reader.read_string()?;
+        let mode = reader.read_u32()?;
+        let major = reader.read_u32()?;
+        let minor = reader.read_u32()?;
+        let gid = reader.read_u32()?;
+
+        self.ensure_writable()?;
+
+        // Device nodes would give the guest access to host devices
+        if major != 0 || minor != 0 {
+            return Err(P9Error::Errno(errno::EPERM));
+        }
+
+        let (dir, name, _) = self.child(dfid, &name)?;
+        let uid = self.get_fid(dfid)?.uid;
+
+        host_fs::mknod(&dir, &name, mode)?;
+        self.chown(&dir, &name, uid, gid)?;
+
+        writer.write_qid(&child_qid(&dir, &name)?);
+
+        Ok(())
+    }
+
+    fn rename(&mut self, reader: &mut WireReader) -> Result<(), P9Error> {
+        let fid = reader.read_u32()?;
+        let dfid = reader.read_u32()?;
+        let name = reader.read_string()?;
+
+        self.ensure_writable()?;
+
+        let (dir, name, new_path) = self.child(dfid, &name)?;
+
+        let old_path = &self.get_fid(fid)?.path;
+        if old_path.as_os_str().is_empty() {
+            return Err(P9Error::Errno(errno::EACCES));
+        }
+        let old = self.at(old_path)?;
+
+        host_fs::rename(old.dirfd(), &old.name, &dir, &name)?;
+        self.get_fid_mut(fid)?.path = new_path;
+
+        Ok(())
+    }
+
+    fn readlink(
+        &mut self,
+        reader: &mut WireReader,
+        writer: &mut WireWriter,
+    ) -> Result<(), P9Error> {
+        let fid = self.get_fid(reader.read_u32()?)?;
+
+        let target = host_fs::readlink(&self.at(&fid.path)?)?;
+        let target = target.to_str().ok_or(P9Error::Errno(errno::EINVAL))?;
+
+        writer.write_string(target);
+
+        Ok(())
+    }
+
+    fn getattr(&mut self, reader: &mut WireReader, writer: &mut WireWriter) -> Result<(), P9Error> {
+        let fid = self.get_fid(reader.read_u32()?)?;
+        let _request_mask = reader.read_u64()?;
+
+        let metadata = fid.node.metadata()?;
+
+        writer.write_u64(P9_GETATTR_BASIC);
+        writer.write_qid(&qid(&metadata));
+        writer.write_u32(metadata.mode());
+        writer.write_u32(host_to_guest(&self.uid_map, metadata.uid()));
+        writer.write_u32(host_to_guest(&self.gid_map, metadata.gid()));
+        writer.write_u64(metadata.nlink());
+        writer.write_u64(metadata.rdev());
+        writer.write_u64(metadata.size());
+        writer.write_u64(metadata.blksize());
+        writer.write_u64(metadata.blocks());
+        writer.write_u64(metadata.atime() as u64);
+        writer.write_u64(metadata.atime_nsec() as u64);
+        writer.write_u64(metadata.mtime() as u64);
+        writer.write_u64(metadata.mtime_nsec() as u64);
+        writer.write_u64(metadata.ctime() as u64);
+        writer.write_u64(metadata.ctime_nsec() as u64);
+        // btime, gen and data_version are not reported
+        writer.write_u64(0);
+        writer.write_u64(0);
+        writer.write_u64(0);
+        writer.write_u64(0);
+
+        Ok(())
+    }
+
+    fn setattr(&mut self, reader: &mut WireReader) -> Result<(), P9Error> {
+        let fid = reader.read_u32()?;
+        let valid = reader.read_u32()?;
+        let mode = reader.read_u32()?;
+        let uid = reader.read_u32()?;
+        let gid = reader.read_u32()?;
+        let size = reader.read_u64()?;
+        let atime_sec = reader.read_u64()?;
+        let atime_nsec = reader.read_u64()?;
+        let mtime_sec = reader.read_u64()?;
+        let mtime_nsec = reader.read_u64()?;
+
+        self.ensure_writable()?;
+
+        let fid = self.get_fid(fid)?;
+        let at = self.at(&fid.path)?;
+
+        if valid & P9_SETATTR_MODE != 0 {
+            host_fs::chmod(&at, mode & 0o7777)?;
+        }
+
+        if valid & (P9_SETATTR_UID | P9_SETATTR_GID) != 0 {
+            let uid = if valid & P9_SETATTR_UID != 0 {
+                Some(guest_to_host(&self.uid_map, uid).ok_or(P9Error::Errno(errno::EPERM))?)
+            } else {
+                None
+            };
+            let gid = if valid & P9_SETATTR_GID != 0 {
+                Some(guest_to_host(&self.gid_map, gid).ok_or(P9Error::Errno(errno::EPERM))?)
+            } else {
+                None
+            };
+
+            host_fs::chown(&at, uid, gid)?;
+        }
+
+        if valid & P9_SETATTR_SIZE != 0 {
+            if fid.node.metadata()?.is_symlink() {
+                return Err(P9Error::Errno(errno::ELOOP));
+            }
+            host_fs::reopen(&fid.node, libc::O_WRONLY)?.set_len(size)?;
+        }
+
+        if valid & (P9_SETATTR_ATIME | P9_SETATTR_MTIME) != 0 {
+            let timespec = |flag: u32, set: u32, sec: u64, nsec: u64| {
+                if valid & flag == 0 {
+                    libc::timespec {
+                        tv_sec: 0,
+                        tv_nsec: libc::UTIME_OMIT,
+                    }
+                } else if valid & set == 0 {
+                    libc::timespec {
+                        tv_sec: 0,
+                        tv_nsec: libc::UTIME_NOW,
+                    }
+                } else {
+                    libc::timespec {
+                        tv_sec: sec as libc::time_t,
+                        tv_nsec: nsec as libc::c_long,
+                    }
+                }
+            };
+
+            let times = [
+                timespec(
+                    P9_SETATTR_ATIME,
+                    P9_SETATTR_ATIME_SET,
+                    atime_sec,
+                    atime_nsec,
+                ),
+                timespec(
+                    P9_SETATTR_MTIME,
+                    P9_SETATTR_MTIME_SET,
+                    mtime_sec,
+                    mtime_nsec,
+                ),
+            ];
+
+            host_fs::utimens(&at, &times)?;
+        }
+
+        Ok(())
+    }
+
+    fn statfs(&mut self, reader: &mut WireReader, writer: &mut WireWriter) -> Result<(), P9Error> {
+        let fid = self.get_fid(reader.read_u32()?)?;
+
+        let stat = host_fs::statvfs(&fid.node)?;
+
+        writer.write_u32(V9FS_MAGIC);
+        writer.write_u32(stat.f_bsize as u32);
+        writer.write_u64(stat.f_blocks as u64);
+        writer.write_u64(stat.f_bfree as u64);
+        writer.write_u64(stat.f_bavail as u64);
+        writer.write_u64(stat.f_files as u64);
+        writer.write_u64(stat.f_ffree as u64);
+        writer.write_u64(stat.f_fsid as u64);
+        writer.write_u32(stat.f_namemax as u32);
+
+        Ok(())
+    }
+
+    fn read_dir_entries(&self, fid: &Fid) -> Result<Vec<DirEntry>, P9Error> {
+        let mut entries = vec![];
+
+        // The `..` of the root is the root itself
+        let parent = match fid.path.parent() {
+            Some(parent) => host_fs::open_beneath(&self.root, parent)?,
+            None => fid.node.try_clone()?,
+        };
+        for (name, node) in [(".", &fid.node), ("..", &parent)] {
+            entries.push(DirEntry {
+                qid: qid(&node.metadata()?),
+                r#type: DT_DIR,
+                name: name.to_string(),
+            });
+        }
+
+        for name in host_fs::read_dir(&fid.node)? {
+            // Removed since it was listed
+            let Ok(metadata) =
+                host_fs::open_beneath(&fid.node, Path::new(&name)).and_then(|node| node.metadata())
+            else {
+                continue;
+            };
+            let Ok(name) = name.into_string() else {
+                continue;
+            };
+
+            entries.push(DirEntry {
+                qid: qid(&metadata),
+                r#type: dirent_type(metadata.file_type()),
+                name,
+            });
+        }
+
+        Ok(entries)
+    }
+
+    fn readdir(&mut self, reader: &mut WireReader, writer: &mut WireWriter) -> Result<(), P9Error> {
+        let fid = reader.read_u32()?;
+        let offset = reader.read_u64()?;
+        let count = reader.read_u32()?;
+
+        let count = count.min(self.msize - P9_IOHDR_LEN as u32) as usize;
+
+        if offset == 0 || self.get_fid(fid)?.dir_entries.is_none() {
+            let entries = self.read_dir_entries(self.get_fid(fid)?)?;
+            self.get_fid_mut(fid)?.dir_entries = Some(entries);
+        }
+
+        let entries = self.get_fid(fid)?.dir_entries.as_ref().unwrap();
+
+        let mut data = WireWriter::default();
+        for (i, entry) in entries.iter().enumerate().skip(offset as usize) {
+            // qid[13] offset[8] type[1] name[s]
+            let len = 13 + 8 + 1 + 2 + entry.name.len();
+            if data.len() + len > count {
+                break;
+            }
+
+            data.write_qid(&entry.qid);
+            data.write_u64(i as u64 + 1);
+            data.write_u8(entry.r#type);
+            data.write_string(&entry.name);
+        }
+
+        writer.write_u32(data.len() as u32);
+        writer.write_bytes(&data.into_inner());
+
+        Ok(())
+    }
+
+    fn fsync(&mut self, reader: &mut WireReader) -> Result<(), P9Error> {
+        let fid = self.get_fid(reader.read_u32()?)?;
+        let datasync = reader.read_u32()?;
+
+        if let Some(file) = &fid.file {
+            if datasync != 0 {
+                file.sync_data()?;
+            } else {
+                file.sync_all()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn getlock(&mut self, reader: &mut WireReader, writer: &mut WireWriter) -> Result<(), P9Error> {
+        let _fid = self.get_fid(reader.read_u32()?)?;
+        let _type = reader.read_u8()?;
+        let start = reader.read_u64()?;
+        let length = reader.read_u64()?;
+        let proc_id = reader.read_u32()?;
+        let client_id = reader.read_string()?;
+
+        // Locks are not shared with the host, so nothing ever conflicts
+        writer.write_u8(P9_LOCK_TYPE_UNLCK);
+        writer.write_u64(start);
+        writer.write_u64(length);
+        writer.write_u32(proc_id);
+        writer.write_string(&client_id);
+
+        Ok(())
+    }
+
+    fn link(&mut self, reader: &mut WireReader) -> Result<(), P9Error> {
+        let dfid = reader.read_u32()?;
+        let fid = reader.read_u32()?;
+        let name = reader.read_string()?;
+
+        self.ensure_writable()?;
+
+        let (dir, name, _) = self.child(dfid, &name)?;
+        let old = self.at(&self.get_fid(fid)?.path)?;
+
+        host_fs::link(&old, &dir, &name)?;
+
+        Ok(())
+    }
+
+    fn mkdir(&mut self, reader: &mut WireReader, writer: &mut WireWriter) -> Result<(), P9Error> {
+        let dfid = reader.read_u32()?;
+        let name = reader.read_string()?;
+        let mode = reader.read_u32()?;
+        let gid = reader.read_u32()?;
+
+        self.ensure_writable()?;
+
+        let (dir, name, _) = self.child(dfid, &name)?;
+        let uid = self.get_fid(dfid)?.uid;
+
+        host_fs::mkdir(&dir, &name, mode & 0o7777)?;
+        self.chown(&dir, &name, uid, gid)?;
+
+        writer.write_qid(&child_qid(&dir, &name)?);
+
+        Ok(())
+    }
+
+    fn renameat(&mut self, reader: &mut WireReader) -> Result<(), P9Error> {
+        let olddirfid = reader.read_u32()?;
+        let oldname = reader.read_string()?;
+        let newdirfid = reader.read_u32()?;
+        let newname = reader.read_string()?;
+
+        self.ensure_writable()?;
+
+        let (old_dir, oldname, _) = self.child(olddirfid, &oldname)?;
+        let (new_dir, newname, _) = self.child(newdirfid, &newname)?;
+
+        host_fs::rename(old_dir.as_raw_fd(), &oldname, &new_dir, &newname)?;
+
+        Ok(())
+    }
+
+    fn unlinkat(&mut self, reader: &mut WireReader) -> Result<(), P9Error> {
+        let dirfid = reader.read_u32()?;
+        let name = reader.read_string()?;
+        let flags = reader.read_u32()?;
+
+        self.ensure_writable()?;
+
+        let (dir, name, _) = self.child(dirfid, &name)?;
+
+        let flags = if flags & P9_AT_REMOVEDIR != 0 {
+            libc::AT_REMOVEDIR
+        } else {
+            0
+        };
+        host_fs::unlink(dir.as_raw_fd(), &name, flags)?;
+
+        Ok(())
+    }
+
+    fn read(&mut self, reader: &mut WireReader, writer: &mut WireWriter) -> Result<(), P9Error> {
+        let fid = reader.read_u32()?;
+        let offset = reader.read_u64()?;
+        let count = reader.read_u32()?;
+
+        let count = count.min(self.msize - P9_IOHDR_LEN as u32) as usize;
+        let file = self.get_fid(fid)?.file()?;
+
+        let mut buf = vec![0; count];
+        let len = file.read_at(&mut buf, offset)?;
+
+        writer.write_u32(len as u32);
+        writer.write_bytes(&buf[..len]);
+
+        Ok(())
+    }
+
+    fn write(&mut self, reader: &mut WireReader, writer: &mut WireWriter) -> Result<(), P9Error> {
+        let fid = reader.read_u32()?;
+        let offset = reader.read_u64()?;
+        let count = reader.read_u32()?;
+        let data = reader.read_bytes(count as usize)?;
+
+        self.ensure_writable()?;
+
+        let file = self.get_fid(fid)?.file()?;
+        let len = file.write_at(data, offset)?;
+
+        writer.write_u32(len as u32);
+
+        Ok(())
+    }
+
+    fn remove(&mut self, reader: &mut WireReader) -> Result<(), P9Error> {
+        let fid = reader.read_u32()?;
+
+        // The fid is clunked even if the remove fails
+        let fid = self.fids.remove(&fid).ok_or(P9Error::Errno(errno::EBADF))?;
+
+        self.ensure_writable()?;
+
+        if fid.path.as_os_str().is_empty() {
+            return Err(P9Error::Errno(errno::EACCES));
+        }
+
+        let at = self.at(&fid.path)?;
+        let flags = if fid.node.metadata()?.is_dir() {
+            libc::AT_REMOVEDIR
+        } else {
+            0
+        };
+        host_fs::unlink(at.dirfd(), &at.name, flags)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::symlink;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    const TAG: u16 = 1;
+
+    fn request(r#type: P9MessageType, body: WireWriter) -> Vec<u8> {
+        let mut request = WireWriter::default();
+        request.write_u32((P9_HEADER_LEN + body.len()) as u32);
+        request.write_u8(r#type as u8);
+        request.write_u16(TAG);
+        request.write_bytes(&body.into_inner());
+
+        request.into_inner()
+    }
+
+    /// Returns the type and the body of the response
+    fn call(server: &mut P9Server, r#type: P9MessageType, body: WireWriter) -> (u8, Vec<u8>) {
+        let response = server.handle_message(&request(r#type, body));
+
+        let mut reader = WireReader::new(&response);
+        assert_eq!(reader.read_u32().unwrap() as usize, response.len());
+        let r#type = reader.read_u8().unwrap();
+        assert_eq!(reader.read_u16().unwrap(), TAG);
+
+        (r#type, response[P9_HEADER_LEN..].to_vec())
+    }
+
+    fn assert_lerror(response: (u8, Vec<u8>), errno: u32) {
+        assert_eq!(response.0, P9MessageType::Rlerror as u8);
+        assert_eq!(WireReader::new(&response.1).read_u32().unwrap(), errno);
+    }
+
+    fn version(server: &mut P9Server, msize: u32, version: &str) -> (u8, Vec<u8>) {
+        let mut body = WireWriter::default();
+        body.write_u32(msize);
+        body.write_string(version);
+
+        call(server, P9MessageType::Tversion, body)
+    }
+
+    fn walk(server: &mut P9Server, fid: u32, newfid: u32, names: &[&str]) -> (u8, Vec<u8>) {
+        let mut body = WireWriter::default();
+        body.write_u32(fid);
+        body.write_u32(newfid);
+        body.write_u16(names.len() as u16);
+        for name in names {
+            body.write_string(name);
+        }
+
+        call(server, P9MessageType::Twalk, body)
+    }
+
+    /// The share has `dir/file` and a symlink to the host root
+    fn server() -> (P9Server, TempDir) {
+        let share = TempDir::new().unwrap();
+        fs::create_dir(share.path().join("dir")).unwrap();
+        fs::write(share.path().join("dir/file"), b"hello").unwrap();
+        symlink("/", share.path().join("escape")).unwrap();
+
+        let mut server = P9Server::new(share.path().to_path_buf(), false, vec![], vec![]).unwrap();
+        assert_eq!(
+            version(&mut server, 8192, P9_VERSION).0,
+            P9MessageType::Rversion as u8
+        );
+
+        let mut body = WireWriter::default();
+        body.write_u32(0);
+        body.write_u32(u32::MAX);
+        body.write_string("root");
+        body.write_string("");
+        body.write_u32(0);
+        assert_eq!(
+            call(&mut server, P9MessageType::Tattach, body).0,
+            P9MessageType::Rattach as u8
+        );
+
+        (server, share)
+    }
+
+    #[test]
+    fn test_version() {
+        let (mut server, _share) = server();
+
+        let (r#type, body) = version(&mut server, 8192, P9_VERSION);
+        assert_eq!(r#type, P9MessageType::Rversion as u8);
+        let mut reader = WireReader::new(&body);
+        assert_eq!(reader.read_u32().unwrap(), 8192);
+        assert_eq!(reader.read_string().unwrap(), P9_VERSION);
+
+        let (_, body) = version(&mut server, u32::MAX, "9P2000.u");
+        let mut reader = WireReader::new(&body);
+        assert_eq!(reader.read_u32().unwrap(), MAX_MSIZE);
+        assert_eq!(reader.read_string().unwrap(), "unknown");
+
+        // Even an empty Rread would not fit
+        assert_lerror(
+            version(&mut server, P9_IOHDR_LEN as u32 - 1, P9_VERSION),
+            errno::EINVAL,
+        );
+    }
+
+    #[test]
+    fn test_walk() {
+        let (mut server, share) = server();
+
+        let (r#type, body) = walk(&mut server, 0, 1, &["dir", "file"]);
+        assert_eq!(r#type, P9MessageType::Rwalk as u8);
+        let mut reader = WireReader::new(&body);
+        assert_eq!(reader.read_u16().unwrap(), 2);
+        assert_eq!(reader.read_u8().unwrap(), P9_QTDIR);
+        let _version = reader.read_u32().unwrap();
+        assert_eq!(
+            reader.read_u64().unwrap(),
+            fs::metadata(share.path().join("dir")).unwrap().ino()
+        );
+        assert_eq!(reader.read_u8().unwrap(), P9_QTFILE);
+
+        // `..` stops at the root of the share
+        let (_, body) = walk(&mut server, 0, 2, &["dir", "..", "..", ".."]);
+        let mut reader = WireReader::new(&body);
+        assert_eq!(reader.read_u16().unwrap(), 4);
+        let root = share.path().metadata().unwrap().ino();
+        for _ in 0..3 {
+            reader.read_bytes(13).unwrap();
+        }
+        reader.read_bytes(5).unwrap();
+        assert_eq!(reader.read_u64().unwrap(), root);
+
+        // The symlink is never followed
+        assert_lerror(walk(&mut server, 0, 3, &["escape", "etc"]), errno::ENOTDIR);
+        assert_lerror(walk(&mut server, 0, 3, &["dir/file"]), errno::EINVAL);
+        assert_lerror(walk(&mut server, 0, 3, &["missing"]), errno::ENOENT);
+
+        // Only the walked prefix is returned and newfid is not created
+        let (_, body) = walk(&mut server, 0, 3, &["dir", "missing"]);
+        assert_eq!(WireReader::new(&body).read_u16().unwrap(), 1);
+        assert_lerror(walk(&mut server, 3, 4, &[]), errno::EBADF);
+    }
+
+    #[test]
+    fn test_read() {
+        let (mut server, _share) = server();
+        walk(&mut server, 0, 1, &["dir", "file"]);
+        walk(&mut server, 0, 2, &["escape"]);
+
+        let mut body = WireWriter::default();
+        body.write_u32(1);
+        body.write_u32(0);
+        assert_eq!(
+            call(&mut server, P9MessageType::Tlopen, body).0,
+            P9MessageType::Rlopen as u8
+        );
+
+        let mut body = WireWriter::default();
+        body.write_u32(1);
+        body.write_u64(1);
+        body.write_u32(u32::MAX);
+        let (r#type, body) = call(&mut server, P9MessageType::Tread, body);
+        assert_eq!(r#type, P9MessageType::Rread as u8);
+        let mut reader = WireReader::new(&body);
+        let len = reader.read_u32().unwrap();
+        assert_eq!(reader.read_bytes(len as usize).unwrap(), b"ello");
+
+        // A symlink is opened by the guest after it resolves it
+        let mut body = WireWriter::default();
+        body.write_u32(2);
+        body.write_u32(0);
+        assert_lerror(call(&mut server, P9MessageType::Tlopen, body), errno::ELOOP);
+
+        // The fid is not opened
+        let mut body = WireWriter::default();
+        body.write_u32(0);
+        body.write_u64(0);
+        body.write_u32(16);
+        assert_lerror(call(&mut server, P9MessageType::Tread, body), errno::EBADF);
+    }
+}
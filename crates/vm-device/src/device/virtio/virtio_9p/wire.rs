@@ -0,0 +1,101 @@
+use crate::device::virtio::virtio_9p::error::P9Error;
+use crate::device::virtio::virtio_9p::error::errno;
+
+pub struct Qid {
+    pub r#type: u8,
+    pub version: u32,
+    pub path: u64,
+}
+
+pub struct WireReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> WireReader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        WireReader { buf, pos: 0 }
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], P9Error> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.buf.len())
+            .ok_or(P9Error::Errno(errno::EPROTO))?;
+
+        let bytes = &self.buf[self.pos..end];
+        self.pos = end;
+
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, P9Error> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, P9Error> {
+        Ok(u16::from_le_bytes(self.read_bytes(2)?.try_into().unwrap()))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, P9Error> {
+        Ok(u32::from_le_bytes(self.read_bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, P9Error> {
+        Ok(u64::from_le_bytes(self.read_bytes(8)?.try_into().unwrap()))
+    }
+
+    pub fn read_string(&mut self) -> Result<String, P9Error> {
+        let len = self.read_u16()?;
+        let bytes = self.read_bytes(len as usize)?;
+
+        String::from_utf8(bytes.to_vec()).map_err(|_| P9Error::Errno(errno::EINVAL))
+    }
+}
+
+#[derive(Default)]
+pub struct WireWriter {
+    buf: Vec<u8>,
+}
+
+impl WireWriter {
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    pub fn write_u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    pub fn write_u16(&mut self, v: u16) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, v: u32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, v: u64) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn write_string(&mut self, s: &str) {
+        self.write_u16(s.len() as u16);
+        self.buf.extend_from_slice(s.as_bytes());
+    }
+
+    pub fn write_qid(&mut self, qid: &Qid) {
+        self.write_u8(qid.r#type);
+        self.write_u32(qid.version);
+        self.write_u64(qid.path);
+    }
+}
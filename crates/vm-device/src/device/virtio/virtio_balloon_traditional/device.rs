@@ -1,10 +1,16 @@
+use std::collections::BTreeMap;
 use std::collections::HashSet;
 use std::io::Read;
 use std::io::Write;
 use std::sync::Arc;
+use std::time::Duration;
 
 use async_trait::async_trait;
+use tokio::select;
 use tokio::sync::Mutex;
+use tokio::sync::Notify;
+use tokio::sync::watch;
+use tracing::warn;
 use vm_core::device::error::DeviceSnapshotError;
 use vm_mm::manager::MemoryAddressSpace;
 use vm_pci::device::interrupt::legacy::InterruptPin;
@@ -16,80 +22,217 @@ use vm_virtio::device::VirtioDevice;
 use vm_virtio::device::virtqueue::VirtqueueHandler;
 use vm_virtio::result::VirtioError;
 use vm_virtio::transport::pci::VirtioPciDevice;
+use vm_virtio::types::device::balloon_tranditional::VIRTIO_BALLOON_PFN_SHIFT;
+use vm_virtio::types::device::balloon_tranditional::VirtioBalloonStat;
+use vm_virtio::types::device::balloon_tranditional::VirtioBalloonStatTag;
 use vm_virtio::types::device::balloon_tranditional::VirtioBalloonTranditionalConfig;
+use vm_virtio::types::device::balloon_tranditional::VirtioBalloonTranditionalFeatureBitmap;
 use vm_virtio::types::device::balloon_tranditional::VirtioBalloonTranditionalVirtqueue;
 use vm_virtio::types::device_features::VIRTIO_F_VERSION_1;
 use vm_virtio::types::device_id::DeviceId;
+use vm_virtio::virtqueue::virtq_desc_table::VirtqDesc;
 use vm_virtio::virtqueue::virtq_desc_table::VirtqDescTableRef;
+use zerocopy::FromBytes;
 use zerocopy::IntoBytes;
 
 const INFLATEQ_QUEUE_SIZE_MAX: u16 = 512;
 const DEFLATEQ_QUEUE_SIZE_MAX: u16 = 512;
+const STATSQ_QUEUE_SIZE_MAX: u16 = 1;
+const REPORTINGQ_QUEUE_SIZE_MAX: u16 = 32;
+
+/// The guest refreshes its stats every time the stats buffer is returned.
+const STATS_POLLING_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Default)]
+pub struct VirtioBalloonStats {
+    /// Latest stats reported by the guest
+    pub stats: Mutex<BTreeMap<&'static str, u64>>,
+    /// Return the stats buffer so that the guest refreshes it
+    pub request: Notify,
+    /// Notified each time the guest reports stats
+    pub updated: Notify,
+}
+
+async fn wait_resumed(paused: &watch::Receiver<bool>) {
+    let _ = paused.clone().wait_for(|paused| !*paused).await;
+}
+
+fn read_pfns(memory: &MemoryAddressSpace, desc: &VirtqDesc) -> Option<Vec<u32>> {
+    let len = desc.len;
+    if !len.is_multiple_of(4) {
+        warn!(len, "virtio-balloon: invalid pfn array length");
+        return None;
+    }
+
+    let mut buf = vec![0u8; len as usize];
+    if let Err(err) = memory.copy_to_slice(desc.gpa(), &mut buf) {
+        warn!(?err, "virtio-balloon: invalid pfn array");
+        return None;
+    }
+
+    Some(
+        buf.chunks_exact(4)
+            .map(|pfn| u32::from_le_bytes(pfn.try_into().unwrap()))
+            .collect(),
+    )
+}
 
 struct InflateqHandler {
     balloon: Arc<Mutex<HashSet<u32>>>,
     memory: Arc<MemoryAddressSpace>,
+    paused: watch::Receiver<bool>,
+}
+
+impl InflateqHandler {
+    fn release_pages(&self, mut pfns: Vec<u32>) {
+        pfns.sort_unstable();
+
+        // Release contiguous pfns together
+        let mut i = 0;
+        while i < pfns.len() {
+            let mut j = i + 1;
+            while j < pfns.len() && pfns[j - 1].checked_add(1) == Some(pfns[j]) {
+                j += 1;
+            }
+
+            let gpa = (pfns[i] as u64) << VIRTIO_BALLOON_PFN_SHIFT;
+            let len = (j - i) << VIRTIO_BALLOON_PFN_SHIFT;
+            if let Err(err) = self.memory.discard(gpa, len) {
+                warn!(?err, gpa, len, "virtio-balloon: failed to release pages");
+            }
+
+            i = j;
+        }
+    }
 }
 
 #[async_trait]
 impl VirtqueueHandler for InflateqHandler {
     async fn handle_desc(&self, desc_ring: &VirtqDescTableRef, desc_id: u16) -> u32 {
+        wait_resumed(&self.paused).await;
+
         let desc = desc_ring.get(desc_id);
-        let len = desc.len;
-        assert!(len.is_multiple_of(4));
-
-        let array: &[u32] = unsafe {
-            std::slice::from_raw_parts(
-                desc.addr(&self.memory).unwrap().as_ptr() as *const u32,
-                (len / 4) as usize,
-            )
+        let Some(pfns) = read_pfns(&self.memory, &desc) else {
+            return 0;
         };
 
-        for &pfn in array {
-            assert!(self.balloon.lock().await.insert(pfn));
-            let gpa = (pfn as u64) << 12;
-            let _hva = self.memory.gpa_to_hva(gpa).unwrap();
-            // TODO: mmap
+        let mut inflated = Vec::with_capacity(pfns.len());
+        {
+            let mut balloon = self.balloon.lock().await;
+            for pfn in pfns {
+                if balloon.insert(pfn) {
+                    inflated.push(pfn);
+                } else {
+                    warn!(pfn, "virtio-balloon: page is already inflated");
+                }
+            }
         }
 
-        len
+        self.release_pages(inflated);
+
+        desc.len
     }
 }
 
 struct DeflateqHandler {
     balloon: Arc<Mutex<HashSet<u32>>>,
     memory: Arc<MemoryAddressSpace>,
+    paused: watch::Receiver<bool>,
 }
 
 #[async_trait]
 impl VirtqueueHandler for DeflateqHandler {
     async fn handle_desc(&self, desc_ring: &VirtqDescTableRef, desc_id: u16) -> u32 {
+        wait_resumed(&self.paused).await;
+
         let desc = desc_ring.get(desc_id);
-        let len = desc.len;
-        assert!(len.is_multiple_of(4));
-
-        let array: &[u32] = unsafe {
-            std::slice::from_raw_parts(
-                desc.addr(&self.memory).unwrap().as_ptr() as *const u32,
-                (len / 4) as usize,
-            )
+        let Some(pfns) = read_pfns(&self.memory, &desc) else {
+            return 0;
         };
 
-        for pfn in array {
-            assert!(self.balloon.lock().await.remove(pfn));
-            let gpa = (*pfn as u64) << 12;
-            let _hva = self.memory.gpa_to_hva(gpa).unwrap();
-            // TODO: mmap
+        // The released pages are faulted in again on the next access
+        let mut balloon = self.balloon.lock().await;
+        for pfn in pfns {
+            if !balloon.remove(&pfn) {
+                warn!(pfn, "virtio-balloon: page is not inflated");
+            }
+        }
+
+        desc.len
+    }
+}
+
+struct StatsqHandler {
+    stats: Arc<VirtioBalloonStats>,
+    memory: Arc<MemoryAddressSpace>,
+    paused: watch::Receiver<bool>,
+}
+
+#[async_trait]
+impl VirtqueueHandler for StatsqHandler {
+    async fn handle_desc(&self, desc_ring: &VirtqDescTableRef, desc_id: u16) -> u32 {
+        wait_resumed(&self.paused).await;
+
+        let desc = desc_ring.get(desc_id);
+
+        let mut buf = vec![0u8; desc.len as usize];
+        match self.memory.copy_to_slice(desc.gpa(), &mut buf) {
+            Ok(()) => {
+                let mut stats = BTreeMap::new();
+                for stat in buf.chunks_exact(size_of::<VirtioBalloonStat>()) {
+                    let stat = VirtioBalloonStat::read_from_bytes(stat).unwrap();
+                    let (tag, val) = (stat.tag, stat.val);
+
+                    if let Some(tag) = VirtioBalloonStatTag::from_repr(tag) {
+                        stats.insert(tag.name(), val);
+                    }
+                }
+
+                *self.stats.stats.lock().await = stats;
+                self.stats.updated.notify_waiters();
+            }
+            Err(err) => warn!(?err, "virtio-balloon: invalid stats buffer"),
         }
 
-        len
+        // Hold the buffer until the next poll, the driver refills it as soon as
+        // it is used.
+        select! {
+            _ = self.stats.request.notified() => {},
+            _ = tokio::time::sleep(STATS_POLLING_INTERVAL) => {},
+        }
+
+        0
+    }
+}
+
+struct ReportingqHandler {
+    memory: Arc<MemoryAddressSpace>,
+    paused: watch::Receiver<bool>,
+}
+
+#[async_trait]
+impl VirtqueueHandler for ReportingqHandler {
+    async fn handle_desc(&self, desc_ring: &VirtqDescTableRef, desc_id: u16) -> u32 {
+        wait_resumed(&self.paused).await;
+
+        // Each buffer describes a range of free pages in the guest
+        for desc in desc_ring.get_chain(desc_id) {
+            if let Err(err) = self.memory.discard(desc.gpa(), desc.len as usize) {
+                warn!(?err, "virtio-balloon: failed to release reported pages");
+            }
+        }
+
+        0
     }
 }
 
 pub struct VirtioBalloonTranditional {
     cfg: Arc<Mutex<VirtioBalloonTranditionalConfig>>,
     balloon: Arc<Mutex<HashSet<u32>>>,
+    stats: Arc<VirtioBalloonStats>,
+    paused: watch::Sender<bool>,
     memory: Arc<MemoryAddressSpace>,
+    driver_features: u64,
 }
 
 impl VirtioBalloonTranditional {
@@ -97,42 +240,98 @@ impl VirtioBalloonTranditional {
         VirtioBalloonTranditional {
             cfg: Default::default(),
             balloon: Default::default(),
+            stats: Default::default(),
+            paused: watch::Sender::new(false),
             memory,
+            driver_features: 0,
         }
     }
 
     pub fn get_cfg(&self) -> Arc<Mutex<VirtioBalloonTranditionalConfig>> {
         self.cfg.clone()
     }
+
+    pub fn get_stats(&self) -> Arc<VirtioBalloonStats> {
+        self.stats.clone()
+    }
+
+    /// Virtqueues only exist for the negotiated features, e.g. without
+    /// VIRTIO_BALLOON_F_STATS_VQ the reporting queue is the queue 2
+    fn virtqueue(&self, queue: u16) -> Option<VirtioBalloonTranditionalVirtqueue> {
+        let negotiated = |feature: VirtioBalloonTranditionalFeatureBitmap| {
+            self.driver_features & (1 << feature as u64) != 0
+        };
+
+        [
+            (VirtioBalloonTranditionalVirtqueue::Inflateq, true),
+            (VirtioBalloonTranditionalVirtqueue::Defalteq, true),
+            (
+                VirtioBalloonTranditionalVirtqueue::Statsq,
+                negotiated(VirtioBalloonTranditionalFeatureBitmap::STATS_VQ),
+            ),
+            (
+                VirtioBalloonTranditionalVirtqueue::FreePageVq,
+                negotiated(VirtioBalloonTranditionalFeatureBitmap::FREE_PAGE_HINT),
+            ),
+            (
+                VirtioBalloonTranditionalVirtqueue::ReportingVq,
+                negotiated(VirtioBalloonTranditionalFeatureBitmap::REPORTING),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(virtqueue, present)| present.then_some(virtqueue))
+        .nth(queue as usize)
+    }
 }
 
 impl VirtioDevice for VirtioBalloonTranditional {
     const NAME: &str = "virtio-balloon-tranditional";
     const DEVICE_ID: u16 = DeviceId::Balloon as u16;
-    const DEVICE_FEATURES: u64 = (1 << VIRTIO_F_VERSION_1);
+    const DEVICE_FEATURES: u64 = (1 << VIRTIO_F_VERSION_1)
+        | (1 << VirtioBalloonTranditionalFeatureBitmap::STATS_VQ as u64)
+        | (1 << VirtioBalloonTranditionalFeatureBitmap::DEFLATE_ON_OOM as u64)
+        | (1 << VirtioBalloonTranditionalFeatureBitmap::REPORTING as u64);
 
     fn virtqueues_size_max(&self) -> Vec<u16> {
-        vec![INFLATEQ_QUEUE_SIZE_MAX, DEFLATEQ_QUEUE_SIZE_MAX]
+        // The queue 2 is the reporting queue when the stats queue is not negotiated
+        vec![
+            INFLATEQ_QUEUE_SIZE_MAX,
+            DEFLATEQ_QUEUE_SIZE_MAX,
+            STATSQ_QUEUE_SIZE_MAX.max(REPORTINGQ_QUEUE_SIZE_MAX),
+            REPORTINGQ_QUEUE_SIZE_MAX,
+        ]
     }
 
-    fn reset(&mut self) {}
+    fn reset(&mut self) {
+        self.driver_features = 0;
+    }
+
+    fn set_driver_features(&mut self, driver_features: u64) {
+        self.driver_features = driver_features;
+    }
 
     fn virtqueue_handler(&self, queue: u16) -> Option<Box<dyn VirtqueueHandler>> {
-        match VirtioBalloonTranditionalVirtqueue::from_repr(queue) {
-            Some(virtq) => match virtq {
-                VirtioBalloonTranditionalVirtqueue::Inflateq => Some(Box::new(InflateqHandler {
-                    balloon: self.balloon.clone(),
-                    memory: self.memory.clone(),
-                })),
-                VirtioBalloonTranditionalVirtqueue::Defalteq => Some(Box::new(DeflateqHandler {
-                    balloon: self.balloon.clone(),
-                    memory: self.memory.clone(),
-                })),
-                VirtioBalloonTranditionalVirtqueue::Statsq => None,
-                VirtioBalloonTranditionalVirtqueue::FreePageVq => None,
-                VirtioBalloonTranditionalVirtqueue::ReportingVq => None,
-            },
-            None => None,
+        match self.virtqueue(queue)? {
+            VirtioBalloonTranditionalVirtqueue::Inflateq => Some(Box::new(InflateqHandler {
+                balloon: self.balloon.clone(),
+                memory: self.memory.clone(),
+                paused: self.paused.subscribe(),
+            })),
+            VirtioBalloonTranditionalVirtqueue::Defalteq => Some(Box::new(DeflateqHandler {
+                balloon: self.balloon.clone(),
+                memory: self.memory.clone(),
+                paused: self.paused.subscribe(),
+            })),
+            VirtioBalloonTranditionalVirtqueue::Statsq => Some(Box::new(StatsqHandler {
+                stats: self.stats.clone(),
+                memory: self.memory.clone(),
+                paused: self.paused.subscribe(),
+            })),
+            VirtioBalloonTranditionalVirtqueue::ReportingVq => Some(Box::new(ReportingqHandler {
+                memory: self.memory.clone(),
+                paused: self.paused.subscribe(),
+            })),
+            VirtioBalloonTranditionalVirtqueue::FreePageVq => None,
         }
     }
 
@@ -147,11 +346,15 @@ impl VirtioDevice for VirtioBalloonTranditional {
     }
 
     fn pause(&self) -> Result<(), DeviceSnapshotError> {
-        todo!()
+        self.paused.send_replace(true);
+
+        Ok(())
     }
 
     fn resume(&self) -> Result<(), DeviceSnapshotError> {
-        todo!()
+        self.paused.send_replace(false);
+
+        Ok(())
     }
 
     fn save(&self, writer: &mut dyn Write) -> Result<(), DeviceSnapshotError> {
@@ -173,6 +376,7 @@ impl VirtioDevice for VirtioBalloonTranditional {
 
         {
             let mut balloon = self.balloon.blocking_lock();
+            balloon.clear();
             let len = read_usize(reader)?;
             for _ in 0..len {
                 balloon.insert(read_u32(reader)?);
@@ -189,3 +393,79 @@ impl VirtioPciDevice for VirtioBalloonTranditional {
     const CLASS_CODE: u32 = 0xff0000;
     const IRQ_PIN: u8 = InterruptPin::INTA as u8;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_virtqueue_follows_features() {
+        const STATS_VQ: u64 = 1 << VirtioBalloonTranditionalFeatureBitmap::STATS_VQ as u64;
+        const REPORTING: u64 = 1 << VirtioBalloonTranditionalFeatureBitmap::REPORTING as u64;
+
+        let mut balloon = VirtioBalloonTranditional::new(Default::default());
+
+        balloon.set_driver_features(STATS_VQ | REPORTING);
+        assert_eq!(
+            balloon.virtqueue(2),
+            Some(VirtioBalloonTranditionalVirtqueue::Statsq)
+        );
+        assert_eq!(
+            balloon.virtqueue(3),
+            Some(VirtioBalloonTranditionalVirtqueue::ReportingVq)
+        );
+
+        balloon.set_driver_features(REPORTING);
+        assert_eq!(
+            balloon.virtqueue(2),
+            Some(VirtioBalloonTranditionalVirtqueue::ReportingVq)
+        );
+        assert_eq!(balloon.virtqueue(3), None);
+
+        balloon.reset();
+        assert_eq!(
+            balloon.virtqueue(1),
+            Some(VirtioBalloonTranditionalVirtqueue::Defalteq)
+        );
+        assert_eq!(balloon.virtqueue(2), None);
+    }
+
+    #[test]
+    fn test_pause_and_snapshot() {
+        let balloon = VirtioBalloonTranditional::new(Default::default());
+
+        balloon.pause().unwrap();
+        assert!(*balloon.paused.borrow());
+        balloon.resume().unwrap();
+        assert!(!*balloon.paused.borrow());
+
+        balloon.cfg.blocking_lock().num_pages = 3;
+        balloon.balloon.blocking_lock().extend([1, 2, u32::MAX]);
+
+        let mut buf = vec![];
+        balloon.save(&mut buf).unwrap();
+
+        let mut restored = VirtioBalloonTranditional::new(Default::default());
+        restored.balloon.blocking_lock().insert(7);
+        restored.load(&mut buf.as_slice()).unwrap();
+
+        assert_eq!({ restored.cfg.blocking_lock().num_pages }, 3);
+        assert_eq!(
+            *restored.balloon.blocking_lock(),
+            HashSet::from([1, 2, u32::MAX])
+        );
+    }
+
+    #[test]
+    fn test_release_last_pfn() {
+        let (_, paused) = watch::channel(false);
+        let handler = InflateqHandler {
+            balloon: Default::default(),
+            memory: Default::default(),
+            paused,
+        };
+
+        // Nothing is mapped, the release only warns
+        handler.release_pages(vec![u32::MAX, u32::MAX - 1, 0]);
+    }
+}
@@ -1,4 +1,5 @@
 use std::sync::Arc;
+use std::time::Duration;
 
 use async_trait::async_trait;
 use serde::Serialize;
@@ -8,6 +9,11 @@ use vm_core::monitor::MonitorError;
 use vm_virtio::device::virtqueue::VirtioConfigurationChangeNotifier;
 use vm_virtio::types::device::balloon_tranditional::VirtioBalloonTranditionalConfig;
 
+use crate::device::virtio::virtio_balloon_traditional::device::VirtioBalloonStats;
+
+/// How long to wait for the guest to refresh its stats
+const STATS_TIMEOUT: Duration = Duration::from_secs(1);
+
 #[derive(Serialize)]
 pub struct BalloonInfo {
     actual: u32,
@@ -16,37 +22,60 @@ pub struct BalloonInfo {
 
 pub struct VirtioBalloonMonitor {
     config: Arc<Mutex<VirtioBalloonTranditionalConfig>>,
+    stats: Arc<VirtioBalloonStats>,
     configuration_change_notifier: Arc<dyn VirtioConfigurationChangeNotifier>,
 }
 
 impl VirtioBalloonMonitor {
     pub fn new(
         config: Arc<Mutex<VirtioBalloonTranditionalConfig>>,
+        stats: Arc<VirtioBalloonStats>,
         configuration_change_notifier: Arc<dyn VirtioConfigurationChangeNotifier>,
     ) -> Self {
         VirtioBalloonMonitor {
             config,
+            stats,
             configuration_change_notifier,
         }
     }
+
+    async fn refresh_stats(&self) {
+        let updated = self.stats.updated.notified();
+        tokio::pin!(updated);
+        updated.as_mut().enable();
+
+        self.stats.request.notify_one();
+
+        // The last reported stats are returned if the guest does not respond
+        let _ = tokio::time::timeout(STATS_TIMEOUT, updated).await;
+    }
 }
 
 #[async_trait]
 impl MonitorCommandOps for VirtioBalloonMonitor {
     async fn handle_command(&self, subcommands: &[&str]) -> Result<String, MonitorError> {
-        let mut config = self.config.lock().await;
-
         match *subcommands {
-            ["info"] => Ok(serde_json::to_string_pretty(&BalloonInfo {
-                actual: config.actual,
-                num_pages: config.num_pages,
-            })?),
+            ["info"] => {
+                let config = self.config.lock().await;
+
+                Ok(serde_json::to_string_pretty(&BalloonInfo {
+                    actual: config.actual,
+                    num_pages: config.num_pages,
+                })?)
+            }
+            ["stats"] => {
+                self.refresh_stats().await;
+
+                Ok(serde_json::to_string_pretty(
+                    &*self.stats.stats.lock().await,
+                )?)
+            }
             ["update_num_pages", num_pages] => {
                 let num_pages = num_pages.parse().map_err(|_err| {
                     MonitorError::Error(format!("failed to parse num_pages: {num_pages}"))
                 })?;
 
-                config.num_pages = num_pages;
+                self.config.lock().await.num_pages = num_pages;
                 self.configuration_change_notifier
                     .update_config_generation();
 
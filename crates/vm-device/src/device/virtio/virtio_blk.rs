@@ -1,8 +1,13 @@
+use std::fs::File;
+use std::fs::OpenOptions;
 use std::io::Read;
 use std::io::Write;
+use std::os::unix::fs::FileExt;
+use std::path::PathBuf;
 use std::sync::Arc;
 
 use async_trait::async_trait;
+use tracing::warn;
 use vm_core::device::error::DeviceSnapshotError;
 use vm_mm::manager::MemoryAddressSpace;
 use vm_pci::device::interrupt::legacy::InterruptPin;
@@ -10,71 +15,247 @@ use vm_virtio::device::VirtioDevice;
 use vm_virtio::device::virtqueue::VirtqueueHandler;
 use vm_virtio::result::VirtioError;
 use vm_virtio::transport::pci::VirtioPciDevice;
+use vm_virtio::types::device::blk::VIRTIO_BLK_ID_BYTES;
+use vm_virtio::types::device::blk::VIRTIO_BLK_SECTOR_SIZE;
 use vm_virtio::types::device::blk::config::VirtioBlkConfig;
-use vm_virtio::types::device::blk::req::VirtioBlkReq;
+use vm_virtio::types::device::blk::features::VIRTIO_BLK_F_FLUSH;
+use vm_virtio::types::device::blk::features::VIRTIO_BLK_F_RO;
 use vm_virtio::types::device::blk::req::VirtioBlkReqType;
+use vm_virtio::types::device::blk::status::VIRTIO_BLK_S_IOERR;
+use vm_virtio::types::device::blk::status::VIRTIO_BLK_S_OK;
+use vm_virtio::types::device::blk::status::VIRTIO_BLK_S_UNSUPP;
 use vm_virtio::types::device_features::VIRTIO_F_VERSION_1;
 use vm_virtio::types::device_id::DeviceId;
+use vm_virtio::virtqueue::virtq_desc_table::VirtqDesc;
 use vm_virtio::virtqueue::virtq_desc_table::VirtqDescTableRef;
 use zerocopy::IntoBytes;
 
+/// Offset of the sector in the request header, after the type and a reserved field
+const REQ_SECTOR_OFFSET: usize = 8;
+
+/// The data is copied between the guest memory and the disk image in chunks of this size
+const BOUNCE_BUF_LEN: usize = 64 * 1024;
+
 struct Requestq0Handler {
     memory: Arc<MemoryAddressSpace>,
+    disk: Arc<File>,
+    read_only: bool,
+    id: [u8; VIRTIO_BLK_ID_BYTES],
+    /// In bytes
+    capacity: u64,
+}
+
+impl Requestq0Handler {
+    /// Reads the disk at `offset` into the buffer of `desc`
+    fn read_disk(&self, desc: &VirtqDesc, offset: u64) -> std::io::Result<()> {
+        let mut buf = vec![0; (desc.len as usize).min(BOUNCE_BUF_LEN)];
+
+        let mut done = 0;
+        while done < desc.len as usize {
+            let step = (desc.len as usize - done).min(buf.len());
+            self.disk
+                .read_exact_at(&mut buf[..step], offset + done as u64)?;
+            self.memory
+                .copy_from_slice(desc.gpa() + done as u64, &buf[..step])
+                .map_err(std::io::Error::other)?;
+            done += step;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the buffer of `desc` to the disk at `offset`
+    fn write_disk(&self, desc: &VirtqDesc, offset: u64) -> std::io::Result<()> {
+        let mut buf = vec![0; (desc.len as usize).min(BOUNCE_BUF_LEN)];
+
+        let mut done = 0;
+        while done < desc.len as usize {
+            let step = (desc.len as usize - done).min(buf.len());
+            self.memory
+                .copy_to_slice(desc.gpa() + done as u64, &mut buf[..step])
+                .map_err(std::io::Error::other)?;
+            self.disk.write_all_at(&buf[..step], offset + done as u64)?;
+            done += step;
+        }
+
+        Ok(())
+    }
+
+    /// Whether the data buffers starting at `offset` end inside the disk
+    fn in_capacity(&self, offset: Option<u64>, data: &[VirtqDesc]) -> bool {
+        let len = data.iter().map(|desc| desc.len as u64).sum::<u64>();
+
+        offset
+            .and_then(|offset| offset.checked_add(len))
+            .is_some_and(|end| end <= self.capacity)
+    }
+
+    /// Returns the status and the length written to the data buffers
+    fn handle_req(
+        &self,
+        r#type: u32,
+        sector: u64,
+        data: &[VirtqDesc],
+    ) -> Result<(u8, u32), VirtioError> {
+        let offset = sector.checked_mul(VIRTIO_BLK_SECTOR_SIZE);
+        let mut written = 0;
+
+        match VirtioBlkReqType::from_repr(r#type) {
+            Some(VirtioBlkReqType::VirtioBlkTIn) => {
+                if !self.in_capacity(offset, data) {
+                    warn!(sector, "virtio-blk read beyond the capacity");
+                    return Ok((VIRTIO_BLK_S_IOERR, 0));
+                }
+
+                let mut offset = offset.unwrap_or_default();
+                for desc in data {
+                    if self.read_disk(desc, offset).is_err() {
+                        return Ok((VIRTIO_BLK_S_IOERR, written));
+                    }
+                    offset += desc.len as u64;
+                    written += desc.len;
+                }
+            }
+            Some(VirtioBlkReqType::VirtioBlkTOut) => {
+                if self.read_only {
+                    return Ok((VIRTIO_BLK_S_IOERR, 0));
+                }
+                if !self.in_capacity(offset, data) {
+                    warn!(sector, "virtio-blk write beyond the capacity");
+                    return Ok((VIRTIO_BLK_S_IOERR, 0));
+                }
+
+                let mut offset = offset.unwrap_or_default();
+                for desc in data {
+                    if self.write_disk(desc, offset).is_err() {
+                        return Ok((VIRTIO_BLK_S_IOERR, 0));
+                    }
+                    offset += desc.len as u64;
+                }
+            }
+            Some(VirtioBlkReqType::VirtioBlkTFlush) => {
+                if self.disk.sync_data().is_err() {
+                    return Ok((VIRTIO_BLK_S_IOERR, 0));
+                }
+            }
+            Some(VirtioBlkReqType::VirtioBlkTGetId) => {
+                if let Some(desc) = data.first() {
+                    let len = (desc.len as usize).min(self.id.len());
+                    self.memory
+                        .copy_from_slice(desc.gpa(), &self.id[..len])
+                        .map_err(|_| VirtioError::AccessInvalidGpa(desc.gpa()))?;
+                    written = len as u32;
+                }
+            }
+            _ => return Ok((VIRTIO_BLK_S_UNSUPP, 0)),
+        }
+
+        Ok((VIRTIO_BLK_S_OK, written))
+    }
 }
 
 #[async_trait]
 impl VirtqueueHandler for Requestq0Handler {
     async fn handle_desc(&self, desc_ring: &VirtqDescTableRef, desc_id: u16) -> u32 {
-        let desc_entry = desc_ring.get(desc_id);
-        let req = desc_entry.addr(&self.memory).unwrap();
-        let req = unsafe { &*(req.as_ptr() as *const VirtioBlkReq) };
+        // The header, the data buffers and the status byte
+        let chain = desc_ring.get_chain(desc_id);
+        if chain.len() < 2 {
+            warn!(desc_id, "virtio-blk request without a status byte");
+            return 0;
+        }
 
-        match req.r#type {
-            VirtioBlkReqType::VirtioBlkTIn => {
-                let chains = desc_ring.get_chain(desc_id);
+        let header = chain[0];
+        let status = chain[chain.len() - 1];
+        let data = &chain[1..chain.len() - 1];
 
-                let data = chains[1];
-                let data_hva = data.addr(&self.memory).unwrap();
-                let data_len = data.len;
-                unsafe { data_hva.write_bytes(0xff, data_len.try_into().unwrap()) };
+        let result = header.addr(&self.memory).and_then(|req| {
+            let req = req.as_ptr();
+            let r#type = unsafe { (req as *const u32).read_unaligned() };
+            let sector = unsafe { (req.add(REQ_SECTOR_OFFSET) as *const u64).read_unaligned() };
 
-                let status = chains[2];
-                let mut status_hva = status.addr(&self.memory).unwrap();
-                *unsafe { status_hva.as_mut() } = 0;
+            self.handle_req(r#type, sector, data)
+        });
+        let (status_code, written) = result.unwrap_or_else(|err| {
+            warn!(?err, "virtio-blk request with an invalid buffer");
+            (VIRTIO_BLK_S_IOERR, 0)
+        });
 
-                data_len + 1
-            }
-            VirtioBlkReqType::VirtioBlkTOut => todo!(),
-            VirtioBlkReqType::VirtioBlkTFlush => todo!(),
-            VirtioBlkReqType::VirtioBlkTGetId => todo!(),
-            VirtioBlkReqType::VirtioBlkTGetLifetime => todo!(),
-            VirtioBlkReqType::VirtioBlkTDiscard => todo!(),
-            VirtioBlkReqType::VirtioBlkTWriteZeroes => todo!(),
-            VirtioBlkReqType::VirtioBlkTSecureErase => todo!(),
+        match status.addr(&self.memory) {
+            Ok(mut status) => *unsafe { status.as_mut() } = status_code,
+            Err(err) => warn!(?err, "virtio-blk request with an invalid status byte"),
         }
+
+        written + 1
     }
 }
 
 pub struct VirtioBlkDevice {
     cfg: VirtioBlkConfig,
     memory: Arc<MemoryAddressSpace>,
+    disk: Arc<File>,
+    /// The capacity in bytes, the guest may overwrite the one in the config space
+    disk_len: u64,
+    read_only: bool,
+    id: [u8; VIRTIO_BLK_ID_BYTES],
 }
 
 impl VirtioBlkDevice {
-    pub fn new(memory: Arc<MemoryAddressSpace>) -> Self {
+    pub fn new(
+        memory: Arc<MemoryAddressSpace>,
+        path: PathBuf,
+        read_only: bool,
+    ) -> Result<Self, VirtioError> {
+        let open = || -> std::io::Result<(File, u64)> {
+            let disk = OpenOptions::new()
+                .read(true)
+                .write(!read_only)
+                .open(&path)?;
+            let len = disk.metadata()?.len();
+
+            Ok((disk, len))
+        };
+        let (disk, len) = open().map_err(|err| VirtioError::OpenBlkImage {
+            path: path.clone(),
+            err,
+        })?;
+
+        let capacity = len / VIRTIO_BLK_SECTOR_SIZE;
         let cfg = VirtioBlkConfig {
-            capacity: 50,
+            capacity,
             ..Default::default()
         };
 
-        VirtioBlkDevice { cfg, memory }
+        // The serial seen by the guest, the file name is cut to its length
+        let mut id = [0; VIRTIO_BLK_ID_BYTES];
+        if let Some(name) = path.file_name() {
+            let name = name.as_encoded_bytes();
+            let len = name.len().min(id.len());
+            id[..len].copy_from_slice(&name[..len]);
+        }
+
+        Ok(VirtioBlkDevice {
+            cfg,
+            memory,
+            disk: Arc::new(disk),
+            disk_len: capacity * VIRTIO_BLK_SECTOR_SIZE,
+            read_only,
+            id,
+        })
     }
 }
 
 impl VirtioDevice for VirtioBlkDevice {
     const NAME: &str = "virtio-blk";
     const DEVICE_ID: u16 = DeviceId::Blk as u16;
-    const DEVICE_FEATURES: u64 = (1 << VIRTIO_F_VERSION_1);
+    const DEVICE_FEATURES: u64 = (1 << VIRTIO_F_VERSION_1) | (1 << VIRTIO_BLK_F_FLUSH);
+
+    fn extra_device_features(&self) -> u64 {
+        if self.read_only {
+            1 << VIRTIO_BLK_F_RO
+        } else {
+            0
+        }
+    }
 
     fn virtqueues_size_max(&self) -> Vec<u16> {
         vec![512]
@@ -89,6 +270,10 @@ impl VirtioDevice for VirtioBlkDevice {
 
         Some(Box::new(Requestq0Handler {
             memory: self.memory.clone(),
+            disk: self.disk.clone(),
+            read_only: self.read_only,
+            id: self.id,
+            capacity: self.disk_len,
         }))
     }
 
@@ -128,3 +313,121 @@ impl VirtioPciDevice for VirtioBlkDevice {
     const CLASS_CODE: u32 = 0x018000;
     const IRQ_PIN: u8 = InterruptPin::INTA as u8;
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempfile::NamedTempFile;
+    use vm_mm::allocator::Allocator;
+    use vm_mm::allocator::mmap_allocator::MmapAllocator;
+    use vm_mm::region::MemoryRegion;
+    use vm_virtio::virtqueue::virtq_desc_table::VIRTQ_DESC_F_WRITE;
+
+    use super::*;
+
+    const MEMORY_LEN: usize = 0x10000;
+    const SECTORS: u64 = 8;
+
+    const IN: u32 = VirtioBlkReqType::VirtioBlkTIn as u32;
+    const OUT: u32 = VirtioBlkReqType::VirtioBlkTOut as u32;
+
+    fn handler() -> (Requestq0Handler, NamedTempFile) {
+        let mut memory = MemoryAddressSpace::default();
+        let ram = MmapAllocator.alloc(MEMORY_LEN, None).unwrap();
+        assert!(
+            memory
+                .try_insert(MemoryRegion::new(0, Box::new(ram)))
+                .is_ok()
+        );
+
+        let mut disk = NamedTempFile::new().unwrap();
+        let data = (0..SECTORS * VIRTIO_BLK_SECTOR_SIZE)
+            .map(|i| (i / VIRTIO_BLK_SECTOR_SIZE) as u8)
+            .collect::<Vec<_>>();
+        disk.write_all(&data).unwrap();
+
+        let handler = Requestq0Handler {
+            memory: Arc::new(memory),
+            disk: Arc::new(disk.reopen().unwrap()),
+            read_only: false,
+            id: [0; VIRTIO_BLK_ID_BYTES],
+            capacity: SECTORS * VIRTIO_BLK_SECTOR_SIZE,
+        };
+
+        (handler, disk)
+    }
+
+    #[test]
+    fn test_in_range() -> Result<(), VirtioError> {
+        let (handler, _disk) = handler();
+
+        let data = [VirtqDesc::new(0x1000, 1024, VIRTQ_DESC_F_WRITE, 0)];
+        assert_eq!(handler.handle_req(IN, 6, &data)?, (VIRTIO_BLK_S_OK, 1024));
+
+        let mut buf = [0; 1024];
+        handler.memory.copy_to_slice(0x1000, &mut buf).unwrap();
+        assert!(buf[..512].iter().all(|&b| b == 6));
+        assert!(buf[512..].iter().all(|&b| b == 7));
+
+        let data = [VirtqDesc::new(0x1000, 512, 0, 0)];
+        assert_eq!(handler.handle_req(OUT, 0, &data)?, (VIRTIO_BLK_S_OK, 0));
+
+        let mut buf = [0; 512];
+        handler.disk.read_exact_at(&mut buf, 0).unwrap();
+        assert!(buf.iter().all(|&b| b == 6));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_out_of_range() -> Result<(), VirtioError> {
+        let (handler, _disk) = handler();
+
+        // The last sector is read past the end of the disk
+        let data = [VirtqDesc::new(0x1000, 1024, VIRTQ_DESC_F_WRITE, 0)];
+        assert_eq!(handler.handle_req(IN, 7, &data)?, (VIRTIO_BLK_S_IOERR, 0));
+        assert_eq!(handler.handle_req(OUT, 7, &data)?, (VIRTIO_BLK_S_IOERR, 0));
+
+        // The offset of the sector overflows
+        assert_eq!(
+            handler.handle_req(IN, u64::MAX / 2, &data)?,
+            (VIRTIO_BLK_S_IOERR, 0)
+        );
+        assert_eq!(
+            handler.handle_req(OUT, u64::MAX / 2, &data)?,
+            (VIRTIO_BLK_S_IOERR, 0)
+        );
+
+        let mut buf = [0; 512];
+        handler.disk.read_exact_at(&mut buf, 7 * 512).unwrap();
+        assert!(buf.iter().all(|&b| b == 7));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_oversized_desc() -> Result<(), VirtioError> {
+        let (handler, _disk) = handler();
+
+        // Larger than the disk
+        let data = [VirtqDesc::new(0, u32::MAX, VIRTQ_DESC_F_WRITE, 0)];
+        assert_eq!(handler.handle_req(IN, 0, &data)?, (VIRTIO_BLK_S_IOERR, 0));
+
+        // Inside the disk but past the end of the guest memory
+        let data = [VirtqDesc::new(
+            MEMORY_LEN as u64 - 512,
+            1024,
+            VIRTQ_DESC_F_WRITE,
+            0,
+        )];
+        assert_eq!(handler.handle_req(IN, 0, &data)?, (VIRTIO_BLK_S_IOERR, 0));
+        assert_eq!(handler.handle_req(OUT, 0, &data)?, (VIRTIO_BLK_S_IOERR, 0));
+
+        let mut buf = [0; 512];
+        handler.disk.read_exact_at(&mut buf, 0).unwrap();
+        assert!(buf.iter().all(|&b| b == 0));
+
+        Ok(())
+    }
+}
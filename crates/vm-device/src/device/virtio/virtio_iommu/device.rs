@@ -0,0 +1,779 @@
+use std::collections::BTreeMap;
+use std::mem::offset_of;
+use std::ops::Range;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::RwLock;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+use async_trait::async_trait;
+use tracing::warn;
+#[cfg(target_arch = "aarch64")]
+use vm_core::arch::aarch64::layout::GIC_MSI;
+use vm_core::arch::irq::Phandle;
+#[cfg(target_arch = "x86_64")]
+use vm_core::arch::x86_64::layout::APIC_ADDR;
+use vm_fdt::FdtWriter;
+use vm_mm::manager::MemoryAddressSpace;
+use vm_virtio::device::VirtioDevice;
+use vm_virtio::device::virtqueue::VirtqueueHandler;
+use vm_virtio::result::VirtioError;
+use vm_virtio::types::device::iommu::VIRTIO_IOMMU_ATTACH_F_BYPASS;
+use vm_virtio::types::device::iommu::VIRTIO_IOMMU_MAP_F_MMIO;
+use vm_virtio::types::device::iommu::VIRTIO_IOMMU_MAP_F_READ;
+use vm_virtio::types::device::iommu::VIRTIO_IOMMU_MAP_F_WRITE;
+use vm_virtio::types::device::iommu::VIRTIO_IOMMU_PROBE_T_RESV_MEM;
+use vm_virtio::types::device::iommu::VIRTIO_IOMMU_RESV_MEM_T_MSI;
+use vm_virtio::types::device::iommu::VirtioIommuConfig;
+use vm_virtio::types::device::iommu::VirtioIommuFeatureBitmap;
+use vm_virtio::types::device::iommu::VirtioIommuProbeResvMem;
+use vm_virtio::types::device::iommu::VirtioIommuReqAttach;
+use vm_virtio::types::device::iommu::VirtioIommuReqDetach;
+use vm_virtio::types::device::iommu::VirtioIommuReqHead;
+use vm_virtio::types::device::iommu::VirtioIommuReqMap;
+use vm_virtio::types::device::iommu::VirtioIommuReqProbe;
+use vm_virtio::types::device::iommu::VirtioIommuReqTail;
+use vm_virtio::types::device::iommu::VirtioIommuReqUnmap;
+use vm_virtio::types::device::iommu::VirtioIommuRequestType;
+use vm_virtio::types::device::iommu::VirtioIommuStatus;
+use vm_virtio::types::device::iommu::VirtioIommuVirtqueue;
+use vm_virtio::types::device_features::VIRTIO_F_VERSION_1;
+use vm_virtio::types::device_id::DeviceId;
+use vm_virtio::virtqueue::virtq_desc_table::VIRTQ_DESC_F_WRITE;
+use vm_virtio::virtqueue::virtq_desc_table::VirtqDescTableRef;
+use zerocopy::FromBytes;
+use zerocopy::IntoBytes;
+
+use crate::device::virtio::virtio_iommu::endpoint::DmaMapListener;
+use crate::device::virtio::virtio_iommu::endpoint::IommuDomain;
+use crate::device::virtio::virtio_iommu::endpoint::IommuEndpoint;
+use crate::device::virtio::virtio_iommu::endpoint::Mapping;
+
+const REQUESTQ_SIZE_MAX: u16 = 256;
+const EVENTQ_SIZE_MAX: u16 = 64;
+
+const PAGE_SIZE_MASK: u64 = !0xfff;
+const PROBE_SIZE: usize = 0x200;
+
+// The msi doorbells are not translated
+#[cfg(target_arch = "x86_64")]
+const MSI_RANGE: (u64, u64) = (APIC_ADDR as u64, APIC_ADDR as u64 + 0xf_ffff);
+// The control and the translation frames of the its
+#[cfg(target_arch = "aarch64")]
+const MSI_RANGE: (u64, u64) = (GIC_MSI, GIC_MSI + 0x1_ffff);
+
+type Status = Result<(), VirtioIommuStatus>;
+
+/// Domains and endpoints of the iommu, the endpoints are added while building the vm and
+/// the domains are created by the driver.
+pub struct VirtioIommuState {
+    ram: Vec<Range<u64>>,
+    bypass: Arc<AtomicBool>,
+    domains: Mutex<BTreeMap<u32, Arc<IommuDomain>>>,
+    endpoints: RwLock<BTreeMap<u32, Arc<IommuEndpoint>>>,
+}
+
+impl VirtioIommuState {
+    fn new(ram: Vec<Range<u64>>) -> Self {
+        VirtioIommuState {
+            ram,
+            // Let the devices work until the driver is loaded
+            bypass: Arc::new(AtomicBool::new(true)),
+            domains: Default::default(),
+            endpoints: Default::default(),
+        }
+    }
+
+    /// The endpoint is also the dma translator of an emulated device, `listener` is for
+    /// a device which does the dma by itself.
+    pub fn new_endpoint(&self, listener: Option<Arc<dyn DmaMapListener>>) -> Arc<IommuEndpoint> {
+        Arc::new(IommuEndpoint::new(self.bypass.clone(), listener))
+    }
+
+    /// Put `endpoint` behind the iommu as the endpoint `id`, the listener gets the identity
+    /// mappings until the driver attaches it.
+    pub fn add_endpoint(
+        &self,
+        id: u32,
+        endpoint: Arc<IommuEndpoint>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        endpoint.sync_listener(&self.ram, vec![])?;
+        self.endpoints.write().unwrap().insert(id, endpoint);
+
+        Ok(())
+    }
+
+    fn endpoint(&self, id: u32) -> Result<Arc<IommuEndpoint>, VirtioIommuStatus> {
+        self.endpoints
+            .read()
+            .unwrap()
+            .get(&id)
+            .cloned()
+            .ok_or(VirtioIommuStatus::NoEnt)
+    }
+
+    fn domain_endpoints(&self, domain: &Arc<IommuDomain>) -> Vec<Arc<IommuEndpoint>> {
+        self.endpoints
+            .read()
+            .unwrap()
+            .values()
+            .filter(|endpoint| endpoint.is_attached_to(domain))
+            .cloned()
+            .collect()
+    }
+
+    /// Move `endpoint` to `domain` and update its listener
+    fn set_domain(&self, endpoint: &IommuEndpoint, domain: Option<Arc<IommuDomain>>) -> Status {
+        let before = endpoint.dma_mappings(&self.ram);
+        *endpoint.domain.write().unwrap() = domain;
+
+        endpoint.sync_listener(&self.ram, before).map_err(|err| {
+            warn!(?err, "virtio-iommu: failed to update the dma mappings");
+            VirtioIommuStatus::DevErr
+        })
+    }
+
+    /// A domain and its mappings are released once its last endpoint is detached
+    fn release_domain_if_unused(
+        &self,
+        domains: &mut BTreeMap<u32, Arc<IommuDomain>>,
+        domain: Option<Arc<IommuDomain>>,
+    ) {
+        let Some(domain) = domain else {
+            return;
+        };
+
+        if self.domain_endpoints(&domain).is_empty() {
+            domains.retain(|_, d| !Arc::ptr_eq(d, &domain));
+        }
+    }
+
+    fn attach(&self, req: &VirtioIommuReqAttach) -> Status {
+        let (domain_id, flags) = (req.domain, req.flags);
+        if flags & !VIRTIO_IOMMU_ATTACH_F_BYPASS != 0 {
+            return Err(VirtioIommuStatus::Inval);
+        }
+        let bypass = flags & VIRTIO_IOMMU_ATTACH_F_BYPASS != 0;
+
+        let endpoint = self.endpoint(req.endpoint)?;
+        let mut domains = self.domains.lock().unwrap();
+
+        let domain = domains
+            .entry(domain_id)
+            .or_insert_with(|| Arc::new(IommuDomain::new(bypass)))
+            .clone();
+        if domain.bypass != bypass {
+            return Err(VirtioIommuStatus::Inval);
+        }
+
+        // An attached endpoint is detached from its domain first
+        let previous = endpoint.domain.read().unwrap().clone();
+        let status = self.set_domain(&endpoint, Some(domain.clone()));
+
+        if previous
+            .as_ref()
+            .is_none_or(|previous| !Arc::ptr_eq(previous, &domain))
+        {
+            self.release_domain_if_unused(&mut domains, previous);
+        }
+
+        status
+    }
+
+    fn detach(&self, req: &VirtioIommuReqDetach) -> Status {
+        let domain_id = req.domain;
+
+        let endpoint = self.endpoint(req.endpoint)?;
+        let mut domains = self.domains.lock().unwrap();
+
+        let domain = domains
+            .get(&domain_id)
+            .cloned()
+            .ok_or(VirtioIommuStatus::NoEnt)?;
+        if !endpoint.is_attached_to(&domain) {
+            return Err(VirtioIommuStatus::Inval);
+        }
+
+        let status = self.set_domain(&endpoint, None);
+        self.release_domain_if_unused(&mut domains, Some(domain));
+
+        status
+    }
+
+    fn map(&self, req: &VirtioIommuReqMap) -> Status {
+        let (domain_id, start, end, phys, flags) = (
+            req.domain,
+            req.virt_start,
+            req.virt_end,
+            req.phys_start,
+            req.flags,
+        );
+
+        if start > end
+            || flags
+                & !(VIRTIO_IOMMU_MAP_F_READ | VIRTIO_IOMMU_MAP_F_WRITE | VIRTIO_IOMMU_MAP_F_MMIO)
+                != 0
+        {
+            return Err(VirtioIommuStatus::Inval);
+        }
+
+        let mapping = Mapping { end, phys, flags };
+        // The listeners take a size, the guest addresses must not wrap
+        let size = mapping.size(start).ok_or(VirtioIommuStatus::Range)?;
+        if phys.checked_add(end - start).is_none() {
+            return Err(VirtioIommuStatus::Range);
+        }
+
+        let domains = self.domains.lock().unwrap();
+        let domain = domains.get(&domain_id).ok_or(VirtioIommuStatus::NoEnt)?;
+        if domain.bypass {
+            return Err(VirtioIommuStatus::Inval);
+        }
+
+        {
+            let mut mappings = domain.mappings.write().unwrap();
+
+            let overlapped = mappings
+                .range(..=end)
+                .next_back()
+                .is_some_and(|(_, mapping)| mapping.end >= start);
+            if overlapped {
+                return Err(VirtioIommuStatus::Inval);
+            }
+
+            mappings.insert(start, mapping);
+        }
+
+        // The device assigned endpoints do not access the mmio of other devices
+        if flags & VIRTIO_IOMMU_MAP_F_MMIO != 0 {
+            return Ok(());
+        }
+
+        let endpoints = self.domain_endpoints(domain);
+        let listeners: Vec<_> = endpoints
+            .iter()
+            .filter_map(|endpoint| endpoint.listener())
+            .collect();
+        for (i, listener) in listeners.iter().enumerate() {
+            if let Err(err) = listener.map(start, phys, size) {
+                warn!(?err, start, size, "virtio-iommu: failed to map");
+
+                // Leave the domain as it was before the request
+                for listener in &listeners[..i] {
+                    if let Err(err) = listener.unmap(start, size) {
+                        warn!(?err, start, size, "virtio-iommu: failed to unmap");
+                    }
+                }
+                domain.mappings.write().unwrap().remove(&start);
+
+                return Err(VirtioIommuStatus::DevErr);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn unmap(&self, req: &VirtioIommuReqUnmap) -> Status {
+        let (domain_id, start, end) = (req.domain, req.virt_start, req.virt_end);
+
+        let domains = self.domains.lock().unwrap();
+        let domain = domains.get(&domain_id).ok_or(VirtioIommuStatus::NoEnt)?;
+        if domain.bypass {
+            return Err(VirtioIommuStatus::Inval);
+        }
+
+        let removed = {
+            let mut mappings = domain.mappings.write().unwrap();
+
+            // A mapping is never split
+            let split = mappings
+                .range(..start)
+                .next_back()
+                .is_some_and(|(_, mapping)| mapping.end >= start)
+                || mappings
+                    .range(start..=end)
+                    .any(|(_, mapping)| mapping.end > end);
+            if split {
+                return Err(VirtioIommuStatus::Range);
+            }
+
+            let removed: Vec<_> = mappings
+                .range(start..=end)
+                .map(|(start, mapping)| (*start, *mapping))
+                .collect();
+            for (start, _) in &removed {
+                mappings.remove(start);
+            }
+
+            removed
+        };
+
+        let endpoints = self.domain_endpoints(domain);
+        for (start, mapping) in removed {
+            if mapping.flags & VIRTIO_IOMMU_MAP_F_MMIO != 0 {
+                continue;
+            }
+
+            let Some(size) = mapping.size(start) else {
+                continue;
+            };
+            for listener in endpoints.iter().filter_map(|endpoint| endpoint.listener()) {
+                if let Err(err) = listener.unmap(start, size) {
+                    warn!(?err, start, size, "virtio-iommu: failed to unmap");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn probe(&self, req: &VirtioIommuReqProbe, props: &mut [u8]) -> Status {
+        self.endpoint(req.endpoint)?;
+
+        let msi = VirtioIommuProbeResvMem {
+            r#type: VIRTIO_IOMMU_PROBE_T_RESV_MEM,
+            length: (size_of::<VirtioIommuProbeResvMem>() - 4) as u16,
+            subtype: VIRTIO_IOMMU_RESV_MEM_T_MSI,
+            reserved: [0; 3],
+            start: MSI_RANGE.0,
+            end: MSI_RANGE.1,
+        };
+        props[..size_of::<VirtioIommuProbeResvMem>()].copy_from_slice(msi.as_bytes());
+
+        Ok(())
+    }
+
+    fn set_bypass(&self, bypass: bool) {
+        let _domains = self.domains.lock().unwrap();
+
+        let endpoints: Vec<_> = self.endpoints.read().unwrap().values().cloned().collect();
+        let before: Vec<_> = endpoints
+            .iter()
+            .map(|endpoint| endpoint.dma_mappings(&self.ram))
+            .collect();
+
+        self.bypass.store(bypass, Ordering::Release);
+
+        for (endpoint, before) in endpoints.iter().zip(before) {
+            if let Err(err) = endpoint.sync_listener(&self.ram, before) {
+                warn!(?err, "virtio-iommu: failed to update the dma mappings");
+            }
+        }
+    }
+
+    fn reset(&self) {
+        let mut domains = self.domains.lock().unwrap();
+
+        for endpoint in self.endpoints.read().unwrap().values() {
+            let _ = self.set_domain(endpoint, None);
+        }
+        domains.clear();
+        drop(domains);
+
+        self.set_bypass(true);
+    }
+
+    /// Returns the device-writable part of the reply, the tail is at its end
+    fn handle_request(&self, request: &[u8]) -> Vec<u8> {
+        fn parse<T: FromBytes>(request: &[u8]) -> Result<T, VirtioIommuStatus> {
+            T::read_from_prefix(request)
+                .map(|(req, _)| req)
+                .map_err(|_| VirtioIommuStatus::Inval)
+        }
+
+        let mut props = vec![];
+
+        let status = match VirtioIommuReqHead::read_from_prefix(request)
+            .ok()
+            .and_then(|(head, _)| VirtioIommuRequestType::from_repr(head.r#type))
+        {
+            Some(VirtioIommuRequestType::Attach) => {
+                parse(request).and_then(|req| self.attach(&req))
+            }
+            Some(VirtioIommuRequestType::Detach) => {
+                parse(request).and_then(|req| self.detach(&req))
+            }
+            Some(VirtioIommuRequestType::Map) => parse(request).and_then(|req| self.map(&req)),
+            Some(VirtioIommuRequestType::Unmap) => parse(request).and_then(|req| self.unmap(&req)),
+            Some(VirtioIommuRequestType::Probe) => {
+                props = vec![0; PROBE_SIZE];
+                parse(request).and_then(|req| self.probe(&req, &mut props))
+            }
+            None => Err(VirtioIommuStatus::Unsupp),
+        };
+
+        let tail = VirtioIommuReqTail {
+            status: status.err().unwrap_or(VirtioIommuStatus::Ok) as u8,
+            reserved: [0; 3],
+        };
+        props.extend_from_slice(tail.as_bytes());
+
+        props
+    }
+}
+
+struct RequestqHandler {
+    state: Arc<VirtioIommuState>,
+    memory: Arc<MemoryAddressSpace>,
+}
+
+#[async_trait]
+impl VirtqueueHandler for RequestqHandler {
+    async fn handle_desc(&self, desc_ring: &VirtqDescTableRef, desc_id: u16) -> u32 {
+        let chain = desc_ring.get_chain(desc_id);
+
+        let mut request = vec![];
+        for desc in chain
+            .iter()
+            .filter(|desc| desc.flags & VIRTQ_DESC_F_WRITE == 0)
+        {
+            let mut buf = vec![0; desc.len as usize];
+            if let Err(err) = self.memory.copy_to_slice(desc.gpa(), &mut buf) {
+                warn!(?err, "virtio-iommu: invalid request buffer");
+                return 0;
+            }
+            request.extend_from_slice(&buf);
+        }
+
+        let reply = self.state.handle_request(&request);
+
+        let mut written = 0;
+        for desc in chain
+            .iter()
+            .filter(|desc| desc.flags & VIRTQ_DESC_F_WRITE != 0)
+        {
+            if written == reply.len() {
+                break;
+            }
+
+            let len = (desc.len as usize).min(reply.len() - written);
+            if let Err(err) = self
+                .memory
+                .copy_from_slice(desc.gpa(), &reply[written..written + len])
+            {
+                warn!(?err, "virtio-iommu: invalid reply buffer");
+                return 0;
+            }
+            written += len;
+        }
+
+        written as u32
+    }
+}
+
+/// Faults are not reported, the buffers are held forever
+struct EventqHandler;
+
+#[async_trait]
+impl VirtqueueHandler for EventqHandler {
+    async fn handle_desc(&self, _desc_ring: &VirtqDescTableRef, _desc_id: u16) -> u32 {
+        std::future::pending().await
+    }
+}
+
+/// A paravirtualized iommu for the pci devices, it is only available on the mmio transport.
+/// The emulated devices translate their dma through `IommuEndpoint`, the assigned devices
+/// get the mappings through a `DmaMapListener`.
+pub struct VirtioIommu {
+    state: Arc<VirtioIommuState>,
+    memory: Arc<MemoryAddressSpace>,
+}
+
+impl VirtioIommu {
+    pub fn new(memory: Arc<MemoryAddressSpace>) -> Self {
+        let ram = memory
+            .regions()
+            .values()
+            .map(|region| region.gpa..region.gpa + region.len() as u64)
+            .collect();
+
+        VirtioIommu {
+            state: Arc::new(VirtioIommuState::new(ram)),
+            memory,
+        }
+    }
+
+    pub fn get_state(&self) -> Arc<VirtioIommuState> {
+        self.state.clone()
+    }
+
+    fn config(&self) -> VirtioIommuConfig {
+        VirtioIommuConfig {
+            page_size_mask: PAGE_SIZE_MASK,
+            input_range_start: 0,
+            input_range_end: u64::MAX,
+            domain_range_start: 0,
+            domain_range_end: u32::MAX,
+            probe_size: PROBE_SIZE as u32,
+            bypass: self.state.bypass.load(Ordering::Acquire) as u8,
+            reserved: [0; 3],
+        }
+    }
+}
+
+impl VirtioDevice for VirtioIommu {
+    const NAME: &str = "virtio-iommu";
+    const DEVICE_ID: u16 = DeviceId::Iommu as u16;
+    const DEVICE_FEATURES: u64 = (1 << VIRTIO_F_VERSION_1)
+        | (1 << VirtioIommuFeatureBitmap::INPUT_RANGE as u64)
+        | (1 << VirtioIommuFeatureBitmap::DOMAIN_RANGE as u64)
+        | (1 << VirtioIommuFeatureBitmap::MAP_UNMAP as u64)
+        | (1 << VirtioIommuFeatureBitmap::PROBE as u64)
+        | (1 << VirtioIommuFeatureBitmap::MMIO as u64)
+        | (1 << VirtioIommuFeatureBitmap::BYPASS_CONFIG as u64);
+
+    fn virtqueues_size_max(&self) -> Vec<u16> {
+        vec![REQUESTQ_SIZE_MAX, EVENTQ_SIZE_MAX]
+    }
+
+    fn reset(&mut self) {
+        self.state.reset();
+    }
+
+    fn virtqueue_handler(&self, queue_sel: u16) -> Option<Box<dyn VirtqueueHandler>> {
+        match VirtioIommuVirtqueue::from_repr(queue_sel)? {
+            VirtioIommuVirtqueue::Requestq => Some(Box::new(RequestqHandler {
+                state: self.state.clone(),
+                memory: self.memory.clone(),
+            })),
+            VirtioIommuVirtqueue::Eventq => Some(Box::new(EventqHandler)),
+        }
+    }
+
+    fn read_config(&self, offset: usize, buf: &mut [u8]) -> Result<(), VirtioError> {
+        let cfg = self.config();
+        let cfg = cfg.as_bytes();
+        if offset + buf.len() > cfg.len() {
+            return Err(VirtioError::DriverReadDeviceConfigurationInvalid);
+        }
+
+        buf.copy_from_slice(&cfg[offset..offset + buf.len()]);
+        Ok(())
+    }
+
+    fn write_config(&mut self, offset: usize, buf: &[u8]) -> Result<(), VirtioError> {
+        // Only the bypass field is writable
+        if offset != offset_of!(VirtioIommuConfig, bypass) || buf.len() != 1 {
+            return Err(VirtioError::DriverWriteDeviceConfigurationInvalid);
+        }
+
+        self.state.set_bypass(buf[0] != 0);
+
+        Ok(())
+    }
+
+    fn generate_dt_properties(&self, fdt: &mut FdtWriter) -> Result<(), vm_fdt::Error> {
+        fdt.property_u32("#iommu-cells", 1)?;
+        fdt.property_phandle(Phandle::IOMMU as u32)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex as StdMutex;
+
+    use vm_virtio::dma::DmaAccess;
+    use vm_virtio::dma::DmaTranslator;
+
+    use super::*;
+    use crate::device::virtio::virtio_iommu::endpoint::DmaMapResult;
+
+    /// Keeps the mappings it is given, `fail` rejects new mappings
+    #[derive(Default)]
+    struct Listener {
+        mappings: StdMutex<Vec<(u64, u64)>>,
+        fail: AtomicBool,
+    }
+
+    impl DmaMapListener for Listener {
+        fn map(&self, iova: u64, _gpa: u64, size: u64) -> DmaMapResult {
+            if self.fail.load(Ordering::Acquire) {
+                return Err("no space left".into());
+            }
+
+            self.mappings.lock().unwrap().push((iova, size));
+            Ok(())
+        }
+
+        fn unmap(&self, iova: u64, size: u64) -> DmaMapResult {
+            self.mappings
+                .lock()
+                .unwrap()
+                .retain(|mapping| *mapping != (iova, size));
+            Ok(())
+        }
+    }
+
+    fn state() -> VirtioIommuState {
+        let ram = 0..0x1000_0000;
+        let state = VirtioIommuState::new(vec![ram]);
+        state.add_endpoint(8, state.new_endpoint(None)).unwrap();
+        state
+    }
+
+    fn status(reply: &[u8]) -> u8 {
+        reply[reply.len() - size_of::<VirtioIommuReqTail>()]
+    }
+
+    fn attach(domain: u32, endpoint: u32, flags: u32) -> Vec<u8> {
+        VirtioIommuReqAttach {
+            head: VirtioIommuReqHead {
+                r#type: VirtioIommuRequestType::Attach as u8,
+                reserved: [0; 3],
+            },
+            domain,
+            endpoint,
+            flags,
+            reserved: [0; 4],
+        }
+        .as_bytes()
+        .to_vec()
+    }
+
+    fn map(domain: u32, virt_start: u64, virt_end: u64, phys_start: u64) -> Vec<u8> {
+        VirtioIommuReqMap {
+            head: VirtioIommuReqHead {
+                r#type: VirtioIommuRequestType::Map as u8,
+                reserved: [0; 3],
+            },
+            domain,
+            virt_start,
+            virt_end,
+            phys_start,
+            flags: VIRTIO_IOMMU_MAP_F_READ | VIRTIO_IOMMU_MAP_F_WRITE,
+        }
+        .as_bytes()
+        .to_vec()
+    }
+
+    fn unmap(domain: u32, virt_start: u64, virt_end: u64) -> Vec<u8> {
+        VirtioIommuReqUnmap {
+            head: VirtioIommuReqHead {
+                r#type: VirtioIommuRequestType::Unmap as u8,
+                reserved: [0; 3],
+            },
+            domain,
+            virt_start,
+            virt_end,
+            reserved: [0; 4],
+        }
+        .as_bytes()
+        .to_vec()
+    }
+
+    #[test]
+    fn test_map_unmap() {
+        let state = state();
+        let endpoint = state.endpoint(8).unwrap();
+
+        assert_eq!(
+            status(&state.handle_request(&attach(1, 9, 0))),
+            VirtioIommuStatus::NoEnt as u8
+        );
+        assert_eq!(
+            status(&state.handle_request(&attach(1, 8, 0))),
+            VirtioIommuStatus::Ok as u8
+        );
+        assert_eq!(endpoint.translate(0x1000, 0x10, DmaAccess::Read), None);
+
+        assert_eq!(
+            status(&state.handle_request(&map(1, 0x1000, 0x2fff, 0x8000))),
+            VirtioIommuStatus::Ok as u8
+        );
+        assert_eq!(
+            status(&state.handle_request(&map(1, 0x2000, 0x3fff, 0x8000))),
+            VirtioIommuStatus::Inval as u8
+        );
+        assert_eq!(
+            endpoint.translate(0x2010, 0x10, DmaAccess::Read),
+            Some(0x9010)
+        );
+
+        // Would split the mapping
+        assert_eq!(
+            status(&state.handle_request(&unmap(1, 0x1000, 0x1fff))),
+            VirtioIommuStatus::Range as u8
+        );
+        assert_eq!(
+            status(&state.handle_request(&unmap(1, 0, 0xffff))),
+            VirtioIommuStatus::Ok as u8
+        );
+        assert_eq!(endpoint.translate(0x2010, 0x10, DmaAccess::Read), None);
+    }
+
+    #[test]
+    fn test_probe() {
+        let state = state();
+
+        let probe = VirtioIommuReqProbe {
+            head: VirtioIommuReqHead {
+                r#type: VirtioIommuRequestType::Probe as u8,
+                reserved: [0; 3],
+            },
+            endpoint: 8,
+            reserved: [0; 64],
+        };
+        let reply = state.handle_request(probe.as_bytes());
+
+        assert_eq!(reply.len(), PROBE_SIZE + size_of::<VirtioIommuReqTail>());
+        assert_eq!(status(&reply), VirtioIommuStatus::Ok as u8);
+
+        let (msi, _) = VirtioIommuProbeResvMem::read_from_prefix(&reply).unwrap();
+        let (start, end) = (msi.start, msi.end);
+        assert_eq!(msi.subtype, VIRTIO_IOMMU_RESV_MEM_T_MSI);
+        assert_eq!((start, end), MSI_RANGE);
+    }
+
+    #[test]
+    fn test_map_wrapping_range() {
+        let state = state();
+        let endpoint = state.endpoint(8).unwrap();
+        state.handle_request(&attach(1, 8, 0));
+
+        assert_eq!(
+            status(&state.handle_request(&map(1, 0, u64::MAX, 0))),
+            VirtioIommuStatus::Range as u8
+        );
+        assert_eq!(
+            status(&state.handle_request(&map(1, 0x1000, 0x1fff, u64::MAX - 0x10))),
+            VirtioIommuStatus::Range as u8
+        );
+        assert_eq!(endpoint.translate(0x1000, 0x10, DmaAccess::Read), None);
+
+        assert_eq!(
+            status(&state.handle_request(&unmap(1, 0, u64::MAX))),
+            VirtioIommuStatus::Ok as u8
+        );
+    }
+
+    #[test]
+    fn test_map_listener_failure() {
+        let state = state();
+        let mapped = Arc::new(Listener::default());
+        let failing = Arc::new(Listener::default());
+        state
+            .add_endpoint(16, state.new_endpoint(Some(mapped.clone())))
+            .unwrap();
+        state
+            .add_endpoint(24, state.new_endpoint(Some(failing.clone())))
+            .unwrap();
+
+        state.handle_request(&attach(1, 8, 0));
+        state.handle_request(&attach(1, 16, 0));
+        state.handle_request(&attach(1, 24, 0));
+        failing.fail.store(true, Ordering::Release);
+
+        assert_eq!(
+            status(&state.handle_request(&map(1, 0x1000, 0x1fff, 0x8000))),
+            VirtioIommuStatus::DevErr as u8
+        );
+
+        // Neither the domain nor the other listeners keep the mapping
+        let endpoint = state.endpoint(8).unwrap();
+        assert_eq!(endpoint.translate(0x1000, 0x10, DmaAccess::Read), None);
+        assert!(mapped.mappings.lock().unwrap().is_empty());
+    }
+}
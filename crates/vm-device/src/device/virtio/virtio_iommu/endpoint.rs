@@ -0,0 +1,302 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::sync::Arc;
+use std::sync::RwLock;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+use tracing::warn;
+use vm_virtio::dma::DmaAccess;
+use vm_virtio::dma::DmaTranslator;
+use vm_virtio::types::device::iommu::VIRTIO_IOMMU_MAP_F_MMIO;
+use vm_virtio::types::device::iommu::VIRTIO_IOMMU_MAP_F_READ;
+use vm_virtio::types::device::iommu::VIRTIO_IOMMU_MAP_F_WRITE;
+
+pub type DmaMapResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+/// Receives the mappings of an endpoint which does the dma by itself, e.g. a vfio device
+pub trait DmaMapListener: Send + Sync {
+    fn map(&self, iova: u64, gpa: u64, size: u64) -> DmaMapResult;
+
+    fn unmap(&self, iova: u64, size: u64) -> DmaMapResult;
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct Mapping {
+    /// Inclusive
+    pub(crate) end: u64,
+    pub(crate) phys: u64,
+    pub(crate) flags: u32,
+}
+
+impl Mapping {
+    /// `None` for a mapping of the whole 64-bit space
+    pub(crate) fn size(&self, start: u64) -> Option<u64> {
+        (self.end - start).checked_add(1)
+    }
+}
+
+pub(crate) struct IommuDomain {
+    /// The domain has an identity mapping of the guest memory instead of its own mappings
+    pub(crate) bypass: bool,
+    /// iova |-> mapping
+    pub(crate) mappings: RwLock<BTreeMap<u64, Mapping>>,
+}
+
+impl IommuDomain {
+    pub(crate) fn new(bypass: bool) -> Self {
+        IommuDomain {
+            bypass,
+            mappings: Default::default(),
+        }
+    }
+
+    fn translate(&self, iova: u64, len: u64, access: DmaAccess) -> Option<u64> {
+        let flag = match access {
+            DmaAccess::Read => VIRTIO_IOMMU_MAP_F_READ,
+            DmaAccess::Write => VIRTIO_IOMMU_MAP_F_WRITE,
+        };
+        let mappings = self.mappings.read().unwrap();
+
+        let (start, mapping) = mappings.range(..=iova).next_back()?;
+        if mapping.end < iova || mapping.flags & flag == 0 {
+            return None;
+        }
+        let gpa = mapping.phys.checked_add(iova - start)?;
+
+        // The buffer may cross several mappings as long as they are contiguous in both spaces
+        let last = iova.checked_add(len.max(1) - 1)?;
+        let mut end = mapping.end;
+        let mut phys_end = mapping.phys.checked_add(mapping.end - start)?;
+        while end < last {
+            let next_start = end.checked_add(1)?;
+            let next = mappings.get(&next_start)?;
+            if phys_end.checked_add(1) != Some(next.phys) || next.flags & flag == 0 {
+                return None;
+            }
+
+            phys_end = next.phys.checked_add(next.end - next_start)?;
+            end = next.end;
+        }
+
+        Some(gpa)
+    }
+}
+
+/// A pci device behind the iommu, identified by its requester id
+pub struct IommuEndpoint {
+    pub(crate) domain: RwLock<Option<Arc<IommuDomain>>>,
+    /// The endpoints which are not attached bypass the iommu if it is set
+    bypass: Arc<AtomicBool>,
+    listener: Option<Arc<dyn DmaMapListener>>,
+}
+
+impl IommuEndpoint {
+    pub(crate) fn new(bypass: Arc<AtomicBool>, listener: Option<Arc<dyn DmaMapListener>>) -> Self {
+        IommuEndpoint {
+            domain: Default::default(),
+            bypass,
+            listener,
+        }
+    }
+
+    pub(crate) fn listener(&self) -> Option<&Arc<dyn DmaMapListener>> {
+        self.listener.as_ref()
+    }
+
+    pub(crate) fn is_attached_to(&self, domain: &Arc<IommuDomain>) -> bool {
+        self.domain
+            .read()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|attached| Arc::ptr_eq(attached, domain))
+    }
+
+    /// (iova, gpa, size) of the ranges the endpoint can access, only the listener needs them
+    pub(crate) fn dma_mappings(&self, ram: &[Range<u64>]) -> Vec<(u64, u64, u64)> {
+        if self.listener.is_none() {
+            return vec![];
+        }
+
+        let identity = || {
+            ram.iter()
+                .map(|range| (range.start, range.start, range.end - range.start))
+                .collect()
+        };
+
+        match &*self.domain.read().unwrap() {
+            Some(domain) if domain.bypass => identity(),
+            Some(domain) => domain
+                .mappings
+                .read()
+                .unwrap()
+                .iter()
+                .filter(|(_, mapping)| mapping.flags & VIRTIO_IOMMU_MAP_F_MMIO == 0)
+                .filter_map(|(start, mapping)| Some((*start, mapping.phys, mapping.size(*start)?)))
+                .collect(),
+            None if self.bypass.load(Ordering::Acquire) => identity(),
+            None => vec![],
+        }
+    }
+
+    /// Replace the mappings of the listener by the current ones, `before` is the
+    /// result of `dma_mappings` before the change.
+    pub(crate) fn sync_listener(
+        &self,
+        ram: &[Range<u64>],
+        before: Vec<(u64, u64, u64)>,
+    ) -> DmaMapResult {
+        let Some(listener) = &self.listener else {
+            return Ok(());
+        };
+
+        for (iova, _, size) in before {
+            if let Err(err) = listener.unmap(iova, size) {
+                warn!(?err, iova, size, "virtio-iommu: failed to unmap");
+            }
+        }
+
+        for (iova, gpa, size) in self.dma_mappings(ram) {
+            listener.map(iova, gpa, size)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl DmaTranslator for IommuEndpoint {
+    fn translate(&self, iova: u64, len: u64, access: DmaAccess) -> Option<u64> {
+        match &*self.domain.read().unwrap() {
+            Some(domain) if domain.bypass => Some(iova),
+            Some(domain) => domain.translate(iova, len, access),
+            None => self.bypass.load(Ordering::Acquire).then_some(iova),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn domain(mappings: &[(u64, u64, u64, u32)]) -> Arc<IommuDomain> {
+        let domain = IommuDomain::new(false);
+        for (start, end, phys, flags) in mappings {
+            domain.mappings.write().unwrap().insert(
+                *start,
+                Mapping {
+                    end: *end,
+                    phys: *phys,
+                    flags: *flags,
+                },
+            );
+        }
+
+        Arc::new(domain)
+    }
+
+    #[test]
+    fn test_translate() {
+        let endpoint = IommuEndpoint::new(Arc::new(AtomicBool::new(false)), None);
+        assert_eq!(endpoint.translate(0x1000, 0x10, DmaAccess::Read), None);
+
+        let rw = VIRTIO_IOMMU_MAP_F_READ | VIRTIO_IOMMU_MAP_F_WRITE;
+        *endpoint.domain.write().unwrap() = Some(domain(&[
+            (0x1000, 0x1fff, 0x8000, rw),
+            (0x2000, 0x2fff, 0x9000, rw),
+            (0x3000, 0x3fff, 0x5000, rw),
+        ]));
+
+        assert_eq!(
+            endpoint.translate(0x1010, 0x10, DmaAccess::Read),
+            Some(0x8010)
+        );
+        // Contiguous in both spaces
+        assert_eq!(
+            endpoint.translate(0x1800, 0x1000, DmaAccess::Read),
+            Some(0x8800)
+        );
+        // The third mapping does not follow the second one
+        assert_eq!(endpoint.translate(0x2800, 0x1000, DmaAccess::Read), None);
+        assert_eq!(endpoint.translate(0x4000, 0x10, DmaAccess::Read), None);
+        assert_eq!(endpoint.translate(0x800, 0x10, DmaAccess::Read), None);
+    }
+
+    #[test]
+    fn test_translate_permission() {
+        let endpoint = IommuEndpoint::new(Arc::new(AtomicBool::new(false)), None);
+        *endpoint.domain.write().unwrap() = Some(domain(&[
+            (0x1000, 0x1fff, 0x8000, VIRTIO_IOMMU_MAP_F_READ),
+            (
+                0x2000,
+                0x2fff,
+                0x9000,
+                VIRTIO_IOMMU_MAP_F_READ | VIRTIO_IOMMU_MAP_F_WRITE,
+            ),
+            (0x3000, 0x3fff, 0xa000, VIRTIO_IOMMU_MAP_F_WRITE),
+        ]));
+
+        assert_eq!(
+            endpoint.translate(0x1010, 0x10, DmaAccess::Read),
+            Some(0x8010)
+        );
+        assert_eq!(endpoint.translate(0x1010, 0x10, DmaAccess::Write), None);
+        assert_eq!(
+            endpoint.translate(0x3010, 0x10, DmaAccess::Write),
+            Some(0xa010)
+        );
+        assert_eq!(endpoint.translate(0x3010, 0x10, DmaAccess::Read), None);
+
+        // Every mapping the buffer crosses must allow the access
+        assert_eq!(
+            endpoint.translate(0x1800, 0x1000, DmaAccess::Read),
+            Some(0x8800)
+        );
+        assert_eq!(
+            endpoint.translate(0x2800, 0x1000, DmaAccess::Write),
+            Some(0x9800)
+        );
+        assert_eq!(endpoint.translate(0x1800, 0x1000, DmaAccess::Write), None);
+        assert_eq!(endpoint.translate(0x2800, 0x1000, DmaAccess::Read), None);
+    }
+
+    #[test]
+    fn test_translate_bypass() {
+        let bypass = Arc::new(AtomicBool::new(true));
+        let endpoint = IommuEndpoint::new(bypass.clone(), None);
+        assert_eq!(
+            endpoint.translate(0x1000, 0x10, DmaAccess::Read),
+            Some(0x1000)
+        );
+
+        bypass.store(false, Ordering::Release);
+        assert_eq!(endpoint.translate(0x1000, 0x10, DmaAccess::Read), None);
+
+        *endpoint.domain.write().unwrap() = Some(Arc::new(IommuDomain::new(true)));
+        assert_eq!(
+            endpoint.translate(0x1000, 0x10, DmaAccess::Read),
+            Some(0x1000)
+        );
+    }
+
+    #[test]
+    fn test_translate_overflow() {
+        let endpoint = IommuEndpoint::new(Arc::new(AtomicBool::new(false)), None);
+
+        let rw = VIRTIO_IOMMU_MAP_F_READ | VIRTIO_IOMMU_MAP_F_WRITE;
+        *endpoint.domain.write().unwrap() = Some(domain(&[
+            (0x1000, 0x1fff, u64::MAX - 0xfff, rw),
+            (0x2000, 0x2fff, 0, rw),
+            (0x3000, u64::MAX, u64::MAX - 0x100, rw),
+        ]));
+
+        assert_eq!(
+            endpoint.translate(0x1ff0, 0x10, DmaAccess::Read),
+            Some(u64::MAX - 0xf)
+        );
+        // The next mapping would follow the end of the physical space
+        assert_eq!(endpoint.translate(0x1ff0, 0x20, DmaAccess::Read), None);
+        // The physical end of the mapping wraps
+        assert_eq!(endpoint.translate(0x3000, 0x10, DmaAccess::Read), None);
+        assert_eq!(endpoint.translate(u64::MAX, 1, DmaAccess::Read), None);
+    }
+}
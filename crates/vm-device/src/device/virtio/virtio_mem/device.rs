@@ -0,0 +1,866 @@
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::io::Write;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tokio::sync::Mutex;
+use tracing::error;
+use tracing::warn;
+use vm_core::device::error::DeviceSnapshotError;
+use vm_core::virtualization::vm::HypervisorVm;
+use vm_core::virtualization::vm::SetUserMemoryRegionFlags;
+use vm_core::virtualization::vm::error::VmError;
+use vm_mm::manager::MemoryAddressSpace;
+use vm_pci::device::interrupt::legacy::InterruptPin;
+use vm_snapshot::helper::read_u8;
+use vm_snapshot::helper::read_usize;
+use vm_snapshot::helper::write_u8;
+use vm_snapshot::helper::write_usize;
+use vm_virtio::device::VirtioDevice;
+use vm_virtio::device::virtqueue::VirtqueueHandler;
+use vm_virtio::result::VirtioError;
+use vm_virtio::transport::pci::VirtioPciDevice;
+use vm_virtio::types::device::mem::VirtioMemBlockState;
+use vm_virtio::types::device::mem::VirtioMemConfig;
+use vm_virtio::types::device::mem::VirtioMemFeatureBitmap;
+use vm_virtio::types::device::mem::VirtioMemRequest;
+use vm_virtio::types::device::mem::VirtioMemRequestType;
+use vm_virtio::types::device::mem::VirtioMemResponse;
+use vm_virtio::types::device::mem::VirtioMemResponseType;
+use vm_virtio::types::device::mem::VirtioMemVirtqueue;
+use vm_virtio::types::device_features::VIRTIO_F_VERSION_1;
+use vm_virtio::types::device_id::DeviceId;
+use vm_virtio::virtqueue::virtq_desc_table::VIRTQ_DESC_F_WRITE;
+use vm_virtio::virtqueue::virtq_desc_table::VirtqDescTableRef;
+use zerocopy::FromBytes;
+use zerocopy::IntoBytes;
+
+const GUESTQ_SIZE_MAX: u16 = 128;
+
+/// Linux adds hotplugged memory in memory blocks (128MB on both x86_64 and
+/// arm64 with 4K pages), so the region must be aligned to them.
+pub const VIRTIO_MEM_REGION_ALIGN: usize = 128 << 20;
+
+struct VirtioMemBlocks {
+    vm: Arc<dyn HypervisorVm>,
+    addr: u64,
+    hva: u64,
+    block_size: u64,
+    plugged: Vec<bool>,
+    /// gpa |-> len, one hypervisor mapping per run of plugged blocks
+    mappings: BTreeMap<u64, u64>,
+}
+
+impl VirtioMemBlocks {
+    fn map(&mut self, gpa: u64, len: u64) -> Result<(), VmError> {
+        self.vm.set_user_memory_region(
+            self.hva + (gpa - self.addr),
+            gpa,
+            len as usize,
+            SetUserMemoryRegionFlags::ReadWriteExec,
+        )?;
+        self.mappings.insert(gpa, len);
+
+        Ok(())
+    }
+
+    fn unmap(&mut self, gpa: u64) -> Result<(), VmError> {
+        let len = self.mappings[&gpa];
+        self.vm.remove_user_memory_region(gpa, len as usize)?;
+        self.mappings.remove(&gpa);
+
+        Ok(())
+    }
+
+    fn state(&self, first: usize, nb_blocks: usize) -> VirtioMemBlockState {
+        let blocks = &self.plugged[first..first + nb_blocks];
+
+        if blocks.iter().all(|plugged| *plugged) {
+            VirtioMemBlockState::Plugged
+        } else if blocks.iter().all(|plugged| !*plugged) {
+            VirtioMemBlockState::Unplugged
+        } else {
+            VirtioMemBlockState::Mixed
+        }
+    }
+
+    fn plug(&mut self, first: usize, nb_blocks: usize) -> Result<(), VmError> {
+        let mut start = self.addr + first as u64 * self.block_size;
+        let mut end = start + nb_blocks as u64 * self.block_size;
+
+        // Merge with the adjacent runs to keep the number of slots low
+        if let Some((&gpa, &len)) = self.mappings.range(..start).next_back()
+            && gpa + len == start
+        {
+            self.unmap(gpa)?;
+            start = gpa;
+        }
+
+        if let Some(&len) = self.mappings.get(&end) {
+            self.unmap(end)?;
+            end += len;
+        }
+
+        self.map(start, end - start)?;
+        self.plugged[first..first + nb_blocks].fill(true);
+
+        Ok(())
+    }
+
+    fn unplug(&mut self, first: usize, nb_blocks: usize) -> Result<(), VmError> {
+        let start = self.addr + first as u64 * self.block_size;
+        let end = start + nb_blocks as u64 * self.block_size;
+
+        // All blocks are plugged, hence they are covered by a single run
+        let (gpa, len) = self
+            .mappings
+            .range(..=start)
+            .next_back()
+            .map(|(gpa, len)| (*gpa, *len))
+            .unwrap();
+
+        self.unmap(gpa)?;
+        if gpa < start {
+            self.map(gpa, start - gpa)?;
+        }
+        if end < gpa + len {
+            self.map(end, gpa + len - end)?;
+        }
+        self.plugged[first..first + nb_blocks].fill(false);
+
+        Ok(())
+    }
+
+    /// Installs one mapping per run of plugged blocks, e.g. after the bitmap was loaded
+    fn remap(&mut self) -> Result<(), VmError> {
+        let mappings: Vec<u64> = self.mappings.keys().copied().collect();
+        for gpa in mappings {
+            self.unmap(gpa)?;
+        }
+
+        let mut first = 0;
+        while first < self.plugged.len() {
+            let len = self.plugged[first..]
+                .iter()
+                .take_while(|plugged| **plugged == self.plugged[first])
+                .count();
+
+            if self.plugged[first] {
+                let gpa = self.addr + first as u64 * self.block_size;
+                self.map(gpa, len as u64 * self.block_size)?;
+            }
+
+            first += len;
+        }
+
+        Ok(())
+    }
+
+    fn unplug_all(&mut self) -> Result<(), VmError> {
+        let mappings: Vec<u64> = self.mappings.keys().copied().collect();
+        for gpa in mappings {
+            self.unmap(gpa)?;
+        }
+        self.plugged.fill(false);
+
+        Ok(())
+    }
+}
+
+struct GuestqHandler {
+    cfg: Arc<Mutex<VirtioMemConfig>>,
+    blocks: Arc<Mutex<VirtioMemBlocks>>,
+    memory: Arc<MemoryAddressSpace>,
+}
+
+fn discard(memory: &MemoryAddressSpace, gpa: u64, len: u64) {
+    if let Err(err) = memory.discard(gpa, len as usize) {
+        warn!(
+            ?err,
+            gpa, len, "virtio-mem: failed to release unplugged memory"
+        );
+    }
+}
+
+fn unplug_all(
+    cfg: &mut VirtioMemConfig,
+    blocks: &mut VirtioMemBlocks,
+    memory: &MemoryAddressSpace,
+) -> Result<(), VmError> {
+    blocks.unplug_all()?;
+    discard(memory, cfg.addr, cfg.region_size);
+    cfg.plugged_size = 0;
+
+    Ok(())
+}
+
+impl GuestqHandler {
+    async fn handle_request(&self, req: &VirtioMemRequest) -> VirtioMemResponse {
+        let mut blocks = self.blocks.lock().await;
+        let mut cfg = self.cfg.lock().await;
+
+        let request_type = req.r#type;
+        let Some(r#type) = VirtioMemRequestType::from_repr(request_type) else {
+            warn!(r#type = request_type, "virtio-mem: unknown request");
+            return VirtioMemResponse::new(VirtioMemResponseType::Error);
+        };
+
+        if let VirtioMemRequestType::UnplugAll = r#type {
+            if let Err(err) = unplug_all(&mut cfg, &mut blocks, &self.memory) {
+                error!(?err, "virtio-mem: failed to unplug all blocks");
+                return VirtioMemResponse::new(VirtioMemResponseType::Error);
+            }
+
+            return VirtioMemResponse::new(VirtioMemResponseType::Ack);
+        }
+
+        let (addr, nb_blocks) = (req.addr, req.nb_blocks as u64);
+        let size = nb_blocks * cfg.block_size;
+        if nb_blocks == 0
+            || !addr.is_multiple_of(cfg.block_size)
+            || addr < cfg.addr
+            || addr
+                .checked_add(size)
+                .is_none_or(|end| end > cfg.addr + cfg.usable_region_size)
+        {
+            warn!(addr, nb_blocks, "virtio-mem: invalid block range");
+            return VirtioMemResponse::new(VirtioMemResponseType::Error);
+        }
+
+        let first = ((addr - cfg.addr) / cfg.block_size) as usize;
+        let nb_blocks = nb_blocks as usize;
+
+        match r#type {
+            VirtioMemRequestType::Plug => {
+                if blocks.state(first, nb_blocks) != VirtioMemBlockState::Unplugged {
+                    return VirtioMemResponse::new(VirtioMemResponseType::Error);
+                }
+
+                if cfg.plugged_size + size > cfg.requested_size {
+                    return VirtioMemResponse::new(VirtioMemResponseType::Nack);
+                }
+
+                if let Err(err) = blocks.plug(first, nb_blocks) {
+                    error!(?err, addr, nb_blocks, "virtio-mem: failed to plug blocks");
+                    return VirtioMemResponse::new(VirtioMemResponseType::Error);
+                }
+                cfg.plugged_size += size;
+
+                VirtioMemResponse::new(VirtioMemResponseType::Ack)
+            }
+            VirtioMemRequestType::Unplug => {
+                if blocks.state(first, nb_blocks) != VirtioMemBlockState::Plugged {
+                    return VirtioMemResponse::new(VirtioMemResponseType::Error);
+                }
+
+                if let Err(err) = blocks.unplug(first, nb_blocks) {
+                    error!(?err, addr, nb_blocks, "virtio-mem: failed to unplug blocks");
+                    return VirtioMemResponse::new(VirtioMemResponseType::Error);
+                }
+                discard(&self.memory, addr, size);
+                cfg.plugged_size -= size;
+
+                VirtioMemResponse::new(VirtioMemResponseType::Ack)
+            }
+            VirtioMemRequestType::State => VirtioMemResponse::state(blocks.state(first, nb_blocks)),
+            VirtioMemRequestType::UnplugAll => unreachable!(),
+        }
+    }
+}
+
+#[async_trait]
+impl VirtqueueHandler for GuestqHandler {
+    async fn handle_desc(&self, desc_ring: &VirtqDescTableRef, desc_id: u16) -> u32 {
+        let chain = desc_ring.get_chain(desc_id);
+
+        let request = chain
+            .iter()
+            .find(|desc| desc.flags & VIRTQ_DESC_F_WRITE == 0);
+        let response = chain
+            .iter()
+            .find(|desc| desc.flags & VIRTQ_DESC_F_WRITE != 0);
+        let (Some(request), Some(response)) = (request, response) else {
+            warn!("virtio-mem: malformed request");
+            return 0;
+        };
+
+        let mut buf = [0u8; size_of::<VirtioMemRequest>()];
+        if (request.len as usize) < buf.len()
+            || (response.len as usize) < size_of::<VirtioMemResponse>()
+        {
+            warn!("virtio-mem: request or response buffer too small");
+            return 0;
+        }
+
+        if let Err(err) = self.memory.copy_to_slice(request.gpa(), &mut buf) {
+            warn!(?err, "virtio-mem: invalid request buffer");
+            return 0;
+        }
+        let req = VirtioMemRequest::read_from_bytes(&buf).unwrap();
+
+        let resp = self.handle_request(&req).await;
+
+        if let Err(err) = self.memory.copy_from_slice(response.gpa(), resp.as_bytes()) {
+            warn!(?err, "virtio-mem: invalid response buffer");
+            return 0;
+        }
+
+        size_of::<VirtioMemResponse>() as u32
+    }
+}
+
+/// Unplugs all the blocks on a system reset, unlike a device reset which
+/// leaves them to the driver
+pub struct VirtioMemSystemReset {
+    cfg: Arc<Mutex<VirtioMemConfig>>,
+    blocks: Arc<Mutex<VirtioMemBlocks>>,
+    memory: Arc<MemoryAddressSpace>,
+}
+
+impl VirtioMemSystemReset {
+    pub async fn reset(&self) -> Result<(), VmError> {
+        let mut blocks = self.blocks.lock().await;
+        let mut cfg = self.cfg.lock().await;
+
+        unplug_all(&mut cfg, &mut blocks, &self.memory)
+    }
+}
+
+pub struct VirtioMem {
+    cfg: Arc<Mutex<VirtioMemConfig>>,
+    blocks: Arc<Mutex<VirtioMemBlocks>>,
+    memory: Arc<MemoryAddressSpace>,
+}
+
+impl VirtioMem {
+    /// `addr..addr + region_size` must already be backed in `memory`, the
+    /// blocks are only mapped into the guest once they are plugged.
+    pub fn new(
+        vm: Arc<dyn HypervisorVm>,
+        memory: Arc<MemoryAddressSpace>,
+        addr: u64,
+        region_size: usize,
+        block_size: usize,
+        requested_size: usize,
+    ) -> Result<Self, VirtioError> {
+        if !block_size.is_power_of_two() || block_size < 4 << 10 {
+            return Err(VirtioError::InvalidVirtioMemSize {
+                name: "block_size",
+                size: block_size,
+            });
+        }
+
+        if region_size == 0
+            || !region_size.is_multiple_of(VIRTIO_MEM_REGION_ALIGN)
+            || !region_size.is_multiple_of(block_size)
+        {
+            return Err(VirtioError::InvalidVirtioMemSize {
+                name: "region_size",
+                size: region_size,
+            });
+        }
+
+        if requested_size > region_size || !requested_size.is_multiple_of(block_size) {
+            return Err(VirtioError::InvalidVirtioMemSize {
+                name: "requested_size",
+                size: requested_size,
+            });
+        }
+
+        let hva = memory
+            .gpa_to_hva(addr)
+            .map_err(|_| VirtioError::AccessInvalidGpa(addr))?;
+
+        let cfg = VirtioMemConfig {
+            block_size: block_size as u64,
+            addr,
+            region_size: region_size as u64,
+            usable_region_size: region_size as u64,
+            requested_size: requested_size as u64,
+            ..Default::default()
+        };
+
+        let blocks = VirtioMemBlocks {
+            vm,
+            addr,
+            hva: hva as u64,
+            block_size: block_size as u64,
+            plugged: vec![false; region_size / block_size],
+            mappings: BTreeMap::new(),
+        };
+
+        Ok(VirtioMem {
+            cfg: Arc::new(Mutex::new(cfg)),
+            blocks: Arc::new(Mutex::new(blocks)),
+            memory,
+        })
+    }
+
+    pub fn get_cfg(&self) -> Arc<Mutex<VirtioMemConfig>> {
+        self.cfg.clone()
+    }
+
+    pub fn system_reset(&self) -> VirtioMemSystemReset {
+        VirtioMemSystemReset {
+            cfg: self.cfg.clone(),
+            blocks: self.blocks.clone(),
+            memory: self.memory.clone(),
+        }
+    }
+}
+
+impl VirtioDevice for VirtioMem {
+    const NAME: &str = "virtio-mem";
+    const DEVICE_ID: u16 = DeviceId::Mem as u16;
+    // Unplugged blocks are never mapped into the guest and are discarded once unplugged
+    const DEVICE_FEATURES: u64 =
+        (1 << VIRTIO_F_VERSION_1) | (1 << VirtioMemFeatureBitmap::UNPLUGGED_INACCESSIBLE as u64);
+
+    fn virtqueues_size_max(&self) -> Vec<u16> {
+        vec![GUESTQ_SIZE_MAX]
+    }
+
+    // Plugged memory is preserved across resets, the driver unplugs it
+    // during initialization.
+    fn reset(&mut self) {}
+
+    fn virtqueue_handler(&self, queue_sel: u16) -> Option<Box<dyn VirtqueueHandler>> {
+        match VirtioMemVirtqueue::from_repr(queue_sel)? {
+            VirtioMemVirtqueue::Guestq => Some(Box::new(GuestqHandler {
+                cfg: self.cfg.clone(),
+                blocks: self.blocks.clone(),
+                memory: self.memory.clone(),
+            })),
+        }
+    }
+
+    fn read_config(&self, offset: usize, buf: &mut [u8]) -> Result<(), VirtioError> {
+        let cfg = self.cfg.blocking_lock();
+        let cfg = cfg.as_bytes();
+        if offset + buf.len() > cfg.len() {
+            return Err(VirtioError::DriverReadDeviceConfigurationInvalid);
+        }
+
+        buf.copy_from_slice(&cfg[offset..offset + buf.len()]);
+        Ok(())
+    }
+
+    fn write_config(&mut self, _offset: usize, _buf: &[u8]) -> Result<(), VirtioError> {
+        Err(VirtioError::DriverWriteDeviceConfigurationInvalid)
+    }
+
+    fn pause(&self) -> Result<(), DeviceSnapshotError> {
+        Ok(())
+    }
+
+    fn resume(&self) -> Result<(), DeviceSnapshotError> {
+        Ok(())
+    }
+
+    fn save(&self, writer: &mut dyn Write) -> Result<(), DeviceSnapshotError> {
+        writer.write_all(self.cfg.blocking_lock().as_bytes())?;
+
+        // One bit per block
+        let blocks = self.blocks.blocking_lock();
+        write_usize(writer, blocks.plugged.len())?;
+        for chunk in blocks.plugged.chunks(8) {
+            let byte = chunk
+                .iter()
+                .enumerate()
+                .fold(0u8, |byte, (i, plugged)| byte | ((*plugged as u8) << i));
+            write_u8(writer, byte)?;
+        }
+
+        Ok(())
+    }
+
+    fn load(&mut self, reader: &mut dyn Read) -> Result<(), DeviceSnapshotError> {
+        reader.read_exact(self.cfg.blocking_lock().as_mut_bytes())?;
+
+        let mut blocks = self.blocks.blocking_lock();
+        let len = read_usize(reader)?;
+        if len != blocks.plugged.len() {
+            return Err(DeviceSnapshotError::Deserde(format!(
+                "virtio-mem has {} blocks but the snapshot has {len}",
+                blocks.plugged.len()
+            )));
+        }
+
+        for i in (0..len).step_by(8) {
+            let byte = read_u8(reader)?;
+            for (j, plugged) in blocks.plugged[i..len.min(i + 8)].iter_mut().enumerate() {
+                *plugged = byte & (1 << j) != 0;
+            }
+        }
+
+        blocks.remap().map_err(|err| DeviceSnapshotError::Restore {
+            name: Self::NAME.to_string(),
+            err: err.to_string(),
+        })
+    }
+}
+
+impl VirtioPciDevice for VirtioMem {
+    const DEVICE_SPECIFICATION_CONFIGURATION_LEN: usize = size_of::<VirtioMemConfig>();
+    const CLASS_CODE: u32 = 0xff0000;
+    const IRQ_PIN: u8 = InterruptPin::INTA as u8;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex as StdMutex;
+
+    use tokio::runtime::Runtime;
+    use vm_core::arch::irq::InterruptController;
+    use vm_core::cpu::vm_exit::VmExit;
+    use vm_core::interrupt_manager::InterruptManager;
+    use vm_core::virtualization::vcpu::HypervisorVcpu;
+    #[cfg(target_os = "linux")]
+    use vm_core::virtualization::vm::IoEventAddress;
+    #[cfg(target_os = "linux")]
+    use vm_core::virtualization::vm::IoEventDatamatch;
+    use vm_mm::allocator::Allocator;
+    use vm_mm::allocator::mmap_allocator::MmapAllocator;
+    use vm_mm::region::MemoryRegion;
+    use vm_utils::cpu_topology::CpuTopology;
+    #[cfg(target_os = "linux")]
+    use vmm_sys_util::eventfd::EventFd;
+
+    use super::*;
+
+    const ADDR: u64 = 0x1_0000_0000;
+    const BLOCK_SIZE: u64 = 2 << 20;
+
+    /// Keeps the guest mappings, gpa |-> len
+    #[derive(Default)]
+    struct MappingVm(StdMutex<BTreeMap<u64, usize>>);
+
+    impl HypervisorVm for MappingVm {
+        fn create_vcpu(
+            &self,
+            _vcpu_id: u64,
+            _cpu_topology: &CpuTopology,
+            _mm: Arc<MemoryAddressSpace>,
+            _vm_exit_handler: Arc<dyn VmExit>,
+        ) -> Result<Box<dyn HypervisorVcpu>, VmError> {
+            unreachable!()
+        }
+
+        fn create_irq_chip(&self) -> Result<Box<dyn InterruptController>, VmError> {
+            unreachable!()
+        }
+
+        fn create_irq_manager(&self) -> Result<InterruptManager, VmError> {
+            unreachable!()
+        }
+
+        fn set_user_memory_region(
+            &self,
+            _userspace_addr: u64,
+            guest_phys_addr: u64,
+            memory_size: usize,
+            _flags: SetUserMemoryRegionFlags,
+        ) -> Result<(), VmError> {
+            self.0.lock().unwrap().insert(guest_phys_addr, memory_size);
+            Ok(())
+        }
+
+        fn remove_user_memory_region(
+            &self,
+            guest_phys_addr: u64,
+            memory_size: usize,
+        ) -> Result<(), VmError> {
+            assert_eq!(
+                self.0.lock().unwrap().remove(&guest_phys_addr),
+                Some(memory_size)
+            );
+            Ok(())
+        }
+
+        #[cfg(target_os = "linux")]
+        fn set_irqfd(&self, _fd: &EventFd, _gsi: u32) -> Result<(), VmError> {
+            unreachable!()
+        }
+
+        #[cfg(target_os = "linux")]
+        fn del_irqfd(&self, _fd: &EventFd, _gsi: u32) -> Result<(), VmError> {
+            unreachable!()
+        }
+
+        #[cfg(target_os = "linux")]
+        fn set_irqfd_with_resample(
+            &self,
+            _fd: &EventFd,
+            _resamplefd: &EventFd,
+            _gsi: u32,
+        ) -> Result<(), VmError> {
+            unreachable!()
+        }
+
+        #[cfg(target_os = "linux")]
+        fn set_gsi_routing(&self) -> Result<(), VmError> {
+            unreachable!()
+        }
+
+        #[cfg(target_os = "linux")]
+        fn register_ioeventfd(
+            &self,
+            _fd: &EventFd,
+            _addr: IoEventAddress,
+            _datamatch: IoEventDatamatch,
+        ) -> Result<(), VmError> {
+            unreachable!()
+        }
+
+        #[cfg(target_os = "linux")]
+        fn unregister_ioeventfd(
+            &self,
+            _fd: &EventFd,
+            _addr: IoEventAddress,
+            _datamatch: IoEventDatamatch,
+        ) -> Result<(), VmError> {
+            unreachable!()
+        }
+
+        fn secondary_cpu_should_run_on_booting(&self) -> bool {
+            false
+        }
+    }
+
+    fn virtio_mem(vm: Arc<MappingVm>) -> VirtioMem {
+        let mut memory = MemoryAddressSpace::default();
+        let region = MmapAllocator.alloc(VIRTIO_MEM_REGION_ALIGN, None).unwrap();
+        assert!(
+            memory
+                .try_insert(MemoryRegion::new(ADDR, Box::new(region)))
+                .is_ok()
+        );
+
+        VirtioMem::new(
+            vm,
+            Arc::new(memory),
+            ADDR,
+            VIRTIO_MEM_REGION_ALIGN,
+            BLOCK_SIZE as usize,
+            VIRTIO_MEM_REGION_ALIGN / 2,
+        )
+        .unwrap()
+    }
+
+    fn handler(dev: &VirtioMem) -> GuestqHandler {
+        GuestqHandler {
+            cfg: dev.cfg.clone(),
+            blocks: dev.blocks.clone(),
+            memory: dev.memory.clone(),
+        }
+    }
+
+    fn request(r#type: VirtioMemRequestType, block: u64, nb_blocks: u16) -> VirtioMemRequest {
+        VirtioMemRequest {
+            r#type: r#type as u16,
+            padding: [0; 3],
+            addr: ADDR + block * BLOCK_SIZE,
+            nb_blocks,
+            padding_1: [0; 3],
+        }
+    }
+
+    fn send(
+        runtime: &Runtime,
+        handler: &GuestqHandler,
+        r#type: VirtioMemRequestType,
+        block: u64,
+        nb_blocks: u16,
+    ) -> (u16, u16) {
+        let resp = runtime.block_on(handler.handle_request(&request(r#type, block, nb_blocks)));
+        (resp.r#type, resp.state)
+    }
+
+    fn mappings(vm: &MappingVm) -> Vec<(u64, usize)> {
+        vm.0.lock()
+            .unwrap()
+            .iter()
+            .map(|(gpa, len)| ((gpa - ADDR) / BLOCK_SIZE, len / BLOCK_SIZE as usize))
+            .collect()
+    }
+
+    const ACK: u16 = VirtioMemResponseType::Ack as u16;
+    const NACK: u16 = VirtioMemResponseType::Nack as u16;
+    const ERROR: u16 = VirtioMemResponseType::Error as u16;
+
+    #[test]
+    fn test_plug_and_unplug() {
+        let runtime = Runtime::new().unwrap();
+        let vm = Arc::new(MappingVm::default());
+        let dev = virtio_mem(vm.clone());
+        let handler = handler(&dev);
+
+        assert_eq!(
+            send(&runtime, &handler, VirtioMemRequestType::Plug, 4, 2),
+            (ACK, 0)
+        );
+        assert_eq!(mappings(&vm), [(4, 2)]);
+        assert_eq!(
+            send(&runtime, &handler, VirtioMemRequestType::State, 4, 2),
+            (ACK, VirtioMemBlockState::Plugged as u16)
+        );
+        assert_eq!(
+            send(&runtime, &handler, VirtioMemRequestType::State, 3, 2),
+            (ACK, VirtioMemBlockState::Mixed as u16)
+        );
+        assert_eq!({ dev.cfg.blocking_lock().plugged_size }, 2 * BLOCK_SIZE);
+
+        // Plugged twice
+        assert_eq!(
+            send(&runtime, &handler, VirtioMemRequestType::Plug, 5, 1),
+            (ERROR, 0)
+        );
+
+        assert_eq!(
+            send(&runtime, &handler, VirtioMemRequestType::Unplug, 4, 2),
+            (ACK, 0)
+        );
+        assert!(mappings(&vm).is_empty());
+        assert_eq!(
+            send(&runtime, &handler, VirtioMemRequestType::State, 4, 2),
+            (ACK, VirtioMemBlockState::Unplugged as u16)
+        );
+        assert_eq!({ dev.cfg.blocking_lock().plugged_size }, 0);
+
+        // Unplugged twice
+        assert_eq!(
+            send(&runtime, &handler, VirtioMemRequestType::Unplug, 4, 1),
+            (ERROR, 0)
+        );
+
+        // More than the requested size
+        assert_eq!(
+            send(&runtime, &handler, VirtioMemRequestType::Plug, 0, 33),
+            (NACK, 0)
+        );
+    }
+
+    #[test]
+    fn test_merge_and_split() {
+        let runtime = Runtime::new().unwrap();
+        let vm = Arc::new(MappingVm::default());
+        let dev = virtio_mem(vm.clone());
+        let handler = handler(&dev);
+
+        send(&runtime, &handler, VirtioMemRequestType::Plug, 0, 2);
+        send(&runtime, &handler, VirtioMemRequestType::Plug, 4, 2);
+        assert_eq!(mappings(&vm), [(0, 2), (4, 2)]);
+
+        // Bridging the gap merges both runs
+        send(&runtime, &handler, VirtioMemRequestType::Plug, 2, 2);
+        assert_eq!(mappings(&vm), [(0, 6)]);
+
+        // Unplugging the middle splits the run
+        send(&runtime, &handler, VirtioMemRequestType::Unplug, 2, 1);
+        assert_eq!(mappings(&vm), [(0, 2), (3, 3)]);
+
+        assert_eq!(
+            send(&runtime, &handler, VirtioMemRequestType::UnplugAll, 0, 0),
+            (ACK, 0)
+        );
+        assert!(mappings(&vm).is_empty());
+        assert_eq!({ dev.cfg.blocking_lock().plugged_size }, 0);
+    }
+
+    #[test]
+    fn test_out_of_range() {
+        let runtime = Runtime::new().unwrap();
+        let vm = Arc::new(MappingVm::default());
+        let dev = virtio_mem(vm.clone());
+        let handler = handler(&dev);
+
+        let blocks = VIRTIO_MEM_REGION_ALIGN as u64 / BLOCK_SIZE;
+
+        // Past the end of the region
+        assert_eq!(
+            send(
+                &runtime,
+                &handler,
+                VirtioMemRequestType::Plug,
+                blocks - 1,
+                2
+            ),
+            (ERROR, 0)
+        );
+        // No block
+        assert_eq!(
+            send(&runtime, &handler, VirtioMemRequestType::Plug, 0, 0),
+            (ERROR, 0)
+        );
+
+        let mut req = request(VirtioMemRequestType::Plug, 0, 1);
+        // Before the region
+        req.addr = ADDR - BLOCK_SIZE;
+        let resp = runtime.block_on(handler.handle_request(&req));
+        assert_eq!({ resp.r#type }, ERROR);
+        // Not aligned on a block
+        req.addr = ADDR + 0x1000;
+        let resp = runtime.block_on(handler.handle_request(&req));
+        assert_eq!({ resp.r#type }, ERROR);
+        // The end overflows
+        req.addr = u64::MAX - BLOCK_SIZE + 1;
+        req.nb_blocks = 2;
+        let resp = runtime.block_on(handler.handle_request(&req));
+        assert_eq!({ resp.r#type }, ERROR);
+
+        assert!(mappings(&vm).is_empty());
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let runtime = Runtime::new().unwrap();
+        let vm = Arc::new(MappingVm::default());
+        let dev = virtio_mem(vm.clone());
+        let handler = handler(&dev);
+
+        send(&runtime, &handler, VirtioMemRequestType::Plug, 0, 3);
+        send(&runtime, &handler, VirtioMemRequestType::Plug, 9, 1);
+
+        let mut buf = vec![];
+        dev.save(&mut buf).unwrap();
+
+        let restored_vm = Arc::new(MappingVm::default());
+        let mut restored = virtio_mem(restored_vm.clone());
+        restored.load(&mut buf.as_slice()).unwrap();
+
+        assert_eq!(mappings(&restored_vm), [(0, 3), (9, 1)]);
+        assert_eq!(
+            { restored.cfg.blocking_lock().plugged_size },
+            4 * BLOCK_SIZE
+        );
+        assert_eq!(
+            restored.blocks.blocking_lock().state(0, 3),
+            VirtioMemBlockState::Plugged
+        );
+        assert_eq!(
+            restored.blocks.blocking_lock().state(3, 6),
+            VirtioMemBlockState::Unplugged
+        );
+    }
+
+    #[test]
+    fn test_system_reset() {
+        let runtime = Runtime::new().unwrap();
+        let vm = Arc::new(MappingVm::default());
+        let dev = virtio_mem(vm.clone());
+        let handler = handler(&dev);
+
+        send(&runtime, &handler, VirtioMemRequestType::Plug, 0, 3);
+        send(&runtime, &handler, VirtioMemRequestType::Plug, 9, 1);
+
+        runtime.block_on(dev.system_reset().reset()).unwrap();
+
+        assert!(mappings(&vm).is_empty());
+        assert_eq!({ dev.cfg.blocking_lock().plugged_size }, 0);
+        assert_eq!(
+            send(&runtime, &handler, VirtioMemRequestType::State, 0, 16),
+            (ACK, VirtioMemBlockState::Unplugged as u16)
+        );
+    }
+}
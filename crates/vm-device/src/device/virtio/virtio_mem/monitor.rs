@@ -0,0 +1,85 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::Serialize;
+use tokio::sync::Mutex;
+use vm_core::monitor::MonitorCommandOps;
+use vm_core::monitor::MonitorError;
+use vm_virtio::device::virtqueue::VirtioConfigurationChangeNotifier;
+use vm_virtio::types::device::mem::VirtioMemConfig;
+
+#[derive(Serialize)]
+pub struct VirtioMemInfo {
+    addr: u64,
+    block_size: u64,
+    region_size: u64,
+    plugged_size: u64,
+    requested_size: u64,
+}
+
+pub struct VirtioMemMonitor {
+    config: Arc<Mutex<VirtioMemConfig>>,
+    configuration_change_notifier: Arc<dyn VirtioConfigurationChangeNotifier>,
+}
+
+impl VirtioMemMonitor {
+    pub fn new(
+        config: Arc<Mutex<VirtioMemConfig>>,
+        configuration_change_notifier: Arc<dyn VirtioConfigurationChangeNotifier>,
+    ) -> Self {
+        VirtioMemMonitor {
+            config,
+            configuration_change_notifier,
+        }
+    }
+}
+
+#[async_trait]
+impl MonitorCommandOps for VirtioMemMonitor {
+    async fn handle_command(&self, subcommands: &[&str]) -> Result<String, MonitorError> {
+        match *subcommands {
+            ["info"] => {
+                let config = self.config.lock().await;
+
+                Ok(serde_json::to_string_pretty(&VirtioMemInfo {
+                    addr: config.addr,
+                    block_size: config.block_size,
+                    region_size: config.region_size,
+                    plugged_size: config.plugged_size,
+                    requested_size: config.requested_size,
+                })?)
+            }
+            ["update_requested_size", requested_size] => {
+                let requested_size: u64 = requested_size.parse().map_err(|_err| {
+                    MonitorError::Error(format!("failed to parse requested_size: {requested_size}"))
+                })?;
+
+                {
+                    let mut config = self.config.lock().await;
+
+                    if requested_size > config.usable_region_size
+                        || !requested_size.is_multiple_of(config.block_size)
+                    {
+                        let block_size = config.block_size;
+                        let usable_region_size = config.usable_region_size;
+                        return Err(MonitorError::Error(format!(
+                            "requested_size must be a multiple of {block_size} and not exceed {usable_region_size}"
+                        )));
+                    }
+
+                    config.requested_size = requested_size;
+                }
+
+                // The driver plugs or unplugs blocks until plugged_size
+                // reaches requested_size
+                self.configuration_change_notifier
+                    .update_config_generation();
+
+                Ok(requested_size.to_string())
+            }
+            _ => Err(MonitorError::UnknownSubcommand(
+                subcommands.iter().map(|s| s.to_string()).collect(),
+            )),
+        }
+    }
+}
@@ -2,4 +2,6 @@
 
 pub mod device;
 
+#[cfg(test)]
+mod test_utils;
 mod utils;
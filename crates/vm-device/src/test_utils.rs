@@ -0,0 +1,52 @@
+use std::io::Read;
+use std::io::Write;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+use vm_core::arch::irq::InterruptController;
+use vm_core::arch::irq::Phandle;
+use vm_core::arch::irq::error::IrqChipError;
+use vm_core::device::system_event::SystemEvent;
+use vm_core::device::system_event::SystemEventNotifier;
+use vm_fdt::FdtWriter;
+
+/// Records the level of the last irq triggered
+#[derive(Default)]
+pub struct IrqChip(pub AtomicBool);
+
+impl IrqChip {
+    pub fn is_active(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+impl InterruptController for IrqChip {
+    fn trigger_irq(&self, _irq_line: u32, active: bool) {
+        self.0.store(active, Ordering::SeqCst);
+    }
+
+    fn send_msi(&self, _address_lo: u32, _address_hi: u32, _data: u32) {}
+
+    fn write_device_tree(&self, _fdt: &mut FdtWriter) -> Result<Phandle, IrqChipError> {
+        unreachable!()
+    }
+
+    fn save(&self, _write: &mut dyn Write) -> Result<(), IrqChipError> {
+        unreachable!()
+    }
+
+    fn load(&mut self, _read: &mut dyn Read) -> Result<(), IrqChipError> {
+        unreachable!()
+    }
+}
+
+/// Records the system events in the order they are notified
+#[derive(Default)]
+pub struct Events(pub Mutex<Vec<SystemEvent>>);
+
+impl SystemEventNotifier for Events {
+    fn notify(&self, event: SystemEvent) {
+        self.0.lock().unwrap().push(event);
+    }
+}
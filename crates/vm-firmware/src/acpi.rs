@@ -11,3 +11,5 @@ mod utils;
 pub mod acpi_table;
 pub mod builder;
 pub mod error;
+pub mod numa;
+pub mod processor;
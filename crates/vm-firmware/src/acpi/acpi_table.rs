@@ -1,20 +1,48 @@
 use vm_mm::manager::MemoryAddressSpace;
+use vm_utils::cpu_topology::CpuTopology;
 use vm_utils::range_allocator::RangeAllocator;
+use zerocopy::IntoBytes;
 
 use crate::acpi::error::AcpiError;
+use crate::acpi::numa::LOCAL_DISTANCE;
+use crate::acpi::numa::NumaNode;
+use crate::acpi::numa::REMOTE_DISTANCE;
 use crate::acpi::r#type::dsdt::Dsdt;
 use crate::acpi::r#type::fadt::Fadt;
+#[cfg(target_arch = "aarch64")]
+use crate::acpi::r#type::gtdt::Gtdt;
+#[cfg(target_arch = "aarch64")]
+use crate::acpi::r#type::iort::Iort;
 use crate::acpi::r#type::madt::Madt;
 use crate::acpi::r#type::mcfg::Mcfg;
 use crate::acpi::r#type::mcfg::PciRangeEntry;
+use crate::acpi::r#type::pptt::Pptt;
 use crate::acpi::r#type::rsdp::Rsdp;
+use crate::acpi::r#type::slit::Slit;
+#[cfg(target_arch = "aarch64")]
+use crate::acpi::r#type::spcr::Spcr;
+#[cfg(target_arch = "aarch64")]
+use crate::acpi::r#type::srat::GiccAffinity;
+use crate::acpi::r#type::srat::MemoryAffinity;
+use crate::acpi::r#type::srat::Srat;
+#[cfg(target_arch = "x86_64")]
+use crate::acpi::r#type::srat::X2ApicAffinity;
+use crate::acpi::r#type::viot::Viot;
 use crate::acpi::r#type::xsdt::Xsdt;
 
 pub struct AcpiTable {
     pub(crate) definition_block: Vec<u8>,
+    #[cfg(target_arch = "x86_64")]
     pub(crate) apic_base_address: u32,
+    #[cfg(target_arch = "x86_64")]
+    pub(crate) sleep_register_port: Option<u16>,
     pub(crate) interrupt_controllers: Vec<u8>,
+    pub(crate) cpu_topology: CpuTopology,
+    pub(crate) numa_nodes: Vec<NumaNode>,
     pub(crate) pci_range_entry: PciRangeEntry, // We only support one yet
+    pub(crate) iommu_base_address: Option<u64>,
+    #[cfg(target_arch = "aarch64")]
+    pub(crate) serial_console: Option<(u64, u32)>,
 }
 
 impl AcpiTable {
@@ -26,19 +54,68 @@ impl AcpiTable {
     ) -> Result<(), AcpiError> {
         ram_allocator.reserve(rsdp_address, size_of::<Rsdp>())?;
 
+        // Built before the fields are moved into the other tables
+        let srat_affinities = self.srat_affinities();
+        let slit_distances = self.slit_distances();
+
         let dsdt = Dsdt::new(self.definition_block);
         let dsdt_address = dsdt.install(ram_allocator, memory)?;
 
-        let fadt = Fadt::new(dsdt_address);
+        let fadt = Fadt::new(
+            dsdt_address,
+            #[cfg(target_arch = "x86_64")]
+            self.sleep_register_port,
+        );
         let fadt_address = fadt.install(ram_allocator, memory)?;
 
-        let madt = Madt::new(self.apic_base_address, self.interrupt_controllers);
+        #[cfg(target_arch = "x86_64")]
+        let local_interrupt_controller_address = self.apic_base_address;
+        // The GICC entries have the address of the cpu interfaces
+        #[cfg(target_arch = "aarch64")]
+        let local_interrupt_controller_address = 0;
+
+        let madt = Madt::new(
+            local_interrupt_controller_address,
+            self.interrupt_controllers,
+        );
         let madt_address = madt.install(ram_allocator, memory)?;
 
         let mcfg = Mcfg::new(vec![self.pci_range_entry]);
         let mcfg_address = mcfg.install(ram_allocator, memory)?;
 
-        let xsdt = Xsdt::new(vec![fadt_address, madt_address, mcfg_address]);
+        let pptt = Pptt::new(&self.cpu_topology);
+        let pptt_address = pptt.install(ram_allocator, memory)?;
+
+        let mut entry = vec![fadt_address, madt_address, mcfg_address, pptt_address];
+
+        if !self.numa_nodes.is_empty() {
+            let srat = Srat::new(srat_affinities);
+            entry.push(srat.install(ram_allocator, memory)?);
+
+            let slit = Slit::new(slit_distances);
+            entry.push(slit.install(ram_allocator, memory)?);
+        }
+
+        #[cfg(target_arch = "aarch64")]
+        {
+            let gtdt = Gtdt::new();
+            entry.push(gtdt.install(ram_allocator, memory)?);
+
+            let iort = Iort::new();
+            entry.push(iort.install(ram_allocator, memory)?);
+
+            if let Some((base, gsi)) = self.serial_console {
+                let spcr = Spcr::new(base, gsi);
+                entry.push(spcr.install(ram_allocator, memory)?);
+            }
+        }
+
+        if let Some(iommu_base_address) = self.iommu_base_address {
+            let viot = Viot::new(iommu_base_address);
+            entry.push(viot.install(ram_allocator, memory)?);
+        }
+
+        let xsdt = Xsdt::new(entry);
         let xsdt_address = xsdt.install(ram_allocator, memory)?;
 
         let rsdp = Rsdp::new(xsdt_address);
@@ -46,4 +123,46 @@ impl AcpiTable {
 
         Ok(())
     }
+
+    fn srat_affinities(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+
+        for (proximity_domain, node) in self.numa_nodes.iter().enumerate() {
+            let proximity_domain = proximity_domain as u32;
+
+            for &vcpu in &node.vcpus {
+                #[cfg(target_arch = "x86_64")]
+                let affinity =
+                    X2ApicAffinity::new(proximity_domain, self.cpu_topology.apic_id(vcpu));
+                // The uid of the GICC is the vcpu index
+                #[cfg(target_arch = "aarch64")]
+                let affinity = GiccAffinity::new(proximity_domain, vcpu);
+
+                buf.extend_from_slice(affinity.as_bytes());
+            }
+
+            let affinity = MemoryAffinity::new(
+                proximity_domain,
+                node.memory.start,
+                node.memory.end - node.memory.start,
+            );
+            buf.extend_from_slice(affinity.as_bytes());
+        }
+
+        buf
+    }
+
+    fn slit_distances(&self) -> Vec<Vec<u8>> {
+        (0..self.numa_nodes.len())
+            .map(|i| {
+                (0..self.numa_nodes.len())
+                    .map(|j| match self.numa_nodes[i].distances.get(j) {
+                        Some(distance) => *distance,
+                        None if i == j => LOCAL_DISTANCE,
+                        None => REMOTE_DISTANCE,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
 }
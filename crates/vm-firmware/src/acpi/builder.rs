@@ -1,25 +1,69 @@
 use std::cell::OnceCell;
 
+use vm_utils::cpu_topology::CpuTopology;
+
 use crate::acpi::acpi_table::AcpiTable;
 use crate::acpi::error::AcpiError;
+use crate::acpi::numa::NumaNode;
+use crate::acpi::processor::local_interrupt_controller;
 use crate::acpi::r#type::mcfg::PciRangeEntry;
 
 #[derive(Default)]
 pub struct AcpiTableBuilder {
-    vcpus: OnceCell<u8>,
+    cpu_topology: OnceCell<CpuTopology>,
+    /// The vcpus from this one on are hotpluggable, all the vcpus are present if it is not set
+    present_vcpus: OnceCell<u32>,
+    /// SRAT and SLIT are installed if it is set
+    numa_nodes: OnceCell<Vec<NumaNode>>,
     definition_block: OnceCell<Vec<u8>>,
-    apic_base_address: OnceCell<u32>,
     pci_mmio_base_addr: OnceCell<u64>,
+    pci_end_bus_number: OnceCell<u8>,
+    iommu_base_address: OnceCell<u64>,
 
+    #[cfg(target_arch = "x86_64")]
+    apic_base_address: OnceCell<u32>,
     #[cfg(target_arch = "x86_64")]
     io_apic_address: OnceCell<u32>,
+    /// The port of the sleep control and status registers, the guest can't enter S5 without it
+    #[cfg(target_arch = "x86_64")]
+    sleep_register_port: OnceCell<u16>,
+
+    #[cfg(target_arch = "aarch64")]
+    gic_distributor_address: OnceCell<u64>,
+    /// (base, length) of the redistributors of all the cpus
+    #[cfg(target_arch = "aarch64")]
+    gic_redistributor_range: OnceCell<(u64, u32)>,
+    #[cfg(target_arch = "aarch64")]
+    gic_its_address: OnceCell<u64>,
+    /// (base, gsi) of the pl011 used as the console, a SPCR is installed if it is set
+    #[cfg(target_arch = "aarch64")]
+    serial_console: OnceCell<(u64, u32)>,
 }
 
 impl AcpiTableBuilder {
-    pub fn set_vcpus(self, vcpus: u8) -> Result<AcpiTableBuilder, AcpiError> {
-        self.vcpus
-            .set(vcpus)
-            .map_err(|_| AcpiError::FieldAlreadySet("vcpus"))?;
+    pub fn set_cpu_topology(
+        self,
+        cpu_topology: CpuTopology,
+    ) -> Result<AcpiTableBuilder, AcpiError> {
+        self.cpu_topology
+            .set(cpu_topology)
+            .map_err(|_| AcpiError::FieldAlreadySet("cpu_topology"))?;
+
+        Ok(self)
+    }
+
+    pub fn set_present_vcpus(self, present_vcpus: u32) -> Result<AcpiTableBuilder, AcpiError> {
+        self.present_vcpus
+            .set(present_vcpus)
+            .map_err(|_| AcpiError::FieldAlreadySet("present_vcpus"))?;
+
+        Ok(self)
+    }
+
+    pub fn set_numa_nodes(self, numa_nodes: Vec<NumaNode>) -> Result<AcpiTableBuilder, AcpiError> {
+        self.numa_nodes
+            .set(numa_nodes)
+            .map_err(|_| AcpiError::FieldAlreadySet("numa_nodes"))?;
 
         Ok(self)
     }
@@ -35,6 +79,7 @@ impl AcpiTableBuilder {
         Ok(self)
     }
 
+    #[cfg(target_arch = "x86_64")]
     pub fn set_apic_base_address(
         self,
         apic_base_address: u32,
@@ -55,6 +100,55 @@ impl AcpiTableBuilder {
         Ok(self)
     }
 
+    #[cfg(target_arch = "x86_64")]
+    pub fn set_sleep_register_port(self, port: u16) -> Result<AcpiTableBuilder, AcpiError> {
+        self.sleep_register_port
+            .set(port)
+            .map_err(|_| AcpiError::FieldAlreadySet("sleep_register_port"))?;
+
+        Ok(self)
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    pub fn set_gic_distributor_address(self, address: u64) -> Result<AcpiTableBuilder, AcpiError> {
+        self.gic_distributor_address
+            .set(address)
+            .map_err(|_| AcpiError::FieldAlreadySet("gic_distributor_address"))?;
+
+        Ok(self)
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    pub fn set_gic_redistributor_range(
+        self,
+        base: u64,
+        length: u32,
+    ) -> Result<AcpiTableBuilder, AcpiError> {
+        self.gic_redistributor_range
+            .set((base, length))
+            .map_err(|_| AcpiError::FieldAlreadySet("gic_redistributor_range"))?;
+
+        Ok(self)
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    pub fn set_gic_its_address(self, address: u64) -> Result<AcpiTableBuilder, AcpiError> {
+        self.gic_its_address
+            .set(address)
+            .map_err(|_| AcpiError::FieldAlreadySet("gic_its_address"))?;
+
+        Ok(self)
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    pub fn set_serial_console(self, base: u64, gsi: u32) -> Result<AcpiTableBuilder, AcpiError> {
+        self.serial_console
+            .set((base, gsi))
+            .map_err(|_| AcpiError::FieldAlreadySet("serial_console"))?;
+
+        Ok(self)
+    }
+
     pub fn set_pci_mmio_base_addr(self, base_address: u64) -> Result<AcpiTableBuilder, AcpiError> {
         self.pci_mmio_base_addr
             .set(base_address)
@@ -63,24 +157,57 @@ impl AcpiTableBuilder {
         Ok(self)
     }
 
+    pub fn set_pci_end_bus_number(self, end_bus_number: u8) -> Result<AcpiTableBuilder, AcpiError> {
+        self.pci_end_bus_number
+            .set(end_bus_number)
+            .map_err(|_| AcpiError::FieldAlreadySet("pci_end_bus_number"))?;
+
+        Ok(self)
+    }
+
+    /// The mmio base of the virtio-iommu translating the pci devices, a VIOT is
+    /// installed if it is set.
+    pub fn set_iommu_base_address(self, base_address: u64) -> Result<AcpiTableBuilder, AcpiError> {
+        self.iommu_base_address
+            .set(base_address)
+            .map_err(|_| AcpiError::FieldAlreadySet("iommu_base_address"))?;
+
+        Ok(self)
+    }
+
     pub fn build(mut self) -> Result<AcpiTable, AcpiError> {
         let interrupt_controllers = self.setup_arch_interrupt_controllers()?;
         let pci_mmio_base_addr = self
             .pci_mmio_base_addr
             .take()
             .ok_or_else(|| AcpiError::FieldNotSet("pci_mmio_configuration_space"))?;
+        let pci_end_bus_number = self
+            .pci_end_bus_number
+            .take()
+            .ok_or_else(|| AcpiError::FieldNotSet("pci_end_bus_number"))?;
 
         let table = AcpiTable {
             definition_block: self
                 .definition_block
                 .take()
                 .ok_or_else(|| AcpiError::FieldNotSet("definition_block"))?,
+            #[cfg(target_arch = "x86_64")]
             apic_base_address: self
                 .apic_base_address
                 .take()
                 .ok_or_else(|| AcpiError::FieldNotSet("apic_base_address"))?,
+            #[cfg(target_arch = "x86_64")]
+            sleep_register_port: self.sleep_register_port.take(),
             interrupt_controllers,
-            pci_range_entry: PciRangeEntry::new(pci_mmio_base_addr, 0, 0, 0),
+            cpu_topology: self
+                .cpu_topology
+                .take()
+                .ok_or_else(|| AcpiError::FieldNotSet("cpu_topology"))?,
+            numa_nodes: self.numa_nodes.take().unwrap_or_default(),
+            pci_range_entry: PciRangeEntry::new(pci_mmio_base_addr, 0, 0, pci_end_bus_number),
+            iommu_base_address: self.iommu_base_address.take(),
+            #[cfg(target_arch = "aarch64")]
+            serial_console: self.serial_console.take(),
         };
 
         Ok(table)
@@ -91,15 +218,16 @@ impl AcpiTableBuilder {
         use zerocopy::IntoBytes;
 
         use crate::acpi::r#type::arch::x86_64::IoApic;
-        use crate::acpi::r#type::arch::x86_64::LocalApic;
+        use crate::acpi::r#type::arch::x86_64::LocalX2Apic;
 
-        let vcpus = *self
-            .vcpus
+        let cpu_topology = self
+            .cpu_topology
             .get()
-            .ok_or_else(|| AcpiError::FieldNotSet("vcpus"))?;
+            .ok_or_else(|| AcpiError::FieldNotSet("cpu_topology"))?;
 
-        let mut buf =
-            Vec::with_capacity(size_of::<IoApic>() + size_of::<LocalApic>() * vcpus as usize);
+        let mut buf = Vec::with_capacity(
+            size_of::<IoApic>() + size_of::<LocalX2Apic>() * cpu_topology.vcpus() as usize,
+        );
 
         {
             let io_apic = IoApic::new(
@@ -112,9 +240,11 @@ impl AcpiTableBuilder {
         }
 
         {
-            for vcpu in 0..vcpus {
-                let local_apic = LocalApic::new(vcpu);
-                buf.extend_from_slice(local_apic.as_bytes());
+            let present_vcpus = self.present_vcpus.get().copied();
+
+            for vcpu in 0..cpu_topology.vcpus() {
+                let present = present_vcpus.is_none_or(|present_vcpus| vcpu < present_vcpus);
+                buf.extend_from_slice(&local_interrupt_controller(cpu_topology, vcpu, present));
             }
         }
 
@@ -123,6 +253,63 @@ impl AcpiTableBuilder {
 
     #[cfg(target_arch = "aarch64")]
     fn setup_arch_interrupt_controllers(&mut self) -> Result<Vec<u8>, AcpiError> {
-        Err(AcpiError::ArchNotSupport("aarch64"))
+        use zerocopy::IntoBytes;
+
+        use crate::acpi::r#type::arch::aarch64::GicIts;
+        use crate::acpi::r#type::arch::aarch64::Gicc;
+        use crate::acpi::r#type::arch::aarch64::Gicd;
+        use crate::acpi::r#type::arch::aarch64::Gicr;
+
+        let cpu_topology = self
+            .cpu_topology
+            .get()
+            .ok_or_else(|| AcpiError::FieldNotSet("cpu_topology"))?;
+
+        let mut buf = Vec::with_capacity(
+            size_of::<Gicc>() * cpu_topology.vcpus() as usize
+                + size_of::<Gicd>()
+                + size_of::<Gicr>()
+                + size_of::<GicIts>(),
+        );
+
+        {
+            let present_vcpus = self.present_vcpus.get().copied();
+
+            for vcpu in 0..cpu_topology.vcpus() {
+                let present = present_vcpus.is_none_or(|present_vcpus| vcpu < present_vcpus);
+                buf.extend_from_slice(&local_interrupt_controller(cpu_topology, vcpu, present));
+            }
+        }
+
+        {
+            let gicd = Gicd::new(
+                *self
+                    .gic_distributor_address
+                    .get()
+                    .ok_or_else(|| AcpiError::FieldNotSet("gic_distributor_address"))?,
+            );
+            buf.extend_from_slice(gicd.as_bytes());
+        }
+
+        {
+            let (base, length) = *self
+                .gic_redistributor_range
+                .get()
+                .ok_or_else(|| AcpiError::FieldNotSet("gic_redistributor_range"))?;
+            let gicr = Gicr::new(base, length);
+            buf.extend_from_slice(gicr.as_bytes());
+        }
+
+        {
+            let gic_its = GicIts::new(
+                *self
+                    .gic_its_address
+                    .get()
+                    .ok_or_else(|| AcpiError::FieldNotSet("gic_its_address"))?,
+            );
+            buf.extend_from_slice(gic_its.as_bytes());
+        }
+
+        Ok(buf)
     }
 }
@@ -0,0 +1,16 @@
+use std::ops::Range;
+
+/// Local distance of a numa node, the distances to the other nodes are relative to it
+pub const LOCAL_DISTANCE: u8 = 10;
+pub const REMOTE_DISTANCE: u8 = 20;
+
+#[derive(Clone)]
+pub struct NumaNode {
+    /// The vcpu indexes belonging to the node
+    pub vcpus: Vec<u32>,
+    /// The guest physical range of the node's memory
+    pub memory: Range<u64>,
+    /// The distances to every node including itself, defaults to `LOCAL_DISTANCE` and
+    /// `REMOTE_DISTANCE` if empty
+    pub distances: Vec<u8>,
+}
@@ -0,0 +1,35 @@
+use vm_utils::cpu_topology::CpuTopology;
+use zerocopy::IntoBytes;
+
+/// The MADT entry of the interrupt controller of a vcpu, the `_MAT` of its processor device
+/// returns the same entry. A vcpu which is not present at boot is online capable.
+#[cfg(target_arch = "x86_64")]
+pub fn local_interrupt_controller(cpu_topology: &CpuTopology, vcpu: u32, present: bool) -> Vec<u8> {
+    use crate::acpi::r#type::arch::x86_64::LocalApic;
+    use crate::acpi::r#type::arch::x86_64::LocalApicFlag;
+    use crate::acpi::r#type::arch::x86_64::LocalX2Apic;
+
+    let apic_id = cpu_topology.apic_id(vcpu);
+    let flags = if present {
+        LocalApicFlag::ENABLED
+    } else {
+        LocalApicFlag::ONLINE_CAPABLE
+    };
+
+    // 0xff is the broadcast apic id, so it needs an x2apic entry as well
+    match (u8::try_from(vcpu), u8::try_from(apic_id)) {
+        (Ok(uid), Ok(apic_id)) if apic_id != u8::MAX => {
+            LocalApic::new(uid, apic_id, flags).as_bytes().to_vec()
+        }
+        _ => LocalX2Apic::new(vcpu, apic_id, flags).as_bytes().to_vec(),
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+pub fn local_interrupt_controller(cpu_topology: &CpuTopology, vcpu: u32, present: bool) -> Vec<u8> {
+    use crate::acpi::r#type::arch::aarch64::Gicc;
+
+    Gicc::new(vcpu, cpu_topology.hwid(vcpu), present)
+        .as_bytes()
+        .to_vec()
+}
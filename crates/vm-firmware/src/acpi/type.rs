@@ -3,7 +3,17 @@ pub(crate) mod common_header;
 pub(crate) mod dsdt;
 pub(crate) mod fadt;
 pub(crate) mod generic_address_structure_format;
+#[cfg(target_arch = "aarch64")]
+pub(crate) mod gtdt;
+#[cfg(target_arch = "aarch64")]
+pub(crate) mod iort;
 pub(crate) mod madt;
 pub(crate) mod mcfg;
+pub(crate) mod pptt;
 pub(crate) mod rsdp;
+pub(crate) mod slit;
+#[cfg(target_arch = "aarch64")]
+pub(crate) mod spcr;
+pub(crate) mod srat;
+pub(crate) mod viot;
 pub(crate) mod xsdt;
@@ -0,0 +1,138 @@
+use zerocopy::Immutable;
+use zerocopy::IntoBytes;
+
+const GICC_ENABLED: u32 = 1 << 0;
+/// Disabled at boot but can be enabled later, i.e. a hotpluggable vcpu
+const GICC_ONLINE_CAPABLE: u32 = 1 << 3;
+const GIC_VERSION_3: u8 = 3;
+
+/// GIC CPU Interface
+#[derive(Immutable, IntoBytes)]
+#[repr(C, packed)]
+pub struct Gicc {
+    r#type: u8,
+    length: u8,
+    reserved_0: u16,
+    cpu_interface_number: u32,
+    acpi_processor_uid: u32,
+    flags: u32,
+    parking_protocol_version: u32,
+    performance_interrupt_gsiv: u32,
+    parked_address: u64,
+    physical_base_address: u64,
+    gicv: u64,
+    gich: u64,
+    vgic_maintenance_interrupt: u32,
+    /// 0 if the redistributors are described by a GICR structure
+    gicr_base_address: u64,
+    mpidr: u64,
+    processor_power_efficiency_class: u8,
+    reserved_1: u8,
+    spe_overflow_interrupt: u16,
+}
+
+impl Gicc {
+    pub fn new(cpu_id: u32, mpidr: u64, enabled: bool) -> Self {
+        Gicc {
+            r#type: 0xb,
+            length: 80,
+            reserved_0: 0,
+            cpu_interface_number: cpu_id,
+            acpi_processor_uid: cpu_id,
+            flags: if enabled {
+                GICC_ENABLED
+            } else {
+                GICC_ONLINE_CAPABLE
+            },
+            parking_protocol_version: 0,
+            performance_interrupt_gsiv: 0,
+            parked_address: 0,
+            physical_base_address: 0,
+            gicv: 0,
+            gich: 0,
+            vgic_maintenance_interrupt: 0,
+            gicr_base_address: 0,
+            mpidr,
+            processor_power_efficiency_class: 0,
+            reserved_1: 0,
+            spe_overflow_interrupt: 0,
+        }
+    }
+}
+
+/// GIC Distributor
+#[derive(Immutable, IntoBytes)]
+#[repr(C, packed)]
+pub struct Gicd {
+    r#type: u8,
+    length: u8,
+    reserved_0: u16,
+    gic_id: u32,
+    physical_base_address: u64,
+    system_vector_base: u32,
+    gic_version: u8,
+    reserved_1: [u8; 3],
+}
+
+impl Gicd {
+    pub fn new(physical_base_address: u64) -> Self {
+        Gicd {
+            r#type: 0xc,
+            length: 24,
+            reserved_0: 0,
+            gic_id: 0,
+            physical_base_address,
+            system_vector_base: 0,
+            gic_version: GIC_VERSION_3,
+            reserved_1: [0; 3],
+        }
+    }
+}
+
+/// GIC Redistributor, one range for the redistributors of all the cpus
+#[derive(Immutable, IntoBytes)]
+#[repr(C, packed)]
+pub struct Gicr {
+    r#type: u8,
+    length: u8,
+    reserved: u16,
+    discovery_range_base_address: u64,
+    discovery_range_length: u32,
+}
+
+impl Gicr {
+    pub fn new(discovery_range_base_address: u64, discovery_range_length: u32) -> Self {
+        Gicr {
+            r#type: 0xe,
+            length: 16,
+            reserved: 0,
+            discovery_range_base_address,
+            discovery_range_length,
+        }
+    }
+}
+
+/// GIC Interrupt Translation Service
+#[derive(Immutable, IntoBytes)]
+#[repr(C, packed)]
+pub struct GicIts {
+    r#type: u8,
+    length: u8,
+    reserved_0: u16,
+    gic_its_id: u32,
+    physical_base_address: u64,
+    reserved_1: u32,
+}
+
+impl GicIts {
+    pub fn new(physical_base_address: u64) -> Self {
+        GicIts {
+            r#type: 0xf,
+            length: 20,
+            reserved_0: 0,
+            gic_its_id: 0, // We only support one its
+            physical_base_address,
+            reserved_1: 0,
+        }
+    }
+}
@@ -20,13 +20,38 @@ pub struct LocalApic {
 }
 
 impl LocalApic {
-    pub fn new(cpu_id: u8) -> Self {
+    pub fn new(acpi_processor_uid: u8, apic_id: u8, flags: LocalApicFlag) -> Self {
         LocalApic {
             r#type: 0,
             length: 8,
-            acpi_processor_uid: cpu_id,
-            apic_id: cpu_id,
-            flags: LocalApicFlag::ENABLED.bits(),
+            acpi_processor_uid,
+            apic_id,
+            flags: flags.bits(),
+        }
+    }
+}
+
+/// For the processors whose apic id or uid does not fit in a LocalApic
+#[derive(Immutable, IntoBytes)]
+#[repr(C, packed)]
+pub struct LocalX2Apic {
+    r#type: u8,
+    length: u8,
+    reserved: u16,
+    x2apic_id: u32,
+    flags: u32,
+    acpi_processor_uid: u32,
+}
+
+impl LocalX2Apic {
+    pub fn new(acpi_processor_uid: u32, x2apic_id: u32, flags: LocalApicFlag) -> Self {
+        LocalX2Apic {
+            r#type: 9,
+            length: 16,
+            reserved: 0,
+            x2apic_id,
+            flags: flags.bits(),
+            acpi_processor_uid,
         }
     }
 }
@@ -23,6 +23,8 @@ const ACPI_FADT_POWER_BUTTON: u32 = 1 << 4; /* 04: [V1] Power button is handled
 // If the system does not have a sleep button, this value would be “1” and no sleep button device would be present.
 const ACPI_FADT_SLEEP_BUTTON: u32 = 1 << 5; /* 05: [V1] Sleep button is handled as a control method device */
 const FADT_F_HW_REDUCED_ACPI: u32 = 1 << 20; /* 20: [V5] ACPI hardware is not implemented (ACPI 5.0) */
+#[cfg(target_arch = "aarch64")]
+const ARM_BOOT_ARCH_PSCI_COMPLIANT: u16 = 1 << 0; /* 00: [V5+] PSCI 0.2+ is implemented, conduit is SMC */
 
 #[derive(Default, Immutable, IntoBytes)]
 #[repr(C, packed)]
@@ -86,7 +88,10 @@ pub struct Fadt {
 }
 
 impl Fadt {
-    pub fn new(x_dsdt: u64) -> Self {
+    pub fn new(
+        x_dsdt: u64,
+        #[cfg(target_arch = "x86_64")] sleep_register_port: Option<u16>,
+    ) -> Self {
         let mut raw = Fadt {
             header: CommonHeader {
                 signature: *b"FACP",
@@ -100,12 +105,21 @@ impl Fadt {
                 creator_revision: CREATOR_REVISION,
             },
             flags: ACPI_FADT_POWER_BUTTON | ACPI_FADT_SLEEP_BUTTON | FADT_F_HW_REDUCED_ACPI,
+            #[cfg(target_arch = "aarch64")]
+            arm_boot_arch: ARM_BOOT_ARCH_PSCI_COMPLIANT,
             fadt_minor_version: 5, // ACPI 6.6 specification says it is 5.
             x_dsdt,
             hypervisor_vendor_id: HYPERVISOR_VENDOR_ID,
             ..Default::default()
         };
 
+        // The guest enters S5 by writing the sleep control register
+        #[cfg(target_arch = "x86_64")]
+        if let Some(port) = sleep_register_port {
+            raw.sleep_control_reg = GenericAddressStructureFormat::new_system_io(port);
+            raw.sleep_status_reg = GenericAddressStructureFormat::new_system_io(port);
+        }
+
         raw.header.checksum = checksum(raw.as_bytes());
 
         raw
@@ -133,7 +147,11 @@ mod tests {
 
     #[test]
     fn test_fadt() {
-        let fadt = Fadt::new(0x12345678);
+        let fadt = Fadt::new(
+            0x12345678,
+            #[cfg(target_arch = "x86_64")]
+            Some(0x600),
+        );
 
         assert_eq!(checksum(fadt.as_bytes()), 0);
         assert_eq!(fadt.len(), fadt.as_bytes().len());
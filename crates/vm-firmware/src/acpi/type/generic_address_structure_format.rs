@@ -10,3 +10,31 @@ pub struct GenericAddressStructureFormat {
     access_size: u8,
     address: u64,
 }
+
+#[cfg(target_arch = "x86_64")]
+impl GenericAddressStructureFormat {
+    /// A byte wide register in the system io space
+    pub fn new_system_io(port: u16) -> Self {
+        GenericAddressStructureFormat {
+            address_space_id: 1,
+            register_bit_width: 8,
+            register_bit_offset: 0,
+            access_size: 1,
+            address: port as u64,
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+impl GenericAddressStructureFormat {
+    /// A register in the system memory space accessed by dwords
+    pub fn new_system_memory(register_bit_width: u8, address: u64) -> Self {
+        GenericAddressStructureFormat {
+            address_space_id: 0,
+            register_bit_width,
+            register_bit_offset: 0,
+            access_size: 3,
+            address,
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use vm_mm::manager::MemoryAddressSpace;
+use vm_utils::range_allocator::RangeAllocator;
+use zerocopy::Immutable;
+use zerocopy::IntoBytes;
+
+use crate::acpi::CREATOR_ID;
+use crate::acpi::CREATOR_REVISION;
+use crate::acpi::OEM_REVISION;
+use crate::acpi::OEM_TABLE_ID;
+use crate::acpi::OEMID;
+use crate::acpi::error::AcpiError;
+use crate::acpi::r#type::common_header::CommonHeader;
+use crate::acpi::utils::checksum;
+
+// The ppis of the arch timer, the gsiv of a ppi is its id plus 16
+const SECURE_EL1_TIMER_GSIV: u32 = 13 + 16;
+const NON_SECURE_EL1_TIMER_GSIV: u32 = 14 + 16;
+const VIRTUAL_EL1_TIMER_GSIV: u32 = 11 + 16;
+const EL2_TIMER_GSIV: u32 = 10 + 16;
+
+const GTDT_TIMER_ACTIVE_LOW: u32 = 1 << 1;
+
+/// Generic Timer Description Table
+#[derive(Default, Immutable, IntoBytes)]
+#[repr(C, packed)]
+pub struct Gtdt {
+    header: CommonHeader,
+    cnt_control_base_physical_address: u64,
+    reserved: u32,
+    secure_el1_timer_gsiv: u32,
+    secure_el1_timer_flags: u32,
+    non_secure_el1_timer_gsiv: u32,
+    non_secure_el1_timer_flags: u32,
+    virtual_el1_timer_gsiv: u32,
+    virtual_el1_timer_flags: u32,
+    el2_timer_gsiv: u32,
+    el2_timer_flags: u32,
+    cnt_read_base_physical_address: u64,
+    platform_timer_count: u32,
+    platform_timer_offset: u32,
+}
+
+impl Gtdt {
+    pub fn new() -> Self {
+        let mut raw = Gtdt {
+            header: CommonHeader {
+                signature: *b"GTDT",
+                length: size_of::<Gtdt>().try_into().unwrap(),
+                revision: 2,
+                checksum: 0,
+                oem_id: OEMID,
+                oem_table_id: OEM_TABLE_ID,
+                oem_revision: OEM_REVISION,
+                creator_id: CREATOR_ID,
+                creator_revision: CREATOR_REVISION,
+            },
+            // There is no memory-mapped timer
+            cnt_control_base_physical_address: u64::MAX,
+            secure_el1_timer_gsiv: SECURE_EL1_TIMER_GSIV,
+            secure_el1_timer_flags: GTDT_TIMER_ACTIVE_LOW,
+            non_secure_el1_timer_gsiv: NON_SECURE_EL1_TIMER_GSIV,
+            non_secure_el1_timer_flags: GTDT_TIMER_ACTIVE_LOW,
+            virtual_el1_timer_gsiv: VIRTUAL_EL1_TIMER_GSIV,
+            virtual_el1_timer_flags: GTDT_TIMER_ACTIVE_LOW,
+            el2_timer_gsiv: EL2_TIMER_GSIV,
+            el2_timer_flags: GTDT_TIMER_ACTIVE_LOW,
+            cnt_read_base_physical_address: u64::MAX,
+            ..Default::default()
+        };
+
+        raw.header.checksum = checksum(raw.as_bytes());
+
+        raw
+    }
+
+    pub fn len(&self) -> usize {
+        self.header.length as usize
+    }
+
+    pub fn install(
+        &self,
+        ram_allocator: &mut RangeAllocator<u64>,
+        memory: &MemoryAddressSpace,
+    ) -> Result<u64, AcpiError> {
+        let address = ram_allocator.alloc(self.len())?.start;
+        memory.copy_from_slice(address, self.as_bytes())?;
+
+        Ok(address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gtdt() {
+        let gtdt = Gtdt::new();
+
+        assert_eq!(checksum(gtdt.as_bytes()), 0);
+        assert_eq!(gtdt.len(), gtdt.as_bytes().len());
+        assert_eq!(gtdt.len(), 96);
+    }
+}
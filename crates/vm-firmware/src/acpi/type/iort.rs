@@ -0,0 +1,188 @@
+use vm_mm::manager::MemoryAddressSpace;
+use vm_utils::range_allocator::RangeAllocator;
+use zerocopy::Immutable;
+use zerocopy::IntoBytes;
+
+use crate::acpi::CREATOR_ID;
+use crate::acpi::CREATOR_REVISION;
+use crate::acpi::OEM_REVISION;
+use crate::acpi::OEM_TABLE_ID;
+use crate::acpi::OEMID;
+use crate::acpi::error::AcpiError;
+use crate::acpi::r#type::common_header::CommonHeader;
+use crate::acpi::utils::checksum;
+
+const IORT_NODE_ITS_GROUP: u8 = 0;
+const IORT_NODE_PCI_ROOT_COMPLEX: u8 = 2;
+
+const IORT_MEMORY_ACCESS_COHERENT: u8 = 1 << 0;
+const IORT_MEMORY_ACCESS_DACS: u8 = 1 << 1;
+
+#[derive(Immutable, IntoBytes)]
+#[repr(C, packed)]
+struct IortHeader {
+    node_count: u32,
+    node_offset: u32,
+    reserved: u32,
+}
+
+#[derive(Immutable, IntoBytes)]
+#[repr(C, packed)]
+struct NodeHeader {
+    r#type: u8,
+    length: u16,
+    revision: u8,
+    identifier: u32,
+    id_mapping_count: u32,
+    id_mapping_offset: u32,
+}
+
+#[derive(Immutable, IntoBytes)]
+#[repr(C, packed)]
+struct ItsGroupNode {
+    header: NodeHeader,
+    its_count: u32,
+    its_id: u32,
+}
+
+#[derive(Immutable, IntoBytes)]
+#[repr(C, packed)]
+struct IdMapping {
+    input_base: u32,
+    /// The number of ids in the range minus one
+    id_count: u32,
+    output_base: u32,
+    /// Offset of the output node from the start of the table
+    output_reference: u32,
+    flags: u32,
+}
+
+#[derive(Immutable, IntoBytes)]
+#[repr(C, packed)]
+struct PciRootComplexNode {
+    header: NodeHeader,
+    cache_coherent: u32,
+    allocation_hints: u8,
+    reserved_0: u16,
+    memory_access_flags: u8,
+    ats_attribute: u32,
+    pci_segment: u32,
+    memory_address_size_limit: u8,
+    reserved_1: [u8; 3],
+    id_mapping: IdMapping,
+}
+
+/// IO Remapping Table, the requester ids of segment 0 are the device ids of the only its
+#[derive(Immutable, IntoBytes)]
+#[repr(C, packed)]
+pub struct Iort {
+    header: CommonHeader,
+    iort: IortHeader,
+    its_group: ItsGroupNode,
+    root_complex: PciRootComplexNode,
+}
+
+impl Iort {
+    pub fn new() -> Self {
+        let its_group_offset = size_of::<CommonHeader>() + size_of::<IortHeader>();
+
+        let mut raw = Iort {
+            header: CommonHeader {
+                signature: *b"IORT",
+                length: size_of::<Iort>().try_into().unwrap(),
+                revision: 0,
+                checksum: 0,
+                oem_id: OEMID,
+                oem_table_id: OEM_TABLE_ID,
+                oem_revision: OEM_REVISION,
+                creator_id: CREATOR_ID,
+                creator_revision: CREATOR_REVISION,
+            },
+            iort: IortHeader {
+                node_count: 2,
+                node_offset: its_group_offset as u32,
+                reserved: 0,
+            },
+            its_group: ItsGroupNode {
+                header: NodeHeader {
+                    r#type: IORT_NODE_ITS_GROUP,
+                    length: size_of::<ItsGroupNode>() as u16,
+                    revision: 0,
+                    identifier: 0,
+                    id_mapping_count: 0,
+                    id_mapping_offset: 0,
+                },
+                its_count: 1,
+                its_id: 0,
+            },
+            root_complex: PciRootComplexNode {
+                header: NodeHeader {
+                    r#type: IORT_NODE_PCI_ROOT_COMPLEX,
+                    length: size_of::<PciRootComplexNode>() as u16,
+                    revision: 1,
+                    identifier: 1,
+                    id_mapping_count: 1,
+                    id_mapping_offset: (size_of::<PciRootComplexNode>() - size_of::<IdMapping>())
+                        as u32,
+                },
+                cache_coherent: 1,
+                allocation_hints: 0,
+                reserved_0: 0,
+                memory_access_flags: IORT_MEMORY_ACCESS_COHERENT | IORT_MEMORY_ACCESS_DACS,
+                ats_attribute: 0,
+                pci_segment: 0,
+                memory_address_size_limit: 64,
+                reserved_1: [0; 3],
+                id_mapping: IdMapping {
+                    input_base: 0,
+                    id_count: 0xffff,
+                    output_base: 0,
+                    output_reference: its_group_offset as u32,
+                    flags: 0,
+                },
+            },
+        };
+
+        raw.header.checksum = checksum(raw.as_bytes());
+
+        raw
+    }
+
+    pub fn len(&self) -> usize {
+        self.header.length as usize
+    }
+
+    pub fn install(
+        &self,
+        ram_allocator: &mut RangeAllocator<u64>,
+        memory: &MemoryAddressSpace,
+    ) -> Result<u64, AcpiError> {
+        let address = ram_allocator.alloc(self.len())?.start;
+        memory.copy_from_slice(address, self.as_bytes())?;
+
+        Ok(address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_iort() {
+        let iort = Iort::new();
+        let bytes = iort.as_bytes();
+
+        assert_eq!(checksum(bytes), 0);
+        assert_eq!(iort.len(), bytes.len());
+        assert_eq!(iort.len(), 48 + 24 + 56);
+
+        // The root complex maps to the its group
+        assert_eq!(bytes[48], IORT_NODE_ITS_GROUP);
+        assert_eq!(bytes[72], IORT_NODE_PCI_ROOT_COMPLEX);
+        assert_eq!(
+            u32::from_le_bytes(bytes[72 + 48..72 + 52].try_into().unwrap()),
+            48
+        );
+    }
+}
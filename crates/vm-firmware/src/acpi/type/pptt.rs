@@ -0,0 +1,167 @@
+use vm_mm::manager::MemoryAddressSpace;
+use vm_utils::cpu_topology::CpuTopology;
+use vm_utils::range_allocator::RangeAllocator;
+use zerocopy::Immutable;
+use zerocopy::IntoBytes;
+
+use crate::acpi::CREATOR_ID;
+use crate::acpi::CREATOR_REVISION;
+use crate::acpi::OEM_REVISION;
+use crate::acpi::OEM_TABLE_ID;
+use crate::acpi::OEMID;
+use crate::acpi::error::AcpiError;
+use crate::acpi::r#type::common_header::CommonHeader;
+use crate::acpi::utils::checksum;
+
+const PPTT_PHYSICAL_PACKAGE: u32 = 1 << 0;
+const PPTT_ACPI_PROCESSOR_ID_VALID: u32 = 1 << 1;
+const PPTT_PROCESSOR_IS_A_THREAD: u32 = 1 << 2;
+const PPTT_NODE_IS_A_LEAF: u32 = 1 << 3;
+const PPTT_IDENTICAL_IMPLEMENTATION: u32 = 1 << 4;
+
+#[derive(Immutable, IntoBytes)]
+#[repr(C, packed)]
+struct ProcessorHierarchyNode {
+    r#type: u8,
+    length: u8,
+    reserved: u16,
+    flags: u32,
+    /// Offset of the parent node from the start of the table, 0 for the sockets
+    parent: u32,
+    acpi_processor_id: u32,
+    number_of_private_resources: u32,
+}
+
+impl ProcessorHierarchyNode {
+    fn new(flags: u32, parent: u32, acpi_processor_id: u32) -> Self {
+        ProcessorHierarchyNode {
+            r#type: 0,
+            length: size_of::<ProcessorHierarchyNode>() as u8,
+            reserved: 0,
+            flags: flags | PPTT_IDENTICAL_IMPLEMENTATION,
+            parent,
+            acpi_processor_id,
+            number_of_private_resources: 0,
+        }
+    }
+}
+
+/// Processor Properties Topology Table, the leaves are the vcpus and their
+/// processor ids are the uids in the MADT.
+pub struct Pptt {
+    header: CommonHeader,
+    nodes: Vec<u8>,
+}
+
+impl Pptt {
+    pub fn new(cpu_topology: &CpuTopology) -> Self {
+        let mut nodes = Vec::new();
+        let offset = |nodes: &Vec<u8>| (size_of::<CommonHeader>() + nodes.len()) as u32;
+
+        for socket in 0..cpu_topology.sockets {
+            let socket_offset = offset(&nodes);
+            let node = ProcessorHierarchyNode::new(
+                PPTT_PHYSICAL_PACKAGE | PPTT_ACPI_PROCESSOR_ID_VALID,
+                0,
+                socket,
+            );
+            nodes.extend_from_slice(node.as_bytes());
+
+            for core in 0..cpu_topology.cores_per_socket {
+                let vcpu_id =
+                    (socket * cpu_topology.cores_per_socket + core) * cpu_topology.threads_per_core;
+
+                if cpu_topology.threads_per_core == 1 {
+                    let node = ProcessorHierarchyNode::new(
+                        PPTT_ACPI_PROCESSOR_ID_VALID | PPTT_NODE_IS_A_LEAF,
+                        socket_offset,
+                        vcpu_id,
+                    );
+                    nodes.extend_from_slice(node.as_bytes());
+
+                    continue;
+                }
+
+                let core_offset = offset(&nodes);
+                let node = ProcessorHierarchyNode::new(0, socket_offset, core);
+                nodes.extend_from_slice(node.as_bytes());
+
+                for thread in 0..cpu_topology.threads_per_core {
+                    let node = ProcessorHierarchyNode::new(
+                        PPTT_ACPI_PROCESSOR_ID_VALID
+                            | PPTT_PROCESSOR_IS_A_THREAD
+                            | PPTT_NODE_IS_A_LEAF,
+                        core_offset,
+                        vcpu_id + thread,
+                    );
+                    nodes.extend_from_slice(node.as_bytes());
+                }
+            }
+        }
+
+        let mut raw = Pptt {
+            header: CommonHeader {
+                signature: *b"PPTT",
+                length: offset(&nodes),
+                revision: 2,
+                checksum: 0,
+                oem_id: OEMID,
+                oem_table_id: OEM_TABLE_ID,
+                oem_revision: OEM_REVISION,
+                creator_id: CREATOR_ID,
+                creator_revision: CREATOR_REVISION,
+            },
+            nodes,
+        };
+
+        raw.header.checksum = checksum(&raw.to_bytes());
+
+        raw
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        [self.header.as_bytes(), self.nodes.as_slice()].concat()
+    }
+
+    pub fn len(&self) -> usize {
+        self.header.length as usize
+    }
+
+    pub fn install(
+        &self,
+        ram_allocator: &mut RangeAllocator<u64>,
+        memory: &MemoryAddressSpace,
+    ) -> Result<u64, AcpiError> {
+        let address = ram_allocator.alloc(self.len())?.start;
+        memory.copy_from_slice(address, &self.to_bytes())?;
+
+        Ok(address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pptt() {
+        let pptt = Pptt::new(&CpuTopology {
+            sockets: 2,
+            cores_per_socket: 2,
+            threads_per_core: 2,
+        });
+        let bytes = pptt.to_bytes();
+
+        assert_eq!(checksum(&bytes), 0);
+        assert_eq!(pptt.len(), bytes.len());
+        // 2 sockets, 4 cores and 8 threads
+        assert_eq!(pptt.len(), 36 + 20 * (2 + 4 + 8));
+
+        // The first thread points to the first core
+        let thread = 36 + 20 * 2;
+        assert_eq!(
+            u32::from_le_bytes(bytes[thread + 8..thread + 12].try_into().unwrap()),
+            36 + 20
+        );
+    }
+}
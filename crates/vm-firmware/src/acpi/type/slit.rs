@@ -0,0 +1,88 @@
+use vm_mm::manager::MemoryAddressSpace;
+use vm_utils::range_allocator::RangeAllocator;
+use zerocopy::IntoBytes;
+
+use crate::acpi::CREATOR_ID;
+use crate::acpi::CREATOR_REVISION;
+use crate::acpi::OEM_REVISION;
+use crate::acpi::OEM_TABLE_ID;
+use crate::acpi::OEMID;
+use crate::acpi::error::AcpiError;
+use crate::acpi::r#type::common_header::CommonHeader;
+use crate::acpi::utils::checksum;
+
+/// System Locality Information Table
+pub struct Slit {
+    header: CommonHeader,
+    localities: u64,
+    /// Row major `localities * localities` matrix of the relative distances
+    distances: Vec<u8>,
+}
+
+impl Slit {
+    pub fn new(distances: Vec<Vec<u8>>) -> Self {
+        let localities = distances.len() as u64;
+        let distances = distances.concat();
+        let length = size_of::<CommonHeader>() + size_of::<u64>() + distances.len();
+
+        let mut raw = Slit {
+            header: CommonHeader {
+                signature: *b"SLIT",
+                length: length.try_into().unwrap(),
+                revision: 1,
+                checksum: 0,
+                oem_id: OEMID,
+                oem_table_id: OEM_TABLE_ID,
+                oem_revision: OEM_REVISION,
+                creator_id: CREATOR_ID,
+                creator_revision: CREATOR_REVISION,
+            },
+            localities,
+            distances,
+        };
+
+        raw.header.checksum = checksum(&raw.to_bytes());
+
+        raw
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        [
+            self.header.as_bytes(),
+            self.localities.as_bytes(),
+            self.distances.as_slice(),
+        ]
+        .concat()
+    }
+
+    pub fn len(&self) -> usize {
+        self.header.length as usize
+    }
+
+    pub fn install(
+        &self,
+        ram_allocator: &mut RangeAllocator<u64>,
+        memory: &MemoryAddressSpace,
+    ) -> Result<u64, AcpiError> {
+        let address = ram_allocator.alloc(self.len())?.start;
+        memory.copy_from_slice(address, &self.to_bytes())?;
+
+        Ok(address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slit() {
+        let slit = Slit::new(vec![vec![10, 20], vec![20, 10]]);
+        let bytes = slit.to_bytes();
+
+        assert_eq!(checksum(&bytes), 0);
+        assert_eq!(slit.len(), bytes.len());
+        assert_eq!(slit.len(), 36 + 8 + 4);
+        assert_eq!(&bytes[44..], &[10, 20, 20, 10]);
+    }
+}
@@ -0,0 +1,109 @@
+use vm_mm::manager::MemoryAddressSpace;
+use vm_utils::range_allocator::RangeAllocator;
+use zerocopy::Immutable;
+use zerocopy::IntoBytes;
+
+use crate::acpi::CREATOR_ID;
+use crate::acpi::CREATOR_REVISION;
+use crate::acpi::OEM_REVISION;
+use crate::acpi::OEM_TABLE_ID;
+use crate::acpi::OEMID;
+use crate::acpi::error::AcpiError;
+use crate::acpi::r#type::common_header::CommonHeader;
+use crate::acpi::r#type::generic_address_structure_format::GenericAddressStructureFormat;
+use crate::acpi::utils::checksum;
+
+const SPCR_INTERFACE_TYPE_PL011: u8 = 0x3;
+const SPCR_INTERRUPT_TYPE_GIC: u8 = 1 << 3;
+const SPCR_BAUD_RATE_115200: u8 = 7;
+const SPCR_TERMINAL_TYPE_VT100: u8 = 0;
+
+/// Serial Port Console Redirection Table
+#[derive(Default, Immutable, IntoBytes)]
+#[repr(C, packed)]
+pub struct Spcr {
+    header: CommonHeader,
+    interface_type: u8,
+    reserved_0: [u8; 3],
+    base_address: GenericAddressStructureFormat,
+    interrupt_type: u8,
+    irq: u8,
+    global_system_interrupt: u32,
+    baud_rate: u8,
+    parity: u8,
+    stop_bits: u8,
+    flow_control: u8,
+    terminal_type: u8,
+    language: u8,
+    pci_device_id: u16,
+    pci_vendor_id: u16,
+    pci_bus_number: u8,
+    pci_device_number: u8,
+    pci_function_number: u8,
+    pci_flags: u32,
+    pci_segment: u8,
+    reserved_1: u32,
+}
+
+impl Spcr {
+    /// A pl011 at `base_address` raising `global_system_interrupt`
+    pub fn new(base_address: u64, global_system_interrupt: u32) -> Self {
+        let mut raw = Spcr {
+            header: CommonHeader {
+                signature: *b"SPCR",
+                length: size_of::<Spcr>().try_into().unwrap(),
+                revision: 2,
+                checksum: 0,
+                oem_id: OEMID,
+                oem_table_id: OEM_TABLE_ID,
+                oem_revision: OEM_REVISION,
+                creator_id: CREATOR_ID,
+                creator_revision: CREATOR_REVISION,
+            },
+            interface_type: SPCR_INTERFACE_TYPE_PL011,
+            base_address: GenericAddressStructureFormat::new_system_memory(32, base_address),
+            interrupt_type: SPCR_INTERRUPT_TYPE_GIC,
+            global_system_interrupt,
+            baud_rate: SPCR_BAUD_RATE_115200,
+            stop_bits: 1,
+            terminal_type: SPCR_TERMINAL_TYPE_VT100,
+            // Not a pci device
+            pci_device_id: 0xffff,
+            pci_vendor_id: 0xffff,
+            ..Default::default()
+        };
+
+        raw.header.checksum = checksum(raw.as_bytes());
+
+        raw
+    }
+
+    pub fn len(&self) -> usize {
+        self.header.length as usize
+    }
+
+    pub fn install(
+        &self,
+        ram_allocator: &mut RangeAllocator<u64>,
+        memory: &MemoryAddressSpace,
+    ) -> Result<u64, AcpiError> {
+        let address = ram_allocator.alloc(self.len())?.start;
+        memory.copy_from_slice(address, self.as_bytes())?;
+
+        Ok(address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spcr() {
+        let spcr = Spcr::new(0x0900_0000, 33);
+
+        assert_eq!(checksum(spcr.as_bytes()), 0);
+        assert_eq!(spcr.len(), spcr.as_bytes().len());
+        assert_eq!(spcr.len(), 80);
+    }
+}
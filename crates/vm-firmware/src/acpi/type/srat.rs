@@ -0,0 +1,163 @@
+use vm_mm::manager::MemoryAddressSpace;
+use vm_utils::range_allocator::RangeAllocator;
+use zerocopy::Immutable;
+use zerocopy::IntoBytes;
+
+use crate::acpi::CREATOR_ID;
+use crate::acpi::CREATOR_REVISION;
+use crate::acpi::OEM_REVISION;
+use crate::acpi::OEM_TABLE_ID;
+use crate::acpi::OEMID;
+use crate::acpi::error::AcpiError;
+use crate::acpi::r#type::common_header::CommonHeader;
+use crate::acpi::utils::checksum;
+
+const SRAT_AFFINITY_ENABLED: u32 = 1 << 0;
+
+#[derive(Immutable, IntoBytes)]
+#[repr(C, packed)]
+pub struct MemoryAffinity {
+    r#type: u8,
+    length: u8,
+    proximity_domain: u32,
+    reserved0: u16,
+    base_address: u64,
+    range_length: u64,
+    reserved1: u32,
+    flags: u32,
+    reserved2: u64,
+}
+
+impl MemoryAffinity {
+    pub fn new(proximity_domain: u32, base_address: u64, range_length: u64) -> Self {
+        MemoryAffinity {
+            r#type: 1,
+            length: 40,
+            proximity_domain,
+            reserved0: 0,
+            base_address,
+            range_length,
+            reserved1: 0,
+            flags: SRAT_AFFINITY_ENABLED,
+            reserved2: 0,
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[derive(Immutable, IntoBytes)]
+#[repr(C, packed)]
+pub struct X2ApicAffinity {
+    r#type: u8,
+    length: u8,
+    reserved0: u16,
+    proximity_domain: u32,
+    x2apic_id: u32,
+    flags: u32,
+    clock_domain: u32,
+    reserved1: u32,
+}
+
+#[cfg(target_arch = "x86_64")]
+impl X2ApicAffinity {
+    pub fn new(proximity_domain: u32, x2apic_id: u32) -> Self {
+        X2ApicAffinity {
+            r#type: 2,
+            length: 24,
+            reserved0: 0,
+            proximity_domain,
+            x2apic_id,
+            flags: SRAT_AFFINITY_ENABLED,
+            clock_domain: 0,
+            reserved1: 0,
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+#[derive(Immutable, IntoBytes)]
+#[repr(C, packed)]
+pub struct GiccAffinity {
+    r#type: u8,
+    length: u8,
+    proximity_domain: u32,
+    acpi_processor_uid: u32,
+    flags: u32,
+    clock_domain: u32,
+}
+
+#[cfg(target_arch = "aarch64")]
+impl GiccAffinity {
+    pub fn new(proximity_domain: u32, acpi_processor_uid: u32) -> Self {
+        GiccAffinity {
+            r#type: 3,
+            length: 18,
+            proximity_domain,
+            acpi_processor_uid,
+            flags: SRAT_AFFINITY_ENABLED,
+            clock_domain: 0,
+        }
+    }
+}
+
+/// System Resource Affinity Table
+pub struct Srat {
+    header: CommonHeader,
+    reserved0: u32,
+    reserved1: u64,
+    affinities: Vec<u8>,
+}
+
+impl Srat {
+    pub fn new(affinities: Vec<u8>) -> Self {
+        let length =
+            size_of::<CommonHeader>() + size_of::<u32>() + size_of::<u64>() + affinities.len();
+
+        let mut raw = Srat {
+            header: CommonHeader {
+                signature: *b"SRAT",
+                length: length.try_into().unwrap(),
+                revision: 3,
+                checksum: 0,
+                oem_id: OEMID,
+                oem_table_id: OEM_TABLE_ID,
+                oem_revision: OEM_REVISION,
+                creator_id: CREATOR_ID,
+                creator_revision: CREATOR_REVISION,
+            },
+            // Must be 1 for backward compatibility
+            reserved0: 1,
+            reserved1: 0,
+            affinities,
+        };
+
+        raw.header.checksum = checksum(&raw.to_bytes());
+
+        raw
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        [
+            self.header.as_bytes(),
+            self.reserved0.as_bytes(),
+            self.reserved1.as_bytes(),
+            self.affinities.as_slice(),
+        ]
+        .concat()
+    }
+
+    pub fn len(&self) -> usize {
+        self.header.length as usize
+    }
+
+    pub fn install(
+        &self,
+        ram_allocator: &mut RangeAllocator<u64>,
+        memory: &MemoryAddressSpace,
+    ) -> Result<u64, AcpiError> {
+        let address = ram_allocator.alloc(self.len())?.start;
+        memory.copy_from_slice(address, &self.to_bytes())?;
+
+        Ok(address)
+    }
+}
@@ -0,0 +1,152 @@
+use vm_mm::manager::MemoryAddressSpace;
+use vm_utils::range_allocator::RangeAllocator;
+use zerocopy::Immutable;
+use zerocopy::IntoBytes;
+
+use crate::acpi::CREATOR_ID;
+use crate::acpi::CREATOR_REVISION;
+use crate::acpi::OEM_REVISION;
+use crate::acpi::OEM_TABLE_ID;
+use crate::acpi::OEMID;
+use crate::acpi::error::AcpiError;
+use crate::acpi::r#type::common_header::CommonHeader;
+use crate::acpi::utils::checksum;
+
+const VIOT_NODE_PCI_RANGE: u8 = 1;
+const VIOT_NODE_VIRTIO_IOMMU_MMIO: u8 = 4;
+
+#[derive(Immutable, IntoBytes)]
+#[repr(C, packed)]
+struct ViotHeader {
+    node_count: u16,
+    node_offset: u16,
+    reserved: [u8; 8],
+}
+
+#[derive(Immutable, IntoBytes)]
+#[repr(C, packed)]
+struct VirtioMmioIommuNode {
+    r#type: u8,
+    reserved0: u8,
+    length: u16,
+    reserved1: [u8; 4],
+    base_address: u64,
+}
+
+#[derive(Immutable, IntoBytes)]
+#[repr(C, packed)]
+struct PciRangeNode {
+    r#type: u8,
+    reserved0: u8,
+    length: u16,
+    endpoint_start: u32,
+    segment_start: u16,
+    segment_end: u16,
+    bdf_start: u16,
+    bdf_end: u16,
+    /// Offset of the iommu node from the start of the table
+    output_node: u16,
+    reserved1: [u8; 6],
+}
+
+/// Virtual I/O Translation Table, all the pci devices of segment 0 are translated by a
+/// virtio-mmio iommu.
+pub struct Viot {
+    header: CommonHeader,
+    viot: ViotHeader,
+    iommu: VirtioMmioIommuNode,
+    pci_range: PciRangeNode,
+}
+
+impl Viot {
+    pub fn new(iommu_base_address: u64) -> Self {
+        let iommu_offset = size_of::<CommonHeader>() + size_of::<ViotHeader>();
+        let length = iommu_offset + size_of::<VirtioMmioIommuNode>() + size_of::<PciRangeNode>();
+
+        let mut raw = Viot {
+            header: CommonHeader {
+                signature: *b"VIOT",
+                length: length.try_into().unwrap(),
+                revision: 0,
+                checksum: 0,
+                oem_id: OEMID,
+                oem_table_id: OEM_TABLE_ID,
+                oem_revision: OEM_REVISION,
+                creator_id: CREATOR_ID,
+                creator_revision: CREATOR_REVISION,
+            },
+            viot: ViotHeader {
+                node_count: 2,
+                node_offset: iommu_offset as u16,
+                reserved: [0; 8],
+            },
+            iommu: VirtioMmioIommuNode {
+                r#type: VIOT_NODE_VIRTIO_IOMMU_MMIO,
+                reserved0: 0,
+                length: size_of::<VirtioMmioIommuNode>() as u16,
+                reserved1: [0; 4],
+                base_address: iommu_base_address,
+            },
+            pci_range: PciRangeNode {
+                r#type: VIOT_NODE_PCI_RANGE,
+                reserved0: 0,
+                length: size_of::<PciRangeNode>() as u16,
+                endpoint_start: 0,
+                segment_start: 0,
+                segment_end: 0,
+                bdf_start: 0,
+                bdf_end: 0xffff,
+                output_node: iommu_offset as u16,
+                reserved1: [0; 6],
+            },
+        };
+
+        raw.header.checksum = checksum(&raw.to_bytes());
+
+        raw
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        [
+            self.header.as_bytes(),
+            self.viot.as_bytes(),
+            self.iommu.as_bytes(),
+            self.pci_range.as_bytes(),
+        ]
+        .concat()
+    }
+
+    pub fn len(&self) -> usize {
+        self.header.length as usize
+    }
+
+    pub fn install(
+        &self,
+        ram_allocator: &mut RangeAllocator<u64>,
+        memory: &MemoryAddressSpace,
+    ) -> Result<u64, AcpiError> {
+        let address = ram_allocator.alloc(self.len())?.start;
+        memory.copy_from_slice(address, &self.to_bytes())?;
+
+        Ok(address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_viot() {
+        let viot = Viot::new(0xd000_0000);
+        let bytes = viot.to_bytes();
+
+        assert_eq!(checksum(&bytes), 0);
+        assert_eq!(viot.len(), bytes.len());
+        assert_eq!(viot.len(), 36 + 12 + 16 + 24);
+
+        // The pci range points to the iommu node
+        assert_eq!(bytes[48], VIOT_NODE_VIRTIO_IOMMU_MMIO);
+        assert_eq!(u16::from_le_bytes([bytes[64 + 16], bytes[64 + 17]]), 48);
+    }
+}
@@ -4,6 +4,8 @@ use serde::Serialize;
 use crate::error::Error;
 use crate::memory_container::MemoryContainer;
 
+#[cfg(target_os = "linux")]
+pub mod memfd_allocator;
 pub mod mmap_allocator;
 pub mod std_allocator;
 
@@ -11,6 +13,8 @@ pub mod std_allocator;
 pub enum AllocatorKind {
     Mmap,
     Std,
+    #[cfg(target_os = "linux")]
+    Memfd,
 }
 
 pub trait Allocator {
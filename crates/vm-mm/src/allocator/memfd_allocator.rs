@@ -0,0 +1,72 @@
+use std::fs::File;
+use std::os::fd::AsRawFd;
+use std::os::fd::FromRawFd;
+use std::os::fd::RawFd;
+
+use memmap2::MmapMut;
+use memmap2::MmapOptions;
+
+use crate::allocator::Allocator;
+use crate::allocator::AllocatorKind;
+use crate::error::Error;
+use crate::memory_container::MemoryContainer;
+
+/// Shared memory backed by a memfd, so that it can be handed to another process
+pub struct MemfdMemoryRegion {
+    file: File,
+    mmap: MmapMut,
+    align: Option<usize>,
+}
+
+impl MemoryContainer for MemfdMemoryRegion {
+    fn kind(&self) -> AllocatorKind {
+        AllocatorKind::Memfd
+    }
+
+    fn align(&self) -> Option<usize> {
+        self.align
+    }
+
+    fn hva(&self) -> *mut u8 {
+        self.mmap.as_ptr() as *mut u8
+    }
+
+    fn length(&self) -> usize {
+        self.mmap.len()
+    }
+
+    fn fd(&self) -> Option<RawFd> {
+        Some(self.file.as_raw_fd())
+    }
+}
+
+pub struct MemfdAllocator;
+
+impl Allocator for MemfdAllocator {
+    type Container = MemfdMemoryRegion;
+
+    const KIND: AllocatorKind = AllocatorKind::Memfd;
+
+    fn alloc(&self, len: usize, align: Option<usize>) -> Result<MemfdMemoryRegion, Error> {
+        let fd = unsafe { libc::memfd_create(c"guest-memory".as_ptr(), libc::MFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(Error::AllocMemfdFailed { len });
+        }
+        let file = unsafe { File::from_raw_fd(fd) };
+
+        file.set_len(len as u64)
+            .map_err(|_| Error::AllocMemfdFailed { len })?;
+
+        let mmap = unsafe { MmapOptions::new().len(len).map_mut(&file) }
+            .map_err(|_| Error::AllocMemfdFailed { len })?;
+
+        // The mapping is only page aligned
+        if let Some(align) = align
+            && !(mmap.as_ptr() as usize).is_multiple_of(align)
+        {
+            return Err(Error::AllocMemfdFailed { len });
+        }
+
+        Ok(MemfdMemoryRegion { file, mmap, align })
+    }
+}
@@ -5,12 +5,22 @@ pub enum Error {
     #[error("failed to allocate anonymous memory, len: {len}")]
     AllocAnonymousMemoryFailed { len: usize },
 
+    #[error("failed to allocate memfd backed memory, len: {len}")]
+    AllocMemfdFailed { len: usize },
+
     #[error("try to access invalid gpa: {0}")]
     AccessInvalidGpa(u64),
 
     #[error("access memory overflow")]
     MemoryOverflow,
 
+    #[error("failed to discard memory, gpa: 0x{gpa:x}, len: {len}, error: {err}")]
+    Discard {
+        gpa: u64,
+        len: usize,
+        err: std::io::Error,
+    },
+
     #[error("failed to save memory snapshot, error: {0}")]
     Save(Box<dyn std::error::Error + Send + Sync>),
 }
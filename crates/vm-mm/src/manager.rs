@@ -5,6 +5,10 @@ use crate::region::MemoryRegion;
 
 pub mod snapshot;
 
+fn host_page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
 #[derive(Default)]
 pub struct MemoryAddressSpace {
     /// gpa |-> memory region
@@ -150,6 +154,41 @@ impl MemoryAddressSpace {
         Ok(())
     }
 
+    /// Release the host pages backing `gpa..gpa + len`. Only the host pages
+    /// fully covered by the range are released.
+    pub fn discard(&self, gpa: u64, len: usize) -> Result<(), Error> {
+        let page_size = host_page_size() as u64;
+
+        let mut start = gpa;
+        let end = gpa.checked_add(len as u64).ok_or(Error::MemoryOverflow)?;
+
+        while start < end {
+            let region = self.try_get_region_by_gpa(start)?;
+            let region_end = region.gpa + region.len() as u64;
+            let step_end = end.min(region_end);
+
+            let hva = region.hva() as u64;
+            let offset = start - region.gpa;
+            let aligned_start = (hva + offset).next_multiple_of(page_size) - hva;
+            let aligned_end = ((hva + (step_end - region.gpa)) & !(page_size - 1)) - hva;
+
+            if aligned_start < aligned_end {
+                let len = (aligned_end - aligned_start) as usize;
+                region
+                    .discard(aligned_start as usize, len)
+                    .map_err(|err| Error::Discard {
+                        gpa: region.gpa + aligned_start,
+                        len,
+                        err,
+                    })?;
+            }
+
+            start = step_end;
+        }
+
+        Ok(())
+    }
+
     fn is_overlapping(&self, region: &MemoryRegion) -> bool {
         let new_left = region.gpa;
         let new_right = region.gpa + region.len() as u64;
@@ -274,6 +313,62 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_discard() -> anyhow::Result<()> {
+        const LEN: usize = 4 << 20;
+
+        let mut memory = MemoryAddressSpace::default();
+        let allocator = MmapAllocator;
+
+        let region = MemoryRegion::new(0, Box::new(allocator.alloc(LEN, None)?));
+        assert!(memory.try_insert(region).is_ok());
+
+        memory.memset(0, 0xff, LEN)?;
+        memory.discard(0, LEN)?;
+
+        let mut buf = [0xffu8; 16];
+        memory.copy_to_slice(0, &mut buf)?;
+        #[cfg(target_os = "linux")]
+        assert_eq!(buf, [0; 16]);
+
+        assert!(memory.discard(0, LEN + 1).is_err());
+
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_discard_memfd() -> anyhow::Result<()> {
+        use std::os::fd::BorrowedFd;
+
+        use crate::allocator::memfd_allocator::MemfdAllocator;
+
+        const LEN: usize = 4 << 20;
+
+        let mut memory = MemoryAddressSpace::default();
+        let allocator = MemfdAllocator;
+
+        let region = MemoryRegion::new(0, Box::new(allocator.alloc(LEN, None)?));
+        let fd = region.fd().unwrap();
+        assert!(memory.try_insert(region).is_ok());
+
+        memory.memset(0, 0xff, LEN)?;
+        memory.discard(0, LEN / 2)?;
+
+        // The punched pages are freed from the memfd, not only unmapped
+        let file = std::fs::File::from(unsafe { BorrowedFd::borrow_raw(fd) }.try_clone_to_owned()?);
+        let blocks = std::os::unix::fs::MetadataExt::blocks(&file.metadata()?);
+        assert_eq!(blocks * 512, LEN as u64 / 2);
+
+        let mut buf = [0xffu8; 16];
+        memory.copy_to_slice(0, &mut buf)?;
+        assert_eq!(buf, [0; 16]);
+        memory.copy_to_slice(LEN as u64 / 2, &mut buf)?;
+        assert_eq!(buf, [0xff; 16]);
+
+        Ok(())
+    }
+
     #[test]
     fn test_memset_multi_regions_ok() -> anyhow::Result<()> {
         let mut memory = MemoryAddressSpace::default();
@@ -3,6 +3,8 @@ use serde::Serialize;
 
 use crate::allocator::Allocator;
 use crate::allocator::AllocatorKind;
+#[cfg(target_os = "linux")]
+use crate::allocator::memfd_allocator::MemfdAllocator;
 use crate::allocator::mmap_allocator::MmapAllocator;
 use crate::allocator::std_allocator::StdAllocator;
 use crate::error::Error;
@@ -44,6 +46,10 @@ impl MemoryAddressSpace {
                 AllocatorKind::Std => {
                     Box::new(StdAllocator.alloc(region.buf.len(), region.align)?) as _
                 }
+                #[cfg(target_os = "linux")]
+                AllocatorKind::Memfd => {
+                    Box::new(MemfdAllocator.alloc(region.buf.len(), region.align)?) as _
+                }
             };
 
             let memory_region = MemoryRegion::new(region.gpa, memory_region);
@@ -1,3 +1,5 @@
+use std::os::fd::RawFd;
+
 use crate::allocator::AllocatorKind;
 
 pub trait MemoryContainer: Send + Sync + 'static {
@@ -9,6 +11,11 @@ pub trait MemoryContainer: Send + Sync + 'static {
 
     fn length(&self) -> usize;
 
+    /// The file backing the memory from offset 0, if it can be shared with another process
+    fn fd(&self) -> Option<RawFd> {
+        None
+    }
+
     fn as_slice(&self) -> &[u8] {
         unsafe { std::slice::from_raw_parts(self.hva(), self.length()) }
     }
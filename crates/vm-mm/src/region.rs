@@ -1,3 +1,5 @@
+use std::os::fd::RawFd;
+
 use crate::allocator::AllocatorKind;
 use crate::memory_container::MemoryContainer;
 
@@ -25,6 +27,10 @@ impl MemoryRegion {
         self.memory.hva()
     }
 
+    pub fn fd(&self) -> Option<RawFd> {
+        self.memory.fd()
+    }
+
     #[allow(clippy::len_without_is_empty)]
     pub fn len(&self) -> usize {
         self.memory.length()
@@ -34,6 +40,39 @@ impl MemoryRegion {
         unsafe { std::slice::from_raw_parts(self.hva(), self.len()) }
     }
 
+    /// Give the backing pages of `offset..offset + len` back to the host,
+    /// they read as zero (or stale data on macOS) when touched again.
+    pub fn discard(&self, offset: usize, len: usize) -> std::io::Result<()> {
+        assert!(offset + len <= self.len());
+
+        let addr = unsafe { self.hva().add(offset) } as *mut libc::c_void;
+
+        #[cfg(target_os = "linux")]
+        {
+            // MADV_DONTNEED only drops the mapping of a shared memfd, the pages stay in its
+            // page cache. The fd is mapped from offset 0, so the hole is punched at `offset`
+            if let Some(fd) = self.fd() {
+                let mode = libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE;
+                if unsafe { libc::fallocate(fd, mode, offset as i64, len as i64) } == 0 {
+                    return Ok(());
+                }
+
+                if unsafe { libc::madvise(addr, len, libc::MADV_REMOVE) } == 0 {
+                    return Ok(());
+                }
+            } else if unsafe { libc::madvise(addr, len, libc::MADV_DONTNEED) } == 0 {
+                return Ok(());
+            }
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        if unsafe { libc::madvise(addr, len, libc::MADV_FREE) } == 0 {
+            return Ok(());
+        }
+
+        Err(std::io::Error::last_os_error())
+    }
+
     pub fn copy_from_slice(&self, src: &[u8]) {
         let point = unsafe { std::slice::from_raw_parts_mut(self.hva(), self.len()) };
         point.copy_from_slice(src);
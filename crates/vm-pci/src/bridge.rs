@@ -0,0 +1,188 @@
+use std::io::Read;
+use std::io::Write;
+use std::iter;
+use std::sync::Arc;
+use std::sync::RwLock;
+use std::sync::Weak;
+
+use vm_core::arch::irq::InterruptController;
+use vm_core::device::Device;
+use vm_core::device::error::DeviceSnapshotError;
+
+use crate::device::capability::pcie::PciExpCap;
+use crate::device::function::PciTypeFunctionCommon;
+use crate::device::function::type1::Type1Function;
+use crate::device::interrupt::legacy::InterruptPin;
+use crate::error::Error;
+use crate::hotplug::PcieHotplugSlot;
+use crate::root_complex::pci_root_complex::PciRootComplex;
+use crate::types::configuration_space::ConfigurationSpace;
+use crate::types::device::PciDevice;
+use crate::types::function::PciFunction;
+
+struct PciBridgeFunction;
+
+impl PciTypeFunctionCommon for PciBridgeFunction {
+    fn vendor_id(&self) -> u16 {
+        0x1b36 // qemu pci-bridge
+    }
+
+    fn device_id(&self) -> u16 {
+        0x0001
+    }
+
+    fn class_code(&self) -> u32 {
+        0x060400
+    }
+
+    fn legacy_interrupt(&self) -> Option<(u8, u8)> {
+        None
+    }
+
+    fn init_capability(&self, _cfg: &mut ConfigurationSpace) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+struct PcieRootPortFunction {
+    port: u8,
+    /// The slot interrupt, if the port has a hot-plug slot
+    hotplug_irq: Option<u8>,
+}
+
+impl PciTypeFunctionCommon for PcieRootPortFunction {
+    fn vendor_id(&self) -> u16 {
+        0x1b36 // qemu pcie-root-port
+    }
+
+    fn device_id(&self) -> u16 {
+        0x000c
+    }
+
+    fn class_code(&self) -> u32 {
+        0x060400
+    }
+
+    fn legacy_interrupt(&self) -> Option<(u8, u8)> {
+        self.hotplug_irq.map(|irq| (irq, InterruptPin::INTA as u8))
+    }
+
+    fn init_capability(&self, cfg: &mut ConfigurationSpace) -> Result<(), Error> {
+        let mut cap = PciExpCap::root_port(self.port);
+        if self.hotplug_irq.is_some() {
+            cap = cap.with_hotplug_slot(self.port.into());
+        }
+        cfg.alloc_capability(cap.into())?;
+
+        Ok(())
+    }
+}
+
+pub struct PciBridgeDevice {
+    name: &'static str,
+    function: Type1Function,
+}
+
+impl Device for PciBridgeDevice {
+    fn name(&self) -> String {
+        self.name.to_string()
+    }
+
+    fn pause(&self) -> Result<(), DeviceSnapshotError> {
+        Ok(())
+    }
+
+    fn resume(&self) -> Result<(), DeviceSnapshotError> {
+        Ok(())
+    }
+
+    fn save(&self, writer: &mut dyn Write) -> Result<(), DeviceSnapshotError> {
+        self.function.save(writer)
+    }
+
+    fn load(&mut self, reader: &mut dyn Read) -> Result<(), DeviceSnapshotError> {
+        self.function.load(reader)
+    }
+}
+
+impl PciDevice for PciBridgeDevice {
+    fn get_function(&self, function: u8) -> Option<&dyn PciFunction> {
+        if function == 0 {
+            return Some(&self.function);
+        }
+
+        None
+    }
+
+    fn get_function_mut(&mut self, function: u8) -> Option<&mut dyn PciFunction> {
+        if function == 0 {
+            return Some(&mut self.function);
+        }
+
+        None
+    }
+
+    fn functions(&self) -> Box<dyn Iterator<Item = &(dyn PciFunction + '_)> + '_> {
+        Box::new(iter::once(&self.function as &dyn PciFunction))
+    }
+}
+
+pub fn new_pci_bridge(primary_bus: u8, secondary_bus: u8) -> Result<PciBridgeDevice, Error> {
+    let function = Type1Function::new(&PciBridgeFunction, primary_bus, secondary_bus)?;
+
+    Ok(PciBridgeDevice {
+        name: "pci bridge",
+        function,
+    })
+}
+
+pub fn new_root_port(primary_bus: u8, secondary_bus: u8) -> Result<PciBridgeDevice, Error> {
+    let function = Type1Function::new(
+        &PcieRootPortFunction {
+            port: secondary_bus,
+            hotplug_irq: None,
+        },
+        primary_bus,
+        secondary_bus,
+    )?;
+
+    Ok(PciBridgeDevice {
+        name: "pcie root port",
+        function,
+    })
+}
+
+pub fn new_hotplug_root_port(
+    primary_bus: u8,
+    secondary_bus: u8,
+    irq: u8,
+    irq_chip: Arc<dyn InterruptController>,
+    root_complex: Weak<RwLock<PciRootComplex>>,
+    bus_index: usize,
+) -> Result<(PciBridgeDevice, Arc<PcieHotplugSlot>), Error> {
+    let mut function = Type1Function::new(
+        &PcieRootPortFunction {
+            port: secondary_bus,
+            hotplug_irq: Some(irq),
+        },
+        primary_bus,
+        secondary_bus,
+    )?;
+
+    let slot = Arc::new(PcieHotplugSlot::new(
+        function.configuration_space.clone(),
+        irq,
+        irq_chip,
+        root_complex,
+        bus_index,
+    ));
+    function.hotplug_slot = Some(slot.clone());
+
+    Ok((
+        PciBridgeDevice {
+            name: "pcie root port",
+            function,
+        },
+        slot,
+    ))
+}
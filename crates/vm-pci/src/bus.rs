@@ -1,13 +1,50 @@
 use std::collections::BTreeMap;
+use std::ops::Range;
 
 use crate::types::device::PciDevice;
 
-#[derive(Default)]
+/// Slots of the root bus which are left to the bridges and the root ports
+const ROOT_BUS_BRIDGE_SLOTS: Range<u8> = 24..32;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum PciBusKind {
+    Root,
+    /// Behind a pci-to-pci bridge
+    PciBridge,
+    /// Behind a pcie root port, only the device 0 is scanned
+    RootPort,
+}
+
+impl PciBusKind {
+    fn device_slots(&self) -> Range<u8> {
+        match self {
+            PciBusKind::Root => 0..ROOT_BUS_BRIDGE_SLOTS.start,
+            PciBusKind::PciBridge => 0..32,
+            PciBusKind::RootPort => 0..1,
+        }
+    }
+}
+
 pub struct PciBus {
+    kind: PciBusKind,
     devices: BTreeMap<u8, Box<dyn PciDevice>>,
+    /// Device number of the bridges, and the index of their secondary bus
+    bridges: BTreeMap<u8, usize>,
 }
 
 impl PciBus {
+    pub fn new(kind: PciBusKind) -> Self {
+        PciBus {
+            kind,
+            devices: Default::default(),
+            bridges: Default::default(),
+        }
+    }
+
+    pub fn kind(&self) -> PciBusKind {
+        self.kind
+    }
+
     pub fn get_device(&self, device_number: u8) -> Option<&dyn PciDevice> {
         self.devices.get(&device_number).map(|dev| dev.as_ref())
     }
@@ -20,9 +57,42 @@ impl PciBus {
         self.devices.iter_mut().map(|(id, dev)| (id, dev.as_mut()))
     }
 
+    pub fn bridges(&self) -> impl Iterator<Item = (&u8, &usize)> {
+        self.bridges.iter()
+    }
+
+    pub fn free_slot(&self) -> Option<u8> {
+        self.kind
+            .device_slots()
+            .find(|slot| !self.devices.contains_key(slot))
+    }
+
+    pub fn free_bridge_slot(&self) -> Option<u8> {
+        if self.kind != PciBusKind::Root {
+            return None;
+        }
+
+        ROOT_BUS_BRIDGE_SLOTS
+            .clone()
+            .find(|slot| !self.devices.contains_key(slot))
+    }
+
     pub fn register_device(&mut self, device_id: u8, device: Box<dyn PciDevice>) {
         let old_dev = self.devices.insert(device_id, device);
 
         assert!(old_dev.is_none());
     }
+
+    pub fn unregister_device(&mut self, device_id: u8) -> Option<Box<dyn PciDevice>> {
+        if self.bridges.contains_key(&device_id) {
+            return None;
+        }
+
+        self.devices.remove(&device_id)
+    }
+
+    pub fn register_bridge(&mut self, device_id: u8, bridge: Box<dyn PciDevice>, secondary: usize) {
+        self.register_device(device_id, bridge);
+        self.bridges.insert(device_id, secondary);
+    }
 }
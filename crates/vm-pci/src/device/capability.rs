@@ -1,10 +1,12 @@
 pub mod msi;
 pub mod msix;
+pub mod pcie;
 
 #[derive(Clone, Copy)]
 #[repr(u16)]
 pub enum PciCapId {
     Msi = 0x05,  /* Message Signalled Interrupts */
     Vndr = 0x09, /* Vendor-Specific */
+    Exp = 0x10,  /* PCI Express */
     MsiX = 0x11, /* MSI-X */
 }
@@ -0,0 +1,102 @@
+use zerocopy::FromBytes;
+use zerocopy::FromZeros;
+use zerocopy::Immutable;
+use zerocopy::IntoBytes;
+use zerocopy::KnownLayout;
+
+use crate::device::capability::PciCapId;
+use crate::types::configuration_space::capability::StandardCapability;
+
+pub const PCI_EXP_FLAGS_VERS2: u16 = 0x0002; /* Capability version 2 */
+pub const PCI_EXP_FLAGS_SLOT: u16 = 0x0100; /* Slot implemented */
+pub const PCI_EXP_TYPE_ROOT_PORT: u16 = 0x4; /* Root Port */
+
+pub const PCI_EXP_LNKCAP_SLS_2_5GB: u32 = 0x00000001; /* LNKCAP2 SLS Vector bit 0 */
+pub const PCI_EXP_LNKCAP_MLW_X1: u32 = 0x00000010; /* Maximum Link Width x1 */
+pub const PCI_EXP_LNKCAP_DLLLARC: u32 = 0x00100000; /* Data Link Layer Link Active Reporting Capable */
+pub const PCI_EXP_LNKSTA_CLS_2_5GB: u16 = 0x0001; /* Current Link Speed 2.5GT/s */
+pub const PCI_EXP_LNKSTA_NLW_X1: u16 = 0x0010; /* Current Link Width x1 */
+pub const PCI_EXP_LNKSTA_DLLLA: u16 = 0x2000; /* Data Link Layer Link Active */
+pub const PCI_EXP_LNKCAP2_SLS_2_5GB: u32 = 0x00000002; /* Supported Speed 2.5GT/s */
+
+pub const PCI_EXP_SLTCAP_ABP: u32 = 0x00000001; /* Attention Button Present */
+pub const PCI_EXP_SLTCAP_PIP: u32 = 0x00000010; /* Power Indicator Present */
+pub const PCI_EXP_SLTCAP_HPC: u32 = 0x00000040; /* Hot-Plug Capable */
+pub const PCI_EXP_SLTCAP_NCCS: u32 = 0x00001000; /* No Command Completed Support */
+pub const PCI_EXP_SLTCAP_PSN_SHIFT: u32 = 19; /* Physical Slot Number */
+
+pub const PCI_EXP_SLTCTL_ABPE: u16 = 0x0001; /* Attention Button Pressed Enable */
+pub const PCI_EXP_SLTCTL_PDCE: u16 = 0x0008; /* Presence Detect Changed Enable */
+pub const PCI_EXP_SLTCTL_HPIE: u16 = 0x0020; /* Hot-Plug Interrupt Enable */
+pub const PCI_EXP_SLTCTL_PIC: u16 = 0x0300; /* Power Indicator Control */
+pub const PCI_EXP_SLTCTL_PWR_IND_OFF: u16 = 0x0300;
+pub const PCI_EXP_SLTCTL_DLLSCE: u16 = 0x1000; /* Data Link Layer State Changed Enable */
+
+pub const PCI_EXP_SLTSTA_ABP: u16 = 0x0001; /* Attention Button Pressed */
+pub const PCI_EXP_SLTSTA_PDC: u16 = 0x0008; /* Presence Detect Changed */
+pub const PCI_EXP_SLTSTA_PDS: u16 = 0x0040; /* Presence Detect State */
+pub const PCI_EXP_SLTSTA_DLLSC: u16 = 0x0100; /* Link State Changed */
+/* Write 1 to clear */
+pub const PCI_EXP_SLTSTA_RW1C: u16 = 0x011f;
+
+#[derive(FromBytes, IntoBytes, KnownLayout, Immutable)]
+#[repr(C, packed)]
+pub struct PciExpCap {
+    pub cap: u8,
+    next: u8,
+    pub flags: u16,
+    pub devcap: u32,
+    pub devctl: u16,
+    pub devsta: u16,
+    pub lnkcap: u32,
+    pub lnkctl: u16,
+    pub lnksta: u16,
+    pub sltcap: u32,
+    pub sltctl: u16,
+    pub sltsta: u16,
+    pub rtctl: u16,
+    pub rtcap: u16,
+    pub rtsta: u32,
+    pub devcap2: u32,
+    pub devctl2: u16,
+    pub devsta2: u16,
+    pub lnkcap2: u32,
+    pub lnkctl2: u16,
+    pub lnksta2: u16,
+    pub sltcap2: u32,
+    pub sltctl2: u16,
+    pub sltsta2: u16,
+}
+
+impl PciExpCap {
+    /// A x1 2.5GT/s root port with the link up
+    pub fn root_port(port: u8) -> Self {
+        let mut cap = PciExpCap::new_zeroed();
+        cap.cap = PciCapId::Exp as u8;
+        cap.flags = PCI_EXP_FLAGS_VERS2 | (PCI_EXP_TYPE_ROOT_PORT << 4);
+        cap.lnkcap = ((port as u32) << 24) | PCI_EXP_LNKCAP_MLW_X1 | PCI_EXP_LNKCAP_SLS_2_5GB;
+        cap.lnksta = PCI_EXP_LNKSTA_NLW_X1 | PCI_EXP_LNKSTA_CLS_2_5GB;
+        cap.lnkcap2 = PCI_EXP_LNKCAP2_SLS_2_5GB;
+
+        cap
+    }
+
+    /// A hot-plug capable slot behind the port, empty and powered
+    pub fn with_hotplug_slot(mut self, slot_number: u16) -> Self {
+        self.flags |= PCI_EXP_FLAGS_SLOT;
+        self.lnkcap |= PCI_EXP_LNKCAP_DLLLARC;
+        self.sltcap = PCI_EXP_SLTCAP_ABP
+            | PCI_EXP_SLTCAP_PIP
+            | PCI_EXP_SLTCAP_HPC
+            | PCI_EXP_SLTCAP_NCCS
+            | ((slot_number as u32) << PCI_EXP_SLTCAP_PSN_SHIFT);
+
+        self
+    }
+}
+
+impl From<PciExpCap> for StandardCapability {
+    fn from(cap: PciExpCap) -> Self {
+        StandardCapability::new(cap.cap, cap.as_bytes()[2..].into())
+    }
+}
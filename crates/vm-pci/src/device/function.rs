@@ -2,6 +2,7 @@ use crate::error::Error;
 use crate::types::configuration_space::ConfigurationSpace;
 
 pub mod type0;
+pub mod type1;
 
 pub trait PciTypeFunctionCommon: Send {
     fn vendor_id(&self) -> u16;
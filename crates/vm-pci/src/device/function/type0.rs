@@ -4,21 +4,24 @@ use std::sync::Arc;
 use std::sync::Mutex;
 
 use strum_macros::FromRepr;
+use vm_core::device::error::DeviceError;
 use vm_core::device::error::DeviceSnapshotError;
 use vm_utils::range_allocator::RangeAllocator;
 
 use crate::device::function::PciTypeFunctionCommon;
 use crate::error::Error;
 use crate::types::bar::PciBarInfo;
+use crate::types::bar::alloc_mmio_bar;
 #[cfg(target_arch = "x86_64")]
 use crate::types::bar::pci_io_bar;
-use crate::types::bar::pci_mmio_32_bar;
+use crate::types::bar::pci_mmio_bar;
 use crate::types::configuration_space::ConfigurationSpace;
 use crate::types::configuration_space::header::type0::Type0Header;
 
 fn init_bar<F>(
     #[cfg(target_arch = "x86_64")] pci_io_window_allocator: &mut RangeAllocator<u16>,
     pci_mmio_window_allocator: &mut RangeAllocator<u64>,
+    pci_mmio64_window_allocator: &mut RangeAllocator<u64>,
     cfg: &mut ConfigurationSpace,
     function: &F,
 ) -> Result<(), Error>
@@ -38,14 +41,24 @@ where
 
                     header.bar[bar_index] = pci_io_bar(start.start);
                 }
-                PciBarInfo::Mmio { is_64bit, len } => {
-                    assert!(!is_64bit);
-
-                    let start = pci_mmio_window_allocator
-                        .alloc(*len)
-                        .map_err(|_| Error::AllocMmio)?;
-
-                    header.bar[bar_index] = pci_mmio_32_bar(start.start.try_into().unwrap());
+                PciBarInfo::Mmio {
+                    is_64bit,
+                    prefetchable,
+                    len,
+                } => {
+                    let start = alloc_mmio_bar(
+                        pci_mmio_window_allocator,
+                        pci_mmio64_window_allocator,
+                        *is_64bit,
+                        *prefetchable,
+                        *len,
+                    )?;
+
+                    let (low, high) = pci_mmio_bar(start, *is_64bit, *prefetchable);
+                    header.bar[bar_index] = low;
+                    if *is_64bit {
+                        header.bar[bar_index + 1] = high;
+                    }
                 }
             }
         }
@@ -79,6 +92,14 @@ pub trait PciType0Function: PciTypeFunctionCommon {
     fn save(&self, writer: &mut dyn Write) -> Result<(), DeviceSnapshotError>;
 
     fn load(&mut self, reader: &mut dyn Read) -> Result<(), DeviceSnapshotError>;
+
+    /// The guest moved `bar` or toggled its decoding, `address` is `None` while it does not decode
+    fn bar_remapped(&self, _bar: Bar, _address: Option<u64>) {}
+
+    /// Back to the power-on state, the configuration space is left to the guest
+    fn reset(&self) -> Result<(), DeviceError> {
+        Ok(())
+    }
 }
 
 pub(crate) struct Type0FunctionInternal<T> {
@@ -97,12 +118,14 @@ where
     pub fn new(
         #[cfg(target_arch = "x86_64")] pci_io_window_allocator: &mut RangeAllocator<u16>,
         pci_mmio_window_allocator: &mut RangeAllocator<u64>,
+        pci_mmio64_window_allocator: &mut RangeAllocator<u64>,
         function: T,
     ) -> Result<Self, Error> {
         Self::new_with_configuration_space(
             #[cfg(target_arch = "x86_64")]
             pci_io_window_allocator,
             pci_mmio_window_allocator,
+            pci_mmio64_window_allocator,
             Default::default(),
             function,
         )
@@ -111,6 +134,7 @@ where
     pub fn new_with_configuration_space(
         #[cfg(target_arch = "x86_64")] pci_io_window_allocator: &mut RangeAllocator<u16>,
         pci_mmio_window_allocator: &mut RangeAllocator<u64>,
+        pci_mmio64_window_allocator: &mut RangeAllocator<u64>,
         configuration_space: Arc<Mutex<ConfigurationSpace>>,
         function: T,
     ) -> Result<Self, Error> {
@@ -131,6 +155,7 @@ where
             #[cfg(target_arch = "x86_64")]
             pci_io_window_allocator,
             pci_mmio_window_allocator,
+            pci_mmio64_window_allocator,
             &mut cfg,
             &function,
         )?;
@@ -148,11 +173,15 @@ where
     }
 
     pub fn pause(&self) -> Result<(), DeviceSnapshotError> {
-        todo!()
+        self.internal.lock().unwrap().function.pause()
     }
 
     pub fn resume(&self) -> Result<(), DeviceSnapshotError> {
-        todo!()
+        self.internal.lock().unwrap().function.resume()
+    }
+
+    pub fn reset(&self) -> Result<(), DeviceError> {
+        self.internal.lock().unwrap().function.reset()
     }
 
     pub fn save(&self, writer: &mut dyn Write) -> Result<(), DeviceSnapshotError> {
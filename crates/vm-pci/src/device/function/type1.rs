@@ -0,0 +1,71 @@
+use std::io::Read;
+use std::io::Write;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use vm_core::device::error::DeviceSnapshotError;
+
+use crate::device::function::PciTypeFunctionCommon;
+use crate::error::Error;
+use crate::hotplug::PcieHotplugSlot;
+use crate::types::configuration_space::ConfigurationSpace;
+use crate::types::configuration_space::header::PciHeaderType;
+use crate::types::configuration_space::header::type1::PCI_PREF_RANGE_TYPE_64;
+use crate::types::configuration_space::header::type1::Type1Header;
+
+/// A pci-to-pci bridge function, it has no bar and only forwards the
+/// configuration requests and the windows to its secondary bus.
+pub struct Type1Function {
+    pub(crate) configuration_space: Arc<Mutex<ConfigurationSpace>>,
+    pub(crate) legacy_irq: Option<(u8, u8)>,
+    pub(crate) hotplug_slot: Option<Arc<PcieHotplugSlot>>,
+}
+
+impl Type1Function {
+    pub fn new<T>(function: &T, primary_bus: u8, secondary_bus: u8) -> Result<Self, Error>
+    where
+        T: PciTypeFunctionCommon,
+    {
+        let mut cfg = ConfigurationSpace::default();
+        cfg.init(function, PciHeaderType::PciToPciBridge as u8);
+        function.init_capability(&mut cfg)?;
+
+        let header = cfg.as_header_mut::<Type1Header>();
+        header.primary_bus = primary_bus;
+        header.secondary_bus = secondary_bus;
+        header.subordinate_bus = secondary_bus;
+        // Windows are closed (base > limit) until the guest programs them
+        header.io_base = 0xf0;
+        header.io_limit = 0;
+        header.memory_base = 0xfff0;
+        header.memory_limit = 0;
+        header.prefetchable_memory_base = 0xfff0 | PCI_PREF_RANGE_TYPE_64;
+        header.prefetchable_memory_limit = PCI_PREF_RANGE_TYPE_64;
+        let legacy_irq = function.legacy_interrupt();
+        if let Some((irq_line, irq_pin)) = legacy_irq {
+            header.interrupt_line = irq_line;
+            header.interrupt_pin = irq_pin;
+        } else {
+            header.interrupt_line = 0xff;
+            header.interrupt_pin = 0x00;
+        }
+
+        Ok(Type1Function {
+            configuration_space: Arc::new(Mutex::new(cfg)),
+            legacy_irq,
+            hotplug_slot: None,
+        })
+    }
+
+    pub fn save(&self, writer: &mut dyn Write) -> Result<(), DeviceSnapshotError> {
+        writer.write_all(&self.configuration_space.lock().unwrap().buf)?;
+
+        Ok(())
+    }
+
+    pub fn load(&mut self, reader: &mut dyn Read) -> Result<(), DeviceSnapshotError> {
+        reader.read_exact(&mut self.configuration_space.lock().unwrap().buf)?;
+
+        Ok(())
+    }
+}
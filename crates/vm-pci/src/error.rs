@@ -16,4 +16,10 @@ pub enum Error {
 
     #[error("Failed to alloc mmio from mmio window")]
     AllocMmio,
+
+    #[error("No pci bus number left for the bridge")]
+    NoBusNumber,
+
+    #[error("No slot left for the bridge on the root bus")]
+    NoBridgeSlot,
 }
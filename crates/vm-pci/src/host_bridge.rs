@@ -121,11 +121,13 @@ impl PciDevice for HostBridgeDevice {
 pub fn new_host_bridge(
     #[cfg(target_arch = "x86_64")] pci_pio_allocator: &mut RangeAllocator<u16>,
     pci_mmio_allocator: &mut RangeAllocator<u64>,
+    pci_mmio64_allocator: &mut RangeAllocator<u64>,
 ) -> Result<HostBridgeDevice, Error> {
     let function = Type0Function::new(
         #[cfg(target_arch = "x86_64")]
         pci_pio_allocator,
         pci_mmio_allocator,
+        pci_mmio64_allocator,
         HostBridgeFunction,
     )?;
 
@@ -0,0 +1,423 @@
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::RwLock;
+use std::sync::Weak;
+
+use vm_core::arch::irq::InterruptController;
+use zerocopy::FromBytes;
+
+use crate::device::capability::PciCapId;
+use crate::device::capability::pcie::PCI_EXP_LNKSTA_DLLLA;
+use crate::device::capability::pcie::PCI_EXP_SLTCTL_ABPE;
+use crate::device::capability::pcie::PCI_EXP_SLTCTL_DLLSCE;
+use crate::device::capability::pcie::PCI_EXP_SLTCTL_HPIE;
+use crate::device::capability::pcie::PCI_EXP_SLTCTL_PDCE;
+use crate::device::capability::pcie::PCI_EXP_SLTCTL_PIC;
+use crate::device::capability::pcie::PCI_EXP_SLTCTL_PWR_IND_OFF;
+use crate::device::capability::pcie::PCI_EXP_SLTSTA_ABP;
+use crate::device::capability::pcie::PCI_EXP_SLTSTA_DLLSC;
+use crate::device::capability::pcie::PCI_EXP_SLTSTA_PDC;
+use crate::device::capability::pcie::PCI_EXP_SLTSTA_PDS;
+use crate::device::capability::pcie::PCI_EXP_SLTSTA_RW1C;
+use crate::device::capability::pcie::PciExpCap;
+use crate::root_complex::pci_root_complex::PciRootComplex;
+use crate::types::configuration_space::ConfigurationSpace;
+use crate::types::configuration_space::command::PciCommand;
+use crate::types::configuration_space::header::type1::Type1Header;
+use crate::types::configuration_space::status::PciStatus;
+use crate::types::device::PciDevice;
+
+/// Offset of the slot control register in the pci express capability, the slot status follows it
+const SLTCTL_OFFSET: u16 = 0x18;
+const SLTSTA_OFFSET: u16 = 0x1a;
+
+type ReleaseCallback = Box<dyn FnOnce() + Send>;
+
+/// The pcie native hot-plug slot of a root port, it holds at most one device
+pub struct PcieHotplugSlot {
+    configuration_space: Arc<Mutex<ConfigurationSpace>>,
+    exp_cap: u16,
+    irq: u8,
+    irq_chip: Arc<dyn InterruptController>,
+    root_complex: Weak<RwLock<PciRootComplex>>,
+    /// Index of the secondary bus of the root port
+    bus_index: usize,
+    /// Run once the guest has powered off the slot after an unplug request
+    on_released: Mutex<Option<ReleaseCallback>>,
+}
+
+impl PcieHotplugSlot {
+    pub(crate) fn new(
+        configuration_space: Arc<Mutex<ConfigurationSpace>>,
+        irq: u8,
+        irq_chip: Arc<dyn InterruptController>,
+        root_complex: Weak<RwLock<PciRootComplex>>,
+        bus_index: usize,
+    ) -> Self {
+        let exp_cap = configuration_space
+            .lock()
+            .unwrap()
+            .find_cap(PciCapId::Exp as u8)
+            .unwrap();
+
+        PcieHotplugSlot {
+            configuration_space,
+            exp_cap,
+            irq,
+            irq_chip,
+            root_complex,
+            bus_index,
+            on_released: Mutex::new(None),
+        }
+    }
+
+    fn exp_cap<'a>(&self, cfg: &'a mut ConfigurationSpace) -> &'a mut PciExpCap {
+        let start = self.exp_cap as usize;
+
+        PciExpCap::mut_from_bytes(&mut cfg.buf[start..start + size_of::<PciExpCap>()]).unwrap()
+    }
+
+    pub fn is_occupied(&self) -> bool {
+        let mut cfg = self.configuration_space.lock().unwrap();
+
+        self.exp_cap(&mut cfg).sltsta & PCI_EXP_SLTSTA_PDS != 0
+    }
+
+    /// Insert the device and report the presence change to the guest
+    pub fn plug(&self, device: Box<dyn PciDevice>) -> Result<(), Box<dyn PciDevice>> {
+        let Some(root_complex) = self.root_complex.upgrade() else {
+            return Err(device);
+        };
+        if self.is_occupied() {
+            return Err(device);
+        }
+
+        root_complex
+            .write()
+            .unwrap()
+            .register_device_on_bus(self.bus_index as u8, device)?;
+
+        let mut cfg = self.configuration_space.lock().unwrap();
+        let cap = self.exp_cap(&mut cfg);
+        cap.sltsta |= PCI_EXP_SLTSTA_PDS | PCI_EXP_SLTSTA_PDC | PCI_EXP_SLTSTA_DLLSC;
+        cap.lnksta |= PCI_EXP_LNKSTA_DLLLA;
+        self.update_irq(&mut cfg);
+
+        Ok(())
+    }
+
+    /// Press the attention button. The guest detaches its driver and turns the power indicator off,
+    /// then `on_released` is called, the device is still on the bus until `remove_device`.
+    pub fn request_unplug(&self, on_released: ReleaseCallback) {
+        *self.on_released.lock().unwrap() = Some(on_released);
+
+        let mut cfg = self.configuration_space.lock().unwrap();
+        self.exp_cap(&mut cfg).sltsta |= PCI_EXP_SLTSTA_ABP;
+        self.update_irq(&mut cfg);
+    }
+
+    /// Drop the device of the slot and report the presence change to the guest
+    pub fn remove_device(&self) {
+        let Some(root_complex) = self.root_complex.upgrade() else {
+            return;
+        };
+
+        let bus_number = {
+            let cfg = self.configuration_space.lock().unwrap();
+            cfg.as_header::<Type1Header>().secondary_bus
+        };
+        let device = root_complex
+            .write()
+            .unwrap()
+            .unregister_device(self.bus_index, bus_number, 0);
+        drop(device);
+
+        let mut cfg = self.configuration_space.lock().unwrap();
+        let cap = self.exp_cap(&mut cfg);
+        cap.sltsta &= !PCI_EXP_SLTSTA_PDS;
+        cap.sltsta |= PCI_EXP_SLTSTA_PDC | PCI_EXP_SLTSTA_DLLSC;
+        cap.lnksta &= !PCI_EXP_LNKSTA_DLLLA;
+        self.update_irq(&mut cfg);
+    }
+
+    /// Returns true if the write hits the slot control or the slot status register
+    pub(crate) fn ecam_write(&self, cfg: &mut ConfigurationSpace, offset: u16, buf: &[u8]) -> bool {
+        let sltctl = self.exp_cap + SLTCTL_OFFSET;
+        let sltsta = self.exp_cap + SLTSTA_OFFSET;
+
+        match (offset, buf.len()) {
+            (offset, 2) if offset == sltctl => {
+                self.write_sltctl(cfg, u16::from_le_bytes([buf[0], buf[1]]))
+            }
+            (offset, 4) if offset == sltctl => {
+                self.write_sltctl(cfg, u16::from_le_bytes([buf[0], buf[1]]));
+                self.write_sltsta(cfg, u16::from_le_bytes([buf[2], buf[3]]));
+            }
+            (offset, 2) if offset == sltsta => {
+                self.write_sltsta(cfg, u16::from_le_bytes([buf[0], buf[1]]))
+            }
+            _ => return false,
+        }
+
+        self.update_irq(cfg);
+
+        true
+    }
+
+    fn write_sltctl(&self, cfg: &mut ConfigurationSpace, val: u16) {
+        self.exp_cap(cfg).sltctl = val;
+
+        if val & PCI_EXP_SLTCTL_PIC == PCI_EXP_SLTCTL_PWR_IND_OFF
+            && let Some(on_released) = self.on_released.lock().unwrap().take()
+        {
+            on_released();
+        }
+    }
+
+    fn write_sltsta(&self, cfg: &mut ConfigurationSpace, val: u16) {
+        self.exp_cap(cfg).sltsta &= !(val & PCI_EXP_SLTSTA_RW1C);
+    }
+
+    /// The INTx line is asserted while an enabled event is pending
+    pub(crate) fn update_irq(&self, cfg: &mut ConfigurationSpace) {
+        let cap = self.exp_cap(cfg);
+        let (sltctl, sltsta) = (cap.sltctl, cap.sltsta);
+
+        let pending = sltctl & PCI_EXP_SLTCTL_HPIE != 0
+            && ((sltctl & PCI_EXP_SLTCTL_ABPE != 0 && sltsta & PCI_EXP_SLTSTA_ABP != 0)
+                || (sltctl & PCI_EXP_SLTCTL_PDCE != 0 && sltsta & PCI_EXP_SLTSTA_PDC != 0)
+                || (sltctl & PCI_EXP_SLTCTL_DLLSCE != 0 && sltsta & PCI_EXP_SLTSTA_DLLSC != 0));
+
+        let header = cfg.as_common_header_mut();
+        if pending {
+            header.status |= PciStatus::Interrupt as u16;
+        } else {
+            header.status &= !(PciStatus::Interrupt as u16);
+        }
+        let masked =
+            PciCommand::from_bits_retain(header.command).contains(PciCommand::INTX_DISABLE);
+
+        self.irq_chip
+            .trigger_irq(self.irq as u32, pending && !masked);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::io::Write;
+    use std::mem::offset_of;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::atomic::Ordering;
+
+    use vm_core::arch::irq::Phandle;
+    use vm_core::arch::irq::error::IrqChipError;
+    use vm_fdt::FdtWriter;
+    use vm_utils::range_allocator::RangeAllocator;
+
+    use super::*;
+    use crate::bridge::new_pci_bridge;
+    use crate::types::configuration_space::header::HeaderCommon;
+
+    const IRQ: u8 = 5;
+    /// Power indicator blinking, the guest is detaching the driver
+    const PWR_IND_BLINK: u16 = 0x0200;
+
+    /// Records the level of the slot interrupt
+    #[derive(Default)]
+    struct IrqChip(AtomicBool);
+
+    impl InterruptController for IrqChip {
+        fn trigger_irq(&self, irq_line: u32, active: bool) {
+            assert_eq!(irq_line, IRQ as u32);
+            self.0.store(active, Ordering::SeqCst);
+        }
+
+        fn send_msi(&self, _address_lo: u32, _address_hi: u32, _data: u32) {
+            unreachable!()
+        }
+
+        fn write_device_tree(&self, _fdt: &mut FdtWriter) -> Result<Phandle, IrqChipError> {
+            unreachable!()
+        }
+
+        fn save(&self, _write: &mut dyn Write) -> Result<(), IrqChipError> {
+            unreachable!()
+        }
+
+        fn load(&mut self, _read: &mut dyn Read) -> Result<(), IrqChipError> {
+            unreachable!()
+        }
+    }
+
+    struct Port {
+        root_complex: Arc<RwLock<PciRootComplex>>,
+        slot: Arc<PcieHotplugSlot>,
+        irq_chip: Arc<IrqChip>,
+        /// Device number of the root port on the root bus
+        device: u8,
+    }
+
+    impl Port {
+        fn new() -> Self {
+            let root_complex = Arc::new(RwLock::new(PciRootComplex::new(
+                #[cfg(target_arch = "x86_64")]
+                &mut RangeAllocator::default(),
+                &mut RangeAllocator::default(),
+                &mut RangeAllocator::default(),
+                0xff,
+            )));
+            let irq_chip = Arc::new(IrqChip::default());
+
+            let slot = root_complex
+                .write()
+                .unwrap()
+                .add_hotplug_root_port(
+                    IRQ,
+                    irq_chip.clone(),
+                    0x1000_0000..0x1100_0000,
+                    Arc::downgrade(&root_complex),
+                )
+                .unwrap();
+            let device = *root_complex.read().unwrap().bus[0]
+                .bridges()
+                .next()
+                .unwrap()
+                .0;
+
+            Port {
+                root_complex,
+                slot,
+                irq_chip,
+                device,
+            }
+        }
+
+        fn read(&self, offset: u16) -> u16 {
+            let mut buf = [0; 2];
+            self.root_complex.read().unwrap().handle_ecam_read(
+                0,
+                self.device,
+                0,
+                self.slot.exp_cap + offset,
+                &mut buf,
+            );
+
+            u16::from_le_bytes(buf)
+        }
+
+        fn write(&self, offset: u16, val: u16) {
+            self.root_complex.read().unwrap().handle_ecam_write(
+                0,
+                self.device,
+                0,
+                self.slot.exp_cap + offset,
+                &val.to_le_bytes(),
+            );
+        }
+
+        fn write_command(&self, command: PciCommand) {
+            self.root_complex.read().unwrap().handle_ecam_write(
+                0,
+                self.device,
+                0,
+                offset_of!(HeaderCommon, command) as u16,
+                &command.bits().to_le_bytes(),
+            );
+        }
+
+        fn sltsta(&self) -> u16 {
+            self.read(SLTSTA_OFFSET)
+        }
+
+        /// The device behind the port, on its secondary bus
+        fn has_device(&self) -> bool {
+            self.root_complex.read().unwrap().get_device(1, 0).is_some()
+        }
+
+        fn irq(&self) -> bool {
+            self.irq_chip.0.load(Ordering::SeqCst)
+        }
+    }
+
+    fn device() -> Box<dyn PciDevice> {
+        Box::new(new_pci_bridge(1, 2).unwrap())
+    }
+
+    #[test]
+    fn test_presence_detect() {
+        let port = Port::new();
+        port.write(SLTCTL_OFFSET, PCI_EXP_SLTCTL_HPIE | PCI_EXP_SLTCTL_PDCE);
+
+        assert!(!port.slot.is_occupied());
+        assert_eq!(port.sltsta() & PCI_EXP_SLTSTA_PDS, 0);
+        assert!(!port.irq());
+
+        assert!(port.slot.plug(device()).is_ok());
+        assert!(port.has_device());
+        let sltsta = port.sltsta();
+        assert_ne!(sltsta & PCI_EXP_SLTSTA_PDS, 0);
+        assert_ne!(sltsta & PCI_EXP_SLTSTA_PDC, 0);
+        assert_ne!(
+            port.read(offset_of!(PciExpCap, lnksta) as u16) & PCI_EXP_LNKSTA_DLLLA,
+            0
+        );
+        assert!(port.irq());
+
+        // Only one device per slot
+        assert!(port.slot.plug(device()).is_err());
+
+        // Write 1 to clear, the presence state is read-only
+        port.write(SLTSTA_OFFSET, PCI_EXP_SLTSTA_PDC | PCI_EXP_SLTSTA_PDS);
+        let sltsta = port.sltsta();
+        assert_eq!(sltsta & PCI_EXP_SLTSTA_PDC, 0);
+        assert_ne!(sltsta & PCI_EXP_SLTSTA_PDS, 0);
+        // The link change is still pending but not enabled
+        assert_ne!(sltsta & PCI_EXP_SLTSTA_DLLSC, 0);
+        assert!(!port.irq());
+
+        port.slot.remove_device();
+        assert!(!port.has_device());
+        assert!(!port.slot.is_occupied());
+        assert_ne!(port.sltsta() & PCI_EXP_SLTSTA_PDC, 0);
+        assert!(port.irq());
+
+        // Masked by the interrupt disable bit of the root port
+        port.write_command(PciCommand::MEMORY | PciCommand::MASTER | PciCommand::INTX_DISABLE);
+        assert!(!port.irq());
+    }
+
+    #[test]
+    fn test_attention_button_and_release() {
+        let port = Port::new();
+        assert!(port.slot.plug(device()).is_ok());
+        port.write(SLTSTA_OFFSET, PCI_EXP_SLTSTA_RW1C);
+
+        let sltctl = PCI_EXP_SLTCTL_HPIE | PCI_EXP_SLTCTL_ABPE;
+        port.write(SLTCTL_OFFSET, sltctl);
+        assert!(!port.irq());
+
+        let released = Arc::new(AtomicBool::new(false));
+        port.slot.request_unplug(Box::new({
+            let released = released.clone();
+            move || assert!(!released.swap(true, Ordering::SeqCst))
+        }));
+        assert_ne!(port.sltsta() & PCI_EXP_SLTSTA_ABP, 0);
+        assert!(port.irq());
+
+        port.write(SLTSTA_OFFSET, PCI_EXP_SLTSTA_ABP);
+        assert_eq!(port.sltsta() & PCI_EXP_SLTSTA_ABP, 0);
+        assert!(!port.irq());
+
+        // Not released until the power indicator is off
+        port.write(SLTCTL_OFFSET, sltctl | PWR_IND_BLINK);
+        assert!(!released.load(Ordering::SeqCst));
+        assert_eq!(port.read(SLTCTL_OFFSET), sltctl | PWR_IND_BLINK);
+
+        port.write(SLTCTL_OFFSET, sltctl | PCI_EXP_SLTCTL_PWR_IND_OFF);
+        assert!(released.load(Ordering::SeqCst));
+        // The device stays until it is removed, the callback is only run once
+        assert!(port.has_device());
+        port.write(SLTCTL_OFFSET, sltctl | PCI_EXP_SLTCTL_PWR_IND_OFF);
+    }
+}
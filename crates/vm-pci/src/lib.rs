@@ -2,10 +2,12 @@
 
 pub mod device;
 pub mod error;
+pub mod hotplug;
 pub mod root_complex_device;
 pub mod types;
 
 pub(crate) mod root_complex;
 
+mod bridge;
 mod bus;
 mod host_bridge;
@@ -1,15 +1,32 @@
-use std::io::Read;
-use std::io::Write;
+use std::mem::offset_of;
+use std::ops::Range;
+use std::sync::Arc;
 use std::sync::RwLock;
+use std::sync::Weak;
 
 use tracing::debug;
+use vm_core::arch::irq::InterruptController;
+use vm_core::device::error::DeviceError;
 use vm_core::device::error::DeviceSnapshotError;
+use vm_core::device::snapshot::load_section;
+use vm_core::device::snapshot::pause_device;
+use vm_core::device::snapshot::resume_device;
+use vm_core::device::snapshot::save_section;
+use vm_snapshot::section::Sections;
 use vm_utils::range_allocator::RangeAllocator;
 
+use crate::bridge::new_hotplug_root_port;
+use crate::bridge::new_pci_bridge;
+use crate::bridge::new_root_port;
 use crate::bus::PciBus;
+use crate::bus::PciBusKind;
+use crate::error::Error;
 use crate::host_bridge::new_host_bridge;
-
+use crate::hotplug::PcieHotplugSlot;
 use crate::root_complex::router::Router;
+use crate::types::configuration_space::command::PciCommand;
+use crate::types::configuration_space::header::HeaderCommon;
+use crate::types::configuration_space::header::type1::Type1Header;
 use crate::types::device::PciDevice;
 use crate::types::function::EcamUpdateCallbackOps;
 
@@ -17,19 +34,22 @@ pub struct PciRootComplex {
     pub(crate) bus: Vec<PciBus>,
     pub(crate) pio_router: RwLock<Router<u16>>,
     pub(crate) mmio_router: RwLock<Router<u64>>,
-    allocation: usize,
+    /// The last bus number decoded by the ecam
+    pub(crate) max_bus: u8,
 }
 
 impl PciRootComplex {
     pub fn new(
         #[cfg(target_arch = "x86_64")] pci_pio_allocator: &mut RangeAllocator<u16>,
         pci_mmio_allocator: &mut RangeAllocator<u64>,
+        pci_mmio64_allocator: &mut RangeAllocator<u64>,
+        max_bus: u8,
     ) -> Self {
         let mut rc = PciRootComplex {
-            bus: vec![PciBus::default()],
+            bus: vec![PciBus::new(PciBusKind::Root)],
             pio_router: Default::default(),
             mmio_router: Default::default(),
-            allocation: 0,
+            max_bus,
         };
 
         rc.register_device(Box::new(
@@ -37,6 +57,7 @@ impl PciRootComplex {
                 #[cfg(target_arch = "x86_64")]
                 pci_pio_allocator,
                 pci_mmio_allocator,
+                pci_mmio64_allocator,
             )
             .unwrap(),
         ))
@@ -46,24 +67,182 @@ impl PciRootComplex {
         rc
     }
 
+    /// Place the device on the first bus with a free slot, a pci-to-pci bridge
+    /// is added once all of them are full. Returns the requester id of the device.
     pub fn register_device(
         &mut self,
         device: Box<dyn PciDevice>,
-    ) -> Result<(), Box<dyn PciDevice>> {
-        let bus_number = self.allocation / 32;
-        let device_number = self.allocation % 32;
-        self.allocation += 1;
+    ) -> Result<u16, Box<dyn PciDevice>> {
+        let bus_number = match self
+            .bus
+            .iter()
+            .position(|bus| bus.kind() != PciBusKind::RootPort && bus.free_slot().is_some())
+        {
+            Some(bus_number) => bus_number as u8,
+            None => match self.add_pci_bridge() {
+                Ok(bus_number) => bus_number,
+                Err(_) => return Err(device),
+            },
+        };
 
-        assert_eq!(bus_number, 0);
+        self.register_device_on_bus(bus_number, device)
+    }
 
-        self.bus[0].register_device(device_number as u8, device);
+    /// The requester id is built from the bus number assigned at boot
+    pub fn register_device_on_bus(
+        &mut self,
+        bus_number: u8,
+        device: Box<dyn PciDevice>,
+    ) -> Result<u16, Box<dyn PciDevice>> {
+        let Some(bus) = self.bus.get_mut(bus_number as usize) else {
+            return Err(device);
+        };
+        let Some(device_number) = bus.free_slot() else {
+            return Err(device);
+        };
 
-        Ok(())
+        bus.register_device(device_number, device);
+
+        Ok(((bus_number as u16) << 8) | ((device_number as u16) << 3))
+    }
+
+    /// Returns the root bus slot and the secondary bus number of a new bridge
+    fn alloc_bridge(&self) -> Result<(u8, u8), Error> {
+        let secondary_bus = u8::try_from(self.bus.len())
+            .ok()
+            .filter(|bus| *bus <= self.max_bus)
+            .ok_or(Error::NoBusNumber)?;
+        let device_number = self.bus[0].free_bridge_slot().ok_or(Error::NoBridgeSlot)?;
+
+        Ok((device_number, secondary_bus))
+    }
+
+    fn add_pci_bridge(&mut self) -> Result<u8, Error> {
+        let (device_number, secondary_bus) = self.alloc_bridge()?;
+
+        let bus_index = self.bus.len();
+        self.bus[0].register_bridge(
+            device_number,
+            Box::new(new_pci_bridge(0, secondary_bus)?),
+            bus_index,
+        );
+        self.bus.push(PciBus::new(PciBusKind::PciBridge));
+
+        Ok(secondary_bus)
+    }
+
+    /// Add a pcie root port to the root bus, returns its secondary bus number
+    pub fn add_root_port(&mut self) -> Result<u8, Error> {
+        let (device_number, secondary_bus) = self.alloc_bridge()?;
+
+        let bus_index = self.bus.len();
+        self.bus[0].register_bridge(
+            device_number,
+            Box::new(new_root_port(0, secondary_bus)?),
+            bus_index,
+        );
+        self.bus.push(PciBus::new(PciBusKind::RootPort));
+
+        Ok(secondary_bus)
+    }
+
+    /// Add a root port with a hot-plug slot, `mmio_window` is assigned to the port like the
+    /// firmware would do so that the guest has room for the bars of the plugged devices.
+    pub fn add_hotplug_root_port(
+        &mut self,
+        irq: u8,
+        irq_chip: Arc<dyn InterruptController>,
+        mmio_window: Range<u64>,
+        root_complex: Weak<RwLock<PciRootComplex>>,
+    ) -> Result<Arc<PcieHotplugSlot>, Error> {
+        let (device_number, secondary_bus) = self.alloc_bridge()?;
+
+        let (root_port, slot) = new_hotplug_root_port(
+            0,
+            secondary_bus,
+            irq,
+            irq_chip,
+            root_complex,
+            self.bus.len(),
+        )?;
+        let bus_index = self.bus.len();
+        self.bus[0].register_bridge(device_number, Box::new(root_port), bus_index);
+        self.bus.push(PciBus::new(PciBusKind::RootPort));
+
+        // 1M granularity
+        let memory_base = (mmio_window.start >> 16) as u16 & 0xfff0;
+        let memory_limit = ((mmio_window.end - 1) >> 16) as u16 & 0xfff0;
+        let command = PciCommand::MEMORY | PciCommand::MASTER;
+        for (offset, data) in [
+            (
+                offset_of!(Type1Header, memory_base),
+                memory_base.to_le_bytes(),
+            ),
+            (
+                offset_of!(Type1Header, memory_limit),
+                memory_limit.to_le_bytes(),
+            ),
+            (
+                offset_of!(HeaderCommon, command),
+                command.bits().to_le_bytes(),
+            ),
+        ] {
+            self.handle_ecam_write(0, device_number, 0, offset as u16, &data);
+        }
+
+        Ok(slot)
+    }
+
+    /// Remove a device from the bus at `bus_index`, `bus_number` is the number the guest gave to it
+    pub(crate) fn unregister_device(
+        &mut self,
+        bus_index: usize,
+        bus_number: u8,
+        device_number: u8,
+    ) -> Option<Box<dyn PciDevice>> {
+        let device = self
+            .bus
+            .get_mut(bus_index)?
+            .unregister_device(device_number)?;
+
+        self.pio_router
+            .write()
+            .unwrap()
+            .unregister_device(bus_number, device_number);
+        self.mmio_router
+            .write()
+            .unwrap()
+            .unregister_device(bus_number, device_number);
+
+        Some(device)
+    }
+
+    /// Follow the bridges which claim `bus_number`, like a type 1 configuration request
+    fn find_bus(&self, bus_number: u8) -> Option<&PciBus> {
+        let mut bus = self.bus.first()?;
+        let mut current = 0;
+
+        while current != bus_number {
+            let (secondary_bus, index) = bus.bridges().find_map(|(device, index)| {
+                let buses = bus
+                    .get_device(*device)?
+                    .get_function(0)?
+                    .secondary_bus_range()?;
+
+                buses
+                    .contains(&bus_number)
+                    .then_some((*buses.start(), *index))
+            })?;
+
+            bus = self.bus.get(index)?;
+            current = secondary_bus;
+        }
+
+        Some(bus)
     }
 
     pub fn get_device(&self, bus_number: u8, device_number: u8) -> Option<&dyn PciDevice> {
-        self.bus
-            .get(bus_number as usize)
+        self.find_bus(bus_number)
             .and_then(|bus| bus.get_device(device_number))
     }
 
@@ -121,28 +300,82 @@ impl PciRootComplex {
                         .write()
                         .unwrap()
                         .unregister_handler(bus, device, func, bar),
+                    EcamUpdateCallbackOps::UpdatePioWindow { buses, window } => self
+                        .pio_router
+                        .write()
+                        .unwrap()
+                        .update_window(bus, device, func, buses, window),
+                    EcamUpdateCallbackOps::UpdateMmioWindow {
+                        buses,
+                        window,
+                        prefetchable_window,
+                    } => self.mmio_router.write().unwrap().update_window(
+                        bus,
+                        device,
+                        func,
+                        buses,
+                        window.into_iter().chain(prefetchable_window),
+                    ),
                 }
             }
         }
     }
 
-    pub fn save(&self, writer: &mut dyn Write) -> Result<(), DeviceSnapshotError> {
+    pub fn reset(&self) -> Result<(), DeviceError> {
         for bus in &self.bus {
             for (_, device) in bus.devices() {
-                device.save(writer)?;
+                device.reset()?;
             }
         }
 
         Ok(())
     }
 
-    pub fn load(&mut self, reader: &mut dyn Read) -> Result<(), DeviceSnapshotError> {
-        for bus in &mut self.bus {
-            for (_, device) in bus.devices_mut() {
-                device.load(reader)?;
+    pub fn pause(&self) -> Result<(), DeviceSnapshotError> {
+        for bus in &self.bus {
+            for (_, device) in bus.devices() {
+                pause_device(device)?;
             }
         }
 
         Ok(())
     }
+
+    pub fn resume(&self) -> Result<(), DeviceSnapshotError> {
+        for bus in &self.bus {
+            for (_, device) in bus.devices() {
+                resume_device(device)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Every device is a section keyed by its bus and device number, so a device moved to
+    /// another slot is not loaded with the state of its neighbour
+    pub fn save_sections(&self, sections: &mut Sections) -> Result<(), DeviceSnapshotError> {
+        for (bus_number, bus) in self.bus.iter().enumerate() {
+            for (device_number, device) in bus.devices() {
+                let id = section_id(bus_number, *device_number, &device.name());
+                save_section(device, id, sections)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn load_sections(&mut self, sections: &mut Sections) -> Result<(), DeviceSnapshotError> {
+        for (bus_number, bus) in self.bus.iter_mut().enumerate() {
+            for (device_number, device) in bus.devices_mut() {
+                let id = section_id(bus_number, *device_number, &device.name());
+                load_section(device, &id, sections)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn section_id(bus_number: usize, device_number: u8, name: &str) -> String {
+    format!("pci/{bus_number:02x}:{device_number:02x}/{name}")
 }
@@ -1,5 +1,7 @@
+use std::collections::BTreeMap;
 use std::fmt::Debug;
 use std::ops::Range;
+use std::ops::RangeInclusive;
 
 use rangemap::RangeMap;
 use tracing::debug;
@@ -13,9 +15,17 @@ pub struct Destination<K> {
     pub(crate) base: K,
 }
 
+struct Window<K> {
+    buses: RangeInclusive<u8>,
+    /// e.g. the memory and the prefetchable memory windows of a bridge
+    ranges: Vec<Range<K>>,
+}
+
 #[derive(Default)]
 pub struct Router<K> {
     map: RangeMap<K, Destination<K>>,
+    /// Windows of the bridges, keyed by the bridge function
+    windows: BTreeMap<(u8, u8, u8), Window<K>>,
 }
 
 impl<K> Router<K>
@@ -64,7 +74,91 @@ where
         }
     }
 
+    /// Drop the handlers of all the bars of the device
+    pub fn unregister_device(&mut self, bus: u8, device: u8) {
+        debug!(bus, device, "remove handlers");
+
+        let to_remove: Vec<_> = self
+            .map
+            .iter()
+            .filter(|(_, dest)| dest.bus == bus && dest.device == device)
+            .map(|(range, _)| range.clone())
+            .collect();
+
+        for range in to_remove {
+            self.map.remove(range);
+        }
+    }
+
+    pub fn update_window(
+        &mut self,
+        bus: u8,
+        device: u8,
+        function: u8,
+        buses: Option<RangeInclusive<u8>>,
+        ranges: impl IntoIterator<Item = Range<K>>,
+    ) {
+        let ranges: Vec<_> = ranges.into_iter().collect();
+        debug!(bus, device, function, ?buses, ?ranges, "update window");
+
+        match buses {
+            Some(buses) => {
+                self.windows
+                    .insert((bus, device, function), Window { buses, ranges });
+            }
+            None => {
+                self.windows.remove(&(bus, device, function));
+            }
+        }
+    }
+
     pub fn get_handler(&self, addr: K) -> Option<Destination<K>> {
-        self.map.get(&addr).cloned()
+        let dst = self.map.get(&addr)?;
+
+        // Every bridge upstream of the destination must forward the address
+        let forwarded = self
+            .windows
+            .values()
+            .filter(|window| window.buses.contains(&dst.bus))
+            .all(|window| window.ranges.iter().any(|range| range.contains(&addr)));
+
+        forwarded.then(|| dst.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bridge_window_forwarding() {
+        let mut router = Router::<u64>::default();
+        router.register_handler(0x1000_0000..0x1000_1000, 0, 1, 0, 0);
+        router.register_handler(0x2000_0000..0x2000_1000, 1, 0, 0, 0);
+
+        // Closed window
+        router.update_window(0, 24, 0, Some(1..=1), None);
+        assert!(router.get_handler(0x1000_0000).is_some());
+        assert!(router.get_handler(0x2000_0000).is_none());
+
+        router.update_window(0, 24, 0, Some(1..=1), Some(0x2000_0000..0x2010_0000));
+        assert_eq!(router.get_handler(0x2000_0800).unwrap().bus, 1);
+
+        router.update_window(0, 24, 0, Some(1..=1), Some(0x3000_0000..0x3010_0000));
+        assert!(router.get_handler(0x2000_0000).is_none());
+
+        // Forwarded by the prefetchable window
+        router.update_window(
+            0,
+            24,
+            0,
+            Some(1..=1),
+            [0x3000_0000..0x3010_0000, 0x2000_0000..0x2010_0000],
+        );
+        assert_eq!(router.get_handler(0x2000_0800).unwrap().bus, 1);
+
+        // The bridge is no longer configured
+        router.update_window(0, 24, 0, None, None);
+        assert!(router.get_handler(0x2000_0000).is_some());
     }
 }
@@ -1,5 +1,3 @@
-use std::io::Read;
-use std::io::Write;
 use std::ops::Range;
 use std::sync::Arc;
 use std::sync::RwLock;
@@ -8,19 +6,29 @@ use acpi_tables::Aml;
 use acpi_tables::AmlSink;
 use acpi_tables::aml::AddressSpace;
 use acpi_tables::aml::AddressSpaceCacheable;
+use acpi_tables::aml::Arg;
 use acpi_tables::aml::Device as AmlDevice;
 use acpi_tables::aml::Memory32Fixed;
+use acpi_tables::aml::Method;
 use acpi_tables::aml::Name;
+use acpi_tables::aml::ONE;
 use acpi_tables::aml::Package;
 use acpi_tables::aml::ResourceTemplate;
+use acpi_tables::aml::Return;
+use vm_core::arch::irq::InterruptController;
 use vm_core::device::Device;
 use vm_core::device::error::DeviceError;
 use vm_core::device::error::DeviceSnapshotError;
 use vm_core::device::mmio::mmio_device::MmioDevice;
 #[cfg(target_arch = "x86_64")]
 use vm_core::device::pio::pio_device::PioDevice;
+#[cfg(target_arch = "x86_64")]
+use vm_core::device::system_event::SystemEventNotifier;
+use vm_snapshot::section::Sections;
 use vm_utils::range_allocator::RangeAllocator;
 
+use crate::error::Error;
+use crate::hotplug::PcieHotplugSlot;
 use crate::root_complex::pci_root_complex::PciRootComplex;
 use crate::root_complex_device::mmio::MmioTransport;
 #[cfg(target_arch = "x86_64")]
@@ -39,19 +47,31 @@ pub struct PciRootComplexDevice {
 }
 
 impl PciRootComplexDevice {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         #[cfg(target_arch = "x86_64")] pci_pio_allocator: &mut RangeAllocator<u16>,
         pci_mmio_allocator: &mut RangeAllocator<u64>,
+        pci_mmio64_allocator: &mut RangeAllocator<u64>,
         #[cfg(target_arch = "x86_64")] pio_allocator: &mut RangeAllocator<u16>,
         mmio_allocator: &mut RangeAllocator<u64>,
         #[cfg(target_arch = "x86_64")] io_port_window: Range<u16>,
         ecam_range: Range<u64>,
         bar_mmio_window: Range<u64>,
+        bar_mmio64_window: Option<Range<u64>>,
+        #[cfg(target_arch = "x86_64")] system_event_notifier: Arc<dyn SystemEventNotifier>,
     ) -> Result<Self, DeviceError> {
+        // Each bus takes (32 devices * 8 functions) * 4K of the ecam
+        let max_bus = ((ecam_range.end - ecam_range.start) >> 20)
+            .checked_sub(1)
+            .and_then(|max_bus| u8::try_from(max_bus).ok())
+            .ok_or(DeviceError::AllocResource)?;
+
         let internal = Arc::new(RwLock::new(PciRootComplex::new(
             #[cfg(target_arch = "x86_64")]
             pci_pio_allocator,
             pci_mmio_allocator,
+            pci_mmio64_allocator,
+            max_bus,
         )));
         let device = PciRootComplexDevice {
             #[cfg(target_arch = "x86_64")]
@@ -59,12 +79,14 @@ impl PciRootComplexDevice {
                 pio_allocator,
                 #[cfg(target_arch = "x86_64")]
                 io_port_window,
+                system_event_notifier,
                 internal.clone(),
             )?,
             mmio_transport: MmioTransport::new(
                 mmio_allocator,
                 ecam_range,
                 bar_mmio_window,
+                bar_mmio64_window,
                 internal.clone(),
             )?,
             internal,
@@ -76,26 +98,69 @@ impl PciRootComplexDevice {
     pub fn register_device(
         &mut self,
         device: Box<dyn PciDevice>,
-    ) -> Result<(), Box<dyn PciDevice>> {
+    ) -> Result<u16, Box<dyn PciDevice>> {
         self.internal.write().unwrap().register_device(device)
     }
+
+    pub fn register_device_on_bus(
+        &mut self,
+        bus_number: u8,
+        device: Box<dyn PciDevice>,
+    ) -> Result<u16, Box<dyn PciDevice>> {
+        self.internal
+            .write()
+            .unwrap()
+            .register_device_on_bus(bus_number, device)
+    }
+
+    /// The devices are translated by the virtio-iommu, their requester id is the endpoint id
+    #[cfg(target_arch = "aarch64")]
+    pub fn set_iommu_map(&mut self) {
+        self.mmio_transport.iommu_map = true;
+    }
+
+    /// Returns the secondary bus number of the new root port
+    pub fn add_root_port(&mut self) -> Result<u8, Error> {
+        self.internal.write().unwrap().add_root_port()
+    }
+
+    /// Add a root port whose slot accepts devices at runtime
+    pub fn add_hotplug_root_port(
+        &mut self,
+        irq: u8,
+        irq_chip: Arc<dyn InterruptController>,
+        mmio_window: Range<u64>,
+    ) -> Result<Arc<PcieHotplugSlot>, Error> {
+        let root_complex = Arc::downgrade(&self.internal);
+
+        self.internal.write().unwrap().add_hotplug_root_port(
+            irq,
+            irq_chip,
+            mmio_window,
+            root_complex,
+        )
+    }
 }
 
 impl Aml for PciRootComplexDevice {
     fn to_aml_bytes(&self, sink: &mut dyn AmlSink) {
         let internal = self.internal.read().unwrap();
 
-        assert_eq!(internal.bus.len(), 1, "we only support one bus(0) yet.");
-
+        // The _PRT only covers the root bus, the guest swizzles the pins behind the bridges
         let mut address_irq = Vec::new();
         for (device_id, device) in internal.bus[0].devices() {
             for (function_id, function) in device.functions().enumerate() {
                 let address = ((*device_id as u32) << 16) | (function_id as u32);
 
                 if let Some((line, pin)) = function.legacy_irq() {
+                    let gsi = line as u32;
+                    // The gsi of a spi is its id plus 32
+                    #[cfg(target_arch = "aarch64")]
+                    let gsi = gsi + vm_core::arch::aarch64::irq::GIC_SPI_START;
+
                     // Pci header: 0x01 -> IntA
                     // Acpi: 0x00 -> IntA
-                    address_irq.push((address, line as u32, pin - 1));
+                    address_irq.push((address, gsi, pin - 1));
                 }
             }
         }
@@ -109,39 +174,59 @@ impl Aml for PciRootComplexDevice {
             .collect::<Vec<Package>>();
         let prt = prt.iter().map(|aml| aml as _).collect();
 
+        let ecam = Memory32Fixed::new(
+            true,
+            self.mmio_transport.ecam_range.start.try_into().unwrap(),
+            (self.mmio_transport.ecam_range.end - self.mmio_transport.ecam_range.start)
+                .try_into()
+                .unwrap(),
+        );
+        let bar_mmio_window = AddressSpace::new_memory(
+            AddressSpaceCacheable::NotCacheable,
+            true,
+            self.mmio_transport.pci_bar_mmio_window.start,
+            self.mmio_transport.pci_bar_mmio_window.end - 1,
+            None,
+        );
+        let bar_mmio64_window = self
+            .mmio_transport
+            .pci_bar_mmio64_window
+            .as_ref()
+            .map(|window| {
+                AddressSpace::new_memory(
+                    AddressSpaceCacheable::PreFetchable,
+                    true,
+                    window.start,
+                    window.end - 1,
+                    None,
+                )
+            });
+        #[cfg(target_arch = "x86_64")]
+        let io_port_window = AddressSpace::new_io(
+            self.pio_transport.io_port_window.start,
+            self.pio_transport.io_port_window.end,
+            None,
+        );
+
+        let bus_number = AddressSpace::new_bus_number(0u16, internal.max_bus as u16);
+        let mut crs: Vec<&dyn Aml> = vec![&bus_number, &ecam, &bar_mmio_window];
+        if let Some(bar_mmio64_window) = &bar_mmio64_window {
+            crs.push(bar_mmio64_window);
+        }
+        #[cfg(target_arch = "x86_64")]
+        crs.push(&io_port_window);
+
         AmlDevice::new(
             "_SB_.PCI0".into(),
             vec![
                 &Name::new("_HID".into(), &"PNP0A08"),
                 &Name::new("_CID".into(), &"PNP0A03"),
-                &Name::new(
-                    "_CRS".into(),
-                    &ResourceTemplate::new(vec![
-                        &AddressSpace::new_bus_number(0u16, 0u16),
-                        &Memory32Fixed::new(
-                            true,
-                            self.mmio_transport.ecam_range.start.try_into().unwrap(),
-                            (self.mmio_transport.ecam_range.end
-                                - self.mmio_transport.ecam_range.start)
-                                .try_into()
-                                .unwrap(),
-                        ),
-                        &AddressSpace::new_memory(
-                            AddressSpaceCacheable::NotCacheable,
-                            true,
-                            self.mmio_transport.pci_bar_mmio_window.start,
-                            self.mmio_transport.pci_bar_mmio_window.end - 1,
-                            None,
-                        ),
-                        #[cfg(target_arch = "x86_64")]
-                        &AddressSpace::new_io(
-                            self.pio_transport.io_port_window.start,
-                            self.pio_transport.io_port_window.end,
-                            None,
-                        ),
-                    ]),
-                ),
+                &Name::new("_CRS".into(), &ResourceTemplate::new(crs)),
                 &Name::new("_PRT".into(), &Package::new(prt)),
+                // The dma of the devices is cache coherent, arm64 requires it
+                &Name::new("_CCA".into(), &ONE),
+                // Grant every control the os asks for, so it drives the native pcie hot-plug
+                &Method::new("_OSC".into(), 4, false, vec![&Return::new(&Arg(3))]),
             ],
         )
         .to_aml_bytes(sink);
@@ -153,12 +238,24 @@ impl Device for PciRootComplexDevice {
         "pci-root-complex".to_string()
     }
 
-    fn save(&self, writer: &mut dyn Write) -> Result<(), DeviceSnapshotError> {
-        self.internal.read().unwrap().save(writer)
+    fn reset(&self) -> Result<(), DeviceError> {
+        self.internal.read().unwrap().reset()
+    }
+
+    fn pause(&self) -> Result<(), DeviceSnapshotError> {
+        self.internal.read().unwrap().pause()
+    }
+
+    fn resume(&self) -> Result<(), DeviceSnapshotError> {
+        self.internal.read().unwrap().resume()
+    }
+
+    fn save_sections(&self, sections: &mut Sections) -> Result<(), DeviceSnapshotError> {
+        self.internal.read().unwrap().save_sections(sections)
     }
 
-    fn load(&mut self, read: &mut dyn Read) -> Result<(), DeviceSnapshotError> {
-        self.internal.write().unwrap().load(read)
+    fn load_sections(&mut self, sections: &mut Sections) -> Result<(), DeviceSnapshotError> {
+        self.internal.write().unwrap().load_sections(sections)
     }
 
     fn support_aml(&self) -> Option<&dyn Aml> {
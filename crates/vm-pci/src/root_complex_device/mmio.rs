@@ -15,6 +15,10 @@ mod ecam_handler;
 pub struct MmioTransport {
     pub(crate) ecam_range: Range<u64>,
     pub(crate) pci_bar_mmio_window: Range<u64>,
+    /// The 64-bit prefetchable window above the guest ram
+    pub(crate) pci_bar_mmio64_window: Option<Range<u64>>,
+    #[cfg(target_arch = "aarch64")]
+    pub(crate) iommu_map: bool,
     internal: Arc<RwLock<PciRootComplex>>,
 }
 
@@ -23,6 +27,7 @@ impl MmioTransport {
         mmio_allocator: &mut RangeAllocator<u64>,
         ecam_range: Range<u64>,
         pci_bar_mmio_window: Range<u64>,
+        pci_bar_mmio64_window: Option<Range<u64>>,
         internal: Arc<RwLock<PciRootComplex>>,
     ) -> Result<Self, DeviceError> {
         let _ = mmio_allocator
@@ -41,14 +46,28 @@ impl MmioTransport {
         Ok(MmioTransport {
             ecam_range,
             pci_bar_mmio_window,
+            pci_bar_mmio64_window,
+            #[cfg(target_arch = "aarch64")]
+            iommu_map: false,
             internal,
         })
     }
+
+    fn is_bar_address(&self, addr: u64) -> bool {
+        self.pci_bar_mmio_window.contains(&addr)
+            || self
+                .pci_bar_mmio64_window
+                .as_ref()
+                .is_some_and(|window| window.contains(&addr))
+    }
 }
 
 impl MmioDevice for MmioTransport {
     fn mmio_ranges(&self) -> Vec<Range<u64>> {
-        vec![self.ecam_range.clone(), self.pci_bar_mmio_window.clone()]
+        let mut ranges = vec![self.ecam_range.clone(), self.pci_bar_mmio_window.clone()];
+        ranges.extend(self.pci_bar_mmio64_window.clone());
+
+        ranges
     }
 
     fn mmio_read(&self, addr: u64, buf: &mut [u8]) -> Result<(), DeviceError> {
@@ -59,7 +78,7 @@ impl MmioDevice for MmioTransport {
             return Ok(());
         }
 
-        if self.pci_bar_mmio_window.contains(&addr) {
+        if self.is_bar_address(addr) {
             let internal = self.internal.read().unwrap();
 
             let dst = internal
@@ -88,7 +107,7 @@ impl MmioDevice for MmioTransport {
             return Ok(());
         }
 
-        if self.pci_bar_mmio_window.contains(&addr) {
+        if self.is_bar_address(addr) {
             let internal = self.internal.read().unwrap();
 
             let dst = internal
@@ -116,19 +135,29 @@ impl MmioDevice for MmioTransport {
         fdt.property_u32("#size-cells", 2)?;
         fdt.property_u32("#address-cells", 3)?;
         fdt.property_u32("#interrupt-cells", 1)?;
-        fdt.property_array_u32(
-            "ranges",
-            &[
-                0x0200_0000,                                   // MEM
-                (self.pci_bar_mmio_window.start >> 32) as u32, // pci addr high
-                self.pci_bar_mmio_window.start as u32,         // pci addr low
-                (self.pci_bar_mmio_window.start >> 32) as u32,
-                self.pci_bar_mmio_window.start as u32,
-                ((self.pci_bar_mmio_window.end - self.pci_bar_mmio_window.start) >> 32) as u32,
-                (self.pci_bar_mmio_window.end - self.pci_bar_mmio_window.start) as u32,
-            ],
-        )?;
-        fdt.property_array_u32("bus-range", &[0, 0])?;
+        let mut ranges = vec![
+            0x0200_0000,                                   // MEM
+            (self.pci_bar_mmio_window.start >> 32) as u32, // pci addr high
+            self.pci_bar_mmio_window.start as u32,         // pci addr low
+            (self.pci_bar_mmio_window.start >> 32) as u32,
+            self.pci_bar_mmio_window.start as u32,
+            ((self.pci_bar_mmio_window.end - self.pci_bar_mmio_window.start) >> 32) as u32,
+            (self.pci_bar_mmio_window.end - self.pci_bar_mmio_window.start) as u32,
+        ];
+        if let Some(window) = &self.pci_bar_mmio64_window {
+            ranges.extend([
+                0x4300_0000, // prefetchable 64-bit MEM
+                (window.start >> 32) as u32,
+                window.start as u32,
+                (window.start >> 32) as u32,
+                window.start as u32,
+                ((window.end - window.start) >> 32) as u32,
+                (window.end - window.start) as u32,
+            ]);
+        }
+        fdt.property_array_u32("ranges", &ranges)?;
+        let max_bus = self.internal.read().unwrap().max_bus;
+        fdt.property_array_u32("bus-range", &[0, max_bus as u32])?;
         fdt.property_array_u64(
             "reg",
             &[
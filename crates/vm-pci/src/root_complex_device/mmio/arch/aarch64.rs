@@ -9,26 +9,27 @@ impl MmioTransport {
 
         let internal = self.internal.read().unwrap();
 
+        // Only the root bus, the guest swizzles the pins behind the bridges
         let mut entries = vec![];
-        for (bus_id, bus) in internal.bus.iter().enumerate() {
-            for (device_id, device) in bus.devices() {
-                for (function_id, function) in device.functions().enumerate() {
-                    if let Some(irq_entry) = function.interrupt_map_entry(
-                        bus_id.try_into().unwrap(),
-                        *device_id,
-                        function_id.try_into().unwrap(),
-                    ) {
-                        entries.extend(irq_entry.to_vec());
-                    }
+        for (device_id, device) in internal.bus[0].devices() {
+            for (function_id, function) in device.functions().enumerate() {
+                if let Some(irq_entry) =
+                    function.interrupt_map_entry(0, *device_id, function_id.try_into().unwrap())
+                {
+                    entries.extend(irq_entry.to_vec());
                 }
             }
         }
 
         fdt.property_array_u32("interrupt-map", &entries[..])?;
         if !entries.is_empty() {
-            fdt.property_array_u32("interrupt-map-mask", &[0, 0, 0, 7])?;
+            // Match on the device, the function and the pin
+            fdt.property_array_u32("interrupt-map-mask", &[0xff00, 0, 0, 7])?;
         }
         fdt.property_array_u32("msi-map", &[0, Phandle::MSI as u32, 0, 0x10000])?;
+        if self.iommu_map {
+            fdt.property_array_u32("iommu-map", &[0, Phandle::IOMMU as u32, 0, 0x10000])?;
+        }
 
         Ok(())
     }
@@ -3,8 +3,11 @@ use std::sync::Arc;
 use std::sync::Mutex;
 use std::sync::RwLock;
 
+use vm_core::arch::x86_64::layout::RESET_CONTROL_PORT;
 use vm_core::device::error::DeviceError;
 use vm_core::device::pio::pio_device::PioDevice;
+use vm_core::device::system_event::SystemEvent;
+use vm_core::device::system_event::SystemEventNotifier;
 use vm_utils::range_allocator::RangeAllocator;
 
 use crate::root_complex::pci_root_complex::PciRootComplex;
@@ -15,9 +18,15 @@ mod config_data;
 const CONFIG_ADDRESS: u16 = 0xcf8;
 const CONFIG_DATA: u16 = 0xcfc;
 
+// Writing it to the reset control register resets the cpus, it reads back as 0
+const RESET_CONTROL_RST_CPU: u8 = 1 << 2;
+
 pub struct PioTransport {
     pub(crate) io_port_window: Range<u16>,
     config_address: Mutex<ConfigAddress>,
+    /// The byte accesses to 0xcf9 are for the reset control register
+    reset_control: Mutex<u8>,
+    system_event_notifier: Arc<dyn SystemEventNotifier>,
     internal: Arc<RwLock<PciRootComplex>>,
 }
 
@@ -25,6 +34,7 @@ impl PioTransport {
     pub fn new(
         pio_allocator: &mut RangeAllocator<u16>,
         io_port_window: Range<u16>,
+        system_event_notifier: Arc<dyn SystemEventNotifier>,
         internal: Arc<RwLock<PciRootComplex>>,
     ) -> Result<Self, DeviceError> {
         let _ = pio_allocator
@@ -43,10 +53,24 @@ impl PioTransport {
         Ok(PioTransport {
             io_port_window,
             config_address: Default::default(),
+            reset_control: Default::default(),
+            system_event_notifier,
             internal,
         })
     }
 
+    fn is_reset_control(port: u16, len: usize) -> bool {
+        port == RESET_CONTROL_PORT && len == 1
+    }
+
+    fn handle_out_reset_control(&self, val: u8) {
+        *self.reset_control.lock().unwrap() = val & !RESET_CONTROL_RST_CPU;
+
+        if val & RESET_CONTROL_RST_CPU != 0 {
+            self.system_event_notifier.notify(SystemEvent::Reset);
+        }
+    }
+
     fn handle_out_config_address(&self, offset: u8, data: &[u8]) {
         self.config_address.lock().unwrap().write(offset, data);
     }
@@ -108,6 +132,12 @@ impl PioDevice for PioTransport {
     }
 
     fn io_in(&self, port: u16, data: &mut [u8]) -> Result<(), DeviceError> {
+        if Self::is_reset_control(port, data.len()) {
+            data[0] = *self.reset_control.lock().unwrap();
+
+            return Ok(());
+        }
+
         if (CONFIG_ADDRESS..CONFIG_ADDRESS + 4).contains(&port) {
             let offset = port - CONFIG_ADDRESS;
             self.handle_in_config_address(offset as u8, data);
@@ -139,6 +169,12 @@ impl PioDevice for PioTransport {
     }
 
     fn io_out(&self, port: u16, data: &[u8]) -> Result<(), DeviceError> {
+        if Self::is_reset_control(port, data.len()) {
+            self.handle_out_reset_control(data[0]);
+
+            return Ok(());
+        }
+
         if (CONFIG_ADDRESS..CONFIG_ADDRESS + 4).contains(&port) {
             let offset = port - CONFIG_ADDRESS;
             self.handle_out_config_address(offset as u8, data);
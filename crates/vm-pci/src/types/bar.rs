@@ -1,8 +1,13 @@
+use vm_utils::range_allocator::RangeAllocator;
+
+use crate::error::Error;
+
 pub const PCI_BASE_ADDRESS_SPACE: u32 = 0x01;
 pub const PCI_BASE_ADDRESS_MEM_TYPE_MASK: u32 = 0x06;
 pub const PCI_BASE_ADDRESS_MEM_TYPE_32: u32 = 0x00; /* 32 bit address */
 pub const PCI_BASE_ADDRESS_MEM_TYPE_1M: u32 = 0x02; /* Below 1M [obsolete] */
 pub const PCI_BASE_ADDRESS_MEM_TYPE_64: u32 = 0x04; /* 64 bit address */
+pub const PCI_BASE_ADDRESS_MEM_PREFETCH: u32 = 0x08; /* prefetchable? */
 
 const PCI_BASE_ADDRESS_IO_MASK: u32 = !0x03;
 const PCI_BASE_ADDRESS_MEMORY_MASK: u32 = !0x0f;
@@ -10,11 +15,10 @@ const PCI_BASE_ADDRESS_MEMORY_MASK: u32 = !0x0f;
 #[derive(Clone)]
 pub enum PciBarInfo {
     #[cfg(target_arch = "x86_64")]
-    Pio {
-        len: usize,
-    },
+    Pio { len: usize },
     Mmio {
         is_64bit: bool,
+        prefetchable: bool,
         len: usize,
     },
 }
@@ -25,16 +29,63 @@ pub fn pci_io_bar(addr: u16) -> u32 {
     (addr as u32) | PCI_BASE_ADDRESS_SPACE
 }
 
-pub fn pci_mmio_64_bar(addr: u32) -> u32 {
+/// The low and the high dword of a memory bar, the high one is only used by 64-bit bars
+pub fn pci_mmio_bar(addr: u64, is_64bit: bool, prefetchable: bool) -> (u32, u32) {
     assert!(addr & 0xf == 0);
+    assert!(is_64bit || addr <= u32::MAX as u64);
+
+    let mut low = addr as u32;
+    if is_64bit {
+        low |= PCI_BASE_ADDRESS_MEM_TYPE_64;
+    }
+    if prefetchable {
+        low |= PCI_BASE_ADDRESS_MEM_PREFETCH;
+    }
+
+    (low, (addr >> 32) as u32)
+}
+
+/// Bars are naturally aligned. A 64-bit prefetchable bar is placed in the 64-bit window if it fits,
+/// otherwise in the 32-bit one.
+pub fn alloc_mmio_bar(
+    pci_mmio_window_allocator: &mut RangeAllocator<u64>,
+    pci_mmio64_window_allocator: &mut RangeAllocator<u64>,
+    is_64bit: bool,
+    prefetchable: bool,
+    len: usize,
+) -> Result<u64, Error> {
+    let align = len.next_power_of_two();
+
+    if is_64bit
+        && prefetchable
+        && let Ok(range) = pci_mmio64_window_allocator.alloc_aligned(len, align)
+    {
+        return Ok(range.start);
+    }
+
+    pci_mmio_window_allocator
+        .alloc_aligned(len, align)
+        .map(|range| range.start)
+        .map_err(|_| Error::AllocMmio)
+}
+
+/// The value read back after `val` is written to the bar, the flag bits are read only
+pub fn pci_bar_write(info: &PciBarInfo, bar: u32, val: u32) -> u32 {
+    let (len, mask) = match info {
+        #[cfg(target_arch = "x86_64")]
+        PciBarInfo::Pio { len } => (*len as u64, PCI_BASE_ADDRESS_IO_MASK),
+        PciBarInfo::Mmio { len, .. } => (*len as u64, PCI_BASE_ADDRESS_MEMORY_MASK),
+    };
+    let size_mask = len.wrapping_sub(1) as u32;
 
-    addr | PCI_BASE_ADDRESS_MEM_TYPE_64
+    (val & mask & !size_mask) | (bar & !mask)
 }
 
-pub fn pci_mmio_32_bar(addr: u32) -> u32 {
-    assert!(addr & 0b111 == 0);
+/// The value read back after `val` is written to the high dword of a 64-bit memory bar
+pub fn pci_bar_upper_write(len: usize, val: u32) -> u32 {
+    let size_mask = ((len as u64).wrapping_sub(1) >> 32) as u32;
 
-    addr
+    val & !size_mask
 }
 
 pub fn is_pio_bar(bar: u32) -> bool {
@@ -49,26 +49,30 @@ impl PciConfigurationSpace {
     }
 
     pub fn find_cap(&self, cap_type: u8) -> Option<u16> {
-        let header = self.as_common_header();
-        if header.status & PciStatus::CapList as u16 == 0 {
-            return None;
-        }
+        find_cap(&self.buf, cap_type)
+    }
+}
 
-        let mut pos = (self.buf[CommonHeaderOffset::CapabilityPointer as usize] & !0x3) as usize;
+fn find_cap(buf: &[u8; 4096], cap_type: u8) -> Option<u16> {
+    let header = HeaderCommon::ref_from_bytes(&buf[0..size_of::<HeaderCommon>()]).unwrap();
+    if header.status & PciStatus::CapList as u16 == 0 {
+        return None;
+    }
 
-        while pos != 0 {
-            let cap_id = self.buf[pos];
+    let mut pos = (buf[CommonHeaderOffset::CapabilityPointer as usize] & !0x3) as usize;
 
-            if cap_id == cap_type {
-                return Some(pos as u16);
-            }
+    while pos != 0 {
+        let cap_id = buf[pos];
 
-            let next = self.buf[pos + 1];
-            pos = (next & !0x3) as usize;
+        if cap_id == cap_type {
+            return Some(pos as u16);
         }
 
-        None
+        let next = buf[pos + 1];
+        pos = (next & !0x3) as usize;
     }
+
+    None
 }
 
 pub struct ConfigurationSpace {
@@ -140,6 +144,10 @@ impl ConfigurationSpace {
         self.buf[offset as usize..offset as usize + buf.len()].copy_from_slice(buf);
     }
 
+    pub fn find_cap(&self, cap_type: u8) -> Option<u16> {
+        find_cap(&self.buf, cap_type)
+    }
+
     pub fn alloc_capability(&mut self, cap: StandardCapability) -> Result<u8, Error> {
         let header = self.as_common_header_mut();
         header.status |= PciStatus::CapList as u16;
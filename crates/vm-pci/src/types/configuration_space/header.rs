@@ -5,6 +5,7 @@ use zerocopy::IntoBytes;
 use zerocopy::KnownLayout;
 
 pub mod type0;
+pub mod type1;
 
 #[derive(FromRepr, PartialEq)]
 #[repr(u8)]
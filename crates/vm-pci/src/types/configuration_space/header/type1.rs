@@ -0,0 +1,37 @@
+use zerocopy::FromBytes;
+use zerocopy::Immutable;
+use zerocopy::IntoBytes;
+use zerocopy::KnownLayout;
+
+use crate::types::configuration_space::header::HeaderCommon;
+
+/// The low bits of the prefetchable base and limit, the window is 64-bit
+pub const PCI_PREF_RANGE_TYPE_64: u16 = 0x1;
+
+#[derive(FromBytes, IntoBytes, KnownLayout, Immutable)]
+#[repr(C, packed)]
+pub struct Type1Header {
+    pub common: HeaderCommon,
+    pub bar: [u32; 2],
+    pub primary_bus: u8,
+    pub secondary_bus: u8,
+    pub subordinate_bus: u8,
+    pub secondary_latency_timer: u8,
+    pub io_base: u8,
+    pub io_limit: u8,
+    pub secondary_status: u16,
+    pub memory_base: u16,
+    pub memory_limit: u16,
+    pub prefetchable_memory_base: u16,
+    pub prefetchable_memory_limit: u16,
+    pub prefetchable_base_upper32: u32,
+    pub prefetchable_limit_upper32: u32,
+    pub io_base_upper16: u16,
+    pub io_limit_upper16: u16,
+    pub cap_pointer: u8,
+    reserved0: [u8; 3],
+    pub expansion_rom_base_address: u32,
+    pub interrupt_line: u8,
+    pub interrupt_pin: u8,
+    pub bridge_control: u16,
+}
@@ -1,8 +1,10 @@
 use std::ops::Range;
+use std::ops::RangeInclusive;
 
 use crate::types::interrupt::InterruptMapEntry;
 
 pub mod type0;
+pub mod type1;
 
 pub enum EcamUpdateCallbackOps {
     AddPioRouter {
@@ -19,6 +21,17 @@ pub enum EcamUpdateCallbackOps {
     RemoveMmioRouter {
         bar: u8,
     },
+    /// The io window a bridge forwards to `buses`
+    UpdatePioWindow {
+        buses: Option<RangeInclusive<u8>>,
+        window: Option<Range<u16>>,
+    },
+    /// The memory windows a bridge forwards to `buses`
+    UpdateMmioWindow {
+        buses: Option<RangeInclusive<u8>>,
+        window: Option<Range<u64>>,
+        prefetchable_window: Option<Range<u64>>,
+    },
 }
 
 pub struct EcamUpdateCallback(pub Vec<EcamUpdateCallbackOps>);
@@ -34,6 +47,11 @@ pub trait PciFunction: PciFunctionArch + Send + Sync {
 
     // irq_line, irq_pin
     fn legacy_irq(&self) -> Option<(u8, u8)>;
+
+    /// Secondary and subordinate bus number, only for pci-to-pci bridges
+    fn secondary_bus_range(&self) -> Option<RangeInclusive<u8>> {
+        None
+    }
 }
 
 pub trait PciFunctionArch {
@@ -5,6 +5,8 @@ use crate::device::function::type0::PciType0Function;
 use crate::device::function::type0::Type0Function;
 use crate::types::bar::PciBarInfo;
 use crate::types::bar::address_of_bar;
+use crate::types::bar::pci_bar_upper_write;
+use crate::types::bar::pci_bar_write;
 use crate::types::configuration_space::command::PciCommand;
 use crate::types::configuration_space::header::type0::Type0Header;
 use crate::types::function::EcamUpdateCallback;
@@ -57,28 +59,61 @@ where
         let val = u32::from_le_bytes(buf.try_into().unwrap());
         let mut configuration_space = internal.configuration_space.lock().unwrap();
         let header = configuration_space.as_header_mut::<Type0Header>();
+        let n = n as usize;
 
-        if let Some(bar_info) = &bar_info[n as usize] {
-            let bar_size: u32 = match bar_info {
-                #[cfg(target_arch = "x86_64")]
-                PciBarInfo::Pio { len } => *len,
-                PciBarInfo::Mmio { len, .. } => *len,
-            }
-            .try_into()
-            .unwrap();
+        // The bar whose address changed, the lower half for the upper dword of a 64-bit bar
+        let base;
+        header.bar[n] = if let Some(bar_info) = &bar_info[n] {
+            base = Some(n);
+            pci_bar_write(bar_info, header.bar[n], val)
+        } else if n > 0
+            && let Some(PciBarInfo::Mmio {
+                is_64bit: true,
+                len,
+                ..
+            }) = &bar_info[n - 1]
+        {
+            base = Some(n - 1);
+            pci_bar_upper_write(*len, val)
+        } else {
+            base = None;
+            0
+        };
+
+        let Some(base) = base else {
+            return;
+        };
 
-            if val == u32::MAX {
-                header.bar[n as usize] = !(bar_size - 1);
-            } else {
-                header.bar[n as usize] = val;
+        let command = PciCommand::from_bits_retain(header.common.command);
+        let address = match &bar_info[base] {
+            #[cfg(target_arch = "x86_64")]
+            Some(PciBarInfo::Pio { .. }) => command
+                .contains(PciCommand::IO)
+                .then(|| address_of_bar(header.bar[base]) as u64),
+            Some(PciBarInfo::Mmio { is_64bit, .. }) => {
+                command.contains(PciCommand::MEMORY).then(|| {
+                    let address = address_of_bar(header.bar[base]) as u64;
+                    if *is_64bit {
+                        (header.bar[base + 1] as u64) << 32 | address
+                    } else {
+                        address
+                    }
+                })
             }
-        } else {
-            header.bar[n as usize] = 0;
+            None => return,
+        };
+        drop(configuration_space);
+
+        if address.is_some() {
+            internal
+                .function
+                .bar_remapped(Bar::from_repr(base as u8).unwrap(), address);
         }
     }
 
     fn write_command(&self, command: u16) -> Option<EcamUpdateCallback> {
         let mut callback_ops = vec![];
+        let mut remapped = vec![];
 
         let internal = self.internal.lock().unwrap();
         let bar_info = internal.function.bar_info();
@@ -107,28 +142,45 @@ where
                             bar: i as u8,
                             port: address as u16..address as u16 + *len as u16,
                         });
+                        remapped.push((i, Some(address as u64)));
                     } else if !command.contains(PciCommand::IO)
                         && old_command.contains(PciCommand::IO)
                     {
                         callback_ops.push(EcamUpdateCallbackOps::RemovePioRouter { bar: i as u8 });
+                        remapped.push((i, None));
                     }
                 }
-                PciBarInfo::Mmio { len, .. } => {
+                PciBarInfo::Mmio { is_64bit, len, .. } => {
+                    let address = if *is_64bit {
+                        (header.bar[i + 1] as u64) << 32 | address as u64
+                    } else {
+                        address as u64
+                    };
+
                     if command.contains(PciCommand::MEMORY)
                         && !old_command.contains(PciCommand::MEMORY)
                     {
                         callback_ops.push(EcamUpdateCallbackOps::AddMmioRouter {
                             bar: i as u8,
-                            pci_address_range: address as u64..address as u64 + *len as u64,
+                            pci_address_range: address..address + *len as u64,
                         });
+                        remapped.push((i, Some(address)));
                     } else if !command.contains(PciCommand::MEMORY)
                         && old_command.contains(PciCommand::MEMORY)
                     {
                         callback_ops.push(EcamUpdateCallbackOps::RemoveMmioRouter { bar: i as u8 });
+                        remapped.push((i, None));
                     }
                 }
             }
         }
+        drop(configuration_space);
+
+        for (i, address) in remapped {
+            internal
+                .function
+                .bar_remapped(Bar::from_repr(i as u8).unwrap(), address);
+        }
 
         Some(EcamUpdateCallback(callback_ops))
     }
@@ -190,3 +242,128 @@ where
         self.internal.lock().unwrap().function.legacy_interrupt()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::io::Write;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    use vm_core::device::error::DeviceSnapshotError;
+    use vm_utils::range_allocator::RangeAllocator;
+
+    use super::*;
+    use crate::device::function::PciTypeFunctionCommon;
+    use crate::error::Error;
+    use crate::types::configuration_space::ConfigurationSpace;
+
+    type RemapLog = Arc<Mutex<Vec<(u8, Option<u64>)>>>;
+
+    #[derive(Default)]
+    struct Remaps(RemapLog);
+
+    impl PciTypeFunctionCommon for Remaps {
+        fn vendor_id(&self) -> u16 {
+            0x1234
+        }
+
+        fn device_id(&self) -> u16 {
+            0x5678
+        }
+
+        fn class_code(&self) -> u32 {
+            0
+        }
+
+        fn legacy_interrupt(&self) -> Option<(u8, u8)> {
+            None
+        }
+
+        fn init_capability(&self, _cfg: &mut ConfigurationSpace) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    impl PciType0Function for Remaps {
+        fn bar_info(&self) -> [Option<PciBarInfo>; 6] {
+            [
+                Some(PciBarInfo::Mmio {
+                    is_64bit: false,
+                    prefetchable: false,
+                    len: 0x1000,
+                }),
+                None,
+                None,
+                None,
+                None,
+                None,
+            ]
+        }
+
+        fn bar_read(&self, _bar: Bar, _offset: u64, _buf: &mut [u8]) {}
+
+        fn bar_write(&self, _bar: Bar, _offset: u64, _buf: &[u8]) {}
+
+        fn pause(&self) -> Result<(), DeviceSnapshotError> {
+            Ok(())
+        }
+
+        fn resume(&self) -> Result<(), DeviceSnapshotError> {
+            Ok(())
+        }
+
+        fn save(&self, _writer: &mut dyn Write) -> Result<(), DeviceSnapshotError> {
+            Ok(())
+        }
+
+        fn load(&mut self, _reader: &mut dyn Read) -> Result<(), DeviceSnapshotError> {
+            Ok(())
+        }
+
+        fn bar_remapped(&self, bar: Bar, address: Option<u64>) {
+            self.0.lock().unwrap().push((bar as u8, address));
+        }
+    }
+
+    #[test]
+    fn test_bar_remapped() {
+        let remaps = Remaps::default();
+        let log = remaps.0.clone();
+
+        #[cfg(target_arch = "x86_64")]
+        let mut pio_allocator = RangeAllocator::<u16>::default();
+        let mut mmio_allocator = RangeAllocator::<u64>::default();
+        mmio_allocator.insert(0x1000_0000, 0x10_0000).unwrap();
+        let mut mmio64_allocator = RangeAllocator::<u64>::default();
+
+        let function = Type0Function::new(
+            #[cfg(target_arch = "x86_64")]
+            &mut pio_allocator,
+            &mut mmio_allocator,
+            &mut mmio64_allocator,
+            remaps,
+        )
+        .unwrap();
+
+        // Not decoding, the bar moves silently
+        function.ecam_write(
+            Type0HeaderOffset::Bar0 as u16,
+            &0x1000_2000u32.to_le_bytes(),
+        );
+        assert!(log.lock().unwrap().is_empty());
+
+        let command = PciCommand::MEMORY.bits();
+        function.ecam_write(Type0HeaderOffset::Command as u16, &command.to_le_bytes());
+        function.ecam_write(
+            Type0HeaderOffset::Bar0 as u16,
+            &0x1000_4000u32.to_le_bytes(),
+        );
+        function.ecam_write(Type0HeaderOffset::Command as u16, &0u16.to_le_bytes());
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            [(0, Some(0x1000_2000)), (0, Some(0x1000_4000)), (0, None)]
+        );
+    }
+}
@@ -1,7 +1,3 @@
-use vm_core::arch::aarch64::irq::GIC_SPI;
-use vm_core::arch::aarch64::irq::IRQ_TYPE_LEVEL_HIGH;
-use vm_core::arch::irq::Phandle;
-
 use crate::device::function::type0::PciType0Function;
 use crate::device::function::type0::Type0Function;
 use crate::types::function::PciFunctionArch;
@@ -15,22 +11,8 @@ where
     fn interrupt_map_entry(&self, bus: u8, device: u8, function: u8) -> Option<InterruptMapEntry> {
         let internal = self.internal.lock().unwrap();
 
-        internal
-            .function
-            .legacy_interrupt()
-            .map(|(irq_line, pin)| InterruptMapEntryAArch64 {
-                pci_addr_high: ((bus as u32) << 16)
-                    | ((device as u32) << 11)
-                    | ((function as u32) << 8),
-                pci_addr_mid: 0,
-                pci_addr_low: 0,
-                pin: pin.into(),
-                gic_phandle: Phandle::GIC as u32,
-                gic_addr_high: 0,
-                gic_addr_low: 0,
-                gic_irq_type: GIC_SPI,
-                gic_irq_num: irq_line.into(),
-                gic_irq_flags: IRQ_TYPE_LEVEL_HIGH,
-            })
+        internal.function.legacy_interrupt().map(|(irq_line, pin)| {
+            InterruptMapEntryAArch64::new(bus, device, function, irq_line, pin)
+        })
     }
 }
@@ -0,0 +1,190 @@
+use std::ops::Range;
+use std::ops::RangeInclusive;
+
+use crate::device::function::type1::Type1Function;
+use crate::types::configuration_space::command::PciCommand;
+use crate::types::configuration_space::header::type1::PCI_PREF_RANGE_TYPE_64;
+use crate::types::configuration_space::header::type1::Type1Header;
+use crate::types::function::EcamUpdateCallback;
+use crate::types::function::EcamUpdateCallbackOps;
+use crate::types::function::PciFunction;
+use crate::types::function::PciFunctionArch;
+use crate::types::interrupt::InterruptMapEntry;
+
+/// What the bridge forwards to its secondary side
+#[derive(PartialEq)]
+struct BridgeWindows {
+    buses: Option<RangeInclusive<u8>>,
+    #[cfg(target_arch = "x86_64")]
+    io: Option<Range<u16>>,
+    mmio: Option<Range<u64>>,
+    prefetchable: Option<Range<u64>>,
+}
+
+impl From<&Type1Header> for BridgeWindows {
+    fn from(header: &Type1Header) -> Self {
+        let command = PciCommand::from_bits_retain(header.common.command);
+
+        // A secondary bus number of 0 means the bridge is not configured yet
+        let buses =
+            (header.secondary_bus != 0).then_some(header.secondary_bus..=header.subordinate_bus);
+
+        // 16-bit io window, 4K granularity
+        #[cfg(target_arch = "x86_64")]
+        let io = {
+            let base = (header.io_base as u16 & 0xf0) << 8;
+            let limit = ((header.io_limit as u16 & 0xf0) << 8) | 0xfff;
+
+            (command.contains(PciCommand::IO) && base <= limit)
+                .then_some(base..limit.saturating_add(1))
+        };
+
+        // 32-bit memory window, 1M granularity
+        let mmio = {
+            let base = ((header.memory_base & 0xfff0) as u64) << 16;
+            let limit = (((header.memory_limit & 0xfff0) as u64) << 16) | 0xf_ffff;
+
+            (command.contains(PciCommand::MEMORY) && base <= limit).then_some(base..limit + 1)
+        };
+
+        // 64-bit prefetchable memory window, 1M granularity
+        let prefetchable = {
+            let base = ((header.prefetchable_base_upper32 as u64) << 32)
+                | ((header.prefetchable_memory_base & 0xfff0) as u64) << 16;
+            let limit = ((header.prefetchable_limit_upper32 as u64) << 32)
+                | ((header.prefetchable_memory_limit & 0xfff0) as u64) << 16
+                | 0xf_ffff;
+
+            (command.contains(PciCommand::MEMORY) && base <= limit).then_some(base..limit + 1)
+        };
+
+        BridgeWindows {
+            buses,
+            #[cfg(target_arch = "x86_64")]
+            io,
+            mmio,
+            prefetchable,
+        }
+    }
+}
+
+impl PciFunction for Type1Function {
+    fn ecam_read(&self, offset: u16, buf: &mut [u8]) {
+        self.configuration_space.lock().unwrap().read(offset, buf);
+    }
+
+    fn ecam_write(&self, offset: u16, buf: &[u8]) -> Option<EcamUpdateCallback> {
+        let mut configuration_space = self.configuration_space.lock().unwrap();
+
+        if let Some(slot) = &self.hotplug_slot
+            && slot.ecam_write(&mut configuration_space, offset, buf)
+        {
+            return None;
+        }
+
+        let old = BridgeWindows::from(configuration_space.as_header::<Type1Header>());
+
+        configuration_space.write(offset, buf);
+
+        // No bar and no 32-bit io window, these read as zero
+        let header = configuration_space.as_header_mut::<Type1Header>();
+        header.bar = [0; 2];
+        header.io_base &= 0xf0;
+        header.io_limit &= 0xf0;
+        #[cfg(not(target_arch = "x86_64"))]
+        {
+            header.io_base = 0;
+            header.io_limit = 0;
+        }
+        header.memory_base &= 0xfff0;
+        header.memory_limit &= 0xfff0;
+        header.prefetchable_memory_base =
+            (header.prefetchable_memory_base & 0xfff0) | PCI_PREF_RANGE_TYPE_64;
+        header.prefetchable_memory_limit =
+            (header.prefetchable_memory_limit & 0xfff0) | PCI_PREF_RANGE_TYPE_64;
+        header.io_base_upper16 = 0;
+        header.io_limit_upper16 = 0;
+        header.expansion_rom_base_address = 0;
+
+        let new = BridgeWindows::from(&*header);
+
+        // The interrupt disable bit may have changed
+        if let Some(slot) = &self.hotplug_slot {
+            slot.update_irq(&mut configuration_space);
+        }
+
+        if new == old {
+            return None;
+        }
+
+        Some(EcamUpdateCallback(vec![
+            #[cfg(target_arch = "x86_64")]
+            EcamUpdateCallbackOps::UpdatePioWindow {
+                buses: new.buses.clone(),
+                window: new.io,
+            },
+            EcamUpdateCallbackOps::UpdateMmioWindow {
+                buses: new.buses,
+                window: new.mmio,
+                prefetchable_window: new.prefetchable,
+            },
+        ]))
+    }
+
+    fn bar_read(&self, _bar: u8, _offset: u64, _buf: &mut [u8]) {
+        unreachable!()
+    }
+
+    fn bar_write(&self, _bar: u8, _offset: u64, _buf: &[u8]) {
+        unreachable!()
+    }
+
+    fn legacy_irq(&self) -> Option<(u8, u8)> {
+        self.legacy_irq
+    }
+
+    fn secondary_bus_range(&self) -> Option<RangeInclusive<u8>> {
+        BridgeWindows::from(
+            self.configuration_space
+                .lock()
+                .unwrap()
+                .as_header::<Type1Header>(),
+        )
+        .buses
+    }
+}
+
+impl PciFunctionArch for Type1Function {
+    fn interrupt_map_entry(&self, bus: u8, device: u8, function: u8) -> Option<InterruptMapEntry> {
+        self.legacy_irq
+            .map(|(irq_line, pin)| InterruptMapEntry::new(bus, device, function, irq_line, pin))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zerocopy::FromZeros;
+
+    use super::*;
+
+    #[test]
+    fn test_prefetchable_window() {
+        let mut header = Type1Header::new_zeroed();
+        header.common.command = PciCommand::MEMORY.bits();
+        header.secondary_bus = 1;
+        header.subordinate_bus = 1;
+        header.memory_base = 0xfff0;
+        header.prefetchable_memory_base = 0x0010 | PCI_PREF_RANGE_TYPE_64;
+        header.prefetchable_memory_limit = 0x0ff0 | PCI_PREF_RANGE_TYPE_64;
+        header.prefetchable_base_upper32 = 0x10;
+        header.prefetchable_limit_upper32 = 0x10;
+
+        let windows = BridgeWindows::from(&header);
+        assert_eq!(windows.mmio, None);
+        assert_eq!(windows.prefetchable, Some(0x10_0010_0000..0x10_1000_0000));
+
+        // Closed
+        header.prefetchable_limit_upper32 = 0xf;
+        assert_eq!(BridgeWindows::from(&header).prefetchable, None);
+    }
+}
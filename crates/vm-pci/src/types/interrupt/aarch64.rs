@@ -1,3 +1,7 @@
+use vm_core::arch::aarch64::irq::GIC_SPI;
+use vm_core::arch::aarch64::irq::IRQ_TYPE_LEVEL_HIGH;
+use vm_core::arch::irq::Phandle;
+
 pub struct InterruptMapEntryAArch64 {
     pub pci_addr_high: u32,
     pub pci_addr_mid: u32,
@@ -12,6 +16,24 @@ pub struct InterruptMapEntryAArch64 {
 }
 
 impl InterruptMapEntryAArch64 {
+    /// Route the pin of the function to the level triggered spi `irq_line`
+    pub fn new(bus: u8, device: u8, function: u8, irq_line: u8, pin: u8) -> Self {
+        InterruptMapEntryAArch64 {
+            pci_addr_high: ((bus as u32) << 16)
+                | ((device as u32) << 11)
+                | ((function as u32) << 8),
+            pci_addr_mid: 0,
+            pci_addr_low: 0,
+            pin: pin.into(),
+            gic_phandle: Phandle::GIC as u32,
+            gic_addr_high: 0,
+            gic_addr_low: 0,
+            gic_irq_type: GIC_SPI,
+            gic_irq_num: irq_line.into(),
+            gic_irq_flags: IRQ_TYPE_LEVEL_HIGH,
+        }
+    }
+
     pub fn to_vec(&self) -> Vec<u32> {
         [
             self.pci_addr_high,
@@ -1 +1,7 @@
 pub struct InterruptMapEntryX86_64 {}
+
+impl InterruptMapEntryX86_64 {
+    pub fn new(_bus: u8, _device: u8, _function: u8, _irq_line: u8, _pin: u8) -> Self {
+        InterruptMapEntryX86_64 {}
+    }
+}
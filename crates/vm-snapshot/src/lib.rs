@@ -2,3 +2,4 @@
 
 pub mod helper;
 pub mod ops;
+pub mod section;
@@ -0,0 +1,14 @@
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+/// The state of a device, `version` is bumped when the layout of `data` changes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Section {
+    pub version: u32,
+    pub data: Vec<u8>,
+}
+
+/// The sections by a stable id, e.g. the name of a device or its pci address
+pub type Sections = BTreeMap<String, Section>;
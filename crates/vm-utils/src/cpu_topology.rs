@@ -0,0 +1,144 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+/// How the vcpus are laid out in sockets, cores and threads, the vcpu ids are
+/// assigned thread first, then core, then socket.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CpuTopology {
+    pub sockets: u32,
+    pub cores_per_socket: u32,
+    pub threads_per_core: u32,
+}
+
+// Aff3 and Aff2..0
+const MPIDR_AFFINITY_MASK: u64 = 0xff_00ff_ffff;
+
+/// Width of a field able to hold `count` ids
+fn field_width(count: u32) -> u32 {
+    count.next_power_of_two().trailing_zeros()
+}
+
+impl CpuTopology {
+    /// One socket with a core per vcpu
+    pub fn flat(vcpus: u32) -> Self {
+        CpuTopology {
+            sockets: 1,
+            cores_per_socket: vcpus,
+            threads_per_core: 1,
+        }
+    }
+
+    pub fn vcpus(&self) -> u32 {
+        self.sockets * self.cores_per_socket * self.threads_per_core
+    }
+
+    pub fn threads_per_socket(&self) -> u32 {
+        self.cores_per_socket * self.threads_per_core
+    }
+
+    /// (socket, core, thread) of the vcpu
+    pub fn position(&self, vcpu_id: u32) -> (u32, u32, u32) {
+        (
+            vcpu_id / self.threads_per_socket(),
+            vcpu_id / self.threads_per_core % self.cores_per_socket,
+            vcpu_id % self.threads_per_core,
+        )
+    }
+
+    /// Width of the thread field of the apic id
+    pub fn thread_id_width(&self) -> u32 {
+        field_width(self.threads_per_core)
+    }
+
+    /// Width of the core field of the apic id
+    pub fn core_id_width(&self) -> u32 {
+        field_width(self.cores_per_socket)
+    }
+
+    /// The x2apic id, each level is a field sized to its count like the hardware does
+    pub fn apic_id(&self, vcpu_id: u32) -> u32 {
+        let (socket, core, thread) = self.position(vcpu_id);
+        let thread_width = self.thread_id_width();
+        let core_width = self.core_id_width();
+
+        (socket << (core_width + thread_width)) | (core << thread_width) | thread
+    }
+
+    /// Aff0 is the thread and Aff1 the core if there are threads, otherwise
+    /// Aff0 is the core. The socket is in the next affinity level.
+    pub fn mpidr(&self, vcpu_id: u32) -> u64 {
+        // The lowest level of affinity consists of logical PEs that are implemented using
+        // a multithreading type approach
+        const MPIDR_MT: u64 = 1 << 24;
+
+        let (socket, core, thread) = self.position(vcpu_id);
+
+        if self.threads_per_core > 1 {
+            MPIDR_MT | ((socket as u64) << 16) | ((core as u64) << 8) | thread as u64
+        } else {
+            ((socket as u64) << 8) | core as u64
+        }
+    }
+
+    /// The affinity fields of the mpidr, which the device tree and the acpi tables use
+    /// to identify the vcpu
+    pub fn hwid(&self, vcpu_id: u32) -> u64 {
+        self.mpidr(vcpu_id) & MPIDR_AFFINITY_MASK
+    }
+
+    pub fn vcpu_id_of_mpidr(&self, mpidr: u64) -> Option<u32> {
+        (0..self.vcpus()).find(|vcpu_id| self.hwid(*vcpu_id) == mpidr & MPIDR_AFFINITY_MASK)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flat() {
+        let topology = CpuTopology::flat(4);
+
+        assert_eq!(topology.vcpus(), 4);
+        for vcpu_id in 0..4 {
+            assert_eq!(topology.position(vcpu_id), (0, vcpu_id, 0));
+            assert_eq!(topology.apic_id(vcpu_id), vcpu_id);
+            assert_eq!(topology.mpidr(vcpu_id), vcpu_id as u64);
+            assert_eq!(topology.vcpu_id_of_mpidr(vcpu_id as u64), Some(vcpu_id));
+        }
+    }
+
+    #[test]
+    // The literals are grouped by the socket, core and thread fields
+    #[allow(clippy::unusual_byte_groupings)]
+    fn test_apic_id() {
+        let topology = CpuTopology {
+            sockets: 2,
+            cores_per_socket: 3,
+            threads_per_core: 2,
+        };
+
+        assert_eq!(topology.vcpus(), 12);
+        assert_eq!(topology.thread_id_width(), 1);
+        assert_eq!(topology.core_id_width(), 2);
+
+        assert_eq!(topology.position(7), (1, 0, 1));
+        // The core field is 2 bits wide though there are only 3 cores
+        assert_eq!(topology.apic_id(5), 0b0_10_1);
+        assert_eq!(topology.apic_id(7), 0b1_00_1);
+    }
+
+    #[test]
+    fn test_mpidr() {
+        let topology = CpuTopology {
+            sockets: 2,
+            cores_per_socket: 2,
+            threads_per_core: 2,
+        };
+
+        assert_eq!(topology.mpidr(6), (1 << 24) | (1 << 16) | (1 << 8));
+        assert_eq!(topology.hwid(6), (1 << 16) | (1 << 8));
+        assert_eq!(topology.vcpu_id_of_mpidr(topology.hwid(6)), Some(6));
+        assert_eq!(topology.vcpu_id_of_mpidr(0xff), None);
+    }
+}
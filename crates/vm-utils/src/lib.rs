@@ -1,2 +1,3 @@
+pub mod cpu_topology;
 pub mod range_allocator;
 pub mod ring;
@@ -55,13 +55,54 @@ macro_rules! define_range_allocator {
             }
 
             pub fn alloc(&mut self, len: usize) -> Result<Range<$t>, RangeAllocatorError> {
+                self.alloc_aligned(len, 1)
+            }
+
+            /// First fit, the start of the range is a multiple of `align`
+            pub fn alloc_aligned(
+                &mut self,
+                len: usize,
+                align: usize,
+            ) -> Result<Range<$t>, RangeAllocatorError> {
+                let len = len as $t;
+                let align = align as $t;
+
+                let Some(start) = self.free.iter().find_map(|r| {
+                    let start = r.start.checked_next_multiple_of(align)?;
+
+                    (start.checked_add(len)? <= r.end).then_some(start)
+                }) else {
+                    return Err(RangeAllocatorError::Alloc);
+                };
+
+                let range = start..start + len;
+
+                self.free.remove(range.clone());
+                self.used.insert(range.clone());
+
+                Ok(range)
+            }
+
+            /// Last fit, the range ends at or below `limit` and starts at a multiple of `align`
+            pub fn alloc_aligned_below(
+                &mut self,
+                len: usize,
+                align: usize,
+                limit: $t,
+            ) -> Result<Range<$t>, RangeAllocatorError> {
                 let len = len as $t;
+                let align = align as $t;
 
-                let Some(range) = self.free.iter().find(|r| r.end - r.start >= len).cloned() else {
+                let Some(start) = self.free.iter().rev().find_map(|r| {
+                    let end = r.end.min(limit);
+                    let start = end.checked_sub(len)? / align * align;
+
+                    (start >= r.start).then_some(start)
+                }) else {
                     return Err(RangeAllocatorError::Alloc);
                 };
 
-                let range = range.start..range.start + len as $t;
+                let range = start..start + len;
 
                 self.free.remove(range.clone());
                 self.used.insert(range.clone());
@@ -178,6 +219,54 @@ mod tests {
         assert_eq!(allocator.alloc(0x50).unwrap().start, 0x50);
     }
 
+    #[test]
+    fn test_range_allocator_alloc_aligned() {
+        let mut allocator = RangeAllocator::<u64>::default();
+
+        assert!(allocator.insert(0x100, 0x10000).is_ok());
+
+        assert_eq!(
+            allocator.alloc_aligned(0x1000, 0x1000).unwrap().start,
+            0x1000
+        );
+        assert_eq!(allocator.alloc(0x100).unwrap().start, 0x100);
+        assert_eq!(
+            allocator.alloc_aligned(0x4000, 0x4000).unwrap().start,
+            0x4000
+        );
+        assert_eq!(
+            allocator.alloc_aligned(0x8000, 0x8000).unwrap().start,
+            0x8000
+        );
+        assert!(allocator.alloc_aligned(0x1000, 0x8000).is_err());
+    }
+
+    #[test]
+    fn test_range_allocator_alloc_aligned_below() {
+        let mut allocator = RangeAllocator::<u64>::default();
+
+        assert!(allocator.insert(0, 0x1000).is_ok());
+        assert!(allocator.insert(0x10000, 0x10000).is_ok());
+
+        assert_eq!(
+            allocator.alloc_aligned_below(0x100, 1, u64::MAX).unwrap(),
+            0x1ff00..0x20000
+        );
+        assert_eq!(
+            allocator
+                .alloc_aligned_below(0x100, 0x1000, u64::MAX)
+                .unwrap(),
+            0x1f000..0x1f100
+        );
+        assert_eq!(
+            allocator
+                .alloc_aligned_below(0x800, 0x100, 0x10400)
+                .unwrap(),
+            0x800..0x1000
+        );
+        assert!(allocator.alloc_aligned_below(0x1000, 1, 0x10800).is_err());
+    }
+
     #[test]
     fn test_range_allocator_alloc_oom() {
         let mut allocator = RangeAllocator::<u64>::default();
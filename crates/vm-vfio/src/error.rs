@@ -44,6 +44,12 @@ pub enum Error {
     #[error("Failed to alloc irq")]
     AllocIrq,
 
+    #[error("Vfio-user command {command} failed, errno: {errno}")]
+    VfioUserReply { command: u16, errno: u32 },
+
+    #[error("Invalid vfio-user message: {0}")]
+    VfioUserProtocol(&'static str),
+
     #[error("{0}")]
     Vfio(#[from] vfio_ioctls::VfioError),
 
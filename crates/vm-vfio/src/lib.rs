@@ -3,6 +3,10 @@
 #[cfg(target_os = "linux")]
 pub mod error;
 #[cfg(target_os = "linux")]
+pub mod ops;
+#[cfg(target_os = "linux")]
 pub mod vfio;
 #[cfg(target_os = "linux")]
 pub mod vfio_pci;
+#[cfg(target_os = "linux")]
+pub mod vfio_user;
@@ -0,0 +1,82 @@
+use memmap2::MmapMut;
+use vfio_bindings::bindings::vfio::VFIO_PCI_INTX_IRQ_INDEX;
+use vfio_bindings::bindings::vfio::VFIO_PCI_MSI_IRQ_INDEX;
+use vfio_bindings::bindings::vfio::VFIO_PCI_MSIX_IRQ_INDEX;
+use vfio_ioctls::VfioRegionInfoCap;
+use vmm_sys_util::eventfd::EventFd;
+
+use crate::error::Result;
+
+pub struct VfioRegionInfo {
+    pub(crate) flags: u32,
+    pub(crate) caps: Vec<VfioRegionInfoCap>,
+    pub(crate) size: u64,
+}
+
+#[derive(Clone, Copy)]
+pub struct VfioIrqInfo {
+    pub(crate) flags: u32,
+    pub(crate) count: u32,
+}
+
+/// A vfio device, served by the kernel or by a vfio-user server
+pub trait VfioDeviceOps: Send + Sync {
+    fn reset(&self) -> Result<()>;
+
+    fn get_region_info(&self, index: u32) -> Result<VfioRegionInfo>;
+
+    fn region_read(&self, index: u32, buf: &mut [u8], addr: u64) -> Result<()>;
+
+    fn region_write(&self, index: u32, buf: &[u8], addr: u64) -> Result<()>;
+
+    /// Map `len` bytes at `offset` of the region, the region must have `VFIO_REGION_INFO_FLAG_MMAP`
+    fn mmap_region(&self, index: u32, offset: u64, len: usize) -> Result<MmapMut>;
+
+    fn get_irq_info(&self, index: u32) -> Option<VfioIrqInfo>;
+
+    fn enable_irq(&self, index: u32, fds: Vec<&EventFd>) -> Result<()>;
+
+    fn set_irq_resample_fd(&self, index: u32, fds: Vec<&EventFd>) -> Result<()>;
+
+    fn disable_irq(&self, index: u32) -> Result<()>;
+
+    fn get_intx_irq_info(&self) -> Option<VfioIrqInfo> {
+        self.get_irq_info(VFIO_PCI_INTX_IRQ_INDEX)
+    }
+
+    fn enable_intx(&self, event_fd: &EventFd) -> Result<()> {
+        self.enable_irq(VFIO_PCI_INTX_IRQ_INDEX, vec![event_fd])
+    }
+
+    fn set_intx_resample_fd(&self, event_fd: &EventFd) -> Result<()> {
+        self.set_irq_resample_fd(VFIO_PCI_INTX_IRQ_INDEX, vec![event_fd])
+    }
+
+    fn disable_intx(&self) -> Result<()> {
+        self.disable_irq(VFIO_PCI_INTX_IRQ_INDEX)
+    }
+
+    fn get_msi_irq_info(&self) -> Option<VfioIrqInfo> {
+        self.get_irq_info(VFIO_PCI_MSI_IRQ_INDEX)
+    }
+
+    fn enable_msi(&self, fds: Vec<&EventFd>) -> Result<()> {
+        self.enable_irq(VFIO_PCI_MSI_IRQ_INDEX, fds)
+    }
+
+    fn disable_msi(&self) -> Result<()> {
+        self.disable_irq(VFIO_PCI_MSI_IRQ_INDEX)
+    }
+
+    fn get_msix_irq_info(&self) -> Option<VfioIrqInfo> {
+        self.get_irq_info(VFIO_PCI_MSIX_IRQ_INDEX)
+    }
+
+    fn enable_msix(&self, fds: Vec<&EventFd>) -> Result<()> {
+        self.enable_irq(VFIO_PCI_MSIX_IRQ_INDEX, fds)
+    }
+
+    fn disable_msix(&self) -> Result<()> {
+        self.disable_irq(VFIO_PCI_MSIX_IRQ_INDEX)
+    }
+}
@@ -1,22 +1,18 @@
+use std::os::fd::AsRawFd;
 use std::path::Path;
 
+use memmap2::MmapMut;
+use memmap2::MmapOptions;
 use vfio_bindings::bindings::vfio::VFIO_PCI_INTX_IRQ_INDEX;
-use vfio_bindings::bindings::vfio::VFIO_PCI_MSI_IRQ_INDEX;
-use vfio_bindings::bindings::vfio::VFIO_PCI_MSIX_IRQ_INDEX;
-use vfio_ioctls::VfioIrq;
-use vfio_ioctls::VfioRegionInfoCap;
 use vmm_sys_util::eventfd::EventFd;
 
 use crate::error::Error;
 use crate::error::Result;
+use crate::ops::VfioDeviceOps;
+use crate::ops::VfioIrqInfo;
+use crate::ops::VfioRegionInfo;
 use crate::vfio::container::VfioContainer;
 
-pub struct VfioRegionInfo {
-    pub(crate) flags: u32,
-    pub(crate) _caps: Vec<VfioRegionInfoCap>,
-    pub(crate) size: u64,
-}
-
 pub struct VfioDevice {
     device: vfio_ioctls::VfioDevice,
 }
@@ -30,17 +26,25 @@ impl VfioDevice {
         Ok(device)
     }
 
-    pub(crate) fn reset(&self) -> Result<()> {
-        self.device.reset();
+    pub(crate) fn num_regions(&self) -> usize {
+        self.device.num_regions() as usize
+    }
 
+    #[allow(dead_code)]
+    pub(crate) fn unmask_intx(&self) -> Result<()> {
+        self.device.unmask_irq(VFIO_PCI_INTX_IRQ_INDEX)?;
         Ok(())
     }
+}
 
-    pub(crate) fn num_regions(&self) -> usize {
-        self.device.num_regions() as usize
+impl VfioDeviceOps for VfioDevice {
+    fn reset(&self) -> Result<()> {
+        self.device.reset();
+
+        Ok(())
     }
 
-    pub(crate) fn get_region_info(&self, index: u32) -> Result<VfioRegionInfo> {
+    fn get_region_info(&self, index: u32) -> Result<VfioRegionInfo> {
         if index as usize >= self.num_regions() {
             return Err(Error::RegionNotExists(index as usize));
         }
@@ -49,84 +53,56 @@ impl VfioDevice {
         let caps = self.device.get_region_caps(index);
         let size = self.device.get_region_size(index);
 
-        Ok(VfioRegionInfo {
-            flags,
-            _caps: caps,
-            size,
-        })
+        Ok(VfioRegionInfo { flags, caps, size })
     }
 
-    pub(crate) fn region_read(&self, index: u32, buf: &mut [u8], addr: u64) -> Result<()> {
+    fn region_read(&self, index: u32, buf: &mut [u8], addr: u64) -> Result<()> {
         self.device.region_read(index, buf, addr);
 
         Ok(())
     }
 
-    pub(crate) fn region_write(&self, index: u32, buf: &[u8], addr: u64) -> Result<()> {
+    fn region_write(&self, index: u32, buf: &[u8], addr: u64) -> Result<()> {
         self.device.region_write(index, buf, addr);
 
         Ok(())
     }
 
-    pub(crate) fn get_intx_irq_info(&self) -> Option<&VfioIrq> {
-        self.device.get_irq_info(VFIO_PCI_INTX_IRQ_INDEX)
-    }
-
-    pub(crate) fn enable_intx(&self, event_fd: &EventFd) -> Result<()> {
-        self.device
-            .enable_irq(VFIO_PCI_INTX_IRQ_INDEX, vec![event_fd])?;
-
-        Ok(())
-    }
-
-    pub(crate) fn set_intx_resample_fd(&self, event_fd: &EventFd) -> Result<()> {
-        self.device
-            .set_irq_resample_fd(VFIO_PCI_INTX_IRQ_INDEX, vec![event_fd])?;
-
-        Ok(())
-    }
-
-    pub(crate) fn disable_intx(&self) -> Result<()> {
-        self.device.disable_irq(VFIO_PCI_INTX_IRQ_INDEX)?;
+    fn mmap_region(&self, index: u32, offset: u64, len: usize) -> Result<MmapMut> {
+        let region_offset = self.device.get_region_offset(index);
 
-        Ok(())
-    }
+        let mmap = unsafe {
+            MmapOptions::new()
+                .offset(region_offset + offset)
+                .len(len)
+                .map_mut(self.device.as_raw_fd())?
+        };
 
-    #[allow(dead_code)]
-    pub(crate) fn unmask_intx(&self) -> Result<()> {
-        self.device.unmask_irq(VFIO_PCI_INTX_IRQ_INDEX)?;
-        Ok(())
+        Ok(mmap)
     }
 
-    pub(crate) fn enable_msi(&self, fds: Vec<&EventFd>) -> Result<()> {
-        self.device.enable_msi(fds)?;
-
-        Ok(())
+    fn get_irq_info(&self, index: u32) -> Option<VfioIrqInfo> {
+        self.device.get_irq_info(index).map(|irq| VfioIrqInfo {
+            flags: irq.flags,
+            count: irq.count,
+        })
     }
 
-    pub(crate) fn disable_msi(&self) -> Result<()> {
-        self.device.disable_msi()?;
+    fn enable_irq(&self, index: u32, fds: Vec<&EventFd>) -> Result<()> {
+        self.device.enable_irq(index, fds)?;
 
         Ok(())
     }
 
-    pub(crate) fn get_msix_irq_info(&self) -> Option<&VfioIrq> {
-        self.device.get_irq_info(VFIO_PCI_MSIX_IRQ_INDEX)
-    }
-
-    pub(crate) fn enable_msix(&self, fds: Vec<&EventFd>) -> Result<()> {
-        self.device.enable_msix(fds)?;
+    fn set_irq_resample_fd(&self, index: u32, fds: Vec<&EventFd>) -> Result<()> {
+        self.device.set_irq_resample_fd(index, fds)?;
 
         Ok(())
     }
 
-    pub(crate) fn disable_msix(&self) -> Result<()> {
-        self.device.disable_msix()?;
+    fn disable_irq(&self, index: u32) -> Result<()> {
+        self.device.disable_irq(index)?;
 
         Ok(())
     }
-
-    pub(crate) fn get_msi_irq_info(&self) -> Option<&VfioIrq> {
-        self.device.get_irq_info(VFIO_PCI_MSI_IRQ_INDEX)
-    }
 }
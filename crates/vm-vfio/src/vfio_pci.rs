@@ -1,4 +1,5 @@
 mod function;
 mod interrupt;
+mod mmap;
 
 pub mod device;
@@ -27,15 +27,16 @@ use vm_pci::device::capability::msix::PCI_MSIX_TABLE_BIR;
 use vm_pci::device::capability::msix::PCI_MSIX_TABLE_OFFSET;
 use vm_pci::device::capability::msix::PciMsixCap;
 use vm_pci::device::interrupt::legacy::InterruptPin;
+use vm_pci::types::bar::PCI_BASE_ADDRESS_MEM_PREFETCH;
 use vm_pci::types::bar::PCI_BASE_ADDRESS_MEM_TYPE_32;
 use vm_pci::types::bar::PCI_BASE_ADDRESS_MEM_TYPE_64;
 use vm_pci::types::bar::PCI_BASE_ADDRESS_MEM_TYPE_MASK;
 use vm_pci::types::bar::PCI_BASE_ADDRESS_SPACE;
 use vm_pci::types::bar::PciBarInfo;
+use vm_pci::types::bar::alloc_mmio_bar;
 #[cfg(target_arch = "x86_64")]
 use vm_pci::types::bar::pci_io_bar;
-use vm_pci::types::bar::pci_mmio_32_bar;
-use vm_pci::types::bar::pci_mmio_64_bar;
+use vm_pci::types::bar::pci_mmio_bar;
 use vm_pci::types::configuration_space::ConfigurationSpace;
 use vm_pci::types::configuration_space::PciConfigurationSpace;
 use vm_pci::types::configuration_space::capability::StandardCapability;
@@ -53,7 +54,7 @@ use zerocopy::IntoBytes;
 
 use crate::error::Error;
 use crate::error::Result;
-use crate::vfio::device::VfioDevice;
+use crate::ops::VfioDeviceOps;
 use crate::vfio_pci::function::VfioPciFunction;
 use crate::vfio_pci::interrupt::VfioInterruptInfo;
 use crate::vfio_pci::interrupt::VfioInterruptManager;
@@ -63,6 +64,7 @@ use crate::vfio_pci::interrupt::msi::VfioMsi;
 use crate::vfio_pci::interrupt::msi::VfioMsiInfo;
 use crate::vfio_pci::interrupt::msix::VfioMsix;
 use crate::vfio_pci::interrupt::msix::VfioMsixInfo;
+use crate::vfio_pci::mmap::VfioBarMmap;
 
 const DEBUG_ENABLE_MSIX: bool = true;
 const DEBUG_ENABLE_MSI: bool = true;
@@ -70,7 +72,7 @@ const DEBUG_ENABLE_INTX: bool = true;
 
 fn setup_interrupt_capability(
     vm: &dyn HypervisorVm,
-    vfio_device: Arc<VfioDevice>,
+    vfio_device: Arc<dyn VfioDeviceOps>,
     interrupt_manager: &InterruptManager,
     raw: &PciConfigurationSpace,
     cfg: &mut ConfigurationSpace,
@@ -292,10 +294,10 @@ impl VfioPciDevice {
         vm: Arc<dyn HypervisorVm>,
         #[cfg(target_arch = "x86_64")] pci_io_window_allocator: &mut RangeAllocator<u16>,
         pci_mmio_window_allocator: &mut RangeAllocator<u64>,
+        pci_mmio64_window_allocator: &mut RangeAllocator<u64>,
         irq_manager: Arc<InterruptManager>,
-        vfio_device: VfioDevice,
+        vfio_device: Arc<dyn VfioDeviceOps>,
     ) -> Result<Self> {
-        let vfio_device = Arc::new(vfio_device);
         vfio_device.reset()?;
 
         // Get raw header from device
@@ -358,6 +360,7 @@ impl VfioPciDevice {
         )?;
 
         let mut bar_info = [const { None }; 6];
+        let mut mmaps = [const { None }; 6];
         {
             let raw_header = raw_configuration_space.as_header::<Type0Header>();
             let header = configuration_space.as_header_mut::<Type0Header>();
@@ -385,18 +388,47 @@ impl VfioPciDevice {
                         return Err(Error::InvalidMmioBarType(index, bar_mem_type));
                     };
 
-                    let range = pci_mmio_window_allocator.alloc(len).unwrap();
+                    let prefetchable = bar & PCI_BASE_ADDRESS_MEM_PREFETCH != 0;
+
+                    let addr = alloc_mmio_bar(
+                        pci_mmio_window_allocator,
+                        pci_mmio64_window_allocator,
+                        is_64bit,
+                        prefetchable,
+                        len,
+                    )?;
+                    let (low, high) = pci_mmio_bar(addr, is_64bit, prefetchable);
+                    header.bar[index] = low;
                     if is_64bit {
-                        let addr = range.start;
-                        let low = (addr & 0xFFFF_FFFF) as u32;
-                        let high = (addr >> 32) as u32;
-                        header.bar[index] = pci_mmio_64_bar(low);
                         header.bar[index + 1] = high;
-                    } else {
-                        header.bar[index] = pci_mmio_32_bar(range.start.try_into().unwrap())
-                    };
+                    }
 
-                    PciBarInfo::Mmio { is_64bit, len }
+                    // The msi-x table and pba are emulated
+                    let trapped = interrupt_info
+                        .msix
+                        .iter()
+                        .flat_map(|msix| {
+                            [
+                                (msix.table_bar, msix.table_offset, msix.table_len),
+                                (msix.pba_bar, msix.pba_offset, msix.pba_len),
+                            ]
+                        })
+                        .filter(|(bar, _, _)| *bar as usize == index)
+                        .map(|(_, offset, len)| offset as u64..offset as u64 + len as u64)
+                        .collect::<Vec<_>>();
+                    mmaps[index] = VfioBarMmap::new(
+                        vm.clone(),
+                        vfio_device.as_ref(),
+                        VFIO_PCI_BAR0_REGION_INDEX + index as u32,
+                        &region,
+                        &trapped,
+                    )?;
+
+                    PciBarInfo::Mmio {
+                        is_64bit,
+                        prefetchable,
+                        len,
+                    }
                 } else {
                     #[cfg(target_arch = "x86_64")]
                     {
@@ -422,6 +454,7 @@ impl VfioPciDevice {
             raw_configuration_space,
             configuration_space,
             bar_info,
+            mmaps,
             vfio_device,
             interrupt_info,
             interrupt_manager,
@@ -18,6 +18,8 @@ use vm_pci::device::capability::msix::PciMsixCap;
 use vm_pci::device::capability::msix::PciMsixCapOffset;
 use vm_pci::types::bar::PciBarInfo;
 use vm_pci::types::bar::address_of_bar;
+use vm_pci::types::bar::pci_bar_upper_write;
+use vm_pci::types::bar::pci_bar_write;
 use vm_pci::types::configuration_space::ConfigurationSpace;
 use vm_pci::types::configuration_space::PciConfigurationSpace;
 use vm_pci::types::configuration_space::command::PciCommand;
@@ -32,12 +34,13 @@ use vm_pci::types::interrupt::InterruptMapEntry;
 use zerocopy::FromBytes;
 use zerocopy::IntoBytes;
 
-use crate::vfio::device::VfioDevice;
+use crate::ops::VfioDeviceOps;
 use crate::vfio_pci::interrupt::VfioInterruptInfo;
 use crate::vfio_pci::interrupt::VfioInterruptManager;
 use crate::vfio_pci::interrupt::msi::VfioMsi;
 use crate::vfio_pci::interrupt::msi::VfioMsiInfo;
 use crate::vfio_pci::interrupt::msix::VfioMsixInfo;
+use crate::vfio_pci::mmap::VfioBarMmap;
 
 pub struct VfioPciFunction {
     vm: Arc<dyn HypervisorVm>,
@@ -45,7 +48,9 @@ pub struct VfioPciFunction {
     raw_configuration_space: PciConfigurationSpace,
     configuration_space: Mutex<ConfigurationSpace>,
     bars: [Option<PciBarInfo>; 6],
-    device: Arc<VfioDevice>,
+    /// The mmap-able parts of each bar, they are mapped while the memory decoding is on
+    mmaps: Mutex<[Option<VfioBarMmap>; 6]>,
+    device: Arc<dyn VfioDeviceOps>,
     interrupt_manager: Arc<Mutex<VfioInterruptManager>>,
     interrupt_info: VfioInterruptInfo,
 }
@@ -58,7 +63,8 @@ impl VfioPciFunction {
         raw_configuration_space: PciConfigurationSpace,
         configuration_space: ConfigurationSpace,
         bars: [Option<PciBarInfo>; 6],
-        device: Arc<VfioDevice>,
+        mmaps: [Option<VfioBarMmap>; 6],
+        device: Arc<dyn VfioDeviceOps>,
         interrupt_info: VfioInterruptInfo,
         interrupt_manager: VfioInterruptManager,
     ) -> Self {
@@ -68,6 +74,7 @@ impl VfioPciFunction {
             raw_configuration_space,
             configuration_space: configuration_space.into(),
             bars,
+            mmaps: Mutex::new(mmaps),
             device,
             interrupt_info,
             interrupt_manager: Arc::new(Mutex::new(interrupt_manager)),
@@ -450,36 +457,66 @@ impl VfioPciFunction {
         }
     }
 
-    fn write_bar(&self, bar_index: usize, buf: &[u8]) {
+    fn mmio_bar_address(&self, header: &Type0Header, bar_index: usize) -> u64 {
+        let address = address_of_bar(header.bar[bar_index]) as u64;
+
+        match &self.bars[bar_index] {
+            Some(PciBarInfo::Mmio { is_64bit: true, .. }) => {
+                (header.bar[bar_index + 1] as u64) << 32 | address
+            }
+            _ => address,
+        }
+    }
+
+    fn write_bar(&self, bar_index: usize, buf: &[u8]) -> Option<EcamUpdateCallback> {
         let mut configuration_space = self.configuration_space.lock().unwrap();
         let header = configuration_space.as_header_mut::<Type0Header>();
 
         let data = u32::from_le_bytes(buf.try_into().unwrap());
 
-        if data == u32::MAX {
-            let size = if let Some(bar_info) = &self.bars[bar_index] {
-                let len: usize = match bar_info {
-                    #[cfg(target_arch = "x86_64")]
-                    PciBarInfo::Pio { len } => *len,
-                    PciBarInfo::Mmio { len, .. } => *len,
-                };
-                len.try_into().unwrap()
-            } else if bar_index > 0
-                && let Some(bar_info) = &self.bars[bar_index - 1]
-                && let PciBarInfo::Mmio {
-                    is_64bit: true,
-                    len,
-                } = bar_info
-            {
-                (*len >> 32) as u32
-            } else {
-                0
-            };
-
-            header.bar[bar_index] = !(size.wrapping_sub(1));
+        // The high dword of a 64-bit bar belongs to the previous bar
+        let owner = if let Some(bar_info) = &self.bars[bar_index] {
+            header.bar[bar_index] = pci_bar_write(bar_info, header.bar[bar_index], data);
+            Some(bar_index)
+        } else if bar_index > 0
+            && let Some(PciBarInfo::Mmio {
+                is_64bit: true,
+                len,
+                ..
+            }) = &self.bars[bar_index - 1]
+        {
+            header.bar[bar_index] = pci_bar_upper_write(*len, data);
+            Some(bar_index - 1)
         } else {
-            header.bar[bar_index] = data;
+            header.bar[bar_index] = 0;
+            None
+        };
+
+        // The guest moves the bar while the memory decoding is on. A 64-bit bar is only moved
+        // once its high dword is written, the low dword alone is a half-updated address
+        let owner = owner?;
+        let Some(PciBarInfo::Mmio { is_64bit, len, .. }) = &self.bars[owner] else {
+            return None;
+        };
+        if !PciCommand::from_bits_retain(header.common.command).contains(PciCommand::MEMORY)
+            || (*is_64bit && owner == bar_index)
+        {
+            return None;
         }
+
+        let address = self.mmio_bar_address(header, owner);
+        if let Some(mmap) = &mut self.mmaps.lock().unwrap()[owner] {
+            mmap.map(address);
+        }
+
+        // The router still covers the trapped msix table and pba at the old address
+        Some(EcamUpdateCallback(vec![
+            EcamUpdateCallbackOps::RemoveMmioRouter { bar: owner as u8 },
+            EcamUpdateCallbackOps::AddMmioRouter {
+                bar: owner as u8,
+                pci_address_range: address..address + *len as u64,
+            },
+        ]))
     }
 
     fn write_command(&self, command: u16) -> Option<EcamUpdateCallback> {
@@ -493,18 +530,17 @@ impl VfioPciFunction {
 
         let old_command = PciCommand::from_bits_retain(old_command);
         let command = PciCommand::from_bits_retain(command);
+        let mut mmaps = self.mmaps.lock().unwrap();
 
         for (i, bar) in self.bars.iter().enumerate() {
             let Some(bar_info) = bar else {
                 continue;
             };
 
-            let bar = header.bar[i];
-
             match bar_info {
                 #[cfg(target_arch = "x86_64")]
                 PciBarInfo::Pio { len } => {
-                    let address = address_of_bar(bar);
+                    let address = address_of_bar(header.bar[i]);
 
                     if command.contains(PciCommand::IO) && !old_command.contains(PciCommand::IO) {
                         callback_ops.push(EcamUpdateCallbackOps::AddPioRouter {
@@ -517,15 +553,16 @@ impl VfioPciFunction {
                         callback_ops.push(EcamUpdateCallbackOps::RemovePioRouter { bar: i as u8 });
                     }
                 }
-                PciBarInfo::Mmio { is_64bit, len } => {
-                    let address = if *is_64bit {
-                        (header.bar[i + 1] as u64) << 32 | address_of_bar(bar) as u64
-                    } else {
-                        address_of_bar(bar) as u64
-                    };
+                PciBarInfo::Mmio { len, .. } => {
+                    let address = self.mmio_bar_address(header, i);
+                    let mmap = &mut mmaps[i];
+
                     if command.contains(PciCommand::MEMORY)
                         && !old_command.contains(PciCommand::MEMORY)
                     {
+                        if let Some(mmap) = mmap {
+                            mmap.map(address);
+                        }
                         callback_ops.push(EcamUpdateCallbackOps::AddMmioRouter {
                             bar: i as u8,
                             pci_address_range: address..address + *len as u64,
@@ -533,6 +570,9 @@ impl VfioPciFunction {
                     } else if !command.contains(PciCommand::MEMORY)
                         && old_command.contains(PciCommand::MEMORY)
                     {
+                        if let Some(mmap) = mmap {
+                            mmap.unmap();
+                        }
                         callback_ops.push(EcamUpdateCallbackOps::RemoveMmioRouter { bar: i as u8 });
                     }
                 }
@@ -652,12 +692,12 @@ impl PciFunction for VfioPciFunction {
                         .region_write(VFIO_PCI_CONFIG_REGION_INDEX, buf, offset as u64)
                         .unwrap();
                 }
-                Type0HeaderOffset::Bar0 => self.write_bar(0, buf),
-                Type0HeaderOffset::Bar1 => self.write_bar(1, buf),
-                Type0HeaderOffset::Bar2 => self.write_bar(2, buf),
-                Type0HeaderOffset::Bar3 => self.write_bar(3, buf),
-                Type0HeaderOffset::Bar4 => self.write_bar(4, buf),
-                Type0HeaderOffset::Bar5 => self.write_bar(5, buf),
+                Type0HeaderOffset::Bar0 => return self.write_bar(0, buf),
+                Type0HeaderOffset::Bar1 => return self.write_bar(1, buf),
+                Type0HeaderOffset::Bar2 => return self.write_bar(2, buf),
+                Type0HeaderOffset::Bar3 => return self.write_bar(3, buf),
+                Type0HeaderOffset::Bar4 => return self.write_bar(4, buf),
+                Type0HeaderOffset::Bar5 => return self.write_bar(5, buf),
             }
 
             None
@@ -0,0 +1,168 @@
+use std::ops::Range;
+use std::sync::Arc;
+
+use memmap2::MmapMut;
+use tracing::warn;
+use vfio_bindings::bindings::vfio::VFIO_REGION_INFO_FLAG_MMAP;
+use vfio_ioctls::VfioRegionInfoCap;
+use vm_core::virtualization::vm::HypervisorVm;
+use vm_core::virtualization::vm::SetUserMemoryRegionFlags;
+
+use crate::error::Result;
+use crate::ops::VfioDeviceOps;
+use crate::ops::VfioRegionInfo;
+
+const PAGE_SIZE: u64 = 0x1000;
+
+struct VfioMmapArea {
+    /// Offset in the bar
+    offset: u64,
+    mmap: MmapMut,
+}
+
+/// The parts of a bar mapped into the guest, accesses to them do not exit to the vmm
+pub(crate) struct VfioBarMmap {
+    vm: Arc<dyn HypervisorVm>,
+    areas: Vec<VfioMmapArea>,
+    /// Guest address of the bar while it is mapped
+    gpa: Option<u64>,
+}
+
+/// Split `areas` so that none of them overlaps the pages of `trapped`
+fn exclude(areas: Vec<Range<u64>>, trapped: &Range<u64>) -> Vec<Range<u64>> {
+    let trapped = trapped.start / PAGE_SIZE * PAGE_SIZE..trapped.end.next_multiple_of(PAGE_SIZE);
+
+    areas
+        .into_iter()
+        .flat_map(|area| {
+            [
+                area.start..area.end.min(trapped.start),
+                area.start.max(trapped.end)..area.end,
+            ]
+        })
+        .filter(|area| area.start < area.end)
+        .collect()
+}
+
+impl VfioBarMmap {
+    /// Returns `None` if no page of the bar can be mapped. `trapped` are the ranges of the bar
+    /// that still go through the vmm, e.g. the msi-x table.
+    pub(crate) fn new(
+        vm: Arc<dyn HypervisorVm>,
+        device: &dyn VfioDeviceOps,
+        index: u32,
+        region: &VfioRegionInfo,
+        trapped: &[Range<u64>],
+    ) -> Result<Option<Self>> {
+        if region.flags & VFIO_REGION_INFO_FLAG_MMAP == 0 {
+            return Ok(None);
+        }
+
+        let mut areas = region
+            .caps
+            .iter()
+            .find_map(|cap| match cap {
+                VfioRegionInfoCap::SparseMmap(sparse) => Some(
+                    sparse
+                        .areas
+                        .iter()
+                        .map(|area| area.offset..area.offset + area.size)
+                        .collect(),
+                ),
+                _ => None,
+            })
+            .unwrap_or_else(|| {
+                let whole = 0..region.size;
+                vec![whole]
+            });
+        for trapped in trapped {
+            areas = exclude(areas, trapped);
+        }
+
+        let areas = areas
+            .into_iter()
+            .map(|area| area.start.next_multiple_of(PAGE_SIZE)..area.end / PAGE_SIZE * PAGE_SIZE)
+            .filter(|area| area.start < area.end)
+            .map(|area| {
+                Ok(VfioMmapArea {
+                    offset: area.start,
+                    mmap: device.mmap_region(
+                        index,
+                        area.start,
+                        (area.end - area.start) as usize,
+                    )?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if areas.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(VfioBarMmap {
+            vm,
+            areas,
+            gpa: None,
+        }))
+    }
+
+    /// Map the bar at `gpa`, it is moved if it is mapped elsewhere
+    pub(crate) fn map(&mut self, gpa: u64) {
+        if self.gpa == Some(gpa) {
+            return;
+        }
+        self.unmap();
+
+        for area in &mut self.areas {
+            if let Err(err) = self.vm.set_user_memory_region(
+                area.mmap.as_mut_ptr() as u64,
+                gpa + area.offset,
+                area.mmap.len(),
+                SetUserMemoryRegionFlags::ReadWriteExec,
+            ) {
+                warn!(?err, gpa = gpa + area.offset, "failed to map vfio bar");
+            }
+        }
+
+        self.gpa = Some(gpa);
+    }
+
+    pub(crate) fn unmap(&mut self) {
+        let Some(gpa) = self.gpa.take() else {
+            return;
+        };
+
+        for area in &self.areas {
+            if let Err(err) = self
+                .vm
+                .remove_user_memory_region(gpa + area.offset, area.mmap.len())
+            {
+                warn!(?err, gpa = gpa + area.offset, "failed to unmap vfio bar");
+            }
+        }
+    }
+}
+
+impl Drop for VfioBarMmap {
+    fn drop(&mut self) {
+        self.unmap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exclude() {
+        let bar = 0..0x4000;
+        let areas = exclude(vec![bar], &(0x1800..0x1900));
+        assert_eq!(areas, vec![0..0x1000, 0x2000..0x4000]);
+
+        let areas = exclude(areas, &(0x3000..0x5000));
+        assert_eq!(areas, vec![0..0x1000, 0x2000..0x3000]);
+
+        let areas = exclude(areas, &(0x8000..0x9000));
+        assert_eq!(areas, vec![0..0x1000, 0x2000..0x3000]);
+    }
+}
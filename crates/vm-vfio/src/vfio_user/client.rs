@@ -0,0 +1,596 @@
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::os::fd::AsRawFd;
+use std::os::fd::RawFd;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicU16;
+use std::sync::atomic::Ordering;
+
+use memmap2::MmapMut;
+use memmap2::MmapOptions;
+use vfio_bindings::bindings::vfio::VFIO_IRQ_SET_ACTION_TRIGGER;
+use vfio_bindings::bindings::vfio::VFIO_IRQ_SET_ACTION_UNMASK;
+use vfio_bindings::bindings::vfio::VFIO_IRQ_SET_DATA_EVENTFD;
+use vfio_bindings::bindings::vfio::VFIO_IRQ_SET_DATA_NONE;
+use vfio_bindings::bindings::vfio::VFIO_REGION_INFO_FLAG_MMAP;
+use vmm_sys_util::eventfd::EventFd;
+use vmm_sys_util::sock_ctrl_msg::ScmSocket;
+use zerocopy::FromBytes;
+use zerocopy::IntoBytes;
+
+use crate::error::Error;
+use crate::error::Result;
+use crate::ops::VfioDeviceOps;
+use crate::ops::VfioIrqInfo;
+use crate::ops::VfioRegionInfo;
+use crate::vfio_user::protocol::*;
+
+struct VfioUserRegion {
+    flags: u32,
+    size: u64,
+    /// The file to mmap the region from, at `offset`
+    file: Option<File>,
+    offset: u64,
+}
+
+/// A pci device emulated by a vfio-user server in another process
+pub struct VfioUserClient {
+    stream: Mutex<UnixStream>,
+    msg_id: AtomicU16,
+    regions: Vec<VfioUserRegion>,
+    irqs: Vec<VfioIrqInfo>,
+}
+
+fn errno_to_io(err: vmm_sys_util::errno::Error) -> io::Error {
+    io::Error::from_raw_os_error(err.errno())
+}
+
+fn parse<T: FromBytes>(buf: &[u8]) -> Result<T> {
+    T::read_from_prefix(buf)
+        .map(|(t, _)| t)
+        .map_err(|_| Error::VfioUserProtocol("reply is too short"))
+}
+
+impl VfioUserClient {
+    pub fn connect(path: &Path) -> Result<Self> {
+        Self::new(UnixStream::connect(path)?)
+    }
+
+    pub fn new(stream: UnixStream) -> Result<Self> {
+        let mut client = VfioUserClient {
+            stream: Mutex::new(stream),
+            msg_id: AtomicU16::new(0),
+            regions: vec![],
+            irqs: vec![],
+        };
+
+        client.negotiate_version()?;
+
+        let info = client.get_device_info()?;
+        client.regions = (0..info.num_regions)
+            .map(|index| client.fetch_region_info(index))
+            .collect::<Result<_>>()?;
+        client.irqs = (0..info.num_irqs)
+            .map(|index| client.fetch_irq_info(index))
+            .collect::<Result<_>>()?;
+
+        Ok(client)
+    }
+
+    /// Send a command and wait for its reply, returns the payload of the reply and the fd
+    /// passed along with it
+    fn request(
+        &self,
+        command: u16,
+        payload: &[u8],
+        fds: &[RawFd],
+    ) -> Result<(Vec<u8>, Option<File>)> {
+        let msg_id = self.msg_id.fetch_add(1, Ordering::Relaxed);
+        let header = Header {
+            msg_id,
+            command,
+            msg_size: (size_of::<Header>() + payload.len()) as u32,
+            flags: VFIO_USER_F_TYPE_COMMAND,
+            error: 0,
+        };
+
+        let mut stream = self.stream.lock().unwrap();
+
+        let len = stream
+            .send_with_fds(&[header.as_bytes()], fds)
+            .map_err(errno_to_io)?;
+        if len != size_of::<Header>() {
+            return Err(Error::VfioUserProtocol("short write of the header"));
+        }
+        stream.write_all(payload)?;
+
+        let mut reply = Header::default();
+        let (len, file) = stream
+            .recv_with_fd(reply.as_mut_bytes())
+            .map_err(errno_to_io)?;
+        if len != size_of::<Header>() {
+            return Err(Error::VfioUserProtocol("short read of the header"));
+        }
+
+        let len = (reply.msg_size as usize)
+            .checked_sub(size_of::<Header>())
+            .ok_or(Error::VfioUserProtocol("invalid message size"))?;
+        let mut buf = vec![0; len];
+        stream.read_exact(&mut buf)?;
+
+        if reply.msg_id != msg_id
+            || reply.command != command
+            || reply.flags & VFIO_USER_F_TYPE_MASK != VFIO_USER_F_TYPE_REPLY
+        {
+            return Err(Error::VfioUserProtocol("unexpected reply"));
+        }
+
+        if reply.flags & VFIO_USER_F_ERROR != 0 {
+            return Err(Error::VfioUserReply {
+                command,
+                errno: reply.error,
+            });
+        }
+
+        Ok((buf, file))
+    }
+
+    fn negotiate_version(&self) -> Result<()> {
+        let mut payload = Version {
+            major: VFIO_USER_MAJOR,
+            minor: VFIO_USER_MINOR,
+        }
+        .as_bytes()
+        .to_vec();
+        payload.extend_from_slice(VFIO_USER_CAPABILITIES.as_bytes());
+        payload.push(0);
+
+        let (buf, _) = self.request(VFIO_USER_VERSION, &payload, &[])?;
+        let version = parse::<Version>(&buf)?;
+        if version.major != VFIO_USER_MAJOR {
+            return Err(Error::VfioUserProtocol("unsupported major version"));
+        }
+
+        Ok(())
+    }
+
+    fn get_device_info(&self) -> Result<DeviceInfo> {
+        let request = DeviceInfo {
+            argsz: size_of::<DeviceInfo>() as u32,
+            ..Default::default()
+        };
+
+        let (buf, _) = self.request(VFIO_USER_DEVICE_GET_INFO, request.as_bytes(), &[])?;
+
+        parse(&buf)
+    }
+
+    fn fetch_region_info(&self, index: u32) -> Result<VfioUserRegion> {
+        let request = RegionInfo {
+            argsz: size_of::<RegionInfo>() as u32,
+            index,
+            ..Default::default()
+        };
+
+        let (buf, file) =
+            self.request(VFIO_USER_DEVICE_GET_REGION_INFO, request.as_bytes(), &[])?;
+        let info = parse::<RegionInfo>(&buf)?;
+
+        // Sparse mmap capabilities are not requested, a region is either mmapped as a whole
+        // or only accessed by messages
+        let mut flags = info.flags;
+        if file.is_none() {
+            flags &= !VFIO_REGION_INFO_FLAG_MMAP;
+        }
+
+        Ok(VfioUserRegion {
+            flags,
+            size: info.size,
+            file,
+            offset: info.offset,
+        })
+    }
+
+    fn fetch_irq_info(&self, index: u32) -> Result<VfioIrqInfo> {
+        let request = IrqInfo {
+            argsz: size_of::<IrqInfo>() as u32,
+            index,
+            ..Default::default()
+        };
+
+        let (buf, _) = self.request(VFIO_USER_DEVICE_GET_IRQ_INFO, request.as_bytes(), &[])?;
+        let info = parse::<IrqInfo>(&buf)?;
+
+        Ok(VfioIrqInfo {
+            flags: info.flags,
+            count: info.count,
+        })
+    }
+
+    fn set_irqs(&self, index: u32, flags: u32, fds: &[RawFd]) -> Result<()> {
+        let request = IrqSet {
+            argsz: size_of::<IrqSet>() as u32,
+            flags,
+            index,
+            start: 0,
+            count: fds.len() as u32,
+        };
+
+        self.request(VFIO_USER_DEVICE_SET_IRQS, request.as_bytes(), fds)?;
+
+        Ok(())
+    }
+
+    /// Share `size` bytes of `fd` at `offset` with the server, the device accesses them at `iova`
+    pub fn dma_map(&self, iova: u64, size: u64, fd: RawFd, offset: u64) -> Result<()> {
+        let request = DmaMap {
+            argsz: size_of::<DmaMap>() as u32,
+            flags: VFIO_USER_F_DMA_REGION_READ | VFIO_USER_F_DMA_REGION_WRITE,
+            offset,
+            address: iova,
+            size,
+        };
+
+        self.request(VFIO_USER_DMA_MAP, request.as_bytes(), &[fd])?;
+
+        Ok(())
+    }
+
+    pub fn dma_unmap(&self, iova: u64, size: u64) -> Result<()> {
+        let request = DmaUnmap {
+            argsz: size_of::<DmaUnmap>() as u32,
+            flags: 0,
+            address: iova,
+            size,
+        };
+
+        self.request(VFIO_USER_DMA_UNMAP, request.as_bytes(), &[])?;
+
+        Ok(())
+    }
+
+    fn region(&self, index: u32) -> Result<&VfioUserRegion> {
+        self.regions
+            .get(index as usize)
+            .ok_or(Error::RegionNotExists(index as usize))
+    }
+}
+
+impl VfioDeviceOps for VfioUserClient {
+    fn reset(&self) -> Result<()> {
+        self.request(VFIO_USER_DEVICE_RESET, &[], &[])?;
+
+        Ok(())
+    }
+
+    fn get_region_info(&self, index: u32) -> Result<VfioRegionInfo> {
+        let region = self.region(index)?;
+
+        Ok(VfioRegionInfo {
+            flags: region.flags,
+            caps: vec![],
+            size: region.size,
+        })
+    }
+
+    fn region_read(&self, index: u32, buf: &mut [u8], addr: u64) -> Result<()> {
+        let mut offset = addr;
+        for chunk in buf.chunks_mut(VFIO_USER_MAX_DATA_XFER_SIZE) {
+            let request = RegionAccess {
+                offset,
+                region: index,
+                count: chunk.len() as u32,
+            };
+
+            let (reply, _) = self.request(VFIO_USER_REGION_READ, request.as_bytes(), &[])?;
+            let data = reply
+                .get(size_of::<RegionAccess>()..size_of::<RegionAccess>() + chunk.len())
+                .ok_or(Error::VfioUserProtocol("short region read"))?;
+            chunk.copy_from_slice(data);
+
+            offset += chunk.len() as u64;
+        }
+
+        Ok(())
+    }
+
+    fn region_write(&self, index: u32, buf: &[u8], addr: u64) -> Result<()> {
+        let mut offset = addr;
+        for chunk in buf.chunks(VFIO_USER_MAX_DATA_XFER_SIZE) {
+            let mut payload = RegionAccess {
+                offset,
+                region: index,
+                count: chunk.len() as u32,
+            }
+            .as_bytes()
+            .to_vec();
+            payload.extend_from_slice(chunk);
+
+            self.request(VFIO_USER_REGION_WRITE, &payload, &[])?;
+
+            offset += chunk.len() as u64;
+        }
+
+        Ok(())
+    }
+
+    fn mmap_region(&self, index: u32, offset: u64, len: usize) -> Result<MmapMut> {
+        let region = self.region(index)?;
+        let file = region
+            .file
+            .as_ref()
+            .ok_or(Error::VfioUserProtocol("region can not be mmapped"))?;
+
+        let mmap = unsafe {
+            MmapOptions::new()
+                .offset(region.offset + offset)
+                .len(len)
+                .map_mut(file)?
+        };
+
+        Ok(mmap)
+    }
+
+    fn get_irq_info(&self, index: u32) -> Option<VfioIrqInfo> {
+        self.irqs.get(index as usize).copied()
+    }
+
+    fn enable_irq(&self, index: u32, fds: Vec<&EventFd>) -> Result<()> {
+        let fds: Vec<_> = fds.iter().map(|fd| fd.as_raw_fd()).collect();
+
+        self.set_irqs(
+            index,
+            VFIO_IRQ_SET_DATA_EVENTFD | VFIO_IRQ_SET_ACTION_TRIGGER,
+            &fds,
+        )
+    }
+
+    fn set_irq_resample_fd(&self, index: u32, fds: Vec<&EventFd>) -> Result<()> {
+        let fds: Vec<_> = fds.iter().map(|fd| fd.as_raw_fd()).collect();
+
+        self.set_irqs(
+            index,
+            VFIO_IRQ_SET_DATA_EVENTFD | VFIO_IRQ_SET_ACTION_UNMASK,
+            &fds,
+        )
+    }
+
+    fn disable_irq(&self, index: u32) -> Result<()> {
+        self.set_irqs(
+            index,
+            VFIO_IRQ_SET_DATA_NONE | VFIO_IRQ_SET_ACTION_TRIGGER,
+            &[],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Seek;
+    use std::io::SeekFrom;
+    use std::thread;
+
+    use vfio_bindings::bindings::vfio::VFIO_IRQ_INFO_EVENTFD;
+    use vfio_bindings::bindings::vfio::VFIO_PCI_BAR0_REGION_INDEX;
+    use vfio_bindings::bindings::vfio::VFIO_PCI_CONFIG_REGION_INDEX;
+    use vfio_bindings::bindings::vfio::VFIO_PCI_INTX_IRQ_INDEX;
+    use vfio_bindings::bindings::vfio::VFIO_REGION_INFO_FLAG_READ;
+    use vfio_bindings::bindings::vfio::VFIO_REGION_INFO_FLAG_WRITE;
+    use vmm_sys_util::tempfile::TempFile;
+
+    use super::*;
+
+    const ENOSYS: u32 = 38;
+
+    /// A device with a vendor id, a scratch bar0 raising intx on writes to its first byte, and
+    /// writing "dma" at the start of each dma mapping
+    fn serve(mut stream: UnixStream) {
+        let mut config = [0u8; 256];
+        config[0..4].copy_from_slice(&[0x34, 0x12, 0x78, 0x56]);
+        let mut bar0 = [0u8; 0x1000];
+        let mut intx: Option<File> = None;
+
+        loop {
+            let mut header = Header::default();
+            let Ok((len, file)) = stream.recv_with_fd(header.as_mut_bytes()) else {
+                return;
+            };
+            if len == 0 {
+                return;
+            }
+            let mut payload = vec![0; header.msg_size as usize - size_of::<Header>()];
+            stream.read_exact(&mut payload).unwrap();
+
+            let mut error = 0;
+            let reply = match header.command {
+                VFIO_USER_VERSION => {
+                    let mut reply = Version {
+                        major: VFIO_USER_MAJOR,
+                        minor: VFIO_USER_MINOR,
+                    }
+                    .as_bytes()
+                    .to_vec();
+                    reply.extend_from_slice(b"{}\0");
+                    reply
+                }
+                VFIO_USER_DEVICE_GET_INFO => DeviceInfo {
+                    argsz: size_of::<DeviceInfo>() as u32,
+                    flags: 0,
+                    num_regions: VFIO_PCI_CONFIG_REGION_INDEX + 1,
+                    num_irqs: VFIO_PCI_INTX_IRQ_INDEX + 1,
+                }
+                .as_bytes()
+                .to_vec(),
+                VFIO_USER_DEVICE_GET_REGION_INFO => {
+                    let request = RegionInfo::read_from_prefix(&payload).unwrap().0;
+                    let size = match request.index {
+                        VFIO_PCI_CONFIG_REGION_INDEX => config.len() as u64,
+                        VFIO_PCI_BAR0_REGION_INDEX => bar0.len() as u64,
+                        _ => 0,
+                    };
+                    RegionInfo {
+                        argsz: size_of::<RegionInfo>() as u32,
+                        flags: if size == 0 {
+                            0
+                        } else {
+                            VFIO_REGION_INFO_FLAG_READ
+                                | VFIO_REGION_INFO_FLAG_WRITE
+                                | VFIO_REGION_INFO_FLAG_MMAP
+                        },
+                        index: request.index,
+                        cap_offset: 0,
+                        size,
+                        offset: 0,
+                    }
+                    .as_bytes()
+                    .to_vec()
+                }
+                VFIO_USER_DEVICE_GET_IRQ_INFO => {
+                    let request = IrqInfo::read_from_prefix(&payload).unwrap().0;
+                    IrqInfo {
+                        argsz: size_of::<IrqInfo>() as u32,
+                        flags: VFIO_IRQ_INFO_EVENTFD,
+                        index: request.index,
+                        count: 1,
+                    }
+                    .as_bytes()
+                    .to_vec()
+                }
+                VFIO_USER_DEVICE_SET_IRQS => {
+                    let request = IrqSet::read_from_prefix(&payload).unwrap().0;
+                    if request.flags & VFIO_IRQ_SET_ACTION_TRIGGER != 0 {
+                        intx = file;
+                    }
+                    vec![]
+                }
+                VFIO_USER_REGION_READ | VFIO_USER_REGION_WRITE => {
+                    let request = RegionAccess::read_from_prefix(&payload).unwrap().0;
+                    let region: &mut [u8] = match request.region {
+                        VFIO_PCI_CONFIG_REGION_INDEX => &mut config,
+                        _ => &mut bar0,
+                    };
+                    let range =
+                        request.offset as usize..request.offset as usize + request.count as usize;
+
+                    let mut reply = request.as_bytes().to_vec();
+                    if header.command == VFIO_USER_REGION_READ {
+                        reply.extend_from_slice(&region[range]);
+                    } else {
+                        region[range.clone()]
+                            .copy_from_slice(&payload[size_of::<RegionAccess>()..]);
+                        if request.region == VFIO_PCI_BAR0_REGION_INDEX
+                            && range.start == 0
+                            && let Some(intx) = &mut intx
+                        {
+                            intx.write_all(&1u64.to_ne_bytes()).unwrap();
+                        }
+                    }
+                    reply
+                }
+                VFIO_USER_DMA_MAP => {
+                    let request = DmaMap::read_from_prefix(&payload).unwrap().0;
+                    let mut mmap = unsafe {
+                        MmapOptions::new()
+                            .offset(request.offset)
+                            .len(request.size as usize)
+                            .map_mut(&file.unwrap())
+                            .unwrap()
+                    };
+                    mmap[..3].copy_from_slice(b"dma");
+                    vec![]
+                }
+                VFIO_USER_DEVICE_RESET => {
+                    bar0.fill(0);
+                    vec![]
+                }
+                _ => {
+                    error = ENOSYS;
+                    vec![]
+                }
+            };
+
+            let header = Header {
+                msg_id: header.msg_id,
+                command: header.command,
+                msg_size: (size_of::<Header>() + reply.len()) as u32,
+                flags: VFIO_USER_F_TYPE_REPLY | if error != 0 { VFIO_USER_F_ERROR } else { 0 },
+                error,
+            };
+            stream.write_all(header.as_bytes()).unwrap();
+            stream.write_all(&reply).unwrap();
+        }
+    }
+
+    fn connect() -> VfioUserClient {
+        let (client, server) = UnixStream::pair().unwrap();
+        thread::spawn(move || serve(server));
+
+        VfioUserClient::new(client).unwrap()
+    }
+
+    #[test]
+    fn test_vfio_user_region_access() -> Result<()> {
+        let client = connect();
+
+        let config = client.get_region_info(VFIO_PCI_CONFIG_REGION_INDEX)?;
+        assert_eq!(config.size, 256);
+        // Regions are not mmappable without an fd
+        assert_eq!(config.flags & VFIO_REGION_INFO_FLAG_MMAP, 0);
+
+        let mut buf = [0u8; 4];
+        client.region_read(VFIO_PCI_CONFIG_REGION_INDEX, &mut buf, 0)?;
+        assert_eq!(u32::from_le_bytes(buf), 0x5678_1234);
+
+        client.region_write(VFIO_PCI_BAR0_REGION_INDEX, &[1, 2, 3, 4], 8)?;
+        client.region_read(VFIO_PCI_BAR0_REGION_INDEX, &mut buf, 8)?;
+        assert_eq!(buf, [1, 2, 3, 4]);
+
+        client.reset()?;
+        client.region_read(VFIO_PCI_BAR0_REGION_INDEX, &mut buf, 8)?;
+        assert_eq!(buf, [0; 4]);
+
+        assert!(matches!(
+            client.dma_unmap(0, 0x1000),
+            Err(Error::VfioUserReply { errno: ENOSYS, .. })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_vfio_user_intx() -> Result<()> {
+        let client = connect();
+
+        let info = client.get_intx_irq_info().unwrap();
+        assert_eq!(info.count, 1);
+
+        let event_fd = EventFd::new(0)?;
+        client.enable_intx(&event_fd)?;
+        client.region_write(VFIO_PCI_BAR0_REGION_INDEX, &[1], 0)?;
+        assert_eq!(event_fd.read()?, 1);
+
+        client.disable_intx()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_vfio_user_dma_map() -> Result<()> {
+        let client = connect();
+
+        let mut memory = TempFile::new().unwrap().into_file();
+        memory.set_len(0x2000)?;
+        client.dma_map(0x8000_0000, 0x1000, memory.as_raw_fd(), 0x1000)?;
+
+        let mut buf = [0u8; 3];
+        memory.seek(SeekFrom::Start(0x1000))?;
+        memory.read_exact(&mut buf)?;
+        assert_eq!(&buf, b"dma");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,125 @@
+use zerocopy::FromBytes;
+use zerocopy::Immutable;
+use zerocopy::IntoBytes;
+use zerocopy::KnownLayout;
+
+/*
+ * vfio-user protocol
+ * https://www.qemu.org/docs/master/interop/vfio-user.html
+ */
+
+pub const VFIO_USER_MAJOR: u16 = 0;
+pub const VFIO_USER_MINOR: u16 = 1;
+
+pub const VFIO_USER_F_TYPE_MASK: u32 = 0xf;
+pub const VFIO_USER_F_TYPE_COMMAND: u32 = 0;
+pub const VFIO_USER_F_TYPE_REPLY: u32 = 1;
+pub const VFIO_USER_F_ERROR: u32 = 1 << 5;
+
+pub const VFIO_USER_F_DMA_REGION_READ: u32 = 1 << 0;
+pub const VFIO_USER_F_DMA_REGION_WRITE: u32 = 1 << 1;
+
+/// Capabilities announced in the version handshake
+pub const VFIO_USER_CAPABILITIES: &str =
+    r#"{"capabilities":{"max_msg_fds":8,"max_data_xfer_size":1048576}}"#;
+pub const VFIO_USER_MAX_DATA_XFER_SIZE: usize = 1 << 20;
+
+pub const VFIO_USER_VERSION: u16 = 1;
+pub const VFIO_USER_DMA_MAP: u16 = 2;
+pub const VFIO_USER_DMA_UNMAP: u16 = 3;
+pub const VFIO_USER_DEVICE_GET_INFO: u16 = 4;
+pub const VFIO_USER_DEVICE_GET_REGION_INFO: u16 = 5;
+pub const VFIO_USER_DEVICE_GET_IRQ_INFO: u16 = 7;
+pub const VFIO_USER_DEVICE_SET_IRQS: u16 = 8;
+pub const VFIO_USER_REGION_READ: u16 = 9;
+pub const VFIO_USER_REGION_WRITE: u16 = 10;
+pub const VFIO_USER_DEVICE_RESET: u16 = 13;
+
+#[derive(Clone, Copy, Default, FromBytes, IntoBytes, KnownLayout, Immutable)]
+#[repr(C)]
+pub struct Header {
+    pub msg_id: u16,
+    pub command: u16,
+    /// Including the header
+    pub msg_size: u32,
+    pub flags: u32,
+    /// Errno of a failed command
+    pub error: u32,
+}
+
+#[derive(Default, FromBytes, IntoBytes, KnownLayout, Immutable)]
+#[repr(C)]
+pub struct Version {
+    pub major: u16,
+    pub minor: u16,
+    // Followed by the capabilities in a nul terminated json string
+}
+
+#[derive(Default, FromBytes, IntoBytes, KnownLayout, Immutable)]
+#[repr(C)]
+pub struct DmaMap {
+    pub argsz: u32,
+    pub flags: u32,
+    /// Offset in the fd passed along
+    pub offset: u64,
+    pub address: u64,
+    pub size: u64,
+}
+
+#[derive(Default, FromBytes, IntoBytes, KnownLayout, Immutable)]
+#[repr(C)]
+pub struct DmaUnmap {
+    pub argsz: u32,
+    pub flags: u32,
+    pub address: u64,
+    pub size: u64,
+}
+
+#[derive(Default, FromBytes, IntoBytes, KnownLayout, Immutable)]
+#[repr(C)]
+pub struct DeviceInfo {
+    pub argsz: u32,
+    pub flags: u32,
+    pub num_regions: u32,
+    pub num_irqs: u32,
+}
+
+#[derive(Default, FromBytes, IntoBytes, KnownLayout, Immutable)]
+#[repr(C)]
+pub struct RegionInfo {
+    pub argsz: u32,
+    pub flags: u32,
+    pub index: u32,
+    pub cap_offset: u32,
+    pub size: u64,
+    /// Offset to mmap in the fd passed along with the reply
+    pub offset: u64,
+}
+
+#[derive(Default, FromBytes, IntoBytes, KnownLayout, Immutable)]
+#[repr(C)]
+pub struct IrqInfo {
+    pub argsz: u32,
+    pub flags: u32,
+    pub index: u32,
+    pub count: u32,
+}
+
+#[derive(Default, FromBytes, IntoBytes, KnownLayout, Immutable)]
+#[repr(C)]
+pub struct IrqSet {
+    pub argsz: u32,
+    pub flags: u32,
+    pub index: u32,
+    pub start: u32,
+    pub count: u32,
+}
+
+#[derive(Default, FromBytes, IntoBytes, KnownLayout, Immutable)]
+#[repr(C)]
+pub struct RegionAccess {
+    pub offset: u64,
+    pub region: u32,
+    pub count: u32,
+    // Followed by the data of a write request or of a read reply
+}
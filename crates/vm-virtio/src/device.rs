@@ -6,6 +6,9 @@ use tokio::runtime::Handle;
 use vm_core::arch::irq::InterruptController;
 use vm_core::device::error::DeviceSnapshotError;
 use vm_core::interrupt_manager::InterruptManager;
+#[cfg(target_os = "linux")]
+use vm_core::virtualization::vm::HypervisorVm;
+use vm_fdt::FdtWriter;
 use vm_mm::manager::MemoryAddressSpace;
 use vm_utils::range_allocator::RangeAllocator;
 
@@ -23,6 +26,11 @@ pub trait VirtioDevice: Sized + Send + Sync + 'static {
     const DEVICE_ID: u16;
     const DEVICE_FEATURES: u64;
 
+    /// Features which depend on how the device is configured, e.g. a read-only disk
+    fn extra_device_features(&self) -> u64 {
+        0
+    }
+
     fn virtqueues_size_max(&self) -> Vec<u16>;
 
     /// A virtio device can have maximum of 65536 virtqueues.
@@ -35,6 +43,9 @@ pub trait VirtioDevice: Sized + Send + Sync + 'static {
 
     fn reset(&mut self);
 
+    /// The driver has set FEATURES_OK, e.g. the virtqueues which exist depend on the features
+    fn set_driver_features(&mut self, _driver_features: u64) {}
+
     fn virtqueue_handler(&self, queue_sel: u16) -> Option<Box<dyn VirtqueueHandler>>;
 
     /// Read to device-specific configuration
@@ -43,6 +54,14 @@ pub trait VirtioDevice: Sized + Send + Sync + 'static {
     /// Write to device-specific configuration
     fn write_config(&mut self, offset: usize, buf: &[u8]) -> Result<()>;
 
+    /// Add device-specific properties to the virtio-mmio node
+    fn generate_dt_properties(
+        &self,
+        _fdt: &mut FdtWriter,
+    ) -> std::result::Result<(), vm_fdt::Error> {
+        Ok(())
+    }
+
     fn pause(&self) -> std::result::Result<(), DeviceSnapshotError> {
         Err(DeviceSnapshotError::DeviceNotSupportSnapshot(
             Self::NAME.to_string(),
@@ -67,8 +86,10 @@ pub trait VirtioDevice: Sized + Send + Sync + 'static {
         ))
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn into_mmio_device(
         self,
+        #[cfg(target_os = "linux")] vm: Arc<dyn HypervisorVm>,
         mmio_allocator: &mut RangeAllocator<u64>,
         interrupt_manager: &InterruptManager,
         virtio_aml_path_allocator: &mut RangeAllocator<u8>,
@@ -85,6 +106,8 @@ pub trait VirtioDevice: Sized + Send + Sync + 'static {
             .map_err(VirtioError::AllocId)?;
 
         let dev = VirtioMmioTransport::new(
+            #[cfg(target_os = "linux")]
+            vm,
             tokio_runtime,
             memory,
             irq_chip,
@@ -92,7 +115,7 @@ pub trait VirtioDevice: Sized + Send + Sync + 'static {
             mmio_range,
             interrupt_manager.allocate_irq()?.try_into().unwrap(),
             VirtioTransportCommon::new(self)?,
-        );
+        )?;
 
         Ok(dev)
     }
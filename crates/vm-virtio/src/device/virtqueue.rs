@@ -6,8 +6,11 @@ use async_trait::async_trait;
 use tokio::select;
 use tokio::sync::Notify;
 use tokio_util::sync::CancellationToken;
+use tracing::warn;
 use vm_mm::manager::MemoryAddressSpace;
 
+use crate::dma::DmaTranslator;
+use crate::result::VirtioError;
 use crate::virtqueue::Virtqueue;
 use crate::virtqueue::virtq_desc_table::VirtqDescTableRef;
 
@@ -22,6 +25,9 @@ pub trait VirtioUsedBufferNotifier: Send + Sync {
 
 pub trait VirtioConfigurationChangeNotifier: Send + Sync {
     fn update_config_generation(&self);
+
+    /// The device hit an error it can't recover from, it stays broken until the driver resets it
+    fn device_needs_reset(&self);
 }
 
 #[derive(Default)]
@@ -34,10 +40,21 @@ pub async fn virtqueue_worker(
     mm: Arc<MemoryAddressSpace>,
     controller: Arc<VirtqueueWorkerController>,
     used_buffer_notification: Arc<dyn VirtioUsedBufferNotifier>,
+    configuration_change_notification: Arc<dyn VirtioConfigurationChangeNotifier>,
     virtqueue: Virtqueue,
     desc_handler: Box<dyn VirtqueueHandler>,
+    dma_translator: Option<Arc<dyn DmaTranslator>>,
 ) {
-    let avail_ring = virtqueue.avail_ring(mm.as_ref()).unwrap();
+    // The driver placed the rings where the device can't reach them
+    let broken = |err: VirtioError| {
+        warn!(?err, "virtqueue is broken, the device needs a reset");
+        configuration_change_notification.device_needs_reset();
+    };
+
+    let avail_ring = match virtqueue.avail_ring(mm.as_ref(), dma_translator.as_ref()) {
+        Ok(avail_ring) => avail_ring,
+        Err(err) => return broken(err),
+    };
     let queue_size = virtqueue.read_queue_size();
     let mut last_available_idx = 0;
 
@@ -54,11 +71,17 @@ pub async fn virtqueue_worker(
             let desc_id = avail_ring.ring(last_available_idx % queue_size);
             last_available_idx += 1;
 
-            let desc_table = virtqueue.desc_table_ref(mm.as_ref()).unwrap();
+            let desc_table = match virtqueue.desc_table_ref(mm.as_ref(), dma_translator.clone()) {
+                Ok(desc_table) => desc_table,
+                Err(err) => return broken(err),
+            };
             let len = desc_handler.handle_desc(&desc_table, desc_id).await;
 
             // update used ring
-            let mut used_ring = virtqueue.used_ring(mm.as_ref()).unwrap();
+            let mut used_ring = match virtqueue.used_ring(mm.as_ref(), dma_translator.as_ref()) {
+                Ok(used_ring) => used_ring,
+                Err(err) => return broken(err),
+            };
             let used_idx = used_ring.idx() % queue_size;
             let used_entry = used_ring.ring(used_idx);
             used_entry.id = desc_id as u32;
@@ -0,0 +1,16 @@
+/// The direction of a dma, the iommu checks it against the permissions of the mapping
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DmaAccess {
+    /// The device reads the guest memory
+    Read,
+    /// The device writes the guest memory
+    Write,
+}
+
+/// Translate the addresses a device uses for dma (iova) to guest physical addresses,
+/// it is set on the devices sitting behind an iommu.
+pub trait DmaTranslator: Send + Sync {
+    /// Returns the gpa of `iova`, `None` if `[iova, iova + len)` is not mapped contiguously
+    /// or the mappings do not allow `access`
+    fn translate(&self, iova: u64, len: u64, access: DmaAccess) -> Option<u64>;
+}
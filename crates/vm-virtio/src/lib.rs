@@ -1,6 +1,7 @@
 #![deny(warnings)]
 
 pub mod device;
+pub mod dma;
 pub mod result;
 pub mod transport;
 pub mod types;
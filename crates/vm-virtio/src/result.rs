@@ -1,5 +1,6 @@
 use thiserror::Error;
 use vm_core::interrupt_manager::InterruptManagerError;
+use vm_core::virtualization::vm::error::VmError;
 use vm_utils::range_allocator::RangeAllocatorError;
 
 use crate::types::device::gpu::error::VirtioGpuError;
@@ -15,6 +16,12 @@ pub enum VirtioError {
     #[error("Failed to alloc virtio-mmio id")]
     AllocId(RangeAllocatorError),
 
+    #[error("Failed to create eventfd: {0}")]
+    EventFd(std::io::Error),
+
+    #[error("Hypervisor error: {0}")]
+    Vm(#[from] VmError),
+
     #[error("queue id exceeds u16")]
     QueueExceedsU16 { device: &'static str },
 
@@ -62,6 +69,21 @@ pub enum VirtioError {
 
     #[error("{0}")]
     VirtioGpu(#[from] VirtioGpuError),
+
+    #[error("invalid 9p mount tag {0:?}")]
+    Invalid9pMountTag(String),
+
+    #[error("shared directory {0:?} is not a directory")]
+    Invalid9pSharedDir(std::path::PathBuf),
+
+    #[error("failed to open the disk image {path:?}: {err}")]
+    OpenBlkImage {
+        path: std::path::PathBuf,
+        err: std::io::Error,
+    },
+
+    #[error("invalid virtio-mem {name}: 0x{size:x}")]
+    InvalidVirtioMemSize { name: &'static str, size: usize },
 }
 
 pub type Result<T> = core::result::Result<T, VirtioError>;
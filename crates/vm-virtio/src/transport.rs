@@ -1,12 +1,18 @@
 use std::sync::Arc;
 
 use crate::device::virtqueue::VirtioConfigurationChangeNotifier;
+use crate::dma::DmaTranslator;
 
 pub mod mmio;
 pub mod pci;
 
 pub(crate) mod common;
+#[cfg(target_os = "linux")]
+pub(crate) mod eventfd;
 
 pub trait VirtioDeviceOps {
     fn configuration_change_notifier(&self) -> Arc<dyn VirtioConfigurationChangeNotifier>;
+
+    /// Put the device behind an iommu, it must be called before the driver probes the device
+    fn set_dma_translator(&self, translator: Arc<dyn DmaTranslator>);
 }
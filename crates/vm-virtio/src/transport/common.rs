@@ -1,7 +1,9 @@
+use std::collections::HashMap;
 use std::io::Read;
 use std::io::Write;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::sync::RwLock;
 
 use tokio::task::JoinHandle;
 use vm_core::device::error::DeviceSnapshotError;
@@ -15,9 +17,13 @@ use vm_snapshot::helper::write_u32;
 
 use crate::device::VirtioDevice;
 use crate::device::virtqueue::VirtqueueWorkerController;
+use crate::dma::DmaTranslator;
 use crate::result::Result;
 use crate::result::VirtioError;
 use crate::transport::common::control_register::ControlRegister;
+#[cfg(target_os = "linux")]
+use crate::transport::eventfd::VirtqueueIoEventFd;
+use crate::types::device_features::VIRTIO_F_ACCESS_PLATFORM;
 use crate::types::interrupt_status::InterruptStatus;
 use crate::types::status::Status;
 use crate::virtqueue::Virtqueue;
@@ -30,6 +36,16 @@ const DRIVER_FEATURE_SEL_MAX: u32 = 1;
 pub struct VirtqueueHandler {
     pub controller: Arc<VirtqueueWorkerController>,
     pub _join_handler: JoinHandle<()>,
+    #[cfg(target_os = "linux")]
+    pub ioeventfd: VirtqueueIoEventFd,
+}
+
+/// Stops the workers of the enabled virtqueues on a device reset, the driver enables them
+/// again afterwards
+pub fn stop_virtqueue_handlers(handlers: &RwLock<HashMap<u16, VirtqueueHandler>>) {
+    for (_, handler) in handlers.write().unwrap().drain() {
+        handler.controller.queue_disable.cancel();
+    }
 }
 
 /// Common state for a VirtIO transport implementation.
@@ -43,8 +59,9 @@ pub struct VirtioTransportCommon<D> {
     queue_sel: u16,
     virtqueues: Vec<Virtqueue>,
     interrupt_status: Arc<Mutex<InterruptStatus>>,
-    status: Status,
+    status: Arc<Mutex<Status>>,
     config_generation: Arc<Mutex<u8>>,
+    dma_translator: Option<Arc<dyn DmaTranslator>>,
 }
 
 impl<D> VirtioTransportCommon<D>
@@ -70,6 +87,7 @@ where
             interrupt_status: Default::default(),
             status: Default::default(),
             config_generation: Default::default(),
+            dma_translator: None,
         };
 
         Ok(virtio_dev)
@@ -90,10 +108,29 @@ where
             virtqueue.reset();
         }
         *self.interrupt_status.lock().unwrap() = InterruptStatus::empty();
-        self.status = Default::default();
+        *self.status.lock().unwrap() = Default::default();
         *self.config_generation.lock().unwrap() = 0;
     }
 
+    /// The device is behind an iommu, the addresses in the virtqueues are iovas
+    pub fn set_dma_translator(&mut self, translator: Arc<dyn DmaTranslator>) {
+        self.dma_translator = Some(translator);
+    }
+
+    pub fn dma_translator(&self) -> Option<Arc<dyn DmaTranslator>> {
+        self.dma_translator.clone()
+    }
+
+    fn device_features(&self) -> u64 {
+        let features = D::DEVICE_FEATURES | self.device.extra_device_features();
+
+        if self.dma_translator.is_some() {
+            features | (1 << VIRTIO_F_ACCESS_PLATFORM)
+        } else {
+            features
+        }
+    }
+
     fn get_device_feature_sel(&self) -> u32 {
         self.device_feature_sel
     }
@@ -116,7 +153,7 @@ where
                     // defined here exceeds 63.
                     0
                 } else {
-                    (D::DEVICE_FEATURES >> (sel * 32)) as u32
+                    (self.device_features() >> (sel * 32)) as u32
                 }
             }
             ControlRegister::DeviceFeaturesSel => self.get_device_feature_sel(),
@@ -157,7 +194,7 @@ where
                 self.get_virtqueue(sel)?.read_queue_ready() as u32
             }
             ControlRegister::InterruptStatus => self.interrupt_status.lock().unwrap().bits(),
-            ControlRegister::Status => self.status.bits() as u32,
+            ControlRegister::Status => self.status.lock().unwrap().bits() as u32,
             ControlRegister::QueueDescLow => unreachable!(),
             ControlRegister::QueueDescHigh => unreachable!(),
             ControlRegister::QueueAvailLow => unreachable!(),
@@ -186,7 +223,7 @@ where
                 let shift = sel * 32;
                 let mask = 0xffff_ffffu64.wrapping_shl(shift);
 
-                let filtered_val = ((val as u64).wrapping_shl(shift)) & D::DEVICE_FEATURES;
+                let filtered_val = ((val as u64).wrapping_shl(shift)) & self.device_features();
 
                 self.driver_features = (self.driver_features & !mask) | filtered_val;
             }
@@ -213,7 +250,19 @@ where
                 if val == 0 {
                     self.reset();
                 } else {
-                    self.status = Status::from_bits_truncate(val as u8);
+                    let mut status = self.status.lock().unwrap();
+                    let new_status = Status::from_bits_truncate(val as u8);
+                    let features_ok = new_status.contains(Status::FEATURES_OK)
+                        && !status.contains(Status::FEATURES_OK);
+                    // Only a reset clears it
+                    let needs_reset = status.device_needs_reset();
+                    *status = new_status;
+                    status.set(Status::DEVICE_NEEDS_RESET, needs_reset);
+                    drop(status);
+
+                    if features_ok {
+                        self.device.set_driver_features(self.driver_features);
+                    }
                 }
             }
             ControlRegister::QueueDescLow => {
@@ -268,16 +317,20 @@ where
         self.interrupt_status.clone()
     }
 
+    pub fn get_status(&self) -> Arc<Mutex<Status>> {
+        self.status.clone()
+    }
+
     pub fn get_config_generation(&self) -> Arc<Mutex<u8>> {
         self.config_generation.clone()
     }
 
     pub fn pause(&self) -> std::result::Result<(), DeviceSnapshotError> {
-        todo!()
+        self.device.pause()
     }
 
     pub fn resume(&self) -> std::result::Result<(), DeviceSnapshotError> {
-        todo!()
+        self.device.resume()
     }
 
     pub fn save(&self, writer: &mut dyn Write) -> std::result::Result<(), DeviceSnapshotError> {
@@ -292,7 +345,7 @@ where
         }
 
         write_u32(writer, self.interrupt_status.lock().unwrap().bits())?;
-        write_u8(writer, self.status.bits())?;
+        write_u8(writer, self.status.lock().unwrap().bits())?;
         write_u8(writer, *self.config_generation.lock().unwrap())?;
 
         Ok(())
@@ -311,9 +364,95 @@ where
 
         *self.interrupt_status.lock().unwrap() =
             InterruptStatus::from_bits_retain(read_u32(reader)?);
-        self.status = Status::from_bits_retain(read_u8(reader)?);
+        *self.status.lock().unwrap() = Status::from_bits_retain(read_u8(reader)?);
         *self.config_generation.lock().unwrap() = read_u8(reader)?;
 
+        if self.status.lock().unwrap().contains(Status::FEATURES_OK) {
+            self.device.set_driver_features(self.driver_features);
+        }
+
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+    use std::sync::atomic::Ordering;
+
+    use super::*;
+    use crate::device::virtqueue::VirtqueueHandler;
+    use crate::types::device_features::VIRTIO_F_VERSION_1;
+
+    #[derive(Default)]
+    struct Device {
+        paused: AtomicBool,
+        state: u8,
+    }
+
+    impl VirtioDevice for Device {
+        const NAME: &str = "test";
+        const DEVICE_ID: u16 = 0;
+        const DEVICE_FEATURES: u64 = 1 << VIRTIO_F_VERSION_1;
+
+        fn virtqueues_size_max(&self) -> Vec<u16> {
+            vec![8]
+        }
+
+        fn reset(&mut self) {}
+
+        fn virtqueue_handler(&self, _queue_sel: u16) -> Option<Box<dyn VirtqueueHandler>> {
+            None
+        }
+
+        fn read_config(&self, _offset: usize, _buf: &mut [u8]) -> Result<()> {
+            Ok(())
+        }
+
+        fn write_config(&mut self, _offset: usize, _buf: &[u8]) -> Result<()> {
+            Ok(())
+        }
+
+        fn pause(&self) -> std::result::Result<(), DeviceSnapshotError> {
+            self.paused.store(true, Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn resume(&self) -> std::result::Result<(), DeviceSnapshotError> {
+            self.paused.store(false, Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn save(&self, writer: &mut dyn Write) -> std::result::Result<(), DeviceSnapshotError> {
+            write_u8(writer, self.state)?;
+            Ok(())
+        }
+
+        fn load(&mut self, reader: &mut dyn Read) -> std::result::Result<(), DeviceSnapshotError> {
+            self.state = read_u8(reader)?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_pause_and_snapshot_reach_the_device() {
+        let mut transport = VirtioTransportCommon::new(Device::default()).unwrap();
+
+        transport.pause().unwrap();
+        assert!(transport.device.paused.load(Ordering::SeqCst));
+        transport.resume().unwrap();
+        assert!(!transport.device.paused.load(Ordering::SeqCst));
+
+        transport.device.state = 5;
+        transport.write_reg(ControlRegister::QueueSize, 4).unwrap();
+
+        let mut buf = vec![];
+        transport.save(&mut buf).unwrap();
+
+        let mut restored = VirtioTransportCommon::new(Device::default()).unwrap();
+        restored.load(&mut buf.as_slice()).unwrap();
+
+        assert_eq!(restored.device.state, 5);
+        assert_eq!(restored.read_reg(ControlRegister::QueueSize).unwrap(), 4);
+    }
+}
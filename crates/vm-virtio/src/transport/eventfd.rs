@@ -0,0 +1,373 @@
+use std::sync::Arc;
+
+use tokio::io::unix::AsyncFd;
+use tokio::runtime::Handle;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tracing::warn;
+use vm_core::virtualization::vm::HypervisorVm;
+use vm_core::virtualization::vm::IoEventAddress;
+use vm_core::virtualization::vm::IoEventDatamatch;
+use vmm_sys_util::eventfd::EFD_NONBLOCK;
+use vmm_sys_util::eventfd::EventFd;
+
+use crate::result::Result;
+use crate::result::VirtioError;
+
+fn new_eventfd() -> Result<EventFd> {
+    EventFd::new(EFD_NONBLOCK).map_err(VirtioError::EventFd)
+}
+
+/// Run `f` on the tokio runtime each time `fd` is signaled
+fn spawn_eventfd_handler<F>(tokio_runtime: &Handle, fd: &EventFd, f: F) -> Result<JoinHandle<()>>
+where
+    F: Fn() + Send + 'static,
+{
+    let fd = {
+        let _guard = tokio_runtime.enter();
+        let fd = fd.try_clone().map_err(VirtioError::EventFd)?;
+        // SAFETY: the eventfd is owned by the `AsyncFd` and stays open until it is dropped
+        unsafe { AsyncFd::register(fd) }.map_err(|err| VirtioError::EventFd(err.into()))?
+    };
+
+    Ok(tokio_runtime.spawn(async move {
+        loop {
+            let Ok(mut guard) = fd.readable().await else {
+                break;
+            };
+
+            match guard.try_io(|fd| fd.get_ref().read()) {
+                Ok(Ok(_)) => f(),
+                Ok(Err(err)) => {
+                    warn!(?err, "failed to read eventfd");
+                    break;
+                }
+                Err(_would_block) => continue,
+            }
+        }
+    }))
+}
+
+/// A queue notification handled by the kernel, the vcpu does not exit on the notify write
+pub struct VirtqueueIoEventFd {
+    vm: Arc<dyn HypervisorVm>,
+    fd: EventFd,
+    addr: Option<IoEventAddress>,
+    datamatch: IoEventDatamatch,
+    handler: JoinHandle<()>,
+}
+
+impl VirtqueueIoEventFd {
+    /// Nothing is registered while `addr` is `None`, e.g. the notify bar does not decode
+    pub fn new(
+        vm: Arc<dyn HypervisorVm>,
+        tokio_runtime: &Handle,
+        addr: Option<IoEventAddress>,
+        datamatch: IoEventDatamatch,
+        queue_notify: Arc<Notify>,
+    ) -> Result<Self> {
+        let fd = new_eventfd()?;
+        let handler = spawn_eventfd_handler(tokio_runtime, &fd, move || queue_notify.notify_one())?;
+
+        if let Some(addr) = addr
+            && let Err(err) = vm.register_ioeventfd(&fd, addr, datamatch)
+        {
+            handler.abort();
+            return Err(err.into());
+        }
+
+        Ok(VirtqueueIoEventFd {
+            vm,
+            fd,
+            addr,
+            datamatch,
+            handler,
+        })
+    }
+
+    /// Move the registration to `addr`, the guest moved the notify bar or toggled its decoding
+    pub fn set_addr(&mut self, addr: Option<IoEventAddress>) -> Result<()> {
+        if addr == self.addr {
+            return Ok(());
+        }
+
+        if let Some(old) = self.addr.take() {
+            self.vm
+                .unregister_ioeventfd(&self.fd, old, self.datamatch)?;
+        }
+
+        if let Some(addr) = addr {
+            self.vm.register_ioeventfd(&self.fd, addr, self.datamatch)?;
+            self.addr = Some(addr);
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for VirtqueueIoEventFd {
+    fn drop(&mut self) {
+        if let Some(addr) = self.addr
+            && let Err(err) = self.vm.unregister_ioeventfd(&self.fd, addr, self.datamatch)
+        {
+            warn!(?err, ?addr, "failed to unregister ioeventfd");
+        }
+        self.handler.abort();
+    }
+}
+
+/// An interrupt injected by the kernel when the eventfd is written
+pub struct IrqFd {
+    vm: Arc<dyn HypervisorVm>,
+    fd: EventFd,
+    gsi: u32,
+    resample_handler: Option<JoinHandle<()>>,
+}
+
+impl IrqFd {
+    /// Edge triggered, e.g. a msi routed through `gsi`
+    pub fn new(vm: Arc<dyn HypervisorVm>, gsi: u32) -> Result<Self> {
+        let fd = new_eventfd()?;
+
+        vm.set_irqfd(&fd, gsi)?;
+
+        Ok(IrqFd {
+            vm,
+            fd,
+            gsi,
+            resample_handler: None,
+        })
+    }
+
+    /// Level triggered. The kernel deasserts the line once the guest has handled it, and it is raised again if
+    /// `pending` still holds.
+    pub fn new_level<F>(
+        vm: Arc<dyn HypervisorVm>,
+        tokio_runtime: &Handle,
+        gsi: u32,
+        pending: F,
+    ) -> Result<Self>
+    where
+        F: Fn() -> bool + Send + 'static,
+    {
+        let fd = new_eventfd()?;
+        let resample_fd = new_eventfd()?;
+
+        let resample_handler = {
+            let fd = fd.try_clone().map_err(VirtioError::EventFd)?;
+
+            spawn_eventfd_handler(tokio_runtime, &resample_fd, move || {
+                if pending() {
+                    let _ = fd.write(1);
+                }
+            })?
+        };
+
+        if let Err(err) = vm.set_irqfd_with_resample(&fd, &resample_fd, gsi) {
+            resample_handler.abort();
+            return Err(err.into());
+        }
+
+        Ok(IrqFd {
+            vm,
+            fd,
+            gsi,
+            resample_handler: Some(resample_handler),
+        })
+    }
+
+    pub fn gsi(&self) -> u32 {
+        self.gsi
+    }
+
+    pub fn trigger(&self) {
+        if let Err(err) = self.fd.write(1) {
+            warn!(?err, gsi = self.gsi, "failed to trigger irqfd");
+        }
+    }
+}
+
+impl Drop for IrqFd {
+    fn drop(&mut self) {
+        if let Err(err) = self.vm.del_irqfd(&self.fd, self.gsi) {
+            warn!(?err, gsi = self.gsi, "failed to unregister irqfd");
+        }
+        if let Some(handler) = &self.resample_handler {
+            handler.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use tokio::runtime::Runtime;
+    use vm_core::arch::irq::InterruptController;
+    use vm_core::cpu::vm_exit::VmExit;
+    use vm_core::interrupt_manager::InterruptManager;
+    use vm_core::virtualization::vcpu::HypervisorVcpu;
+    use vm_core::virtualization::vm::SetUserMemoryRegionFlags;
+    use vm_core::virtualization::vm::error::VmError;
+    use vm_mm::manager::MemoryAddressSpace;
+    use vm_utils::cpu_topology::CpuTopology;
+
+    use super::*;
+
+    /// Keeps the registered ioeventfd addresses, `fail` rejects new registrations
+    #[derive(Default)]
+    struct IoEventVm {
+        registered: Mutex<Vec<IoEventAddress>>,
+        fail: bool,
+    }
+
+    impl HypervisorVm for IoEventVm {
+        fn create_vcpu(
+            &self,
+            _vcpu_id: u64,
+            _cpu_topology: &CpuTopology,
+            _mm: Arc<MemoryAddressSpace>,
+            _vm_exit_handler: Arc<dyn VmExit>,
+        ) -> std::result::Result<Box<dyn HypervisorVcpu>, VmError> {
+            unreachable!()
+        }
+
+        fn create_irq_chip(&self) -> std::result::Result<Box<dyn InterruptController>, VmError> {
+            unreachable!()
+        }
+
+        fn create_irq_manager(&self) -> std::result::Result<InterruptManager, VmError> {
+            unreachable!()
+        }
+
+        fn set_user_memory_region(
+            &self,
+            _userspace_addr: u64,
+            _guest_phys_addr: u64,
+            _memory_size: usize,
+            _flags: SetUserMemoryRegionFlags,
+        ) -> std::result::Result<(), VmError> {
+            unreachable!()
+        }
+
+        fn remove_user_memory_region(
+            &self,
+            _guest_phys_addr: u64,
+            _memory_size: usize,
+        ) -> std::result::Result<(), VmError> {
+            unreachable!()
+        }
+
+        fn set_irqfd(&self, _fd: &EventFd, _gsi: u32) -> std::result::Result<(), VmError> {
+            unreachable!()
+        }
+
+        fn del_irqfd(&self, _fd: &EventFd, _gsi: u32) -> std::result::Result<(), VmError> {
+            unreachable!()
+        }
+
+        fn set_irqfd_with_resample(
+            &self,
+            _fd: &EventFd,
+            _resamplefd: &EventFd,
+            _gsi: u32,
+        ) -> std::result::Result<(), VmError> {
+            unreachable!()
+        }
+
+        fn set_gsi_routing(&self) -> std::result::Result<(), VmError> {
+            unreachable!()
+        }
+
+        fn register_ioeventfd(
+            &self,
+            _fd: &EventFd,
+            addr: IoEventAddress,
+            _datamatch: IoEventDatamatch,
+        ) -> std::result::Result<(), VmError> {
+            if self.fail {
+                let (IoEventAddress::Pio(addr) | IoEventAddress::Mmio(addr)) = addr;
+                return Err(VmError::MemoryRegionNotMapped(addr));
+            }
+
+            self.registered.lock().unwrap().push(addr);
+            Ok(())
+        }
+
+        fn unregister_ioeventfd(
+            &self,
+            _fd: &EventFd,
+            addr: IoEventAddress,
+            _datamatch: IoEventDatamatch,
+        ) -> std::result::Result<(), VmError> {
+            self.registered.lock().unwrap().retain(|a| *a != addr);
+            Ok(())
+        }
+
+        fn secondary_cpu_should_run_on_booting(&self) -> bool {
+            false
+        }
+    }
+
+    fn ioeventfd(
+        runtime: &Runtime,
+        vm: Arc<IoEventVm>,
+        addr: Option<IoEventAddress>,
+    ) -> Result<VirtqueueIoEventFd> {
+        VirtqueueIoEventFd::new(
+            vm,
+            runtime.handle(),
+            addr,
+            IoEventDatamatch::U16(0),
+            Arc::new(Notify::new()),
+        )
+    }
+
+    #[test]
+    fn test_ioeventfd_follows_notify_bar() {
+        let runtime = Runtime::new().unwrap();
+        let vm = Arc::new(IoEventVm::default());
+
+        // The notify bar does not decode yet
+        let mut fd = ioeventfd(&runtime, vm.clone(), None).unwrap();
+        assert!(vm.registered.lock().unwrap().is_empty());
+
+        fd.set_addr(Some(IoEventAddress::Mmio(0x1000))).unwrap();
+        assert_eq!(
+            *vm.registered.lock().unwrap(),
+            [IoEventAddress::Mmio(0x1000)]
+        );
+
+        // The bar is moved
+        fd.set_addr(Some(IoEventAddress::Mmio(0x2000))).unwrap();
+        assert_eq!(
+            *vm.registered.lock().unwrap(),
+            [IoEventAddress::Mmio(0x2000)]
+        );
+
+        // The memory decoding is turned off
+        fd.set_addr(None).unwrap();
+        assert!(vm.registered.lock().unwrap().is_empty());
+
+        fd.set_addr(Some(IoEventAddress::Mmio(0x3000))).unwrap();
+        drop(fd);
+        assert!(vm.registered.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_ioeventfd_register_error() {
+        let runtime = Runtime::new().unwrap();
+        let vm = Arc::new(IoEventVm {
+            fail: true,
+            ..Default::default()
+        });
+
+        assert!(ioeventfd(&runtime, vm.clone(), Some(IoEventAddress::Mmio(0x1000))).is_err());
+
+        // Nothing to register until the bar decodes
+        let mut fd = ioeventfd(&runtime, vm.clone(), None).unwrap();
+        assert!(fd.set_addr(Some(IoEventAddress::Mmio(0x1000))).is_err());
+        drop(fd);
+        assert!(vm.registered.lock().unwrap().is_empty());
+    }
+}
@@ -22,16 +22,22 @@ use vm_core::device::Device;
 use vm_core::device::error::DeviceError;
 use vm_core::device::error::DeviceSnapshotError;
 use vm_core::device::mmio::mmio_device::MmioDevice;
+#[cfg(target_os = "linux")]
+use vm_core::virtualization::vm::HypervisorVm;
 use vm_fdt::FdtWriter;
 use vm_mm::manager::MemoryAddressSpace;
 
 use crate::device::VirtioDevice;
 use crate::device::virtqueue::VirtioConfigurationChangeNotifier;
 use crate::device::virtqueue::VirtioUsedBufferNotifier;
+use crate::dma::DmaTranslator;
+use crate::result::Result;
 use crate::transport::VirtioDeviceOps;
 use crate::transport::common::VirtioTransportCommon;
 use crate::transport::common::VirtqueueHandler;
+use crate::transport::common::stop_virtqueue_handlers;
 use crate::transport::mmio::interrupt::VirtioMmioEventNotifier;
+use crate::types::device_id::DeviceId;
 
 mod control_register;
 mod interrupt;
@@ -44,6 +50,8 @@ pub struct VirtioMmioTransport<D> {
     irq: u8,
     common: Mutex<VirtioTransportCommon<D>>,
 
+    #[cfg(target_os = "linux")]
+    vm: Arc<dyn HypervisorVm>,
     tokio_runtime: Handle,
     memory: Arc<MemoryAddressSpace>,
     irq_chip: Arc<dyn InterruptController>,
@@ -56,7 +64,9 @@ impl<D> VirtioMmioTransport<D>
 where
     D: VirtioDevice,
 {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
+        #[cfg(target_os = "linux")] vm: Arc<dyn HypervisorVm>,
         tokio_runtime: Handle,
         memory: Arc<MemoryAddressSpace>,
         irq_chip: Arc<dyn InterruptController>,
@@ -64,27 +74,35 @@ where
         mmio_range: Range<u64>,
         irq: u8,
         common: VirtioTransportCommon<D>,
-    ) -> Self {
+    ) -> Result<Self> {
         let event_notification = Arc::new(VirtioMmioEventNotifier::new(
+            #[cfg(target_os = "linux")]
+            vm.clone(),
+            #[cfg(target_os = "linux")]
+            &tokio_runtime,
+            #[cfg(not(target_os = "linux"))]
             irq_chip.clone(),
             irq as u32,
             common.get_interrupt_status(),
+            common.get_status(),
             common.get_config_generation(),
-        ));
+        )?);
 
-        VirtioMmioTransport {
+        Ok(VirtioMmioTransport {
             virtio_mmio_device_index,
             mmio_range,
             irq,
             common: Mutex::new(common),
 
+            #[cfg(target_os = "linux")]
+            vm,
             tokio_runtime,
             memory,
             irq_chip,
 
             virtqueue_handlers: Default::default(),
             event_notification,
-        }
+        })
     }
 
     pub(crate) fn get_used_buffer_notification(&self) -> Arc<dyn VirtioUsedBufferNotifier> {
@@ -106,6 +124,16 @@ where
         D::NAME.to_string()
     }
 
+    fn reset(&self) -> std::result::Result<(), DeviceError> {
+        let mut common = self.common.lock().unwrap();
+
+        stop_virtqueue_handlers(&self.virtqueue_handlers);
+        common.reset();
+        self.irq_chip.trigger_irq(self.irq as u32, false);
+
+        Ok(())
+    }
+
     fn pause(&self) -> std::result::Result<(), DeviceSnapshotError> {
         self.common.lock().unwrap().pause()
     }
@@ -133,6 +161,10 @@ where
     fn support_mmio_transport_mut(&mut self) -> Option<&mut dyn MmioDevice> {
         Some(self)
     }
+
+    fn iommu_mmio_base(&self) -> Option<u64> {
+        (D::DEVICE_ID == DeviceId::Iommu as u16).then_some(self.mmio_range.start)
+    }
 }
 
 impl<D> Aml for VirtioMmioTransport<D>
@@ -140,6 +172,12 @@ where
     D: VirtioDevice,
 {
     fn to_aml_bytes(&self, sink: &mut dyn AmlSink) {
+        // The gsi of a spi is its id plus 32
+        #[cfg(target_arch = "aarch64")]
+        let gsi = self.irq as u32 + vm_core::arch::aarch64::irq::GIC_SPI_START;
+        #[cfg(not(target_arch = "aarch64"))]
+        let gsi = self.irq as u32;
+
         AmlDevice::new(
             Path::new(&format!("V{:03}", self.virtio_mmio_device_index)),
             vec![
@@ -156,7 +194,7 @@ where
                             self.mmio_range.end - 1,
                             None,
                         ),
-                        &Interrupt::new(true, true, false, false, self.irq as u32),
+                        &Interrupt::new(true, true, false, false, gsi),
                     ]),
                 ),
             ],
@@ -213,6 +251,12 @@ where
             fdt.property_array_u32("interrupts", &[self.irq as u32, 0])?;
         }
 
+        self.common
+            .lock()
+            .unwrap()
+            .device
+            .generate_dt_properties(fdt)?;
+
         fdt.end_node(node)?;
 
         Ok(())
@@ -226,4 +270,8 @@ where
     fn configuration_change_notifier(&self) -> Arc<dyn VirtioConfigurationChangeNotifier> {
         self.get_configuration_change_notification()
     }
+
+    fn set_dma_translator(&self, translator: Arc<dyn DmaTranslator>) {
+        self.common.lock().unwrap().set_dma_translator(translator);
+    }
 }
@@ -1,32 +1,67 @@
 use std::sync::Arc;
 use std::sync::Mutex;
 
+#[cfg(not(target_os = "linux"))]
 use vm_core::arch::irq::InterruptController;
+#[cfg(target_os = "linux")]
+use vm_core::virtualization::vm::HypervisorVm;
 
 use crate::device::virtqueue::VirtioConfigurationChangeNotifier;
 use crate::device::virtqueue::VirtioUsedBufferNotifier;
+use crate::result::Result;
+#[cfg(target_os = "linux")]
+use crate::transport::eventfd::IrqFd;
 use crate::types::interrupt_status::InterruptStatus;
+use crate::types::status::Status;
 
 pub struct VirtioMmioEventNotifier {
+    #[cfg(target_os = "linux")]
+    irqfd: IrqFd,
+    #[cfg(not(target_os = "linux"))]
     irq_chip: Arc<dyn InterruptController>,
+    #[cfg(not(target_os = "linux"))]
     irq: u32,
     is: Arc<Mutex<InterruptStatus>>,
+    status: Arc<Mutex<Status>>,
     config_generation: Arc<Mutex<u8>>,
 }
 
 impl VirtioMmioEventNotifier {
     pub fn new(
-        irq_chip: Arc<dyn InterruptController>,
+        #[cfg(target_os = "linux")] vm: Arc<dyn HypervisorVm>,
+        #[cfg(target_os = "linux")] tokio_runtime: &tokio::runtime::Handle,
+        #[cfg(not(target_os = "linux"))] irq_chip: Arc<dyn InterruptController>,
         irq: u32,
         is: Arc<Mutex<InterruptStatus>>,
+        status: Arc<Mutex<Status>>,
         config_generation: Arc<Mutex<u8>>,
-    ) -> Self {
-        VirtioMmioEventNotifier {
+    ) -> Result<Self> {
+        // The line stays asserted until the driver acks every pending interrupt
+        #[cfg(target_os = "linux")]
+        let irqfd = IrqFd::new_level(vm, tokio_runtime, irq, {
+            let is = is.clone();
+            move || !is.lock().unwrap().is_empty()
+        })?;
+
+        Ok(VirtioMmioEventNotifier {
+            #[cfg(target_os = "linux")]
+            irqfd,
+            #[cfg(not(target_os = "linux"))]
             irq_chip,
+            #[cfg(not(target_os = "linux"))]
             irq,
             is,
+            status,
             config_generation,
-        }
+        })
+    }
+
+    fn trigger_irq(&self) {
+        #[cfg(target_os = "linux")]
+        self.irqfd.trigger();
+
+        #[cfg(not(target_os = "linux"))]
+        self.irq_chip.trigger_irq(self.irq, true);
     }
 }
 
@@ -36,7 +71,7 @@ impl VirtioUsedBufferNotifier for VirtioMmioEventNotifier {
             .lock()
             .unwrap()
             .insert(InterruptStatus::VIRTIO_MMIO_INT_VRING);
-        self.irq_chip.trigger_irq(self.irq, true);
+        self.trigger_irq();
     }
 }
 
@@ -48,6 +83,14 @@ impl VirtioConfigurationChangeNotifier for VirtioMmioEventNotifier {
             .unwrap()
             .insert(InterruptStatus::VIRTIO_MMIO_INT_CONFIG);
 
-        self.irq_chip.trigger_irq(self.irq, true);
+        self.trigger_irq();
+    }
+
+    fn device_needs_reset(&self) {
+        self.status
+            .lock()
+            .unwrap()
+            .insert(Status::DEVICE_NEEDS_RESET);
+        self.update_config_generation();
     }
 }
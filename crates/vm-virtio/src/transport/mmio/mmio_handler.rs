@@ -1,6 +1,10 @@
 use std::sync::Arc;
 
 use tracing::trace;
+#[cfg(target_os = "linux")]
+use vm_core::virtualization::vm::IoEventAddress;
+#[cfg(target_os = "linux")]
+use vm_core::virtualization::vm::IoEventDatamatch;
 
 use crate::device::VirtioDevice;
 use crate::device::virtqueue::VirtqueueWorkerController;
@@ -8,6 +12,9 @@ use crate::device::virtqueue::virtqueue_worker;
 use crate::result::Result;
 use crate::result::VirtioError;
 use crate::transport::common::control_register::ControlRegister;
+use crate::transport::common::stop_virtqueue_handlers;
+#[cfg(target_os = "linux")]
+use crate::transport::eventfd::VirtqueueIoEventFd;
 use crate::transport::mmio::VirtioMmioTransport;
 use crate::transport::mmio::VirtqueueHandler;
 use crate::transport::mmio::control_register::MmioControlRegister;
@@ -75,12 +82,25 @@ where
 
                     let controller = Arc::new(VirtqueueWorkerController::default());
 
+                    #[cfg(target_os = "linux")]
+                    let ioeventfd = VirtqueueIoEventFd::new(
+                        self.vm.clone(),
+                        &self.tokio_runtime,
+                        Some(IoEventAddress::Mmio(
+                            self.mmio_range.start + MmioControlRegister::QueueNotify as u64,
+                        )),
+                        IoEventDatamatch::U32(queue_sel as u32),
+                        controller.queue_notify.clone(),
+                    )?;
+
                     let _join_handler = self.tokio_runtime.spawn(virtqueue_worker(
                         self.memory.clone(),
                         controller.clone(),
                         self.get_used_buffer_notification(),
+                        self.get_configuration_change_notification(),
                         *common.get_virtqueue(queue_sel)?,
                         handler,
+                        common.dma_translator(),
                     ));
 
                     assert!(
@@ -90,6 +110,8 @@ where
                                 VirtqueueHandler {
                                     controller,
                                     _join_handler,
+                                    #[cfg(target_os = "linux")]
+                                    ioeventfd,
                                 },
                             )
                             .is_none()
@@ -121,7 +143,13 @@ where
 
                 Ok(())
             }
-            MmioControlRegister::Status => common.write_reg(ControlRegister::Status, val),
+            MmioControlRegister::Status => {
+                if val == 0 {
+                    stop_virtqueue_handlers(&self.virtqueue_handlers);
+                }
+
+                common.write_reg(ControlRegister::Status, val)
+            }
             MmioControlRegister::QueueDescLow => {
                 common.write_reg(ControlRegister::QueueDescLow, val)
             }
@@ -9,8 +9,11 @@ use std::sync::RwLock;
 use tokio::runtime::Handle;
 use vm_core::arch::irq::InterruptController;
 use vm_core::device::Device;
+use vm_core::device::error::DeviceError;
 use vm_core::device::error::DeviceSnapshotError;
 use vm_core::interrupt_manager::InterruptManager;
+#[cfg(target_os = "linux")]
+use vm_core::virtualization::vm::HypervisorVm;
 use vm_mm::manager::MemoryAddressSpace;
 use vm_pci::device::capability::msix::PciMsixCap;
 use vm_pci::device::function::PciTypeFunctionCommon;
@@ -22,15 +25,22 @@ use vm_pci::types::bar::PciBarInfo;
 use vm_pci::types::configuration_space::ConfigurationSpace;
 use vm_pci::types::device::PciDevice;
 use vm_pci::types::function::PciFunction;
+use vm_snapshot::helper::read_u16;
+use vm_snapshot::helper::write_u16;
 use vm_utils::range_allocator::RangeAllocator;
+use zerocopy::IntoBytes;
 
 use crate::device::VirtioDevice;
 use crate::device::virtqueue::VirtioConfigurationChangeNotifier;
 use crate::device::virtqueue::VirtioUsedBufferNotifier;
+use crate::dma::DmaTranslator;
 use crate::result::Result;
 use crate::transport::VirtioDeviceOps;
 use crate::transport::common::VirtioTransportCommon;
 use crate::transport::common::VirtqueueHandler;
+use crate::transport::common::stop_virtqueue_handlers;
+#[cfg(target_os = "linux")]
+use crate::transport::eventfd::IrqFd;
 use crate::transport::pci::interrupt::VirtioPciConfigurationChangeNotifier;
 use crate::transport::pci::interrupt::VirtioPciEventUsedBufferNotifier;
 use crate::transport::pci::interrupt::VirtioPciIrqDispatcher;
@@ -63,6 +73,8 @@ pub struct VirtioPciTransport<D> {
     common: Mutex<VirtioTransportCommon<D>>,
     interrupt_dispatcher: Arc<VirtioPciIrqDispatcher>,
 
+    #[cfg(target_os = "linux")]
+    vm: Arc<dyn HypervisorVm>,
     tokio_runtime: Handle,
     memory: Arc<MemoryAddressSpace>,
 
@@ -78,11 +90,13 @@ where
         self,
         #[cfg(target_arch = "x86_64")] pci_io_window_allocator: &mut RangeAllocator<u16>,
         pci_mmio_window_allocator: &mut RangeAllocator<u64>,
+        pci_mmio64_window_allocator: &mut RangeAllocator<u64>,
     ) -> Result<VirtioPciDev<D>> {
         let function = Type0Function::new_with_configuration_space(
             #[cfg(target_arch = "x86_64")]
             pci_io_window_allocator,
             pci_mmio_window_allocator,
+            pci_mmio64_window_allocator,
             self.configuration_space.clone(),
             self,
         )
@@ -92,12 +106,13 @@ where
     }
 
     fn new(
+        #[cfg(target_os = "linux")] vm: Arc<dyn HypervisorVm>,
         interrupt_manager: &InterruptManager,
         tokio_runtime: Handle,
         memory: Arc<MemoryAddressSpace>,
         irq_chip: Arc<dyn InterruptController>,
         common: VirtioTransportCommon<D>,
-    ) -> Self {
+    ) -> Result<Self> {
         let configuration_space = Arc::new(Mutex::new(ConfigurationSpace::default()));
 
         let num_queues = common.device.num_queues();
@@ -112,8 +127,19 @@ where
             let msix;
 
             if cfg!(target_os = "linux") {
+                let msix_info = VirtioPciMsixInfo::new(num_queues);
+
+                // Each vector owns a gsi, its msi route is updated when the driver programs the table
+                #[cfg(target_os = "linux")]
+                let msix_info = {
+                    let irqfds = (0..msix_info.vectors())
+                        .map(|_| IrqFd::new(vm.clone(), interrupt_manager.allocate_gsi()?))
+                        .collect::<Result<_>>()?;
+                    msix_info.with_irqfds(irqfds)
+                };
+
                 legacy_int = None;
-                msix = Some(Arc::new(RwLock::new(VirtioPciMsixInfo::new(num_queues))));
+                msix = Some(Arc::new(RwLock::new(msix_info)));
             } else {
                 legacy_int = Some(
                     interrupt_manager
@@ -138,18 +164,21 @@ where
         let configuration_change_notification = Arc::new(VirtioPciConfigurationChangeNotifier {
             interrupt_dispatcher: interrupt_dispatcher.clone(),
             is: common.get_interrupt_status(),
+            status: common.get_status(),
             config_generation: common.get_config_generation(),
         });
 
-        VirtioPciTransport {
+        Ok(VirtioPciTransport {
             configuration_space,
             common: Mutex::new(common),
             interrupt_dispatcher,
+            #[cfg(target_os = "linux")]
+            vm,
             tokio_runtime,
             memory,
             virtqueue_handlers: Default::default(),
             configuration_change_notification,
-        }
+        })
     }
 
     fn get_used_buffer_notification(
@@ -165,6 +194,21 @@ where
 
         Arc::new(notifier)
     }
+
+    /// The transport specific part of a device reset
+    fn reset_transport(&self) {
+        stop_virtqueue_handlers(&self.virtqueue_handlers);
+
+        let mut virtio_pci_msix_vector = self
+            .interrupt_dispatcher
+            .virtio_pci_msix_vector
+            .write()
+            .unwrap();
+        virtio_pci_msix_vector.config_msix_vector = VIRTIO_MSI_NO_VECTOR;
+        virtio_pci_msix_vector
+            .queue_msix_vector
+            .fill(VIRTIO_MSI_NO_VECTOR);
+    }
 }
 
 impl<D> PciTypeFunctionCommon for VirtioPciTransport<D>
@@ -274,16 +318,19 @@ where
             // virtio_pci_common_cfg
             Some(PciBarInfo::Mmio {
                 is_64bit: false,
+                prefetchable: false,
                 len: 0x1000,
             }),
             // virtio_pci_notify_cap
             Some(PciBarInfo::Mmio {
                 is_64bit: false,
+                prefetchable: false,
                 len: 0x1000,
             }),
             // virtio_pci_isr_cap
             Some(PciBarInfo::Mmio {
                 is_64bit: false,
+                prefetchable: false,
                 len: 0x1000,
             }),
             // device_spec_cfg
@@ -292,6 +339,7 @@ where
             } else {
                 Some(PciBarInfo::Mmio {
                     is_64bit: false,
+                    prefetchable: false,
                     len: 0x1000,
                 })
             },
@@ -300,6 +348,7 @@ where
                 .as_ref()
                 .map(|msix| PciBarInfo::Mmio {
                     is_64bit: false,
+                    prefetchable: false,
                     len: msix.read().unwrap().bar_size().try_into().unwrap(),
                 }),
             None,
@@ -329,19 +378,80 @@ where
     }
 
     fn pause(&self) -> std::result::Result<(), DeviceSnapshotError> {
-        todo!()
+        self.common.lock().unwrap().pause()
     }
 
     fn resume(&self) -> std::result::Result<(), DeviceSnapshotError> {
-        todo!()
+        self.common.lock().unwrap().resume()
     }
 
-    fn save(&self, _writer: &mut dyn Write) -> std::result::Result<(), DeviceSnapshotError> {
-        todo!()
+    fn save(&self, writer: &mut dyn Write) -> std::result::Result<(), DeviceSnapshotError> {
+        self.common.lock().unwrap().save(writer)?;
+
+        {
+            let vectors = self
+                .interrupt_dispatcher
+                .virtio_pci_msix_vector
+                .read()
+                .unwrap();
+            write_u16(writer, vectors.config_msix_vector)?;
+            for vector in &vectors.queue_msix_vector {
+                write_u16(writer, *vector)?;
+            }
+        }
+
+        if let Some(msix) = self.interrupt_dispatcher.msix.as_ref() {
+            writer.write_all(msix.read().unwrap().table.as_bytes())?;
+        }
+
+        Ok(())
     }
 
-    fn load(&mut self, _reader: &mut dyn Read) -> std::result::Result<(), DeviceSnapshotError> {
-        todo!()
+    fn load(&mut self, reader: &mut dyn Read) -> std::result::Result<(), DeviceSnapshotError> {
+        self.common.lock().unwrap().load(reader)?;
+
+        {
+            let mut vectors = self
+                .interrupt_dispatcher
+                .virtio_pci_msix_vector
+                .write()
+                .unwrap();
+            vectors.config_msix_vector = read_u16(reader)?;
+            for vector in vectors.queue_msix_vector.iter_mut() {
+                *vector = read_u16(reader)?;
+            }
+        }
+
+        if let Some(msix) = self.interrupt_dispatcher.msix.as_ref() {
+            let mut msix = msix.write().unwrap();
+            reader.read_exact(msix.table.as_mut_bytes())?;
+
+            // The irqfds are routed to the restored messages
+            #[cfg(target_os = "linux")]
+            for vector in 0..msix.vectors() as usize {
+                self.update_msi_routing(&msix, vector);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn bar_remapped(&self, bar: Bar, address: Option<u64>) {
+        #[cfg(target_os = "linux")]
+        if let Bar::Bar1 = bar {
+            self.remap_notify(address);
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = (bar, address);
+    }
+
+    fn reset(&self) -> std::result::Result<(), DeviceError> {
+        let mut common = self.common.lock().unwrap();
+
+        self.reset_transport();
+        common.reset();
+
+        Ok(())
     }
 }
 
@@ -352,6 +462,10 @@ where
     fn configuration_change_notifier(&self) -> Arc<dyn VirtioConfigurationChangeNotifier> {
         self.configuration_change_notification.clone()
     }
+
+    fn set_dma_translator(&self, translator: Arc<dyn DmaTranslator>) {
+        self.common.lock().unwrap().set_dma_translator(translator);
+    }
 }
 
 pub struct VirtioPciDev<D>
@@ -369,6 +483,10 @@ where
         "virtio pci dev".to_string()
     }
 
+    fn reset(&self) -> std::result::Result<(), DeviceError> {
+        self.function.reset()
+    }
+
     fn pause(&self) -> std::result::Result<(), DeviceSnapshotError> {
         self.function.pause()
     }
@@ -418,35 +536,49 @@ pub trait VirtioPciDevice: VirtioDevice {
 
     fn into_virtio_pci_device(
         self,
+        #[cfg(target_os = "linux")] vm: Arc<dyn HypervisorVm>,
         interrupt_manager: &InterruptManager,
         tokio_runtime: Handle,
         memory: Arc<MemoryAddressSpace>,
         irq_chip: Arc<dyn InterruptController>,
     ) -> Result<VirtioPciTransport<Self>> {
         let dev = VirtioPciTransport::new(
+            #[cfg(target_os = "linux")]
+            vm,
             interrupt_manager,
             tokio_runtime,
             memory,
             irq_chip,
             VirtioTransportCommon::new(self)?,
-        );
+        )?;
         Ok(dev)
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn into_pci_device(
         self,
+        #[cfg(target_os = "linux")] vm: Arc<dyn HypervisorVm>,
         #[cfg(target_arch = "x86_64")] pci_io_window_allocator: &mut RangeAllocator<u16>,
         pci_mmio_window_allocator: &mut RangeAllocator<u64>,
+        pci_mmio64_window_allocator: &mut RangeAllocator<u64>,
         interrupt_manager: &InterruptManager,
         tokio_runtime: Handle,
         memory: Arc<MemoryAddressSpace>,
         irq_chip: Arc<dyn InterruptController>,
     ) -> Result<VirtioPciDev<Self>> {
-        self.into_virtio_pci_device(interrupt_manager, tokio_runtime, memory, irq_chip)?
-            .into_pci_device(
-                #[cfg(target_arch = "x86_64")]
-                pci_io_window_allocator,
-                pci_mmio_window_allocator,
-            )
+        self.into_virtio_pci_device(
+            #[cfg(target_os = "linux")]
+            vm,
+            interrupt_manager,
+            tokio_runtime,
+            memory,
+            irq_chip,
+        )?
+        .into_pci_device(
+            #[cfg(target_arch = "x86_64")]
+            pci_io_window_allocator,
+            pci_mmio_window_allocator,
+            pci_mmio64_window_allocator,
+        )
     }
 }
@@ -1,12 +1,19 @@
+use std::collections::hash_map::Entry;
 use std::sync::Arc;
 
 use strum_macros::FromRepr;
 use tracing::warn;
+#[cfg(target_os = "linux")]
+use vm_core::virtualization::vm::IoEventAddress;
+#[cfg(target_os = "linux")]
+use vm_core::virtualization::vm::IoEventDatamatch;
 
 use crate::device::virtqueue::VirtqueueWorkerController;
 use crate::device::virtqueue::virtqueue_worker;
 use crate::transport::common::VirtqueueHandler;
 use crate::transport::common::control_register::ControlRegister;
+#[cfg(target_os = "linux")]
+use crate::transport::eventfd::VirtqueueIoEventFd;
 use crate::transport::pci::VirtioPciDevice;
 use crate::transport::pci::VirtioPciTransport;
 
@@ -211,6 +218,9 @@ where
             CommonCfgOffset::DeviceStatus => {
                 assert_eq!(data.len(), 1);
                 let status = data[0];
+                if status == 0 {
+                    self.reset_transport();
+                }
                 dev.write_reg(ControlRegister::Status, status as u32)
                     .unwrap();
             }
@@ -247,31 +257,48 @@ where
                     // disable
                     todo!()
                 } else {
+                    let Entry::Vacant(entry) = virtqueue.entry(queue_sel) else {
+                        warn!(name = D::NAME, queue_sel, "queue is already enabled");
+                        return;
+                    };
+
                     let Some(handler) = dev.device.virtqueue_handler(queue_sel) else {
                         unreachable!("no handler for queue {queue_sel}");
                     };
 
                     let controller = Arc::new(VirtqueueWorkerController::default());
 
+                    #[cfg(target_os = "linux")]
+                    let ioeventfd = match VirtqueueIoEventFd::new(
+                        self.vm.clone(),
+                        &self.tokio_runtime,
+                        self.notify_address().map(IoEventAddress::Mmio),
+                        IoEventDatamatch::U16(queue_sel),
+                        controller.queue_notify.clone(),
+                    ) {
+                        Ok(ioeventfd) => ioeventfd,
+                        Err(err) => {
+                            warn!(name = D::NAME, queue_sel, ?err, "failed to enable queue");
+                            return;
+                        }
+                    };
+
                     let _join_handler = self.tokio_runtime.spawn(virtqueue_worker(
                         self.memory.clone(),
                         controller.clone(),
                         self.get_used_buffer_notification(dev.get_interrupt_status(), queue_sel),
+                        self.configuration_change_notification.clone(),
                         *dev.get_virtqueue(queue_sel).unwrap(),
                         handler,
+                        dev.dma_translator(),
                     ));
 
-                    assert!(
-                        virtqueue
-                            .insert(
-                                queue_sel,
-                                VirtqueueHandler {
-                                    controller,
-                                    _join_handler,
-                                },
-                            )
-                            .is_none()
-                    );
+                    entry.insert(VirtqueueHandler {
+                        controller,
+                        _join_handler,
+                        #[cfg(target_os = "linux")]
+                        ioeventfd,
+                    });
                 }
 
                 dev.write_reg(ControlRegister::QueueReady, queue_enable as u32)
@@ -16,6 +16,7 @@ use crate::device::virtqueue::VirtioUsedBufferNotifier;
 use crate::transport::pci::VirtioPciMsixVector;
 use crate::transport::pci::msix::VirtioPciMsixInfo;
 use crate::types::interrupt_status::InterruptStatus;
+use crate::types::status::Status;
 
 pub struct VirtioPciIrqDispatcher {
     pub irq_chip: Arc<dyn InterruptController>,
@@ -35,10 +36,7 @@ impl VirtioPciIrqDispatcher {
                 .read()
                 .unwrap()
                 .queue_msix_vector[queue_sel as usize];
-            let msix = self.msix.as_ref().unwrap();
-            let msi = msix.read().unwrap();
-            let msi = &msi.table[vector as usize];
-            self.irq_chip.send_msi(msi.addr_lo, msi.addr_hi, msi.data);
+            self.send_msix(vector);
         } else {
             // If MSI-X capability is disabled, the device MUST set the Interrupt Status bit in the PCI Status register in the
             // PCI Configuration Header of the device to the logical OR of all bits in ISR status of the device. The device
@@ -62,9 +60,20 @@ impl VirtioPciIrqDispatcher {
                 .read()
                 .unwrap()
                 .config_msix_vector;
-            let msix = self.msix.as_ref().unwrap();
-            let msi = msix.read().unwrap();
-            let msi = &msi.table[vector as usize];
+            self.send_msix(vector);
+        }
+    }
+
+    fn send_msix(&self, vector: u16) {
+        let msix = self.msix.as_ref().unwrap();
+        let msix = msix.read().unwrap();
+
+        #[cfg(target_os = "linux")]
+        msix.irqfds[vector as usize].trigger();
+
+        #[cfg(not(target_os = "linux"))]
+        {
+            let msi = &msix.table[vector as usize];
             self.irq_chip.send_msi(msi.addr_lo, msi.addr_hi, msi.data);
         }
     }
@@ -111,6 +120,7 @@ impl VirtioUsedBufferNotifier for VirtioPciEventUsedBufferNotifier {
 pub struct VirtioPciConfigurationChangeNotifier {
     pub interrupt_dispatcher: Arc<VirtioPciIrqDispatcher>,
     pub is: Arc<Mutex<InterruptStatus>>,
+    pub status: Arc<Mutex<Status>>,
     pub config_generation: Arc<Mutex<u8>>,
 }
 
@@ -126,4 +136,12 @@ impl VirtioConfigurationChangeNotifier for VirtioPciConfigurationChangeNotifier
 
         self.interrupt_dispatcher.notify_configuration_change();
     }
+
+    fn device_needs_reset(&self) {
+        self.status
+            .lock()
+            .unwrap()
+            .insert(Status::DEVICE_NEEDS_RESET);
+        self.update_config_generation();
+    }
 }
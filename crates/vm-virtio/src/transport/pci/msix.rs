@@ -1,5 +1,8 @@
 use vm_pci::device::capability::msix::MsixEntry;
 
+#[cfg(target_os = "linux")]
+use crate::transport::eventfd::IrqFd;
+
 /*
  * 4.1.5.1.2.1 Device Requirements: MSI-X Vector Configuration
  *   A device that has an MSI-X capability SHOULD support at least 2 and at most 0x800 MSI-X vectors.
@@ -11,6 +14,9 @@ pub struct VirtioPciMsixInfo {
     // The number of MSI-X vectors
     vectors: u16,
     pub table: Vec<MsixEntry>,
+    /// One per vector
+    #[cfg(target_os = "linux")]
+    pub irqfds: Vec<IrqFd>,
 }
 
 impl VirtioPciMsixInfo {
@@ -21,7 +27,19 @@ impl VirtioPciMsixInfo {
          */
         let vectors = (num_queues + 1).clamp(MSI_X_VECTORS_MIN, MSI_X_VECTORS_MAX);
         let table = (0..vectors).map(|_| MsixEntry::default()).collect();
-        VirtioPciMsixInfo { vectors, table }
+        VirtioPciMsixInfo {
+            vectors,
+            table,
+            #[cfg(target_os = "linux")]
+            irqfds: Vec::new(),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn with_irqfds(self, irqfds: Vec<IrqFd>) -> Self {
+        assert_eq!(irqfds.len(), self.vectors as usize);
+
+        VirtioPciMsixInfo { irqfds, ..self }
     }
 
     pub fn vectors(&self) -> u16 {
@@ -1,3 +1,9 @@
+#[cfg(target_os = "linux")]
+use tracing::warn;
+#[cfg(target_os = "linux")]
+use vm_core::virtualization::kvm::gsi_routing::get_kvm_gsi_routing_instance;
+#[cfg(target_os = "linux")]
+use vm_pci::device::capability::msix::MsixEntry;
 use zerocopy::IntoBytes;
 
 use crate::transport::pci::VirtioPciDevice;
@@ -15,6 +21,28 @@ where
     fn write_table(&self, msix: &mut VirtioPciMsixInfo, offset: u64, data: &[u8]) {
         msix.table.as_mut_bytes()[offset as usize..offset as usize + data.len()]
             .copy_from_slice(data);
+
+        #[cfg(target_os = "linux")]
+        {
+            let vector = offset as usize / size_of::<MsixEntry>();
+            self.update_msi_routing(msix, vector);
+        }
+    }
+
+    /// Route the gsi of the vector's irqfd to the address/data programmed by the driver
+    #[cfg(target_os = "linux")]
+    pub(crate) fn update_msi_routing(&self, msix: &VirtioPciMsixInfo, vector: usize) {
+        let entry = &msix.table[vector];
+        let gsi = msix.irqfds[vector].gsi();
+
+        let updated = get_kvm_gsi_routing_instance()
+            .lock()
+            .unwrap()
+            .insert_or_update_msi_gsi_routing(gsi, entry.addr_lo, entry.addr_hi, entry.data);
+
+        if updated && let Err(err) = self.vm.set_gsi_routing() {
+            warn!(name = D::NAME, ?err, vector, "failed to update msi routing");
+        }
     }
 
     fn read_pba(&self, _msix: &VirtioPciMsixInfo, _offset: u64, _data: &mut [u8]) {
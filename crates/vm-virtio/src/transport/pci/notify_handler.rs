@@ -1,3 +1,16 @@
+#[cfg(target_os = "linux")]
+use tracing::warn;
+#[cfg(target_os = "linux")]
+use vm_core::virtualization::vm::IoEventAddress;
+#[cfg(target_os = "linux")]
+use vm_pci::device::function::type0::Bar;
+#[cfg(target_os = "linux")]
+use vm_pci::types::bar::address_of_bar;
+#[cfg(target_os = "linux")]
+use vm_pci::types::configuration_space::command::PciCommand;
+#[cfg(target_os = "linux")]
+use vm_pci::types::configuration_space::header::type0::Type0Header;
+
 use crate::transport::pci::VirtioPciDevice;
 use crate::transport::pci::VirtioPciTransport;
 
@@ -5,6 +18,36 @@ impl<D> VirtioPciTransport<D>
 where
     D: VirtioPciDevice,
 {
+    /// Every queue shares the start of the notify bar, `notify_off_multiplier` is 0. `None`
+    /// while the memory decoding is off.
+    #[cfg(target_os = "linux")]
+    pub(crate) fn notify_address(&self) -> Option<u64> {
+        let cfg = self.configuration_space.lock().unwrap();
+        let header = cfg.as_header::<Type0Header>();
+
+        PciCommand::from_bits_retain(header.common.command)
+            .contains(PciCommand::MEMORY)
+            .then(|| address_of_bar(header.bar[Bar::Bar1 as usize]) as u64)
+    }
+
+    /// Follow the notify bar with the ioeventfds of the enabled queues
+    #[cfg(target_os = "linux")]
+    pub(crate) fn remap_notify(&self, address: Option<u64>) {
+        for (queue_sel, handler) in self.virtqueue_handlers.write().unwrap().iter_mut() {
+            if let Err(err) = handler
+                .ioeventfd
+                .set_addr(address.map(IoEventAddress::Mmio))
+            {
+                warn!(
+                    name = D::NAME,
+                    queue_sel,
+                    ?err,
+                    "failed to move the queue ioeventfd"
+                );
+            }
+        }
+    }
+
     pub fn read_notify(&self, _offset: u64, _data: &mut [u8]) {
         unreachable!()
     }
@@ -2,3 +2,6 @@ pub mod balloon_tranditional;
 pub mod blk;
 pub mod entropy;
 pub mod gpu;
+pub mod iommu;
+pub mod mem;
+pub mod p9;
@@ -2,8 +2,9 @@ use strum_macros::FromRepr;
 use zerocopy::FromBytes;
 use zerocopy::Immutable;
 use zerocopy::IntoBytes;
+use zerocopy::KnownLayout;
 
-#[derive(FromRepr)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromRepr)]
 #[repr(u16)]
 pub enum VirtioBalloonTranditionalVirtqueue {
     Inflateq = 0,
@@ -23,6 +24,61 @@ pub enum VirtioBalloonTranditionalFeatureBitmap {
     REPORTING = 5,      /* Page reporting virtqueue */
 }
 
+/// Balloon PFNs are always in 4K units, regardless of the page size
+pub const VIRTIO_BALLOON_PFN_SHIFT: u32 = 12;
+
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, FromRepr)]
+#[repr(u16)]
+pub enum VirtioBalloonStatTag {
+    SWAP_IN = 0,          /* Amount of memory swapped in */
+    SWAP_OUT = 1,         /* Amount of memory swapped out */
+    MAJFLT = 2,           /* Number of major faults */
+    MINFLT = 3,           /* Number of minor faults */
+    MEMFREE = 4,          /* Total amount of free memory */
+    MEMTOT = 5,           /* Total amount of memory */
+    AVAIL = 6,            /* Available memory as in /proc */
+    CACHES = 7,           /* Disk caches */
+    HTLB_PGALLOC = 8,     /* Hugetlb page allocations */
+    HTLB_PGFAIL = 9,      /* Hugetlb page allocation failures */
+    OOM_KILLS = 10,       /* OOM killer invocations */
+    ALLOC_STALLS = 11,    /* Stall count of memory allocation */
+    ASYNC_SCANS = 12,     /* Amount of memory scanned asynchronously */
+    DIRECT_SCANS = 13,    /* Amount of memory scanned directly */
+    ASYNC_RECLAIMS = 14,  /* Amount of memory reclaimed asynchronously */
+    DIRECT_RECLAIMS = 15, /* Amount of memory reclaimed directly */
+}
+
+impl VirtioBalloonStatTag {
+    pub fn name(&self) -> &'static str {
+        match self {
+            VirtioBalloonStatTag::SWAP_IN => "swap_in",
+            VirtioBalloonStatTag::SWAP_OUT => "swap_out",
+            VirtioBalloonStatTag::MAJFLT => "major_faults",
+            VirtioBalloonStatTag::MINFLT => "minor_faults",
+            VirtioBalloonStatTag::MEMFREE => "free_memory",
+            VirtioBalloonStatTag::MEMTOT => "total_memory",
+            VirtioBalloonStatTag::AVAIL => "available_memory",
+            VirtioBalloonStatTag::CACHES => "disk_caches",
+            VirtioBalloonStatTag::HTLB_PGALLOC => "hugetlb_allocations",
+            VirtioBalloonStatTag::HTLB_PGFAIL => "hugetlb_failures",
+            VirtioBalloonStatTag::OOM_KILLS => "oom_kills",
+            VirtioBalloonStatTag::ALLOC_STALLS => "alloc_stalls",
+            VirtioBalloonStatTag::ASYNC_SCANS => "async_scans",
+            VirtioBalloonStatTag::DIRECT_SCANS => "direct_scans",
+            VirtioBalloonStatTag::ASYNC_RECLAIMS => "async_reclaims",
+            VirtioBalloonStatTag::DIRECT_RECLAIMS => "direct_reclaims",
+        }
+    }
+}
+
+#[derive(FromBytes, IntoBytes, Immutable, KnownLayout)]
+#[repr(C, packed)]
+pub struct VirtioBalloonStat {
+    pub tag: u16,
+    pub val: u64,
+}
+
 #[derive(Default, FromBytes, IntoBytes, Immutable)]
 #[repr(C, packed)]
 pub struct VirtioBalloonTranditionalConfig {
@@ -121,3 +121,15 @@ pub mod req {
         // pub status: u8,
     }
 }
+
+pub mod status {
+    pub const VIRTIO_BLK_S_OK: u8 = 0;
+    pub const VIRTIO_BLK_S_IOERR: u8 = 1;
+    pub const VIRTIO_BLK_S_UNSUPP: u8 = 2;
+}
+
+/// The sector size of the capacity and of the requests, whatever the block size is
+pub const VIRTIO_BLK_SECTOR_SIZE: u64 = 512;
+
+/// Length of the id returned by VIRTIO_BLK_T_GET_ID
+pub const VIRTIO_BLK_ID_BYTES: usize = 20;
@@ -0,0 +1,148 @@
+use strum_macros::FromRepr;
+use zerocopy::FromBytes;
+use zerocopy::Immutable;
+use zerocopy::IntoBytes;
+use zerocopy::KnownLayout;
+
+#[derive(FromRepr)]
+#[repr(u16)]
+pub enum VirtioIommuVirtqueue {
+    Requestq = 0,
+    Eventq = 1,
+}
+
+#[allow(non_camel_case_types)]
+pub enum VirtioIommuFeatureBitmap {
+    INPUT_RANGE = 0,   /* Available range of virtual addresses */
+    DOMAIN_RANGE = 1,  /* Number of domains supported */
+    MAP_UNMAP = 2,     /* Map and unmap requests are available */
+    BYPASS = 3,        /* Endpoints that are not attached to a domain are in bypass mode */
+    PROBE = 4,         /* Probe requests are available */
+    MMIO = 5,          /* VIRTIO_IOMMU_MAP_F_MMIO flag is available */
+    BYPASS_CONFIG = 6, /* Bypass field of the configuration is writable */
+}
+
+#[derive(Clone, Copy, FromRepr)]
+#[repr(u8)]
+pub enum VirtioIommuRequestType {
+    Attach = 1,
+    Detach = 2,
+    Map = 3,
+    Unmap = 4,
+    Probe = 5,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum VirtioIommuStatus {
+    Ok = 0,
+    IoErr = 1,
+    Unsupp = 2,
+    DevErr = 3,
+    Inval = 4,
+    Range = 5,
+    NoEnt = 6,
+    Fault = 7,
+    NoMem = 8,
+}
+
+/// The domain has an identity mapping of the guest memory
+pub const VIRTIO_IOMMU_ATTACH_F_BYPASS: u32 = 1 << 0;
+
+pub const VIRTIO_IOMMU_MAP_F_READ: u32 = 1 << 0;
+pub const VIRTIO_IOMMU_MAP_F_WRITE: u32 = 1 << 1;
+pub const VIRTIO_IOMMU_MAP_F_MMIO: u32 = 1 << 2;
+
+pub const VIRTIO_IOMMU_PROBE_T_RESV_MEM: u16 = 1;
+
+pub const VIRTIO_IOMMU_RESV_MEM_T_RESERVED: u8 = 0;
+pub const VIRTIO_IOMMU_RESV_MEM_T_MSI: u8 = 1;
+
+#[derive(FromBytes, IntoBytes, Immutable, KnownLayout)]
+#[repr(C, packed)]
+pub struct VirtioIommuReqHead {
+    pub r#type: u8,
+    pub reserved: [u8; 3],
+}
+
+#[derive(FromBytes, IntoBytes, Immutable, KnownLayout)]
+#[repr(C, packed)]
+pub struct VirtioIommuReqTail {
+    pub status: u8,
+    pub reserved: [u8; 3],
+}
+
+#[derive(FromBytes, IntoBytes, Immutable, KnownLayout)]
+#[repr(C, packed)]
+pub struct VirtioIommuReqAttach {
+    pub head: VirtioIommuReqHead,
+    pub domain: u32,
+    pub endpoint: u32,
+    pub flags: u32,
+    pub reserved: [u8; 4],
+}
+
+#[derive(FromBytes, IntoBytes, Immutable, KnownLayout)]
+#[repr(C, packed)]
+pub struct VirtioIommuReqDetach {
+    pub head: VirtioIommuReqHead,
+    pub domain: u32,
+    pub endpoint: u32,
+    pub reserved: [u8; 8],
+}
+
+/// `virt_end` and `phys_start + virt_end - virt_start` are inclusive
+#[derive(FromBytes, IntoBytes, Immutable, KnownLayout)]
+#[repr(C, packed)]
+pub struct VirtioIommuReqMap {
+    pub head: VirtioIommuReqHead,
+    pub domain: u32,
+    pub virt_start: u64,
+    pub virt_end: u64,
+    pub phys_start: u64,
+    pub flags: u32,
+}
+
+#[derive(FromBytes, IntoBytes, Immutable, KnownLayout)]
+#[repr(C, packed)]
+pub struct VirtioIommuReqUnmap {
+    pub head: VirtioIommuReqHead,
+    pub domain: u32,
+    pub virt_start: u64,
+    pub virt_end: u64,
+    pub reserved: [u8; 4],
+}
+
+/// Followed by `probe_size` bytes of properties in the device-writable part
+#[derive(FromBytes, IntoBytes, Immutable, KnownLayout)]
+#[repr(C, packed)]
+pub struct VirtioIommuReqProbe {
+    pub head: VirtioIommuReqHead,
+    pub endpoint: u32,
+    pub reserved: [u8; 64],
+}
+
+/// `length` does not include the property header
+#[derive(FromBytes, IntoBytes, Immutable, KnownLayout)]
+#[repr(C, packed)]
+pub struct VirtioIommuProbeResvMem {
+    pub r#type: u16,
+    pub length: u16,
+    pub subtype: u8,
+    pub reserved: [u8; 3],
+    pub start: u64,
+    pub end: u64,
+}
+
+#[derive(Default, FromBytes, IntoBytes, Immutable)]
+#[repr(C, packed)]
+pub struct VirtioIommuConfig {
+    pub page_size_mask: u64,
+    pub input_range_start: u64,
+    pub input_range_end: u64,
+    pub domain_range_start: u32,
+    pub domain_range_end: u32,
+    pub probe_size: u32,
+    pub bypass: u8,
+    pub reserved: [u8; 3],
+}
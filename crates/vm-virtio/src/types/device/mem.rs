@@ -0,0 +1,94 @@
+use strum_macros::FromRepr;
+use zerocopy::FromBytes;
+use zerocopy::Immutable;
+use zerocopy::IntoBytes;
+use zerocopy::KnownLayout;
+
+#[derive(FromRepr)]
+#[repr(u16)]
+pub enum VirtioMemVirtqueue {
+    Guestq = 0,
+}
+
+#[allow(non_camel_case_types)]
+pub enum VirtioMemFeatureBitmap {
+    ACPI_PXM = 0,               /* node_id is an ACPI PXM and is valid */
+    UNPLUGGED_INACCESSIBLE = 1, /* Unplugged memory cannot be accessed */
+}
+
+#[derive(FromRepr)]
+#[repr(u16)]
+pub enum VirtioMemRequestType {
+    Plug = 0,
+    Unplug = 1,
+    UnplugAll = 2,
+    State = 3,
+}
+
+#[derive(Clone, Copy)]
+#[repr(u16)]
+pub enum VirtioMemResponseType {
+    Ack = 0,
+    Nack = 1,
+    Busy = 2,
+    Error = 3,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u16)]
+pub enum VirtioMemBlockState {
+    Plugged = 0,
+    Unplugged = 1,
+    Mixed = 2,
+}
+
+/// `addr` and `nb_blocks` are ignored by VIRTIO_MEM_REQ_UNPLUG_ALL
+#[derive(FromBytes, IntoBytes, Immutable, KnownLayout)]
+#[repr(C, packed)]
+pub struct VirtioMemRequest {
+    pub r#type: u16,
+    pub padding: [u16; 3],
+    pub addr: u64,
+    pub nb_blocks: u16,
+    pub padding_1: [u16; 3],
+}
+
+/// `state` is only valid for VIRTIO_MEM_REQ_STATE
+#[derive(FromBytes, IntoBytes, Immutable)]
+#[repr(C, packed)]
+pub struct VirtioMemResponse {
+    pub r#type: u16,
+    pub padding: [u16; 3],
+    pub state: u16,
+}
+
+impl VirtioMemResponse {
+    pub fn new(r#type: VirtioMemResponseType) -> Self {
+        VirtioMemResponse {
+            r#type: r#type as u16,
+            padding: [0; 3],
+            state: 0,
+        }
+    }
+
+    pub fn state(state: VirtioMemBlockState) -> Self {
+        VirtioMemResponse {
+            r#type: VirtioMemResponseType::Ack as u16,
+            padding: [0; 3],
+            state: state as u16,
+        }
+    }
+}
+
+#[derive(Default, FromBytes, IntoBytes, Immutable)]
+#[repr(C, packed)]
+pub struct VirtioMemConfig {
+    pub block_size: u64,
+    pub node_id: u16,
+    pub padding: [u8; 6],
+    pub addr: u64,
+    pub region_size: u64,
+    pub usable_region_size: u64,
+    pub plugged_size: u64,
+    pub requested_size: u64,
+}
@@ -0,0 +1,107 @@
+use strum_macros::FromRepr;
+use zerocopy::FromBytes;
+use zerocopy::Immutable;
+use zerocopy::IntoBytes;
+
+/// The mount tag is available in the config space.
+pub const VIRTIO_9P_MOUNT_TAG: u32 = 0;
+
+pub const VIRTIO_9P_MAX_TAG_LEN: usize = 32;
+
+#[derive(FromRepr)]
+#[repr(u16)]
+pub enum VirtioP9Virtqueue {
+    Requestq = 0,
+}
+
+#[derive(FromBytes, IntoBytes, Immutable)]
+#[repr(C, packed)]
+pub struct VirtioP9Config {
+    pub tag_len: u16,
+    pub tag: [u8; VIRTIO_9P_MAX_TAG_LEN],
+}
+
+/// Message types of 9P2000.L
+#[derive(Clone, Copy, Debug, FromRepr)]
+#[repr(u8)]
+pub enum P9MessageType {
+    Rlerror = 7,
+    Tstatfs = 8,
+    Rstatfs = 9,
+    Tlopen = 12,
+    Rlopen = 13,
+    Tlcreate = 14,
+    Rlcreate = 15,
+    Tsymlink = 16,
+    Rsymlink = 17,
+    Tmknod = 18,
+    Rmknod = 19,
+    Trename = 20,
+    Rrename = 21,
+    Treadlink = 22,
+    Rreadlink = 23,
+    Tgetattr = 24,
+    Rgetattr = 25,
+    Tsetattr = 26,
+    Rsetattr = 27,
+    Txattrwalk = 30,
+    Rxattrwalk = 31,
+    Txattrcreate = 32,
+    Rxattrcreate = 33,
+    Treaddir = 40,
+    Rreaddir = 41,
+    Tfsync = 50,
+    Rfsync = 51,
+    Tlock = 52,
+    Rlock = 53,
+    Tgetlock = 54,
+    Rgetlock = 55,
+    Tlink = 70,
+    Rlink = 71,
+    Tmkdir = 72,
+    Rmkdir = 73,
+    Trenameat = 74,
+    Rrenameat = 75,
+    Tunlinkat = 76,
+    Runlinkat = 77,
+    Tversion = 100,
+    Rversion = 101,
+    Tauth = 102,
+    Rauth = 103,
+    Tattach = 104,
+    Rattach = 105,
+    Tflush = 108,
+    Rflush = 109,
+    Twalk = 110,
+    Rwalk = 111,
+    Tread = 116,
+    Rread = 117,
+    Twrite = 118,
+    Rwrite = 119,
+    Tclunk = 120,
+    Rclunk = 121,
+    Tremove = 122,
+    Rremove = 123,
+}
+
+pub const P9_QTDIR: u8 = 0x80;
+pub const P9_QTSYMLINK: u8 = 0x02;
+pub const P9_QTFILE: u8 = 0x00;
+
+/// Mask of the fields filled by Rgetattr.
+pub const P9_GETATTR_BASIC: u64 = 0x000007ff;
+
+pub const P9_SETATTR_MODE: u32 = 0x00000001;
+pub const P9_SETATTR_UID: u32 = 0x00000002;
+pub const P9_SETATTR_GID: u32 = 0x00000004;
+pub const P9_SETATTR_SIZE: u32 = 0x00000008;
+pub const P9_SETATTR_ATIME: u32 = 0x00000010;
+pub const P9_SETATTR_MTIME: u32 = 0x00000020;
+pub const P9_SETATTR_ATIME_SET: u32 = 0x00000080;
+pub const P9_SETATTR_MTIME_SET: u32 = 0x00000100;
+
+pub const P9_LOCK_SUCCESS: u8 = 0;
+pub const P9_LOCK_TYPE_UNLCK: u8 = 2;
+
+/// Flag of Tunlinkat
+pub const P9_AT_REMOVEDIR: u32 = 0x200;
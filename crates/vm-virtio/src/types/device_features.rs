@@ -1 +1,3 @@
 pub const VIRTIO_F_VERSION_1: u32 = 32;
+/// The device accesses memory through the platform iommu
+pub const VIRTIO_F_ACCESS_PLATFORM: u32 = 33;
@@ -2,5 +2,8 @@ pub enum DeviceId {
     Blk = 2,
     Entropy = 4,
     Balloon = 5,
+    P9 = 9,
     Gpu = 16,
+    Iommu = 23,
+    Mem = 24,
 }
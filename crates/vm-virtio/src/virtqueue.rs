@@ -1,5 +1,6 @@
 use std::io::Read;
 use std::io::Write;
+use std::sync::Arc;
 
 use vm_core::device::error::DeviceSnapshotError;
 use vm_mm::manager::MemoryAddressSpace;
@@ -10,6 +11,8 @@ use vm_snapshot::helper::write_bool;
 use vm_snapshot::helper::write_u16;
 use vm_snapshot::helper::write_u32;
 
+use crate::dma::DmaAccess;
+use crate::dma::DmaTranslator;
 use crate::result::VirtioError;
 use crate::virtqueue::virtq_avail_ring::VirtqAvail;
 use crate::virtqueue::virtq_desc_table::VirtqDescTableRef;
@@ -23,6 +26,25 @@ fn to_gpa(high: u32, low: u32) -> u64 {
     ((high as u64) << 32) + (low as u64)
 }
 
+/// The ring addresses are iovas if the device is behind an iommu
+fn ring_to_hva(
+    mm: &MemoryAddressSpace,
+    translator: Option<&Arc<dyn DmaTranslator>>,
+    addr: u64,
+    len: u64,
+    access: DmaAccess,
+) -> Result<*mut u8, VirtioError> {
+    let gpa = match translator {
+        Some(translator) => translator
+            .translate(addr, len, access)
+            .ok_or(VirtioError::AccessInvalidGpa(addr))?,
+        None => addr,
+    };
+
+    mm.gpa_to_hva(gpa)
+        .map_err(|_| VirtioError::AccessInvalidGpa(gpa))
+}
+
 #[derive(Clone, Copy)]
 pub struct Virtqueue {
     queue_size_max: u16,
@@ -109,29 +131,50 @@ impl Virtqueue {
     pub fn desc_table_ref(
         &self,
         mm: &MemoryAddressSpace,
+        translator: Option<Arc<dyn DmaTranslator>>,
     ) -> Result<VirtqDescTableRef, VirtioError> {
-        let gpa = self.queue_desc_table_gpa();
-        let hva = mm
-            .gpa_to_hva(gpa)
-            .map_err(|_| VirtioError::AccessInvalidGpa(gpa))?;
+        let len = 16 * self.queue_size as u64;
+        let hva = ring_to_hva(
+            mm,
+            translator.as_ref(),
+            self.queue_desc_table_gpa(),
+            len,
+            DmaAccess::Read,
+        )?;
 
-        Ok(VirtqDescTableRef::new(self.queue_size, hva))
+        Ok(VirtqDescTableRef::new(self.queue_size, hva, translator))
     }
 
-    pub fn avail_ring(&self, mm: &MemoryAddressSpace) -> Result<VirtqAvail, VirtioError> {
-        let gpa = self.queue_available_ring_gpa();
-        let hva = mm
-            .gpa_to_hva(gpa)
-            .map_err(|_| VirtioError::AccessInvalidGpa(gpa))?;
+    pub fn avail_ring(
+        &self,
+        mm: &MemoryAddressSpace,
+        translator: Option<&Arc<dyn DmaTranslator>>,
+    ) -> Result<VirtqAvail, VirtioError> {
+        let len = 6 + 2 * self.queue_size as u64;
+        let hva = ring_to_hva(
+            mm,
+            translator,
+            self.queue_available_ring_gpa(),
+            len,
+            DmaAccess::Read,
+        )?;
 
         Ok(VirtqAvail::new(self.queue_size, hva as *const u16))
     }
 
-    pub fn used_ring(&self, mm: &MemoryAddressSpace) -> Result<VirtqUsed, VirtioError> {
-        let gpa = self.queue_used_ring_gpa();
-        let hva = mm
-            .gpa_to_hva(gpa)
-            .map_err(|_| VirtioError::AccessInvalidGpa(gpa))?;
+    pub fn used_ring(
+        &self,
+        mm: &MemoryAddressSpace,
+        translator: Option<&Arc<dyn DmaTranslator>>,
+    ) -> Result<VirtqUsed, VirtioError> {
+        let len = 6 + 8 * self.queue_size as u64;
+        let hva = ring_to_hva(
+            mm,
+            translator,
+            self.queue_used_ring_gpa(),
+            len,
+            DmaAccess::Write,
+        )?;
 
         Ok(VirtqUsed::new(self.queue_size, hva))
     }
@@ -1,12 +1,16 @@
 use std::ptr::NonNull;
 use std::slice;
+use std::sync::Arc;
 
+use tracing::warn;
 use vm_mm::manager::MemoryAddressSpace;
 use zerocopy::FromBytes;
 use zerocopy::Immutable;
 use zerocopy::IntoBytes;
 use zerocopy::KnownLayout;
 
+use crate::dma::DmaAccess;
+use crate::dma::DmaTranslator;
 use crate::result::Result;
 use crate::result::VirtioError;
 
@@ -17,7 +21,7 @@ pub const VIRTQ_DESC_F_WRITE: u16 = 2;
 /// This means the buffer contains a list of buffer descriptors.
 pub const VIRTQ_DESC_F_INDIRECT: u16 = 4;
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 #[repr(C, packed)]
 pub struct VirtqDesc {
     /// Address (guest-physical).
@@ -31,6 +35,20 @@ pub struct VirtqDesc {
 }
 
 impl VirtqDesc {
+    pub fn new(addr: u64, len: u32, flags: u16, next: u16) -> Self {
+        VirtqDesc {
+            addr,
+            len,
+            flags,
+            next,
+        }
+    }
+
+    /// Get gpa of the buf
+    pub fn gpa(&self) -> u64 {
+        self.addr
+    }
+
     /// Get hva of the buf
     pub fn addr(&self, mm: &MemoryAddressSpace) -> Result<NonNull<u8>> {
         let addr = mm
@@ -39,7 +57,7 @@ impl VirtqDesc {
         NonNull::new(addr).ok_or(VirtioError::AccessInvalidGpa(self.addr))
     }
 
-    pub fn as_ref<T>(&self, memory: &MemoryAddressSpace) -> Result<&T>
+    pub fn as_ref<'a, T>(&self, memory: &'a MemoryAddressSpace) -> Result<&'a T>
     where
         T: FromBytes + KnownLayout + Immutable,
     {
@@ -53,7 +71,7 @@ impl VirtqDesc {
 
     // TODO: Refine virtqueue API
     #[allow(clippy::mut_from_ref)]
-    pub fn as_mut<T>(&self, memory: &MemoryAddressSpace) -> Result<&mut T>
+    pub fn as_mut<'a, T>(&self, memory: &'a MemoryAddressSpace) -> Result<&'a mut T>
     where
         T: FromBytes + IntoBytes + KnownLayout,
     {
@@ -65,7 +83,7 @@ impl VirtqDesc {
         Ok(t)
     }
 
-    pub fn as_slice<T>(&self, memory: &MemoryAddressSpace, len: usize) -> Result<Vec<&T>>
+    pub fn as_slice<'a, T>(&self, memory: &'a MemoryAddressSpace, len: usize) -> Result<Vec<&'a T>>
     where
         T: FromBytes + KnownLayout + Immutable,
     {
@@ -98,15 +116,21 @@ impl VirtqDesc {
 pub struct VirtqDescTableRef {
     queue_size: u16,
     table: *mut VirtqDesc,
+    translator: Option<Arc<dyn DmaTranslator>>,
 }
 unsafe impl Send for VirtqDescTableRef {}
 unsafe impl Sync for VirtqDescTableRef {}
 
 impl VirtqDescTableRef {
-    pub fn new(queue_size: u16, table: *mut u8) -> Self {
+    pub fn new(
+        queue_size: u16,
+        table: *mut u8,
+        translator: Option<Arc<dyn DmaTranslator>>,
+    ) -> Self {
         VirtqDescTableRef {
             queue_size,
             table: table as *mut VirtqDesc,
+            translator,
         }
     }
 
@@ -114,28 +138,32 @@ impl VirtqDescTableRef {
         self.queue_size
     }
 
-    pub fn get(&self, idx: u16) -> &VirtqDesc {
-        unsafe { &*self.table.add(idx as usize) }
-    }
-
-    pub fn get_mut(&mut self, idx: u16) -> &mut VirtqDesc {
-        unsafe { &mut *self.table.add(idx as usize) }
-    }
-
-    pub fn get_chain(&self, first_idx: u16) -> Vec<&VirtqDesc> {
-        let mut descs = vec![];
-
-        let mut curr = self.get(first_idx);
-        descs.push(curr);
-        while curr.flags & VIRTQ_DESC_F_NEXT != 0 {
-            curr = self.get(curr.next);
-            descs.push(curr);
+    /// Returns a copy of the descriptor, the buffer address is translated to a gpa if the
+    /// device is behind an iommu.
+    pub fn get(&self, idx: u16) -> VirtqDesc {
+        let mut desc = unsafe { self.table.add(idx as usize).read_unaligned() };
+
+        if let Some(translator) = &self.translator {
+            let iova = desc.addr;
+            let len = desc.len;
+            let access = if desc.flags & VIRTQ_DESC_F_WRITE != 0 {
+                DmaAccess::Write
+            } else {
+                DmaAccess::Read
+            };
+            desc.addr = translator
+                .translate(iova, len as u64, access)
+                .unwrap_or_else(|| {
+                    warn!(iova, len, "dma to an unmapped iova");
+                    // No gpa is mapped at the top of the address space, so the access fails
+                    u64::MAX
+                });
         }
 
-        descs
+        desc
     }
 
-    pub fn get_chain_mut(&self, first_idx: u16) -> Vec<&VirtqDesc> {
+    pub fn get_chain(&self, first_idx: u16) -> Vec<VirtqDesc> {
         let mut descs = vec![];
 
         let mut curr = self.get(first_idx);
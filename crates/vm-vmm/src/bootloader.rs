@@ -1,3 +1,19 @@
+use tokio::sync::Mutex;
+use vm_bootloader::boot_loader::BootLoader;
+use vm_bootloader::boot_loader::firmware::FirmwareBootLoader;
+use vm_core::arch::irq::InterruptController;
+use vm_core::cpu::vcpu_manager::VcpuManager;
+use vm_mm::manager::MemoryAddressSpace;
+use vm_utils::range_allocator::RangeAllocator;
+
+#[cfg(target_arch = "aarch64")]
+use crate::bootloader::aarch64::install_bootloader;
+use crate::bootloader::error::BootloaderError;
+#[cfg(target_arch = "x86_64")]
+use crate::bootloader::x86_64::install_bootloader;
+use crate::device::device_manager_v2::DeviceManagerV2;
+use crate::vm::config::VmConfig;
+
 #[cfg(target_arch = "x86_64")]
 pub(crate) mod x86_64;
 
@@ -5,3 +21,70 @@ pub(crate) mod x86_64;
 pub(crate) mod aarch64;
 
 pub mod error;
+
+/// The flash regions of the firmware must have been mapped
+pub async fn install_firmware(
+    firmware: &FirmwareBootLoader,
+    vm_config: &VmConfig,
+    vcpu_manager: &Mutex<VcpuManager>,
+    ram_allocator: &mut RangeAllocator<u64>,
+    memory_address_space: &MemoryAddressSpace,
+    irq_chip: &dyn InterruptController,
+    device_manager: &DeviceManagerV2,
+) -> Result<(), BootloaderError> {
+    let mut vcpu_manager = vcpu_manager.lock().await;
+
+    let boot_vcpu = vcpu_manager.get_vcpu_mut(0)?;
+    firmware
+        .load(
+            vm_config.memory_size as u64,
+            &vm_config.cpu_topology(),
+            boot_vcpu,
+            ram_allocator,
+            memory_address_space,
+            irq_chip,
+            device_manager.iter(),
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Installs the firmware if there is one, the kernel otherwise
+pub async fn install_boot_source(
+    firmware: Option<&FirmwareBootLoader>,
+    vm_config: &VmConfig,
+    vcpu_manager: &Mutex<VcpuManager>,
+    ram_allocator: &mut RangeAllocator<u64>,
+    memory_address_space: &MemoryAddressSpace,
+    irq_chip: &dyn InterruptController,
+    device_manager: &DeviceManagerV2,
+) -> Result<(), BootloaderError> {
+    match (firmware, vm_config.kernel.clone()) {
+        (Some(firmware), _) => {
+            install_firmware(
+                firmware,
+                vm_config,
+                vcpu_manager,
+                ram_allocator,
+                memory_address_space,
+                irq_chip,
+                device_manager,
+            )
+            .await
+        }
+        (None, Some(kernel)) => {
+            install_bootloader(
+                vm_config,
+                kernel,
+                vcpu_manager,
+                ram_allocator,
+                memory_address_space,
+                irq_chip,
+                device_manager,
+            )
+            .await
+        }
+        (None, None) => unreachable!(),
+    }
+}
@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use tokio::sync::Mutex;
 use vm_bootloader::boot_loader::BootLoader;
 use vm_bootloader::boot_loader::BootLoaderBuilder;
@@ -13,6 +15,7 @@ use crate::vm::config::VmConfig;
 
 pub async fn install_bootloader(
     vm_config: &VmConfig,
+    kernel: PathBuf,
     vcpu_manager: &Mutex<VcpuManager>,
     ram_allocator: &mut RangeAllocator<u64>,
     memory_address_space: &MemoryAddressSpace,
@@ -20,10 +23,13 @@ pub async fn install_bootloader(
     device_manager: &DeviceManagerV2,
 ) -> Result<(), BootloaderError> {
     let bootloader = AArch64BootLoader::new(
-        vm_config.kernel.clone(),
+        kernel,
         vm_config.initramfs.clone(),
         vm_config.cmdline.clone(),
-    );
+    )
+    .with_acpi(vm_config.acpi)
+    .with_numa_nodes(vm_config.numa_nodes())
+    .with_present_vcpus(vm_config.vcpus as u32);
 
     let mut vcpu_manager = vcpu_manager.lock().await;
 
@@ -31,7 +37,7 @@ pub async fn install_bootloader(
     bootloader
         .load(
             vm_config.memory_size as u64,
-            vm_config.vcpus,
+            &vm_config.cpu_topology(),
             boot_vcpu,
             ram_allocator,
             memory_address_space,
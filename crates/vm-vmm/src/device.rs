@@ -1,2 +1,3 @@
 pub(crate) mod device_manager_v2;
 pub(crate) mod error;
+pub(crate) mod pci_hotplug;
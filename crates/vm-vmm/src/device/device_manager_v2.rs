@@ -1,12 +1,17 @@
 use std::slice::Iter;
 use std::slice::IterMut;
+use std::sync::Arc;
 
 use rangemap::RangeMap;
 use tracing::trace;
 use vm_core::cpu::vm_exit::VmExitHandlerError;
 use vm_core::device::Device;
+use vm_core::device::cpu_hotplug::CpuHotplug;
+use vm_core::device::power_button::PowerButton;
+use vm_device::device::virtio::virtio_mem::device::VirtioMemSystemReset;
 
 use crate::device::error::InitDeviceError;
+use crate::device::pci_hotplug::PciHotplugManager;
 
 pub(crate) mod snapshot;
 
@@ -17,6 +22,12 @@ pub struct DeviceManagerV2 {
     #[cfg(target_arch = "x86_64")]
     pio_dispatcher: RangeMap<u16, usize>,
     mmio_dispatcher: RangeMap<u64, usize>,
+
+    power_button: Option<Arc<dyn PowerButton>>,
+    pci_hotplug: Option<PciHotplugManager>,
+    cpu_hotplug: Option<Arc<dyn CpuHotplug>>,
+    /// Unplug the hotplugged memory on a system reset
+    virtio_mem_resets: Vec<VirtioMemSystemReset>,
 }
 
 impl DeviceManagerV2 {
@@ -113,6 +124,38 @@ impl DeviceManagerV2 {
         Ok(())
     }
 
+    pub fn set_power_button(&mut self, power_button: Arc<dyn PowerButton>) {
+        self.power_button = Some(power_button);
+    }
+
+    pub fn power_button(&self) -> Option<&Arc<dyn PowerButton>> {
+        self.power_button.as_ref()
+    }
+
+    pub fn set_pci_hotplug(&mut self, pci_hotplug: PciHotplugManager) {
+        self.pci_hotplug = Some(pci_hotplug);
+    }
+
+    pub fn pci_hotplug(&self) -> Option<&PciHotplugManager> {
+        self.pci_hotplug.as_ref()
+    }
+
+    pub fn set_cpu_hotplug(&mut self, cpu_hotplug: Arc<dyn CpuHotplug>) {
+        self.cpu_hotplug = Some(cpu_hotplug);
+    }
+
+    pub fn cpu_hotplug(&self) -> Option<&Arc<dyn CpuHotplug>> {
+        self.cpu_hotplug.as_ref()
+    }
+
+    pub fn add_virtio_mem_reset(&mut self, reset: VirtioMemSystemReset) {
+        self.virtio_mem_resets.push(reset);
+    }
+
+    pub fn virtio_mem_resets(&self) -> &[VirtioMemSystemReset] {
+        &self.virtio_mem_resets
+    }
+
     pub fn iter(&self) -> Iter<'_, Box<dyn Device>> {
         self.devices.iter()
     }
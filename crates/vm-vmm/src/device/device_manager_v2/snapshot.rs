@@ -1,44 +1,44 @@
-use std::collections::BTreeMap;
-use std::io::Cursor;
-
 use serde::Deserialize;
 use serde::Serialize;
-use tracing::warn;
 use vm_core::device::error::DeviceSnapshotError;
+use vm_snapshot::section::Sections;
 
 use crate::device::device_manager_v2::DeviceManagerV2;
 
 #[derive(Serialize, Deserialize)]
 pub struct DeviceSnapshot {
-    // TODO: use name is not elegant
-    devices: BTreeMap<String, Vec<u8>>,
+    sections: Sections,
+}
+
+impl DeviceSnapshot {
+    pub fn sections(&self) -> &Sections {
+        &self.sections
+    }
 }
 
 impl DeviceManagerV2 {
     pub fn build_snapshot(&self) -> Result<DeviceSnapshot, DeviceSnapshotError> {
-        let mut devices = BTreeMap::default();
+        let mut sections = Sections::default();
 
         for device in self.iter() {
-            let mut buf = vec![];
-            device.save(&mut buf)?;
-            let old = devices.insert(device.name(), buf);
-
-            assert!(old.is_none());
+            device.save_sections(&mut sections)?;
         }
 
-        let snap = DeviceSnapshot { devices };
+        let snap = DeviceSnapshot { sections };
 
         Ok(snap)
     }
 
+    /// Every device must find its section and every section must be taken by a device
     pub fn install_snapshot(&mut self, snap: DeviceSnapshot) -> Result<(), DeviceSnapshotError> {
+        let mut sections = snap.sections;
+
         for device in &mut self.iter_mut() {
-            let Some(buf) = snap.devices.get(&device.name()) else {
-                warn!(name = device.name(), "device snapshot not found, skipped");
-                continue;
-            };
+            device.load_sections(&mut sections)?;
+        }
 
-            device.load(&mut Cursor::new(buf))?;
+        if let Some(id) = sections.into_keys().next() {
+            return Err(DeviceSnapshotError::UnknownSection(id));
         }
 
         Ok(())
@@ -35,6 +35,12 @@ pub enum InitDeviceError {
     #[error("Failed to register virtio device, err: {0}")]
     Virtio(#[from] VirtioError),
 
+    #[error("No hotplug memory region is reserved for the virtio-mem device")]
+    MemoryHotplugRegionNotReserved,
+
+    #[error("Failed to put the device behind the iommu, {0}")]
+    IommuEndpoint(Box<dyn Error + Send + Sync>),
+
     #[error("Vfio not support")]
     VfioNotSupport,
 
@@ -44,6 +50,9 @@ pub enum InitDeviceError {
     #[error("Vfio container does not init")]
     VfioContainerNotInit,
 
+    #[error("Memory at {gpa:#x} can not be shared with the vfio-user server")]
+    VfioUserMemoryNotShared { gpa: u64 },
+
     #[cfg(target_os = "linux")]
     #[error("Vfio error: {0} ")]
     Vfio(#[from] vm_vfio::error::Error),
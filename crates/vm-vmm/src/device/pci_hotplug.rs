@@ -0,0 +1,595 @@
+use std::ops::Range;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use serde::Deserialize;
+use tracing::info;
+use vm_core::arch::irq::InterruptController;
+use vm_core::device::system_event::SystemEvent;
+use vm_core::device::system_event::SystemEventNotifier;
+use vm_core::interrupt_manager::InterruptManager;
+#[cfg(target_os = "linux")]
+use vm_core::virtualization::vm::HypervisorVm;
+use vm_device::device::Device;
+use vm_device::device::VirtioTransport;
+use vm_device::device::virtio::virtio_9p::Virtio9p;
+use vm_device::device::virtio::virtio_blk::VirtioBlkDevice;
+use vm_device::device::virtio::virtio_entropy::VirtioEntropy;
+use vm_device::device::virtio::virtio_gpu::VirtioGpu;
+use vm_mm::manager::MemoryAddressSpace;
+use vm_pci::hotplug::PcieHotplugSlot;
+use vm_pci::types::device::PciDevice;
+use vm_utils::range_allocator::RangeAllocator;
+use vm_virtio::transport::pci::VirtioPciDevice;
+
+use crate::device::error::InitDeviceError;
+use crate::vmm::error::PciHotplugError;
+
+/// Memory window of each hot-plug slot, 1M aligned as the bridge windows
+pub const PCI_HOTPLUG_SLOT_MMIO_SIZE: usize = 16 << 20;
+
+/// Argument of `device_add`, e.g.
+/// `{"id": "disk0", "VirtioBlk": {"transport": "Pci", "path": "disk.img"}}`
+#[derive(Deserialize)]
+pub struct DeviceAddArgs {
+    pub id: String,
+    #[serde(flatten)]
+    pub device: Device,
+}
+
+struct HotplugSlot {
+    slot: Arc<PcieHotplugSlot>,
+    mmio_window: Range<u64>,
+    /// Id and config of the plugged device, kept until the guest has released the slot
+    device: Mutex<Option<(String, Device)>>,
+}
+
+pub struct PciHotplugManager {
+    #[cfg(target_os = "linux")]
+    vm: Arc<dyn HypervisorVm>,
+    interrupt_manager: Arc<InterruptManager>,
+    irq_chip: Arc<dyn InterruptController>,
+    memory: Arc<MemoryAddressSpace>,
+    system_event_notifier: Arc<dyn SystemEventNotifier>,
+    slots: Vec<HotplugSlot>,
+}
+
+impl PciHotplugManager {
+    pub fn new(
+        #[cfg(target_os = "linux")] vm: Arc<dyn HypervisorVm>,
+        interrupt_manager: Arc<InterruptManager>,
+        irq_chip: Arc<dyn InterruptController>,
+        memory: Arc<MemoryAddressSpace>,
+        system_event_notifier: Arc<dyn SystemEventNotifier>,
+    ) -> Self {
+        PciHotplugManager {
+            #[cfg(target_os = "linux")]
+            vm,
+            interrupt_manager,
+            irq_chip,
+            memory,
+            system_event_notifier,
+            slots: Vec::new(),
+        }
+    }
+
+    pub fn add_slot(&mut self, slot: Arc<PcieHotplugSlot>, mmio_window: Range<u64>) {
+        self.slots.push(HotplugSlot {
+            slot,
+            mmio_window,
+            device: Default::default(),
+        });
+    }
+
+    fn wrap_pci_device<D>(
+        &self,
+        dev: D,
+        mmio_window: &Range<u64>,
+    ) -> Result<Box<dyn PciDevice>, InitDeviceError>
+    where
+        D: VirtioPciDevice,
+    {
+        // The root port has no io window nor prefetchable window
+        #[cfg(target_arch = "x86_64")]
+        let mut pci_pio_allocator = RangeAllocator::<u16>::default();
+        let mut pci_mmio64_allocator = RangeAllocator::<u64>::default();
+        let mut pci_mmio_allocator = RangeAllocator::<u64>::default();
+        pci_mmio_allocator
+            .insert(
+                mmio_window.start,
+                (mmio_window.end - mmio_window.start) as usize,
+            )
+            .map_err(|err| InitDeviceError::AllocResource(Box::new(err)))?;
+
+        Ok(Box::new(dev.into_pci_device(
+            #[cfg(target_os = "linux")]
+            self.vm.clone(),
+            #[cfg(target_arch = "x86_64")]
+            &mut pci_pio_allocator,
+            &mut pci_mmio_allocator,
+            &mut pci_mmio64_allocator,
+            &self.interrupt_manager,
+            tokio::runtime::Handle::current(),
+            self.memory.clone(),
+            self.irq_chip.clone(),
+        )?))
+    }
+
+    fn new_device(
+        &self,
+        device: &Device,
+        mmio_window: &Range<u64>,
+    ) -> Result<Box<dyn PciDevice>, PciHotplugError> {
+        let device = match device {
+            Device::VirtioBlk {
+                transport: VirtioTransport::Pci,
+                path,
+                read_only,
+            } => {
+                let dev = VirtioBlkDevice::new(self.memory.clone(), path.clone(), *read_only)
+                    .map_err(InitDeviceError::from)?;
+
+                self.wrap_pci_device(dev, mmio_window)?
+            }
+            Device::VirtioEntropy {
+                transport: VirtioTransport::Pci,
+            } => self.wrap_pci_device(VirtioEntropy::new(self.memory.clone()), mmio_window)?,
+            Device::VirtioGpu {
+                transport: VirtioTransport::Pci,
+            } => self.wrap_pci_device(VirtioGpu::new(self.memory.clone()), mmio_window)?,
+            Device::Virtio9p {
+                transport: VirtioTransport::Pci,
+                tag,
+                path,
+                read_only,
+                uid_map,
+                gid_map,
+            } => {
+                let dev = Virtio9p::new(
+                    self.memory.clone(),
+                    tag,
+                    path.clone(),
+                    *read_only,
+                    uid_map.clone(),
+                    gid_map.clone(),
+                )
+                .map_err(InitDeviceError::from)?;
+
+                self.wrap_pci_device(dev, mmio_window)?
+            }
+            _ => return Err(PciHotplugError::NotHotpluggable),
+        };
+
+        Ok(device)
+    }
+
+    fn plugged_id(slot: &HotplugSlot) -> Option<String> {
+        slot.device
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|(id, _)| id.clone())
+    }
+
+    pub fn device_add(&self, id: String, device: &Device) -> Result<(), PciHotplugError> {
+        if self
+            .slots
+            .iter()
+            .any(|slot| Self::plugged_id(slot).as_ref() == Some(&id))
+        {
+            return Err(PciHotplugError::DuplicateId(id));
+        }
+
+        let slot = self
+            .slots
+            .iter()
+            .find(|slot| slot.device.lock().unwrap().is_none())
+            .ok_or(PciHotplugError::NoFreeSlot)?;
+
+        let pci_device = self.new_device(device, &slot.mmio_window)?;
+        slot.slot
+            .plug(pci_device)
+            .map_err(|_| InitDeviceError::RegisterPciDevice)?;
+
+        info!(%id, "pci device plugged");
+        *slot.device.lock().unwrap() = Some((id, device.clone()));
+
+        Ok(())
+    }
+
+    /// Ask the guest to release the device, it is removed once the guest is done with it
+    pub fn device_del(&self, id: &str) -> Result<(), PciHotplugError> {
+        let (index, slot) = self
+            .slots
+            .iter()
+            .enumerate()
+            .find(|(_, slot)| Self::plugged_id(slot).as_deref() == Some(id))
+            .ok_or_else(|| PciHotplugError::DeviceNotFound(id.to_string()))?;
+
+        let tokio_runtime = tokio::runtime::Handle::current();
+        let pcie_slot = slot.slot.clone();
+        let system_event_notifier = self.system_event_notifier.clone();
+
+        // Called from the ecam write of the guest, the root complex is still locked
+        slot.slot.request_unplug(Box::new(move || {
+            tokio_runtime.spawn(async move {
+                pcie_slot.remove_device();
+
+                system_event_notifier.notify(SystemEvent::PciDeviceReleased(index as u32));
+            });
+        }));
+
+        Ok(())
+    }
+
+    /// Frees the slot whose device has been removed, returns the id and config of that device
+    pub fn device_released(&self, slot: usize) -> Option<(String, Device)> {
+        let (id, device) = self.slots.get(slot)?.device.lock().unwrap().take()?;
+        info!(%id, "pci device unplugged");
+
+        Some((id, device))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::io::Write;
+
+    use tokio::runtime::Runtime;
+    use tokio::sync::mpsc;
+    use tokio::sync::mpsc::UnboundedReceiver;
+    use tokio::sync::mpsc::UnboundedSender;
+    use vm_core::arch::irq::Phandle;
+    use vm_core::arch::irq::error::IrqChipError;
+    use vm_core::cpu::vm_exit::VmExit;
+    use vm_core::device::Device as VmDevice;
+    use vm_core::virtualization::vcpu::HypervisorVcpu;
+    #[cfg(target_os = "linux")]
+    use vm_core::virtualization::vm::IoEventAddress;
+    #[cfg(target_os = "linux")]
+    use vm_core::virtualization::vm::IoEventDatamatch;
+    use vm_core::virtualization::vm::SetUserMemoryRegionFlags;
+    use vm_core::virtualization::vm::error::VmError;
+    use vm_fdt::FdtWriter;
+    use vm_pci::device::capability::PciCapId;
+    use vm_pci::device::capability::pcie::PCI_EXP_SLTCTL_ABPE;
+    use vm_pci::device::capability::pcie::PCI_EXP_SLTCTL_HPIE;
+    use vm_pci::device::capability::pcie::PCI_EXP_SLTCTL_PWR_IND_OFF;
+    use vm_pci::device::capability::pcie::PCI_EXP_SLTSTA_ABP;
+    use vm_pci::device::capability::pcie::PCI_EXP_SLTSTA_PDS;
+    use vm_pci::root_complex_device::PciRootComplexDevice;
+    use vm_pci::types::function::PciFunction;
+    use vm_utils::cpu_topology::CpuTopology;
+    #[cfg(target_os = "linux")]
+    use vmm_sys_util::eventfd::EventFd;
+
+    use super::*;
+
+    const ECAM_START: u64 = 0x3000_0000;
+    const SLOT_MMIO_WINDOW: Range<u64> = 0x4f00_0000..0x5000_0000;
+    /// Offset of the slot control and status registers in the pci express capability
+    const SLTCTL_OFFSET: u16 = 0x18;
+    const SLTSTA_OFFSET: u16 = 0x1a;
+
+    struct NoVm;
+
+    impl HypervisorVm for NoVm {
+        fn create_vcpu(
+            &self,
+            _vcpu_id: u64,
+            _cpu_topology: &CpuTopology,
+            _mm: Arc<MemoryAddressSpace>,
+            _vm_exit_handler: Arc<dyn VmExit>,
+        ) -> Result<Box<dyn HypervisorVcpu>, VmError> {
+            unreachable!()
+        }
+
+        fn create_irq_chip(&self) -> Result<Box<dyn InterruptController>, VmError> {
+            unreachable!()
+        }
+
+        fn create_irq_manager(&self) -> Result<InterruptManager, VmError> {
+            unreachable!()
+        }
+
+        fn set_user_memory_region(
+            &self,
+            _userspace_addr: u64,
+            _guest_phys_addr: u64,
+            _memory_size: usize,
+            _flags: SetUserMemoryRegionFlags,
+        ) -> Result<(), VmError> {
+            unreachable!()
+        }
+
+        fn remove_user_memory_region(
+            &self,
+            _guest_phys_addr: u64,
+            _memory_size: usize,
+        ) -> Result<(), VmError> {
+            unreachable!()
+        }
+
+        #[cfg(target_os = "linux")]
+        fn set_irqfd(&self, _fd: &EventFd, _gsi: u32) -> Result<(), VmError> {
+            unreachable!()
+        }
+
+        #[cfg(target_os = "linux")]
+        fn del_irqfd(&self, _fd: &EventFd, _gsi: u32) -> Result<(), VmError> {
+            unreachable!()
+        }
+
+        #[cfg(target_os = "linux")]
+        fn set_irqfd_with_resample(
+            &self,
+            _fd: &EventFd,
+            _resamplefd: &EventFd,
+            _gsi: u32,
+        ) -> Result<(), VmError> {
+            unreachable!()
+        }
+
+        #[cfg(target_os = "linux")]
+        fn set_gsi_routing(&self) -> Result<(), VmError> {
+            unreachable!()
+        }
+
+        #[cfg(target_os = "linux")]
+        fn register_ioeventfd(
+            &self,
+            _fd: &EventFd,
+            _addr: IoEventAddress,
+            _datamatch: IoEventDatamatch,
+        ) -> Result<(), VmError> {
+            unreachable!()
+        }
+
+        #[cfg(target_os = "linux")]
+        fn unregister_ioeventfd(
+            &self,
+            _fd: &EventFd,
+            _addr: IoEventAddress,
+            _datamatch: IoEventDatamatch,
+        ) -> Result<(), VmError> {
+            unreachable!()
+        }
+
+        fn secondary_cpu_should_run_on_booting(&self) -> bool {
+            false
+        }
+    }
+
+    struct IrqChip;
+
+    impl InterruptController for IrqChip {
+        fn trigger_irq(&self, _irq_line: u32, _active: bool) {}
+
+        fn send_msi(&self, _address_lo: u32, _address_hi: u32, _data: u32) {
+            unreachable!()
+        }
+
+        fn write_device_tree(&self, _fdt: &mut FdtWriter) -> Result<Phandle, IrqChipError> {
+            unreachable!()
+        }
+
+        fn save(&self, _write: &mut dyn Write) -> Result<(), IrqChipError> {
+            unreachable!()
+        }
+
+        fn load(&mut self, _read: &mut dyn Read) -> Result<(), IrqChipError> {
+            unreachable!()
+        }
+    }
+
+    struct Events(UnboundedSender<SystemEvent>);
+
+    impl SystemEventNotifier for Events {
+        fn notify(&self, event: SystemEvent) {
+            self.0.send(event).unwrap();
+        }
+    }
+
+    /// Stands for the device of the slot, it has no function
+    struct Endpoint;
+
+    impl VmDevice for Endpoint {
+        fn name(&self) -> String {
+            "endpoint".to_string()
+        }
+    }
+
+    impl PciDevice for Endpoint {
+        fn get_function(&self, _function: u8) -> Option<&dyn PciFunction> {
+            None
+        }
+
+        fn get_function_mut(&mut self, _function: u8) -> Option<&mut dyn PciFunction> {
+            None
+        }
+
+        fn functions(&self) -> Box<dyn Iterator<Item = &(dyn PciFunction + '_)> + '_> {
+            Box::new(std::iter::empty())
+        }
+    }
+
+    struct Setup {
+        root_complex: PciRootComplexDevice,
+        manager: PciHotplugManager,
+        events: UnboundedReceiver<SystemEvent>,
+        /// Offset of the pci express capability of the root port in the ecam
+        exp_cap: u64,
+    }
+
+    impl Setup {
+        fn new() -> Self {
+            #[cfg(target_arch = "x86_64")]
+            let mut pio_allocator = RangeAllocator::<u16>::default();
+            #[cfg(target_arch = "x86_64")]
+            pio_allocator.insert(0, 0xffff).unwrap();
+            let mut mmio_allocator = RangeAllocator::<u64>::default();
+            mmio_allocator.insert(0, 1 << 32).unwrap();
+
+            let mut root_complex = PciRootComplexDevice::new(
+                #[cfg(target_arch = "x86_64")]
+                &mut RangeAllocator::default(),
+                &mut RangeAllocator::default(),
+                &mut RangeAllocator::default(),
+                #[cfg(target_arch = "x86_64")]
+                &mut pio_allocator,
+                &mut mmio_allocator,
+                #[cfg(target_arch = "x86_64")]
+                (0x1000..0x2000),
+                ECAM_START..ECAM_START + (16 << 20),
+                0x4000_0000..SLOT_MMIO_WINDOW.end,
+                None,
+                #[cfg(target_arch = "x86_64")]
+                Arc::new(Events(mpsc::unbounded_channel().0)),
+            )
+            .unwrap();
+
+            let irq_chip: Arc<dyn InterruptController> = Arc::new(IrqChip);
+            let slot = root_complex
+                .add_hotplug_root_port(5, irq_chip.clone(), SLOT_MMIO_WINDOW)
+                .unwrap();
+
+            let (tx, events) = mpsc::unbounded_channel();
+            let mut manager = PciHotplugManager::new(
+                #[cfg(target_os = "linux")]
+                Arc::new(NoVm),
+                Arc::new(
+                    InterruptManager::new(
+                        32,
+                        32,
+                        #[cfg(target_arch = "x86_64")]
+                        32,
+                        #[cfg(target_arch = "x86_64")]
+                        32,
+                    )
+                    .unwrap(),
+                ),
+                irq_chip,
+                Arc::new(MemoryAddressSpace::default()),
+                Arc::new(Events(tx)),
+            );
+            manager.add_slot(slot, SLOT_MMIO_WINDOW);
+
+            let mut setup = Setup {
+                root_complex,
+                manager,
+                events,
+                exp_cap: 0,
+            };
+            setup.exp_cap = setup.find_exp_cap();
+
+            setup
+        }
+
+        fn read(&self, offset: u64) -> u16 {
+            let mut buf = [0; 2];
+            self.root_complex
+                .support_mmio_transport()
+                .unwrap()
+                .mmio_read(ECAM_START + offset, &mut buf)
+                .unwrap();
+
+            u16::from_le_bytes(buf)
+        }
+
+        fn write(&self, offset: u64, val: u16) {
+            self.root_complex
+                .support_mmio_transport()
+                .unwrap()
+                .mmio_write(ECAM_START + offset, &val.to_le_bytes())
+                .unwrap();
+        }
+
+        /// The root port is the only bridge with a pci express capability on the root bus
+        fn find_exp_cap(&self) -> u64 {
+            for device in 0..32u64 {
+                let function = device << 15;
+                if self.read(function) == 0xffff {
+                    continue;
+                }
+
+                let mut cap = self.read(function + 0x34) as u64 & 0xfc;
+                while cap != 0 {
+                    let header = self.read(function + cap);
+                    if header as u8 == PciCapId::Exp as u8 {
+                        return function + cap;
+                    }
+                    cap = (header >> 8) as u64 & 0xfc;
+                }
+            }
+
+            panic!("no root port")
+        }
+
+        fn sltsta(&self) -> u16 {
+            self.read(self.exp_cap + SLTSTA_OFFSET as u64)
+        }
+
+        /// Plug the device the way `device_add` does, without building a virtio device
+        fn plug(&self, id: &str) {
+            let slot = &self.manager.slots[0];
+            assert!(slot.slot.plug(Box::new(Endpoint)).is_ok());
+            *slot.device.lock().unwrap() = Some((
+                id.to_string(),
+                Device::VirtioEntropy {
+                    transport: VirtioTransport::Pci,
+                },
+            ));
+        }
+    }
+
+    #[test]
+    fn test_device_del_and_release() {
+        Runtime::new().unwrap().block_on(async {
+            let mut setup = Setup::new();
+            setup.plug("rng0");
+            assert_ne!(setup.sltsta() & PCI_EXP_SLTSTA_PDS, 0);
+
+            assert!(matches!(
+                setup.manager.device_del("rng1"),
+                Err(PciHotplugError::DeviceNotFound(_))
+            ));
+            setup.manager.device_del("rng0").unwrap();
+
+            // The attention button is pressed, the device stays until the guest releases it
+            assert_ne!(setup.sltsta() & PCI_EXP_SLTSTA_ABP, 0);
+            assert!(setup.events.try_recv().is_err());
+            assert!(setup.manager.slots[0].device.lock().unwrap().is_some());
+
+            // The guest turns the power indicator off once its driver is detached
+            setup.write(
+                setup.exp_cap + SLTCTL_OFFSET as u64,
+                PCI_EXP_SLTCTL_HPIE | PCI_EXP_SLTCTL_ABPE | PCI_EXP_SLTCTL_PWR_IND_OFF,
+            );
+            assert_eq!(
+                setup.events.recv().await,
+                Some(SystemEvent::PciDeviceReleased(0))
+            );
+            assert_eq!(setup.sltsta() & PCI_EXP_SLTSTA_PDS, 0);
+
+            let (id, device) = setup.manager.device_released(0).unwrap();
+            assert_eq!(id, "rng0");
+            assert!(matches!(
+                device,
+                Device::VirtioEntropy {
+                    transport: VirtioTransport::Pci
+                }
+            ));
+
+            // The record is dropped, the id and the slot are free again
+            assert!(setup.manager.slots[0].device.lock().unwrap().is_none());
+            assert!(setup.manager.device_released(0).is_none());
+            assert!(matches!(
+                setup.manager.device_del("rng0"),
+                Err(PciHotplugError::DeviceNotFound(_))
+            ));
+            setup.plug("rng0");
+            assert_ne!(setup.sltsta() & PCI_EXP_SLTSTA_PDS, 0);
+        });
+    }
+}
@@ -10,7 +10,22 @@ use crate::vmm::handler::VmmCommand;
 pub enum MonitorCommand {
     Pause,
     Resume,
+    SystemPowerdown,
+    SystemReset,
     Save(PathBuf),
+    /// Hotplug a pci device described in json, e.g. `device_add {"id": "rng0", "VirtioEntropy": {"transport": "Pci"}}`
+    DeviceAdd(String),
+    /// Ask the guest to release the hotplugged device with this id
+    DeviceDel(String),
+    /// Plug the vcpu next to the present ones
+    CpuPlug,
+    /// Ask the guest to release the last plugged vcpu
+    CpuUnplug,
+    /// Command handled by a device, e.g. `balloon info`
+    Device {
+        name: String,
+        subcommands: Vec<String>,
+    },
 }
 
 pub struct MonitorCommandRequest {
@@ -21,6 +36,7 @@ pub struct MonitorCommandRequest {
 #[derive(Debug)]
 pub enum MonitorCommandResponse {
     Ok,
+    Output(String),
     Err(Box<dyn std::error::Error + Send + Sync>),
 }
 
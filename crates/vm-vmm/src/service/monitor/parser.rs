@@ -14,6 +14,18 @@ fn parse_resume(input: &mut &str) -> winnow::Result<MonitorCommand> {
     "resume".map(|_| MonitorCommand::Resume).parse_next(input)
 }
 
+fn parse_system_powerdown(input: &mut &str) -> winnow::Result<MonitorCommand> {
+    "system_powerdown"
+        .map(|_| MonitorCommand::SystemPowerdown)
+        .parse_next(input)
+}
+
+fn parse_system_reset(input: &mut &str) -> winnow::Result<MonitorCommand> {
+    "system_reset"
+        .map(|_| MonitorCommand::SystemReset)
+        .parse_next(input)
+}
+
 fn parse_save(input: &mut &str) -> winnow::Result<MonitorCommand> {
     preceded(("save", multispace1), take_till(1.., |_| false))
         .map(str::trim)
@@ -21,13 +33,68 @@ fn parse_save(input: &mut &str) -> winnow::Result<MonitorCommand> {
         .parse_next(input)
 }
 
+fn parse_device_add(input: &mut &str) -> winnow::Result<MonitorCommand> {
+    preceded(("device_add", multispace1), take_till(1.., |_| false))
+        .map(str::trim)
+        .map(|args| MonitorCommand::DeviceAdd(args.to_string()))
+        .parse_next(input)
+}
+
+fn parse_device_del(input: &mut &str) -> winnow::Result<MonitorCommand> {
+    preceded(("device_del", multispace1), take_till(1.., |_| false))
+        .map(str::trim)
+        .map(|id| MonitorCommand::DeviceDel(id.to_string()))
+        .parse_next(input)
+}
+
+fn parse_cpu_plug(input: &mut &str) -> winnow::Result<MonitorCommand> {
+    "cpu_plug"
+        .map(|_| MonitorCommand::CpuPlug)
+        .parse_next(input)
+}
+
+fn parse_cpu_unplug(input: &mut &str) -> winnow::Result<MonitorCommand> {
+    "cpu_unplug"
+        .map(|_| MonitorCommand::CpuUnplug)
+        .parse_next(input)
+}
+
+fn parse_device(input: &mut &str) -> winnow::Result<MonitorCommand> {
+    (
+        take_till(1.., char::is_whitespace),
+        take_till(0.., |_| false),
+    )
+        .map(|(name, subcommands): (&str, &str)| MonitorCommand::Device {
+            name: name.to_string(),
+            subcommands: subcommands.split_whitespace().map(str::to_string).collect(),
+        })
+        .parse_next(input)
+}
+
 impl TryFrom<&str> for MonitorCommand {
     type Error = winnow::error::ContextError;
 
     fn try_from(input: &str) -> Result<Self, Self::Error> {
         let mut input = input;
 
-        alt((parse_pause, parse_resume, parse_save)).parse_next(&mut input)
+        // An alt takes at most 9 alternatives
+        alt((
+            alt((
+                parse_pause,
+                parse_resume,
+                parse_system_powerdown,
+                parse_system_reset,
+                parse_save,
+            )),
+            alt((
+                parse_device_add,
+                parse_device_del,
+                parse_cpu_plug,
+                parse_cpu_unplug,
+            )),
+            parse_device,
+        ))
+        .parse_next(&mut input)
     }
 }
 
@@ -47,6 +114,22 @@ mod tests {
             assert_eq!(MonitorCommand::try_from(input), Ok(MonitorCommand::Resume));
         }
 
+        {
+            let input = "system_powerdown";
+            assert_eq!(
+                MonitorCommand::try_from(input),
+                Ok(MonitorCommand::SystemPowerdown)
+            );
+        }
+
+        {
+            let input = "system_reset";
+            assert_eq!(
+                MonitorCommand::try_from(input),
+                Ok(MonitorCommand::SystemReset)
+            );
+        }
+
         {
             let input = "save ./snapshot";
             assert_eq!(
@@ -54,5 +137,47 @@ mod tests {
                 Ok(MonitorCommand::Save("./snapshot".into()))
             );
         }
+
+        {
+            let input = r#"device_add {"id": "rng0", "VirtioEntropy": {"transport": "Pci"}}"#;
+            assert_eq!(
+                MonitorCommand::try_from(input),
+                Ok(MonitorCommand::DeviceAdd(
+                    r#"{"id": "rng0", "VirtioEntropy": {"transport": "Pci"}}"#.to_string()
+                ))
+            );
+        }
+
+        {
+            let input = "device_del rng0";
+            assert_eq!(
+                MonitorCommand::try_from(input),
+                Ok(MonitorCommand::DeviceDel("rng0".to_string()))
+            );
+        }
+
+        {
+            let input = "cpu_plug";
+            assert_eq!(MonitorCommand::try_from(input), Ok(MonitorCommand::CpuPlug));
+        }
+
+        {
+            let input = "cpu_unplug";
+            assert_eq!(
+                MonitorCommand::try_from(input),
+                Ok(MonitorCommand::CpuUnplug)
+            );
+        }
+
+        {
+            let input = "balloon update_num_pages  1024";
+            assert_eq!(
+                MonitorCommand::try_from(input),
+                Ok(MonitorCommand::Device {
+                    name: "balloon".to_string(),
+                    subcommands: vec!["update_num_pages".to_string(), "1024".to_string()],
+                })
+            );
+        }
     }
 }
@@ -5,25 +5,40 @@ use std::sync::Arc;
 
 use tempfile::NamedTempFile;
 use tokio::sync::Mutex;
+use vm_bootloader::boot_loader::firmware::FirmwareBootLoader;
+#[cfg(target_arch = "aarch64")]
+use vm_core::arch::aarch64::layout::RAM_BASE;
 use vm_core::arch::irq::InterruptController;
 use vm_core::arch::registers::ArchCoreRegisters;
+#[cfg(target_arch = "x86_64")]
+use vm_core::arch::x86_64::layout::RAM_BASE;
 use vm_core::cpu::vcpu_manager::VcpuManager;
+use vm_core::cpu::vm_exit::VmExit;
+use vm_core::device::error::DeviceError;
+use vm_core::device::snapshot::pause_device;
+use vm_core::device::snapshot::resume_device;
 use vm_core::monitor::MonitorCommandOps;
 use vm_core::virtualization::vcpu::error::VcpuError;
 use vm_core::virtualization::vm::HypervisorVm;
 use vm_core::virtualization::vm::error::VmError;
 use vm_core::virtualization::vm::state::VmState;
+use vm_device::device::Device;
 use vm_mm::manager::MemoryAddressSpace;
+use vm_utils::range_allocator::RangeAllocator;
 
+use crate::bootloader::error::BootloaderError;
+use crate::bootloader::install_boot_source;
 use crate::device::device_manager_v2::DeviceManagerV2;
 use crate::service::gdbstub::connection::VmGdbStubConnector;
 use crate::vm::config::VmConfig;
+use crate::vmm::error::PciHotplugError;
 use crate::vmm::error::VmSnapshotError;
+use crate::vmm::error::VmmError;
 
 pub mod config;
 
 mod device_builder;
-mod snapshot;
+pub mod snapshot;
 mod vm_exit_handler;
 
 const PAGE_SIZE: usize = 4 << 10;
@@ -33,6 +48,8 @@ pub struct Vm {
     vm_instance: Arc<dyn HypervisorVm>,
     vm_state: VmState,
     vcpu_manager: Arc<Mutex<VcpuManager>>,
+    /// Handles the exits of the vcpus plugged at runtime too
+    vm_exit_handler: Arc<dyn VmExit>,
     memory_address_space: Arc<MemoryAddressSpace>,
     irq_chip: Arc<dyn InterruptController>,
     device_manager: Arc<DeviceManagerV2>,
@@ -69,20 +86,26 @@ impl Vm {
                 .map_err(|_| VmError::GdbListenerCreation)?;
         }
 
+        if !stop_on_boot {
+            self.boot_vcpus().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn boot_vcpus(&mut self) -> Result<(), VmError> {
         let mut vcpu_manager = self.vcpu_manager.lock().await;
 
-        if !stop_on_boot {
-            vcpu_manager.get_vcpu_mut(0)?.boot().await?;
+        vcpu_manager.get_vcpu_mut(0)?.boot().await?;
 
-            if self.vm_instance.secondary_cpu_should_run_on_booting() {
-                for vcpu_id in 1..vcpu_manager.get_active_vcpus() {
-                    vcpu_manager.get_vcpu_mut(vcpu_id)?.boot().await?;
-                }
+        if self.vm_instance.secondary_cpu_should_run_on_booting() {
+            for vcpu_id in 1..vcpu_manager.get_active_vcpus() {
+                vcpu_manager.get_vcpu_mut(vcpu_id)?.boot().await?;
             }
-
-            self.vm_state = VmState::Running;
         }
 
+        self.vm_state = VmState::Running;
+
         Ok(())
     }
 
@@ -174,7 +197,12 @@ impl Vm {
             vcpu_manager.pause_all_vcpus().await?;
         }
 
-        // TODO: pause devices
+        for device in self.device_manager.iter() {
+            pause_device(device.as_ref()).map_err(|err| VmError::PauseDevice {
+                name: device.name(),
+                err,
+            })?;
+        }
 
         self.vm_state = VmState::Paused;
 
@@ -184,27 +212,240 @@ impl Vm {
     pub async fn resume(&mut self) -> Result<(), VmError> {
         self.vm_state.ensure_is_not_running()?;
 
+        for device in self.device_manager.iter() {
+            resume_device(device.as_ref()).map_err(|err| VmError::ResumeDevice {
+                name: device.name(),
+                err,
+            })?;
+        }
+
         {
             let mut vcpu_manager = self.vcpu_manager.lock().await;
 
             vcpu_manager.resume_all_vcpus().await?;
         }
 
-        // TODO: resume devices
-
         self.vm_state = VmState::Running;
 
         Ok(())
     }
 
-    pub async fn save(&mut self, path: PathBuf) -> Result<(), VmSnapshotError> {
+    pub fn system_powerdown(&self) -> Result<(), VmError> {
+        self.vm_state.ensure_is_running()?;
+
+        let power_button = self
+            .device_manager
+            .power_button()
+            .ok_or(VmError::PowerButtonNotPresent)?;
+        power_button.press();
+
+        Ok(())
+    }
+
+    pub async fn shutdown(&mut self) -> Result<(), VmmError> {
+        if self.vm_state == VmState::Running {
+            self.pause().await?;
+        }
+
+        self.flush_firmware_vars()?;
+
+        Ok(())
+    }
+
+    /// The guest writes its firmware variables to the vars bank in memory, they are saved back
+    /// to the vars file once the vcpus are stopped
+    fn flush_firmware_vars(&self) -> Result<(), BootloaderError> {
+        let Some(firmware) = &self.vm_config.firmware else {
+            return Ok(());
+        };
+        let Some(vars) = &firmware.vars else {
+            return Ok(());
+        };
+
+        FirmwareBootLoader::new(&firmware.code, Some(vars))?
+            .flush_vars(&self.memory_address_space, vars)?;
+
+        Ok(())
+    }
+
+    /// Reboots the guest in place, the memory is reloaded with the firmware or the kernel
+    pub async fn reset(&mut self) -> Result<(), VmmError> {
+        self.vm_state.ensure_is_not_created()?;
+
+        {
+            let mut vcpu_manager = self.vcpu_manager.lock().await;
+
+            vcpu_manager.reset_all_vcpus().await?;
+        }
+
+        for device in self.device_manager.iter() {
+            device.reset().map_err(|err| VmError::ResetDevice {
+                name: device.name(),
+                err,
+            })?;
+        }
+
+        for virtio_mem in self.device_manager.virtio_mem_resets() {
+            virtio_mem
+                .reset()
+                .await
+                .map_err(|err| VmError::ResetDevice {
+                    name: "virtio-mem".to_string(),
+                    err: DeviceError::Device(Box::new(err)),
+                })?;
+        }
+
+        if self.vm_config.clear_memory_on_reset {
+            self.memory_address_space
+                .memset(RAM_BASE, 0, self.vm_config.memory_size)?;
+        }
+
+        let mut ram_allocator = RangeAllocator::<u64>::default();
+        ram_allocator
+            .insert(RAM_BASE, self.vm_config.memory_size)
+            .unwrap();
+
+        let firmware = self
+            .vm_config
+            .firmware
+            .as_ref()
+            .map(|firmware| FirmwareBootLoader::new(&firmware.code, firmware.vars.as_deref()))
+            .transpose()
+            .map_err(BootloaderError::from)?;
+
+        install_boot_source(
+            firmware.as_ref(),
+            &self.vm_config,
+            &self.vcpu_manager,
+            &mut ram_allocator,
+            &self.memory_address_space,
+            self.irq_chip.as_ref(),
+            self.device_manager.as_ref(),
+        )
+        .await?;
+
+        self.boot_vcpus().await?;
+
+        Ok(())
+    }
+
+    /// Plugs the vcpu next to the present ones, the guest is notified to bring it online
+    pub async fn cpu_plug(&mut self) -> Result<usize, VmError> {
+        self.vm_state.ensure_is_running()?;
+
+        let cpu_hotplug = self
+            .device_manager
+            .cpu_hotplug()
+            .ok_or(VmError::CpuHotplugNotPresent)?;
+        if cpu_hotplug.unplug_pending() {
+            return Err(VmError::VcpuUnplugPending);
+        }
+
+        let vcpu_id = {
+            let mut vcpu_manager = self.vcpu_manager.lock().await;
+
+            let vcpu_id = vcpu_manager.plug_vcpu(
+                self.memory_address_space.clone(),
+                self.vm_exit_handler.clone(),
+            )?;
+
+            // A x86 vcpu waits for the INIT-SIPI of the guest, an arm64 one is turned on by
+            // PSCI CPU_ON
+            if self.vm_instance.secondary_cpu_should_run_on_booting() {
+                vcpu_manager.get_vcpu_mut(vcpu_id)?.boot().await?;
+            }
+
+            vcpu_id
+        };
+
+        cpu_hotplug.plug(vcpu_id as u32);
+        self.vm_config.vcpus += 1;
+
+        Ok(vcpu_id)
+    }
+
+    /// Asks the guest to release the last plugged vcpu, it is unplugged once it is ejected
+    pub async fn cpu_unplug(&self) -> Result<(), VmError> {
+        self.vm_state.ensure_is_running()?;
+
+        let cpu_hotplug = self
+            .device_manager
+            .cpu_hotplug()
+            .ok_or(VmError::CpuHotplugNotPresent)?;
+        if cpu_hotplug.unplug_pending() {
+            return Err(VmError::VcpuUnplugPending);
+        }
+
+        let vcpus = self.get_active_vcpus().await;
+        if vcpus <= 1 {
+            return Err(VmError::NoVcpuToUnplug);
+        }
+
+        cpu_hotplug.request_unplug(vcpus as u32 - 1);
+
+        Ok(())
+    }
+
+    /// The guest has ejected the vcpu, it is parked until it is plugged again
+    pub async fn vcpu_ejected(&mut self, vcpu_id: usize) -> Result<(), VmError> {
+        {
+            let mut vcpu_manager = self.vcpu_manager.lock().await;
+
+            vcpu_manager.unplug_vcpu(vcpu_id).await?;
+        }
+
+        self.vm_config.vcpus -= 1;
+
+        Ok(())
+    }
+
+    pub fn device_add(&mut self, id: String, device: &Device) -> Result<(), PciHotplugError> {
+        self.device_manager
+            .pci_hotplug()
+            .ok_or(PciHotplugError::NotSupported)?
+            .device_add(id, device)?;
+        self.vm_config.devices.push(device.clone());
+
+        Ok(())
+    }
+
+    pub fn device_del(&self, id: &str) -> Result<(), PciHotplugError> {
+        self.device_manager
+            .pci_hotplug()
+            .ok_or(PciHotplugError::NotSupported)?
+            .device_del(id)
+    }
+
+    /// The guest has released the device of the slot, it is dropped from the config
+    pub fn pci_device_released(&mut self, slot: usize) -> Result<(), PciHotplugError> {
+        let (_id, device) = self
+            .device_manager
+            .pci_hotplug()
+            .ok_or(PciHotplugError::NotSupported)?
+            .device_released(slot)
+            .ok_or(PciHotplugError::SlotNotPlugged(slot))?;
+
+        if let Some(index) = self.vm_config.devices.iter().position(|d| *d == device) {
+            self.vm_config.devices.remove(index);
+        }
+
+        Ok(())
+    }
+
+    pub async fn save(
+        &mut self,
+        path: PathBuf,
+        hypervisor: &'static str,
+    ) -> Result<(), VmSnapshotError> {
         self.vm_state.ensure_is_not_running()?;
 
         let mut tmp = NamedTempFile::new()?;
 
+        self.flush_firmware_vars()?;
+
         let snap = self.build_snapshot().await?;
 
-        let bytes = postcard::to_stdvec(&snap)?;
+        let bytes = snap.to_bytes(hypervisor)?;
         tmp.write_all(&bytes)?;
         tmp.persist(&path).map_err(|e| e.error)?;
 
@@ -1,33 +1,58 @@
+use std::collections::HashSet;
+use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
 
 use serde::Deserialize;
 use serde::Serialize;
+use serde_json::Value;
 use tokio::sync::Mutex;
 use tokio::sync::mpsc;
+use vm_bootloader::boot_loader::firmware::FirmwareBootLoader;
 #[cfg(target_arch = "aarch64")]
-use vm_core::arch::aarch64::firmware::psci::psci_0_2::Psci02;
+use vm_core::arch::aarch64::firmware::psci::psci_1_1::Psci11;
+#[cfg(target_arch = "aarch64")]
+use vm_core::arch::aarch64::layout::MEMORY_HOTPLUG_LEN;
+#[cfg(target_arch = "aarch64")]
+use vm_core::arch::aarch64::layout::MEMORY_HOTPLUG_START;
+#[cfg(target_arch = "aarch64")]
+use vm_core::arch::aarch64::layout::PCI_BAR_MMIO64_WINDOW_MAX_LEN;
 #[cfg(target_arch = "aarch64")]
 use vm_core::arch::aarch64::layout::RAM_BASE;
 use vm_core::arch::irq::InterruptController;
 #[cfg(target_arch = "x86_64")]
+use vm_core::arch::x86_64::layout::MEMORY_HOTPLUG_LEN;
+#[cfg(target_arch = "x86_64")]
+use vm_core::arch::x86_64::layout::MEMORY_HOTPLUG_START;
+#[cfg(target_arch = "x86_64")]
+use vm_core::arch::x86_64::layout::MMIO_START;
+#[cfg(target_arch = "x86_64")]
 use vm_core::arch::x86_64::layout::RAM_BASE;
 use vm_core::cpu::vcpu_manager::VcpuManager;
+use vm_core::device::system_event::SystemEventNotifier;
 use vm_core::virtualization::hypervisor::Hypervisor;
 use vm_core::virtualization::vm::SetUserMemoryRegionFlags;
 use vm_core::virtualization::vm::error::VmError;
 use vm_core::virtualization::vm::state::VmState;
 use vm_device::device::Device;
+use vm_device::device::rtc::RtcConfig;
+use vm_device::device::serial::SerialConfig;
+use vm_device::device::virtio::virtio_mem::device::VIRTIO_MEM_REGION_ALIGN;
+use vm_firmware::acpi::numa::NumaNode;
 use vm_mm::allocator::Allocator;
+#[cfg(target_os = "linux")]
+use vm_mm::allocator::memfd_allocator::MemfdAllocator;
 use vm_mm::allocator::std_allocator::StdAllocator;
 use vm_mm::manager::MemoryAddressSpace;
+use vm_mm::memory_container::MemoryContainer;
 use vm_mm::region::MemoryRegion;
+use vm_utils::cpu_topology::CpuTopology;
 use vm_utils::range_allocator::RangeAllocator;
+use vm_virtio::result::VirtioError;
 
-#[cfg(target_arch = "aarch64")]
-use crate::bootloader::aarch64::install_bootloader;
-#[cfg(target_arch = "x86_64")]
-use crate::bootloader::x86_64::install_bootloader;
+use crate::bootloader::error::BootloaderError;
+use crate::bootloader::install_boot_source;
+use crate::device::error::InitDeviceError;
 use crate::service::gdbstub::connection::VmGdbStubConnector;
 use crate::service::monitor::builder::MonitorServerBuilder;
 use crate::vm::PAGE_SIZE;
@@ -36,16 +61,418 @@ use crate::vm::device_builder::DeviceManagerBuilder;
 use crate::vm::vm_exit_handler::VmExitHandler;
 use crate::vmm::error::VmmError;
 use crate::vmm::handler::VmmCommand;
+use crate::vmm::handler::system_event::VmmSystemEventNotifier;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct NumaNodeConfig {
+    pub vcpus: Vec<u32>,
+    pub memory_size: usize,
+    /// The distances to every node including itself, defaults to 10 for itself and 20 for
+    /// the others
+    #[serde(default)]
+    pub distances: Vec<u8>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FirmwareConfig {
+    /// Mapped read-only, the boot vcpu starts at its reset vector
+    pub code: PathBuf,
+    /// The writable variable store, the guest writes are not saved back
+    pub vars: Option<PathBuf>,
+}
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct VmConfig {
     pub memory_size: usize,
     pub vcpus: usize,
+    /// The vcpus above `vcpus` are plugged at runtime, the vcpus can't be hotplugged if it is
+    /// not set
+    #[serde(default)]
+    pub max_vcpus: Option<usize>,
+    /// One socket with a single thread core per vcpu if it is not set
+    #[serde(default)]
+    pub cpu_topology: Option<CpuTopology>,
+    /// The ram is split between the nodes in order
+    #[serde(default)]
+    pub numa_nodes: Vec<NumaNodeConfig>,
     pub devices: Vec<Device>,
+    /// Pcie root ports left empty for `device_add`
+    pub pci_hotplug_slots: usize,
+    /// Size of the 64-bit prefetchable pci window, 0 to disable it
+    pub pci_mmio64_size: usize,
     pub gdb_port: Option<u16>,
-    pub kernel: PathBuf,
+    /// Either a kernel or a firmware is booted
+    #[serde(default)]
+    pub kernel: Option<PathBuf>,
+    #[serde(default)]
+    pub firmware: Option<FirmwareConfig>,
     pub initramfs: Option<PathBuf>,
     pub cmdline: Option<String>,
+    pub rtc: RtcConfig,
+    #[serde(default)]
+    pub serial: SerialConfig,
+    /// Zero the ram before the firmware or the kernel is reloaded on a reset
+    #[serde(default)]
+    pub clear_memory_on_reset: bool,
+    /// Describe the hardware with acpi tables instead of the device tree
+    #[cfg(target_arch = "aarch64")]
+    #[serde(default)]
+    pub acpi: bool,
+}
+
+impl VmConfig {
+    pub fn max_vcpus(&self) -> usize {
+        self.max_vcpus.unwrap_or(self.vcpus)
+    }
+
+    /// It covers every vcpu which can be plugged
+    pub fn cpu_topology(&self) -> CpuTopology {
+        self.cpu_topology
+            .unwrap_or_else(|| CpuTopology::flat(self.max_vcpus() as u32))
+    }
+
+    pub fn numa_nodes(&self) -> Vec<NumaNode> {
+        let mut start = RAM_BASE;
+
+        self.numa_nodes
+            .iter()
+            .map(|node| {
+                let memory = start..start + node.memory_size as u64;
+                start = memory.end;
+
+                NumaNode {
+                    vcpus: node.vcpus.clone(),
+                    memory,
+                    distances: node.distances.clone(),
+                }
+            })
+            .collect()
+    }
+
+    pub fn validate(&self) -> Result<(), VmmError> {
+        match (&self.kernel, &self.firmware) {
+            (Some(_), Some(_)) => {
+                return Err(VmmError::InvalidBootSource(
+                    "a kernel and a firmware are both set",
+                ));
+            }
+            (None, None) => {
+                return Err(VmmError::InvalidBootSource(
+                    "neither a kernel nor a firmware is set",
+                ));
+            }
+            (None, Some(_)) if self.initramfs.is_some() || self.cmdline.is_some() => {
+                return Err(VmmError::InvalidBootSource(
+                    "the initramfs and the cmdline are only for a kernel",
+                ));
+            }
+            _ => (),
+        }
+
+        let cpu_topology = self.cpu_topology();
+        if cpu_topology.vcpus() as usize != self.max_vcpus() {
+            return Err(VmmError::CpuTopologyMismatch {
+                vcpus: self.max_vcpus(),
+                topology: cpu_topology.vcpus(),
+            });
+        }
+
+        if self.max_vcpus.is_some() && (self.vcpus == 0 || self.vcpus > self.max_vcpus()) {
+            return Err(VmmError::InvalidCpuHotplug(
+                "the vcpus at boot must be between 1 and the max vcpus",
+            ));
+        }
+
+        // The guest learns about the plugged vcpus from the acpi tables only
+        #[cfg(target_arch = "aarch64")]
+        if self.max_vcpus.is_some() && !self.acpi {
+            return Err(VmmError::InvalidCpuHotplug(
+                "the vcpus are only hotpluggable with acpi",
+            ));
+        }
+
+        self.validate_files()?;
+        self.validate_memory()?;
+        self.validate_devices()?;
+
+        if self.numa_nodes.is_empty() {
+            return Ok(());
+        }
+
+        if self
+            .numa_nodes
+            .iter()
+            .map(|node| node.memory_size)
+            .sum::<usize>()
+            != self.memory_size
+        {
+            return Err(VmmError::InvalidNumaNodes(
+                "the memory of the nodes does not add up to the memory size",
+            ));
+        }
+
+        if self
+            .numa_nodes
+            .iter()
+            .any(|node| !node.memory_size.is_multiple_of(PAGE_SIZE))
+        {
+            return Err(VmmError::InvalidNumaNodes(
+                "the memory of a node is not page aligned",
+            ));
+        }
+
+        let mut vcpus = self
+            .numa_nodes
+            .iter()
+            .flat_map(|node| node.vcpus.iter().copied())
+            .collect::<Vec<_>>();
+        vcpus.sort_unstable();
+        if !vcpus.iter().copied().eq(0..self.max_vcpus() as u32) {
+            return Err(VmmError::InvalidNumaNodes(
+                "every vcpu must belong to exactly one node",
+            ));
+        }
+
+        if self
+            .numa_nodes
+            .iter()
+            .any(|node| !node.distances.is_empty() && node.distances.len() != self.numa_nodes.len())
+        {
+            return Err(VmmError::InvalidNumaNodes(
+                "the distances of a node must cover every node",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Applies a json merge patch (RFC 7386), the arrays are replaced as a whole
+fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+
+    if !target.is_object() {
+        *target = Value::Object(Default::default());
+    }
+    let target = target.as_object_mut().unwrap();
+
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            merge_patch(target.entry(key.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+impl VmConfig {
+    /// The config with the host side resources cleared
+    fn guest_view(&self) -> VmConfig {
+        let mut vm_config = self.clone();
+
+        vm_config.gdb_port = None;
+        vm_config.serial = SerialConfig::default();
+        for path in [&mut vm_config.kernel, &mut vm_config.initramfs]
+            .into_iter()
+            .flatten()
+        {
+            *path = PathBuf::new();
+        }
+        if let Some(firmware) = &mut vm_config.firmware {
+            firmware.code = PathBuf::new();
+            if let Some(vars) = &mut firmware.vars {
+                *vars = PathBuf::new();
+            }
+        }
+        for device in &mut vm_config.devices {
+            for path in device.host_paths_mut() {
+                *path = PathBuf::new();
+            }
+        }
+
+        vm_config
+    }
+
+    /// Applies `patch` as a json merge patch, only the host side resources may change since
+    /// the guest has seen the rest, e.g. a disk image moved to another path
+    pub fn apply_override(&self, patch: &Value) -> Result<VmConfig, VmmError> {
+        let invalid = |err: serde_json::Error| VmmError::InvalidOverride(err.to_string());
+
+        let mut value = serde_json::to_value(self).map_err(invalid)?;
+        merge_patch(&mut value, patch);
+        let vm_config = serde_json::from_value::<VmConfig>(value).map_err(invalid)?;
+
+        let Value::Object(old) = serde_json::to_value(self.guest_view()).map_err(invalid)? else {
+            unreachable!()
+        };
+        let new = serde_json::to_value(vm_config.guest_view()).map_err(invalid)?;
+        if let Some((key, _)) = old.iter().find(|&(key, value)| new.get(key) != Some(value)) {
+            return Err(VmmError::GuestVisibleOverride(key.clone()));
+        }
+
+        Ok(vm_config)
+    }
+}
+
+fn check_file(what: &'static str, path: &Path) -> Result<(), VmmError> {
+    if !path.exists() {
+        return Err(VmmError::MissingFile {
+            what,
+            path: path.to_path_buf(),
+        });
+    }
+
+    Ok(())
+}
+
+impl VmConfig {
+    fn validate_files(&self) -> Result<(), VmmError> {
+        if let Some(kernel) = &self.kernel {
+            check_file("kernel", kernel)?;
+        }
+        if let Some(initramfs) = &self.initramfs {
+            check_file("initramfs", initramfs)?;
+        }
+        if let Some(firmware) = &self.firmware {
+            check_file("firmware", &firmware.code)?;
+            if let Some(vars) = &firmware.vars {
+                check_file("firmware vars", vars)?;
+            }
+        }
+
+        for device in &self.devices {
+            match device {
+                Device::VirtioBlk { path, .. } => check_file("disk image", path)?,
+                Device::Virtio9p { path, .. } => check_file("shared directory", path)?,
+                #[cfg(target_os = "linux")]
+                Device::VfioPci { path, .. } => check_file("vfio device", path)?,
+                #[cfg(target_os = "linux")]
+                Device::VfioUser { socket, .. } => check_file("vfio-user socket", socket)?,
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_memory(&self) -> Result<(), VmmError> {
+        if self.memory_size == 0 || !self.memory_size.is_multiple_of(PAGE_SIZE) {
+            return Err(VmmError::InvalidMemory(format!(
+                "the memory size {:#x} is not a multiple of the page size",
+                self.memory_size
+            )));
+        }
+
+        // The ram is a single region which must end below the first mmio window above it
+        #[cfg(target_arch = "x86_64")]
+        let ram_end = MMIO_START as u64;
+        #[cfg(target_arch = "aarch64")]
+        let ram_end = MEMORY_HOTPLUG_START;
+        if RAM_BASE + self.memory_size as u64 > ram_end {
+            return Err(VmmError::InvalidMemory(format!(
+                "the memory size {:#x} overlaps the mmio space at {ram_end:#x}",
+                self.memory_size
+            )));
+        }
+
+        let mut hotplug_size = 0;
+        for device in &self.devices {
+            let Device::VirtioMem { region_size, .. } = device else {
+                continue;
+            };
+
+            if *region_size == 0 || !region_size.is_multiple_of(VIRTIO_MEM_REGION_ALIGN) {
+                return Err(VmmError::InvalidMemory(format!(
+                    "the virtio-mem region size {region_size:#x} is not a multiple of {VIRTIO_MEM_REGION_ALIGN:#x}"
+                )));
+            }
+            hotplug_size += *region_size as u64;
+        }
+        if hotplug_size > MEMORY_HOTPLUG_LEN {
+            return Err(VmmError::InvalidMemory(format!(
+                "the virtio-mem regions need {hotplug_size:#x} bytes but the hotplug window is {MEMORY_HOTPLUG_LEN:#x}"
+            )));
+        }
+
+        #[cfg(target_arch = "aarch64")]
+        if self.pci_mmio64_size as u64 > PCI_BAR_MMIO64_WINDOW_MAX_LEN {
+            return Err(VmmError::InvalidMemory(format!(
+                "the 64-bit pci window {:#x} is larger than {PCI_BAR_MMIO64_WINDOW_MAX_LEN:#x}",
+                self.pci_mmio64_size
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn validate_devices(&self) -> Result<(), VmmError> {
+        let mut disks = HashSet::new();
+        let mut tags = HashSet::new();
+        #[cfg(target_os = "linux")]
+        let mut names = HashSet::new();
+        let mut iommus = 0;
+
+        for device in &self.devices {
+            match device {
+                Device::GicV3 => {
+                    #[cfg(target_arch = "x86_64")]
+                    return Err(VmmError::InvalidDevice(
+                        "the gic-v3 is only available on aarch64".to_string(),
+                    ));
+                    #[cfg(target_arch = "aarch64")]
+                    return Err(VmmError::InvalidDevice(
+                        "a userspace gic-v3 is not supported yet".to_string(),
+                    ));
+                }
+                Device::VirtioBlk { path, .. } if !disks.insert(path) => {
+                    return Err(VmmError::InvalidDevice(format!(
+                        "the disk image {path:?} is attached twice"
+                    )));
+                }
+                Device::Virtio9p { tag, .. } if !tags.insert(tag) => {
+                    return Err(VmmError::InvalidDevice(format!(
+                        "the 9p tag {tag:?} is used twice"
+                    )));
+                }
+                Device::VirtioIommu => iommus += 1,
+                #[cfg(target_os = "linux")]
+                Device::VfioPci { name, .. } | Device::VfioUser { name, .. }
+                    if !names.insert(name) =>
+                {
+                    return Err(VmmError::InvalidDevice(format!(
+                        "the vfio device name {name:?} is used twice"
+                    )));
+                }
+                _ => (),
+            }
+        }
+
+        if iommus > 1 {
+            return Err(VmmError::InvalidDevice(
+                "at most one virtio-iommu is supported".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Guest memory shared with another process, e.g. a vfio-user server, is backed by a memfd
+fn alloc_guest_memory(
+    len: usize,
+    shared: bool,
+) -> Result<Box<dyn MemoryContainer>, vm_mm::error::Error> {
+    #[cfg(target_os = "linux")]
+    if shared {
+        return Ok(Box::new(MemfdAllocator.alloc(len, Some(PAGE_SIZE))?));
+    }
+    #[cfg(not(target_os = "linux"))]
+    assert!(!shared);
+
+    Ok(Box::new(StdAllocator.alloc(len, Some(PAGE_SIZE))?))
 }
 
 impl Vm {
@@ -54,18 +481,22 @@ impl Vm {
         vmm_tx: Arc<mpsc::Sender<VmmCommand>>,
         vm_config: VmConfig,
     ) -> Result<Self, VmmError> {
+        vm_config.validate()?;
+
         let mut ram_allocator = RangeAllocator::<u64>::default();
 
         let mut monitor_server_builder = MonitorServerBuilder::default();
 
         let vm_instance = hypervisor.create_vm()?;
 
+        let shared_memory = vm_config.devices.iter().any(Device::needs_shared_memory);
+
         let mut memory_address_space = MemoryAddressSpace::default();
         {
-            let memory_region = StdAllocator.alloc(vm_config.memory_size, Some(PAGE_SIZE))?;
+            let memory_region = alloc_guest_memory(vm_config.memory_size, shared_memory)?;
 
             memory_address_space
-                .try_insert(MemoryRegion::new(RAM_BASE, Box::new(memory_region)))
+                .try_insert(MemoryRegion::new(RAM_BASE, memory_region))
                 .map_err(|_| VmError::MemoryRegionOverlap)?;
 
             for region in memory_address_space.regions().values() {
@@ -79,6 +510,69 @@ impl Vm {
             }
         }
 
+        let firmware = vm_config
+            .firmware
+            .as_ref()
+            .map(|firmware| FirmwareBootLoader::new(&firmware.code, firmware.vars.as_deref()))
+            .transpose()
+            .map_err(BootloaderError::from)?;
+
+        if let Some(firmware) = &firmware {
+            for flash in firmware.flash_regions() {
+                let memory_region = alloc_guest_memory(flash.image.len(), shared_memory)?;
+                memory_region.copy_from_slice(flash.image);
+                let memory_region = MemoryRegion::new(flash.gpa, memory_region);
+
+                vm_instance.set_user_memory_region(
+                    memory_region.hva() as u64,
+                    memory_region.gpa,
+                    memory_region.len(),
+                    if flash.read_only {
+                        SetUserMemoryRegionFlags::ReadExec
+                    } else {
+                        SetUserMemoryRegionFlags::ReadWriteExec
+                    },
+                )?;
+
+                memory_address_space
+                    .try_insert(memory_region)
+                    .map_err(|_| VmError::MemoryRegionOverlap)?;
+            }
+        }
+
+        // Hotpluggable memory is backed up front but is only mapped into the
+        // guest by virtio-mem once it is plugged
+        {
+            let mut memory_hotplug_allocator = RangeAllocator::<u64>::default();
+            memory_hotplug_allocator
+                .insert(MEMORY_HOTPLUG_START, MEMORY_HOTPLUG_LEN as usize)
+                .unwrap();
+
+            for device in &vm_config.devices {
+                let Device::VirtioMem { region_size, .. } = device else {
+                    continue;
+                };
+
+                if *region_size == 0 || !region_size.is_multiple_of(VIRTIO_MEM_REGION_ALIGN) {
+                    return Err(InitDeviceError::Virtio(VirtioError::InvalidVirtioMemSize {
+                        name: "region_size",
+                        size: *region_size,
+                    })
+                    .into());
+                }
+
+                let range = memory_hotplug_allocator
+                    .alloc(*region_size)
+                    .map_err(|err| InitDeviceError::AllocResource(Box::new(err)))?;
+
+                let memory_region = alloc_guest_memory(*region_size, shared_memory)?;
+
+                memory_address_space
+                    .try_insert(MemoryRegion::new(range.start, memory_region))
+                    .map_err(|_| VmError::MemoryRegionOverlap)?;
+            }
+        }
+
         let memory_address_space = Arc::new(memory_address_space);
 
         let irq_chip: Arc<dyn InterruptController> =
@@ -88,6 +582,9 @@ impl Vm {
                 todo!()
             };
 
+        let system_event_notifier: Arc<dyn SystemEventNotifier> =
+            Arc::new(VmmSystemEventNotifier::new(vmm_tx.clone()));
+
         let device_manager = {
             let device_manager = DeviceManagerBuilder::new(
                 vm_instance.clone(),
@@ -95,21 +592,38 @@ impl Vm {
                 vm_instance.create_irq_manager()?,
                 memory_address_space.clone(),
                 &mut monitor_server_builder,
+                vm_config.rtc.clone(),
+                vm_config.serial.clone(),
+                vm_config.cpu_topology(),
+                vm_config
+                    .max_vcpus
+                    .is_some()
+                    .then_some(vm_config.vcpus as u32),
+                system_event_notifier.clone(),
             )?
-            .build(&vm_config.devices)?;
+            .build(
+                &vm_config.devices,
+                vm_config.pci_hotplug_slots,
+                vm_config.pci_mmio64_size,
+            )?;
 
             Arc::new(device_manager)
         };
 
-        let vcpu_manager = Arc::new(Mutex::new(VcpuManager::new(vm_instance.clone())));
+        let vcpu_manager = Arc::new(Mutex::new(VcpuManager::new(
+            vm_instance.clone(),
+            vm_config.cpu_topology(),
+        )));
 
         #[cfg(target_arch = "aarch64")]
-        let psci = Psci02 {
+        let psci = Psci11 {
             vcpu_manager: vcpu_manager.clone(),
+            system_event_notifier: system_event_notifier.clone(),
         };
 
         let vm_exit_handler = Arc::new(VmExitHandler::new(
             device_manager.clone(),
+            system_event_notifier,
             #[cfg(target_arch = "aarch64")]
             psci,
         ));
@@ -127,7 +641,8 @@ impl Vm {
             }
         }
 
-        install_bootloader(
+        install_boot_source(
+            firmware.as_ref(),
             &vm_config,
             &vcpu_manager,
             &mut ram_allocator,
@@ -146,6 +661,7 @@ impl Vm {
             vm_instance,
             vm_state: VmState::Created,
             vcpu_manager,
+            vm_exit_handler,
             memory_address_space,
             irq_chip,
             device_manager,
@@ -156,3 +672,68 @@ impl Vm {
         Ok(vm)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use vm_device::device::VirtioTransport;
+
+    use super::*;
+
+    fn vm_config() -> VmConfig {
+        VmConfig {
+            memory_size: 1 << 30,
+            vcpus: 1,
+            max_vcpus: None,
+            cpu_topology: None,
+            numa_nodes: vec![],
+            devices: vec![Device::VirtioBlk {
+                transport: VirtioTransport::Pci,
+                path: "disk.img".into(),
+                read_only: false,
+            }],
+            pci_hotplug_slots: 0,
+            pci_mmio64_size: 0,
+            gdb_port: None,
+            kernel: Some("Image".into()),
+            firmware: None,
+            initramfs: None,
+            cmdline: None,
+            rtc: RtcConfig::default(),
+            serial: SerialConfig::default(),
+            clear_memory_on_reset: false,
+            #[cfg(target_arch = "aarch64")]
+            acpi: false,
+        }
+    }
+
+    #[test]
+    fn test_apply_override() -> Result<(), VmmError> {
+        let config = vm_config().apply_override(&json!({
+            "gdb_port": 1235,
+            "devices": [{"VirtioBlk": {"transport": "Pci", "path": "/mnt/disk.img"}}],
+        }))?;
+        assert_eq!(config.gdb_port, Some(1235));
+        assert_eq!(
+            config.devices[0],
+            Device::VirtioBlk {
+                transport: VirtioTransport::Pci,
+                path: "/mnt/disk.img".into(),
+                read_only: false,
+            }
+        );
+
+        assert!(matches!(
+            vm_config().apply_override(&json!({"vcpus": 2})),
+            Err(VmmError::GuestVisibleOverride(field)) if field == "vcpus"
+        ));
+        assert!(matches!(
+            vm_config().apply_override(&json!({
+                "devices": [{"VirtioBlk": {"transport": "Mmio", "path": "disk.img"}}],
+            })),
+            Err(VmmError::GuestVisibleOverride(field)) if field == "devices"
+        ));
+
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 use std::cell::OnceCell;
+use std::collections::VecDeque;
 use std::sync::Arc;
 
 #[cfg(target_arch = "aarch64")]
@@ -6,26 +7,43 @@ use vm_core::arch::aarch64::layout::*;
 use vm_core::arch::irq::InterruptController;
 #[cfg(target_arch = "x86_64")]
 use vm_core::arch::x86_64::layout::*;
+use vm_core::device::system_event::SystemEventNotifier;
 use vm_core::interrupt_manager::InterruptManager;
 use vm_core::virtualization::vm::HypervisorVm;
 use vm_device::device::Device;
 use vm_device::device::VirtioTransport;
+use vm_device::device::cpu_hotplug::CpuHotplugController;
+use vm_device::device::ged::Ged;
+#[cfg(target_arch = "x86_64")]
+use vm_device::device::i8042;
+use vm_device::device::rtc::RtcConfig;
+use vm_device::device::serial::SerialConfig;
+use vm_device::device::virtio::virtio_9p::Virtio9p;
 use vm_device::device::virtio::virtio_balloon_traditional::device::VirtioBalloonTranditional;
 use vm_device::device::virtio::virtio_balloon_traditional::monitor::VirtioBalloonMonitor;
 use vm_device::device::virtio::virtio_blk::VirtioBlkDevice;
 use vm_device::device::virtio::virtio_entropy::VirtioEntropy;
 use vm_device::device::virtio::virtio_gpu::VirtioGpu;
+use vm_device::device::virtio::virtio_iommu::device::VirtioIommu;
+use vm_device::device::virtio::virtio_iommu::device::VirtioIommuState;
+use vm_device::device::virtio::virtio_iommu::endpoint::IommuEndpoint;
+use vm_device::device::virtio::virtio_mem::device::VirtioMem;
+use vm_device::device::virtio::virtio_mem::monitor::VirtioMemMonitor;
 use vm_mm::manager::MemoryAddressSpace;
 use vm_pci::root_complex_device::PciRootComplexDevice;
+use vm_utils::cpu_topology::CpuTopology;
 use vm_utils::range_allocator::RangeAllocator;
 #[cfg(target_os = "linux")]
 use vm_vfio::vfio::container::VfioContainer;
 use vm_virtio::device::VirtioDevice;
 use vm_virtio::transport::VirtioDeviceOps;
 use vm_virtio::transport::pci::VirtioPciDevice;
+use vm_virtio::transport::pci::VirtioPciTransport;
 
 use crate::device::device_manager_v2::DeviceManagerV2;
 use crate::device::error::InitDeviceError;
+use crate::device::pci_hotplug::PCI_HOTPLUG_SLOT_MMIO_SIZE;
+use crate::device::pci_hotplug::PciHotplugManager;
 use crate::service::monitor::builder::MonitorServerBuilder;
 #[cfg(target_arch = "aarch64")]
 use crate::vm::device_builder::arch::aarch64::mmio_allocator;
@@ -39,14 +57,23 @@ mod arch;
 mod vfio;
 
 pub struct DeviceManagerBuilder<'a> {
-    #[allow(dead_code)]
     vm: Arc<dyn HypervisorVm>,
     interrupt_manager: Arc<InterruptManager>,
     irq_chip: Arc<dyn InterruptController>,
     memory: Arc<MemoryAddressSpace>,
     monitor_server_builder: &'a mut MonitorServerBuilder,
+    rtc: RtcConfig,
+    serial: SerialConfig,
+    /// Every vcpu the guest may have, including the ones plugged at runtime
+    cpu_topology: CpuTopology,
+    /// The vcpus created at boot if the others are hotpluggable
+    present_vcpus: Option<u32>,
+    /// Where the guest requests to power off or to reset
+    system_event_notifier: Arc<dyn SystemEventNotifier>,
 
     device_manager: DeviceManagerV2,
+    /// The pci devices are put behind it if it is set
+    virtio_iommu: Option<Arc<VirtioIommuState>>,
 
     #[cfg(target_os = "linux")]
     vfio_container: OnceCell<VfioContainer>,
@@ -59,7 +86,11 @@ pub struct DeviceManagerBuilder<'a> {
     pci_pio_allocator: OnceCell<RangeAllocator<u16>>,
     mmio_allocator: RangeAllocator<u64>,
     pci_mmio_allocator: OnceCell<RangeAllocator<u64>>,
+    /// Empty if the 64-bit window is disabled
+    pci_mmio64_allocator: OnceCell<RangeAllocator<u64>>,
     virtio_mmio_index_allocator: RangeAllocator<u8>,
+    /// Regions in the hotplug window, one per virtio-mem device in order
+    memory_hotplug_regions: VecDeque<(u64, usize)>,
 }
 
 impl<'a> DeviceManagerBuilder<'a> {
@@ -70,13 +101,19 @@ impl<'a> DeviceManagerBuilder<'a> {
     ) -> Result<(), InitDeviceError> {
         match device {
             Device::GicV3 => todo!(),
-            Device::VirtioBlk { transport } => {
-                let dev = VirtioBlkDevice::new(self.memory.clone());
+            Device::VirtioBlk {
+                transport,
+                path,
+                read_only,
+            } => {
+                let dev = VirtioBlkDevice::new(self.memory.clone(), path.clone(), *read_only)?;
 
                 match transport {
                     VirtioTransport::Mmio => {
                         self.device_manager
                             .attach_device(Box::new(dev.into_mmio_device(
+                                #[cfg(target_os = "linux")]
+                                self.vm.clone(),
                                 &mut self.mmio_allocator,
                                 &self.interrupt_manager,
                                 &mut self.virtio_mmio_index_allocator,
@@ -86,17 +123,16 @@ impl<'a> DeviceManagerBuilder<'a> {
                             )?))?;
                     }
                     VirtioTransport::Pci => {
-                        pci_root_complex
-                            .register_device(Box::new(dev.into_pci_device(
-                                #[cfg(target_arch = "x86_64")]
-                                self.pci_pio_allocator.get_mut().unwrap(),
-                                self.pci_mmio_allocator.get_mut().unwrap(),
-                                &self.interrupt_manager,
-                                tokio::runtime::Handle::current(),
-                                self.memory.clone(),
-                                self.irq_chip.clone(),
-                            )?))
-                            .map_err(|_| InitDeviceError::RegisterPciDevice)?;
+                        let device = dev.into_virtio_pci_device(
+                            #[cfg(target_os = "linux")]
+                            self.vm.clone(),
+                            &self.interrupt_manager,
+                            tokio::runtime::Handle::current(),
+                            self.memory.clone(),
+                            self.irq_chip.clone(),
+                        )?;
+
+                        self.register_virtio_pci_device(pci_root_complex, device)?;
                     }
                 }
             }
@@ -104,12 +140,15 @@ impl<'a> DeviceManagerBuilder<'a> {
                 let dev = VirtioBalloonTranditional::new(self.memory.clone());
 
                 let cfg = dev.get_cfg();
+                let stats = dev.get_stats();
 
                 let configuration_change_notifier;
 
                 match transport {
                     VirtioTransport::Mmio => {
                         let device = dev.into_mmio_device(
+                            #[cfg(target_os = "linux")]
+                            self.vm.clone(),
                             &mut self.mmio_allocator,
                             &self.interrupt_manager,
                             &mut self.virtio_mmio_index_allocator,
@@ -124,6 +163,8 @@ impl<'a> DeviceManagerBuilder<'a> {
                     }
                     VirtioTransport::Pci => {
                         let device = dev.into_virtio_pci_device(
+                            #[cfg(target_os = "linux")]
+                            self.vm.clone(),
                             &self.interrupt_manager,
                             tokio::runtime::Handle::current(),
                             self.memory.clone(),
@@ -132,19 +173,11 @@ impl<'a> DeviceManagerBuilder<'a> {
 
                         configuration_change_notifier = device.configuration_change_notifier();
 
-                        let device = device.into_pci_device(
-                            #[cfg(target_arch = "x86_64")]
-                            self.pci_pio_allocator.get_mut().unwrap(),
-                            self.pci_mmio_allocator.get_mut().unwrap(),
-                        )?;
-
-                        pci_root_complex
-                            .register_device(Box::new(device))
-                            .map_err(|_| InitDeviceError::RegisterPciDevice)?;
+                        self.register_virtio_pci_device(pci_root_complex, device)?;
                     }
                 }
 
-                let monitor = VirtioBalloonMonitor::new(cfg, configuration_change_notifier);
+                let monitor = VirtioBalloonMonitor::new(cfg, stats, configuration_change_notifier);
                 self.monitor_server_builder
                     .register_command_handler("balloon", Box::new(monitor))
                     .map_err(|_| InitDeviceError::RegisterMonitorCommand {
@@ -158,6 +191,8 @@ impl<'a> DeviceManagerBuilder<'a> {
                     VirtioTransport::Mmio => {
                         self.device_manager
                             .attach_device(Box::new(dev.into_mmio_device(
+                                #[cfg(target_os = "linux")]
+                                self.vm.clone(),
                                 &mut self.mmio_allocator,
                                 &self.interrupt_manager,
                                 &mut self.virtio_mmio_index_allocator,
@@ -167,17 +202,16 @@ impl<'a> DeviceManagerBuilder<'a> {
                             )?))?;
                     }
                     VirtioTransport::Pci => {
-                        pci_root_complex
-                            .register_device(Box::new(dev.into_pci_device(
-                                #[cfg(target_arch = "x86_64")]
-                                self.pci_pio_allocator.get_mut().unwrap(),
-                                self.pci_mmio_allocator.get_mut().unwrap(),
-                                &self.interrupt_manager,
-                                tokio::runtime::Handle::current(),
-                                self.memory.clone(),
-                                self.irq_chip.clone(),
-                            )?))
-                            .map_err(|_| InitDeviceError::RegisterPciDevice)?;
+                        let device = dev.into_virtio_pci_device(
+                            #[cfg(target_os = "linux")]
+                            self.vm.clone(),
+                            &self.interrupt_manager,
+                            tokio::runtime::Handle::current(),
+                            self.memory.clone(),
+                            self.irq_chip.clone(),
+                        )?;
+
+                        self.register_virtio_pci_device(pci_root_complex, device)?;
                     }
                 }
             }
@@ -187,6 +221,8 @@ impl<'a> DeviceManagerBuilder<'a> {
                     VirtioTransport::Mmio => {
                         self.device_manager
                             .attach_device(Box::new(dev.into_mmio_device(
+                                #[cfg(target_os = "linux")]
+                                self.vm.clone(),
                                 &mut self.mmio_allocator,
                                 &self.interrupt_manager,
                                 &mut self.virtio_mmio_index_allocator,
@@ -196,38 +232,231 @@ impl<'a> DeviceManagerBuilder<'a> {
                             )?))?;
                     }
                     VirtioTransport::Pci => {
-                        pci_root_complex
-                            .register_device(Box::new(dev.into_pci_device(
-                                #[cfg(target_arch = "x86_64")]
-                                self.pci_pio_allocator.get_mut().unwrap(),
-                                self.pci_mmio_allocator.get_mut().unwrap(),
+                        let device = dev.into_virtio_pci_device(
+                            #[cfg(target_os = "linux")]
+                            self.vm.clone(),
+                            &self.interrupt_manager,
+                            tokio::runtime::Handle::current(),
+                            self.memory.clone(),
+                            self.irq_chip.clone(),
+                        )?;
+
+                        self.register_virtio_pci_device(pci_root_complex, device)?;
+                    }
+                }
+            }
+            Device::Virtio9p {
+                transport,
+                tag,
+                path,
+                read_only,
+                uid_map,
+                gid_map,
+            } => {
+                let dev = Virtio9p::new(
+                    self.memory.clone(),
+                    tag,
+                    path.clone(),
+                    *read_only,
+                    uid_map.clone(),
+                    gid_map.clone(),
+                )?;
+
+                match transport {
+                    VirtioTransport::Mmio => {
+                        self.device_manager
+                            .attach_device(Box::new(dev.into_mmio_device(
+                                #[cfg(target_os = "linux")]
+                                self.vm.clone(),
+                                &mut self.mmio_allocator,
                                 &self.interrupt_manager,
+                                &mut self.virtio_mmio_index_allocator,
                                 tokio::runtime::Handle::current(),
                                 self.memory.clone(),
                                 self.irq_chip.clone(),
-                            )?))
-                            .map_err(|_| InitDeviceError::RegisterPciDevice)?;
+                            )?))?;
+                    }
+                    VirtioTransport::Pci => {
+                        let device = dev.into_virtio_pci_device(
+                            #[cfg(target_os = "linux")]
+                            self.vm.clone(),
+                            &self.interrupt_manager,
+                            tokio::runtime::Handle::current(),
+                            self.memory.clone(),
+                            self.irq_chip.clone(),
+                        )?;
+
+                        self.register_virtio_pci_device(pci_root_complex, device)?;
                     }
                 }
             }
+            Device::VirtioMem {
+                transport,
+                region_size,
+                block_size,
+                requested_size,
+            } => {
+                let (addr, len) = self
+                    .memory_hotplug_regions
+                    .pop_front()
+                    .ok_or(InitDeviceError::MemoryHotplugRegionNotReserved)?;
+                if len != *region_size {
+                    return Err(InitDeviceError::MemoryHotplugRegionNotReserved);
+                }
+
+                let dev = VirtioMem::new(
+                    self.vm.clone(),
+                    self.memory.clone(),
+                    addr,
+                    *region_size,
+                    *block_size,
+                    *requested_size,
+                )?;
+
+                let cfg = dev.get_cfg();
+                self.device_manager.add_virtio_mem_reset(dev.system_reset());
+
+                let configuration_change_notifier;
+
+                match transport {
+                    VirtioTransport::Mmio => {
+                        let device = dev.into_mmio_device(
+                            #[cfg(target_os = "linux")]
+                            self.vm.clone(),
+                            &mut self.mmio_allocator,
+                            &self.interrupt_manager,
+                            &mut self.virtio_mmio_index_allocator,
+                            tokio::runtime::Handle::current(),
+                            self.memory.clone(),
+                            self.irq_chip.clone(),
+                        )?;
+
+                        configuration_change_notifier = device.configuration_change_notifier();
+
+                        self.device_manager.attach_device(Box::new(device))?;
+                    }
+                    VirtioTransport::Pci => {
+                        let device = dev.into_virtio_pci_device(
+                            #[cfg(target_os = "linux")]
+                            self.vm.clone(),
+                            &self.interrupt_manager,
+                            tokio::runtime::Handle::current(),
+                            self.memory.clone(),
+                            self.irq_chip.clone(),
+                        )?;
+
+                        configuration_change_notifier = device.configuration_change_notifier();
+
+                        self.register_virtio_pci_device(pci_root_complex, device)?;
+                    }
+                }
+
+                let monitor = VirtioMemMonitor::new(cfg, configuration_change_notifier);
+                self.monitor_server_builder
+                    .register_command_handler("virtio-mem", Box::new(monitor))
+                    .map_err(|_| InitDeviceError::RegisterMonitorCommand {
+                        device: "virtio-mem".to_string(),
+                    })?;
+            }
+            // Created by `init_virtio_iommu` before the other devices
+            Device::VirtioIommu => {}
             #[cfg(target_os = "linux")]
             Device::VfioPci { name, path } => {
-                let vfio_deivce = self.init_vfio_device(name.to_string(), path)?;
+                let (vfio_deivce, endpoint) = self.init_vfio_device(name.to_string(), path)?;
 
-                self.need_dma_map = true;
-
-                pci_root_complex
+                let rid = pci_root_complex
                     .register_device(Box::new(vfio_deivce))
                     .map_err(|_| {
                         InitDeviceError::PciDevice(vm_pci::error::Error::FailedRegisterPciDevice)
                     })?;
+                self.add_iommu_endpoint(rid, endpoint)?;
+            }
+            #[cfg(target_os = "linux")]
+            Device::VfioUser { name, socket } => {
+                let (vfio_device, endpoint) =
+                    self.init_vfio_user_device(name.to_string(), socket)?;
+
+                let rid = pci_root_complex
+                    .register_device(Box::new(vfio_device))
+                    .map_err(|_| {
+                        InitDeviceError::PciDevice(vm_pci::error::Error::FailedRegisterPciDevice)
+                    })?;
+                self.add_iommu_endpoint(rid, endpoint)?;
             }
         }
 
         Ok(())
     }
 
-    fn init_pci_root_complex(&mut self) -> Result<PciRootComplexDevice, InitDeviceError> {
+    /// Put the device behind the virtio-iommu if there is one
+    fn register_virtio_pci_device<D>(
+        &mut self,
+        pci_root_complex: &mut PciRootComplexDevice,
+        device: VirtioPciTransport<D>,
+    ) -> Result<(), InitDeviceError>
+    where
+        D: VirtioPciDevice,
+    {
+        let endpoint = self
+            .virtio_iommu
+            .as_ref()
+            .map(|iommu| iommu.new_endpoint(None));
+        if let Some(endpoint) = &endpoint {
+            device.set_dma_translator(endpoint.clone());
+        }
+
+        let device = device.into_pci_device(
+            #[cfg(target_arch = "x86_64")]
+            self.pci_pio_allocator.get_mut().unwrap(),
+            self.pci_mmio_allocator.get_mut().unwrap(),
+            self.pci_mmio64_allocator.get_mut().unwrap(),
+        )?;
+
+        let rid = pci_root_complex
+            .register_device(Box::new(device))
+            .map_err(|_| InitDeviceError::RegisterPciDevice)?;
+
+        self.add_iommu_endpoint(rid, endpoint)
+    }
+
+    /// The endpoint id of a pci device is its requester id
+    fn add_iommu_endpoint(
+        &self,
+        rid: u16,
+        endpoint: Option<Arc<IommuEndpoint>>,
+    ) -> Result<(), InitDeviceError> {
+        if let (Some(iommu), Some(endpoint)) = (&self.virtio_iommu, endpoint) {
+            iommu
+                .add_endpoint(rid.into(), endpoint)
+                .map_err(InitDeviceError::IommuEndpoint)?;
+        }
+
+        Ok(())
+    }
+
+    fn init_virtio_iommu(&mut self) -> Result<(), InitDeviceError> {
+        let dev = VirtioIommu::new(self.memory.clone());
+        self.virtio_iommu = Some(dev.get_state());
+
+        self.device_manager
+            .attach_device(Box::new(dev.into_mmio_device(
+                #[cfg(target_os = "linux")]
+                self.vm.clone(),
+                &mut self.mmio_allocator,
+                &self.interrupt_manager,
+                &mut self.virtio_mmio_index_allocator,
+                tokio::runtime::Handle::current(),
+                self.memory.clone(),
+                self.irq_chip.clone(),
+            )?))?;
+
+        Ok(())
+    }
+
+    fn init_pci_root_complex(
+        &mut self,
+        pci_mmio64_size: usize,
+    ) -> Result<PciRootComplexDevice, InitDeviceError> {
         #[cfg(target_arch = "x86_64")]
         {
             let mut pci_io_port_allocator = RangeAllocator::<u16>::default();
@@ -248,10 +477,24 @@ impl<'a> DeviceManagerBuilder<'a> {
             assert!(self.pci_mmio_allocator.set(pci_mmio_allocator).is_ok());
         }
 
+        let pci_mmio64_window = (pci_mmio64_size > 0).then(|| {
+            PCI_BAR_MMIO64_WINDOW_START..PCI_BAR_MMIO64_WINDOW_START + pci_mmio64_size as u64
+        });
+        {
+            let mut pci_mmio64_allocator = RangeAllocator::<u64>::default();
+            if let Some(window) = &pci_mmio64_window {
+                pci_mmio64_allocator
+                    .insert(window.start, pci_mmio64_size)
+                    .map_err(|err| InitDeviceError::AllocResource(Box::new(err)))?;
+            }
+            assert!(self.pci_mmio64_allocator.set(pci_mmio64_allocator).is_ok());
+        }
+
         Ok(PciRootComplexDevice::new(
             #[cfg(target_arch = "x86_64")]
             self.pci_pio_allocator.get_mut().unwrap(),
             self.pci_mmio_allocator.get_mut().unwrap(),
+            self.pci_mmio64_allocator.get_mut().unwrap(),
             #[cfg(target_arch = "x86_64")]
             &mut self.pio_allocator,
             &mut self.mmio_allocator,
@@ -260,15 +503,95 @@ impl<'a> DeviceManagerBuilder<'a> {
             ECAM_BASE as u64..ECAM_BASE as u64 + ECAM_LENGTH as u64,
             PCI_BAR_MMIO_WINDOW_START as u64
                 ..PCI_BAR_MMIO_WINDOW_START as u64 + PCI_BAR_MMIO_WINDOW_LENGTH as u64,
+            pci_mmio64_window,
+            #[cfg(target_arch = "x86_64")]
+            self.system_event_notifier.clone(),
         )?)
     }
 
+    #[cfg(target_arch = "aarch64")]
+    fn cpu_hotplug_enabled(&self) -> bool {
+        self.present_vcpus.is_some()
+    }
+
+    /// The controller reporting the vcpus plugged at runtime, it is scanned on a ged event
+    fn init_cpu_hotplug(&mut self, ged: &mut Ged) -> Result<(), InitDeviceError> {
+        let Some(present_vcpus) = self.present_vcpus else {
+            return Ok(());
+        };
+
+        let controller = CpuHotplugController::new(
+            &mut self.mmio_allocator,
+            self.cpu_topology,
+            present_vcpus,
+            ged.cpu_hotplug_event(),
+            self.system_event_notifier.clone(),
+        )?;
+        self.device_manager
+            .set_cpu_hotplug(controller.cpu_hotplug());
+        self.device_manager.attach_device(Box::new(controller))?;
+
+        Ok(())
+    }
+
+    /// Root ports with a hot-plug slot each, for the devices added at runtime
+    fn init_pci_hotplug(
+        &mut self,
+        pci_root_complex: &mut PciRootComplexDevice,
+        slots: usize,
+    ) -> Result<(), InitDeviceError> {
+        if slots == 0 {
+            return Ok(());
+        }
+
+        let mut pci_hotplug = PciHotplugManager::new(
+            #[cfg(target_os = "linux")]
+            self.vm.clone(),
+            self.interrupt_manager.clone(),
+            self.irq_chip.clone(),
+            self.memory.clone(),
+            self.system_event_notifier.clone(),
+        );
+
+        // Taken from the top of the bar window, the boot devices are allocated from the bottom
+        let window_end = PCI_BAR_MMIO_WINDOW_START as u64 + PCI_BAR_MMIO_WINDOW_LENGTH as u64;
+        for i in 1..=slots {
+            let mmio_window = self
+                .pci_mmio_allocator
+                .get_mut()
+                .unwrap()
+                .reserve(
+                    window_end.saturating_sub((i * PCI_HOTPLUG_SLOT_MMIO_SIZE) as u64),
+                    PCI_HOTPLUG_SLOT_MMIO_SIZE,
+                )
+                .map_err(|err| InitDeviceError::AllocResource(Box::new(err)))?;
+            let irq = self.interrupt_manager.allocate_irq()?.try_into().unwrap();
+
+            let slot = pci_root_complex.add_hotplug_root_port(
+                irq,
+                self.irq_chip.clone(),
+                mmio_window.clone(),
+            )?;
+            pci_hotplug.add_slot(slot, mmio_window);
+        }
+
+        self.device_manager.set_pci_hotplug(pci_hotplug);
+
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         vm: Arc<dyn HypervisorVm>,
         irq_chip: Arc<dyn InterruptController>,
         interrupt_manager: InterruptManager,
         memory: Arc<MemoryAddressSpace>,
         monitor_server_builder: &'a mut MonitorServerBuilder,
+        rtc: RtcConfig,
+        serial: SerialConfig,
+        cpu_topology: CpuTopology,
+        present_vcpus: Option<u32>,
+        system_event_notifier: Arc<dyn SystemEventNotifier>,
     ) -> Result<Self, InitDeviceError> {
         let interrupt_manager = Arc::new(interrupt_manager);
         let device_manager = DeviceManagerV2::default();
@@ -276,13 +599,25 @@ impl<'a> DeviceManagerBuilder<'a> {
         let mut virtio_mmio_index_allocator = RangeAllocator::<u8>::default();
         virtio_mmio_index_allocator.insert(0, 128).unwrap();
 
+        let memory_hotplug_regions = memory
+            .regions()
+            .range(MEMORY_HOTPLUG_START..)
+            .map(|(gpa, region)| (*gpa, region.len()))
+            .collect();
+
         Ok(DeviceManagerBuilder {
             vm,
             interrupt_manager,
             irq_chip,
             memory,
             monitor_server_builder,
+            rtc,
+            serial,
+            cpu_topology,
+            present_vcpus,
+            system_event_notifier,
             device_manager,
+            virtio_iommu: None,
 
             #[cfg(target_os = "linux")]
             vfio_container: Default::default(),
@@ -295,18 +630,35 @@ impl<'a> DeviceManagerBuilder<'a> {
             pci_pio_allocator: Default::default(),
             mmio_allocator: mmio_allocator(),
             pci_mmio_allocator: Default::default(),
+            pci_mmio64_allocator: Default::default(),
             virtio_mmio_index_allocator,
+            memory_hotplug_regions,
         })
     }
 
-    pub fn build(mut self, devices: &[Device]) -> Result<DeviceManagerV2, InitDeviceError> {
+    pub fn build(
+        mut self,
+        devices: &[Device],
+        pci_hotplug_slots: usize,
+        pci_mmio64_size: usize,
+    ) -> Result<DeviceManagerV2, InitDeviceError> {
+        #[cfg(target_arch = "x86_64")]
+        self.interrupt_manager.reserve_irq(i8042::AUX_IRQ)?;
+
         #[cfg(target_os = "linux")]
         self.init_vfio()?;
 
-        let mut pci_root_complex = self.init_pci_root_complex()?;
+        let mut pci_root_complex = self.init_pci_root_complex(pci_mmio64_size)?;
+        self.init_pci_hotplug(&mut pci_root_complex, pci_hotplug_slots)?;
 
         self.init_device_arch()?;
 
+        if devices.contains(&Device::VirtioIommu) {
+            self.init_virtio_iommu()?;
+            #[cfg(target_arch = "aarch64")]
+            pci_root_complex.set_iommu_map();
+        }
+
         for device in devices {
             self.init_device(&mut pci_root_complex, device)?;
         }
@@ -1,6 +1,9 @@
 use vm_core::arch::aarch64::layout::*;
 
+use vm_device::device::ged::Ged;
 use vm_device::device::pl011::Pl011;
+use vm_device::device::pl031::Pl031;
+use vm_device::device::pl061::Pl061;
 use vm_utils::range_allocator::RangeAllocator;
 
 use crate::device::error::InitDeviceError;
@@ -31,10 +34,42 @@ impl<'a> DeviceManagerBuilder<'a> {
                 &mut self.mmio_allocator,
                 self.interrupt_manager.allocate_irq()?,
                 self.irq_chip.clone(),
+                &self.serial,
             )?;
             self.device_manager.attach_device(Box::new(pl011))?;
         }
 
+        {
+            let pl031 = Pl031::new(
+                &mut self.mmio_allocator,
+                self.interrupt_manager.allocate_irq()?,
+                self.irq_chip.clone(),
+                &self.rtc,
+            )?;
+            self.device_manager.attach_device(Box::new(pl031))?;
+        }
+
+        {
+            let pl061 = Pl061::new(
+                &mut self.mmio_allocator,
+                self.interrupt_manager.allocate_irq()?,
+                self.irq_chip.clone(),
+            )?;
+            self.device_manager.set_power_button(pl061.power_button());
+            self.device_manager.attach_device(Box::new(pl061))?;
+        }
+
+        // The ged is only needed to signal the cpu hotplug events, the power button is the pl061
+        if self.cpu_hotplug_enabled() {
+            let mut ged = Ged::new(
+                &mut self.mmio_allocator,
+                self.interrupt_manager.allocate_irq()?,
+                self.irq_chip.clone(),
+            )?;
+            self.init_cpu_hotplug(&mut ged)?;
+            self.device_manager.attach_device(Box::new(ged))?;
+        }
+
         Ok(())
     }
 }
@@ -1,6 +1,9 @@
 use vm_core::arch::x86_64::layout::*;
+use vm_device::device::acpi_pm::AcpiPm;
 use vm_device::device::cmos::Cmos;
 use vm_device::device::dummy::Dummy;
+use vm_device::device::ged::Ged;
+use vm_device::device::i8042::I8042;
 use vm_device::device::post_debug::PostDebug;
 use vm_device::device::uart8250::Uart8250;
 use vm_utils::range_allocator::RangeAllocator;
@@ -37,23 +40,34 @@ pub fn mmio_allocator() -> RangeAllocator<u64> {
 
 impl<'a> DeviceManagerBuilder<'a> {
     pub fn init_device_arch(&mut self) -> Result<(), InitDeviceError> {
-        let uart8250_com1 =
-            Uart8250::<4>::new(&mut self.pio_allocator, 0x3f8, self.irq_chip.clone(), true)?;
+        let uart8250_com1 = Uart8250::<4>::new(
+            &mut self.pio_allocator,
+            0x3f8,
+            self.irq_chip.clone(),
+            Some(&self.serial),
+        )?;
         self.device_manager.attach_device(Box::new(uart8250_com1))?;
 
         let uart8250_com2 =
-            Uart8250::<3>::new(&mut self.pio_allocator, 0x2f8, self.irq_chip.clone(), false)?;
+            Uart8250::<3>::new(&mut self.pio_allocator, 0x2f8, self.irq_chip.clone(), None)?;
         self.device_manager.attach_device(Box::new(uart8250_com2))?;
 
         let uart8250_com3 =
-            Uart8250::<4>::new(&mut self.pio_allocator, 0x3e8, self.irq_chip.clone(), false)?;
+            Uart8250::<4>::new(&mut self.pio_allocator, 0x3e8, self.irq_chip.clone(), None)?;
         self.device_manager.attach_device(Box::new(uart8250_com3))?;
 
         let uart8250_com4 =
-            Uart8250::<3>::new(&mut self.pio_allocator, 0x2e8, self.irq_chip.clone(), false)?;
+            Uart8250::<3>::new(&mut self.pio_allocator, 0x2e8, self.irq_chip.clone(), None)?;
         self.device_manager.attach_device(Box::new(uart8250_com4))?;
 
-        let cmos = Cmos::new(&mut self.pio_allocator)?;
+        let cmos = Cmos::new(&mut self.pio_allocator, self.irq_chip.clone(), &self.rtc)?;
+        // The ram is a single region below the 32-bit mmio hole
+        let ram_size = self
+            .memory
+            .regions()
+            .get(&RAM_BASE)
+            .map_or(0, |region| region.len() as u64);
+        cmos.set_memory_size(ram_size, 0);
         self.device_manager.attach_device(Box::new(cmos))?;
 
         let post_debug = PostDebug::new(&mut self.pio_allocator)?;
@@ -62,8 +76,25 @@ impl<'a> DeviceManagerBuilder<'a> {
         let dummy = Dummy::new(&mut self.pio_allocator)?;
         self.device_manager.attach_device(Box::new(dummy))?;
 
-        // let i8042 = I8042::new(self.irq_chip.clone());
-        // self.device_manager.attach_device(Box::new(i8042))?;
+        let acpi_pm = AcpiPm::new(&mut self.pio_allocator, self.system_event_notifier.clone())?;
+        self.device_manager.attach_device(Box::new(acpi_pm))?;
+
+        let mut ged = Ged::new(
+            &mut self.mmio_allocator,
+            self.interrupt_manager.allocate_irq()?,
+            self.irq_chip.clone(),
+        )?;
+        self.init_cpu_hotplug(&mut ged)?;
+        self.device_manager.set_power_button(ged.power_button());
+        self.device_manager.attach_device(Box::new(ged))?;
+
+        // The guest resets through it with 0xfe
+        let i8042 = I8042::new(
+            &mut self.pio_allocator,
+            self.irq_chip.clone(),
+            self.system_event_notifier.clone(),
+        )?;
+        self.device_manager.attach_device(Box::new(i8042))?;
 
         Ok(())
     }
@@ -1,12 +1,75 @@
 use std::path::Path;
+use std::sync::Arc;
 
+use vm_device::device::virtio::virtio_iommu::endpoint::DmaMapListener;
+use vm_device::device::virtio::virtio_iommu::endpoint::DmaMapResult;
+use vm_device::device::virtio::virtio_iommu::endpoint::IommuEndpoint;
+use vm_mm::manager::MemoryAddressSpace;
 use vm_vfio::vfio::container::VfioContainer;
 use vm_vfio::vfio::device::VfioDevice;
 use vm_vfio::vfio_pci::device::VfioPciDevice;
+use vm_vfio::vfio_user::client::VfioUserClient;
 
 use crate::device::error::InitDeviceError;
 use crate::vm::device_builder::DeviceManagerBuilder;
 
+/// A vfio device behind the virtio-iommu, the container of the device only has the
+/// mappings of its domain
+struct VfioIommuListener {
+    container: VfioContainer,
+    memory: Arc<MemoryAddressSpace>,
+}
+
+impl DmaMapListener for VfioIommuListener {
+    fn map(&self, iova: u64, gpa: u64, size: u64) -> DmaMapResult {
+        let hva = self.memory.gpa_to_hva(gpa)?;
+
+        // SAFETY: The guest memory lives as long as the vm
+        unsafe {
+            self.container.vfio_dma_map(iova, size as usize, hva)?;
+        }
+
+        Ok(())
+    }
+
+    fn unmap(&self, iova: u64, size: u64) -> DmaMapResult {
+        self.container.vfio_dma_unmap(iova, size as usize)?;
+
+        Ok(())
+    }
+}
+
+/// A vfio-user device behind the virtio-iommu, the server maps the shared fds by iova
+struct VfioUserIommuListener {
+    client: Arc<VfioUserClient>,
+    memory: Arc<MemoryAddressSpace>,
+}
+
+impl DmaMapListener for VfioUserIommuListener {
+    fn map(&self, iova: u64, gpa: u64, size: u64) -> DmaMapResult {
+        let (region_gpa, region) = self
+            .memory
+            .regions()
+            .range(..=gpa)
+            .next_back()
+            .filter(|(region_gpa, region)| gpa + size <= **region_gpa + region.len() as u64)
+            .ok_or_else(|| format!("{gpa:#x} is not in the guest memory"))?;
+        let fd = region
+            .fd()
+            .ok_or_else(|| format!("{region_gpa:#x} is not shared with the server"))?;
+
+        self.client.dma_map(iova, size, fd, gpa - region_gpa)?;
+
+        Ok(())
+    }
+
+    fn unmap(&self, iova: u64, size: u64) -> DmaMapResult {
+        self.client.dma_unmap(iova, size)?;
+
+        Ok(())
+    }
+}
+
 impl<'a> DeviceManagerBuilder<'a> {
     pub fn init_vfio(&mut self) -> Result<(), InitDeviceError> {
         let vfio_container = VfioContainer::new()?;
@@ -18,29 +81,94 @@ impl<'a> DeviceManagerBuilder<'a> {
         Ok(())
     }
 
+    /// Returns the iommu endpoint of the device if there is a virtio-iommu
     pub fn init_vfio_device(
         &mut self,
         name: String,
         path: &Path,
-    ) -> Result<VfioPciDevice, InitDeviceError> {
-        let container = self
-            .vfio_container
-            .get()
-            .ok_or(InitDeviceError::VfioContainerNotInit)?;
+    ) -> Result<(VfioPciDevice, Option<Arc<IommuEndpoint>>), InitDeviceError> {
+        // The devices behind the iommu are isolated from each other
+        let own_container = self
+            .virtio_iommu
+            .is_some()
+            .then(VfioContainer::new)
+            .transpose()?;
+        let container = match &own_container {
+            Some(container) => container,
+            None => self
+                .vfio_container
+                .get()
+                .ok_or(InitDeviceError::VfioContainerNotInit)?,
+        };
 
         let vfio_device = VfioDevice::new(path, container)?;
 
+        let endpoint = match (&self.virtio_iommu, own_container) {
+            (Some(iommu), Some(container)) => {
+                Some(iommu.new_endpoint(Some(Arc::new(VfioIommuListener {
+                    container,
+                    memory: self.memory.clone(),
+                }))))
+            }
+            _ => {
+                self.need_dma_map = true;
+                None
+            }
+        };
+
+        let vfio_pci_device = VfioPciDevice::new(
+            name,
+            self.vm.clone(),
+            #[cfg(target_arch = "x86_64")]
+            self.pci_pio_allocator.get_mut().unwrap(),
+            self.pci_mmio_allocator.get_mut().unwrap(),
+            self.pci_mmio64_allocator.get_mut().unwrap(),
+            self.interrupt_manager.clone(),
+            Arc::new(vfio_device),
+        )?;
+
+        Ok((vfio_pci_device, endpoint))
+    }
+
+    /// Returns the iommu endpoint of the device if there is a virtio-iommu
+    pub fn init_vfio_user_device(
+        &mut self,
+        name: String,
+        socket: &Path,
+    ) -> Result<(VfioPciDevice, Option<Arc<IommuEndpoint>>), InitDeviceError> {
+        let client = Arc::new(VfioUserClient::connect(socket)?);
+
+        let endpoint = match &self.virtio_iommu {
+            Some(iommu) => Some(iommu.new_endpoint(Some(Arc::new(VfioUserIommuListener {
+                client: client.clone(),
+                memory: self.memory.clone(),
+            })))),
+            None => {
+                // The server accesses the guest memory through the shared fds
+                for region in self.memory.regions().values() {
+                    let fd = region
+                        .fd()
+                        .ok_or(InitDeviceError::VfioUserMemoryNotShared { gpa: region.gpa })?;
+
+                    client.dma_map(region.gpa, region.len() as u64, fd, 0)?;
+                }
+
+                None
+            }
+        };
+
         let vfio_pci_device = VfioPciDevice::new(
             name,
             self.vm.clone(),
             #[cfg(target_arch = "x86_64")]
             self.pci_pio_allocator.get_mut().unwrap(),
             self.pci_mmio_allocator.get_mut().unwrap(),
+            self.pci_mmio64_allocator.get_mut().unwrap(),
             self.interrupt_manager.clone(),
-            vfio_device,
+            client,
         )?;
 
-        Ok(vfio_pci_device)
+        Ok((vfio_pci_device, endpoint))
     }
 
     pub fn vfio_dma_map(&mut self) -> Result<(), InitDeviceError> {
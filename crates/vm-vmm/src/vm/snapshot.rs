@@ -1,22 +1,37 @@
+use std::fs;
 use std::io::Cursor;
 use std::path::Path;
 use std::sync::Arc;
 
 use serde::Deserialize;
 use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
 use tokio::fs::read;
 use tokio::sync::Mutex;
 use tokio::sync::mpsc;
+use vm_bootloader::boot_loader::firmware::is_firmware_code;
 #[cfg(target_arch = "aarch64")]
-use vm_core::arch::aarch64::firmware::psci::psci_0_2::Psci02;
+use vm_core::arch::aarch64::firmware::psci::psci_1_1::Psci11;
+#[cfg(target_arch = "aarch64")]
+use vm_core::arch::aarch64::layout::MEMORY_HOTPLUG_LEN;
+#[cfg(target_arch = "aarch64")]
+use vm_core::arch::aarch64::layout::MEMORY_HOTPLUG_START;
 use vm_core::arch::irq::InterruptController;
+#[cfg(target_arch = "x86_64")]
+use vm_core::arch::x86_64::layout::MEMORY_HOTPLUG_LEN;
+#[cfg(target_arch = "x86_64")]
+use vm_core::arch::x86_64::layout::MEMORY_HOTPLUG_START;
 use vm_core::cpu::vcpu_manager::VcpuManager;
 use vm_core::cpu::vcpu_manager::snapshot::VcpuManagerSnapshot;
+use vm_core::device::system_event::SystemEventNotifier;
 use vm_core::virtualization::hypervisor::Hypervisor;
 use vm_core::virtualization::vm::SetUserMemoryRegionFlags;
 use vm_device::device::Device;
 use vm_mm::manager::MemoryAddressSpace;
 use vm_mm::manager::snapshot::MemoryAddressSpaceSnapshot;
+use vm_snapshot::section::Section;
+use vm_snapshot::section::Sections;
 
 use crate::device::device_manager_v2::snapshot::DeviceSnapshot;
 use crate::service::gdbstub::connection::VmGdbStubConnector;
@@ -29,17 +44,176 @@ use crate::vm::vm_exit_handler::VmExitHandler;
 use crate::vmm::error::VmSnapshotError;
 use crate::vmm::error::VmmError;
 use crate::vmm::handler::VmmCommand;
+use crate::vmm::handler::system_event::VmmSystemEventNotifier;
+
+/// The first bytes of a snapshot file
+const SNAPSHOT_MAGIC: [u8; 8] = *b"RVMSNAP\0";
+
+/// Bumped when the layout of `VmSnapshot` changes, the devices have their own versions
+const SNAPSHOT_FORMAT_VERSION: u32 = 2;
+
+/// Bumped when the layout of the section changes
+const VM_CONFIG_SNAPSHOT_VERSION: u32 = 1;
+const VCPUS_SNAPSHOT_VERSION: u32 = 1;
+const IRQ_CHIP_SNAPSHOT_VERSION: u32 = 1;
+
+/// Follows the magic, its layout must not change so that any version can be reported
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnapshotHeader {
+    pub format_version: u32,
+    pub arch: String,
+    pub hypervisor: String,
+    pub vmm_version: String,
+}
+
+impl SnapshotHeader {
+    fn new(hypervisor: &str) -> Self {
+        SnapshotHeader {
+            format_version: SNAPSHOT_FORMAT_VERSION,
+            arch: std::env::consts::ARCH.to_string(),
+            hypervisor: hypervisor.to_string(),
+            vmm_version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+
+    /// The snapshot is restored by the same arch, hypervisor, format and vmm only
+    fn check(&self, hypervisor: &'static str) -> Result<(), VmSnapshotError> {
+        if self.format_version != SNAPSHOT_FORMAT_VERSION {
+            return Err(VmSnapshotError::FormatVersionMismatch {
+                found: self.format_version,
+                expected: SNAPSHOT_FORMAT_VERSION,
+            });
+        }
+
+        if self.arch != std::env::consts::ARCH {
+            return Err(VmSnapshotError::ArchMismatch {
+                found: self.arch.clone(),
+                expected: std::env::consts::ARCH,
+            });
+        }
+
+        if self.hypervisor != hypervisor {
+            return Err(VmSnapshotError::HypervisorMismatch {
+                found: self.hypervisor.clone(),
+                expected: hypervisor,
+            });
+        }
+
+        if self.vmm_version != env!("CARGO_PKG_VERSION") {
+            return Err(VmSnapshotError::VmmVersionMismatch {
+                found: self.vmm_version.clone(),
+                expected: env!("CARGO_PKG_VERSION"),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+fn encode_section<T>(version: u32, value: &T) -> Result<Section, VmSnapshotError>
+where
+    T: Serialize,
+{
+    Ok(Section {
+        version,
+        data: postcard::to_stdvec(value)?,
+    })
+}
+
+/// The section is only decoded if it has the version of this vmm
+fn decode_section<T>(
+    id: &'static str,
+    version: u32,
+    section: &Section,
+) -> Result<T, VmSnapshotError>
+where
+    T: DeserializeOwned,
+{
+    if section.version != version {
+        return Err(VmSnapshotError::SectionVersionMismatch {
+            id,
+            found: section.version,
+            expected: version,
+        });
+    }
+
+    Ok(postcard::from_bytes(&section.data)?)
+}
 
 #[derive(Serialize, Deserialize)]
 pub struct VmSnapshot {
-    vm_config: VmConfig,
+    vm_config: Section,
     vm_state: VmState,
     memory_address_space: MemoryAddressSpaceSnapshot,
-    vcpus: VcpuManagerSnapshot,
-    irq_chip: Vec<u8>,
+    vcpus: Section,
+    irq_chip: Section,
     devices: DeviceSnapshot,
 }
 
+impl VmSnapshot {
+    pub fn vm_config(&self) -> Result<VmConfig, VmSnapshotError> {
+        decode_section("vm_config", VM_CONFIG_SNAPSHOT_VERSION, &self.vm_config)
+    }
+
+    fn vcpus(&self) -> Result<VcpuManagerSnapshot, VmSnapshotError> {
+        decode_section("vcpus", VCPUS_SNAPSHOT_VERSION, &self.vcpus)
+    }
+
+    /// The state saved by the hypervisor, it is loaded by the irq chip itself
+    fn irq_chip(&self) -> Result<&[u8], VmSnapshotError> {
+        if self.irq_chip.version != IRQ_CHIP_SNAPSHOT_VERSION {
+            return Err(VmSnapshotError::SectionVersionMismatch {
+                id: "irq_chip",
+                found: self.irq_chip.version,
+                expected: IRQ_CHIP_SNAPSHOT_VERSION,
+            });
+        }
+
+        Ok(&self.irq_chip.data)
+    }
+
+    pub fn device_sections(&self) -> &Sections {
+        self.devices.sections()
+    }
+
+    pub fn irq_chip_len(&self) -> usize {
+        self.irq_chip.data.len()
+    }
+
+    /// The magic, the header and the snapshot
+    pub fn to_bytes(&self, hypervisor: &str) -> Result<Vec<u8>, VmSnapshotError> {
+        let mut buf = SNAPSHOT_MAGIC.to_vec();
+        buf.extend(postcard::to_stdvec(&SnapshotHeader::new(hypervisor))?);
+        buf.extend(postcard::to_stdvec(self)?);
+
+        Ok(buf)
+    }
+
+    /// The snapshot is only decoded if the header has the current format version
+    pub fn from_bytes(buf: &[u8]) -> Result<(SnapshotHeader, Self), VmSnapshotError> {
+        let buf = buf
+            .strip_prefix(&SNAPSHOT_MAGIC)
+            .ok_or(VmSnapshotError::NotASnapshot)?;
+
+        let (header, buf) = postcard::take_from_bytes::<SnapshotHeader>(buf)?;
+        if header.format_version != SNAPSHOT_FORMAT_VERSION {
+            return Err(VmSnapshotError::FormatVersionMismatch {
+                found: header.format_version,
+                expected: SNAPSHOT_FORMAT_VERSION,
+            });
+        }
+
+        let snap = postcard::from_bytes::<VmSnapshot>(buf)?;
+
+        Ok((header, snap))
+    }
+}
+
+/// Reads a snapshot without restoring it
+pub fn inspect_snapshot(path: &Path) -> Result<(SnapshotHeader, VmSnapshot), VmSnapshotError> {
+    VmSnapshot::from_bytes(&fs::read(path)?)
+}
+
 impl Vm {
     pub async fn build_snapshot(&self) -> Result<VmSnapshot, VmSnapshotError> {
         let vcpus = {
@@ -56,11 +230,14 @@ impl Vm {
         };
 
         let snap = VmSnapshot {
-            vm_config: self.vm_config.clone(),
+            vm_config: encode_section(VM_CONFIG_SNAPSHOT_VERSION, &self.vm_config)?,
             vm_state: self.vm_state,
             memory_address_space: self.memory_address_space().build_snapshot()?,
-            vcpus,
-            irq_chip,
+            vcpus: encode_section(VCPUS_SNAPSHOT_VERSION, &vcpus)?,
+            irq_chip: Section {
+                version: IRQ_CHIP_SNAPSHOT_VERSION,
+                data: irq_chip,
+            },
             devices: self.device_manager.build_snapshot()?,
         };
 
@@ -71,15 +248,26 @@ impl Vm {
         hypervisor: &dyn Hypervisor,
         vmm_tx: Arc<mpsc::Sender<VmmCommand>>,
         path: &Path,
+        config_override: Option<&Value>,
     ) -> Result<Self, VmmError> {
         let snap = {
             let buf = read(path)
                 .await
                 .map_err(|err| VmmError::SnapshotError(VmSnapshotError::Io(err)))?;
-            postcard::from_bytes::<VmSnapshot>(&buf)
-                .map_err(|err| VmmError::SnapshotError(VmSnapshotError::Postcard(err)))?
+            let (header, snap) = VmSnapshot::from_bytes(&buf)?;
+            header.check(hypervisor.name())?;
+
+            snap
         };
 
+        let mut vm_config = snap.vm_config()?;
+        if let Some(patch) = config_override {
+            vm_config = vm_config.apply_override(patch)?;
+        }
+        vm_config.validate()?;
+        let vcpus = snap.vcpus()?;
+        let irq_chip_state = snap.irq_chip()?.to_vec();
+
         let mut monitor_server_builder = MonitorServerBuilder::default();
 
         let vm_instance = hypervisor.create_vm()?;
@@ -89,11 +277,24 @@ impl Vm {
                 MemoryAddressSpace::from_snapshot(snap.memory_address_space)?;
 
             for (gpa, memory_region) in memory_address_space.regions() {
+                // virtio-mem maps its plugged blocks when it is loaded
+                if (MEMORY_HOTPLUG_START..MEMORY_HOTPLUG_START + MEMORY_HOTPLUG_LEN).contains(gpa) {
+                    continue;
+                }
+
+                let flags = if vm_config.firmware.is_some()
+                    && is_firmware_code(*gpa, memory_region.len())
+                {
+                    SetUserMemoryRegionFlags::ReadExec
+                } else {
+                    SetUserMemoryRegionFlags::ReadWriteExec
+                };
+
                 vm_instance.set_user_memory_region(
                     memory_region.hva() as _,
                     *gpa,
                     memory_region.len(),
-                    SetUserMemoryRegionFlags::ReadWriteExec,
+                    flags,
                 )?;
             }
 
@@ -101,11 +302,11 @@ impl Vm {
         };
 
         let irq_chip: Arc<dyn InterruptController> =
-            if !snap.vm_config.devices.iter().any(Device::is_irq_chip) {
+            if !vm_config.devices.iter().any(Device::is_irq_chip) {
                 let mut irq_chip = vm_instance.create_irq_chip()?;
 
                 irq_chip
-                    .load(&mut Cursor::new(snap.irq_chip))
+                    .load(&mut Cursor::new(irq_chip_state))
                     .map_err(|err| VmmError::SnapshotError(VmSnapshotError::IrqChip(err)))?;
 
                 Arc::from(irq_chip)
@@ -113,6 +314,9 @@ impl Vm {
                 todo!()
             };
 
+        let system_event_notifier: Arc<dyn SystemEventNotifier> =
+            Arc::new(VmmSystemEventNotifier::new(vmm_tx.clone()));
+
         let device_manager = {
             let mut device_manager = DeviceManagerBuilder::new(
                 vm_instance.clone(),
@@ -120,8 +324,20 @@ impl Vm {
                 vm_instance.create_irq_manager()?,
                 memory_address_space.clone(),
                 &mut monitor_server_builder,
+                vm_config.rtc.clone(),
+                vm_config.serial.clone(),
+                vm_config.cpu_topology(),
+                vm_config
+                    .max_vcpus
+                    .is_some()
+                    .then_some(vm_config.vcpus as u32),
+                system_event_notifier.clone(),
             )?
-            .build(&snap.vm_config.devices)?;
+            .build(
+                &vm_config.devices,
+                vm_config.pci_hotplug_slots,
+                vm_config.pci_mmio64_size,
+            )?;
             device_manager
                 .install_snapshot(snap.devices)
                 .map_err(|err| VmmError::SnapshotError(VmSnapshotError::Device(err)))?;
@@ -129,15 +345,20 @@ impl Vm {
             Arc::new(device_manager)
         };
 
-        let vcpu_manager = Arc::new(Mutex::new(VcpuManager::new(vm_instance.clone())));
+        let vcpu_manager = Arc::new(Mutex::new(VcpuManager::new(
+            vm_instance.clone(),
+            vm_config.cpu_topology(),
+        )));
 
         #[cfg(target_arch = "aarch64")]
-        let psci = Psci02 {
+        let psci = Psci11 {
             vcpu_manager: vcpu_manager.clone(),
+            system_event_notifier: system_event_notifier.clone(),
         };
 
         let vm_exit_handler = Arc::new(VmExitHandler::new(
             device_manager.clone(),
+            system_event_notifier,
             #[cfg(target_arch = "aarch64")]
             psci,
         ));
@@ -146,24 +367,20 @@ impl Vm {
             let mut vcpu_manager = vcpu_manager.lock().await;
 
             vcpu_manager
-                .install_snapshot(
-                    memory_address_space.clone(),
-                    vm_exit_handler.clone(),
-                    snap.vcpus,
-                )
+                .install_snapshot(memory_address_space.clone(), vm_exit_handler.clone(), vcpus)
                 .await?;
         }
 
-        let gdb_stub = snap
-            .vm_config
+        let gdb_stub = vm_config
             .gdb_port
             .map(|port| VmGdbStubConnector::new(vmm_tx, port));
 
         let vm = Vm {
-            vm_config: snap.vm_config,
+            vm_config,
             vm_instance,
             vm_state: snap.vm_state,
             vcpu_manager,
+            vm_exit_handler,
             memory_address_space,
             irq_chip,
             device_manager,
@@ -174,3 +391,59 @@ impl Vm {
         Ok(vm)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_header() {
+        assert!(matches!(
+            VmSnapshot::from_bytes(b"not a snapshot"),
+            Err(VmSnapshotError::NotASnapshot)
+        ));
+
+        let mut buf = SNAPSHOT_MAGIC.to_vec();
+        buf.extend(
+            postcard::to_stdvec(&SnapshotHeader {
+                format_version: SNAPSHOT_FORMAT_VERSION + 1,
+                ..SnapshotHeader::new("kvm")
+            })
+            .unwrap(),
+        );
+        assert!(matches!(
+            VmSnapshot::from_bytes(&buf),
+            Err(VmSnapshotError::FormatVersionMismatch { .. })
+        ));
+
+        let header = SnapshotHeader::new("kvm");
+        assert!(header.check("kvm").is_ok());
+        assert!(matches!(
+            header.check("hvf"),
+            Err(VmSnapshotError::HypervisorMismatch { .. })
+        ));
+
+        let header = SnapshotHeader {
+            vmm_version: "0.0.0-old".to_string(),
+            ..SnapshotHeader::new("kvm")
+        };
+        assert!(matches!(
+            header.check("kvm"),
+            Err(VmSnapshotError::VmmVersionMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_section_version() {
+        let section = encode_section(VCPUS_SNAPSHOT_VERSION, &42u32).unwrap();
+        assert_eq!(
+            decode_section::<u32>("vcpus", VCPUS_SNAPSHOT_VERSION, &section).unwrap(),
+            42
+        );
+
+        assert!(matches!(
+            decode_section::<u32>("vcpus", VCPUS_SNAPSHOT_VERSION + 1, &section),
+            Err(VmSnapshotError::SectionVersionMismatch { id: "vcpus", .. })
+        ));
+    }
+}
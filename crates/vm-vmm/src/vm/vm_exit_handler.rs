@@ -3,27 +3,34 @@ use std::sync::Arc;
 #[cfg(target_arch = "aarch64")]
 use vm_core::arch::aarch64::firmware::psci::Psci;
 #[cfg(target_arch = "aarch64")]
-use vm_core::arch::aarch64::firmware::psci::psci_0_2::Psci02;
+use vm_core::arch::aarch64::firmware::psci::PsciCallResult;
+#[cfg(target_arch = "aarch64")]
+use vm_core::arch::aarch64::firmware::psci::psci_1_1::Psci11;
 #[cfg(target_arch = "aarch64")]
 use vm_core::arch::aarch64::vcpu::AArch64Vcpu;
 use vm_core::cpu::vm_exit::VmExit;
 use vm_core::cpu::vm_exit::VmExitHandlerError;
+use vm_core::device::system_event::SystemEvent;
+use vm_core::device::system_event::SystemEventNotifier;
 
 use crate::device::device_manager_v2::DeviceManagerV2;
 
 pub struct VmExitHandler {
     device_manager: Arc<DeviceManagerV2>,
+    system_event_notifier: Arc<dyn SystemEventNotifier>,
     #[cfg(target_arch = "aarch64")]
-    psci: Psci02,
+    psci: Psci11,
 }
 
 impl VmExitHandler {
     pub fn new(
         device_manager: Arc<DeviceManagerV2>,
-        #[cfg(target_arch = "aarch64")] psci: Psci02,
+        system_event_notifier: Arc<dyn SystemEventNotifier>,
+        #[cfg(target_arch = "aarch64")] psci: Psci11,
     ) -> Self {
         VmExitHandler {
             device_manager,
+            system_event_notifier,
             #[cfg(target_arch = "aarch64")]
             psci,
         }
@@ -51,10 +58,14 @@ impl VmExit for VmExitHandler {
         self.device_manager.mmio_write(addr, data)
     }
 
+    fn system_event(&self, event: SystemEvent) {
+        self.system_event_notifier.notify(event);
+    }
+
     #[cfg(target_arch = "aarch64")]
-    fn call_smc(&self, vcpu: &mut dyn AArch64Vcpu) -> Result<(), VmExitHandlerError> {
-        self.psci.call(vcpu)?;
+    fn call_smc(&self, vcpu: &mut dyn AArch64Vcpu) -> Result<PsciCallResult, VmExitHandlerError> {
+        let result = self.psci.call(vcpu)?;
 
-        Ok(())
+        Ok(result)
     }
 }
@@ -2,14 +2,17 @@ use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
 
+use serde_json::Value;
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::Receiver;
 use tokio::sync::mpsc::Sender;
 use vm_core::virtualization::hypervisor::Hypervisor;
 use vm_core::virtualization::vm::state::VmState;
 
+use crate::device::pci_hotplug::DeviceAddArgs;
 use crate::vm::Vm;
 use crate::vm::config::VmConfig;
+use crate::vmm::error::PciHotplugError;
 use crate::vmm::error::VmmError;
 use crate::vmm::handler::VmmCommand;
 
@@ -19,8 +22,11 @@ pub(crate) mod handler;
 
 mod service;
 
+const DEFAULT_MONITOR_SOCKET: &str = "/tmp/vm.sock";
+
 pub struct Vmm {
     hypervisor: Box<dyn Hypervisor>,
+    monitor_socket: PathBuf,
     vm: Option<Vm>,
     command_rx: Receiver<VmmCommand>,
     command_tx: Arc<Sender<VmmCommand>>,
@@ -32,12 +38,18 @@ impl Vmm {
 
         Vmm {
             hypervisor,
+            monitor_socket: PathBuf::from(DEFAULT_MONITOR_SOCKET),
             vm: None,
             command_rx,
             command_tx: Arc::new(command_tx),
         }
     }
 
+    /// Where the monitor listens, it must be set before `run`
+    pub fn set_monitor_socket(&mut self, path: PathBuf) {
+        self.monitor_socket = path;
+    }
+
     pub fn try_get_vm(&self) -> Result<&Vm, VmmError> {
         self.vm.as_ref().ok_or(VmmError::VmNotExists)
     }
@@ -59,12 +71,23 @@ impl Vmm {
         Ok(())
     }
 
-    pub async fn create_vm_from_snapshot(&mut self, path: &Path) -> Result<(), VmmError> {
+    /// `config_override` is a json merge patch of the saved config
+    pub async fn create_vm_from_snapshot(
+        &mut self,
+        path: &Path,
+        config_override: Option<Value>,
+    ) -> Result<(), VmmError> {
         if self.vm.is_some() {
             return Err(VmmError::VmAlreadyExists);
         }
 
-        let vm = Vm::from_snapshot(self.hypervisor.as_ref(), self.command_tx.clone(), path).await?;
+        let vm = Vm::from_snapshot(
+            self.hypervisor.as_ref(),
+            self.command_tx.clone(),
+            path,
+            config_override.as_ref(),
+        )
+        .await?;
 
         self.vm = Some(vm);
 
@@ -97,10 +120,88 @@ impl Vmm {
         Ok(())
     }
 
+    pub fn system_powerdown(&self) -> Result<(), VmmError> {
+        let vm = self.try_get_vm()?;
+
+        vm.system_powerdown()?;
+
+        Ok(())
+    }
+
+    /// Reboots the guest without restarting the vmm
+    pub async fn reset(&mut self) -> Result<(), VmmError> {
+        let vm = self.try_get_vm_mut()?;
+
+        vm.reset().await?;
+
+        Ok(())
+    }
+
+    /// Stops the vm for good, the guest has powered off
+    pub async fn shutdown(&mut self) -> Result<(), VmmError> {
+        let mut vm = self.vm.take().ok_or(VmmError::VmNotExists)?;
+
+        vm.shutdown().await?;
+
+        Ok(())
+    }
+
+    pub async fn cpu_plug(&mut self) -> Result<usize, VmmError> {
+        let vm = self.try_get_vm_mut()?;
+
+        let vcpu_id = vm.cpu_plug().await?;
+
+        Ok(vcpu_id)
+    }
+
+    pub async fn cpu_unplug(&self) -> Result<(), VmmError> {
+        let vm = self.try_get_vm()?;
+
+        vm.cpu_unplug().await?;
+
+        Ok(())
+    }
+
+    /// The guest has released the vcpu whose unplug was requested
+    pub async fn vcpu_ejected(&mut self, vcpu_id: usize) -> Result<(), VmmError> {
+        let vm = self.try_get_vm_mut()?;
+
+        vm.vcpu_ejected(vcpu_id).await?;
+
+        Ok(())
+    }
+
+    pub fn device_add(&mut self, args: &str) -> Result<(), VmmError> {
+        let vm = self.try_get_vm_mut()?;
+
+        let args: DeviceAddArgs = serde_json::from_str(args).map_err(PciHotplugError::from)?;
+        vm.device_add(args.id, &args.device)?;
+
+        Ok(())
+    }
+
+    pub fn device_del(&self, id: &str) -> Result<(), VmmError> {
+        let vm = self.try_get_vm()?;
+
+        vm.device_del(id)?;
+
+        Ok(())
+    }
+
+    /// The guest has released the device of a hot-plug slot
+    pub fn pci_device_released(&mut self, slot: usize) -> Result<(), VmmError> {
+        let vm = self.try_get_vm_mut()?;
+
+        vm.pci_device_released(slot)?;
+
+        Ok(())
+    }
+
     pub async fn save(&mut self, path: PathBuf) -> Result<(), VmmError> {
+        let hypervisor = self.hypervisor.name();
         let vm = self.try_get_vm_mut()?;
 
-        vm.save(path).await?;
+        vm.save(path, hypervisor).await?;
 
         Ok(())
     }
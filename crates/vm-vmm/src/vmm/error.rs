@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use thiserror::Error;
 use vm_core::arch::irq::error::IrqChipError;
 use vm_core::cpu::error::CpuError;
@@ -32,6 +34,67 @@ pub enum VmSnapshotError {
 
     #[error("vm error: {0}")]
     Vm(#[from] VmError),
+
+    #[error("bootloader error: {0}")]
+    Bootloader(#[from] BootloaderError),
+
+    #[error("not a snapshot file")]
+    NotASnapshot,
+
+    #[error("snapshot format version {found} is not supported, expected {expected}")]
+    FormatVersionMismatch { found: u32, expected: u32 },
+
+    #[error("the snapshot was taken on {found} but the host is {expected}")]
+    ArchMismatch {
+        found: String,
+        expected: &'static str,
+    },
+
+    #[error("the snapshot was taken with {found} but the hypervisor is {expected}")]
+    HypervisorMismatch {
+        found: String,
+        expected: &'static str,
+    },
+
+    #[error("the snapshot was taken by vmm {found} but this vmm is {expected}")]
+    VmmVersionMismatch {
+        found: String,
+        expected: &'static str,
+    },
+
+    #[error("the snapshot section {id} has version {found} but version {expected} is supported")]
+    SectionVersionMismatch {
+        id: &'static str,
+        found: u32,
+        expected: u32,
+    },
+}
+
+#[derive(Error, Debug)]
+pub enum PciHotplugError {
+    #[error("No pci hotplug slot is configured")]
+    NotSupported,
+
+    #[error("Device {0} already exists")]
+    DuplicateId(String),
+
+    #[error("Device {0} not found")]
+    DeviceNotFound(String),
+
+    #[error("All pci hotplug slots are in use")]
+    NoFreeSlot,
+
+    #[error("Pci hotplug slot {0} has no device")]
+    SlotNotPlugged(usize),
+
+    #[error("Device can not be hotplugged")]
+    NotHotpluggable,
+
+    #[error("Invalid device: {0}")]
+    InvalidDevice(#[from] serde_json::Error),
+
+    #[error("Failed to init device: {0}")]
+    InitDevice(#[from] InitDeviceError),
 }
 
 #[derive(Error, Debug)]
@@ -42,6 +105,33 @@ pub enum VmmError {
     #[error("Vm not exists")]
     VmNotExists,
 
+    #[error("The cpu topology has {topology} vcpus but {vcpus} vcpus are configured")]
+    CpuTopologyMismatch { vcpus: usize, topology: u32 },
+
+    #[error("Invalid cpu hotplug: {0}")]
+    InvalidCpuHotplug(&'static str),
+
+    #[error("Invalid numa nodes: {0}")]
+    InvalidNumaNodes(&'static str),
+
+    #[error("Invalid boot source: {0}")]
+    InvalidBootSource(&'static str),
+
+    #[error("Invalid memory: {0}")]
+    InvalidMemory(String),
+
+    #[error("Invalid device: {0}")]
+    InvalidDevice(String),
+
+    #[error("Invalid snapshot override: {0}")]
+    InvalidOverride(String),
+
+    #[error("The snapshot override changes the guest visible {0}")]
+    GuestVisibleOverride(String),
+
+    #[error("The {what} {path:?} does not exist")]
+    MissingFile { what: &'static str, path: PathBuf },
+
     #[error("Hypervisor error: {0}")]
     HypervisorError(#[from] HypervisorError),
 
@@ -68,4 +158,7 @@ pub enum VmmError {
 
     #[error("Save vm error: {0}")]
     SnapshotError(#[from] VmSnapshotError),
+
+    #[error("Pci hotplug error: {0}")]
+    PciHotplug(#[from] PciHotplugError),
 }
@@ -1,10 +1,14 @@
+use vm_core::device::system_event::SystemEvent;
+
 use crate::service::gdbstub::command::GdbStubCommandRequest;
 use crate::service::monitor::command::MonitorCommandRequest;
 
 pub(crate) mod gdbstub;
 pub(crate) mod monitor;
+pub(crate) mod system_event;
 
 pub enum VmmCommand {
     GdbCommand(GdbStubCommandRequest),
     MonitorCommand(MonitorCommandRequest),
+    SystemEvent(SystemEvent),
 }
@@ -1,4 +1,5 @@
 use tracing::error;
+use vm_core::monitor::MonitorError;
 
 use crate::service::monitor::command::MonitorCommand;
 use crate::service::monitor::command::MonitorCommandResponse;
@@ -21,11 +22,56 @@ impl Vmm {
 
                     Ok(MonitorCommandResponse::Ok)
                 }
+                MonitorCommand::SystemPowerdown => {
+                    self.system_powerdown()?;
+
+                    Ok(MonitorCommandResponse::Ok)
+                }
+                MonitorCommand::SystemReset => {
+                    self.reset().await?;
+
+                    Ok(MonitorCommandResponse::Ok)
+                }
                 MonitorCommand::Save(path) => {
                     self.save(path).await?;
 
                     Ok(MonitorCommandResponse::Ok)
                 }
+                MonitorCommand::DeviceAdd(args) => {
+                    self.device_add(&args)?;
+
+                    Ok(MonitorCommandResponse::Ok)
+                }
+                MonitorCommand::DeviceDel(id) => {
+                    self.device_del(&id)?;
+
+                    Ok(MonitorCommandResponse::Ok)
+                }
+                MonitorCommand::CpuPlug => {
+                    let vcpu_id = self.cpu_plug().await?;
+
+                    Ok(MonitorCommandResponse::Output(format!(
+                        "vcpu {vcpu_id} is plugged"
+                    )))
+                }
+                MonitorCommand::CpuUnplug => {
+                    self.cpu_unplug().await?;
+
+                    Ok(MonitorCommandResponse::Ok)
+                }
+                MonitorCommand::Device { name, subcommands } => {
+                    let vm = self.try_get_vm()?;
+
+                    let handler = vm
+                        .monitor_handlers()
+                        .get(&name)
+                        .ok_or(MonitorError::UnknownCommand(name))?;
+
+                    let subcommands: Vec<&str> = subcommands.iter().map(String::as_str).collect();
+                    let output = handler.handle_command(&subcommands).await?;
+
+                    Ok(MonitorCommandResponse::Output(output))
+                }
             }
         }
         .await
@@ -34,23 +80,5 @@ impl Vmm {
 
             MonitorCommandResponse::Err(err)
         })
-
-        /*
-                let Some(command) = tokens.next() else {
-                    return Err(CommandError::InvalidCommand);
-                };
-
-                let subcommands: Vec<&str> = tokens.collect();
-
-                let vm = self.try_get_vm()?;
-                let Some(handler) = vm.monitor_handlers().get(command) else {
-                    return Err(CommandError::InvalidCommand);
-                };
-
-                match handler.handle_command(&subcommands).await {
-                    Ok(resp) => Ok(MonitorCommandResponse(resp)),
-                    Err(err) => Ok(MonitorCommandResponse(err.to_string())),
-                }
-        */
     }
 }
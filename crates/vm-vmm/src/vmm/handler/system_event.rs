@@ -0,0 +1,75 @@
+use std::ops::ControlFlow;
+use std::sync::Arc;
+
+use tokio::sync::mpsc::Sender;
+use tracing::error;
+use tracing::info;
+use vm_core::device::system_event::SystemEvent;
+use vm_core::device::system_event::SystemEventNotifier;
+
+use crate::vmm::Vmm;
+use crate::vmm::handler::VmmCommand;
+
+/// Forwards the events raised by the devices to the vmm
+pub(crate) struct VmmSystemEventNotifier {
+    tx: Arc<Sender<VmmCommand>>,
+}
+
+impl VmmSystemEventNotifier {
+    pub(crate) fn new(tx: Arc<Sender<VmmCommand>>) -> Self {
+        VmmSystemEventNotifier { tx }
+    }
+}
+
+impl SystemEventNotifier for VmmSystemEventNotifier {
+    fn notify(&self, event: SystemEvent) {
+        // It is called on the vcpu threads, which must not block on the vmm
+        if self.tx.try_send(VmmCommand::SystemEvent(event)).is_err() {
+            error!(?event, "Failed to send system event");
+        }
+    }
+}
+
+impl Vmm {
+    /// Breaks once the vmm has nothing left to run
+    pub async fn handle_system_event(&mut self, event: SystemEvent) -> ControlFlow<()> {
+        match event {
+            SystemEvent::Shutdown => {
+                info!("The guest has powered off");
+
+                if let Err(err) = self.shutdown().await {
+                    error!(?err, "Failed to shut down the vm");
+                }
+
+                ControlFlow::Break(())
+            }
+            SystemEvent::Reset => {
+                info!("The guest has requested a reset");
+
+                if let Err(err) = self.reset().await {
+                    error!(?err, "Failed to reset the vm");
+
+                    return ControlFlow::Break(());
+                }
+
+                ControlFlow::Continue(())
+            }
+            SystemEvent::VcpuEjected(vcpu_id) => {
+                info!(vcpu_id, "The guest has ejected a vcpu");
+
+                if let Err(err) = self.vcpu_ejected(vcpu_id as usize).await {
+                    error!(?err, vcpu_id, "Failed to unplug the vcpu");
+                }
+
+                ControlFlow::Continue(())
+            }
+            SystemEvent::PciDeviceReleased(slot) => {
+                if let Err(err) = self.pci_device_released(slot as usize) {
+                    error!(?err, slot, "Failed to release the pci hot-plug slot");
+                }
+
+                ControlFlow::Continue(())
+            }
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use std::ops::ControlFlow;
+
 use tracing::error;
 
 use crate::vmm::Vmm;
@@ -10,11 +12,13 @@ impl Vmm {
         self.listen_for_monitor_client();
 
         while let Some(command) = self.command_rx.recv().await {
-            self.handle_command(command).await;
+            if self.handle_command(command).await.is_break() {
+                break;
+            }
         }
     }
 
-    async fn handle_command(&mut self, command: VmmCommand) {
+    async fn handle_command(&mut self, command: VmmCommand) -> ControlFlow<()> {
         match command {
             VmmCommand::GdbCommand(cmd) => {
                 let response = self.handle_gdbstub_command(cmd.command).await;
@@ -30,6 +34,9 @@ impl Vmm {
                     error!("Failed to send monitor command response");
                 }
             }
+            VmmCommand::SystemEvent(event) => return self.handle_system_event(event).await,
         }
+
+        ControlFlow::Continue(())
     }
 }
@@ -8,11 +8,10 @@ use tokio::sync::mpsc::Sender;
 use vm_core::monitor::MonitorError;
 
 use crate::service::monitor::command::MonitorCommand;
+use crate::service::monitor::command::MonitorCommandResponse;
 use crate::vmm::Vmm;
 use crate::vmm::handler::VmmCommand;
 
-const PATH: &str = "/tmp/vm.sock";
-
 struct MonitorConnection {
     tx: Arc<Sender<VmmCommand>>,
 }
@@ -57,7 +56,18 @@ impl MonitorConnection {
                                 Ok(resp) => {
                                     stream.writable().await?;
 
-                                    stream.write_all(format!("{resp:?}").as_bytes()).await?;
+                                    match resp {
+                                        MonitorCommandResponse::Output(output) => {
+                                            stream
+                                                .write_all(format!("{output}\n").as_bytes())
+                                                .await?;
+                                        }
+                                        resp => {
+                                            stream
+                                                .write_all(format!("{resp:?}").as_bytes())
+                                                .await?;
+                                        }
+                                    }
                                 }
                                 Err(err) => {
                                     stream.write_all(format!("ERR {err}\n").as_bytes()).await?;
@@ -82,9 +92,10 @@ impl MonitorConnection {
 impl Vmm {
     pub fn listen_for_monitor_client(&self) {
         let tx = self.command_tx.clone();
+        let path = self.monitor_socket.clone();
 
         tokio::spawn(async move {
-            let Ok(listener) = UnixListener::bind(PATH) else {
+            let Ok(listener) = UnixListener::bind(path) else {
                 return;
             };
 